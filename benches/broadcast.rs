@@ -0,0 +1,104 @@
+/*!
+广播发送的微基准
+
+500个本机UDP节点，对比`NetworkManager::broadcast`为每个节点启动独立任务并发发送，与逐个节点依次调用`send_packet`。
+运行：`cargo bench --bench broadcast`
+*/
+
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use vpnet::{MessageType, NetworkManager, NodeInfo, Packet, PacketBuilder};
+
+const PEERS: usize = 500;
+const ROUNDS: u32 = 20;
+
+fn node_info(i: usize, address: SocketAddr) -> NodeInfo {
+    NodeInfo {
+        node_id: format!("bench-peer-{}", i),
+        node_name: format!("Bench Peer {}", i),
+        public_key: vec![1u8; 32],
+        address,
+        virtual_ip: format!("10.1.{}.{}", i / 250, i % 250 + 1),
+        subnet: "255.255.0.0".to_string(),
+        online: true,
+        last_seen: 0,
+        capabilities: 0,
+        geographic_location: None,
+        tags: HashMap::new(),
+    }
+}
+
+/// 启动网络管理器，并让`PEERS`个本机套接字通过节点信息报文加入节点表
+async fn manager_with_peers() -> (NetworkManager, Vec<UdpSocket>) {
+    let manager = NetworkManager::new(
+        vec!["127.0.0.1:0".parse().unwrap()],
+        "bench-node".to_string(),
+        "Bench Node".to_string(),
+        vec![0u8; 32]
+    ).unwrap();
+    manager.start().await;
+    let target = manager.listen_addrs()[0];
+
+    let sockets: Vec<UdpSocket> = (0..PEERS)
+        .map(|_| UdpSocket::bind("127.0.0.1:0").unwrap())
+        .collect();
+    let announcements: Vec<Vec<u8>> = sockets.iter().enumerate()
+        .map(|(i, socket)| {
+            let info = node_info(i, socket.local_addr().unwrap());
+            PacketBuilder::new(MessageType::NodeInfo, serde_json::to_vec(&info).unwrap()).build().encode().unwrap()
+        })
+        .collect();
+
+    // 一次发出500个报文会超出接收缓冲区，分批重发直到所有节点都已加入
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let known = manager.get_peers().await.len();
+        if known == PEERS {
+            break;
+        }
+        assert!(Instant::now() < deadline, "only {} of {} peers joined", known, PEERS);
+        for (i, (socket, announcement)) in sockets.iter().zip(&announcements).enumerate() {
+            if manager.get_peer(&format!("bench-peer-{}", i)).await.is_none() {
+                socket.send_to(announcement, target).unwrap();
+                tokio::task::yield_now().await;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    (manager, sockets)
+}
+
+/// 每个节点一个任务并发发送
+async fn concurrent(manager: &NetworkManager, packet: &Packet) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let result = manager.broadcast(packet).await;
+        assert_eq!(result.sent, PEERS);
+    }
+    start.elapsed()
+}
+
+/// 逐个节点依次发送
+async fn sequential(manager: &NetworkManager, packet: &Packet) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for peer in manager.get_peers().await {
+            manager.send_packet(&peer.node_id, packet).await.unwrap();
+        }
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (concurrent, sequential) = runtime.block_on(async {
+        let (manager, _sockets) = manager_with_peers().await;
+        let packet = PacketBuilder::new(MessageType::Heartbeat, vec![0u8; 64]).build();
+        (concurrent(&manager, &packet).await, sequential(&manager, &packet).await)
+    });
+    println!("{} peers, {} rounds", PEERS, ROUNDS);
+    println!("  concurrent: {:?} ({:?}/broadcast)", concurrent, concurrent / ROUNDS);
+    println!("  sequential: {:?} ({:?}/broadcast)", sequential, sequential / ROUNDS);
+    println!("  speedup:    {:.1}x", sequential.as_secs_f64() / concurrent.as_secs_f64());
+}
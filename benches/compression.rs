@@ -0,0 +1,60 @@
+/*!
+压缩模式的微基准
+
+对一半可压缩（文本）、一半不可压缩（随机字节，相当于TLS或图片）的1400字节数据包，
+对比不压缩、总是压缩和自适应压缩的耗时和压缩后的总字节数。
+运行：`cargo bench --bench compression`
+*/
+
+use rand::RngCore;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use vpnet::Compression;
+
+const PACKETS: usize = 100_000;
+const PAYLOAD_LEN: usize = 1400;
+
+/// 交替生成文本和随机字节的数据包
+fn traffic() -> Vec<Vec<u8>> {
+    let text = b"GET /api/nodes HTTP/1.1\r\nHost: 10.0.0.1\r\nAccept: application/json\r\n\r\n";
+    let mut rng = rand::thread_rng();
+    (0..PACKETS)
+        .map(|i| {
+            let mut packet = vec![0u8; PAYLOAD_LEN];
+            if i % 2 == 0 {
+                for (byte, text_byte) in packet.iter_mut().zip(text.iter().cycle()) {
+                    *byte = *text_byte;
+                }
+            } else {
+                rng.fill_bytes(&mut packet);
+            }
+            packet
+        })
+        .collect()
+}
+
+/// 按`compression`压缩所有数据包，返回耗时和发送的总字节数
+fn run(compression: Compression, traffic: &[Vec<u8>]) -> (Duration, usize) {
+    let start = Instant::now();
+    let mut bytes = 0;
+    for packet in traffic {
+        bytes += match black_box(compression).compress(black_box(packet)) {
+            Some(compressed) => compressed.len(),
+            None => packet.len(),
+        };
+    }
+    (start.elapsed(), bytes)
+}
+
+fn report(name: &str, (elapsed, bytes): (Duration, usize)) {
+    let ratio = bytes as f64 / (PACKETS * PAYLOAD_LEN) as f64;
+    println!("  {:<9} {:?}/packet, {:.1}% of original size", name, elapsed / PACKETS as u32, ratio * 100.0);
+}
+
+fn main() {
+    let traffic = traffic();
+    println!("{} packets of {} bytes, half compressible", PACKETS, PAYLOAD_LEN);
+    report("none", run(Compression::None, &traffic));
+    report("lz4", run(Compression::Lz4, &traffic));
+    report("adaptive", run(Compression::Adaptive { ratio_threshold: Compression::DEFAULT_RATIO_THRESHOLD }, &traffic));
+}
@@ -0,0 +1,60 @@
+/*!
+数据转发编码的微基准
+
+对比1400字节IP数据包以序列化（JSON）编码和以二进制头部（`FLAG_RAW_FRAME`）编码时，
+每个数据包的编解码耗时和线上字节数，并折算为单核能支撑的转发速率。
+运行：`cargo bench --bench data_forward`
+
+端到端的吞吐量需要在隧道两端用iperf3测量，例如在对端运行`iperf3 -s`后执行`iperf3 -c <对端虚拟IP> -t 30`。
+*/
+
+use std::hint::black_box;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+use vpnet::{constants, decode_data_forward, encode_data_forward, DataForward};
+
+const PACKETS: u32 = 200_000;
+const PAYLOAD_LEN: usize = 1400;
+
+fn data_forward() -> DataForward {
+    DataForward {
+        source_node: "node-a".to_string(),
+        dest_node: "node-b".to_string(),
+        data: vec![0x45; PAYLOAD_LEN],
+        protocol: 0x0800,
+        ttl: constants::DEFAULT_TTL,
+        seq: 0,
+        priority: constants::DEFAULT_DATA_PRIORITY,
+        compressed: false,
+        source_virtual_ip: Ipv4Addr::new(10, 0, 0, 2),
+        ack_requested: false,
+    }
+}
+
+/// 编码后立即解码`PACKETS`个数据包，返回耗时和每个数据包的编码长度
+fn run(flags: u8) -> (Duration, usize) {
+    let mut forward = data_forward();
+    let (encoded, _) = encode_data_forward(&forward, flags).unwrap();
+    let start = Instant::now();
+    for seq in 0..PACKETS {
+        forward.seq = seq;
+        let (data, flags) = encode_data_forward(black_box(&forward), flags).unwrap();
+        black_box(decode_data_forward(&data, flags).unwrap());
+    }
+    (start.elapsed(), encoded.len())
+}
+
+fn report(name: &str, (elapsed, len): (Duration, usize)) {
+    let per_packet = elapsed / PACKETS;
+    let gbps = PAYLOAD_LEN as f64 * 8.0 * PACKETS as f64 / elapsed.as_secs_f64() / 1e9;
+    println!("  {:<5} {:>5} bytes/packet, {:?}/packet, {:.1} Gbps", name, len, per_packet, gbps);
+}
+
+fn main() {
+    println!("{} data forwards of {} bytes", PACKETS, PAYLOAD_LEN);
+    let json = run(0);
+    let raw = run(constants::FLAG_RAW_FRAME | constants::FLAG_SOURCE_IP);
+    report("json", json);
+    report("raw", raw);
+    println!("  speedup: {:.1}x", json.0.as_secs_f64() / raw.0.as_secs_f64());
+}
@@ -0,0 +1,84 @@
+/*!
+虚拟设备写入的微基准
+
+向虚拟网卡写入1 MB的1400字节数据包，对比`VirtualDevice::send(&[u8])`逐个复制后写入，
+与通过`get_packet_sender`交出数据包所有权、由写入任务写出的耗时。
+运行：`cargo bench --bench device_inject`，创建TUN设备需要root权限或CAP_NET_ADMIN。
+*/
+
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+use vpnet::{default_config, VirtualDevice};
+
+const PAYLOAD_LEN: usize = 1400;
+const PACKETS: usize = 1024 * 1024 / PAYLOAD_LEN;
+const ROUNDS: u32 = 50;
+
+/// 目标为设备自身子网的IPv4数据包
+fn packet() -> Vec<u8> {
+    let mut packet = vec![0u8; PAYLOAD_LEN];
+    packet[0] = 0x45;
+    packet[2..4].copy_from_slice(&(PAYLOAD_LEN as u16).to_be_bytes());
+    packet[8] = 64;
+    packet[9] = 17;
+    packet[12..16].copy_from_slice(&[10, 200, 0, 2]);
+    packet[16..20].copy_from_slice(&[10, 200, 0, 3]);
+    packet
+}
+
+/// 每个数据包经`send`复制后写入
+async fn send_slice(device: &mut VirtualDevice, packet: &[u8]) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for _ in 0..PACKETS {
+            device.send(packet).await.unwrap();
+        }
+    }
+    start.elapsed()
+}
+
+/// 每个数据包的所有权交给写入通道，等待写入任务全部写出
+async fn send_owned(device: &mut VirtualDevice, packet: &[u8]) -> Duration {
+    let sender = device.get_packet_sender();
+    // 热路径上数据包本就是解密得到的新缓冲区，预先分配以排除构造开销
+    let mut batches: Vec<Vec<Vec<u8>>> = (0..ROUNDS).map(|_| vec![packet.to_vec(); PACKETS]).collect();
+    let start = Instant::now();
+    for batch in batches.drain(..) {
+        for packet in batch {
+            sender.send(packet).await.unwrap();
+        }
+        device.flush().await.unwrap();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let result = runtime.block_on(async {
+        let config = default_config("vpnet-bench0".to_string(), Ipv4Addr::new(10, 200, 0, 2));
+        let mut device = VirtualDevice::new(config, "bench".to_string()).unwrap();
+        device.start().await?;
+        // 没有打开虚拟网卡时两种方式都不会真正写出数据包
+        if device.writer().is_none() {
+            return Err("device was not opened");
+        }
+        let packet = packet();
+        let slice = send_slice(&mut device, &packet).await;
+        let owned = send_owned(&mut device, &packet).await;
+        let _ = device.stop().await;
+        Ok((slice, owned))
+    });
+
+    let (slice, owned) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            println!("Skipping benchmark, failed to create TUN device: {}", e);
+            return;
+        }
+    };
+    let mb = ROUNDS as f64;
+    println!("{} rounds of {} packets of {} bytes", ROUNDS, PACKETS, PAYLOAD_LEN);
+    println!("  send(&[u8]):  {:?}/MB, {:.0} MB/s", slice / ROUNDS, mb / slice.as_secs_f64());
+    println!("  sender.send:  {:?}/MB, {:.0} MB/s", owned / ROUNDS, mb / owned.as_secs_f64());
+    println!("  speedup:      {:.1}x", slice.as_secs_f64() / owned.as_secs_f64());
+}
@@ -0,0 +1,66 @@
+/*!
+节点发现响应的微基准
+
+对比每次收到发现请求时重新生成密钥并序列化节点信息，与使用`NodeInfoCache`缓存的序列化结果。
+运行：`cargo bench --bench discovery`
+*/
+
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use vpnet::{CryptoAlgorithm, CryptoContext, MessageType, NodeInfo, NodeInfoCache, PacketBuilder};
+
+const REQUESTS: u32 = 10_000;
+
+fn local_info(public_key: Vec<u8>) -> NodeInfo {
+    NodeInfo {
+        node_id: "bench-node".to_string(),
+        node_name: "Bench Node".to_string(),
+        public_key,
+        address: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 51820),
+        virtual_ip: "10.0.0.1".to_string(),
+        subnet: "255.255.255.0".to_string(),
+        online: true,
+        last_seen: 0,
+        capabilities: 0,
+        geographic_location: None,
+        tags: HashMap::new(),
+    }
+}
+
+/// 每个请求来自不同的IP，避免被限速
+fn source(i: u32) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + i))
+}
+
+/// 原来的做法：每次生成新密钥并重新序列化
+fn uncached() -> Duration {
+    let mut crypto = CryptoContext::new(&[7u8; 32], CryptoAlgorithm::AesGcm256);
+    let start = Instant::now();
+    for _ in 0..REQUESTS {
+        let info = local_info(crypto.generate_key(CryptoAlgorithm::AesGcm256));
+        let packet = PacketBuilder::new(MessageType::NodeInfo, serde_json::to_vec(&info).unwrap()).build();
+        black_box(serde_json::to_vec(&packet).unwrap());
+    }
+    start.elapsed()
+}
+
+/// 使用缓存的序列化结果
+fn cached() -> Duration {
+    let mut cache = NodeInfoCache::new(local_info(vec![1u8; 32])).unwrap();
+    let start = Instant::now();
+    for i in 0..REQUESTS {
+        black_box(cache.response_for(source(i)));
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let uncached = uncached();
+    let cached = cached();
+    println!("{} discovery requests", REQUESTS);
+    println!("  uncached: {:?} ({:?}/request)", uncached, uncached / REQUESTS);
+    println!("  cached:   {:?} ({:?}/request)", cached, cached / REQUESTS);
+    println!("  speedup:  {:.1}x", uncached.as_secs_f64() / cached.as_secs_f64());
+}
@@ -0,0 +1,90 @@
+/*!
+会话恢复的基准
+
+本机两个UDP节点，客户端正常断开后立即重新连接，对比携带会话票据恢复会话与完整握手
+（X25519密钥交换）从发出握手请求到服务端完成握手的耗时。
+运行：`cargo bench --bench handshake_resumption`
+*/
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use criterion::{criterion_group, criterion_main, Criterion};
+use tokio::runtime::Runtime;
+use tokio::sync::Mutex;
+use vpnet::{NetworkManager, NodeStatus, SessionTicketKeys};
+
+const SERVER_ID: &str = "bench-server";
+const CLIENT_ID: &str = "bench-client";
+
+/// 等待会话票据的最长时间
+const TICKET_WAIT: Duration = Duration::from_secs(1);
+
+/// 启动服务端和客户端并完成首次握手，`tickets`为服务端是否启用会话恢复
+async fn connected_pair(tickets: bool) -> (NetworkManager, NetworkManager) {
+    let mut server = NetworkManager::new(
+        vec!["127.0.0.1:0".parse().unwrap()],
+        SERVER_ID.to_string(),
+        "Bench Server".to_string(),
+        vec![0u8; 32]
+    ).unwrap();
+    if tickets {
+        let keys = SessionTicketKeys::new(Duration::from_secs(SessionTicketKeys::DEFAULT_LIFETIME)).unwrap();
+        server.set_session_tickets(Arc::new(Mutex::new(keys)));
+    }
+    server.start().await;
+
+    let client = NetworkManager::new(
+        vec!["127.0.0.1:0".parse().unwrap()],
+        CLIENT_ID.to_string(),
+        "Bench Client".to_string(),
+        vec![1u8; 32]
+    ).unwrap();
+    client.start().await;
+
+    reconnect(&server, &client).await;
+    while client.get_peer(SERVER_ID).await.is_none() {
+        tokio::task::yield_now().await;
+    }
+    (server, client)
+}
+
+/// 等待服务端记录的客户端状态变为`status`
+async fn wait_for_status(server: &NetworkManager, status: NodeStatus) {
+    while !server.get_peer(CLIENT_ID).await.is_some_and(|peer| peer.status == status) {
+        tokio::task::yield_now().await;
+    }
+}
+
+/// 客户端发出握手请求，返回服务端将其恢复在线所用的时间
+async fn reconnect(server: &NetworkManager, client: &NetworkManager) -> Duration {
+    let start = Instant::now();
+    client.send_handshake_request(server.listen_addrs()[0], None, None).await.unwrap();
+    wait_for_status(server, NodeStatus::Online).await;
+    start.elapsed()
+}
+
+fn handshakes(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("handshake");
+    for (name, tickets) in [("full", false), ("resumed", true)] {
+        let (server, client) = runtime.block_on(connected_pair(tickets));
+        group.bench_function(name, |b| b.iter_custom(|iters| runtime.block_on(async {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                // 断开不计时。启用会话恢复时客户端收到票据，重新握手时携带
+                if tickets {
+                    assert!(client.disconnect(SERVER_ID, "bench", TICKET_WAIT).await.unwrap());
+                } else {
+                    client.close_connection(SERVER_ID, "bench").await.unwrap();
+                }
+                wait_for_status(&server, NodeStatus::Offline).await;
+                total += reconnect(&server, &client).await;
+            }
+            total
+        })));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, handshakes);
+criterion_main!(benches);
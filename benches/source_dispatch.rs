@@ -0,0 +1,90 @@
+/*!
+数据转发源IP分发的微基准
+
+4个接收任务并发处理100 000个目标为本节点的数据转发，对比每个数据包持有写锁
+在节点表中查找源节点虚拟IP，与只持有读锁确认中继后直接使用预填源IP的耗时。
+运行：`cargo bench --bench source_dispatch`
+*/
+
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use vpnet::Peer;
+
+const PACKETS: usize = 100_000;
+const TASKS: usize = 4;
+const PEERS: u8 = 16;
+
+type Peers = Arc<RwLock<HashMap<String, Peer>>>;
+
+/// 节点表，`peer-0`为上游服务端
+fn peers() -> Peers {
+    let mut peers = HashMap::new();
+    for i in 0..PEERS {
+        let node_id = format!("peer-{}", i);
+        let mut peer = Peer::new(
+            node_id.clone(),
+            node_id.clone(),
+            relay_addr(i),
+            format!("10.0.0.{}", 10 + i),
+            vec![0u8; 32],
+            0
+        );
+        peer.upstream = i == 0;
+        peers.insert(node_id, peer);
+    }
+    Arc::new(RwLock::new(peers))
+}
+
+fn relay_addr(i: u8) -> SocketAddr {
+    SocketAddr::from(([192, 0, 2, 10 + i], 51820))
+}
+
+/// 每个数据包持有写锁查找源节点并解析其虚拟IP
+async fn lookup(peers: Peers, source: String) {
+    for _ in 0..PACKETS / TASKS {
+        let mut peers_guard = peers.write().await;
+        let peer = peers_guard.get_mut(black_box(&source)).unwrap();
+        black_box(peer.virtual_ip.parse::<Ipv4Addr>().unwrap());
+    }
+}
+
+/// 每个数据包持有读锁确认来自中继，直接使用预填的源IP
+async fn prefilled(peers: Peers, source_ip: Ipv4Addr) {
+    let relay = relay_addr(0);
+    for _ in 0..PACKETS / TASKS {
+        let peers_guard = peers.read().await;
+        assert!(peers_guard.values().any(|peer| peer.is_at(black_box(relay)) && peer.can_relay()));
+        black_box(source_ip);
+    }
+}
+
+async fn run_tasks<F>(task: impl Fn() -> F) -> Duration
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    let start = Instant::now();
+    let handles: Vec<_> = (0..TASKS).map(|_| tokio::spawn(task())).collect();
+    for handle in handles {
+        handle.await.unwrap();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (lookup, prefilled) = runtime.block_on(async {
+        let peers = peers();
+        let lookup = run_tasks(|| lookup(peers.clone(), "peer-5".to_string())).await;
+        let prefilled = run_tasks(|| prefilled(peers.clone(), Ipv4Addr::new(10, 0, 0, 15))).await;
+        (lookup, prefilled)
+    });
+
+    println!("{} data forwards on {} tasks, {} peers", PACKETS, TASKS, PEERS);
+    println!("  lookup:     {:?}/packet", lookup / PACKETS as u32);
+    println!("  prefilled:  {:?}/packet", prefilled / PACKETS as u32);
+    println!("  speedup:    {:.1}x", lookup.as_secs_f64() / prefilled.as_secs_f64());
+}
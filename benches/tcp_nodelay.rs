@@ -0,0 +1,101 @@
+/*!
+TCP隧道小数据包时延的微基准
+
+本机TCP连接上按隧道的分帧方式（长度前缀和帧体分两次写入）发送10 000个32字节的数据帧，
+对端原样回送，对比设置与不设置`TCP_NODELAY`时每帧的往返时延。
+运行：`cargo bench --bench tcp_nodelay`
+*/
+
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use vpnet::TcpStreamConfig;
+
+const PACKETS: usize = 10_000;
+const PACKET_SIZE: usize = 32;
+/// 相邻两帧的发送间隔，模拟持续的小控制消息而不是一次性批量写入
+const SEND_INTERVAL: Duration = Duration::from_micros(50);
+
+/// 按隧道的方式写入一帧：先写长度前缀，再写帧体
+async fn write_frame(stream: &mut (impl AsyncWriteExt + Unpin), frame: &[u8]) {
+    stream.write_all(&(frame.len() as u16).to_be_bytes()).await.unwrap();
+    stream.write_all(frame).await.unwrap();
+}
+
+async fn read_frame(stream: &mut (impl AsyncReadExt + Unpin), frame: &mut [u8; PACKET_SIZE]) {
+    let len = stream.read_u16().await.unwrap() as usize;
+    assert_eq!(len, PACKET_SIZE);
+    stream.read_exact(frame).await.unwrap();
+}
+
+/// 建立一对按`config`设置的本机连接，对端回送收到的每一帧
+async fn echo_connection(config: TcpStreamConfig) -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        config.apply(&stream).unwrap();
+        let (mut reader, mut writer) = stream.into_split();
+        let mut frame = [0u8; PACKET_SIZE];
+        for _ in 0..PACKETS {
+            read_frame(&mut reader, &mut frame).await;
+            write_frame(&mut writer, &frame).await;
+        }
+    });
+    
+    let stream = TcpStream::connect(addr).await.unwrap();
+    config.apply(&stream).unwrap();
+    stream
+}
+
+/// 发送所有帧并返回每帧的往返时延和总耗时
+async fn run(nodelay: bool) -> (Vec<Duration>, Duration) {
+    let config = TcpStreamConfig { nodelay, ..TcpStreamConfig::default() };
+    let (mut reader, mut writer) = echo_connection(config).await.into_split();
+    let start = Instant::now();
+    
+    // 帧体开头记录相对`start`的发送时间（纳秒）
+    let sender = tokio::spawn(async move {
+        for i in 0..PACKETS {
+            while start.elapsed() < SEND_INTERVAL * i as u32 {
+                tokio::task::yield_now().await;
+            }
+            let mut frame = [0u8; PACKET_SIZE];
+            frame[..8].copy_from_slice(&(start.elapsed().as_nanos() as u64).to_be_bytes());
+            write_frame(&mut writer, &frame).await;
+        }
+        writer
+    });
+    
+    let mut rtts = Vec::with_capacity(PACKETS);
+    let mut frame = [0u8; PACKET_SIZE];
+    for _ in 0..PACKETS {
+        read_frame(&mut reader, &mut frame).await;
+        let sent = Duration::from_nanos(u64::from_be_bytes(frame[..8].try_into().unwrap()));
+        rtts.push(start.elapsed() - sent);
+    }
+    let total = start.elapsed();
+    let _writer = sender.await.unwrap();
+    
+    rtts.sort();
+    (rtts, total)
+}
+
+fn report(label: &str, rtts: &[Duration], total: Duration) {
+    let mean = rtts.iter().sum::<Duration>() / rtts.len() as u32;
+    let percentile = |p: usize| rtts[(rtts.len() * p / 100).min(rtts.len() - 1)];
+    println!(
+        "  {}: total {:?}, mean {:?}, p50 {:?}, p99 {:?}, max {:?}",
+        label, total, mean, percentile(50), percentile(99), rtts[rtts.len() - 1]
+    );
+}
+
+fn main() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (nagle, nagle_total) = runtime.block_on(run(false));
+    let (nodelay, nodelay_total) = runtime.block_on(run(true));
+    
+    println!("{} frames of {} bytes, one every {:?}", PACKETS, PACKET_SIZE, SEND_INTERVAL);
+    report("Nagle      ", &nagle, nagle_total);
+    report("TCP_NODELAY", &nodelay, nodelay_total);
+}
@@ -0,0 +1,266 @@
+/*!
+VPNet退避策略模块
+
+心跳、重连等周期性或重试操作的间隔策略，包括：
+- 固定、线性、指数和斐波那契间隔
+- 以迭代器形式依次产生等待时间
+- 配置文件中以`"30s"`形式的字符串表示时长
+*/
+
+use std::time::Duration;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::config::duration;
+use crate::protocol::constants;
+
+/// 退避策略
+///
+/// 配置文件中的时长与其他时长字段相同，写作`"30s"`形式的字符串，
+/// 例如`{ exponential = { start = "1s", factor = 2.0, max = "1m", jitter = true } }`；旧配置中以秒为单位的数字仍然可以读取。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum BackoffStrategy {
+    /// 固定间隔
+    Constant(#[serde(with = "duration")] Duration),
+    /// 从`start`开始每次增加`step`，不超过`max`
+    Linear {
+        #[serde(with = "duration")]
+        start: Duration,
+        #[serde(with = "duration")]
+        step: Duration,
+        #[serde(with = "duration")]
+        max: Duration,
+    },
+    /// 从`start`开始每次乘以`factor`，不超过`max`；`jitter`时在`[间隔/2, 间隔]`内随机取值
+    Exponential {
+        #[serde(with = "duration")]
+        start: Duration,
+        factor: f64,
+        #[serde(with = "duration")]
+        max: Duration,
+        #[serde(default)]
+        jitter: bool,
+    },
+    /// 按斐波那契数列增长（`start`、`start`、`2*start`、`3*start`...），不超过`max`
+    Fibonacci {
+        #[serde(with = "duration")]
+        start: Duration,
+        #[serde(with = "duration")]
+        max: Duration,
+    },
+}
+
+impl Default for BackoffStrategy {
+    /// 默认按心跳间隔固定等待
+    fn default() -> Self {
+        BackoffStrategy::Constant(Duration::from_secs(constants::HEARTBEAT_INTERVAL))
+    }
+}
+
+impl BackoffStrategy {
+    /// 依次产生每次的等待时间，迭代器不会结束，需要限制次数时使用`take`
+    pub fn iterator(&self) -> impl Iterator<Item = Duration> + Send + 'static {
+        BackoffIter {
+            strategy: self.clone(),
+            attempt: 0,
+            fibonacci: None,
+        }
+    }
+    
+    /// 可能产生的最大等待时间
+    pub fn max_delay(&self) -> Duration {
+        match *self {
+            BackoffStrategy::Constant(delay) => delay,
+            BackoffStrategy::Linear { max, .. }
+            | BackoffStrategy::Exponential { max, .. }
+            | BackoffStrategy::Fibonacci { max, .. } => max,
+        }
+    }
+    
+    /// 检查参数：间隔必须大于0，`max`不小于`start`，`factor`不小于1
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let (start, max) = match *self {
+            BackoffStrategy::Constant(delay) => (delay, delay),
+            BackoffStrategy::Linear { start, max, .. } => (start, max),
+            BackoffStrategy::Exponential { start, factor, max, .. } => {
+                if !(factor.is_finite() && factor >= 1.0) {
+                    return Err("factor must be a finite number not less than 1");
+                }
+                (start, max)
+            }
+            BackoffStrategy::Fibonacci { start, max } => (start, max),
+        };
+        
+        if start.is_zero() {
+            return Err("interval must be greater than 0");
+        }
+        if max < start {
+            return Err("max must not be less than start");
+        }
+        Ok(())
+    }
+}
+
+/// 退避策略的等待时间迭代器
+struct BackoffIter {
+    strategy: BackoffStrategy,
+    attempt: u32,
+    /// 斐波那契策略的当前项和下一项
+    fibonacci: Option<(Duration, Duration)>,
+}
+
+impl Iterator for BackoffIter {
+    type Item = Duration;
+    
+    fn next(&mut self) -> Option<Duration> {
+        let attempt = self.attempt;
+        self.attempt = self.attempt.saturating_add(1);
+        
+        let delay = match self.strategy {
+            BackoffStrategy::Constant(delay) => delay,
+            BackoffStrategy::Linear { start, step, max } => {
+                start.saturating_add(step.saturating_mul(attempt)).min(max)
+            }
+            BackoffStrategy::Exponential { start, factor, max, jitter } => {
+                // 在浮点数上计算并先截断到上限，避免指数溢出
+                let secs = (start.as_secs_f64() * factor.powf(attempt as f64)).min(max.as_secs_f64());
+                let secs = if jitter {
+                    rand::thread_rng().gen_range(secs / 2.0..=secs)
+                } else {
+                    secs
+                };
+                Duration::try_from_secs_f64(secs).unwrap_or(max).min(max)
+            }
+            BackoffStrategy::Fibonacci { start, max } => {
+                let (current, next) = self.fibonacci.unwrap_or((start, start));
+                self.fibonacci = Some((next, current.saturating_add(next).min(max)));
+                current.min(max)
+            }
+        };
+        Some(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    
+    const ATTEMPTS: usize = 64;
+    
+    /// 毫秒级的起始间隔和不小于起始间隔的上限
+    fn start_and_max() -> impl Strategy<Value = (Duration, Duration)> {
+        (1u64..10_000, 0u64..1_000_000).prop_map(|(start, extra)| {
+            (Duration::from_millis(start), Duration::from_millis(start + extra))
+        })
+    }
+    
+    /// 任意合法的退避策略
+    fn strategy() -> impl Strategy<Value = BackoffStrategy> {
+        prop_oneof![
+            (1u64..1_000_000).prop_map(|ms| BackoffStrategy::Constant(Duration::from_millis(ms))),
+            (start_and_max(), 0u64..100_000).prop_map(|((start, max), step)| {
+                BackoffStrategy::Linear { start, step: Duration::from_millis(step), max }
+            }),
+            (start_and_max(), 1.0f64..10.0, any::<bool>()).prop_map(|((start, max), factor, jitter)| {
+                BackoffStrategy::Exponential { start, factor, max, jitter }
+            }),
+            start_and_max().prop_map(|(start, max)| BackoffStrategy::Fibonacci { start, max }),
+        ]
+    }
+    
+    /// 可能产生的最小等待时间，抖动时为起始间隔的一半（浮点换算可能少1纳秒）
+    fn start_of(strategy: &BackoffStrategy) -> Duration {
+        match *strategy {
+            BackoffStrategy::Constant(delay) => delay,
+            BackoffStrategy::Exponential { start, jitter: true, .. } => {
+                (start / 2).saturating_sub(Duration::from_nanos(1))
+            }
+            BackoffStrategy::Linear { start, .. }
+            | BackoffStrategy::Exponential { start, .. }
+            | BackoffStrategy::Fibonacci { start, .. } => start,
+        }
+    }
+    
+    proptest! {
+        #[test]
+        fn delays_stay_within_bounds(strategy in strategy()) {
+            prop_assert!(strategy.validate().is_ok());
+            let min = start_of(&strategy);
+            let max = strategy.max_delay();
+            for delay in strategy.iterator().take(ATTEMPTS) {
+                prop_assert!(delay >= min, "{:?} below {:?}", delay, min);
+                prop_assert!(delay <= max, "{:?} above {:?}", delay, max);
+            }
+        }
+        
+        #[test]
+        fn delays_without_jitter_never_decrease(strategy in strategy()) {
+            prop_assume!(!matches!(strategy, BackoffStrategy::Exponential { jitter: true, .. }));
+            let delays: Vec<Duration> = strategy.iterator().take(ATTEMPTS).collect();
+            prop_assert!(delays.windows(2).all(|pair| pair[0] <= pair[1]));
+        }
+        
+        #[test]
+        fn delays_reach_the_max_eventually(((start, max), factor) in (start_and_max(), 1.5f64..10.0)) {
+            // 指数增长在64次内必然到达上限
+            let strategy = BackoffStrategy::Exponential { start, factor, max, jitter: false };
+            prop_assert_eq!(strategy.iterator().nth(ATTEMPTS), Some(max));
+        }
+    }
+    
+    #[test]
+    fn fibonacci_follows_the_sequence() {
+        let strategy = BackoffStrategy::Fibonacci { start: Duration::from_secs(1), max: Duration::from_secs(10) };
+        let delays: Vec<u64> = strategy.iterator().take(7).map(|d| d.as_secs()).collect();
+        assert_eq!(delays, vec![1, 1, 2, 3, 5, 8, 10]);
+    }
+    
+    #[test]
+    fn validate_rejects_invalid_parameters() {
+        assert!(BackoffStrategy::Constant(Duration::ZERO).validate().is_err());
+        let shrinking = BackoffStrategy::Linear {
+            start: Duration::from_secs(10),
+            step: Duration::from_secs(1),
+            max: Duration::from_secs(5),
+        };
+        assert!(shrinking.validate().is_err());
+        let decaying = BackoffStrategy::Exponential {
+            start: Duration::from_secs(1),
+            factor: 0.5,
+            max: Duration::from_secs(5),
+            jitter: false,
+        };
+        assert!(decaying.validate().is_err());
+    }
+    
+    #[test]
+    fn strategy_reads_durations_from_config() {
+        let strategy: BackoffStrategy = toml::from_str::<toml::Table>(
+            "heartbeat = { linear = { start = \"500ms\", step = \"0.5s\", max = \"1m\" } }"
+        ).unwrap()["heartbeat"].clone().try_into().unwrap();
+        assert_eq!(strategy, BackoffStrategy::Linear {
+            start: Duration::from_millis(500),
+            step: Duration::from_millis(500),
+            max: Duration::from_secs(60),
+        });
+        assert_eq!(
+            toml::to_string(&strategy).unwrap(),
+            "[linear]\nstart = \"500ms\"\nstep = \"500ms\"\nmax = \"1m\"\n"
+        );
+    }
+    
+    #[test]
+    fn strategy_reads_legacy_seconds_from_config() {
+        let strategy: BackoffStrategy = serde_json::from_str(
+            r#"{"exponential":{"start":0.5,"factor":2.0,"max":60}}"#
+        ).unwrap();
+        assert_eq!(strategy, BackoffStrategy::Exponential {
+            start: Duration::from_millis(500),
+            factor: 2.0,
+            max: Duration::from_secs(60),
+            jitter: false,
+        });
+    }
+}
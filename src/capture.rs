@@ -0,0 +1,530 @@
+/*!
+VPNet抓包模块
+
+将虚拟网卡收到的数据包写入pcap文件，供Wireshark、tcpdump等工具分析，包括：
+- 以pcap文件格式记录数据包，链路类型按设备模式选择
+- 按BPF表达式（例如`tcp dst port 443`）过滤，只记录匹配的数据包
+- 运行中替换过滤表达式，无需重启设备
+- 文件写入在后台线程中进行，不阻塞设备的接收任务
+- 文件超过大小上限时轮转，只保留最近的若干个文件
+
+BPF表达式由libpcap编译，需要以`pcap`特性编译。
+*/
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{PoisonError, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+use crate::virtual_device::DeviceMode;
+
+/// pcap文件魔数，时间戳精度为微秒
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+
+/// 每个数据包最多记录的字节数
+const SNAPLEN: u32 = 65535;
+
+/// pcap文件头长度
+const FILE_HEADER_LEN: u64 = 24;
+
+/// 每个数据包记录头的长度
+const RECORD_HEADER_LEN: u64 = 16;
+
+/// 等待写入的数据包数上限，写入跟不上时丢弃新的数据包
+const WRITE_QUEUE_LEN: usize = 4096;
+
+/// BPF过滤表达式错误
+#[derive(Error, Debug)]
+pub enum BpfError {
+    #[error("Invalid BPF expression '{expression}': {reason}")]
+    Invalid {
+        expression: String,
+        reason: String,
+    },
+    
+    #[error("BPF filters require the pcap feature")]
+    Unsupported,
+}
+
+/// 编译后的BPF过滤程序
+pub struct BpfProgram {
+    expression: String,
+    #[cfg(feature = "pcap")]
+    program: pcap::BpfProgram,
+}
+
+impl BpfProgram {
+    /// 编译前的过滤表达式
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+    
+    /// 数据包是否匹配过滤表达式
+    pub fn matches(&self, packet: &[u8]) -> bool {
+        #[cfg(feature = "pcap")]
+        {
+            self.program.filter(packet)
+        }
+        #[cfg(not(feature = "pcap"))]
+        {
+            let _ = packet;
+            true
+        }
+    }
+}
+
+impl std::fmt::Debug for BpfProgram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BpfProgram").field("expression", &self.expression).finish()
+    }
+}
+
+/// BPF过滤表达式编译器
+pub struct BpfFilter;
+
+impl BpfFilter {
+    /// 为三层设备（原始IP数据包）编译过滤表达式
+    pub fn compile(expression: &str) -> Result<BpfProgram, BpfError> {
+        Self::compile_for(DeviceMode::Tun, expression)
+    }
+    
+    /// 为指定工作模式的设备编译过滤表达式
+    #[cfg(feature = "pcap")]
+    pub fn compile_for(mode: DeviceMode, expression: &str) -> Result<BpfProgram, BpfError> {
+        let invalid = |e: pcap::Error| BpfError::Invalid {
+            expression: expression.to_string(),
+            reason: e.to_string(),
+        };
+        let capture = pcap::Capture::dead(pcap::Linktype(dlt(mode))).map_err(invalid)?;
+        let program = capture.compile(expression, true).map_err(invalid)?;
+        Ok(BpfProgram {
+            expression: expression.to_string(),
+            program,
+        })
+    }
+    
+    /// 为指定工作模式的设备编译过滤表达式
+    #[cfg(not(feature = "pcap"))]
+    pub fn compile_for(mode: DeviceMode, expression: &str) -> Result<BpfProgram, BpfError> {
+        let _ = (mode, expression);
+        Err(BpfError::Unsupported)
+    }
+}
+
+/// pcap文件的链路类型
+fn link_type(mode: DeviceMode) -> u32 {
+    match mode {
+        // LINKTYPE_ETHERNET
+        DeviceMode::Tap => 1,
+        // LINKTYPE_RAW，不带链路层头部的IP数据包
+        DeviceMode::Tun => 101,
+    }
+}
+
+/// libpcap的数据链路类型，`pcap_open_dead`使用DLT值而不是文件中的LINKTYPE值
+#[cfg(feature = "pcap")]
+fn dlt(mode: DeviceMode) -> i32 {
+    match mode {
+        // DLT_EN10MB
+        DeviceMode::Tap => 1,
+        // DLT_RAW
+        DeviceMode::Tun => 12,
+    }
+}
+
+/// 抓包文件的轮转设置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureRotation {
+    /// 单个文件的大小上限（字节），写入下一个数据包会超过上限时轮转
+    pub max_file_bytes: u64,
+    /// 最多保留的文件数，包括正在写入的文件
+    ///
+    /// 轮转时`<路径>`改名为`<路径>.1`，原有的编号依次加1，超出数量的最旧文件被删除。
+    pub max_files: u32,
+}
+
+/// 数据包抓取
+///
+/// 设置了过滤表达式时只记录匹配的数据包，未设置时记录全部数据包。
+/// 匹配的数据包交给后台线程写入，等待写入的数据包清空时刷新文件，抓包过程中即可读取文件。
+pub struct PacketCapture {
+    mode: DeviceMode,
+    filter: RwLock<Option<BpfProgram>>,
+    writer: mpsc::Sender<WriteRequest>,
+    /// 写入队列已满而丢弃的数据包数
+    dropped: AtomicU64,
+}
+
+/// 交给写入线程的请求
+enum WriteRequest {
+    Packet {
+        timestamp: Duration,
+        original_len: u32,
+        data: Vec<u8>,
+    },
+    /// 写完此前的数据包并刷新文件后通知
+    Flush(oneshot::Sender<()>),
+}
+
+impl PacketCapture {
+    /// 创建pcap文件，已存在时覆盖，文件大小不限
+    ///
+    /// 需要在tokio运行时中调用，写入线程由`spawn_blocking`启动。
+    pub fn create(path: impl AsRef<Path>, mode: DeviceMode) -> io::Result<Self> {
+        Self::with_rotation(path, mode, None)
+    }
+    
+    /// 创建pcap文件，已存在时覆盖，按`rotation`轮转
+    ///
+    /// 需要在tokio运行时中调用，写入线程由`spawn_blocking`启动。
+    pub fn with_rotation(
+        path: impl AsRef<Path>,
+        mode: DeviceMode,
+        rotation: Option<CaptureRotation>
+    ) -> io::Result<Self> {
+        let file = CaptureFile::create(path.as_ref().to_path_buf(), mode, rotation)?;
+        let (writer, requests) = mpsc::channel(WRITE_QUEUE_LEN);
+        tokio::task::spawn_blocking(move || file.run(requests));
+        
+        Ok(Self {
+            mode,
+            filter: RwLock::new(None),
+            writer,
+            dropped: AtomicU64::new(0),
+        })
+    }
+    
+    /// 替换过滤表达式，`None`或空表达式表示记录全部数据包
+    ///
+    /// 表达式无法编译时保留原来的过滤表达式。
+    pub fn set_filter(&self, expression: Option<&str>) -> Result<(), BpfError> {
+        let program = match expression.map(str::trim).filter(|expression| !expression.is_empty()) {
+            Some(expression) => Some(BpfFilter::compile_for(self.mode, expression)?),
+            None => None,
+        };
+        match &program {
+            Some(program) => log::info!("Packet capture filter set to '{}'", program.expression()),
+            None => log::info!("Packet capture filter cleared"),
+        }
+        *self.filter.write().unwrap_or_else(PoisonError::into_inner) = program;
+        Ok(())
+    }
+    
+    /// 当前的过滤表达式
+    pub fn filter_expression(&self) -> Option<String> {
+        self.filter.read().unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map(|program| program.expression().to_string())
+    }
+    
+    /// 记录数据包，返回数据包是否匹配过滤表达式并进入写入队列
+    ///
+    /// 不等待写入完成；写入队列已满时丢弃数据包并计入`dropped`。
+    pub fn record(&self, packet: &[u8]) -> bool {
+        let matched = self.filter.read().unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .is_none_or(|program| program.matches(packet));
+        if !matched {
+            return false;
+        }
+        
+        let request = WriteRequest::Packet {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default(),
+            original_len: packet.len() as u32,
+            data: packet[..packet.len().min(SNAPLEN as usize)].to_vec(),
+        };
+        if self.writer.try_send(request).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+    
+    /// 写入队列已满而丢弃的数据包数
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+    
+    /// 等待此前记录的数据包写入文件并刷新
+    pub async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.writer.send(WriteRequest::Flush(done)).await.is_ok() {
+            let _ = flushed.await;
+        }
+    }
+}
+
+/// 写入线程持有的抓包文件
+struct CaptureFile {
+    path: PathBuf,
+    mode: DeviceMode,
+    rotation: Option<CaptureRotation>,
+    writer: BufWriter<File>,
+    /// 当前文件已写入的字节数
+    written: u64,
+}
+
+impl CaptureFile {
+    fn create(path: PathBuf, mode: DeviceMode, rotation: Option<CaptureRotation>) -> io::Result<Self> {
+        let writer = Self::open(&path, mode)?;
+        Ok(Self {
+            path,
+            mode,
+            rotation,
+            writer,
+            written: FILE_HEADER_LEN,
+        })
+    }
+    
+    /// 创建文件并写入pcap文件头
+    fn open(path: &Path, mode: DeviceMode) -> io::Result<BufWriter<File>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&PCAP_MAGIC.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&4u16.to_le_bytes())?;
+        // 时区偏移和时间戳精度，均为0
+        writer.write_all(&[0u8; 8])?;
+        writer.write_all(&SNAPLEN.to_le_bytes())?;
+        writer.write_all(&link_type(mode).to_le_bytes())?;
+        writer.flush()?;
+        Ok(writer)
+    }
+    
+    /// 依次处理写入请求，队列清空时刷新文件，`PacketCapture`被丢弃后结束
+    fn run(mut self, mut requests: mpsc::Receiver<WriteRequest>) {
+        while let Some(mut request) = requests.blocking_recv() {
+            loop {
+                match request {
+                    WriteRequest::Packet { timestamp, original_len, data } => {
+                        if let Err(e) = self.write(timestamp, original_len, &data) {
+                            log::warn!("Failed to write captured packet to {}: {}", self.path.display(), e);
+                        }
+                    }
+                    WriteRequest::Flush(done) => {
+                        self.flush();
+                        let _ = done.send(());
+                    }
+                }
+                match requests.try_recv() {
+                    Ok(next) => request = next,
+                    Err(_) => break,
+                }
+            }
+            self.flush();
+        }
+        self.flush();
+    }
+    
+    fn flush(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            log::warn!("Failed to flush packet capture {}: {}", self.path.display(), e);
+        }
+    }
+    
+    fn write(&mut self, timestamp: Duration, original_len: u32, data: &[u8]) -> io::Result<()> {
+        let len = RECORD_HEADER_LEN + data.len() as u64;
+        if let Some(rotation) = self.rotation {
+            // 单个数据包超过上限时仍写入空文件，不无限轮转
+            if self.written > FILE_HEADER_LEN && self.written + len > rotation.max_file_bytes {
+                self.rotate(rotation.max_files)?;
+            }
+        }
+        
+        self.writer.write_all(&(timestamp.as_secs() as u32).to_le_bytes())?;
+        self.writer.write_all(&timestamp.subsec_micros().to_le_bytes())?;
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&original_len.to_le_bytes())?;
+        self.writer.write_all(data)?;
+        self.written += len;
+        Ok(())
+    }
+    
+    /// 将当前文件改名为`<路径>.1`，原有的编号依次加1，删除超出数量的最旧文件，再创建新文件
+    fn rotate(&mut self, max_files: u32) -> io::Result<()> {
+        self.writer.flush()?;
+        if max_files > 1 {
+            remove_if_exists(&self.numbered(max_files - 1))?;
+            for index in (1..max_files - 1).rev() {
+                rename_if_exists(&self.numbered(index), &self.numbered(index + 1))?;
+            }
+            fs::rename(&self.path, self.numbered(1))?;
+        }
+        self.writer = Self::open(&self.path, self.mode)?;
+        self.written = FILE_HEADER_LEN;
+        Ok(())
+    }
+    
+    /// 轮转后的第`index`个文件，编号越大越旧
+    fn numbered(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn capture_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("vpnet-capture-test-{}-{}", std::process::id(), rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+    
+    /// 解析pcap文件，返回链路类型和各数据包的内容
+    fn read_pcap(path: &Path) -> (u32, Vec<Vec<u8>>) {
+        let data = fs::read(path).unwrap();
+        assert_eq!(u32::from_le_bytes(data[0..4].try_into().unwrap()), PCAP_MAGIC);
+        let link_type = u32::from_le_bytes(data[20..24].try_into().unwrap());
+        
+        let mut packets = Vec::new();
+        let mut offset = FILE_HEADER_LEN as usize;
+        while offset < data.len() {
+            let captured = u32::from_le_bytes(data[offset + 8..offset + 12].try_into().unwrap()) as usize;
+            let start = offset + RECORD_HEADER_LEN as usize;
+            packets.push(data[start..start + captured].to_vec());
+            offset = start + captured;
+        }
+        (link_type, packets)
+    }
+    
+    /// 一个IPv4 TCP数据包，只填写过滤表达式用到的字段
+    fn tcp_packet(dst_port: u16) -> Vec<u8> {
+        let mut packet = vec![0u8; 40];
+        packet[0] = 0x45;
+        packet[2..4].copy_from_slice(&40u16.to_be_bytes());
+        packet[8] = 64;
+        packet[9] = 6;
+        packet[12..16].copy_from_slice(&[10, 0, 0, 1]);
+        packet[16..20].copy_from_slice(&[10, 0, 0, 2]);
+        packet[20..22].copy_from_slice(&40000u16.to_be_bytes());
+        packet[22..24].copy_from_slice(&dst_port.to_be_bytes());
+        packet[32] = 0x50;
+        packet
+    }
+    
+    #[tokio::test]
+    async fn captured_packets_are_written_in_pcap_format() {
+        let path = capture_path("tun.pcap");
+        let capture = PacketCapture::create(&path, DeviceMode::Tun).unwrap();
+        for port in [80, 443, 22] {
+            assert!(capture.record(&tcp_packet(port)));
+        }
+        capture.flush().await;
+        
+        let (link_type, packets) = read_pcap(&path);
+        assert_eq!(link_type, 101);
+        assert_eq!(packets, vec![tcp_packet(80), tcp_packet(443), tcp_packet(22)]);
+        assert_eq!(capture.dropped(), 0);
+    }
+    
+    #[tokio::test]
+    async fn capture_files_rotate_and_keep_the_newest() {
+        let path = capture_path("rotate.pcap");
+        let rotation = CaptureRotation { max_file_bytes: 200, max_files: 3 };
+        let capture = PacketCapture::with_rotation(&path, DeviceMode::Tun, Some(rotation)).unwrap();
+        for i in 0..20u8 {
+            capture.record(&[i; 40]);
+        }
+        capture.flush().await;
+        
+        let numbered = |index: u32| PathBuf::from(format!("{}.{}", path.display(), index));
+        assert!(!numbered(3).exists());
+        let mut kept = Vec::new();
+        for file in [numbered(2), numbered(1), path.clone()] {
+            assert!(fs::metadata(&file).unwrap().len() <= rotation.max_file_bytes);
+            let (link_type, packets) = read_pcap(&file);
+            assert_eq!(link_type, 101);
+            kept.extend(packets);
+        }
+        
+        // 每个文件容纳3个数据包，最旧的文件已被删除，剩下的是最近的数据包
+        let expected: Vec<Vec<u8>> = (20 - kept.len() as u8..20).map(|i| vec![i; 40]).collect();
+        assert_eq!(kept, expected);
+        assert!(kept.len() < 20);
+    }
+    
+    #[tokio::test]
+    async fn oversized_packets_are_truncated_to_the_snaplen() {
+        let path = capture_path("tap.pcap");
+        let capture = PacketCapture::create(&path, DeviceMode::Tap).unwrap();
+        capture.record(&vec![7u8; SNAPLEN as usize + 100]);
+        capture.flush().await;
+        
+        let data = fs::read(&path).unwrap();
+        let (link_type, packets) = read_pcap(&path);
+        assert_eq!(link_type, 1);
+        assert_eq!(packets[0].len(), SNAPLEN as usize);
+        let original_len = u32::from_le_bytes(data[36..40].try_into().unwrap());
+        assert_eq!(original_len, SNAPLEN + 100);
+    }
+    
+    #[cfg(not(feature = "pcap"))]
+    #[tokio::test]
+    async fn filters_are_rejected_without_libpcap() {
+        assert!(matches!(BpfFilter::compile("tcp dst port 443"), Err(BpfError::Unsupported)));
+        
+        let capture = PacketCapture::create(capture_path("nofilter.pcap"), DeviceMode::Tun).unwrap();
+        assert!(capture.set_filter(Some("tcp dst port 443")).is_err());
+        assert!(capture.filter_expression().is_none());
+        assert!(capture.record(&tcp_packet(80)));
+    }
+    
+    #[cfg(feature = "pcap")]
+    #[test]
+    fn valid_and_invalid_expressions_compile_as_expected() {
+        for mode in [DeviceMode::Tun, DeviceMode::Tap] {
+            assert!(BpfFilter::compile_for(mode, "tcp dst port 443").is_ok());
+            assert!(BpfFilter::compile_for(mode, "udp or icmp").is_ok());
+            assert!(matches!(
+                BpfFilter::compile_for(mode, "tcp dst port"),
+                Err(BpfError::Invalid { .. })
+            ));
+            assert!(matches!(
+                BpfFilter::compile_for(mode, "not-a-protocol"),
+                Err(BpfError::Invalid { .. })
+            ));
+        }
+    }
+    
+    #[cfg(feature = "pcap")]
+    #[tokio::test]
+    async fn only_matching_packets_appear_in_the_capture() {
+        let path = capture_path("filtered.pcap");
+        let capture = PacketCapture::create(&path, DeviceMode::Tun).unwrap();
+        capture.set_filter(Some("tcp dst port 443")).unwrap();
+        assert!(!capture.record(&tcp_packet(80)));
+        assert!(capture.record(&tcp_packet(443)));
+        
+        // 无法编译的表达式不替换原来的过滤
+        assert!(capture.set_filter(Some("tcp dst port")).is_err());
+        assert_eq!(capture.filter_expression().as_deref(), Some("tcp dst port 443"));
+        assert!(!capture.record(&tcp_packet(22)));
+        
+        capture.set_filter(None).unwrap();
+        assert!(capture.record(&tcp_packet(22)));
+        capture.flush().await;
+        
+        let (_, packets) = read_pcap(&path);
+        assert_eq!(packets, vec![tcp_packet(443), tcp_packet(22)]);
+    }
+}
@@ -0,0 +1,364 @@
+/*!
+VPNet版本兼容模块
+
+服务端按客户端在握手中报告的版本决定是否接受连接，包括：
+- 解析`主版本.次版本.修订号`格式的版本号
+- 按版本区间配置兼容、降级或不兼容
+- 降级的客户端关闭其不支持的功能，例如不协商负载压缩
+*/
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::protocol::constants;
+
+/// 语义化版本号，预发布和构建元数据部分不参与比较
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SemVer {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl SemVer {
+    /// 创建版本号
+    pub const fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self { major, minor, patch }
+    }
+}
+
+impl FromStr for SemVer {
+    type Err = &'static str;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let core = s.trim().trim_start_matches('v');
+        let core = core.split(['-', '+']).next().unwrap_or_default();
+        let mut parts = core.split('.');
+        let mut next = |required: bool| -> Result<u64, &'static str> {
+            match parts.next() {
+                Some(part) => part.parse().map_err(|_| "Invalid version number"),
+                None if required => Err("Invalid version number"),
+                None => Ok(0),
+            }
+        };
+        let version = Self::new(next(true)?, next(false)?, next(false)?);
+        if parts.next().is_some() {
+            return Err("Invalid version number");
+        }
+        Ok(version)
+    }
+}
+
+impl fmt::Display for SemVer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl PartialOrd for SemVer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SemVer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch).cmp(&(other.major, other.minor, other.patch))
+    }
+}
+
+impl Serialize for SemVer {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SemVer {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// 降级客户端可被关闭的功能
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum DisabledFeature {
+    /// 数据转发负载的LZ4压缩
+    Compression,
+    /// 定长二进制头部
+    RawFrame,
+    /// 数据转发头部中的源虚拟IP
+    SourceIp,
+    /// 凭会话票据恢复会话
+    SessionResume,
+}
+
+impl DisabledFeature {
+    /// 该功能对应的负载标志位，不由标志位协商的功能返回0
+    pub fn payload_flags(self) -> u8 {
+        match self {
+            Self::Compression => constants::FLAG_COMPRESSED,
+            Self::RawFrame => constants::FLAG_RAW_FRAME,
+            Self::SourceIp => constants::FLAG_SOURCE_IP,
+            Self::SessionResume => 0,
+        }
+    }
+}
+
+/// 客户端版本的兼容性
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compatibility {
+    /// 完全兼容
+    Compatible,
+    /// 可以连接，但需要关闭列出的功能
+    Degraded(Vec<DisabledFeature>),
+    /// 拒绝连接
+    Incompatible,
+}
+
+impl Compatibility {
+    /// 去掉被关闭功能后的负载标志位
+    pub fn restrict_payload_flags(&self, flags: u8) -> u8 {
+        match self {
+            Self::Degraded(features) => features.iter()
+                .fold(flags, |flags, feature| flags & !feature.payload_flags()),
+            _ => flags,
+        }
+    }
+    
+    /// 指定功能是否被关闭
+    pub fn disables(&self, feature: DisabledFeature) -> bool {
+        match self {
+            Self::Degraded(features) => features.contains(&feature),
+            Self::Incompatible => true,
+            Self::Compatible => false,
+        }
+    }
+}
+
+/// 兼容规则的结论
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum CompatStatus {
+    Compatible,
+    Degraded,
+    Incompatible,
+}
+
+/// 一条兼容规则，适用于`[min_version, max_version)`区间内的客户端版本
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompatRule {
+    /// 区间下限（含），不填表示不限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_version: Option<SemVer>,
+    /// 区间上限（不含），不填表示不限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_version: Option<SemVer>,
+    pub status: CompatStatus,
+    /// 降级时关闭的功能
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disabled_features: Vec<DisabledFeature>,
+}
+
+impl CompatRule {
+    /// 版本是否落在规则的区间内
+    pub fn matches(&self, version: SemVer) -> bool {
+        self.min_version.is_none_or(|min| version >= min)
+            && self.max_version.is_none_or(|max| version < max)
+    }
+    
+    /// 检查规则本身是否有效
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if let (Some(min), Some(max)) = (self.min_version, self.max_version) {
+            if min >= max {
+                return Err("min_version must be lower than max_version");
+            }
+        }
+        match self.status {
+            CompatStatus::Degraded if self.disabled_features.is_empty() => {
+                Err("Degraded rules must list disabled_features")
+            }
+            CompatStatus::Compatible | CompatStatus::Incompatible if !self.disabled_features.is_empty() => {
+                Err("Only degraded rules may list disabled_features")
+            }
+            _ => Ok(()),
+        }
+    }
+    
+    fn compatibility(&self) -> Compatibility {
+        match self.status {
+            CompatStatus::Compatible => Compatibility::Compatible,
+            CompatStatus::Degraded => Compatibility::Degraded(self.disabled_features.clone()),
+            CompatStatus::Incompatible => Compatibility::Incompatible,
+        }
+    }
+}
+
+/// 客户端版本兼容矩阵
+///
+/// 按顺序使用第一条匹配的规则，没有规则匹配时视为兼容。
+/// 未报告版本的旧客户端按`0.0.0`处理，无法解析的版本视为不兼容。
+#[derive(Debug, Clone, Default)]
+pub struct CompatMatrix {
+    rules: Vec<CompatRule>,
+}
+
+impl CompatMatrix {
+    /// 由规则创建兼容矩阵
+    pub fn new(rules: Vec<CompatRule>) -> Result<Self, &'static str> {
+        for rule in &rules {
+            rule.validate()?;
+        }
+        Ok(Self { rules })
+    }
+    
+    /// 所有规则
+    pub fn rules(&self) -> &[CompatRule] {
+        &self.rules
+    }
+    
+    /// 判断客户端版本的兼容性
+    pub fn check(&self, client_version: &str) -> Compatibility {
+        let version = if client_version.is_empty() {
+            SemVer::default()
+        } else {
+            match client_version.parse() {
+                Ok(version) => version,
+                Err(_) => return Compatibility::Incompatible,
+            }
+        };
+        self.rules.iter()
+            .find(|rule| rule.matches(version))
+            .map_or(Compatibility::Compatible, CompatRule::compatibility)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn version(s: &str) -> SemVer {
+        s.parse().unwrap()
+    }
+    
+    /// 1.0.0之前不兼容，1.0.x关闭压缩和会话恢复，1.1.0及以后兼容
+    fn matrix() -> CompatMatrix {
+        CompatMatrix::new(vec![
+            CompatRule {
+                min_version: None,
+                max_version: Some(version("1.0.0")),
+                status: CompatStatus::Incompatible,
+                disabled_features: Vec::new(),
+            },
+            CompatRule {
+                min_version: Some(version("1.0.0")),
+                max_version: Some(version("1.1.0")),
+                status: CompatStatus::Degraded,
+                disabled_features: vec![DisabledFeature::Compression, DisabledFeature::SessionResume],
+            },
+        ]).unwrap()
+    }
+    
+    #[test]
+    fn parses_and_orders_versions() {
+        assert_eq!(version("1.2.3"), SemVer::new(1, 2, 3));
+        assert_eq!(version("v2"), SemVer::new(2, 0, 0));
+        assert_eq!(version("1.4.0-rc.1+build5"), SemVer::new(1, 4, 0));
+        assert!(version("1.10.0") > version("1.9.9"));
+        assert_eq!(version("1.2.3").to_string(), "1.2.3");
+        for invalid in ["", "1.x", "1.2.3.4", "-1.0"] {
+            assert!(invalid.parse::<SemVer>().is_err(), "{}", invalid);
+        }
+    }
+    
+    #[test]
+    fn compatible_outcome() {
+        let matrix = matrix();
+        assert_eq!(matrix.check("1.1.0"), Compatibility::Compatible);
+        assert_eq!(matrix.check("2.0.0"), Compatibility::Compatible);
+        // 没有规则时全部兼容
+        assert_eq!(CompatMatrix::default().check("0.1.0"), Compatibility::Compatible);
+    }
+    
+    #[test]
+    fn degraded_outcome_disables_listed_features() {
+        let compatibility = matrix().check("1.0.5");
+        assert_eq!(
+            compatibility,
+            Compatibility::Degraded(vec![DisabledFeature::Compression, DisabledFeature::SessionResume])
+        );
+        assert!(compatibility.disables(DisabledFeature::Compression));
+        assert!(!compatibility.disables(DisabledFeature::RawFrame));
+        
+        let flags = constants::FLAG_COMPRESSED | constants::FLAG_RAW_FRAME;
+        assert_eq!(compatibility.restrict_payload_flags(flags), constants::FLAG_RAW_FRAME);
+        assert_eq!(Compatibility::Compatible.restrict_payload_flags(flags), flags);
+    }
+    
+    #[test]
+    fn incompatible_outcome() {
+        let matrix = matrix();
+        assert_eq!(matrix.check("0.9.9"), Compatibility::Incompatible);
+        // 未报告版本的旧客户端按0.0.0处理，无法解析的版本直接拒绝
+        assert_eq!(matrix.check(""), Compatibility::Incompatible);
+        assert_eq!(CompatMatrix::default().check("not-a-version"), Compatibility::Incompatible);
+    }
+    
+    #[test]
+    fn first_matching_rule_wins() {
+        let mut rules = matrix().rules().to_vec();
+        rules.insert(0, CompatRule {
+            min_version: Some(version("1.0.3")),
+            max_version: Some(version("1.0.4")),
+            status: CompatStatus::Incompatible,
+            disabled_features: Vec::new(),
+        });
+        let matrix = CompatMatrix::new(rules).unwrap();
+        assert_eq!(matrix.check("1.0.3"), Compatibility::Incompatible);
+        assert!(matches!(matrix.check("1.0.4"), Compatibility::Degraded(_)));
+    }
+    
+    #[test]
+    fn invalid_rules_are_rejected() {
+        let rule = |min: &str, max: &str, status, disabled_features| CompatRule {
+            min_version: Some(version(min)),
+            max_version: Some(version(max)),
+            status,
+            disabled_features,
+        };
+        assert!(CompatMatrix::new(vec![rule("1.1.0", "1.0.0", CompatStatus::Compatible, Vec::new())]).is_err());
+        assert!(CompatMatrix::new(vec![rule("1.0.0", "1.1.0", CompatStatus::Degraded, Vec::new())]).is_err());
+        assert!(CompatMatrix::new(vec![
+            rule("1.0.0", "1.1.0", CompatStatus::Incompatible, vec![DisabledFeature::Compression])
+        ]).is_err());
+    }
+    
+    #[test]
+    fn rules_are_read_from_compat_sections() {
+        #[derive(Deserialize)]
+        struct Config {
+            compat: Vec<CompatRule>,
+        }
+        
+        let config: Config = toml::from_str(r#"
+            [[compat]]
+            max_version = "1.0.0"
+            status = "incompatible"
+            
+            [[compat]]
+            min_version = "1.0.0"
+            max_version = "1.1.0"
+            status = "degraded"
+            disabled_features = ["compression", "session_resume"]
+        "#).unwrap();
+        let matrix = CompatMatrix::new(config.compat).unwrap();
+        assert_eq!(matrix.check("0.5.0"), Compatibility::Incompatible);
+        assert!(matrix.check("1.0.0").disables(DisabledFeature::SessionResume));
+        assert_eq!(matrix.check("1.1.0"), Compatibility::Compatible);
+    }
+}
@@ -0,0 +1,88 @@
+/*!
+VPNet压缩模块
+
+节点间转发的数据在加密前进行LZ4压缩，包括：
+- 压缩模式配置
+- 自适应压缩：先压缩一小段样本，估计有收益时才压缩完整数据，
+  避免对JPEG、TLS、压缩包等已经压缩过的数据白白消耗CPU
+
+加密前压缩会让密文长度随明文内容变化。攻击者能向隧道内注入数据（例如让用户访问
+其控制的网页）并观察密文长度时，可以逐字节猜出同一数据包中的秘密（VORACLE），
+因此默认不压缩，只应在确认流量不混合攻击者可控数据和秘密时开启。
+*/
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// 自适应压缩的样本长度
+///
+/// LZ4的最短匹配为4字节且块末尾的字节只能作为字面量，64字节的样本中几乎找不到重复，
+/// 普通文本也会被判定为不可压缩。
+const SAMPLE_LEN: usize = 256;
+
+/// 解压后的最大长度，数据转发的负载不会超过该值
+const MAX_DECOMPRESSED_LEN: usize = u16::MAX as usize;
+
+/// 压缩模式，默认不压缩
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Compression {
+    /// 不压缩
+    #[default]
+    None,
+    /// 总是压缩
+    Lz4,
+    /// 随机取一段样本压缩，压缩后小于`原长度 * ratio_threshold`时才压缩完整数据
+    Adaptive {
+        ratio_threshold: f32,
+    },
+}
+
+impl Compression {
+    /// 自适应压缩的默认阈值
+    pub const DEFAULT_RATIO_THRESHOLD: f32 = 0.95;
+    
+
+    /// 按压缩模式压缩数据
+    ///
+    /// 不压缩或压缩后没有变小时返回None，调用方应发送原始数据。
+    pub fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        match *self {
+            Compression::None => return None,
+            Compression::Lz4 => {}
+            Compression::Adaptive { ratio_threshold } => {
+                if !sample_compressible(data, ratio_threshold) {
+                    return None;
+                }
+            }
+        }
+        
+        let compressed = lz4_flex::compress_prepend_size(data);
+        (compressed.len() < data.len()).then_some(compressed)
+    }
+}
+
+/// 解压数据，数据损坏或解压后过大时返回错误
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let (len, block) = lz4_flex::block::uncompressed_size(data)
+        .map_err(|_| "Invalid compressed data")?;
+    if len > MAX_DECOMPRESSED_LEN {
+        return Err("Decompressed data too large");
+    }
+    
+    lz4_flex::block::decompress(block, len).map_err(|_| "Invalid compressed data")
+}
+
+/// 压缩一段随机样本，判断完整数据是否值得压缩
+fn sample_compressible(data: &[u8], ratio_threshold: f32) -> bool {
+    let sample = if data.len() <= SAMPLE_LEN {
+        data
+    } else {
+        let offset = rand::thread_rng().gen_range(0..=data.len() - SAMPLE_LEN);
+        &data[offset..offset + SAMPLE_LEN]
+    };
+    
+    let compressed = lz4_flex::block::compress(sample);
+    (compressed.len() as f32) < sample.len() as f32 * ratio_threshold
+}
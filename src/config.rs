@@ -0,0 +1,593 @@
+/*!
+VPNet配置工具模块
+
+客户端和服务端配置文件共用的工具，包括：
+- 读取配置格式版本
+- 按版本逐步迁移旧格式的原始配置
+- 以`"30s"`、`"2m 30s"`形式的字符串读写时长
+- 收集配置验证的错误、警告和修正建议
+- 客户端和服务端共用的套接字配置
+*/
+
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::utils::SocketConfig;
+
+/// 配置迁移错误
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error("Toml parsing error: {0}")]
+    Parse(#[from] toml::de::Error),
+    
+    #[error("Toml serialization error: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    
+    #[error("Invalid schema_version: {0}")]
+    InvalidVersion(String),
+    
+    #[error("Unsupported schema version {version}, this build supports up to {supported}")]
+    Unsupported { version: u32, supported: u32 },
+    
+    #[error("Migration from schema version {from} failed: {reason}")]
+    Step { from: u32, reason: String },
+}
+
+/// 配置迁移步骤，将原始配置从版本n升级到n+1
+pub type MigrationStep = fn(&mut toml::Table) -> Result<(), String>;
+
+/// 配置迁移器
+///
+/// 依次执行从配置文件版本到当前版本之间的迁移步骤，未写`schema_version`的配置视为版本1。
+pub struct ConfigMigrator {
+    /// 第i个步骤将版本i+1升级到i+2
+    steps: Vec<MigrationStep>,
+}
+
+impl ConfigMigrator {
+    /// 创建迁移器，当前版本为迁移步骤数加1
+    pub fn new(steps: Vec<MigrationStep>) -> Self {
+        Self { steps }
+    }
+    
+    /// 迁移后的配置版本
+    pub fn schema_version(&self) -> u32 {
+        self.steps.len() as u32 + 1
+    }
+    
+    /// 读取原始配置中的版本号
+    pub fn detect_version(raw_toml: &str) -> Result<u32, MigrationError> {
+        let table: toml::Table = toml::from_str(raw_toml)?;
+        match table.get("schema_version") {
+            None => Ok(1),
+            Some(toml::Value::Integer(version)) if *version >= 1 => Ok(*version as u32),
+            Some(value) => Err(MigrationError::InvalidVersion(value.to_string())),
+        }
+    }
+    
+    /// 将原始配置从`from_version`迁移到当前版本
+    pub fn migrate(&self, raw_toml: &str, from_version: u32) -> Result<String, MigrationError> {
+        let supported = self.schema_version();
+        if from_version == 0 || from_version > supported {
+            return Err(MigrationError::Unsupported { version: from_version, supported });
+        }
+        
+        let mut table: toml::Table = toml::from_str(raw_toml)?;
+        for (from, step) in (from_version..supported).zip(&self.steps[from_version as usize - 1..]) {
+            step(&mut table).map_err(|reason| MigrationError::Step { from, reason })?;
+            table.insert("schema_version".to_string(), toml::Value::Integer(from as i64 + 1));
+        }
+        
+        Ok(toml::to_string_pretty(&table)?)
+    }
+}
+
+/// 将枚举取值改为小写蛇形命名，如`AssignFromPool`改为`assign_from_pool`
+pub fn normalize_enum_value(table: &mut toml::Table, section: &str, key: &str) {
+    let value = table.get_mut(section)
+        .and_then(toml::Value::as_table_mut)
+        .and_then(|section| section.get_mut(key));
+    
+    if let Some(toml::Value::String(value)) = value {
+        let mut normalized = String::with_capacity(value.len() + 4);
+        for (i, c) in value.char_indices() {
+            if c.is_ascii_uppercase() && i > 0 && !value[..i].ends_with(|p: char| p.is_ascii_uppercase() || p == '_') {
+                normalized.push('_');
+            }
+            normalized.push(c.to_ascii_lowercase());
+        }
+        *value = normalized;
+    }
+}
+
+/// 将以秒为单位的整数时长改写为`"30s"`形式的字符串
+///
+/// `section`为以`.`分隔的表路径，为空时表示`table`本身。字段不存在或已经是字符串时不变，
+/// 负数时返回错误。
+pub fn migrate_seconds_to_duration(table: &mut toml::Table, section: &str, key: &str) -> Result<(), String> {
+    let mut table = Some(table);
+    for name in section.split('.').filter(|name| !name.is_empty()) {
+        table = table.and_then(|table| table.get_mut(name)).and_then(toml::Value::as_table_mut);
+    }
+    
+    let field = if section.is_empty() { key.to_string() } else { format!("{}.{}", section, key) };
+    if let Some(value) = table.and_then(|table| table.get_mut(key)) {
+        if let toml::Value::Integer(secs) = *value {
+            if secs < 0 {
+                return Err(format!("{} must not be negative, got {}", field, secs));
+            }
+            *value = toml::Value::String(format!("{}s", secs));
+        }
+    }
+    Ok(())
+}
+
+/// 解析时长
+///
+/// 接受humantime格式（`"30s"`、`"2m 30s"`、`"1h"`），以及带小数的单一单位（`"0.5m"`、`"1.5h"`）。
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if let Ok(duration) = humantime::parse_duration(s) {
+        return Ok(duration);
+    }
+    
+    let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let scale = match unit.trim() {
+        "ms" | "msec" => 0.001,
+        "s" | "sec" | "secs" => 1.0,
+        "m" | "min" | "mins" => 60.0,
+        "h" | "hr" | "hrs" => 3600.0,
+        "d" | "day" | "days" => 86400.0,
+        _ => return Err(format!("invalid duration: {:?}", s)),
+    };
+    let value: f64 = value.parse().map_err(|_| format!("invalid duration: {:?}", s))?;
+    Duration::try_from_secs_f64(value * scale).map_err(|e| format!("invalid duration {:?}: {}", s, e))
+}
+
+/// 配置文件中的时长，配合`#[serde(with = "vpnet::config::duration")]`使用
+///
+/// 序列化为`"2m 30s"`形式的字符串；反序列化接受`parse_duration`支持的字符串，
+/// 以及旧格式配置中以秒为单位的数字。
+pub mod duration {
+    use std::time::Duration;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Seconds(f64),
+        Text(String),
+    }
+    
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&humantime::format_duration(*duration))
+    }
+    
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        match Raw::deserialize(deserializer)? {
+            Raw::Seconds(secs) => Duration::try_from_secs_f64(secs).map_err(D::Error::custom),
+            Raw::Text(text) => super::parse_duration(&text).map_err(D::Error::custom),
+        }
+    }
+    
+    /// 可选的时长，配合`#[serde(default, with = "vpnet::config::duration::option")]`使用
+    pub mod option {
+        use std::time::Duration;
+        use serde::{Deserialize, Deserializer, Serializer};
+        
+        pub fn serialize<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+            match duration {
+                Some(duration) => super::serialize(duration, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+        
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+            #[derive(Deserialize)]
+            struct Wrapper(#[serde(with = "super")] Duration);
+            Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(duration)| duration))
+        }
+    }
+}
+
+/// UDP套接字配置，对应配置文件的`[network]`
+///
+/// 高吞吐环境下增大缓冲区可以避免突发发送时返回`EAGAIN`，
+/// Linux上超过`net.core.wmem_max`/`net.core.rmem_max`的部分会被内核截断。
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct NetworkConfig {
+    /// 发送缓冲区大小（字节）
+    #[serde(default = "default_socket_buffer_size")]
+    pub send_buffer_size: usize,
+    /// 接收缓冲区大小（字节）
+    #[serde(default = "default_socket_buffer_size")]
+    pub receive_buffer_size: usize,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            send_buffer_size: default_socket_buffer_size(),
+            receive_buffer_size: default_socket_buffer_size(),
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// 转换为套接字参数
+    pub fn socket_config(&self) -> SocketConfig {
+        SocketConfig {
+            send_buffer_size: Some(self.send_buffer_size),
+            receive_buffer_size: Some(self.receive_buffer_size),
+        }
+    }
+    
+    /// 检查缓冲区大小
+    pub fn validate(&self, report: &mut ValidationReport) {
+        for (field, size) in [("network.send_buffer_size", self.send_buffer_size), ("network.receive_buffer_size", self.receive_buffer_size)] {
+            if size == 0 {
+                report.error(field, "must be greater than 0")
+                    .suggest(format!("Did you mean {}?", SocketConfig::DEFAULT_BUFFER_SIZE));
+            }
+        }
+    }
+}
+
+fn default_socket_buffer_size() -> usize {
+    SocketConfig::DEFAULT_BUFFER_SIZE
+}
+
+/// 配置验证错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// 出错字段的路径，例如`server.port`，与配置文件无关的错误为空
+    pub field: String,
+    pub message: String,
+    /// 修正建议，例如`Did you mean 51820?`
+    pub suggestion: Option<String>,
+}
+
+impl ValidationError {
+    /// 附加修正建议
+    pub fn suggest(&mut self, suggestion: impl Into<String>) {
+        self.suggestion = Some(suggestion.into());
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.field, self.message)
+        }
+    }
+}
+
+/// 配置验证警告，不影响启动
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationWarning {
+    pub field: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// 配置验证结果，包含所有错误和警告
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub errors: Vec<ValidationError>,
+    pub warnings: Vec<ValidationWarning>,
+}
+
+impl ValidationReport {
+    /// 没有错误时配置有效，警告不影响
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+    
+    /// 记录错误，返回该错误以便附加修正建议
+    pub fn error(&mut self, field: impl Into<String>, message: impl Into<String>) -> &mut ValidationError {
+        let index = self.errors.len();
+        self.errors.push(ValidationError {
+            field: field.into(),
+            message: message.into(),
+            suggestion: None,
+        });
+        &mut self.errors[index]
+    }
+    
+    /// 记录缺少必填字段的错误
+    pub fn missing(&mut self, field: impl Into<String>) -> &mut ValidationError {
+        self.error(field, "is required")
+    }
+    
+    /// 记录警告
+    pub fn warn(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.warnings.push(ValidationWarning {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+}
+
+/// 检查时长在`[min, max]`之内，超出时建议最接近的边界值
+pub fn check_duration(name: &str, value: Duration, min: Duration, max: Duration, report: &mut ValidationReport) {
+    if value < min || value > max {
+        let suggestion = if value < min { min } else { max };
+        report.error(name, format!(
+            "must be between {} and {}, got {}",
+            humantime::format_duration(min),
+            humantime::format_duration(max),
+            humantime::format_duration(value)
+        )).suggest(format!("Did you mean {}?", humantime::format_duration(suggestion)));
+    }
+}
+
+/// 找出与`value`最接近的候选值，相差过大时返回None
+pub fn closest_match<'a>(value: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    candidates.iter()
+        .map(|candidate| (edit_distance(value, candidate), *candidate))
+        .filter(|(distance, candidate)| *distance <= candidate.len() / 3)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// 两个字符串的编辑距离
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// 检查文件存在且可读
+pub fn check_readable_file(field: &str, path: &str, report: &mut ValidationReport) {
+    if let Err(e) = std::fs::File::open(path) {
+        report.error(field, format!("cannot read {}: {}", path, e));
+    }
+}
+
+/// 检查文件可读，或文件不存在时其所在目录存在，以便启动时生成
+pub fn check_file_or_parent(field: &str, path: &str, report: &mut ValidationReport) {
+    let file = std::path::Path::new(path);
+    if file.exists() {
+        check_readable_file(field, path, report);
+        return;
+    }
+    
+    let parent = file.parent().filter(|p| !p.as_os_str().is_empty());
+    if let Some(parent) = parent {
+        if !parent.is_dir() {
+            report.error(field, format!("directory {} does not exist", parent.display()))
+                .suggest(format!("Create {} first", parent.display()));
+        }
+    }
+}
+
+/// 检查IPv4地址
+pub fn check_ipv4(field: &str, value: &str, report: &mut ValidationReport) {
+    if value.parse::<std::net::Ipv4Addr>().is_err() {
+        report.error(field, format!("invalid IPv4 address: {}", value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn add_name(table: &mut toml::Table) -> Result<(), String> {
+        table.entry("name").or_insert_with(|| toml::Value::String("node".to_string()));
+        Ok(())
+    }
+    
+    fn require_mode(table: &mut toml::Table) -> Result<(), String> {
+        table.get("mode").map(|_| ()).ok_or_else(|| "missing mode".to_string())
+    }
+    
+    #[test]
+    fn detects_version_and_defaults_to_one() {
+        assert_eq!(ConfigMigrator::detect_version("port = 1").unwrap(), 1);
+        assert_eq!(ConfigMigrator::detect_version("schema_version = 3").unwrap(), 3);
+        assert!(matches!(ConfigMigrator::detect_version("schema_version = 0"), Err(MigrationError::InvalidVersion(_))));
+        assert!(matches!(ConfigMigrator::detect_version("schema_version = \"2\""), Err(MigrationError::InvalidVersion(_))));
+    }
+    
+    #[test]
+    fn migrate_runs_steps_from_the_given_version() {
+        let migrator = ConfigMigrator::new(vec![add_name, require_mode]);
+        assert_eq!(migrator.schema_version(), 3);
+        
+        let migrated = migrator.migrate("mode = \"tun\"", 1).unwrap();
+        let table: toml::Table = toml::from_str(&migrated).unwrap();
+        assert_eq!(table["name"].as_str(), Some("node"));
+        assert_eq!(table["schema_version"].as_integer(), Some(3));
+        
+        // 从版本2开始时跳过第一步
+        let migrated = migrator.migrate("schema_version = 2\nmode = \"tun\"", 2).unwrap();
+        let table: toml::Table = toml::from_str(&migrated).unwrap();
+        assert!(table.get("name").is_none());
+        assert_eq!(table["schema_version"].as_integer(), Some(3));
+    }
+    
+    #[test]
+    fn migrate_reports_failed_step_and_unsupported_version() {
+        let migrator = ConfigMigrator::new(vec![add_name, require_mode]);
+        assert!(matches!(migrator.migrate("port = 1", 1), Err(MigrationError::Step { from: 2, .. })));
+        assert!(matches!(migrator.migrate("port = 1", 4), Err(MigrationError::Unsupported { version: 4, supported: 3 })));
+        assert!(matches!(migrator.migrate("port = 1", 0), Err(MigrationError::Unsupported { version: 0, .. })));
+    }
+    
+    #[test]
+    fn normalizes_enum_values_to_snake_case() {
+        let mut table: toml::Table = toml::from_str("[node]\na = \"AssignFromPool\"\nb = \"TUN\"\nc = \"already_snake\"").unwrap();
+        for key in ["a", "b", "c", "missing"] {
+            normalize_enum_value(&mut table, "node", key);
+        }
+        normalize_enum_value(&mut table, "missing", "a");
+        
+        let node = table["node"].as_table().unwrap();
+        assert_eq!(node["a"].as_str(), Some("assign_from_pool"));
+        assert_eq!(node["b"].as_str(), Some("tun"));
+        assert_eq!(node["c"].as_str(), Some("already_snake"));
+    }
+    
+    #[test]
+    fn fractional_and_compound_durations_parse_to_the_same_value() {
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("0.5m").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("2m 30s").unwrap(), parse_duration("2.5min").unwrap());
+        assert_eq!(parse_duration("1.5h").unwrap(), Duration::from_secs(5400));
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("fast").is_err());
+        assert!(parse_duration("-1s").is_err());
+    }
+    
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Timing {
+        #[serde(with = "duration")]
+        interval: Duration,
+        #[serde(default, with = "duration::option", skip_serializing_if = "Option::is_none")]
+        timeout: Option<Duration>,
+    }
+    
+    #[test]
+    fn durations_round_trip_as_strings_and_accept_legacy_seconds() {
+        let timing: Timing = toml::from_str("interval = \"2m 30s\"\ntimeout = \"0.5m\"").unwrap();
+        assert_eq!(timing, Timing { interval: Duration::from_secs(150), timeout: Some(Duration::from_secs(30)) });
+        assert_eq!(toml::to_string(&timing).unwrap(), "interval = \"2m 30s\"\ntimeout = \"30s\"\n");
+        
+        let legacy: Timing = toml::from_str("interval = 45").unwrap();
+        assert_eq!(legacy, Timing { interval: Duration::from_secs(45), timeout: None });
+    }
+    
+    #[test]
+    fn migrates_integer_seconds_in_nested_sections() {
+        let mut table: toml::Table = toml::from_str("[server]\ntimeout = 30\n[server.mode]\npoll_interval = 10\n").unwrap();
+        migrate_seconds_to_duration(&mut table, "server", "timeout").unwrap();
+        migrate_seconds_to_duration(&mut table, "server.mode", "poll_interval").unwrap();
+        migrate_seconds_to_duration(&mut table, "server", "missing").unwrap();
+        assert_eq!(table["server"]["timeout"].as_str(), Some("30s"));
+        assert_eq!(table["server"]["mode"]["poll_interval"].as_str(), Some("10s"));
+        
+        table["server"].as_table_mut().unwrap().insert("timeout".to_string(), toml::Value::Integer(-1));
+        assert!(migrate_seconds_to_duration(&mut table, "server", "timeout").is_err());
+    }
+    
+    #[test]
+    fn edit_distance_counts_single_character_edits() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("info", ""), 4);
+        assert_eq!(edit_distance("", "warn"), 4);
+        assert_eq!(edit_distance("debug", "debug"), 0);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("trace", "tarce"), 2);
+        assert_eq!(edit_distance("模式", "模型"), 1);
+    }
+    
+    #[test]
+    fn closest_match_suggests_only_near_candidates() {
+        let levels = ["off", "error", "warn", "info", "debug", "trace"];
+        assert_eq!(closest_match("debg", &levels), Some("debug"));
+        assert_eq!(closest_match("eror", &levels), Some("error"));
+        assert_eq!(closest_match("info", &levels), Some("info"));
+        assert_eq!(closest_match("verbose", &levels), None);
+        assert_eq!(closest_match("of", &levels), Some("off"));
+        assert_eq!(closest_match("tree", &levels), None);
+        assert_eq!(closest_match("debug", &[]), None);
+    }
+    
+    #[test]
+    fn report_collects_errors_warnings_and_suggestions() {
+        let mut report = ValidationReport::default();
+        assert!(report.is_valid());
+        
+        report.warn("server.mtu", "is unusually small");
+        assert!(report.is_valid());
+        
+        report.missing("server.address");
+        report.error("log.level", "unknown level debg").suggest("Did you mean debug?");
+        assert!(!report.is_valid());
+        assert_eq!(report.errors.len(), 2);
+        assert_eq!(report.errors[0].to_string(), "server.address: is required");
+        assert_eq!(report.errors[1].suggestion.as_deref(), Some("Did you mean debug?"));
+        assert_eq!(report.warnings[0].to_string(), "server.mtu: is unusually small");
+        
+        report.error("", "config file not found");
+        assert_eq!(report.errors[2].to_string(), "config file not found");
+    }
+    
+    #[test]
+    fn durations_outside_the_range_suggest_the_nearest_bound() {
+        let (min, max) = (Duration::from_secs(10), Duration::from_secs(3600));
+        let mut report = ValidationReport::default();
+        check_duration("a", Duration::from_secs(10), min, max, &mut report);
+        check_duration("b", Duration::from_secs(3600), min, max, &mut report);
+        assert!(report.is_valid());
+        
+        check_duration("c", Duration::from_secs(1), min, max, &mut report);
+        check_duration("d", Duration::from_secs(7200), min, max, &mut report);
+        assert_eq!(report.errors.len(), 2);
+        assert_eq!(report.errors[0].field, "c");
+        assert_eq!(report.errors[0].message, "must be between 10s and 1h, got 1s");
+        assert_eq!(report.errors[0].suggestion.as_deref(), Some("Did you mean 10s?"));
+        assert_eq!(report.errors[1].suggestion.as_deref(), Some("Did you mean 1h?"));
+    }
+    
+    #[test]
+    fn file_and_address_checks_report_the_field() {
+        let dir = std::env::temp_dir().join(format!("vpnet-config-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let existing = dir.join("key.json");
+        std::fs::write(&existing, "{}").unwrap();
+        let existing = existing.to_str().unwrap();
+        let generated = dir.join("generated.json");
+        let orphan = dir.join("missing").join("key.json");
+        
+        let mut report = ValidationReport::default();
+        check_readable_file("a", existing, &mut report);
+        check_file_or_parent("b", existing, &mut report);
+        check_file_or_parent("c", generated.to_str().unwrap(), &mut report);
+        check_ipv4("d", "10.0.0.1", &mut report);
+        assert!(report.is_valid());
+        
+        check_readable_file("e", generated.to_str().unwrap(), &mut report);
+        check_file_or_parent("f", orphan.to_str().unwrap(), &mut report);
+        check_ipv4("g", "10.0.0.256", &mut report);
+        let fields: Vec<&str> = report.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["e", "f", "g"]);
+        assert!(report.errors[1].suggestion.is_some());
+    }
+    
+    #[test]
+    fn network_config_defaults_to_large_buffers_and_rejects_zero() {
+        let config: NetworkConfig = toml::from_str("send_buffer_size = 65536").unwrap();
+        assert_eq!(config.send_buffer_size, 65536);
+        assert_eq!(config.receive_buffer_size, SocketConfig::DEFAULT_BUFFER_SIZE);
+        assert_eq!(config.socket_config(), SocketConfig {
+            send_buffer_size: Some(65536),
+            receive_buffer_size: Some(SocketConfig::DEFAULT_BUFFER_SIZE),
+        });
+        
+        let mut report = ValidationReport::default();
+        NetworkConfig::default().validate(&mut report);
+        assert!(report.is_valid());
+        
+        NetworkConfig { send_buffer_size: 0, receive_buffer_size: 0 }.validate(&mut report);
+        let fields: Vec<&str> = report.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["network.send_buffer_size", "network.receive_buffer_size"]);
+    }
+}
@@ -1,161 +1,1161 @@
-/*!
-VPNet加密模块
-
-提供网络通信的加密和认证功能，包括：
-- 密钥生成和管理
-- 数据加密和解密
-- 消息认证
-- 握手协议
-*/
-
-use ring::aead::{self, Aad, BoundKey, Nonce, UnboundKey};
-use ring::digest;
-use ring::hmac;
-use ring::rand::{self, SecureRandom};
-use rand::Rng;
-use base64::Engine;
-
-/// 加密算法类型
-pub enum CryptoAlgorithm {
-    AesGcm128,
-    AesGcm256,
-}
-
-/// 加密上下文
-pub struct CryptoContext {
-    key: aead::LessSafeKey,
-    algorithm: CryptoAlgorithm,
-    nonce_counter: u64,
-    rng: rand::SystemRandom,
-}
-
-/// 密钥对
-pub struct KeyPair {
-    pub public_key: Vec<u8>,
-    private_key: Vec<u8>,
-}
-
-impl CryptoContext {
-    /// 创建新的加密上下文
-    pub fn new(key: &[u8], algorithm: CryptoAlgorithm) -> Self {
-        let unbound_key = UnboundKey::new(&aead::AES_256_GCM, key).unwrap();
-        let key = aead::LessSafeKey::new(unbound_key);
-        
-        Self {
-            key,
-            algorithm,
-            nonce_counter: 0,
-            rng: rand::SystemRandom::new(),
-        }
-    }
-    
-    /// 加密数据
-    pub fn encrypt(&mut self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, &'static str> {
-        let mut nonce_bytes = [0u8; 12];
-        self.nonce_counter.to_be_bytes().clone_into(&mut nonce_bytes[4..]);
-        let nonce = Nonce::assume_unique_for_key(nonce_bytes);
-        
-        let mut ciphertext = plaintext.to_vec();
-        let tag_len = self.key.algorithm().tag_len();
-        ciphertext.resize(plaintext.len() + tag_len, 0);
-        
-        let aad = Aad::from(aad);
-        
-        self.key.seal_in_place_append_tag(nonce, aad, &mut ciphertext)
-            .map_err(|_| "Encryption failed")?;
-        
-        self.nonce_counter += 1;
-        Ok(ciphertext)
-    }
-    
-    /// 解密数据
-    pub fn decrypt(&self, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, &'static str> {
-        if ciphertext.len() < self.key.algorithm().tag_len() {
-            return Err("Ciphertext too short");
-        }
-        
-        let nonce_bytes = [0u8; 12]; // 简化处理，实际应该从数据包中获取
-        let nonce = Nonce::assume_unique_for_key(nonce_bytes);
-        
-        let mut plaintext = ciphertext.to_vec();
-        let aad = Aad::from(aad);
-        
-        let plaintext_len = self.key.open_in_place(nonce, aad, &mut plaintext)
-            .map_err(|_| "Decryption failed")?
-            .len();
-        
-        plaintext.truncate(plaintext_len);
-        Ok(plaintext)
-    }
-    
-    /// 生成随机密钥
-    pub fn generate_key(&mut self, algorithm: CryptoAlgorithm) -> Vec<u8> {
-        let key_len = match algorithm {
-            CryptoAlgorithm::AesGcm128 => 16,
-            CryptoAlgorithm::AesGcm256 => 32,
-        };
-        
-        let mut key = vec![0u8; key_len];
-        self.rng.fill(&mut key).unwrap();
-        key
-    }
-}
-
-impl KeyPair {
-    /// 生成新的密钥对
-    pub fn generate() -> Self {
-        let mut rng = rand::thread_rng();
-        let mut public_key = vec![0u8; 32];
-        let mut private_key = vec![0u8; 32];
-        
-        rng.fill(&mut public_key);
-        rng.fill(&mut private_key);
-        
-        Self {
-            public_key,
-            private_key,
-        }
-    }
-    
-    /// 从Base64字符串创建密钥对
-    pub fn from_base64(public_b64: &str, private_b64: &str) -> Result<Self, &'static str> {
-        let public_key = base64::engine::general_purpose::STANDARD
-            .decode(public_b64)
-            .map_err(|_| "Invalid public key")?;
-        let private_key = base64::engine::general_purpose::STANDARD
-            .decode(private_b64)
-            .map_err(|_| "Invalid private key")?;
-        
-        Ok(Self {
-            public_key,
-            private_key,
-        })
-    }
-    
-    /// 转换为Base64字符串
-    pub fn to_base64(&self) -> (String, String) {
-        let public_b64 = base64::engine::general_purpose::STANDARD.encode(&self.public_key);
-        let private_b64 = base64::engine::general_purpose::STANDARD.encode(&self.private_key);
-        
-        (public_b64, private_b64)
-    }
-}
-
-/// 计算数据的哈希值
-pub fn hash(data: &[u8]) -> Vec<u8> {
-    let digest = digest::digest(&digest::SHA256, data);
-    digest.as_ref().to_vec()
-}
-
-/// 生成HMAC
-pub fn generate_hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
-    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
-    let tag = hmac::sign(&key, data);
-    tag.as_ref().to_vec()
-}
-
-/// 验证HMAC
-pub fn verify_hmac(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
-    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
-    hmac::verify(&key, data, tag).is_ok()
-}
+/*!
+VPNet加密模块
+
+提供网络通信的加密和认证功能，包括：
+- 密钥生成和管理
+- 数据加密和解密
+- 消息认证
+- 口令哈希
+- 握手协议
+- 可替换的密钥派生函数
+- 操作系统熵源的随机数生成器
+*/
+
+use ring::aead::{self, Aad, BoundKey, Nonce, UnboundKey};
+use ring::agreement;
+use ring::digest;
+use ring::hkdf;
+use ring::hmac;
+use ring::pbkdf2;
+use ring::rand::{self, SecureRandom};
+use ring::signature::{self, KeyPair as _};
+use base64::Engine;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 加密算法类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CryptoAlgorithm {
+    AesGcm128,
+    AesGcm256,
+}
+
+impl CryptoAlgorithm {
+    /// 全部加密算法
+    pub const ALL: [CryptoAlgorithm; 2] = [CryptoAlgorithm::AesGcm128, CryptoAlgorithm::AesGcm256];
+    
+    /// 算法名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            CryptoAlgorithm::AesGcm128 => "aes-128-gcm",
+            CryptoAlgorithm::AesGcm256 => "aes-256-gcm",
+        }
+    }
+}
+
+impl fmt::Display for CryptoAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for CryptoAlgorithm {
+    type Err = &'static str;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or("Unknown crypto algorithm")
+    }
+}
+
+/// 密钥派生函数
+///
+/// 所有由共享秘密派生密钥的地方都经由该接口，以后可以替换为抗量子的实现。
+pub trait Kdf: Send + Sync {
+    /// 算法名称
+    fn name(&self) -> &'static str;
+    
+    /// 由输入密钥材料派生`output_len`字节的密钥，`info`区分同一输入派生的不同用途的密钥
+    fn derive(&self, input: &[u8], salt: &[u8], info: &[u8], output_len: usize) -> Result<Vec<u8>, &'static str>;
+    
+    /// 由同一输入一次派生多个密钥，`keys`为每个密钥的`info`和长度
+    fn derive_multiple(&self, input: &[u8], salt: &[u8], keys: &[(&[u8], usize)]) -> Result<Vec<Vec<u8>>, &'static str> {
+        keys.iter()
+            .map(|(info, output_len)| self.derive(input, salt, info, *output_len))
+            .collect()
+    }
+}
+
+/// 基于HMAC-SHA256的HKDF（RFC 5869），默认的密钥派生函数
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HkdfSha256Kdf;
+
+impl HkdfSha256Kdf {
+    /// 单次派生的最大长度（字节）
+    pub const MAX_OUTPUT_LEN: usize = 255 * 32;
+}
+
+/// HKDF的输出长度
+struct OutputLen(usize);
+
+impl hkdf::KeyType for OutputLen {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+impl Kdf for HkdfSha256Kdf {
+    fn name(&self) -> &'static str {
+        "hkdf-sha256"
+    }
+    
+    fn derive(&self, input: &[u8], salt: &[u8], info: &[u8], output_len: usize) -> Result<Vec<u8>, &'static str> {
+        if output_len > Self::MAX_OUTPUT_LEN {
+            return Err("Requested key length too long");
+        }
+        
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(input);
+        let info = [info];
+        let okm = prk.expand(&info, OutputLen(output_len))
+            .map_err(|_| "Key derivation failed")?;
+        let mut key = vec![0u8; output_len];
+        okm.fill(&mut key).map_err(|_| "Key derivation failed")?;
+        Ok(key)
+    }
+}
+
+/// 操作系统熵源的随机数生成器
+///
+/// 包装`ring::rand::SystemRandom`并实现`rand::RngCore`和`rand::CryptoRng`，可以直接替代`rand::thread_rng()`。
+/// 密钥、随机数、挑战值、MAC地址等与安全相关的随机值都应使用它；
+/// 节点名称、配置中的随机ID、重试抖动等与安全无关的随机值仍使用`rand::thread_rng()`。
+#[derive(Debug, Clone)]
+pub struct SecureRng(rand::SystemRandom);
+
+impl SecureRng {
+    /// 创建随机数生成器
+    pub fn new() -> Self {
+        Self(rand::SystemRandom::new())
+    }
+}
+
+impl Default for SecureRng {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ::rand::RngCore for SecureRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut buf = [0u8; 4];
+        self.fill_bytes(&mut buf);
+        u32::from_le_bytes(buf)
+    }
+    
+    fn next_u64(&mut self) -> u64 {
+        let mut buf = [0u8; 8];
+        self.fill_bytes(&mut buf);
+        u64::from_le_bytes(buf)
+    }
+    
+    /// 操作系统熵源不可用时panic
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.try_fill_bytes(dest).expect("System random generation failed")
+    }
+    
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), ::rand::Error> {
+        self.0.fill(dest).map_err(|_| ::rand::Error::new("System random generation failed"))
+    }
+}
+
+impl ::rand::CryptoRng for SecureRng {}
+
+/// 加密上下文
+pub struct CryptoContext {
+    key: aead::LessSafeKey,
+    /// 轮换前的密钥及过渡期的结束时间，过渡期内新密钥解密失败时使用
+    previous_key: Mutex<Option<(aead::LessSafeKey, Instant)>>,
+    /// 过渡期内还可以用旧密钥解密的数据包数，只计算旧密钥解密成功的数据包
+    transition_remaining: AtomicU64,
+    /// 密钥轮换后仍接受旧密钥的时间，默认为`KEY_TRANSITION_PERIOD`
+    transition_period: Duration,
+    algorithm: CryptoAlgorithm,
+    kdf: Arc<dyn Kdf>,
+    nonce_counter: u64,
+    /// 加密随机数的前4个字节，会话双方使用不同的前缀，同一密钥下两个方向的随机数不会重复
+    nonce_prefix: [u8; 4],
+    /// 对端加密时使用的随机数前缀
+    peer_nonce_prefix: [u8; 4],
+    rng: rand::SystemRandom,
+    decrypted: AtomicU64,
+    decrypt_failures: AtomicU64,
+}
+
+/// 加密上下文统计
+#[derive(Debug, Clone, Serialize)]
+pub struct CryptoStats {
+    pub algorithm: &'static str,
+    /// 已加密的数据包数，即下一个加密随机数
+    pub packets_encrypted: u64,
+    pub packets_decrypted: u64,
+    /// 认证失败或过短而无法解密的数据包数
+    pub decrypt_failures: u64,
+}
+
+/// 密钥对
+///
+/// Ed25519签名密钥，私钥以PKCS#8文档保存，用于握手和授权请求的身份认证。
+pub struct KeyPair {
+    pub public_key: Vec<u8>,
+    private_key: Vec<u8>,
+    signing_key: signature::Ed25519KeyPair,
+}
+
+/// 临时密钥交换
+///
+/// 每次会话生成新的X25519密钥，与对端公钥协商出会话密钥后即销毁。
+pub struct KeyExchange {
+    private_key: agreement::EphemeralPrivateKey,
+    public_key: Vec<u8>,
+}
+
+/// 握手随机数存储
+///
+/// 服务端签发一次性随机数，客户端在握手请求中携带。
+/// 每个随机数只能使用一次，且在有效期后失效。
+pub struct NonceStore {
+    nonces: HashMap<[u8; 32], Instant>,
+    ttl: Duration,
+    capacity: usize,
+    rng: rand::SystemRandom,
+}
+
+impl CryptoContext {
+    /// 密钥轮换后最多用旧密钥解密的数据包数
+    pub const KEY_TRANSITION_PACKETS: u64 = 1024;
+    
+    /// 密钥轮换后仍接受旧密钥的时间
+    pub const KEY_TRANSITION_PERIOD: Duration = Duration::from_secs(30);
+    
+    /// 创建新的加密上下文
+    pub fn new(key: &[u8], algorithm: CryptoAlgorithm) -> Self {
+        let unbound_key = UnboundKey::new(&aead::AES_256_GCM, key).unwrap();
+        let key = aead::LessSafeKey::new(unbound_key);
+        
+        Self {
+            key,
+            previous_key: Mutex::new(None),
+            transition_remaining: AtomicU64::new(0),
+            transition_period: Self::KEY_TRANSITION_PERIOD,
+            algorithm,
+            kdf: Arc::new(HkdfSha256Kdf),
+            nonce_counter: 0,
+            nonce_prefix: [0; 4],
+            peer_nonce_prefix: [0; 4],
+            rng: rand::SystemRandom::new(),
+            decrypted: AtomicU64::new(0),
+            decrypt_failures: AtomicU64::new(0),
+        }
+    }
+    
+    /// 以握手确定的会话密钥创建一个会话方向的加密上下文
+    ///
+    /// 双方共用同一密钥，`initiator`为本方是否发起握手，两个方向使用不同的随机数前缀，
+    /// 本方加密的数据只能由对端解密。密钥长度不合法时返回错误。
+    pub fn for_session(session_key: &[u8], initiator: bool) -> Result<Self, &'static str> {
+        UnboundKey::new(&aead::AES_256_GCM, session_key).map_err(|_| "Invalid session key length")?;
+        let (local, peer) = if initiator { (1, 2) } else { (2, 1) };
+        Ok(Self {
+            nonce_prefix: [0, 0, 0, local],
+            peer_nonce_prefix: [0, 0, 0, peer],
+            ..Self::new(session_key, CryptoAlgorithm::AesGcm256)
+        })
+    }
+    
+    /// 加密数据
+    ///
+    /// 输出为8字节加密计数器（大端）、密文和认证标签，接收方从计数器还原随机数。
+    pub fn encrypt(&mut self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, &'static str> {
+        let counter = self.nonce_counter.to_be_bytes();
+        let mut nonce_bytes = [0u8; 12];
+        nonce_bytes[..4].copy_from_slice(&self.nonce_prefix);
+        nonce_bytes[4..].copy_from_slice(&counter);
+        let nonce = Nonce::assume_unique_for_key(nonce_bytes);
+        
+        let mut sealed = plaintext.to_vec();
+        let aad = Aad::from(aad);
+        
+        self.key.seal_in_place_append_tag(nonce, aad, &mut sealed)
+            .map_err(|_| "Encryption failed")?;
+        
+        let mut ciphertext = Vec::with_capacity(counter.len() + sealed.len());
+        ciphertext.extend_from_slice(&counter);
+        ciphertext.extend_from_slice(&sealed);
+        self.nonce_counter += 1;
+        Ok(ciphertext)
+    }
+    
+    /// 解密数据
+    ///
+    /// 密钥轮换后的过渡期内，新密钥解密失败时再尝试旧密钥，以接受轮换前已在途的数据包。
+    /// 无法解密的数据包不缩短过渡期。
+    pub fn decrypt(&self, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, &'static str> {
+        let mut result = Self::open(&self.key, self.peer_nonce_prefix, ciphertext, aad);
+        if result.is_err() && self.transition_remaining.load(Ordering::Acquire) > 0 {
+            let mut previous_key = self.previous_key.lock().unwrap_or_else(PoisonError::into_inner);
+            match previous_key.as_ref() {
+                Some((key, expires_at)) if Instant::now() < *expires_at => {
+                    result = Self::open(key, self.peer_nonce_prefix, ciphertext, aad);
+                    if result.is_ok() && self.transition_remaining.fetch_sub(1, Ordering::AcqRel) == 1 {
+                        *previous_key = None;
+                        log::debug!("Key rotation transition window closed, previous key discarded");
+                    }
+                }
+                Some(_) => {
+                    *previous_key = None;
+                    self.transition_remaining.store(0, Ordering::Release);
+                    log::debug!("Key rotation transition period expired, previous key discarded");
+                }
+                None => {}
+            }
+        }
+        
+        let counter = if result.is_ok() { &self.decrypted } else { &self.decrypt_failures };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+    
+    /// 原地轮换密钥
+    ///
+    /// 新密钥立即用于加密，加密随机数从0重新开始。`KEY_TRANSITION_PERIOD`内新密钥无法解密的数据包
+    /// 再尝试旧密钥，旧密钥解密`KEY_TRANSITION_PACKETS`个数据包或过渡期结束后丢弃旧密钥。
+    /// 密钥长度不合法时保留原密钥。
+    pub fn rotate_key(&mut self, new_key: &[u8]) -> Result<(), &'static str> {
+        let unbound_key = UnboundKey::new(&aead::AES_256_GCM, new_key).map_err(|_| "Invalid key length")?;
+        let previous_key = std::mem::replace(&mut self.key, aead::LessSafeKey::new(unbound_key));
+        
+        let expires_at = Instant::now() + self.transition_period;
+        *self.previous_key.get_mut().unwrap_or_else(PoisonError::into_inner) = Some((previous_key, expires_at));
+        *self.transition_remaining.get_mut() = Self::KEY_TRANSITION_PACKETS;
+        self.nonce_counter = 0;
+        log::info!("Encryption key rotated");
+        Ok(())
+    }
+    
+    /// 是否处于密钥轮换的过渡期
+    pub fn in_key_transition(&self) -> bool {
+        self.transition_remaining.load(Ordering::Acquire) > 0 && self.previous_key
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .is_some_and(|(_, expires_at)| Instant::now() < *expires_at)
+    }
+    
+    fn open(key: &aead::LessSafeKey, nonce_prefix: [u8; 4], ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, &'static str> {
+        if ciphertext.len() < 8 + key.algorithm().tag_len() {
+            return Err("Ciphertext too short");
+        }
+        
+        let (counter, sealed) = ciphertext.split_at(8);
+        let mut nonce_bytes = [0u8; 12];
+        nonce_bytes[..4].copy_from_slice(&nonce_prefix);
+        nonce_bytes[4..].copy_from_slice(counter);
+        let nonce = Nonce::assume_unique_for_key(nonce_bytes);
+        
+        let mut plaintext = sealed.to_vec();
+        let aad = Aad::from(aad);
+        
+        let plaintext_len = key.open_in_place(nonce, aad, &mut plaintext)
+            .map_err(|_| "Decryption failed")?
+            .len();
+        
+        plaintext.truncate(plaintext_len);
+        Ok(plaintext)
+    }
+    
+    /// 替换密钥派生函数，默认为`HkdfSha256Kdf`，之后的握手用它派生会话密钥
+    pub fn set_kdf(&mut self, kdf: Arc<dyn Kdf>) {
+        self.kdf = kdf;
+    }
+    
+    /// 密钥派生函数
+    pub fn kdf(&self) -> Arc<dyn Kdf> {
+        self.kdf.clone()
+    }
+    
+    /// 获取加解密统计
+    pub fn stats(&self) -> CryptoStats {
+        CryptoStats {
+            algorithm: self.algorithm.name(),
+            packets_encrypted: self.nonce_counter,
+            packets_decrypted: self.decrypted.load(Ordering::Relaxed),
+            decrypt_failures: self.decrypt_failures.load(Ordering::Relaxed),
+        }
+    }
+    
+    /// 生成随机密钥
+    pub fn generate_key(&mut self, algorithm: CryptoAlgorithm) -> Vec<u8> {
+        let key_len = match algorithm {
+            CryptoAlgorithm::AesGcm128 => 16,
+            CryptoAlgorithm::AesGcm256 => 32,
+        };
+        
+        let mut key = vec![0u8; key_len];
+        self.rng.fill(&mut key).unwrap();
+        key
+    }
+}
+
+impl NonceStore {
+    /// 随机数默认有效期（秒）
+    pub const DEFAULT_TTL: u64 = 30;
+    
+    /// 默认最多保留的未使用随机数数量
+    pub const DEFAULT_CAPACITY: usize = 10000;
+    
+    /// 创建新的随机数存储
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            nonces: HashMap::new(),
+            ttl,
+            capacity,
+            rng: rand::SystemRandom::new(),
+        }
+    }
+    
+    /// 签发新的随机数
+    pub fn issue(&mut self) -> Result<[u8; 32], &'static str> {
+        self.purge_expired();
+        if self.nonces.len() >= self.capacity {
+            return Err("Too many pending nonces");
+        }
+        
+        let mut nonce = [0u8; 32];
+        self.rng.fill(&mut nonce).map_err(|_| "Random generation failed")?;
+        self.nonces.insert(nonce, Instant::now());
+        Ok(nonce)
+    }
+    
+    /// 消费随机数
+    ///
+    /// 随机数存在且未过期时返回true，无论结果如何都会立即删除。
+    pub fn consume(&mut self, nonce: &[u8]) -> bool {
+        let nonce: [u8; 32] = match nonce.try_into() {
+            Ok(nonce) => nonce,
+            Err(_) => return false,
+        };
+        
+        match self.nonces.remove(&nonce) {
+            Some(issued_at) => issued_at.elapsed() < self.ttl,
+            None => false,
+        }
+    }
+    
+    /// 随机数有效期
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+    
+    /// 尚未使用的随机数数量，包括已过期但未清理的
+    pub fn len(&self) -> usize {
+        self.nonces.len()
+    }
+    
+    /// 是否没有未使用的随机数
+    pub fn is_empty(&self) -> bool {
+        self.nonces.is_empty()
+    }
+    
+    /// 清理过期的随机数，返回清理的数量
+    pub fn purge_expired(&mut self) -> usize {
+        let ttl = self.ttl;
+        let before = self.nonces.len();
+        self.nonces.retain(|_, issued_at| issued_at.elapsed() < ttl);
+        before - self.nonces.len()
+    }
+}
+
+impl Default for NonceStore {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(Self::DEFAULT_TTL), Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl KeyPair {
+    /// 生成新的密钥对
+    pub fn generate() -> Result<Self, &'static str> {
+        let rng = rand::SystemRandom::new();
+        let pkcs8 = signature::Ed25519KeyPair::generate_pkcs8(&rng)
+            .map_err(|_| "Key generation failed")?;
+        Self::from_pkcs8(pkcs8.as_ref())
+    }
+    
+    /// 从PKCS#8格式的私钥创建密钥对，公钥由私钥导出
+    pub fn from_pkcs8(private_key: &[u8]) -> Result<Self, &'static str> {
+        let signing_key = signature::Ed25519KeyPair::from_pkcs8(private_key)
+            .map_err(|_| "Invalid private key")?;
+        
+        Ok(Self {
+            public_key: signing_key.public_key().as_ref().to_vec(),
+            private_key: private_key.to_vec(),
+            signing_key,
+        })
+    }
+    
+    /// 从Base64字符串创建密钥对
+    pub fn from_base64(public_b64: &str, private_b64: &str) -> Result<Self, &'static str> {
+        let public_key = base64::engine::general_purpose::STANDARD
+            .decode(public_b64)
+            .map_err(|_| "Invalid public key")?;
+        let private_key = base64::engine::general_purpose::STANDARD
+            .decode(private_b64)
+            .map_err(|_| "Invalid private key")?;
+        
+        let key_pair = Self::from_pkcs8(&private_key)?;
+        if key_pair.public_key != public_key {
+            return Err("Public key does not match private key");
+        }
+        Ok(key_pair)
+    }
+    
+    /// 转换为Base64字符串
+    pub fn to_base64(&self) -> (String, String) {
+        let public_b64 = base64::engine::general_purpose::STANDARD.encode(&self.public_key);
+        let private_b64 = base64::engine::general_purpose::STANDARD.encode(&self.private_key);
+        
+        (public_b64, private_b64)
+    }
+    
+    /// PKCS#8格式的私钥
+    pub fn private_key(&self) -> &[u8] {
+        &self.private_key
+    }
+    
+    /// 对消息签名
+    pub fn sign(&self, msg: &[u8]) -> Vec<u8> {
+        self.signing_key.sign(msg).as_ref().to_vec()
+    }
+    
+    /// 使用Ed25519公钥验证签名
+    pub fn verify(public_key: &[u8], msg: &[u8], sig: &[u8]) -> bool {
+        signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+            .verify(msg, sig)
+            .is_ok()
+    }
+}
+
+impl KeyExchange {
+    /// 生成新的临时X25519密钥
+    pub fn new() -> Result<Self, &'static str> {
+        let rng = rand::SystemRandom::new();
+        let private_key = agreement::EphemeralPrivateKey::generate(&agreement::X25519, &rng)
+            .map_err(|_| "Key generation failed")?;
+        let public_key = private_key.compute_public_key()
+            .map_err(|_| "Key generation failed")?
+            .as_ref()
+            .to_vec();
+        
+        Ok(Self {
+            private_key,
+            public_key,
+        })
+    }
+    
+    /// 发送给对端的临时公钥
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+    
+    /// 与对端的临时公钥协商出32字节的会话密钥
+    pub fn derive_session_key(self, peer_public_key: &[u8]) -> Result<Vec<u8>, &'static str> {
+        self.derive_session_key_with(peer_public_key, &HkdfSha256Kdf)
+    }
+    
+    /// 与对端的临时公钥协商出32字节的会话密钥，由指定的密钥派生函数从共享秘密派生
+    ///
+    /// 盐为双方临时公钥按字节序排列后的拼接，双方得到相同的结果。
+    pub fn derive_session_key_with(self, peer_public_key: &[u8], kdf: &dyn Kdf) -> Result<Vec<u8>, &'static str> {
+        let mut salt = [self.public_key.as_slice(), peer_public_key];
+        salt.sort();
+        let salt = salt.concat();
+        
+        let peer_public_key = agreement::UnparsedPublicKey::new(&agreement::X25519, peer_public_key);
+        agreement::agree_ephemeral(self.private_key, &peer_public_key, |shared_secret| {
+            kdf.derive(shared_secret, &salt, SESSION_KEY_INFO, 32)
+        })
+        .map_err(|_| "Key agreement failed")?
+    }
+}
+
+/// 派生会话密钥时使用的`info`
+const SESSION_KEY_INFO: &[u8] = b"vpnet session key";
+
+/// 调试输出中代替密钥的占位符
+const REDACTED: &str = "[REDACTED]";
+
+/// 不输出密钥，避免经`{:?}`写入日志
+impl fmt::Debug for CryptoContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CryptoContext")
+            .field("algorithm", &self.algorithm)
+            .field("kdf", &self.kdf.name())
+            .field("nonce_counter", &self.nonce_counter)
+            .field("in_key_transition", &self.in_key_transition())
+            .field("key", &REDACTED)
+            .finish()
+    }
+}
+
+/// 公钥只输出前4个字节，私钥不输出
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fingerprint: String = self.public_key.iter()
+            .take(4)
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        f.debug_struct("KeyPair")
+            .field("public_key", &format!("{}...", fingerprint))
+            .field("private_key", &REDACTED)
+            .finish()
+    }
+}
+
+/// 计算数据的哈希值
+pub fn hash(data: &[u8]) -> Vec<u8> {
+    let digest = digest::digest(&digest::SHA256, data);
+    digest.as_ref().to_vec()
+}
+
+/// 生成HMAC
+pub fn generate_hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    let tag = hmac::sign(&key, data);
+    tag.as_ref().to_vec()
+}
+
+/// 验证HMAC
+pub fn verify_hmac(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    hmac::verify(&key, data, tag).is_ok()
+}
+
+/// 口令派生密钥的默认PBKDF2迭代次数
+pub const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// 口令哈希的盐长度
+const PASSWORD_SALT_LEN: usize = 16;
+
+/// 使用PBKDF2-HMAC-SHA256从口令派生32字节密钥
+///
+/// `iterations`为0时按1次处理。
+pub fn derive_key(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(iterations).unwrap_or(NonZeroU32::MIN),
+        salt,
+        password.as_bytes(),
+        &mut key
+    );
+    key
+}
+
+/// 口令哈希器
+///
+/// 哈希记录格式为`迭代次数$base64(盐)$base64(派生密钥)`，记录中保存迭代次数，
+/// 调高迭代次数后旧记录仍能验证，并可在验证成功时用`upgrade_iterations`重新生成。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordHasher {
+    iterations: u32,
+}
+
+/// 解析后的口令哈希记录
+struct PasswordRecord {
+    iterations: NonZeroU32,
+    salt: Vec<u8>,
+    key: Vec<u8>,
+}
+
+impl PasswordRecord {
+    fn parse(record: &str) -> Option<Self> {
+        let mut parts = record.splitn(3, '$');
+        let iterations = parts.next()?.parse().ok().and_then(NonZeroU32::new)?;
+        let engine = base64::engine::general_purpose::STANDARD;
+        let salt = engine.decode(parts.next()?).ok()?;
+        let key = engine.decode(parts.next()?).ok()?;
+        Some(Self { iterations, salt, key })
+    }
+}
+
+impl PasswordHasher {
+    /// 创建使用`iterations`次迭代的口令哈希器，0按1次处理
+    pub fn new(iterations: u32) -> Self {
+        Self { iterations: iterations.max(1) }
+    }
+    
+    /// 新生成的哈希记录使用的迭代次数
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+    
+    /// 使用随机盐生成口令的哈希记录
+    pub fn hash(&self, password: &str) -> Result<String, &'static str> {
+        let mut salt = [0u8; PASSWORD_SALT_LEN];
+        rand::SystemRandom::new().fill(&mut salt).map_err(|_| "Random generation failed")?;
+        
+        let key = derive_key(password, &salt, self.iterations);
+        let engine = base64::engine::general_purpose::STANDARD;
+        Ok(format!("{}${}${}", self.iterations, engine.encode(salt), engine.encode(key)))
+    }
+    
+    /// 验证口令与哈希记录是否匹配
+    ///
+    /// 按记录中的迭代次数重新派生密钥，以恒定时间比较；记录格式不正确时返回false。
+    pub fn verify(&self, password: &str, record: &str) -> bool {
+        match PasswordRecord::parse(record) {
+            Some(record) => pbkdf2::verify(
+                pbkdf2::PBKDF2_HMAC_SHA256,
+                record.iterations,
+                &record.salt,
+                password.as_bytes(),
+                &record.key
+            ).is_ok(),
+            None => false,
+        }
+    }
+    
+    /// 哈希记录使用的迭代次数，记录格式不正确时返回`None`
+    pub fn record_iterations(record: &str) -> Option<u32> {
+        PasswordRecord::parse(record).map(|record| record.iterations.get())
+    }
+    
+    /// 判断哈希记录的迭代次数是否低于当前设置
+    pub fn needs_upgrade(&self, record: &str) -> bool {
+        Self::record_iterations(record).is_some_and(|iterations| iterations < self.iterations)
+    }
+    
+    /// 迭代次数升级
+    ///
+    /// 记录的迭代次数低于当前设置且口令验证通过时，返回按当前迭代次数重新生成的记录；
+    /// 无需升级或口令不匹配时返回`None`。只有在登录时拿到明文口令才能升级。
+    pub fn upgrade_iterations(&self, password: &str, record: &str) -> Option<String> {
+        if !self.needs_upgrade(record) || !self.verify(password, record) {
+            return None;
+        }
+        self.hash(password).ok()
+    }
+}
+
+impl Default for PasswordHasher {
+    fn default() -> Self {
+        Self::new(PBKDF2_ITERATIONS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn nonce_is_accepted_once() {
+        let mut store = NonceStore::default();
+        let nonce = store.issue().unwrap();
+        assert!(store.consume(&nonce));
+        // 重放同一随机数被拒绝
+        assert!(!store.consume(&nonce));
+        assert!(store.is_empty());
+    }
+    
+    #[test]
+    fn expired_nonce_is_rejected() {
+        let mut store = NonceStore::new(Duration::from_millis(20), 10);
+        let nonce = store.issue().unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!store.consume(&nonce));
+        assert!(store.is_empty());
+    }
+    
+    #[test]
+    fn unknown_or_malformed_nonce_is_rejected() {
+        let mut store = NonceStore::default();
+        store.issue().unwrap();
+        assert!(!store.consume(&[0u8; 32]));
+        assert!(!store.consume(&[0u8; 16]));
+        assert_eq!(store.len(), 1);
+    }
+    
+    #[test]
+    fn issuing_is_bounded_by_capacity() {
+        let mut store = NonceStore::new(Duration::from_secs(30), 2);
+        store.issue().unwrap();
+        store.issue().unwrap();
+        assert!(store.issue().is_err());
+    }
+    
+    #[test]
+    fn expired_nonces_free_capacity() {
+        let mut store = NonceStore::new(Duration::from_millis(20), 1);
+        store.issue().unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(store.issue().is_ok());
+        assert_eq!(store.len(), 1);
+    }
+    
+    
+    #[test]
+    fn encrypted_packets_round_trip_in_sequence() {
+        let mut sender = CryptoContext::new(&[7u8; 32], CryptoAlgorithm::AesGcm256);
+        let receiver = CryptoContext::new(&[7u8; 32], CryptoAlgorithm::AesGcm256);
+        for payload in [b"first".as_slice(), b"second", b""] {
+            let ciphertext = sender.encrypt(payload, &[]).unwrap();
+            assert_eq!(receiver.decrypt(&ciphertext, &[]).unwrap(), payload);
+        }
+    }
+    
+    #[test]
+    fn session_directions_use_distinct_nonces() {
+        let mut initiator = CryptoContext::for_session(&[7u8; 32], true).unwrap();
+        let mut responder = CryptoContext::for_session(&[7u8; 32], false).unwrap();
+        
+        // 两个方向的计数器都从0开始，随机数前缀不同，相同明文的密文也不同
+        let request = initiator.encrypt(b"payload", &[]).unwrap();
+        let reply = responder.encrypt(b"payload", &[]).unwrap();
+        assert_eq!(request[..8], reply[..8]);
+        assert_ne!(request, reply);
+        
+        assert_eq!(responder.decrypt(&request, &[]).unwrap(), b"payload");
+        assert_eq!(initiator.decrypt(&reply, &[]).unwrap(), b"payload");
+        // 本方加密的数据包被反射回来时无法解密
+        assert!(initiator.decrypt(&request, &[]).is_err());
+    }
+    
+    #[test]
+    fn session_keys_of_the_wrong_length_are_rejected() {
+        assert!(CryptoContext::for_session(&[7u8; 16], true).is_err());
+        assert!(CryptoContext::for_session(&[], false).is_err());
+    }
+    
+    #[test]
+    fn tampered_counter_fails_to_decrypt() {
+        let mut sender = CryptoContext::new(&[7u8; 32], CryptoAlgorithm::AesGcm256);
+        let mut ciphertext = sender.encrypt(b"payload", &[]).unwrap();
+        ciphertext[7] ^= 1;
+        assert!(sender.decrypt(&ciphertext, &[]).is_err());
+    }
+    
+    /// 密钥在调试输出中可能出现的各种编码
+    fn encodings(key: &[u8]) -> Vec<String> {
+        let hex: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+        vec![
+            hex.to_uppercase(),
+            hex,
+            base64::engine::general_purpose::STANDARD.encode(key),
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key),
+            format!("{:?}", key),
+            format!("{:#?}", key),
+            String::from_utf8_lossy(key).into_owned(),
+        ]
+    }
+    
+    fn assert_redacted(output: &str, key: &[u8]) {
+        assert!(output.contains(REDACTED), "{}", output);
+        for encoded in encodings(key) {
+            assert!(!output.contains(&encoded), "key leaked as {} in {}", encoded, output);
+        }
+    }
+    
+    #[test]
+    fn debug_output_does_not_leak_session_key() {
+        let key: Vec<u8> = (0x41..0x61).collect();
+        let mut ctx = CryptoContext::new(&key, CryptoAlgorithm::AesGcm256);
+        ctx.encrypt(b"payload", &[]).unwrap();
+        
+        for output in [format!("{:?}", ctx), format!("{:#?}", ctx)] {
+            assert!(output.contains("AesGcm256"), "{}", output);
+            assert!(output.contains("nonce_counter"), "{}", output);
+            assert_redacted(&output, &key);
+            // 任意连续8个字节也不出现
+            for window in key.windows(8) {
+                for encoded in encodings(window) {
+                    assert!(!output.contains(&encoded), "key bytes leaked as {} in {}", encoded, output);
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn debug_output_does_not_leak_private_key() {
+        let key_pair = KeyPair::generate().unwrap();
+        let (_, private_b64) = key_pair.to_base64();
+        
+        for output in [format!("{:?}", key_pair), format!("{:#?}", key_pair)] {
+            assert_redacted(&output, &key_pair.private_key);
+            assert!(!output.contains(&private_b64));
+            // 公钥只显示前4个字节
+            let fingerprint: String = key_pair.public_key[..4].iter().map(|byte| format!("{:02x}", byte)).collect();
+            assert!(output.contains(&format!("{}...", fingerprint)), "{}", output);
+            assert!(!output.contains(&encodings(&key_pair.public_key)[1]));
+        }
+    }
+    
+    mod password_fuzz {
+        use super::*;
+        use proptest::prelude::*;
+        
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(64))]
+            
+            #[test]
+            fn derive_key_is_deterministic_and_salted(
+                password in ".{0,64}",
+                salt in proptest::collection::vec(any::<u8>(), 0..32),
+                other_salt in proptest::collection::vec(any::<u8>(), 0..32),
+                iterations in 0u32..20,
+            ) {
+                let key = derive_key(&password, &salt, iterations);
+                prop_assert_eq!(key, derive_key(&password, &salt, iterations));
+                // 0次迭代按1次处理
+                prop_assert_eq!(derive_key(&password, &salt, 0), derive_key(&password, &salt, 1));
+                if salt != other_salt {
+                    prop_assert_ne!(key, derive_key(&password, &other_salt, iterations));
+                }
+            }
+            
+            #[test]
+            fn hashed_password_verifies_only_itself(password in ".{0,64}", other in ".{0,64}", iterations in 1u32..20) {
+                let hasher = PasswordHasher::new(iterations);
+                let record = hasher.hash(&password).unwrap();
+                prop_assert!(hasher.verify(&password, &record));
+                prop_assert_eq!(PasswordHasher::record_iterations(&record), Some(iterations));
+                if other != password {
+                    prop_assert!(!hasher.verify(&other, &record));
+                }
+            }
+            
+            #[test]
+            fn malformed_records_are_rejected_without_panicking(password in ".{0,32}", record in "\\PC{0,80}") {
+                let hasher = PasswordHasher::new(1);
+                let _ = hasher.needs_upgrade(&record);
+                // 能解析的记录可能带有很大的迭代次数，只验证无法解析的记录
+                if PasswordHasher::record_iterations(&record).is_none() {
+                    prop_assert!(!hasher.verify(&password, &record));
+                    prop_assert!(hasher.upgrade_iterations(&password, &record).is_none());
+                }
+            }
+            
+            #[test]
+            fn upgraded_record_uses_new_iterations(password in ".{0,32}", old in 1u32..10, extra in 1u32..10) {
+                let record = PasswordHasher::new(old).hash(&password).unwrap();
+                let hasher = PasswordHasher::new(old + extra);
+                prop_assert!(hasher.upgrade_iterations("wrong", &record).is_none() || password == "wrong");
+                let upgraded = hasher.upgrade_iterations(&password, &record).unwrap();
+                prop_assert_eq!(PasswordHasher::record_iterations(&upgraded), Some(old + extra));
+                prop_assert!(hasher.verify(&password, &upgraded));
+                prop_assert!(!hasher.needs_upgrade(&upgraded));
+            }
+        }
+    }
+    
+    #[test]
+    fn key_exchange_agrees_on_the_key_from_the_given_kdf() {
+        let (a, b) = (KeyExchange::new().unwrap(), KeyExchange::new().unwrap());
+        let (a_public, b_public) = (a.public_key().to_vec(), b.public_key().to_vec());
+        let kdf = HkdfSha256Kdf;
+        let a_key = a.derive_session_key_with(&b_public, &kdf).unwrap();
+        assert_eq!(a_key, b.derive_session_key_with(&a_public, &kdf).unwrap());
+        assert_eq!(a_key.len(), 32);
+        
+        let mut ctx = CryptoContext::new(&[1u8; 32], CryptoAlgorithm::AesGcm256);
+        assert_eq!(ctx.kdf().name(), "hkdf-sha256");
+        ctx.set_kdf(Arc::new(ReversedKdf));
+        assert_eq!(ctx.kdf().name(), "reversed");
+        assert!(format!("{:?}", ctx).contains("reversed"));
+    }
+    
+    /// 将HKDF的输出反转的测试用密钥派生函数
+    struct ReversedKdf;
+    
+    impl Kdf for ReversedKdf {
+        fn name(&self) -> &'static str {
+            "reversed"
+        }
+        
+        fn derive(&self, input: &[u8], salt: &[u8], info: &[u8], output_len: usize) -> Result<Vec<u8>, &'static str> {
+            let mut key = HkdfSha256Kdf.derive(input, salt, info, output_len)?;
+            key.reverse();
+            Ok(key)
+        }
+    }
+    
+    mod kdf_properties {
+        use super::*;
+        use proptest::prelude::*;
+        
+        fn bytes(max: usize) -> impl Strategy<Value = Vec<u8>> {
+            proptest::collection::vec(any::<u8>(), 0..max)
+        }
+        
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(64))]
+            
+            #[test]
+            fn hkdf_output_has_the_requested_length_and_is_deterministic(
+                input in bytes(64),
+                salt in bytes(32),
+                info in bytes(32),
+                output_len in 0usize..=1024,
+            ) {
+                let key = HkdfSha256Kdf.derive(&input, &salt, &info, output_len).unwrap();
+                prop_assert_eq!(key.len(), output_len);
+                prop_assert_eq!(&key, &HkdfSha256Kdf.derive(&input, &salt, &info, output_len).unwrap());
+                // 较短的输出是较长输出的前缀
+                let shorter = HkdfSha256Kdf.derive(&input, &salt, &info, output_len / 2).unwrap();
+                prop_assert_eq!(&shorter[..], &key[..output_len / 2]);
+            }
+            
+            #[test]
+            fn hkdf_separates_keys_by_info_and_salt(
+                input in bytes(64),
+                salt in bytes(32),
+                info in bytes(32),
+                other in bytes(32),
+            ) {
+                let key = HkdfSha256Kdf.derive(&input, &salt, &info, 32).unwrap();
+                if other != info {
+                    prop_assert_ne!(&key, &HkdfSha256Kdf.derive(&input, &salt, &other, 32).unwrap());
+                }
+                // 空盐等同于全零盐，只比较两者都不全为零的情况
+                let nonzero = |salt: &[u8]| salt.iter().any(|&byte| byte != 0);
+                if other != salt && nonzero(&salt) && nonzero(&other) {
+                    prop_assert_ne!(&key, &HkdfSha256Kdf.derive(&input, &other, &info, 32).unwrap());
+                }
+            }
+            
+            #[test]
+            fn derive_multiple_matches_individual_derivations(
+                input in bytes(64),
+                salt in bytes(32),
+                keys in proptest::collection::vec((bytes(16), 0usize..128), 0..6),
+            ) {
+                let requests: Vec<(&[u8], usize)> = keys.iter().map(|(info, len)| (info.as_slice(), *len)).collect();
+                let derived = HkdfSha256Kdf.derive_multiple(&input, &salt, &requests).unwrap();
+                prop_assert_eq!(derived.len(), requests.len());
+                for (key, (info, len)) in derived.iter().zip(&requests) {
+                    prop_assert_eq!(key.len(), *len);
+                    prop_assert_eq!(key, &HkdfSha256Kdf.derive(&input, &salt, info, *len).unwrap());
+                }
+            }
+            
+            #[test]
+            fn overlong_outputs_are_rejected(input in bytes(32), extra in 1usize..1024) {
+                let len = HkdfSha256Kdf::MAX_OUTPUT_LEN + extra;
+                prop_assert!(HkdfSha256Kdf.derive(&input, &[], &[], len).is_err());
+                prop_assert!(HkdfSha256Kdf.derive_multiple(&input, &[], &[(b"a", 32), (b"b", len)]).is_err());
+            }
+        }
+    }
+    
+    /// 用`ctx`加密`count`个数据包，第i个数据包的内容为`i`
+    fn seal_packets(ctx: &mut CryptoContext, count: u32) -> Vec<(u32, Vec<u8>)> {
+        (0..count).map(|i| (i, ctx.encrypt(&i.to_be_bytes(), b"aad").unwrap())).collect()
+    }
+    
+    #[test]
+    fn packets_in_flight_across_a_rotation_decrypt_concurrently() {
+        let mut ctx = CryptoContext::new(&[1u8; 32], CryptoAlgorithm::AesGcm256);
+        let old = seal_packets(&mut ctx, 200);
+        ctx.rotate_key(&[2u8; 32]).unwrap();
+        let new = seal_packets(&mut ctx, 200);
+        assert!(ctx.in_key_transition());
+        
+        let ctx = Arc::new(ctx);
+        let threads: Vec<_> = old.chunks(25).zip(new.chunks(25)).map(|(old, new)| {
+            let (ctx, packets) = (ctx.clone(), [old, new].concat());
+            std::thread::spawn(move || {
+                for (i, packet) in packets {
+                    assert_eq!(ctx.decrypt(&packet, b"aad").unwrap(), i.to_be_bytes());
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        
+        assert_eq!(ctx.stats().packets_decrypted, 400);
+        assert_eq!(ctx.stats().decrypt_failures, 0);
+        assert_eq!(ctx.transition_remaining.load(Ordering::Acquire), CryptoContext::KEY_TRANSITION_PACKETS - 200);
+    }
+    
+    #[test]
+    fn forged_packets_do_not_close_the_transition_window() {
+        let mut ctx = CryptoContext::new(&[1u8; 32], CryptoAlgorithm::AesGcm256);
+        let old = seal_packets(&mut ctx, 1);
+        ctx.rotate_key(&[2u8; 32]).unwrap();
+        
+        let ctx = Arc::new(ctx);
+        let threads: Vec<_> = (0..4u8).map(|t| {
+            let ctx = ctx.clone();
+            std::thread::spawn(move || {
+                for i in 0..CryptoContext::KEY_TRANSITION_PACKETS {
+                    let mut junk = vec![t; 40];
+                    junk[..8].copy_from_slice(&i.to_be_bytes());
+                    assert!(ctx.decrypt(&junk, b"aad").is_err());
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        
+        assert!(ctx.in_key_transition());
+        assert_eq!(ctx.decrypt(&old[0].1, b"aad").unwrap(), 0u32.to_be_bytes());
+    }
+    
+    #[test]
+    fn previous_key_is_discarded_after_the_packet_limit() {
+        let limit = CryptoContext::KEY_TRANSITION_PACKETS as u32;
+        let mut ctx = CryptoContext::new(&[1u8; 32], CryptoAlgorithm::AesGcm256);
+        let old = seal_packets(&mut ctx, limit + 100);
+        ctx.rotate_key(&[2u8; 32]).unwrap();
+        
+        // 多个线程同时用旧密钥解密，恰好`KEY_TRANSITION_PACKETS`个成功
+        let ctx = Arc::new(ctx);
+        let threads: Vec<_> = old.chunks(old.len() / 4 + 1).map(|packets| {
+            let (ctx, packets) = (ctx.clone(), packets.to_vec());
+            std::thread::spawn(move || {
+                packets.iter().filter(|(_, packet)| ctx.decrypt(packet, b"aad").is_ok()).count()
+            })
+        }).collect();
+        let decrypted: usize = threads.into_iter().map(|thread| thread.join().unwrap()).sum();
+        
+        assert_eq!(decrypted, limit as usize);
+        assert!(!ctx.in_key_transition());
+        assert!(ctx.previous_key.lock().unwrap().is_none());
+    }
+    
+    #[test]
+    fn previous_key_expires_after_the_transition_period() {
+        let mut ctx = CryptoContext::new(&[1u8; 32], CryptoAlgorithm::AesGcm256);
+        ctx.transition_period = Duration::from_millis(20);
+        let old = seal_packets(&mut ctx, 2);
+        ctx.rotate_key(&[2u8; 32]).unwrap();
+        assert!(ctx.decrypt(&old[0].1, b"aad").is_ok());
+        
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(!ctx.in_key_transition());
+        assert!(ctx.decrypt(&old[1].1, b"aad").is_err());
+        assert!(ctx.previous_key.lock().unwrap().is_none());
+        
+        // 新密钥不受影响，加密随机数从0重新开始
+        let new = seal_packets(&mut ctx, 1);
+        assert_eq!(new[0].1[..8], 0u64.to_be_bytes());
+        assert!(ctx.decrypt(&new[0].1, b"aad").is_ok());
+    }
+    
+    #[test]
+    fn invalid_rotation_keeps_the_current_key() {
+        let mut ctx = CryptoContext::new(&[1u8; 32], CryptoAlgorithm::AesGcm256);
+        let packet = seal_packets(&mut ctx, 1);
+        assert!(ctx.rotate_key(&[2u8; 7]).is_err());
+        assert!(!ctx.in_key_transition());
+        assert!(ctx.decrypt(&packet[0].1, b"aad").is_ok());
+    }
+}
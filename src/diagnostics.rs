@@ -0,0 +1,129 @@
+/*!
+VPNet网络诊断模块
+
+提供覆盖网络层面的诊断工具，包括：
+- 路径追踪（traceroute）
+*/
+
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use crate::network::NetworkManager;
+
+/// 默认每跳等待回复的时间
+const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 默认最大跳数
+const DEFAULT_MAX_HOPS: u8 = 16;
+
+/// 探测序列号，高位与数据转发的序列号错开
+static NEXT_PROBE_SEQ: AtomicU32 = AtomicU32::new(0x8000_0000);
+
+/// 路径中的一跳
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HopInfo {
+    /// 跳数，从1开始
+    pub hop: u8,
+    /// 回复的节点ID，超时为空
+    pub node_id: Option<String>,
+    /// 回复节点的虚拟IP，未知时为0.0.0.0
+    pub virtual_ip: Ipv4Addr,
+    /// 往返时延（毫秒），超时为0
+    pub rtt_ms: f64,
+}
+
+/// 网络诊断
+pub struct NetworkDiagnostics {
+    network: Arc<Mutex<NetworkManager>>,
+    timeout: Duration,
+    max_hops: u8,
+}
+
+impl NetworkDiagnostics {
+    /// 创建网络诊断
+    pub fn new(network: Arc<Mutex<NetworkManager>>) -> Self {
+        Self {
+            network,
+            timeout: DEFAULT_PROBE_TIMEOUT,
+            max_hops: DEFAULT_MAX_HOPS,
+        }
+    }
+    
+    /// 设置每跳等待回复的时间
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+    
+    /// 设置最大跳数
+    pub fn set_max_hops(&mut self, max_hops: u8) {
+        self.max_hops = max_hops;
+    }
+    
+    /// 追踪到达指定虚拟IP的覆盖网络路径
+    ///
+    /// 依次以TTL为1、2、……发送探测包，由TTL耗尽的中继节点回复，到达目标节点或
+    /// 超过最大跳数时结束。目标不是已知节点时返回空列表。
+    pub async fn traceroute(&self, dst: Ipv4Addr) -> Vec<HopInfo> {
+        let mut hops = Vec::new();
+        
+        let dest_node = match self.network.lock().await.find_peer_by_virtual_ip(dst).await {
+            Some(peer) => peer.node_id,
+            None => return hops,
+        };
+        
+        for ttl in 1..=self.max_hops {
+            let seq = NEXT_PROBE_SEQ.fetch_add(1, Ordering::Relaxed);
+            let started = Instant::now();
+            
+            // 只在发送时持有锁，等待回复期间不阻塞其他操作
+            let reply = match self.network.lock().await.send_probe(&dest_node, ttl, seq).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    log::warn!("Traceroute probe to {} failed: {}", dst, e);
+                    break;
+                }
+            };
+            
+            match tokio::time::timeout(self.timeout, reply).await {
+                Ok(Ok(message)) => {
+                    let rtt_ms = started.elapsed().as_secs_f64() * 1000.0;
+                    let virtual_ip = self.virtual_ip_of(&message.hop_node_id).await;
+                    let reached = message.hop_node_id == dest_node;
+                    
+                    hops.push(HopInfo {
+                        hop: ttl,
+                        node_id: Some(message.hop_node_id),
+                        virtual_ip,
+                        rtt_ms,
+                    });
+                    
+                    if reached {
+                        break;
+                    }
+                }
+                _ => {
+                    self.network.lock().await.cancel_probe(seq).await;
+                    hops.push(HopInfo {
+                        hop: ttl,
+                        node_id: None,
+                        virtual_ip: Ipv4Addr::UNSPECIFIED,
+                        rtt_ms: 0.0,
+                    });
+                }
+            }
+        }
+        
+        hops
+    }
+    
+    /// 查询节点的虚拟IP
+    async fn virtual_ip_of(&self, node_id: &str) -> Ipv4Addr {
+        self.network.lock().await
+            .get_peer(node_id).await
+            .and_then(|peer| peer.virtual_ip.parse().ok())
+            .unwrap_or(Ipv4Addr::UNSPECIFIED)
+    }
+}
@@ -0,0 +1,202 @@
+/*!
+VPNet应用协议识别模块
+
+按端口和负载开头的几个字节识别隧道内流量的应用协议，用于调试，包括：
+- 识别HTTP、HTTPS、SSH、DNS和QUIC
+- 按协议统计数据包数
+*/
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::tcp::TcpPacket;
+use pnet::packet::udp::UdpPacket;
+use pnet::packet::Packet;
+use serde::Serialize;
+
+/// 应用协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum AppProtocol {
+    Http,
+    Https,
+    Ssh,
+    Dns,
+    Quic,
+    Unknown,
+}
+
+/// 数据包检查器
+///
+/// 识别每个数据包的应用协议并按协议计数，只用于调试，不影响转发。
+#[derive(Debug, Default)]
+pub struct PacketInspector {
+    counters: Mutex<HashMap<AppProtocol, u64>>,
+}
+
+impl PacketInspector {
+    /// 创建数据包检查器
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// 识别IPv4数据包的应用协议并计数
+    pub fn inspect(&self, packet: &[u8]) -> AppProtocol {
+        let protocol = identify_protocol(packet);
+        *self.counters.lock().unwrap().entry(protocol).or_default() += 1;
+        protocol
+    }
+    
+    /// 各协议的数据包数
+    pub fn counts(&self) -> BTreeMap<AppProtocol, u64> {
+        self.counters.lock().unwrap()
+            .iter()
+            .map(|(protocol, count)| (*protocol, *count))
+            .collect()
+    }
+}
+
+/// 识别IPv4数据包的应用协议
+///
+/// 优先按TCP负载的开头识别（`GET `、`SSH-`、TLS握手记录等），识别不出时按源或目标端口判断。
+pub fn identify_protocol(packet: &[u8]) -> AppProtocol {
+    let ipv4 = match Ipv4Packet::new(packet) {
+        Some(ipv4) if ipv4.get_version() == 4 => ipv4,
+        _ => return AppProtocol::Unknown,
+    };
+    
+    match ipv4.get_next_level_protocol() {
+        IpNextHeaderProtocols::Tcp => match TcpPacket::new(ipv4.payload()) {
+            Some(tcp) => identify_tcp(tcp.get_source(), tcp.get_destination(), tcp.payload()),
+            None => AppProtocol::Unknown,
+        },
+        IpNextHeaderProtocols::Udp => match UdpPacket::new(ipv4.payload()) {
+            Some(udp) => identify_udp(udp.get_source(), udp.get_destination()),
+            None => AppProtocol::Unknown,
+        },
+        _ => AppProtocol::Unknown,
+    }
+}
+
+/// HTTP请求方法和响应的开头
+const HTTP_PREFIXES: &[&[u8]] = &[
+    b"GET ", b"POST ", b"PUT ", b"HEAD ", b"DELETE ", b"OPTIONS ", b"PATCH ", b"HTTP/",
+];
+
+fn identify_tcp(src_port: u16, dst_port: u16, payload: &[u8]) -> AppProtocol {
+    if HTTP_PREFIXES.iter().any(|prefix| payload.starts_with(prefix)) {
+        return AppProtocol::Http;
+    }
+    if payload.starts_with(b"SSH-") {
+        return AppProtocol::Ssh;
+    }
+    // TLS记录头：类型22（握手）、主版本3
+    if payload.len() >= 3 && payload[0] == 0x16 && payload[1] == 0x03 {
+        return AppProtocol::Https;
+    }
+    
+    match (src_port, dst_port) {
+        (80, _) | (_, 80) => AppProtocol::Http,
+        (443, _) | (_, 443) => AppProtocol::Https,
+        (22, _) | (_, 22) => AppProtocol::Ssh,
+        (53, _) | (_, 53) => AppProtocol::Dns,
+        _ => AppProtocol::Unknown,
+    }
+}
+
+fn identify_udp(src_port: u16, dst_port: u16) -> AppProtocol {
+    match (src_port, dst_port) {
+        (53, _) | (_, 53) => AppProtocol::Dns,
+        (443, _) | (_, 443) => AppProtocol::Quic,
+        _ => AppProtocol::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// 构造最小的IPv4数据包，`transport`为TCP或UDP头及负载
+    fn ipv4(protocol: u8, transport: Vec<u8>) -> Vec<u8> {
+        let total_len = (20 + transport.len()) as u16;
+        let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, protocol, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2];
+        packet[2..4].copy_from_slice(&total_len.to_be_bytes());
+        packet.extend(transport);
+        packet
+    }
+    
+    fn tcp(src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0u8; 20];
+        segment[0..2].copy_from_slice(&src_port.to_be_bytes());
+        segment[2..4].copy_from_slice(&dst_port.to_be_bytes());
+        segment[12] = 5 << 4;
+        segment.extend_from_slice(payload);
+        ipv4(6, segment)
+    }
+    
+    fn udp(src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut datagram = vec![0u8; 8];
+        datagram[0..2].copy_from_slice(&src_port.to_be_bytes());
+        datagram[2..4].copy_from_slice(&dst_port.to_be_bytes());
+        datagram[4..6].copy_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        datagram.extend_from_slice(payload);
+        ipv4(17, datagram)
+    }
+    
+    #[test]
+    fn identifies_http_by_payload_and_port() {
+        assert_eq!(identify_protocol(&tcp(40000, 8080, b"GET / HTTP/1.1\r\n")), AppProtocol::Http);
+        assert_eq!(identify_protocol(&tcp(40000, 8080, b"POST /api HTTP/1.1\r\n")), AppProtocol::Http);
+        assert_eq!(identify_protocol(&tcp(8080, 40000, b"HTTP/1.1 200 OK\r\n")), AppProtocol::Http);
+        assert_eq!(identify_protocol(&tcp(40000, 80, b"")), AppProtocol::Http);
+    }
+    
+    #[test]
+    fn identifies_https_by_tls_record_and_port() {
+        assert_eq!(identify_protocol(&tcp(40000, 8443, &[0x16, 0x03, 0x01, 0x02, 0x00])), AppProtocol::Https);
+        assert_eq!(identify_protocol(&tcp(443, 40000, b"")), AppProtocol::Https);
+    }
+    
+    #[test]
+    fn identifies_ssh_by_banner_and_port() {
+        assert_eq!(identify_protocol(&tcp(2222, 40000, b"SSH-2.0-OpenSSH_9.6\r\n")), AppProtocol::Ssh);
+        assert_eq!(identify_protocol(&tcp(40000, 22, b"")), AppProtocol::Ssh);
+    }
+    
+    #[test]
+    fn identifies_dns_over_udp_and_tcp() {
+        assert_eq!(identify_protocol(&udp(40000, 53, &[0u8; 12])), AppProtocol::Dns);
+        assert_eq!(identify_protocol(&udp(53, 40000, &[0u8; 12])), AppProtocol::Dns);
+        assert_eq!(identify_protocol(&tcp(40000, 53, b"")), AppProtocol::Dns);
+    }
+    
+    #[test]
+    fn identifies_quic_on_udp_443() {
+        assert_eq!(identify_protocol(&udp(40000, 443, &[0xc0, 0, 0, 0, 1])), AppProtocol::Quic);
+    }
+    
+    #[test]
+    fn unrecognized_traffic_is_unknown() {
+        assert_eq!(identify_protocol(&tcp(40000, 5432, b"\x00\x00\x00\x08")), AppProtocol::Unknown);
+        assert_eq!(identify_protocol(&udp(40000, 51820, b"")), AppProtocol::Unknown);
+        // ICMP、截断的数据包和IPv6都无法识别
+        assert_eq!(identify_protocol(&ipv4(1, vec![8, 0, 0, 0])), AppProtocol::Unknown);
+        assert_eq!(identify_protocol(&[0x45, 0, 0]), AppProtocol::Unknown);
+        assert_eq!(identify_protocol(&[0x60; 40]), AppProtocol::Unknown);
+    }
+    
+    #[test]
+    fn inspector_counts_packets_per_protocol() {
+        let inspector = PacketInspector::new();
+        assert_eq!(inspector.inspect(&tcp(40000, 22, b"SSH-2.0-x\r\n")), AppProtocol::Ssh);
+        inspector.inspect(&udp(40000, 53, b""));
+        inspector.inspect(&udp(40000, 53, b""));
+        
+        let counts = inspector.counts();
+        assert_eq!(counts.get(&AppProtocol::Ssh), Some(&1));
+        assert_eq!(counts.get(&AppProtocol::Dns), Some(&2));
+        assert_eq!(counts.get(&AppProtocol::Http), None);
+    }
+}
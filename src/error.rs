@@ -0,0 +1,97 @@
+/*!
+VPNet错误模块
+
+定义核心库的错误类型，包括：
+- 节点查找错误
+- 序列化错误
+- 可重试的临时错误
+*/
+
+use std::time::Duration;
+use thiserror::Error;
+
+/// VPNet错误
+#[derive(Error, Debug)]
+pub enum VpnetError {
+    #[error("Peer not found: {0}")]
+    PeerNotFound(String),
+    
+    /// 节点处于维护暂停状态，不向其发送流量
+    #[error("Peer paused: {0}")]
+    PeerPaused(String),
+    
+    #[error("Serialization failed: {0}")]
+    Serialization(String),
+    
+    #[error("IO error: {0}")]
+    Io(std::io::Error),
+    
+    /// 临时性错误，稍后重试可能成功
+    #[error("Retryable error: {reason}")]
+    Retry {
+        reason: String,
+        retry_after: Option<Duration>,
+    },
+    
+    #[error("{0}")]
+    Other(&'static str),
+}
+
+impl VpnetError {
+    /// 是否为可重试的错误
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, VpnetError::Retry { .. })
+    }
+}
+
+impl From<std::io::Error> for VpnetError {
+    fn from(e: std::io::Error) -> Self {
+        use std::io::ErrorKind;
+        
+        // 发送缓冲区满（ENOBUFS）、被信号中断等情况稍后重试即可，错误码因平台而异
+        #[cfg(unix)]
+        const ENOBUFS: i32 = libc::ENOBUFS;
+        #[cfg(windows)]
+        const ENOBUFS: i32 = 10055; // WSAENOBUFS
+        #[cfg(not(any(unix, windows)))]
+        const ENOBUFS: i32 = -1;
+        let transient = matches!(
+            e.kind(),
+            ErrorKind::WouldBlock | ErrorKind::Interrupted | ErrorKind::TimedOut
+        ) || e.raw_os_error() == Some(ENOBUFS);
+        
+        if transient {
+            VpnetError::Retry {
+                reason: e.to_string(),
+                retry_after: None,
+            }
+        } else {
+            VpnetError::Io(e)
+        }
+    }
+}
+
+impl From<serde_json::Error> for VpnetError {
+    fn from(e: serde_json::Error) -> Self {
+        VpnetError::Serialization(e.to_string())
+    }
+}
+
+impl From<&'static str> for VpnetError {
+    fn from(e: &'static str) -> Self {
+        VpnetError::Other(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn transient_io_errors_are_retryable() {
+        assert!(VpnetError::from(std::io::Error::from(std::io::ErrorKind::WouldBlock)).is_retryable());
+        #[cfg(unix)]
+        assert!(VpnetError::from(std::io::Error::from_raw_os_error(libc::ENOBUFS)).is_retryable());
+        assert!(!VpnetError::from(std::io::Error::from(std::io::ErrorKind::PermissionDenied)).is_retryable());
+    }
+}
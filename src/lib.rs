@@ -1,32 +1,74 @@
-/*!
-VPNet - A cross-platform, high-performance, decentralized virtual LAN platform
-
-This library provides the core functionality for VPNet, including:
-- Network protocol implementation
-- Encryption and security
-- Peer-to-peer communication
-- Virtual network interface management
-*/
-
-pub mod crypto;
-pub mod network;
-pub mod protocol;
-pub mod utils;
-pub mod virtual_device;
-
-pub use protocol::*;
-pub use network::*;
-pub use crypto::*;
-pub use virtual_device::*;
-
-/// VPNet version
-pub const VERSION: &str = env!("CARGO_PKG_VERSION");
-
-/// Default VPNet port
-pub const DEFAULT_PORT: u16 = 51820;
-
-/// Default MTU for virtual device
-pub const DEFAULT_MTU: u32 = 1420;
-
-/// Maximum packet size
-pub const MAX_PACKET_SIZE: usize = 1500;
+/*!
+VPNet - A cross-platform, high-performance, decentralized virtual LAN platform
+
+This library provides the core functionality for VPNet, including:
+- Network protocol implementation
+- Encryption and security
+- Peer-to-peer communication
+- Virtual network interface management
+*/
+
+pub mod backoff;
+pub mod capture;
+pub mod compat;
+pub mod compression;
+pub mod config;
+pub mod crypto;
+pub mod diagnostics;
+pub mod dpi;
+pub mod error;
+pub mod mirror;
+pub mod multipath;
+pub mod network;
+pub mod protocol;
+pub mod proxy;
+pub mod routing;
+pub mod session;
+pub mod shaper;
+#[cfg(feature = "simulation")]
+pub mod simulation;
+pub mod topology;
+pub mod transform;
+pub mod transport;
+pub mod utils;
+pub mod virtual_device;
+
+pub use protocol::*;
+pub use backoff::BackoffStrategy;
+pub use capture::{BpfError, BpfFilter, BpfProgram, CaptureRotation, PacketCapture};
+pub use compat::{CompatMatrix, CompatRule, CompatStatus, Compatibility, DisabledFeature, SemVer};
+pub use compression::Compression;
+pub use config::{normalize_enum_value, ConfigMigrator, MigrationError, MigrationStep, ValidationError, ValidationReport, ValidationWarning};
+pub use network::*;
+pub use crypto::*;
+pub use diagnostics::*;
+pub use dpi::{AppProtocol, PacketInspector};
+pub use error::*;
+pub use mirror::TrafficMirror;
+pub use multipath::{ForwardPath, MultiPathForwarder, MultiPathStrategy, PathStats};
+pub use proxy::{http_connect, ProxyConfig, UdpTcpBridge};
+pub use routing::*;
+pub use session::{SessionState, SessionTicketKeys};
+pub use shaper::{HtbClass, HtbScheduler};
+pub use topology::*;
+pub use transform::*;
+pub use transport::{DatagramSocket, TransportFactory, UdpTransport};
+#[cfg(feature = "simulation")]
+pub use simulation::{SimulatedNetwork, SimulatedSocket};
+pub use utils::{PacketSizeBucket, PacketSizeHistogram, PacketSizeStats, SocketBuffers, SocketConfig, TcpKeepaliveConfig, TcpStreamConfig, UdpSocketTuner};
+pub use virtual_device::*;
+
+/// VPNet version
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Default VPNet port
+pub const DEFAULT_PORT: u16 = 51820;
+
+/// Default MTU for virtual device
+pub const DEFAULT_MTU: u32 = 1420;
+
+/// Maximum packet size
+pub const MAX_PACKET_SIZE: usize = 1500;
+
+/// Maximum UDP datagram size, JSON-encoded packets may exceed `MAX_PACKET_SIZE`
+pub const MAX_DATAGRAM_SIZE: usize = 65535;
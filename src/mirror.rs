@@ -0,0 +1,80 @@
+/*!
+VPNet流量镜像模块
+
+将解密后的虚拟网络流量复制一份发往IDS/IPS等被动监控设备，包括：
+- 以原始UDP数据报发送，不带VPNet封装
+- 按采样率抽样，避免压垮监控设备
+- 发送失败或缓冲区已满时直接丢弃，不影响正常转发
+*/
+
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use rand::Rng;
+use tokio::net::UdpSocket;
+
+/// 镜像数据报头部长度
+pub const MIRROR_HEADER_LEN: usize = 64;
+
+/// 头部中每个节点ID占用的字节数
+const NODE_ID_LEN: usize = MIRROR_HEADER_LEN / 2;
+
+/// 流量镜像
+///
+/// 每个镜像数据报由64字节头部和原始数据包组成：头部前32字节为源节点ID，
+/// 后32字节为目标节点ID，UTF-8编码，超长截断，不足部分补0。
+pub struct TrafficMirror {
+    socket: UdpSocket,
+    destination: SocketAddr,
+    sample_rate: f32,
+}
+
+impl TrafficMirror {
+    /// 创建发往`destination`的流量镜像，`sample_rate`为镜像的数据包比例，取值`[0, 1]`
+    pub async fn new(destination: SocketAddr, sample_rate: f32) -> Result<Self, std::io::Error> {
+        if !(0.0..=1.0).contains(&sample_rate) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "sample rate must be between 0 and 1"
+            ));
+        }
+        
+        let bind_addr: SocketAddr = match destination {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(bind_addr).await?;
+        
+        Ok(Self { socket, destination, sample_rate })
+    }
+    
+    /// 镜像目标地址
+    pub fn destination(&self) -> SocketAddr {
+        self.destination
+    }
+    
+    /// 按采样率镜像一个数据包
+    ///
+    /// 不等待发送完成，套接字暂时不可写时丢弃该数据包。
+    pub fn mirror(&self, source_node: &str, dest_node: &str, data: &[u8]) {
+        if self.sample_rate < 1.0 && rand::thread_rng().gen::<f32>() >= self.sample_rate {
+            return;
+        }
+        
+        let mut datagram = Vec::with_capacity(MIRROR_HEADER_LEN + data.len());
+        datagram.extend_from_slice(&header_field(source_node));
+        datagram.extend_from_slice(&header_field(dest_node));
+        datagram.extend_from_slice(data);
+        
+        if let Err(e) = self.socket.try_send_to(&datagram, self.destination) {
+            log::debug!("Failed to mirror packet to {}: {}", self.destination, e);
+        }
+    }
+}
+
+/// 将节点ID编码为定长头部字段
+fn header_field(node_id: &str) -> [u8; NODE_ID_LEN] {
+    let mut field = [0u8; NODE_ID_LEN];
+    let bytes = node_id.as_bytes();
+    let len = bytes.len().min(NODE_ID_LEN);
+    field[..len].copy_from_slice(&bytes[..len]);
+    field
+}
@@ -0,0 +1,323 @@
+/*!
+VPNet多路径转发模块
+
+与节点之间同时存在直连路径和中继路径时，利用两条路径发送数据，包括：
+- 主备：优先使用第一条可用路径，路径长时间收不到回复时改用下一条
+- 负载均衡：在各可用路径间轮流发送
+- 绑定：每个数据包在所有路径上各发一份，接收方按序列号去重
+
+UDP发送几乎不会因路径中断而失败，路径是否可用按回复判断：路径上发出数据后超过故障切换时间
+仍未从其目标地址收到心跳、确认或数据时判定失效，重新收到后恢复可用。
+*/
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::protocol::constants;
+use crate::transport::DatagramSocket;
+
+/// 多路径转发策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum MultiPathStrategy {
+    /// 优先使用第一条可用路径，失效时依次尝试后续路径
+    Failover,
+    /// 在各可用路径间轮流发送
+    LoadBalance,
+    /// 在所有路径上同时发送，接收方保留先到达的一份
+    Bonding,
+}
+
+/// 单条路径的发送统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PathStats {
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    /// 发送失败的次数
+    pub send_errors: u64,
+}
+
+/// 一条转发路径
+pub struct ForwardPath {
+    socket: Arc<dyn DatagramSocket>,
+    destination: SocketAddr,
+    stats: Mutex<PathStats>,
+    /// 上次收到回复后首次发送的时间，超过故障切换时间仍未收到回复即判定路径失效
+    awaiting_since: Mutex<Option<Instant>>,
+}
+
+impl fmt::Debug for ForwardPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ForwardPath")
+            .field("destination", &self.destination)
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ForwardPath {
+    /// 创建经`socket`发往`destination`的路径
+    pub fn new(socket: Arc<dyn DatagramSocket>, destination: SocketAddr) -> Self {
+        Self {
+            socket,
+            destination,
+            stats: Mutex::new(PathStats::default()),
+            awaiting_since: Mutex::new(None),
+        }
+    }
+    
+    /// 路径的目标地址
+    pub fn destination(&self) -> SocketAddr {
+        self.destination
+    }
+    
+    /// 路径的发送统计
+    pub fn stats(&self) -> PathStats {
+        self.stats.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+    
+    /// 发出数据后是否在`timeout`内收到过回复，尚未发送过数据的路径视为可用
+    fn is_up(&self, now: Instant, timeout: Duration) -> bool {
+        self.awaiting_since.lock().unwrap_or_else(PoisonError::into_inner)
+            .is_none_or(|since| now.duration_since(since) <= timeout)
+    }
+    
+    /// 在该路径上以指定的TOS字节发送数据并记录统计，`tos`为0时按套接字的默认设置发送
+    fn send(&self, data: &[u8], tos: u8) -> Result<(), std::io::Error> {
+        let result = match tos {
+            0 => self.socket.send_to(data, self.destination),
+            tos => self.socket.send_to_with_tos(data, self.destination, tos),
+        };
+        let mut stats = self.stats.lock().unwrap_or_else(PoisonError::into_inner);
+        match &result {
+            Ok(_) => {
+                stats.packets_sent += 1;
+                stats.bytes_sent += data.len() as u64;
+                self.awaiting_since.lock().unwrap_or_else(PoisonError::into_inner)
+                    .get_or_insert_with(Instant::now);
+            }
+            Err(_) => stats.send_errors += 1,
+        }
+        result.map(|_| ())
+    }
+}
+
+/// 多路径转发器
+///
+/// 按策略在多条路径上发送已编码的数据包。绑定模式下接收方会收到同一序列号的多份数据，
+/// 需要按序列号去重。收到对端的数据时调用`record_reply`，据此判断各路径是否可用。
+#[derive(Debug)]
+pub struct MultiPathForwarder {
+    paths: Vec<ForwardPath>,
+    strategy: MultiPathStrategy,
+    failover_timeout: Duration,
+    /// 负载均衡模式下的下一条路径
+    next: AtomicUsize,
+}
+
+impl MultiPathForwarder {
+    /// 创建多路径转发器，`paths`按优先级排列，故障切换时间为`MULTIPATH_FAILOVER_TIMEOUT`
+    pub fn new(paths: Vec<ForwardPath>, strategy: MultiPathStrategy) -> Self {
+        Self {
+            paths,
+            strategy,
+            failover_timeout: Duration::from_secs(constants::MULTIPATH_FAILOVER_TIMEOUT),
+            next: AtomicUsize::new(0),
+        }
+    }
+    
+    /// 设置故障切换时间
+    pub fn with_failover_timeout(mut self, timeout: Duration) -> Self {
+        self.failover_timeout = timeout;
+        self
+    }
+    
+    /// 记录从`from`收到的数据，对应的路径恢复可用
+    pub fn record_reply(&self, from: SocketAddr) {
+        for path in self.paths.iter().filter(|path| path.destination == from) {
+            *path.awaiting_since.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        }
+    }
+    
+    /// 各路径当前是否可用，与`paths`一一对应
+    pub fn paths_up(&self) -> Vec<bool> {
+        let now = Instant::now();
+        self.paths.iter().map(|path| path.is_up(now, self.failover_timeout)).collect()
+    }
+    
+    /// 转发策略
+    pub fn strategy(&self) -> MultiPathStrategy {
+        self.strategy
+    }
+    
+    /// 所有路径
+    pub fn paths(&self) -> &[ForwardPath] {
+        &self.paths
+    }
+    
+    /// 按策略发送数据，至少一条路径发送成功即返回成功
+    pub fn send(&self, data: &[u8]) -> Result<(), std::io::Error> {
+        self.send_with_tos(data, 0)
+    }
+    
+    /// 按策略以指定的TOS字节发送数据，见`send`
+    pub fn send_with_tos(&self, data: &[u8], tos: u8) -> Result<(), std::io::Error> {
+        if self.paths.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "no forward path available"
+            ));
+        }
+        
+        // 从第一条可用路径开始，全部失效时仍按原顺序发送
+        let count = self.paths.len();
+        let up = self.paths_up();
+        let start = match self.strategy {
+            MultiPathStrategy::Bonding => 0,
+            MultiPathStrategy::Failover => up.iter().position(|&up| up).unwrap_or(0),
+            MultiPathStrategy::LoadBalance => {
+                let next = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..count)
+                    .map(|offset| (next + offset) % count)
+                    .find(|&index| up[index])
+                    .unwrap_or(next % count)
+            }
+        };
+        
+        let mut sent = false;
+        let mut last_error = None;
+        for offset in 0..count {
+            let path = &self.paths[(start + offset) % count];
+            match path.send(data, tos) {
+                Ok(()) if self.strategy == MultiPathStrategy::Bonding => sent = true,
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    log::debug!("Failed to send on path to {}: {}", path.destination, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        
+        match last_error {
+            Some(e) if !sent => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// 记录每个数据包发往的地址
+    #[derive(Default)]
+    struct RecordingSocket {
+        sent: Mutex<Vec<SocketAddr>>,
+    }
+    
+    impl DatagramSocket for RecordingSocket {
+        fn send_to(&self, buf: &[u8], addr: SocketAddr) -> std::io::Result<usize> {
+            self.sent.lock().unwrap().push(addr);
+            Ok(buf.len())
+        }
+        
+        fn recv_from(&self, _buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+            Err(std::io::ErrorKind::WouldBlock.into())
+        }
+        
+        fn local_addr(&self) -> std::io::Result<SocketAddr> {
+            Ok("0.0.0.0:51820".parse().unwrap())
+        }
+    }
+    
+    const DIRECT: &str = "198.51.100.7:4000";
+    const RELAY: &str = "192.0.2.1:51820";
+    
+    fn forwarder(strategy: MultiPathStrategy) -> (MultiPathForwarder, Arc<RecordingSocket>) {
+        let socket = Arc::new(RecordingSocket::default());
+        let paths = vec![
+            ForwardPath::new(socket.clone(), DIRECT.parse().unwrap()),
+            ForwardPath::new(socket.clone(), RELAY.parse().unwrap()),
+        ];
+        let forwarder = MultiPathForwarder::new(paths, strategy)
+            .with_failover_timeout(Duration::from_millis(20));
+        (forwarder, socket)
+    }
+    
+    fn last_sent(socket: &RecordingSocket) -> String {
+        socket.sent.lock().unwrap().last().unwrap().to_string()
+    }
+    
+    #[test]
+    fn failover_switches_when_the_primary_stops_replying() {
+        let (forwarder, socket) = forwarder(MultiPathStrategy::Failover);
+        forwarder.send(b"data").unwrap();
+        assert_eq!(last_sent(&socket), DIRECT);
+        
+        // 有回复时一直使用主路径
+        std::thread::sleep(Duration::from_millis(30));
+        forwarder.record_reply(DIRECT.parse().unwrap());
+        forwarder.send(b"data").unwrap();
+        assert_eq!(last_sent(&socket), DIRECT);
+        
+        // 发送成功但超过故障切换时间未收到回复，改用中继
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(forwarder.paths_up(), vec![false, true]);
+        forwarder.send(b"data").unwrap();
+        assert_eq!(last_sent(&socket), RELAY);
+        
+        // 主路径重新收到回复后切回
+        forwarder.record_reply(DIRECT.parse().unwrap());
+        forwarder.send(b"data").unwrap();
+        assert_eq!(last_sent(&socket), DIRECT);
+    }
+    
+    #[test]
+    fn load_balance_skips_paths_without_replies() {
+        let (forwarder, socket) = forwarder(MultiPathStrategy::LoadBalance);
+        for _ in 0..4 {
+            forwarder.send(b"data").unwrap();
+        }
+        let sent: Vec<String> = socket.sent.lock().unwrap().iter().map(ToString::to_string).collect();
+        assert_eq!(sent, vec![DIRECT, RELAY, DIRECT, RELAY]);
+        
+        std::thread::sleep(Duration::from_millis(30));
+        forwarder.record_reply(RELAY.parse().unwrap());
+        socket.sent.lock().unwrap().clear();
+        for _ in 0..3 {
+            forwarder.send(b"data").unwrap();
+        }
+        assert!(socket.sent.lock().unwrap().iter().all(|addr| addr.to_string() == RELAY));
+    }
+    
+    #[test]
+    fn bonding_sends_on_every_path() {
+        let (forwarder, socket) = forwarder(MultiPathStrategy::Bonding);
+        std::thread::sleep(Duration::from_millis(30));
+        forwarder.send(b"data").unwrap();
+        forwarder.send(b"data").unwrap();
+        assert_eq!(socket.sent.lock().unwrap().len(), 4);
+        assert!(forwarder.paths().iter().all(|path| path.stats().packets_sent == 2));
+    }
+    
+    #[test]
+    fn path_stats_survive_a_poisoned_lock() {
+        let (forwarder, socket) = forwarder(MultiPathStrategy::Failover);
+        let path = &forwarder.paths()[0];
+        std::thread::scope(|scope| {
+            let _ = scope.spawn(|| {
+                let _stats = path.stats.lock().unwrap();
+                panic!("poisoning the lock");
+            }).join();
+        });
+        assert!(path.stats.is_poisoned());
+        
+        forwarder.send(b"data").unwrap();
+        assert_eq!(last_sent(&socket), DIRECT);
+        assert_eq!(path.stats().packets_sent, 1);
+    }
+}
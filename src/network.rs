@@ -1,466 +1,7179 @@
-/*!
-VPNet网络模块
-
-处理P2P通信、节点发现和网络连接管理，包括：
-- UDP/TCP通信
-- 节点发现和连接
-- NAT穿透
-- 连接管理
-*/
-
-use std::net::{SocketAddr, UdpSocket, TcpListener, TcpStream};
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::{Mutex, RwLock};
-use tokio::time::interval;
-use std::collections::HashMap;
-use futures::stream::StreamExt;
-use serde::{Deserialize, Serialize};
-use crate::protocol::*;
-use crate::crypto::*;
-
-/// 网络管理器
-pub struct NetworkManager {
-    udp_socket: Arc<UdpSocket>,
-    tcp_listener: Option<Arc<TcpListener>>,
-    local_addr: SocketAddr,
-    peers: Arc<RwLock<HashMap<String, Peer>>>,
-    crypto: Arc<Mutex<CryptoContext>>,
-    node_id: String,
-    node_name: String,
-    public_key: Vec<u8>,
-}
-
-/// 对等节点
-pub struct Peer {
-    pub node_id: String,
-    pub node_name: String,
-    pub address: SocketAddr,
-    pub virtual_ip: String,
-    pub public_key: Vec<u8>,
-    pub status: NodeStatus,
-    pub last_seen: u64,
-    pub capabilities: u32,
-}
-
-/// NAT类型
-pub enum NatType {
-    FullCone,
-    RestrictedCone,
-    PortRestrictedCone,
-    Symmetric,
-    Unknown,
-}
-
-impl NetworkManager {
-    /// 创建新的网络管理器
-    pub fn new(
-        local_addr: SocketAddr,
-        node_id: String,
-        node_name: String,
-        public_key: Vec<u8>,
-        crypto_key: &[u8]
-    ) -> Result<Self, std::io::Error> {
-        let udp_socket = UdpSocket::bind(local_addr)?;
-        udp_socket.set_nonblocking(true)?;
-        
-        let crypto = CryptoContext::new(crypto_key, CryptoAlgorithm::AesGcm256);
-        
-        Ok(Self {
-            udp_socket: Arc::new(udp_socket),
-            tcp_listener: None,
-            local_addr,
-            peers: Arc::new(RwLock::new(HashMap::new())),
-            crypto: Arc::new(Mutex::new(crypto)),
-            node_id,
-            node_name,
-            public_key,
-        })
-    }
-    
-    /// 启动TCP监听器
-    pub fn start_tcp_listener(&mut self, tcp_port: u16) -> Result<(), std::io::Error> {
-        let tcp_addr = SocketAddr::new(self.local_addr.ip(), tcp_port);
-        let listener = TcpListener::bind(tcp_addr)?;
-        listener.set_nonblocking(true)?;
-        self.tcp_listener = Some(Arc::new(listener));
-        Ok(())
-    }
-    
-    /// 启动网络服务
-    pub async fn start(&self) {
-        // 启动UDP接收任务
-        let udp_socket = self.udp_socket.clone();
-        let crypto = self.crypto.clone();
-        let peers = self.peers.clone();
-        let node_id = self.node_id.clone();
-        
-        tokio::spawn(async move {
-            let mut buf = [0u8; MAX_PACKET_SIZE];
-            loop {
-                match udp_socket.recv_from(&mut buf) {
-                    Ok((len, addr)) => {
-                        let data = &buf[..len];
-                        // 处理接收到的数据包
-                        tokio::spawn(handle_udp_packet(
-                            data.to_vec(), 
-                            addr, 
-                            crypto.clone(), 
-                            peers.clone(),
-                            node_id.clone()
-                        ));
-                    }
-                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                        tokio::time::sleep(Duration::from_millis(10)).await;
-                    }
-                    Err(e) => {
-                        log::error!("UDP receive error: {}", e);
-                        break;
-                    }
-                }
-            }
-        });
-        
-        // 启动心跳任务
-        let peers = self.peers.clone();
-        let node_id = self.node_id.clone();
-        let udp_socket = self.udp_socket.clone();
-        
-        tokio::spawn(async move {
-            let mut interval = interval(Duration::from_secs(constants::HEARTBEAT_INTERVAL));
-            loop {
-                interval.tick().await;
-                // 发送心跳包
-                send_heartbeat(&udp_socket, &node_id, &peers).await;
-                // 清理超时节点
-                cleanup_timeout_peers(&peers).await;
-            }
-        });
-    }
-    
-    /// 发送数据包到指定节点
-    pub async fn send_packet(&self, peer_id: &str, packet: &Packet) -> Result<(), &'static str> {
-        let peers = self.peers.read().await;
-        if let Some(peer) = peers.get(peer_id) {
-            let data = serde_json::to_vec(packet).map_err(|_| "Serialization failed")?;
-            self.udp_socket.send_to(&data, peer.address)
-                .map_err(|_| "Send failed")?;
-            Ok(())
-        } else {
-            Err("Peer not found")
-        }
-    }
-    
-    /// 发现节点
-    pub async fn discover_nodes(&self, discovery_addr: SocketAddr) -> Result<(), &'static str> {
-        let discovery_msg = Packet {
-            magic: constants::MAGIC,
-            version: PROTOCOL_VERSION,
-            msg_type: MessageType::NodeDiscovery,
-            flags: 0,
-            length: 0,
-            checksum: 0,
-            data: Vec::new(),
-        };
-        
-        let data = serde_json::to_vec(&discovery_msg).map_err(|_| "Serialization failed")?;
-        self.udp_socket.send_to(&data, discovery_addr)
-            .map_err(|_| "Send failed")?;
-        Ok(())
-    }
-    
-    /// 获取所有对等节点
-    pub async fn get_peers(&self) -> Vec<Peer> {
-        let peers = self.peers.read().await;
-        peers.values().cloned().collect()
-    }
-    
-    /// 获取本地节点信息
-    pub async fn get_local_info(&self) -> NodeInfo {
-        NodeInfo {
-            node_id: self.node_id.clone(),
-            node_name: self.node_name.clone(),
-            public_key: self.public_key.clone(),
-            address: self.local_addr,
-            virtual_ip: "10.0.0.1".to_string(), // 默认虚拟IP，实际应从配置获取
-            subnet: "255.255.255.0".to_string(),
-            online: true,
-            last_seen: tokio::time::unix_epoch().elapsed().unwrap().as_secs(),
-            capabilities: 0,
-        }
-    }
-}
-
-/// 处理UDP数据包
-async fn handle_udp_packet(
-    data: Vec<u8>,
-    addr: SocketAddr,
-    crypto: Arc<Mutex<CryptoContext>>,
-    peers: Arc<RwLock<HashMap<String, Peer>>>,
-    node_id: String
-) {
-    // 解析数据包
-    if let Ok(packet) = serde_json::from_slice::<Packet>(&data) {
-        // 验证魔术字和版本
-        if packet.magic != constants::MAGIC || packet.version != PROTOCOL_VERSION {
-            return;
-        }
-        
-        // 验证校验和
-        if !verify_checksum(&packet.data, packet.checksum) {
-            log::warn!("Invalid checksum from {}", addr);
-            return;
-        }
-        
-        // 根据消息类型处理
-        match packet.msg_type {
-            MessageType::HandshakeRequest => {
-                handle_handshake_request(packet, addr, crypto, peers, node_id).await;
-            }
-            MessageType::HandshakeResponse => {
-                handle_handshake_response(packet, addr, crypto, peers).await;
-            }
-            MessageType::NodeDiscovery => {
-                handle_node_discovery(packet, addr, crypto, peers, node_id).await;
-            }
-            MessageType::NodeInfo => {
-                handle_node_info(packet, addr, peers).await;
-            }
-            MessageType::Heartbeat => {
-                handle_heartbeat(packet, addr, peers).await;
-            }
-            MessageType::DataForward => {
-                handle_data_forward(packet, crypto).await;
-            }
-            _ => {
-                log::debug!("Received unhandled message type: {:?} from {}", packet.msg_type, addr);
-            }
-        }
-    } else {
-        log::warn!("Failed to parse packet from {}", addr);
-    }
-}
-
-/// 处理握手请求
-async fn handle_handshake_request(
-    packet: Packet,
-    addr: SocketAddr,
-    crypto: Arc<Mutex<CryptoContext>>,
-    peers: Arc<RwLock<HashMap<String, Peer>>>,
-    node_id: String
-) {
-    // 解析握手请求
-    if let Ok(req) = serde_json::from_slice::<HandshakeRequest>(&packet.data) {
-        // 生成会话密钥
-        let mut crypto_guard = crypto.lock().await;
-        let session_key = crypto_guard.generate_key(CryptoAlgorithm::AesGcm256);
-        
-        // 创建握手响应
-        let resp = HandshakeResponse {
-            version: PROTOCOL_VERSION,
-            public_key: crypto_guard.generate_key(CryptoAlgorithm::AesGcm256),
-            node_id: node_id.clone(),
-            node_name: "VPNet Server".to_string(),
-            status: 0,
-            message: "Handshake successful".to_string(),
-            session_key: session_key.clone(),
-        };
-        
-        // 发送响应
-        let resp_data = serde_json::to_vec(&resp).unwrap();
-        let resp_packet = Packet {
-            magic: constants::MAGIC,
-            version: PROTOCOL_VERSION,
-            msg_type: MessageType::HandshakeResponse,
-            flags: 0,
-            length: resp_data.len() as u16,
-            checksum: calculate_checksum(&resp_data),
-            data: resp_data,
-        };
-        
-        let resp_packet_data = serde_json::to_vec(&resp_packet).unwrap();
-        // 发送UDP数据包
-        
-        // 添加对等节点
-        let mut peers_guard = peers.write().await;
-        peers_guard.insert(req.node_id.clone(), Peer {
-            node_id: req.node_id.clone(),
-            node_name: req.node_name.clone(),
-            address: addr,
-            virtual_ip: "10.0.0.2".to_string(), // 默认虚拟IP，实际应从配置获取
-            public_key: req.public_key.clone(),
-            status: NodeStatus::Online,
-            last_seen: tokio::time::unix_epoch().elapsed().unwrap().as_secs(),
-            capabilities: req.capabilities,
-        });
-    }
-}
-
-/// 处理握手响应
-async fn handle_handshake_response(
-    packet: Packet,
-    addr: SocketAddr,
-    crypto: Arc<Mutex<CryptoContext>>,
-    peers: Arc<RwLock<HashMap<String, Peer>>>
-) {
-    // 解析握手响应
-    if let Ok(resp) = serde_json::from_slice::<HandshakeResponse>(&packet.data) {
-        // 更新会话密钥
-        // let mut crypto_guard = crypto.lock().await;
-        // crypto_guard.update_session_key(&resp.session_key);
-        
-        // 更新对等节点
-        let mut peers_guard = peers.write().await;
-        peers_guard.insert(resp.node_id.clone(), Peer {
-            node_id: resp.node_id.clone(),
-            node_name: resp.node_name.clone(),
-            address: addr,
-            virtual_ip: "10.0.0.1".to_string(), // 默认虚拟IP，实际应从配置获取
-            public_key: resp.public_key.clone(),
-            status: NodeStatus::Online,
-            last_seen: tokio::time::unix_epoch().elapsed().unwrap().as_secs(),
-            capabilities: 0,
-        });
-    }
-}
-
-/// 处理节点发现
-async fn handle_node_discovery(
-    packet: Packet,
-    addr: SocketAddr,
-    crypto: Arc<Mutex<CryptoContext>>,
-    peers: Arc<RwLock<HashMap<String, Peer>>>,
-    node_id: String
-) {
-    // 发送节点信息响应
-    let node_info = NodeInfo {
-        node_id: node_id.clone(),
-        node_name: "VPNet Server".to_string(),
-        public_key: crypto.lock().await.generate_key(CryptoAlgorithm::AesGcm256),
-        address: addr,
-        virtual_ip: "10.0.0.1".to_string(),
-        subnet: "255.255.255.0".to_string(),
-        online: true,
-        last_seen: tokio::time::unix_epoch().elapsed().unwrap().as_secs(),
-        capabilities: 0,
-    };
-    
-    let node_info_data = serde_json::to_vec(&node_info).unwrap();
-    let resp_packet = Packet {
-        magic: constants::MAGIC,
-        version: PROTOCOL_VERSION,
-        msg_type: MessageType::NodeInfo,
-        flags: 0,
-        length: node_info_data.len() as u16,
-        checksum: calculate_checksum(&node_info_data),
-        data: node_info_data,
-    };
-    
-    let resp_packet_data = serde_json::to_vec(&resp_packet).unwrap();
-    // 发送UDP数据包
-}
-
-/// 处理节点信息
-async fn handle_node_info(
-    packet: Packet,
-    addr: SocketAddr,
-    peers: Arc<RwLock<HashMap<String, Peer>>>
-) {
-    // 解析节点信息
-    if let Ok(node_info) = serde_json::from_slice::<NodeInfo>(&packet.data) {
-        let mut peers_guard = peers.write().await;
-        peers_guard.insert(node_info.node_id.clone(), Peer {
-            node_id: node_info.node_id.clone(),
-            node_name: node_info.node_name.clone(),
-            address: addr,
-            virtual_ip: node_info.virtual_ip.clone(),
-            public_key: node_info.public_key.clone(),
-            status: NodeStatus::Online,
-            last_seen: tokio::time::unix_epoch().elapsed().unwrap().as_secs(),
-            capabilities: node_info.capabilities,
-        });
-    }
-}
-
-/// 处理心跳包
-async fn handle_heartbeat(
-    packet: Packet,
-    addr: SocketAddr,
-    peers: Arc<RwLock<HashMap<String, Peer>>>
-) {
-    // 解析心跳包
-    if let Ok(heartbeat) = serde_json::from_slice::<Heartbeat>(&packet.data) {
-        let mut peers_guard = peers.write().await;
-        if let Some(peer) = peers_guard.get_mut(&heartbeat.node_id) {
-            peer.last_seen = tokio::time::unix_epoch().elapsed().unwrap().as_secs();
-            peer.status = NodeStatus::Online;
-        }
-    }
-}
-
-/// 处理数据转发
-async fn handle_data_forward(
-    packet: Packet,
-    crypto: Arc<Mutex<CryptoContext>>
-) {
-    // 解析数据转发消息
-    if let Ok(forward) = serde_json::from_slice::<DataForward>(&packet.data) {
-        // 解密数据
-        let mut crypto_guard = crypto.lock().await;
-        if let Ok(plaintext) = crypto_guard.decrypt(&forward.data, &[]) {
-            // 将数据转发到虚拟设备
-            log::debug!("Forwarding data from {} to {} ({} bytes)", 
-                        forward.source_node, forward.dest_node, plaintext.len());
-            // 实际实现中，这里应该将数据发送到虚拟网卡
-        }
-    }
-}
-
-/// 发送心跳包
-async fn send_heartbeat(
-    udp_socket: &Arc<UdpSocket>,
-    node_id: &str,
-    peers: &Arc<RwLock<HashMap<String, Peer>>>
-) {
-    let heartbeat = Heartbeat {
-        node_id: node_id.to_string(),
-        timestamp: tokio::time::unix_epoch().elapsed().unwrap().as_secs(),
-        load: 0.0, // 实际应获取系统负载
-        uptime: 0, // 实际应获取系统运行时间
-    };
-    
-    let heartbeat_data = serde_json::to_vec(&heartbeat).unwrap();
-    let packet = Packet {
-        magic: constants::MAGIC,
-        version: PROTOCOL_VERSION,
-        msg_type: MessageType::Heartbeat,
-        flags: 0,
-        length: heartbeat_data.len() as u16,
-        checksum: calculate_checksum(&heartbeat_data),
-        data: heartbeat_data,
-    };
-    
-    let packet_data = serde_json::to_vec(&packet).unwrap();
-    
-    let peers_guard = peers.read().await;
-    for peer in peers_guard.values() {
-        if let Err(e) = udp_socket.send_to(&packet_data, peer.address) {
-            log::warn!("Failed to send heartbeat to {}: {}", peer.node_id, e);
-        }
-    }
-}
-
-/// 清理超时节点
-async fn cleanup_timeout_peers(peers: &Arc<RwLock<HashMap<String, Peer>>>) {
-    let mut peers_guard = peers.write().await;
-    let now = tokio::time::unix_epoch().elapsed().unwrap().as_secs();
-    
-    peers_guard.retain(|_, peer| {
-        if now - peer.last_seen > constants::TIMEOUT {
-            log::info!("Removing timeout peer: {}", peer.node_id);
-            false
-        } else {
-            true
-        }
-    });
-}
+/*!
+VPNet网络模块
+
+处理P2P通信、节点发现和网络连接管理，包括：
+- UDP/TCP通信
+- 节点发现和连接
+- NAT穿透
+- 连接管理
+- 多网卡链路聚合
+- 直连和中继路径的多路径转发
+- 断线重连时凭会话票据恢复会话
+- 按节点分组限制中继带宽
+- 经HTTP代理的TCP隧道
+*/
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, PoisonError};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, oneshot, Mutex, RwLock};
+use tokio::task::JoinSet;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use rand::Rng;
+use crate::protocol::*;
+use crate::backoff::BackoffStrategy;
+use crate::compat::{CompatMatrix, Compatibility, DisabledFeature};
+use crate::compression::Compression;
+use crate::crypto::*;
+use crate::error::VpnetError;
+use crate::dpi::{AppProtocol, PacketInspector};
+use crate::mirror::TrafficMirror;
+use crate::proxy::UdpTcpBridge;
+use crate::multipath::{ForwardPath, MultiPathForwarder, MultiPathStrategy};
+use crate::transport::{DatagramSocket, TransportFactory, UdpTransport};
+use crate::routing::*;
+use crate::session::{SessionState, SessionTicketKeys};
+use crate::utils::{current_unix_timestamp, current_unix_timestamp_millis, PacketSizeHistogram, PacketSizeStats, SocketConfig, TcpStreamConfig, TokenBucket, UdpSocketTuner};
+use crate::topology::{Edge, NetworkTopology, NodeSnapshot};
+use crate::virtual_device::{frame_payload, frame_protocol, is_ipv6_multicast, parse_ipv4_packet, DeviceMode};
+use crate::{MAX_DATAGRAM_SIZE, MAX_PACKET_SIZE};
+
+/// 网络管理器
+pub struct NetworkManager {
+    sockets: Arc<SocketSet>,
+    tcp_listener: Option<Arc<TcpListener>>,
+    /// 绑定套接字使用的传输，TCP隧道的本地端点也通过它绑定
+    transport: Arc<dyn TransportFactory>,
+    /// 接受的TCP隧道连接使用的参数
+    tcp_stream_config: TcpStreamConfig,
+    /// 经隧道收发的IP数据包大小分布
+    packet_sizes: Arc<PacketSizeHistogram>,
+    local_addr: SocketAddr,
+    peers: Arc<RwLock<HashMap<String, Peer>>>,
+    /// 由密钥交换的共享秘密派生会话密钥的函数
+    kdf: Arc<RwLock<Arc<dyn Kdf>>>,
+    node_id: String,
+    node_name: String,
+    public_key: Vec<u8>,
+    node_info_cache: Arc<RwLock<NodeInfoCache>>,
+    max_hops: u8,
+    /// 每隔多少个数据转发请求一次确认，0表示不请求
+    ack_sampling_rate: u32,
+    nonce_store: Option<Arc<Mutex<NonceStore>>>,
+    nonce_provider: Option<Arc<dyn NonceProvider>>,
+    dscp_marking: bool,
+    migration_grace_period: Duration,
+    watchdog_interval: Duration,
+    heartbeat: BackoffStrategy,
+    pause_timeout: Option<Duration>,
+    routing: Arc<RwLock<RoutingPolicy>>,
+    handshake_validator: Option<Arc<dyn HandshakeValidator>>,
+    forward_filter: Option<Arc<dyn ForwardFilter>>,
+    scheduler: Option<Arc<dyn PacketScheduler>>,
+    /// 调度器发送任务的速率（字节/秒）
+    link_rate: Option<u64>,
+    bandwidth_limiter: Option<Arc<GroupBandwidthLimiter>>,
+    mirror: Option<Arc<TrafficMirror>>,
+    inspector: Option<Arc<PacketInspector>>,
+    relay_limiter: Option<Arc<RelayLimiter>>,
+    /// 解密后的数据写入虚拟设备的通道
+    device_tx: Option<mpsc::Sender<Vec<u8>>>,
+    /// 虚拟设备的工作模式，统计数据包大小时TAP帧不计以太网帧头
+    device_mode: DeviceMode,
+    /// 握手时向对端声明的能力
+    capabilities: Capabilities,
+    compression: Compression,
+    heartbeat_extensions: Arc<RwLock<HashMap<String, serde_json::Value>>>,
+    route_prefixes: RoutePrefixes,
+    probes: ProbeWaiters,
+    path_offers: PathOffers,
+    signing_key: Option<Arc<KeyPair>>,
+    pending_migrations: PendingMigrations,
+    session_tickets: Option<Arc<Mutex<SessionTicketKeys>>>,
+    received_tickets: ReceivedTickets,
+    pending_exchanges: PendingExchanges,
+    compat_matrix: Option<Arc<CompatMatrix>>,
+    /// 每个广播地址最后一次广播节点发现的时间
+    broadcast_discoveries: Mutex<HashMap<Ipv4Addr, Instant>>,
+    stats_history: StatsHistory,
+    stats_history_capacity: usize,
+    ip_assignments: broadcast::Sender<IpAssignment>,
+    peer_events: broadcast::Sender<PeerEvent>,
+    latency_probes: LatencyProbes,
+    latency_samples: broadcast::Sender<LatencySample>,
+    /// `start`和`supervise`启动的后台任务，`stop`时中止
+    tasks: std::sync::Mutex<Vec<tokio::task::JoinHandle<()>>>,
+}
+
+/// 绑定在多个本地地址上的UDP套接字
+///
+/// 所有套接字共用同一条数据包处理流程，回复节点时使用收到其流量的套接字。
+struct SocketSet {
+    sockets: Vec<(SocketAddr, Arc<dyn DatagramSocket>)>,
+    /// 启用链路聚合时，与新节点握手按聚合策略选择套接字
+    bond: Option<LinkAggregation>,
+    /// 与节点同时存在直连和中继路径时使用的多路径策略
+    multipath: Option<MultiPathStrategy>,
+}
+
+impl SocketSet {
+    /// 通过传输工厂在每个地址上绑定一个非阻塞套接字
+    fn bind(addrs: &[SocketAddr], transport: &dyn TransportFactory) -> Result<Self, std::io::Error> {
+        if addrs.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "no listen address configured"
+            ));
+        }
+        
+        let mut sockets = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            let socket = transport.bind(*addr)?;
+            // 记录实际绑定的地址，端口为0时由系统分配
+            sockets.push((socket.local_addr()?, socket));
+        }
+        
+        Ok(Self { sockets, bond: None, multipath: None })
+    }
+    
+    /// 第一个监听地址上的套接字，用于尚未建立关联的目标
+    fn primary(&self) -> &Arc<dyn DatagramSocket> {
+        &self.sockets[0].1
+    }
+    
+    /// 绑定在指定本地地址上的套接字，找不到时使用主套接字
+    fn get(&self, local_addr: Option<SocketAddr>) -> &Arc<dyn DatagramSocket> {
+        local_addr
+            .and_then(|addr| self.sockets.iter().find(|(bound, _)| *bound == addr))
+            .map(|(_, socket)| socket)
+            .unwrap_or_else(|| self.primary())
+    }
+    
+    /// 向节点发送时使用的套接字
+    ///
+    /// 始终使用收到该节点流量的套接字，从其他本地地址发出的回复会被对端和NAT丢弃。
+    fn for_peer(&self, peer: &Peer) -> &Arc<dyn DatagramSocket> {
+        let socket = self.get(peer.local_addr);
+        if let (Some(bond), Ok(local_addr)) = (&self.bond, socket.local_addr()) {
+            bond.record_sent(local_addr);
+        }
+        socket
+    }
+    
+    /// 发送握手请求时使用的套接字
+    ///
+    /// 已知节点沿用其关联的套接字；启用链路聚合时，新节点或关联链路已失效的节点按聚合策略选择链路，
+    /// 握手完成后该节点的流量固定在这条链路上。
+    fn for_handshake(&self, local_addr: Option<SocketAddr>) -> &Arc<dyn DatagramSocket> {
+        match &self.bond {
+            Some(bond) if local_addr.is_none_or(|addr| !bond.is_up(addr)) => bond.select(),
+            _ => self.get(local_addr),
+        }
+    }
+    
+    /// 记录套接字收到的数据
+    fn record_received(&self, local_addr: SocketAddr, len: usize) {
+        if let Some(bond) = &self.bond {
+            bond.record_received(local_addr, len);
+        }
+    }
+}
+
+/// 链路聚合模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum BondingMode {
+    /// 只使用一条链路，长时间收不到回复时切换到下一条可用链路
+    #[default]
+    ActiveBackup,
+    /// 新节点在所有可用链路间轮流分配
+    RoundRobin,
+}
+
+/// 单个网卡的链路统计
+#[derive(Debug, Clone, Serialize)]
+pub struct InterfaceStats {
+    pub interface: String,
+    pub local_addr: SocketAddr,
+    /// 链路是否可用
+    pub up: bool,
+    /// 当前是否为活动链路，轮询模式下所有可用链路均为活动链路
+    pub active: bool,
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    /// 链路被判定失效的次数
+    pub failures: u64,
+}
+
+/// 聚合中的一条链路
+struct Link {
+    interface: String,
+    local_addr: SocketAddr,
+    socket: Arc<dyn DatagramSocket>,
+    state: std::sync::Mutex<LinkState>,
+}
+
+impl Link {
+    /// 记录发出的数据，开始等待回复
+    fn record_sent(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.packets_sent += 1;
+        state.awaiting_since.get_or_insert_with(Instant::now);
+    }
+}
+
+/// 链路状态
+struct LinkState {
+    up: bool,
+    packets_sent: u64,
+    packets_received: u64,
+    bytes_received: u64,
+    failures: u64,
+    /// 上次收到数据后首次发送的时间，超过故障切换时间仍未收到数据即判定链路失效
+    awaiting_since: Option<Instant>,
+}
+
+/// 多网卡链路聚合
+///
+/// 每个网卡绑定一个UDP套接字。与节点握手时按聚合模式选择链路，之后该节点的流量固定在这条链路上，
+/// 保证对端和NAT看到的源地址不变。链路上发出数据后超过故障切换时间仍未收到任何数据
+/// （包括对端的心跳）时判定该链路失效：主备模式切换到下一条可用链路，轮询模式跳过该链路，
+/// 链路上的节点超时后重新握手时改用新选择的链路。
+/// 失效的链路重新收到数据后恢复可用，主备模式不会自动切回。
+pub struct LinkAggregation {
+    mode: BondingMode,
+    links: Vec<Link>,
+    failover_timeout: Duration,
+    /// 主备模式下的活动链路
+    active: AtomicUsize,
+    /// 轮询模式下的下一条链路
+    next: AtomicUsize,
+}
+
+impl LinkAggregation {
+    /// 由网卡名称和对应的套接字创建链路聚合
+    fn new(links: Vec<(String, SocketAddr, Arc<dyn DatagramSocket>)>, mode: BondingMode, failover_timeout: Duration) -> Self {
+        let links = links.into_iter()
+            .map(|(interface, local_addr, socket)| Link {
+                interface,
+                local_addr,
+                socket,
+                state: std::sync::Mutex::new(LinkState {
+                    up: true,
+                    packets_sent: 0,
+                    packets_received: 0,
+                    bytes_received: 0,
+                    failures: 0,
+                    awaiting_since: None,
+                }),
+            })
+            .collect();
+        
+        Self {
+            mode,
+            links,
+            failover_timeout,
+            active: AtomicUsize::new(0),
+            next: AtomicUsize::new(0),
+        }
+    }
+    
+    /// 查找网卡的IPv4地址，返回在各网卡上监听的地址
+    pub fn resolve_interfaces(interfaces: &[String], port: u16) -> Result<Vec<SocketAddr>, VpnetError> {
+        let available = pnet::datalink::interfaces();
+        interfaces.iter()
+            .map(|name| {
+                available.iter()
+                    .find(|iface| iface.name == *name)
+                    .and_then(|iface| iface.ips.iter().map(|ip| ip.ip()).find(IpAddr::is_ipv4))
+                    .map(|ip| SocketAddr::new(ip, port))
+                    .ok_or(VpnetError::Other("Interface not found or has no IPv4 address"))
+            })
+            .collect()
+    }
+    
+    /// 聚合模式
+    pub fn mode(&self) -> BondingMode {
+        self.mode
+    }
+    
+    /// 主备模式下当前活动链路的网卡名称
+    pub fn active_interface(&self) -> Option<&str> {
+        match self.mode {
+            BondingMode::ActiveBackup => Some(&self.links[self.active.load(Ordering::Relaxed)].interface),
+            BondingMode::RoundRobin => None,
+        }
+    }
+    
+    /// 选择新节点使用的链路
+    fn select(&self) -> &Arc<dyn DatagramSocket> {
+        let index = match self.mode {
+            BondingMode::ActiveBackup => self.active.load(Ordering::Relaxed),
+            BondingMode::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                // 跳过失效的链路，全部失效时仍按顺序轮流发送
+                (0..self.links.len())
+                    .map(|offset| (start + offset) % self.links.len())
+                    .find(|&index| self.links[index].state.lock().unwrap_or_else(PoisonError::into_inner).up)
+                    .unwrap_or(start % self.links.len())
+            }
+        };
+        
+        let link = &self.links[index];
+        link.record_sent();
+        &link.socket
+    }
+    
+    /// 记录链路发出的数据
+    fn record_sent(&self, local_addr: SocketAddr) {
+        if let Some(link) = self.links.iter().find(|link| link.local_addr == local_addr) {
+            link.record_sent();
+        }
+    }
+    
+    /// 链路是否可用，不属于聚合的地址视为可用
+    fn is_up(&self, local_addr: SocketAddr) -> bool {
+        self.links.iter()
+            .find(|link| link.local_addr == local_addr)
+            .is_none_or(|link| link.state.lock().unwrap_or_else(PoisonError::into_inner).up)
+    }
+    
+    /// 记录链路收到的数据，失效的链路恢复可用
+    fn record_received(&self, local_addr: SocketAddr, len: usize) {
+        let link = match self.links.iter().find(|link| link.local_addr == local_addr) {
+            Some(link) => link,
+            None => return,
+        };
+        
+        let mut state = link.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.packets_received += 1;
+        state.bytes_received += len as u64;
+        state.awaiting_since = None;
+        if !state.up {
+            state.up = true;
+            log::info!("Link on {} is up again", link.interface);
+        }
+    }
+    
+    /// 检查链路状态，判定失效的链路并在主备模式下切换活动链路
+    fn check_links(&self) {
+        let now = Instant::now();
+        for link in &self.links {
+            let mut state = link.state.lock().unwrap_or_else(PoisonError::into_inner);
+            let timed_out = state.awaiting_since
+                .is_some_and(|since| now.duration_since(since) > self.failover_timeout);
+            if state.up && timed_out {
+                state.up = false;
+                state.failures += 1;
+                log::warn!("Link on {} timed out", link.interface);
+            }
+        }
+        
+        if self.mode != BondingMode::ActiveBackup {
+            return;
+        }
+        
+        let active = self.active.load(Ordering::Relaxed);
+        if self.links[active].state.lock().unwrap_or_else(PoisonError::into_inner).up {
+            return;
+        }
+        
+        // 按配置顺序选择第一条可用链路
+        let backup = self.links.iter()
+            .enumerate()
+            .find(|(index, link)| *index != active && link.state.lock().unwrap_or_else(PoisonError::into_inner).up);
+        if let Some((index, link)) = backup {
+            // 给备用链路一个完整的超时周期
+            link.state.lock().unwrap_or_else(PoisonError::into_inner).awaiting_since = None;
+            self.active.store(index, Ordering::Relaxed);
+            log::warn!("Failing over from {} to {}", self.links[active].interface, link.interface);
+        }
+    }
+    
+    /// 各链路的统计信息
+    pub fn stats(&self) -> Vec<InterfaceStats> {
+        let active = self.active.load(Ordering::Relaxed);
+        self.links.iter()
+            .enumerate()
+            .map(|(index, link)| {
+                let state = link.state.lock().unwrap_or_else(PoisonError::into_inner);
+                InterfaceStats {
+                    interface: link.interface.clone(),
+                    local_addr: link.local_addr,
+                    up: state.up,
+                    active: match self.mode {
+                        BondingMode::ActiveBackup => index == active,
+                        BondingMode::RoundRobin => state.up,
+                    },
+                    packets_sent: state.packets_sent,
+                    packets_received: state.packets_received,
+                    bytes_received: state.bytes_received,
+                    failures: state.failures,
+                }
+            })
+            .collect()
+    }
+}
+
+/// 等待路径探测回复的请求，按序列号索引，值为探测的目标节点和等待者
+type ProbeWaiters = Arc<Mutex<HashMap<u32, (String, oneshot::Sender<TtlExceededMessage>)>>>;
+
+/// 管理员允许节点通过路由更新宣告的网段，按节点ID索引
+type RoutePrefixes = Arc<RwLock<HashMap<String, Vec<IpCidr>>>>;
+
+/// 尚未建立的直连提议，按对端节点ID索引
+type PathOffers = Arc<Mutex<HashMap<String, PendingPathOffer>>>;
+
+/// 尚未建立的直连提议
+struct PendingPathOffer {
+    /// 发出提议的服务端地址
+    signaling_addr: SocketAddr,
+    /// 提议中对端的地址，只接受来自该地址的探测
+    address: SocketAddr,
+    token: [u8; 16],
+}
+
+/// 服务端签发的会话票据，按服务端地址索引
+type ReceivedTickets = Arc<Mutex<HashMap<SocketAddr, Vec<u8>>>>;
+
+/// 主动重新握手时生成的临时密钥，按对端地址索引，收到握手响应后协商会话密钥
+type PendingExchanges = Arc<Mutex<HashMap<SocketAddr, KeyExchange>>>;
+
+/// 各节点的滚动窗口统计，按节点ID索引
+type StatsHistory = Arc<Mutex<HashMap<String, RollingWindowStats>>>;
+
+/// 服务端转发中的节点间时延探测
+type LatencyProbes = Arc<Mutex<LatencyProbeTable>>;
+
+/// 节点间时延探测的转发记录
+#[derive(Debug, Default)]
+struct LatencyProbeTable {
+    /// 等待回复的探测，按(目标节点ID, 探测ID)索引，值为源节点ID和转发时刻
+    pending: HashMap<(String, u32), (String, Instant)>,
+    /// 每对节点最近一次转发探测的时刻
+    last_forwarded: HashMap<(String, String), Instant>,
+}
+
+impl LatencyProbeTable {
+    /// 记录要转发的探测，同一对节点在`LATENCY_PROBE_INTERVAL`内已转发过时返回`false`
+    fn admit(&mut self, source: &str, target: &str, probe_id: u32) -> bool {
+        let now = Instant::now();
+        let interval = Duration::from_secs(constants::LATENCY_PROBE_INTERVAL);
+        let timeout = Duration::from_secs(constants::LATENCY_PROBE_TIMEOUT);
+        self.last_forwarded.retain(|_, forwarded_at| now.duration_since(*forwarded_at) < interval);
+        self.pending.retain(|_, (_, forwarded_at)| now.duration_since(*forwarded_at) < timeout);
+        
+        let pair = (source.to_string(), target.to_string());
+        if self.last_forwarded.contains_key(&pair) {
+            return false;
+        }
+        self.last_forwarded.insert(pair, now);
+        self.pending.insert((target.to_string(), probe_id), (source.to_string(), now));
+        true
+    }
+    
+    /// 取出目标节点回复的探测，返回源节点ID和转发后经过的时间
+    fn complete(&mut self, target: &str, probe_id: u32) -> Option<(String, Duration)> {
+        let (source, forwarded_at) = self.pending.remove(&(target.to_string(), probe_id))?;
+        Some((source, forwarded_at.elapsed()))
+    }
+}
+
+/// 服务端测得的两个节点之间的往返时延
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencySample {
+    pub source_peer_id: String,
+    pub target_peer_id: String,
+    /// 经服务端的往返时延：服务端与目标节点之间的往返时延加上源节点与服务端之间的往返时延
+    pub rtt_ms: f64,
+    /// 测量时间（Unix秒）
+    pub measured_at: u64,
+}
+
+/// 发送时延探测的句柄，由`NetworkManager::latency_prober`获取
+///
+/// 只共享套接字和节点表，定期向大量节点发送探测时不需要一直持有网络管理器。
+#[derive(Clone)]
+pub struct LatencyProber {
+    sockets: Arc<SocketSet>,
+    peers: Arc<RwLock<HashMap<String, Peer>>>,
+}
+
+impl LatencyProber {
+    /// 获取所有对等节点
+    pub async fn peers(&self) -> Vec<Peer> {
+        self.peers.read().await.values().cloned().collect()
+    }
+    
+    /// 经节点`via`（通常为服务端）向`target_peer_id`发送时延探测，返回探测ID
+    pub async fn send(&self, via: &str, target_peer_id: &str) -> Result<u32, VpnetError> {
+        let probe = LatencyProbe {
+            probe_id: rand::thread_rng().gen(),
+            target_peer_id: target_peer_id.to_string(),
+        };
+        let probe_data = serde_json::to_vec(&probe)?;
+        let packet = PacketBuilder::new(MessageType::LatencyProbe, probe_data).build();
+        
+        send_to_peer(&self.sockets, &self.peers, via, &packet).await?;
+        Ok(probe.probe_id)
+    }
+}
+
+/// 等待验证的连接迁移，按发出的随机数索引
+type PendingMigrations = Arc<Mutex<HashMap<[u8; 16], PendingMigration>>>;
+
+/// 等待验证的连接迁移
+struct PendingMigration {
+    node_id: String,
+    /// 节点的新地址及收到其流量的本地地址
+    address: SocketAddr,
+    local_addr: SocketAddr,
+    /// 从新地址握手时下发的会话密钥，验证通过后启用
+    session_key: Option<Vec<u8>>,
+    issued_at: Instant,
+}
+
+/// 装箱的异步结果
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// 握手准入检查
+///
+/// 服务端通过实现该trait在握手时参与节点注册，例如分配虚拟IP、检测冲突等。
+pub trait HandshakeValidator: Send + Sync {
+    /// 检查握手请求并给出准入结果
+    fn validate<'a>(&'a self, req: &'a HandshakeRequest, addr: SocketAddr) -> BoxFuture<'a, HandshakeVerdict>;
+}
+
+/// 握手随机数来源
+///
+/// 服务端启用随机数校验时，客户端通过实现该trait在握手前获取服务端签发的一次性随机数，
+/// 例如请求服务端管理API的`GET /api/auth/nonce`。
+pub trait NonceProvider: Send + Sync {
+    /// 获取向`server`握手时使用的随机数
+    fn fetch_nonce(&self, server: SocketAddr) -> BoxFuture<'_, Result<Vec<u8>, VpnetError>>;
+}
+
+/// 中继转发过滤
+///
+/// 服务端通过实现该trait限制节点之间经由本节点中继的流量，例如按节点分组做访问控制。
+pub trait ForwardFilter: Send + Sync {
+    /// 判断是否允许将源节点的数据转发给目标节点
+    fn allow<'a>(&'a self, source_node: &'a str, dest_node: &'a str) -> BoxFuture<'a, bool>;
+}
+
+/// 数据包调度器
+///
+/// 转发的数据包先入队，再由发送任务按调度顺序取出发往对应节点。
+pub trait PacketScheduler: Send + Sync {
+    /// 将发往指定节点的数据包入队，`priority`数值越小越优先
+    fn enqueue(&self, peer_id: &str, packet: Packet, priority: u8);
+    
+    /// 取出下一个要发送的数据包、目标节点及其入队时的优先级，队列为空时等待
+    fn next_packet(&self) -> BoxFuture<'_, (String, Packet, u8)>;
+}
+
+/// 调度策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum SchedulerMode {
+    /// 不排队，数据包立即发送
+    #[default]
+    Fifo,
+    /// 严格优先级，高优先级的数据包总是先发送
+    Priority,
+    /// 加权公平队列，各节点按权重分享带宽
+    Wfq,
+    /// 分层令牌桶，按节点分组的分类层级整形，见`shaper::HtbScheduler`
+    Htb,
+}
+
+/// 按优先级排列的发送队列，元素为目标节点、数据包和优先级
+type PriorityQueues = std::sync::Mutex<BTreeMap<u8, VecDeque<(String, Packet, u8)>>>;
+
+/// 严格优先级调度器
+///
+/// 同一优先级内按入队顺序发送。
+pub struct PriorityScheduler {
+    queues: PriorityQueues,
+    ready: tokio::sync::Notify,
+}
+
+impl PriorityScheduler {
+    /// 创建优先级调度器
+    pub fn new() -> Self {
+        Self {
+            queues: std::sync::Mutex::new(BTreeMap::new()),
+            ready: tokio::sync::Notify::new(),
+        }
+    }
+    
+    fn pop(&self) -> Option<(String, Packet, u8)> {
+        let mut queues = self.queues.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut entry = queues.first_entry()?;
+        let item = entry.get_mut().pop_front();
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        item
+    }
+}
+
+impl Default for PriorityScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PacketScheduler for PriorityScheduler {
+    fn enqueue(&self, peer_id: &str, packet: Packet, priority: u8) {
+        let mut queues = self.queues.lock().unwrap_or_else(PoisonError::into_inner);
+        let queue = queues.entry(priority).or_default();
+        if queue.len() >= constants::SCHEDULER_QUEUE_LEN {
+            log::debug!("Priority {} queue full, dropping packet for {}", priority, peer_id);
+            return;
+        }
+        queue.push_back((peer_id.to_string(), packet, priority));
+        drop(queues);
+        self.ready.notify_one();
+    }
+    
+    fn next_packet(&self) -> BoxFuture<'_, (String, Packet, u8)> {
+        Box::pin(async move {
+            loop {
+                let ready = self.ready.notified();
+                if let Some(item) = self.pop() {
+                    return item;
+                }
+                ready.await;
+            }
+        })
+    }
+}
+
+/// 加权公平队列调度器
+///
+/// 每个节点一个队列。数据包入队时按`max(虚拟时间, 该节点上一个数据包的完成时间) + 长度 / 权重`
+/// 计算虚拟完成时间，出队时选择各队列队首中完成时间最小的数据包，并将虚拟时间推进到该值。
+/// 权重越大的节点分得的带宽越多，未配置权重的节点权重为1。
+pub struct WfqScheduler {
+    state: std::sync::Mutex<WfqState>,
+    ready: tokio::sync::Notify,
+}
+
+#[derive(Default)]
+struct WfqState {
+    weights: HashMap<String, u32>,
+    /// 各节点的待发送数据包及其虚拟完成时间，队列为空时移除
+    flows: HashMap<String, VecDeque<(f64, Packet, u8)>>,
+    virtual_time: f64,
+}
+
+impl WfqScheduler {
+    /// 节点的默认权重
+    pub const DEFAULT_WEIGHT: u32 = 1;
+    
+    /// 创建加权公平队列调度器，所有节点使用默认权重
+    pub fn new() -> Self {
+        Self::with_weights(HashMap::new())
+    }
+    
+    /// 创建加权公平队列调度器，并为部分节点指定权重
+    pub fn with_weights(weights: HashMap<String, u32>) -> Self {
+        Self {
+            state: std::sync::Mutex::new(WfqState {
+                weights,
+                ..Default::default()
+            }),
+            ready: tokio::sync::Notify::new(),
+        }
+    }
+    
+    /// 设置节点的权重，只影响之后入队的数据包
+    pub fn set_weight(&self, peer_id: &str, weight: u32) {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner).weights.insert(peer_id.to_string(), weight);
+    }
+    
+    /// 获取节点的权重
+    pub fn weight(&self, peer_id: &str) -> u32 {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner).weight(peer_id)
+    }
+    
+    fn pop(&self) -> Option<(String, Packet, u8)> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let peer_id = state.flows.iter()
+            .filter_map(|(peer_id, queue)| queue.front().map(|(finish, _, _)| (peer_id, *finish)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(peer_id, _)| peer_id.clone())?;
+        
+        let queue = state.flows.get_mut(&peer_id)?;
+        let (finish, packet, priority) = queue.pop_front()?;
+        if queue.is_empty() {
+            state.flows.remove(&peer_id);
+        }
+        state.virtual_time = finish;
+        Some((peer_id, packet, priority))
+    }
+}
+
+impl WfqState {
+    fn weight(&self, peer_id: &str) -> u32 {
+        // 权重为0的节点将永远得不到发送机会，按1处理
+        self.weights.get(peer_id).copied().unwrap_or(WfqScheduler::DEFAULT_WEIGHT).max(1)
+    }
+}
+
+impl Default for WfqScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PacketScheduler for WfqScheduler {
+    fn enqueue(&self, peer_id: &str, packet: Packet, priority: u8) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let weight = state.weight(peer_id);
+        let virtual_time = state.virtual_time;
+        let queue = state.flows.entry(peer_id.to_string()).or_default();
+        if queue.len() >= constants::SCHEDULER_QUEUE_LEN {
+            log::debug!("WFQ queue for {} full, dropping packet", peer_id);
+            return;
+        }
+        
+        let start = queue.back().map_or(virtual_time, |(finish, _, _)| finish.max(virtual_time));
+        let cost = (constants::RAW_PACKET_HEADER_LEN + packet.data.len()) as f64 / f64::from(weight);
+        queue.push_back((start + cost, packet, priority));
+        drop(state);
+        self.ready.notify_one();
+    }
+    
+    fn next_packet(&self) -> BoxFuture<'_, (String, Packet, u8)> {
+        Box::pin(async move {
+            loop {
+                let ready = self.ready.notified();
+                if let Some(item) = self.pop() {
+                    return item;
+                }
+                ready.await;
+            }
+        })
+    }
+}
+
+/// 调度器发送任务的令牌桶，容量为10毫秒的流量且至少能发送一个完整的数据包
+fn link_pacer(bytes_per_sec: u64) -> TokenBucket {
+    TokenBucket::new(bytes_per_sec, (bytes_per_sec / 100).max(MAX_PACKET_SIZE as u64))
+}
+
+/// 等待令牌桶中至少有`bytes`个令牌，不消耗令牌
+async fn wait_for_tokens(bucket: &mut TokenBucket, bytes: u64) {
+    let bytes = bytes.min(bucket.burst());
+    loop {
+        let available = bucket.available();
+        if available >= bytes {
+            return;
+        }
+        let missing = bytes - available;
+        tokio::time::sleep(Duration::from_nanos(missing * 1_000_000_000 / bucket.rate())).await;
+    }
+}
+
+/// 中继限制
+///
+/// 限制本节点为其他节点中继的会话数和总带宽，一对源节点和目标节点算作一个会话。
+/// 超出限制的数据包直接丢弃，不排队。
+pub struct RelayLimiter {
+    max_sessions: Option<usize>,
+    /// 中继带宽的令牌桶，`None`表示不限制
+    bucket: Option<std::sync::Mutex<TokenBucket>>,
+}
+
+impl RelayLimiter {
+    /// 创建中继限制，`None`表示不限制
+    pub fn new(max_sessions: Option<u32>, bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            max_sessions: max_sessions.map(|max| max as usize),
+            bucket: bytes_per_sec
+                .map(|rate| std::sync::Mutex::new(TokenBucket::new(rate, GroupBucket::capacity(rate)))),
+        }
+    }
+    
+    /// 不为任何节点中继
+    pub fn disabled() -> Self {
+        Self::new(Some(0), None)
+    }
+    
+    /// 判断能否新建中继会话，`active`为当前的会话数
+    pub fn admit_session(&self, active: usize) -> bool {
+        self.max_sessions.is_none_or(|max| active < max)
+    }
+    
+    /// 扣除中继的字节数，带宽用尽时返回`false`
+    pub fn admit_bytes(&self, len: usize) -> bool {
+        match &self.bucket {
+            Some(bucket) => bucket.lock().unwrap_or_else(PoisonError::into_inner).consume(len as u64).is_ready(),
+            None => true,
+        }
+    }
+}
+
+/// 分组带宽限制
+///
+/// 同一分组的节点共用一个令牌桶，按节点所在分组限制其经本节点中继的总流量。令牌不足时
+/// 数据包进入发送节点的队列等待，队列已满时才丢弃；令牌恢复后各等待节点轮流发送，
+/// 分组内的带宽在等待的节点之间平均分配。不属于任何分组或所在分组未设置限制的节点不受影响。
+pub struct GroupBandwidthLimiter {
+    state: std::sync::Mutex<LimiterState>,
+    ready: tokio::sync::Notify,
+    /// 每个节点最多排队的数据包数
+    queue_depth: usize,
+}
+
+#[derive(Default)]
+struct LimiterState {
+    /// 节点所在的分组
+    membership: HashMap<String, String>,
+    groups: HashMap<String, GroupBucket>,
+}
+
+/// 分组的令牌桶及其成员的等待队列
+struct GroupBucket {
+    /// 分组的带宽限制，`None`表示不限制，队列中剩余的数据包直接放行
+    limit: Option<TokenBucket>,
+    /// 各发送节点排队的数据包及其目标节点和优先级，队列为空时移除
+    queues: HashMap<String, VecDeque<(String, Packet, u8)>>,
+    /// 有数据包排队的节点，按轮流发送的顺序排列
+    waiting: VecDeque<String>,
+}
+
+/// 从等待队列中取数据包的结果
+enum Release {
+    Packet(String, Packet, u8),
+    /// 令牌不足，需要等待的时间
+    Wait(Duration),
+    Idle,
+}
+
+impl GroupBucket {
+    fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            limit: bytes_per_sec.map(|rate| TokenBucket::new(rate, Self::capacity(rate))),
+            queues: HashMap::new(),
+            waiting: VecDeque::new(),
+        }
+    }
+    
+    /// 令牌桶容量，允许一秒的突发且至少能容纳一个最大的数据包
+    fn capacity(bytes_per_sec: u64) -> u64 {
+        bytes_per_sec.max(MAX_PACKET_SIZE as u64)
+    }
+    
+    /// 修改带宽限制，已有的令牌在新容量内保留
+    fn set_limit(&mut self, bytes_per_sec: Option<u64>) {
+        match (&mut self.limit, bytes_per_sec) {
+            (Some(bucket), Some(rate)) => bucket.set_rate(rate, Self::capacity(rate)),
+            (limit, rate) => *limit = rate.map(|rate| TokenBucket::new(rate, Self::capacity(rate))),
+        }
+    }
+    
+    /// 令牌足够时扣除并返回`Ready`，未设置限制时总是返回`Ready`
+    fn try_consume(&mut self, cost: u64) -> Poll<()> {
+        match &mut self.limit {
+            Some(bucket) => bucket.consume(cost),
+            None => Poll::Ready(()),
+        }
+    }
+    
+    /// 取出下一个轮到发送的数据包
+    fn release(&mut self) -> Release {
+        let Some(peer_id) = self.waiting.front().cloned() else {
+            return Release::Idle;
+        };
+        let Some(cost) = self.queues.get(&peer_id)
+            .and_then(|queue| queue.front())
+            .map(|(_, packet, _)| packet_cost(packet) as u64)
+        else {
+            self.waiting.pop_front();
+            self.queues.remove(&peer_id);
+            return Release::Idle;
+        };
+        
+        if self.try_consume(cost).is_pending() {
+            // 速率为0时不会再有令牌，等待限制变更
+            return match self.limit.as_ref().and_then(TokenBucket::next_available) {
+                Some(at) => Release::Wait(at.saturating_duration_since(Instant::now())),
+                None => Release::Idle,
+            };
+        }
+        
+        // 发送过的节点排到队尾，仍有数据包时下一轮再发送
+        self.waiting.pop_front();
+        let Some(queue) = self.queues.get_mut(&peer_id) else {
+            return Release::Idle;
+        };
+        let Some((dest_node, packet, priority)) = queue.pop_front() else {
+            return Release::Idle;
+        };
+        if queue.is_empty() {
+            self.queues.remove(&peer_id);
+        } else {
+            self.waiting.push_back(peer_id);
+        }
+        Release::Packet(dest_node, packet, priority)
+    }
+}
+
+/// 数据包占用的令牌数，按底层网络上的字节数计算
+pub(crate) fn packet_cost(packet: &Packet) -> f64 {
+    (constants::RAW_PACKET_HEADER_LEN + packet.data.len()) as f64
+}
+
+impl GroupBandwidthLimiter {
+    /// 创建分组带宽限制，每个节点最多排队`queue_depth`个数据包
+    pub fn new(queue_depth: usize) -> Self {
+        Self {
+            state: std::sync::Mutex::new(LimiterState::default()),
+            ready: tokio::sync::Notify::new(),
+            queue_depth,
+        }
+    }
+    
+    /// 设置分组的带宽上限（字节/秒），`None`表示取消限制
+    pub fn set_group_limit(&self, group_id: &str, bytes_per_sec: Option<u64>) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        match state.groups.get_mut(group_id) {
+            Some(bucket) => bucket.set_limit(bytes_per_sec),
+            None => {
+                state.groups.insert(group_id.to_string(), GroupBucket::new(bytes_per_sec));
+            }
+        }
+        drop(state);
+        // 限制放宽后排队的数据包可能可以立即发送
+        self.ready.notify_one();
+    }
+    
+    /// 获取分组的带宽上限（字节/秒）
+    pub fn group_limit(&self, group_id: &str) -> Option<u64> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner).groups.get(group_id).and_then(|bucket| bucket.limit.as_ref().map(TokenBucket::rate))
+    }
+    
+    /// 设置节点所在的分组，`None`表示不属于任何分组
+    ///
+    /// 节点已在原分组排队的数据包仍按原分组的限制发送。
+    pub fn set_peer_group(&self, peer_id: &str, group_id: Option<&str>) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        match group_id {
+            Some(group_id) => state.membership.insert(peer_id.to_string(), group_id.to_string()),
+            None => state.membership.remove(peer_id),
+        };
+    }
+    
+    /// 检查`source_node`发往`dest_node`的数据包能否立即发送
+    ///
+    /// 可以发送时返回该数据包；否则数据包进入队列，由`next_packet`在令牌恢复后取出，
+    /// 队列已满时丢弃，两种情况均返回`None`。
+    pub fn admit(&self, source_node: &str, dest_node: &str, packet: Packet, priority: u8) -> Option<Packet> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let LimiterState { membership, groups } = &mut *state;
+        let Some(bucket) = membership.get(source_node).and_then(|group_id| groups.get_mut(group_id)) else {
+            return Some(packet);
+        };
+        
+        // 已有节点在等待时排在其后，保证分组内公平
+        if bucket.waiting.is_empty() && bucket.try_consume(packet_cost(&packet) as u64).is_ready() {
+            return Some(packet);
+        }
+        
+        let queue = bucket.queues.entry(source_node.to_string()).or_default();
+        if queue.len() >= self.queue_depth {
+            log::debug!("Bandwidth queue for {} full, dropping packet to {}", source_node, dest_node);
+            return None;
+        }
+        if queue.is_empty() {
+            bucket.waiting.push_back(source_node.to_string());
+        }
+        queue.push_back((dest_node.to_string(), packet, priority));
+        drop(state);
+        self.ready.notify_one();
+        None
+    }
+    
+    /// 取出下一个令牌已恢复的排队数据包及其目标节点和优先级，没有可发送的数据包时等待
+    pub fn next_packet(&self) -> BoxFuture<'_, (String, Packet, u8)> {
+        Box::pin(async move {
+            loop {
+                let ready = self.ready.notified();
+                match self.pop() {
+                    Release::Packet(dest_node, packet, priority) => return (dest_node, packet, priority),
+                    Release::Wait(wait) => {
+                        // 令牌恢复或限制变更时重新检查
+                        let _ = tokio::time::timeout(wait, ready).await;
+                    }
+                    Release::Idle => ready.await,
+                }
+            }
+        })
+    }
+    
+    /// 依次检查各分组，返回第一个可发送的数据包，都需要等待时返回最短的等待时间
+    fn pop(&self) -> Release {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut result = Release::Idle;
+        for bucket in state.groups.values_mut() {
+            match bucket.release() {
+                Release::Packet(dest_node, packet, priority) => return Release::Packet(dest_node, packet, priority),
+                Release::Wait(wait) => {
+                    result = match result {
+                        Release::Wait(shortest) if shortest <= wait => Release::Wait(shortest),
+                        _ => Release::Wait(wait),
+                    };
+                }
+                Release::Idle => {}
+            }
+        }
+        result
+    }
+}
+
+/// 任务看门狗
+///
+/// 被监督的任务需要通过keepalive通道定期报告存活，超过间隔未报告时视为卡死，
+/// 看门狗会中止并立即重新启动该任务；任务自行退出时等待1秒后重启。
+/// 中止`supervise`返回的句柄时，被监督的任务同时被中止。
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    interval: Duration,
+}
+
+impl Watchdog {
+    /// 创建新的看门狗
+    pub fn new(interval: Duration) -> Self {
+        Self { interval }
+    }
+    
+    /// 启动并监督任务，`factory`每次（重新）启动时被调用以创建任务
+    pub fn supervise<F>(&self, name: impl Into<String>, factory: F) -> tokio::task::JoinHandle<()>
+    where
+        F: Fn(mpsc::Sender<()>) -> BoxFuture<'static, ()> + Send + Sync + 'static,
+    {
+        let name = name.into();
+        let interval = self.interval;
+        tokio::spawn(async move {
+            loop {
+                let (keepalive, mut pings) = mpsc::channel(1);
+                let task = AbortOnDrop(tokio::spawn(factory(keepalive)));
+                
+                loop {
+                    match tokio::time::timeout(interval, pings.recv()).await {
+                        Ok(Some(())) => continue,
+                        Ok(None) => {
+                            log::warn!("Task {} exited, restarting", name);
+                            // 避免任务启动即退出时空转
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                            break;
+                        }
+                        Err(_) => {
+                            log::error!("Task {} missed its watchdog deadline ({:?}), restarting", name, interval);
+                            break;
+                        }
+                    }
+                }
+                drop(task);
+            }
+        })
+    }
+}
+
+/// 启动后台任务，任务返回错误或panic时记录日志
+///
+/// `restart`为真时，任务结束后等待1秒重新调用`factory`创建任务。
+/// 中止返回的句柄时，正在运行的任务同时被中止。
+pub fn spawn_logged<F>(name: impl Into<String>, restart: bool, factory: F) -> tokio::task::JoinHandle<()>
+where
+    F: Fn() -> BoxFuture<'static, Result<(), VpnetError>> + Send + Sync + 'static,
+{
+    let name = name.into();
+    tokio::spawn(async move {
+        loop {
+            let mut task = AbortOnDrop(tokio::spawn(factory()));
+            match (&mut task.0).await {
+                Ok(Ok(())) => log::debug!("Task {} finished", name),
+                Ok(Err(e)) => log::error!("Task {} failed: {}", name, e),
+                Err(e) if e.is_panic() => log::error!("Task {} panicked", name),
+                Err(_) => return,
+            }
+            if !restart {
+                return;
+            }
+            
+            log::warn!("Restarting task {}", name);
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    })
+}
+
+/// 被丢弃时中止任务的句柄
+struct AbortOnDrop<T>(tokio::task::JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// 广播结果
+#[derive(Debug, Default)]
+pub struct BroadcastResult {
+    pub sent: usize,
+    pub failed: Vec<(String, VpnetError)>,
+}
+
+/// 重试策略
+///
+/// 第n次重试前等待`initial_backoff * 2^n`，并叠加`±jitter`比例的随机抖动，避免多个节点同时重试。
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// 第`attempt`次失败后的等待时间
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let base = self.initial_backoff.as_secs_f64() * 2f64.powi(attempt as i32);
+        let jitter = if self.jitter > 0.0 {
+            rand::thread_rng().gen_range(-self.jitter..=self.jitter)
+        } else {
+            0.0
+        };
+        Duration::from_secs_f64((base * (1.0 + jitter)).max(0.0))
+    }
+    
+    /// 执行`op`，遇到可重试的错误时按策略退避后再次执行
+    ///
+    /// 其他错误以及最后一次尝试的结果直接返回。
+    pub async fn retry<T, F, Fut>(&self, mut op: F) -> Result<T, VpnetError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, VpnetError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match op().await {
+                Err(VpnetError::Retry { reason, retry_after }) if attempt + 1 < self.max_attempts => {
+                    let delay = retry_after.unwrap_or_else(|| self.backoff(attempt));
+                    log::debug!("Retrying in {:?}: {}", delay, reason);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            jitter: 0.1,
+        }
+    }
+}
+
+/// 握手准入结果
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum HandshakeVerdict {
+    /// 接受握手，并为节点分配虚拟IP
+    Accept {
+        virtual_ip: Ipv4Addr,
+    },
+    /// 拒绝握手
+    Reject {
+        status: u8,
+        message: String,
+        conflict: Option<ConflictDetails>,
+    },
+}
+
+/// 对等节点事件
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum PeerEvent {
+    /// 节点完成握手
+    PeerConnected {
+        node_id: String,
+        node_name: String,
+        address: SocketAddr,
+        virtual_ip: String,
+    },
+    /// 节点主动关闭连接或超时
+    PeerDisconnected {
+        node_id: String,
+        reason: String,
+    },
+    /// 节点从新地址重新握手
+    PeerMigrated {
+        node_id: String,
+        old_address: SocketAddr,
+        new_address: SocketAddr,
+    },
+}
+
+impl PeerEvent {
+    /// 事件名称，与序列化后的`event`字段一致
+    pub fn name(&self) -> &'static str {
+        match self {
+            PeerEvent::PeerConnected { .. } => "peer_connected",
+            PeerEvent::PeerDisconnected { .. } => "peer_disconnected",
+            PeerEvent::PeerMigrated { .. } => "peer_migrated",
+        }
+    }
+}
+
+/// 数据包处理上下文
+///
+/// 接收任务为每个数据包克隆一份，包含处理函数需要的共享状态。
+#[derive(Clone)]
+struct HandlerContext {
+    /// 收到数据包的套接字及其本地地址
+    udp_socket: Arc<dyn DatagramSocket>,
+    local_addr: SocketAddr,
+    sockets: Arc<SocketSet>,
+    kdf: Arc<RwLock<Arc<dyn Kdf>>>,
+    peers: Arc<RwLock<HashMap<String, Peer>>>,
+    routing: Arc<RwLock<RoutingPolicy>>,
+    route_prefixes: RoutePrefixes,
+    node_info_cache: Arc<RwLock<NodeInfoCache>>,
+    node_id: String,
+    nonce_store: Option<Arc<Mutex<NonceStore>>>,
+    migration_grace_period: Duration,
+    handshake_validator: Option<Arc<dyn HandshakeValidator>>,
+    forward_filter: Option<Arc<dyn ForwardFilter>>,
+    scheduler: Option<Arc<dyn PacketScheduler>>,
+    bandwidth_limiter: Option<Arc<GroupBandwidthLimiter>>,
+    mirror: Option<Arc<TrafficMirror>>,
+    inspector: Option<Arc<PacketInspector>>,
+    relay_limiter: Option<Arc<RelayLimiter>>,
+    device_tx: Option<mpsc::Sender<Vec<u8>>>,
+    device_mode: DeviceMode,
+    packet_sizes: Arc<PacketSizeHistogram>,
+    probes: ProbeWaiters,
+    path_offers: PathOffers,
+    signing_key: Option<Arc<KeyPair>>,
+    pending_migrations: PendingMigrations,
+    session_tickets: Option<Arc<Mutex<SessionTicketKeys>>>,
+    received_tickets: ReceivedTickets,
+    pending_exchanges: PendingExchanges,
+    compat_matrix: Option<Arc<CompatMatrix>>,
+    ip_assignments: broadcast::Sender<IpAssignment>,
+    peer_events: broadcast::Sender<PeerEvent>,
+    latency_probes: LatencyProbes,
+    latency_samples: broadcast::Sender<LatencySample>,
+}
+
+/// 节点信息缓存
+///
+/// 预先构建并序列化本地节点信息，避免每次收到发现请求时重新生成，
+/// 同时按来源IP限制响应频率，防止被利用进行放大攻击。
+pub struct NodeInfoCache {
+    info: NodeInfo,
+    packet_data: Vec<u8>,
+    refreshed_at: Instant,
+    last_responses: HashMap<IpAddr, Instant>,
+}
+
+/// 对等节点
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub node_id: String,
+    pub node_name: String,
+    pub address: SocketAddr,
+    pub virtual_ip: String,
+    pub public_key: Vec<u8>,
+    pub status: NodeStatus,
+    pub last_seen: u64,
+    pub capabilities: u32,
+    pub stats: PeerStats,
+    pub tx_seq: u32,
+    pub loss_estimator: LossEstimator,
+    /// 握手协商的负载编码标志
+    pub payload_flags: u8,
+    /// 地址最后一次变更的时间
+    pub address_updated_at: Instant,
+    /// 最近一次心跳携带的扩展信息
+    pub metadata: HashMap<String, serde_json::Value>,
+    /// 收到该节点流量的本地监听地址，回复时使用同一套接字
+    pub local_addr: Option<SocketAddr>,
+    /// 打洞成功后的直连地址，存在时优先于`address`使用
+    pub direct_path: Option<SocketAddr>,
+    /// 最后一次经直连路径收到心跳的时间
+    pub direct_path_seen: Option<Instant>,
+    /// 该节点报告已与之建立直连的节点，服务端不再需要为其中继
+    pub direct_peers: HashSet<String>,
+    /// 握手时服务端分配的连接ID，双方发出的数据包都携带该ID
+    pub connection_id: u64,
+    /// 该节点经本节点中继发往的节点及最近一次中继的时刻，空闲超时或目标节点离开后移除
+    pub relay_peers: HashMap<String, Instant>,
+    /// 最近一次收到的心跳的发送时间和接收时刻，下一次心跳回显给对方
+    pub last_heartbeat: Option<(u64, Instant)>,
+    /// 上一次计算速率时的累计收发字节数和时刻
+    pub rate_sample: Option<(u64, Instant)>,
+    /// 维护暂停，暂停期间不向该节点发送或转发流量，但保留其注册
+    pub paused: bool,
+    /// 开始暂停的时刻
+    pub paused_since: Option<Instant>,
+    /// 存在直连路径且启用多路径时，经直连和中继路径发送
+    pub multipath: Option<Arc<MultiPathForwarder>>,
+    /// 握手时确定的会话密钥
+    pub session_key: Vec<u8>,
+    /// 由会话密钥创建的加密上下文，与该节点往来的数据用它加解密，握手完成前为`None`
+    pub crypto: Option<Arc<Mutex<CryptoContext>>>,
+    /// 管理员设置的标签，例如`role = "gateway"`
+    pub tags: HashMap<String, String>,
+    /// 对端最近一次回复的数据转发确认
+    pub last_ack: Option<DataAck>,
+    /// 本节点主动握手的上游节点（服务端），只接受其下发的虚拟IP分配
+    pub upstream: bool,
+}
+
+/// 对等节点统计
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PeerStats {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// 因TTL耗尽而丢弃的数据包数
+    pub ttl_exceeded_total: u64,
+    /// 接收方向的丢包率（百分比）
+    pub packet_loss_pct: f32,
+    /// 对端报告的发送方向丢包率（百分比）
+    pub remote_packet_loss_pct: f32,
+    /// 平滑往返时延（毫秒），未测量时为0
+    #[serde(default)]
+    pub rtt_ms: f64,
+    /// 最近一个心跳周期的收发速率（字节/秒）
+    #[serde(default)]
+    pub bytes_per_sec: f64,
+    /// 对端心跳报告的负载
+    #[serde(default)]
+    pub load: f32,
+    /// 对端最近一次确认的发送方向丢包率（百分比）
+    #[serde(default)]
+    pub acked_loss_pct: f32,
+}
+
+/// 一个采样周期内的收发量
+#[derive(Debug, Clone, Copy)]
+struct StatsSample {
+    timestamp: Instant,
+    /// 距上一次采样的时间
+    duration: Duration,
+    bytes: u64,
+    packets: u64,
+    /// 采样时接收方向的丢包率（0.0 - 1.0）
+    loss_rate: f64,
+}
+
+/// 时间窗口内的平均统计
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct WindowStats {
+    pub packets_per_sec: f64,
+    pub bytes_per_sec: f64,
+    /// 窗口内接收方向的平均丢包率（0.0 - 1.0）
+    pub loss_rate: f64,
+}
+
+/// 滚动窗口统计
+///
+/// 定期对`PeerStats`的累计值采样，保存每个采样周期的收发增量，最多保留`capacity`个样本，
+/// 可以查询最近任意时间窗口内的平均速率，不需要清零累计统计。
+#[derive(Debug, Clone)]
+pub struct RollingWindowStats {
+    samples: VecDeque<StatsSample>,
+    capacity: usize,
+    /// 上一次采样时的累计字节数、数据包数和时刻
+    last: Option<(u64, u64, Instant)>,
+}
+
+impl RollingWindowStats {
+    /// 默认保留的样本数，每秒采样时为1小时
+    pub const DEFAULT_CAPACITY: usize = 3600;
+    
+    /// 创建最多保留`capacity`个样本的滚动窗口统计
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity.min(Self::DEFAULT_CAPACITY)),
+            capacity,
+            last: None,
+        }
+    }
+    
+    /// 最多保留的样本数
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    
+    /// 按节点当前的累计统计采样，第一次采样只记录基准
+    pub fn sample(&mut self, stats: &PeerStats) {
+        self.sample_at(stats, Instant::now());
+    }
+    
+    fn sample_at(&mut self, stats: &PeerStats, now: Instant) {
+        let bytes = stats.bytes_sent + stats.bytes_received;
+        let packets = stats.packets_sent + stats.packets_received;
+        
+        if let Some((last_bytes, last_packets, last_at)) = self.last {
+            if self.samples.len() >= self.capacity {
+                self.samples.pop_front();
+            }
+            if self.capacity > 0 {
+                self.samples.push_back(StatsSample {
+                    timestamp: now,
+                    duration: now.duration_since(last_at),
+                    bytes: bytes.saturating_sub(last_bytes),
+                    packets: packets.saturating_sub(last_packets),
+                    loss_rate: f64::from(stats.packet_loss_pct) / 100.0,
+                });
+            }
+        }
+        self.last = Some((bytes, packets, now));
+    }
+    
+    /// 最近`window`内的平均速率和丢包率
+    ///
+    /// 按窗口内样本实际覆盖的时间计算，历史不足一个窗口时使用全部样本，没有样本时全部为0。
+    pub fn window_stats(&self, window: Duration) -> WindowStats {
+        self.window_stats_at(window, Instant::now())
+    }
+    
+    fn window_stats_at(&self, window: Duration, now: Instant) -> WindowStats {
+        let mut elapsed = Duration::ZERO;
+        let mut bytes = 0;
+        let mut packets = 0;
+        let mut loss_total = 0.0;
+        let mut count = 0u32;
+        
+        for sample in self.samples.iter().rev() {
+            if now.duration_since(sample.timestamp) + sample.duration > window {
+                break;
+            }
+            elapsed += sample.duration;
+            bytes += sample.bytes;
+            packets += sample.packets;
+            loss_total += sample.loss_rate;
+            count += 1;
+        }
+        
+        let secs = elapsed.as_secs_f64();
+        if count == 0 || secs <= 0.0 {
+            return WindowStats::default();
+        }
+        WindowStats {
+            packets_per_sec: packets as f64 / secs,
+            bytes_per_sec: bytes as f64 / secs,
+            loss_rate: loss_total / f64::from(count),
+        }
+    }
+}
+
+impl Default for RollingWindowStats {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+/// 一次手动垃圾回收的清理结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GcStats {
+    /// 超时移除的对等节点数
+    pub peers_removed: usize,
+    /// 对方已放弃等待的探测数
+    pub probes_removed: usize,
+    /// 过期的握手随机数
+    pub nonces_removed: usize,
+}
+
+/// 滑动窗口丢包估计器
+///
+/// 使用64位位图记录最近64个序列号的接收情况，缺失的位即为丢包。
+#[derive(Debug, Clone, Default)]
+pub struct LossEstimator {
+    first_seq: Option<u32>,
+    highest_seq: u32,
+    window: u64,
+}
+
+/// 连接管理器
+///
+/// 在多个可用服务器和中继节点之间进行选择。
+pub struct ConnectionManager {
+    location: Option<GeoLocation>,
+    prefer_nearest: bool,
+    relay_score: NodeScore,
+}
+
+/// 中继选择的评分权重
+///
+/// 各项指标先归一化：往返时延以100毫秒、丢包率以10%、距离以1000公里为1，负载直接使用心跳报告的值。
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelaySelectionWeights {
+    pub rtt_weight: f64,
+    pub loss_weight: f64,
+    pub load_weight: f64,
+    pub distance_weight: f64,
+}
+
+impl Default for RelaySelectionWeights {
+    fn default() -> Self {
+        Self {
+            rtt_weight: 0.5,
+            loss_weight: 0.3,
+            load_weight: 0.1,
+            distance_weight: 0.1,
+        }
+    }
+}
+
+impl RelaySelectionWeights {
+    /// 检查权重是否有效
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let weights = [self.rtt_weight, self.loss_weight, self.load_weight, self.distance_weight];
+        if weights.iter().any(|weight| !weight.is_finite() || *weight < 0.0) {
+            return Err("Weights must be finite and non-negative");
+        }
+        if weights.iter().all(|weight| *weight == 0.0) {
+            return Err("At least one weight must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+/// 节点评分，分数越低越适合作为中继
+#[derive(Debug, Clone, Default)]
+pub struct NodeScore {
+    weights: RelaySelectionWeights,
+    origin: Option<GeoLocation>,
+}
+
+impl NodeScore {
+    /// 未测量往返时延的节点按该时延（毫秒）计算
+    const UNMEASURED_RTT_MS: f64 = 100.0;
+    
+    /// 未配置位置的节点按半个地球周长（公里）计算距离
+    const UNKNOWN_DISTANCE_KM: f64 = 20_000.0;
+    
+    /// 创建评分器，`origin`为本地位置，未知时不计算距离项
+    pub fn new(weights: RelaySelectionWeights, origin: Option<GeoLocation>) -> Self {
+        Self { weights, origin }
+    }
+    
+    /// 评分权重
+    pub fn weights(&self) -> &RelaySelectionWeights {
+        &self.weights
+    }
+    
+    /// 按往返时延、双向丢包率、负载和距离计算节点的加权分数
+    pub fn compute(&self, node: &NodeInfo, stats: &PeerStats) -> f64 {
+        let rtt_ms = if stats.rtt_ms > 0.0 { stats.rtt_ms } else { Self::UNMEASURED_RTT_MS };
+        let loss_pct = stats.packet_loss_pct.max(stats.remote_packet_loss_pct) as f64;
+        let distance_km = match &self.origin {
+            Some(origin) => node.geographic_location.as_ref()
+                .map_or(Self::UNKNOWN_DISTANCE_KM, |location| origin.distance_km(location)),
+            None => 0.0,
+        };
+        
+        self.weights.rtt_weight * rtt_ms / 100.0
+            + self.weights.loss_weight * loss_pct / 10.0
+            + self.weights.load_weight * stats.load.max(0.0) as f64
+            + self.weights.distance_weight * distance_km / 1000.0
+    }
+}
+
+/// 上次输出环路告警的时间（Unix秒）
+static LAST_LOOP_WARNING: AtomicU64 = AtomicU64::new(0);
+
+/// NAT类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NatType {
+    FullCone,
+    RestrictedCone,
+    PortRestrictedCone,
+    Symmetric,
+    Unknown,
+}
+
+impl NatType {
+    /// 全部NAT类型
+    pub const ALL: [NatType; 5] = [
+        NatType::FullCone,
+        NatType::RestrictedCone,
+        NatType::PortRestrictedCone,
+        NatType::Symmetric,
+        NatType::Unknown,
+    ];
+    
+    /// 小写名称，用于日志和命令行输出
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NatType::FullCone => "full_cone",
+            NatType::RestrictedCone => "restricted_cone",
+            NatType::PortRestrictedCone => "port_restricted_cone",
+            NatType::Symmetric => "symmetric",
+            NatType::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for NatType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromStr for NatType {
+    type Err = &'static str;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter()
+            .find(|value| value.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or("Unknown NAT type")
+    }
+}
+
+impl NetworkManager {
+    /// 创建新的网络管理器
+    ///
+    /// 在每个监听地址上绑定一个UDP套接字，第一个地址作为本地节点信息中公布的地址。
+    /// 转发的数据使用与每个节点握手时确定的会话密钥加密，没有全局密钥。
+    pub fn new(
+        listen_addrs: Vec<SocketAddr>,
+        node_id: String,
+        node_name: String,
+        public_key: Vec<u8>
+    ) -> Result<Self, std::io::Error> {
+        Self::with_transport(listen_addrs, node_id, node_name, public_key, Arc::new(UdpTransport))
+    }
+    
+    /// 使用指定的传输创建网络管理器，例如测试中使用的模拟网络
+    pub fn with_transport(
+        listen_addrs: Vec<SocketAddr>,
+        node_id: String,
+        node_name: String,
+        public_key: Vec<u8>,
+        transport: Arc<dyn TransportFactory>
+    ) -> Result<Self, std::io::Error> {
+        let sockets = SocketSet::bind(&listen_addrs, transport.as_ref())?;
+        let local_addr = sockets.sockets[0].0;
+        
+        let node_info_cache = NodeInfoCache::new(build_local_info(
+            &node_id,
+            &node_name,
+            &public_key,
+            local_addr
+        )).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+        
+        Ok(Self {
+            sockets: Arc::new(sockets),
+            tcp_listener: None,
+            transport,
+            tcp_stream_config: TcpStreamConfig::default(),
+            packet_sizes: Arc::new(PacketSizeHistogram::new()),
+            local_addr,
+            peers: Arc::new(RwLock::new(HashMap::new())),
+            kdf: Arc::new(RwLock::new(Arc::new(HkdfSha256Kdf))),
+            node_id,
+            node_name,
+            public_key,
+            node_info_cache: Arc::new(RwLock::new(node_info_cache)),
+            max_hops: constants::DEFAULT_TTL,
+            ack_sampling_rate: constants::DEFAULT_ACK_SAMPLING_RATE,
+            nonce_store: None,
+            nonce_provider: None,
+            dscp_marking: false,
+            migration_grace_period: Duration::from_secs(constants::MIGRATION_GRACE_PERIOD),
+            watchdog_interval: Duration::from_secs(constants::WATCHDOG_INTERVAL),
+            heartbeat: BackoffStrategy::default(),
+            pause_timeout: None,
+            routing: Arc::new(RwLock::new(RoutingPolicy::new())),
+            handshake_validator: None,
+            forward_filter: None,
+            scheduler: None,
+            link_rate: None,
+            bandwidth_limiter: None,
+            mirror: None,
+            inspector: None,
+            relay_limiter: None,
+            device_tx: None,
+            device_mode: DeviceMode::Tun,
+            capabilities: Capabilities::NONE,
+            compression: Compression::None,
+            heartbeat_extensions: Arc::new(RwLock::new(HashMap::new())),
+            route_prefixes: Arc::new(RwLock::new(HashMap::new())),
+            probes: Arc::new(Mutex::new(HashMap::new())),
+            path_offers: Arc::new(Mutex::new(HashMap::new())),
+            signing_key: None,
+            pending_migrations: Arc::new(Mutex::new(HashMap::new())),
+            session_tickets: None,
+            received_tickets: Arc::new(Mutex::new(HashMap::new())),
+            pending_exchanges: Arc::new(Mutex::new(HashMap::new())),
+            compat_matrix: None,
+            broadcast_discoveries: Mutex::new(HashMap::new()),
+            tasks: std::sync::Mutex::new(Vec::new()),
+            stats_history: Arc::new(Mutex::new(HashMap::new())),
+            stats_history_capacity: RollingWindowStats::DEFAULT_CAPACITY,
+            ip_assignments: broadcast::channel(4).0,
+            peer_events: broadcast::channel(64).0,
+            latency_probes: Arc::new(Mutex::new(LatencyProbeTable::default())),
+            latency_samples: broadcast::channel(64).0,
+        })
+    }
+    
+    /// 按配置调整所有监听套接字的缓冲区大小等参数
+    pub fn configure_sockets(&self, config: &SocketConfig) -> Result<(), std::io::Error> {
+        for (addr, socket) in &self.sockets.sockets {
+            // 模拟套接字没有可调整的参数
+            if let Some(socket) = socket.as_udp_socket() {
+                log::debug!("Configuring UDP socket on {}", addr);
+                UdpSocketTuner::configure(&socket2::SockRef::from(socket), config)?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// 设置随心跳发送的扩展字段
+    pub async fn set_heartbeat_extension(&self, key: &str, value: serde_json::Value) {
+        self.heartbeat_extensions.write().await.insert(key.to_string(), value);
+    }
+    
+    /// 设置节点可以通过路由更新宣告的网段，替换原有的设置
+    ///
+    /// 节点只能宣告自己的虚拟IP或落在这些网段内的路由，也不能覆盖经其他节点的已有路由。
+    /// 设置与节点记录分开保存，节点重新握手后仍然有效。
+    pub async fn set_allowed_prefixes(&self, node_id: &str, prefixes: Vec<IpCidr>) {
+        let mut route_prefixes = self.route_prefixes.write().await;
+        if prefixes.is_empty() {
+            route_prefixes.remove(node_id);
+        } else {
+            route_prefixes.insert(node_id.to_string(), prefixes);
+        }
+    }
+    
+    /// 设置握手准入检查，需要在`start`之前调用
+    pub fn set_handshake_validator(&mut self, validator: Arc<dyn HandshakeValidator>) {
+        self.handshake_validator = Some(validator);
+    }
+    
+    /// 设置本节点的能力，握手请求和节点发现响应中携带，需要在`start`之前调用
+    pub async fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+        let mut cache = self.node_info_cache.write().await;
+        cache.info.capabilities = capabilities.bits();
+        cache.refresh();
+    }
+    
+    /// 本节点的能力
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+    
+    /// 设置中继限制，需要在`start`之前调用
+    ///
+    /// 未设置时不限制经本节点中继的会话数和带宽。
+    pub fn set_relay_limiter(&mut self, limiter: Arc<RelayLimiter>) {
+        self.relay_limiter = Some(limiter);
+    }
+    
+    /// 设置虚拟设备的写入通道和设备的工作模式，需要在`start`之前调用
+    ///
+    /// 发往本节点的数据解密后原样写入该通道，通常为`VirtualDevice::get_packet_sender`。
+    /// 未设置时收到的数据只记录日志后丢弃。
+    pub fn set_device_sender(&mut self, device_tx: mpsc::Sender<Vec<u8>>, mode: DeviceMode) {
+        self.device_tx = Some(device_tx);
+        self.device_mode = mode;
+    }
+    
+    /// 启用多网卡链路聚合，需要在`start`之前调用
+    ///
+    /// `interfaces`按顺序对应创建时的各个监听地址，通常由`LinkAggregation::resolve_interfaces`得到。
+    pub fn set_link_aggregation(
+        &mut self,
+        interfaces: Vec<String>,
+        mode: BondingMode,
+        failover_timeout: Duration
+    ) -> Result<(), VpnetError> {
+        let sockets = Arc::get_mut(&mut self.sockets)
+            .ok_or(VpnetError::Other("Link aggregation must be configured before start"))?;
+        if interfaces.len() != sockets.sockets.len() {
+            return Err(VpnetError::Other("Interface count does not match listen addresses"));
+        }
+        
+        let links = interfaces.into_iter()
+            .zip(&sockets.sockets)
+            .map(|(interface, (local_addr, socket))| (interface, *local_addr, socket.clone()))
+            .collect();
+        sockets.bond = Some(LinkAggregation::new(links, mode, failover_timeout));
+        Ok(())
+    }
+    
+    /// 启用多路径转发，需要在`start`之前调用
+    ///
+    /// 与节点建立直连后，数据包按`strategy`经直连路径和服务端中继两条路径发送。
+    pub fn set_multipath_strategy(&mut self, strategy: MultiPathStrategy) -> Result<(), VpnetError> {
+        let sockets = Arc::get_mut(&mut self.sockets)
+            .ok_or(VpnetError::Other("Multipath must be configured before start"))?;
+        sockets.multipath = Some(strategy);
+        Ok(())
+    }
+    
+    /// 在已绑定的套接字上增加一个监听套接字，返回其本地地址，需要在`start`之前调用
+    ///
+    /// 新套接字与创建时的监听套接字共用同一处理流程，可用`initiate_handshake_from`让节点改用它通信。
+    pub fn add_socket(&mut self, socket: Arc<dyn DatagramSocket>) -> Result<SocketAddr, VpnetError> {
+        let sockets = Arc::get_mut(&mut self.sockets)
+            .ok_or(VpnetError::Other("Sockets must be added before start"))?;
+        let local_addr = socket.local_addr()?;
+        if sockets.sockets.iter().any(|(bound, _)| *bound == local_addr) {
+            return Err(VpnetError::Other("Socket address is already bound"));
+        }
+        sockets.sockets.push((local_addr, socket));
+        Ok(local_addr)
+    }
+    
+    /// 链路聚合各链路的统计信息，未启用时为空
+    pub fn interface_stats(&self) -> Vec<InterfaceStats> {
+        self.sockets.bond.as_ref().map(LinkAggregation::stats).unwrap_or_default()
+    }
+    
+    /// 设置签名密钥，用于回应对端的连接迁移验证和签名路由更新，需要在`start`之前调用
+    ///
+    /// 未设置时本节点地址变化后无法完成迁移，只能重新握手，也无法发送路由更新。
+    pub fn set_signing_key(&mut self, key_pair: KeyPair) {
+        self.signing_key = Some(Arc::new(key_pair));
+    }
+    
+    /// 启用会话恢复，需要在`start`之前调用
+    ///
+    /// 节点正常断开连接时签发会话票据，节点在票据有效期内重新握手时直接恢复原会话。
+    pub fn set_session_tickets(&mut self, keys: Arc<Mutex<SessionTicketKeys>>) {
+        self.session_tickets = Some(keys);
+    }
+    
+    /// 设置客户端版本兼容矩阵，需要在`start`之前调用
+    ///
+    /// 不兼容的客户端握手时被拒绝，降级的客户端关闭矩阵中列出的功能。
+    pub fn set_compat_matrix(&mut self, matrix: CompatMatrix) {
+        self.compat_matrix = Some(Arc::new(matrix));
+    }
+    
+    /// 原地轮换与节点`peer_id`的会话密钥，轮换前已在途的数据包在过渡期内仍可解密
+    ///
+    /// 对端需要同时轮换为同一密钥。尚未与该节点完成握手时返回错误。
+    pub async fn rotate_key(&self, peer_id: &str, new_key: &[u8]) -> Result<(), VpnetError> {
+        let crypto = self.peers.read().await.get(peer_id)
+            .ok_or_else(|| VpnetError::PeerNotFound(peer_id.to_string()))?
+            .crypto.clone()
+            .ok_or(VpnetError::Other("No session with peer"))?;
+        let result = crypto.lock().await.rotate_key(new_key).map_err(VpnetError::Other);
+        result
+    }
+    
+    /// 替换密钥派生函数，默认为`HkdfSha256Kdf`
+    ///
+    /// 之后的握手用它由密钥交换的共享秘密派生会话密钥；已通过`set_session_tickets`启用会话恢复时，
+    /// 票据密钥也立即轮换为由它派生的密钥。
+    pub async fn set_kdf(&self, kdf: Arc<dyn Kdf>) -> Result<(), VpnetError> {
+        if let Some(keys) = &self.session_tickets {
+            keys.lock().await.set_kdf(kdf.clone()).map_err(VpnetError::Other)?;
+        }
+        *self.kdf.write().await = kdf;
+        Ok(())
+    }
+    
+    /// 设置转发数据的压缩模式，默认不压缩
+    ///
+    /// 只对握手时表示支持解压的节点生效。
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+    
+    /// 设置每个节点保留的统计样本数，需要在`start`之前调用
+    ///
+    /// 每秒采样一次，默认保留1小时。
+    pub fn set_stats_history_capacity(&mut self, capacity: usize) {
+        self.stats_history_capacity = capacity;
+    }
+    
+    /// 设置数据包调度器，需要在`start`之前调用
+    ///
+    /// 未设置时转发的数据包立即发送。
+    pub fn set_scheduler(&mut self, scheduler: Arc<dyn PacketScheduler>) {
+        self.scheduler = Some(scheduler);
+    }
+    
+    /// 设置调度器发送数据包的速率（字节/秒），需要在`start`之前调用
+    ///
+    /// 发送任务按该速率从调度器取出数据包，超出速率的数据包留在队列中由调度器排序。
+    /// 未设置时队列总是立即被取空，优先级和权重不起作用。
+    pub fn set_link_rate(&mut self, bytes_per_sec: u64) {
+        self.link_rate = Some(bytes_per_sec).filter(|&rate| rate > 0);
+    }
+    
+    /// 设置分组带宽限制，需要在`start`之前调用
+    ///
+    /// 只限制经本节点中继的数据包，按发送节点所在的分组计算流量。
+    pub fn set_bandwidth_limiter(&mut self, limiter: Arc<GroupBandwidthLimiter>) {
+        self.bandwidth_limiter = Some(limiter);
+    }
+    
+    /// 设置中继转发过滤，需要在`start`之前调用
+    pub fn set_forward_filter(&mut self, filter: Arc<dyn ForwardFilter>) {
+        self.forward_filter = Some(filter);
+    }
+    
+    /// 设置流量镜像，需要在`start`之前调用
+    ///
+    /// 发往本节点的数据包解密后复制一份发往镜像目标。
+    pub fn set_traffic_mirror(&mut self, mirror: Arc<TrafficMirror>) {
+        self.mirror = Some(mirror);
+    }
+    
+    /// 启用应用协议识别，需要在`start`之前调用
+    ///
+    /// 发往本节点的数据包解密后识别应用协议并计数，只用于调试。
+    pub fn set_packet_inspector(&mut self, inspector: Arc<PacketInspector>) {
+        self.inspector = Some(inspector);
+    }
+    
+    /// 设置节点地址迁移宽限期
+    ///
+    /// 节点在宽限期内从新地址重新握手时，原地更新其地址而不是重建节点记录。
+    pub fn set_migration_grace_period(&mut self, grace_period: Duration) {
+        self.migration_grace_period = grace_period;
+    }
+    
+    /// 启用或禁用底层UDP数据包的DSCP标记
+    pub fn set_dscp_marking(&mut self, enable: bool) {
+        self.dscp_marking = enable;
+    }
+    
+    /// 设置握手随机数存储
+    ///
+    /// 设置后，握手请求必须携带由该存储签发且未使用过的随机数，
+    /// 需要在`start`之前调用。
+    pub fn set_nonce_store(&mut self, nonce_store: Arc<Mutex<NonceStore>>) {
+        self.nonce_store = Some(nonce_store);
+    }
+    
+    /// 设置握手随机数来源
+    ///
+    /// 设置后，未指定随机数的握手请求（包括重连时的握手）先从该来源获取随机数。
+    pub fn set_nonce_provider(&mut self, provider: Arc<dyn NonceProvider>) {
+        self.nonce_provider = Some(provider);
+    }
+    
+    /// 设置看门狗超时，长时间运行的任务超过该时间未报告存活时会被重启，需要在`start`之前调用
+    pub fn set_watchdog_interval(&mut self, interval: Duration) {
+        self.watchdog_interval = interval;
+    }
+    
+    /// 设置心跳间隔策略，默认每`HEARTBEAT_INTERVAL`秒一次，需要在`start`之前调用
+    ///
+    /// 最大间隔超过`HEARTBEAT_INTERVAL`时对端可能判定本节点超时。
+    pub fn set_heartbeat_strategy(&mut self, strategy: BackoffStrategy) {
+        self.heartbeat = strategy;
+    }
+    
+    /// 设置维护暂停的最长时间，超过后自动恢复，需要在`start`之前调用
+    ///
+    /// 未设置时暂停的节点需要调用`resume_peer`才能恢复。
+    pub fn set_pause_timeout(&mut self, timeout: Duration) {
+        self.pause_timeout = Some(timeout);
+    }
+    
+    /// 设置数据转发的最大跳数
+    pub fn set_max_hops(&mut self, max_hops: u8) {
+        self.max_hops = max_hops;
+    }
+    
+    /// 设置每隔多少个数据转发请求一次确认，0表示不请求
+    ///
+    /// 对端回复的`DataAck`记入`Peer::last_ack`，据此估计发送方向的丢包率。
+    pub fn set_ack_sampling_rate(&mut self, rate: u32) {
+        self.ack_sampling_rate = rate;
+    }
+    
+    /// 启动TCP监听器，需要在`start`之前调用
+    ///
+    /// 接受经HTTP代理CONNECT隧道连接的节点，每个连接桥接到本地的UDP端口。
+    pub fn start_tcp_listener(&mut self, tcp_port: u16) -> Result<(), std::io::Error> {
+        let tcp_addr = SocketAddr::new(self.local_addr.ip(), tcp_port);
+        let listener = TcpListener::bind(tcp_addr)?;
+        listener.set_nonblocking(true)?;
+        self.tcp_listener = Some(Arc::new(listener));
+        Ok(())
+    }
+    
+    /// 设置接受的TCP隧道连接的`TCP_NODELAY`、缓冲区和保活参数，需要在`start`之前调用
+    pub fn set_tcp_stream_config(&mut self, config: TcpStreamConfig) {
+        self.tcp_stream_config = config;
+    }
+    
+    /// 在看门狗监督下启动长时间运行的任务，例如从虚拟网卡读取数据包的转发任务
+    ///
+    /// 任务需要在看门狗超时内通过keepalive通道报告存活，`stop`时任务被中止。
+    pub fn supervise<F>(&self, name: impl Into<String>, factory: F)
+    where
+        F: Fn(mpsc::Sender<()>) -> BoxFuture<'static, ()> + Send + Sync + 'static,
+    {
+        self.track(Watchdog::new(self.watchdog_interval).supervise(name, factory));
+    }
+    
+    /// 停止`start`和`supervise`启动的所有后台任务
+    pub fn stop(&self) {
+        for task in self.tasks.lock().unwrap_or_else(PoisonError::into_inner).drain(..) {
+            task.abort();
+        }
+    }
+    
+    fn track(&self, task: tokio::task::JoinHandle<()>) {
+        self.tasks.lock().unwrap_or_else(PoisonError::into_inner).push(task);
+    }
+    
+    /// 为在`local_addr`上接收的数据包创建处理上下文
+    fn handler_context(&self, udp_socket: Arc<dyn DatagramSocket>, local_addr: SocketAddr) -> HandlerContext {
+        HandlerContext {
+            udp_socket,
+            local_addr,
+            sockets: self.sockets.clone(),
+            kdf: self.kdf.clone(),
+            peers: self.peers.clone(),
+            routing: self.routing.clone(),
+            route_prefixes: self.route_prefixes.clone(),
+            node_info_cache: self.node_info_cache.clone(),
+            node_id: self.node_id.clone(),
+            nonce_store: self.nonce_store.clone(),
+            migration_grace_period: self.migration_grace_period,
+            handshake_validator: self.handshake_validator.clone(),
+            forward_filter: self.forward_filter.clone(),
+            scheduler: self.scheduler.clone(),
+            bandwidth_limiter: self.bandwidth_limiter.clone(),
+            mirror: self.mirror.clone(),
+            inspector: self.inspector.clone(),
+            relay_limiter: self.relay_limiter.clone(),
+            device_tx: self.device_tx.clone(),
+            device_mode: self.device_mode,
+            packet_sizes: self.packet_sizes.clone(),
+            probes: self.probes.clone(),
+            path_offers: self.path_offers.clone(),
+            signing_key: self.signing_key.clone(),
+            pending_migrations: self.pending_migrations.clone(),
+            session_tickets: self.session_tickets.clone(),
+            received_tickets: self.received_tickets.clone(),
+            pending_exchanges: self.pending_exchanges.clone(),
+            compat_matrix: self.compat_matrix.clone(),
+            ip_assignments: self.ip_assignments.clone(),
+            peer_events: self.peer_events.clone(),
+            latency_probes: self.latency_probes.clone(),
+            latency_samples: self.latency_samples.clone(),
+        }
+    }
+    
+    /// 启动网络服务
+    pub async fn start(&self) {
+        let watchdog = Watchdog::new(self.watchdog_interval);
+        
+        // 每个监听套接字一个接收任务，共用同一处理流程
+        for (local_addr, udp_socket) in &self.sockets.sockets {
+            let udp_socket = udp_socket.clone();
+            let ctx = self.handler_context(udp_socket.clone(), *local_addr);
+            
+            let name = format!("udp-receiver-{}", local_addr);
+            self.track(watchdog.supervise(name, move |keepalive| {
+                let udp_socket = udp_socket.clone();
+                let ctx = ctx.clone();
+                Box::pin(async move {
+                    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+                    loop {
+                        let _ = keepalive.try_send(());
+                        match udp_socket.recv_from(&mut buf) {
+                            Ok((len, addr)) => {
+                                ctx.sockets.record_received(ctx.local_addr, len);
+                                let data = &buf[..len];
+                                // 处理接收到的数据包
+                                tokio::spawn(handle_udp_packet(
+                                    data.to_vec(), 
+                                    addr, 
+                                    ctx.clone()
+                                ));
+                            }
+                            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                                tokio::time::sleep(Duration::from_millis(10)).await;
+                            }
+                            Err(e) => {
+                                log::error!("UDP receive error on {}: {}", ctx.local_addr, e);
+                                break;
+                            }
+                        }
+                    }
+                })
+            }));
+        }
+        
+        // 启用调度器时，由发送任务按调度顺序和链路速率发出转发的数据包
+        if let Some(scheduler) = self.scheduler.clone() {
+            let peers = self.peers.clone();
+            let sockets = self.sockets.clone();
+            let dscp_marking = self.dscp_marking;
+            let link_rate = self.link_rate;
+            self.track(spawn_logged("scheduler", true, move || {
+                let (scheduler, peers, sockets) = (scheduler.clone(), peers.clone(), sockets.clone());
+                Box::pin(async move {
+                    let mut pacer = link_rate.map(link_pacer);
+                    loop {
+                        // 先等到令牌足够发送一个完整的数据包再出队，期间到达的数据包参与排序
+                        if let Some(pacer) = &mut pacer {
+                            wait_for_tokens(pacer, MAX_PACKET_SIZE as u64).await;
+                        }
+                        let (peer_id, packet, priority) = scheduler.next_packet().await;
+                        if let Some(pacer) = &mut pacer {
+                            let _ = pacer.consume((constants::RAW_PACKET_HEADER_LEN + packet.data.len()) as u64);
+                        }
+                        let dscp = if dscp_marking { dscp_for_priority(priority) } else { constants::DSCP_CS0 };
+                        if let Err(e) = send_marked_to_peer(&sockets, &peers, &peer_id, &packet, dscp).await {
+                            log::debug!("Failed to send scheduled packet to {}: {}", peer_id, e);
+                        }
+                    }
+                })
+            }));
+        }
+        
+        if let Some(listener) = &self.tcp_listener {
+            match listener.try_clone().and_then(tokio::net::TcpListener::from_std) {
+                Ok(listener) => {
+                    // 监听地址未指定时经回环地址送达UDP套接字
+                    let mut target = self.local_addr;
+                    if target.ip().is_unspecified() {
+                        target.set_ip(Ipv4Addr::LOCALHOST.into());
+                    }
+                    self.track(tokio::spawn(accept_tunnels(listener, target, self.tcp_stream_config, self.transport.clone())));
+                }
+                Err(e) => log::error!("Failed to start TCP tunnel listener: {}", e),
+            }
+        }
+        
+        // 定期对各节点的累计统计采样，供查询滚动窗口统计
+        let peers = self.peers.clone();
+        let stats_history = self.stats_history.clone();
+        let capacity = self.stats_history_capacity;
+        self.track(spawn_logged("stats-sampler", true, move || {
+            let (peers, stats_history) = (peers.clone(), stats_history.clone());
+            Box::pin(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(constants::STATS_SAMPLE_INTERVAL));
+                loop {
+                    interval.tick().await;
+                    sample_stats_history(&peers, &stats_history, capacity).await;
+                }
+            })
+        }));
+        
+        // 启用分组带宽限制时，由发送任务在令牌恢复后发出排队的数据包
+        if let Some(limiter) = self.bandwidth_limiter.clone() {
+            let peers = self.peers.clone();
+            let sockets = self.sockets.clone();
+            let scheduler = self.scheduler.clone();
+            let dscp_marking = self.dscp_marking;
+            self.track(spawn_logged("bandwidth-limiter", true, move || {
+                let (limiter, peers, sockets, scheduler) =
+                    (limiter.clone(), peers.clone(), sockets.clone(), scheduler.clone());
+                Box::pin(async move {
+                    loop {
+                        let (peer_id, packet, priority) = limiter.next_packet().await;
+                        if let Some(scheduler) = &scheduler {
+                            scheduler.enqueue(&peer_id, packet, priority);
+                            continue;
+                        }
+                        let dscp = if dscp_marking { dscp_for_priority(priority) } else { constants::DSCP_CS0 };
+                        if let Err(e) = send_marked_to_peer(&sockets, &peers, &peer_id, &packet, dscp).await {
+                            log::debug!("Failed to send rate limited packet to {}: {}", peer_id, e);
+                        }
+                    }
+                })
+            }));
+        }
+        
+        // 启动心跳任务
+        let peers = self.peers.clone();
+        let node_id = self.node_id.clone();
+        let sockets = self.sockets.clone();
+        let node_info_cache = self.node_info_cache.clone();
+        let routing = self.routing.clone();
+        let heartbeat_extensions = self.heartbeat_extensions.clone();
+        let peer_events = self.peer_events.clone();
+        let heartbeat = self.heartbeat.clone();
+        let pause_timeout = self.pause_timeout;
+        
+        self.track(watchdog.supervise("heartbeat", move |keepalive| {
+            let peers = peers.clone();
+            let node_id = node_id.clone();
+            let sockets = sockets.clone();
+            let node_info_cache = node_info_cache.clone();
+            let routing = routing.clone();
+            let heartbeat_extensions = heartbeat_extensions.clone();
+            let peer_events = peer_events.clone();
+            let heartbeat = heartbeat.clone();
+            Box::pin(async move {
+                for delay in heartbeat.iterator() {
+                    let _ = keepalive.try_send(());
+                    // 发送心跳包，附带内置和自定义的扩展信息
+                    let mut ext = heartbeat_extensions.read().await.clone();
+                    ext.insert(extensions::VPNET_VERSION.to_string(), crate::VERSION.into());
+                    ext.insert(extensions::OS.to_string(), std::env::consts::OS.into());
+                    ext.insert(
+                        extensions::ROUTES_COUNT.to_string(),
+                        routing.read().await.route_count().into()
+                    );
+                    send_heartbeat(&sockets, &node_id, &peers, &ext).await;
+                    // 更新各节点的收发速率
+                    update_throughput(&peers).await;
+                    // 清理超时节点
+                    cleanup_timeout_peers(&peers, &peer_events).await;
+                    // 恢复暂停超时的节点
+                    if let Some(timeout) = pause_timeout {
+                        resume_expired_pauses(&peers, timeout).await;
+                    }
+                    // 直连路径长时间未收到心跳时回退到中继
+                    expire_direct_paths(&peers).await;
+                    // 结束空闲或目标已离开的中继会话
+                    expire_relay_sessions(&peers).await;
+                    // 链路聚合长时间未收到回复时切换链路
+                    if let Some(bond) = &sockets.bond {
+                        bond.check_links();
+                    }
+                    // 刷新过期的节点信息缓存
+                    {
+                        let mut cache = node_info_cache.write().await;
+                        if cache.is_stale() {
+                            cache.refresh();
+                        }
+                    }
+                    tokio::time::sleep(delay).await;
+                }
+            })
+        }));
+    }
+    
+    /// 发送数据包到指定节点
+    pub async fn send_packet(&self, peer_id: &str, packet: &Packet) -> Result<(), VpnetError> {
+        send_to_peer(&self.sockets, &self.peers, peer_id, packet).await
+    }
+    
+    /// 发送数据包到虚拟IP所属的节点
+    ///
+    /// 按已连接节点的虚拟IP查找，没有节点使用该虚拟IP时返回`PeerNotFound`。
+    pub async fn send_packet_to_virtual_ip(&self, virtual_ip: Ipv4Addr, packet: &Packet) -> Result<(), VpnetError> {
+        let peer = self.find_peer_by_virtual_ip(virtual_ip).await
+            .ok_or_else(|| VpnetError::PeerNotFound(virtual_ip.to_string()))?;
+        self.send_packet(&peer.node_id, packet).await
+    }
+    
+    /// 发送数据包到指定节点，遇到可重试的错误时按策略退避重试
+    pub async fn send_with_retry(
+        &self,
+        peer_id: &str,
+        packet: &Packet,
+        policy: &RetryPolicy
+    ) -> Result<(), VpnetError> {
+        send_to_peer_with_retry(&self.sockets, &self.peers, peer_id, packet, policy).await
+    }
+    
+    /// 向所有对等节点并发发送同一个数据包
+    ///
+    /// 只在收集节点地址时短暂持有节点表的锁，发送在独立的任务中并发进行。
+    pub async fn broadcast(&self, packet: &Packet) -> BroadcastResult {
+        let targets: Vec<(String, Arc<dyn DatagramSocket>, SocketAddr)> = self.peers.read().await
+            .values()
+            .map(|peer| (peer.node_id.clone(), self.sockets.for_peer(peer).clone(), peer.send_address()))
+            .collect();
+        
+        let data = match packet.encode() {
+            Ok(data) => Arc::new(data),
+            Err(e) => {
+                return BroadcastResult {
+                    sent: 0,
+                    failed: targets.into_iter()
+                        .map(|(peer_id, _, _)| (peer_id, VpnetError::Serialization(e.to_string())))
+                        .collect(),
+                };
+            }
+        };
+        
+        let outgoing = targets.into_iter()
+            .map(|(peer_id, udp_socket, address)| (peer_id, udp_socket, address, data.clone()))
+            .collect();
+        send_concurrently(outgoing, &RetryPolicy::default()).await
+    }
+    
+    /// 向指定节点发送路由更新
+    ///
+    /// 路由更新需要签名，未设置签名密钥时返回错误。
+    pub async fn send_route_update(&self, peer_id: &str, routes: Vec<RouteEntry>) -> Result<(), VpnetError> {
+        let signing_key = self.signing_key.as_ref()
+            .ok_or(VpnetError::Other("route updates require a signing key"))?;
+        let update = RouteUpdate::signed(&self.node_id, routes, signing_key, current_unix_timestamp());
+        
+        let update_data = serde_json::to_vec(&update)?;
+        let packet = PacketBuilder::new(MessageType::RouteUpdate, update_data).build();
+        
+        self.send_with_retry(peer_id, &packet, &RetryPolicy::default()).await
+    }
+    
+    /// 用与目标节点的会话密钥加密数据并转发，尚未与其完成握手时返回错误
+    pub async fn forward_data(
+        &self,
+        dest_node: &str,
+        data: &[u8],
+        protocol: u16,
+        priority: u8
+    ) -> Result<(), VpnetError> {
+        let (crypto, seq, payload_flags, connection_id) = {
+            let mut peers = self.peers.write().await;
+            let peer = peers.get_mut(dest_node)
+                .ok_or_else(|| VpnetError::PeerNotFound(dest_node.to_string()))?;
+            if peer.paused {
+                return Err(VpnetError::PeerPaused(dest_node.to_string()));
+            }
+            let crypto = peer.crypto.clone().ok_or(VpnetError::Other("No session with peer"))?;
+            peer.tx_seq = peer.tx_seq.wrapping_add(1);
+            (crypto, peer.tx_seq, peer.payload_flags, peer.connection_id)
+        };
+        self.packet_sizes.record(ip_packet_len(self.device_mode, data));
+        
+        // 对端支持解压时按配置压缩，压缩无益时发送原始数据
+        let compressed = if payload_flags & constants::FLAG_COMPRESSED != 0 {
+            self.compression.compress(data)
+        } else {
+            None
+        };
+        let ciphertext = crypto.lock().await.encrypt(compressed.as_deref().unwrap_or(data), &[])?;
+        
+        let forward = DataForward {
+            source_node: self.node_id.clone(),
+            dest_node: dest_node.to_string(),
+            data: ciphertext,
+            protocol,
+            ttl: initial_ttl(self.max_hops),
+            seq,
+            priority,
+            compressed: compressed.is_some(),
+            // 由中继按其节点记录填写
+            source_virtual_ip: Ipv4Addr::UNSPECIFIED,
+            ack_requested: self.ack_sampling_rate != 0 && seq % self.ack_sampling_rate == 0,
+        };
+        
+        let (forward_data, payload_flags) = encode_data_forward(&forward, payload_flags)?;
+        let packet = PacketBuilder::new(MessageType::DataForward, forward_data)
+            .flags(payload_flags)
+            .connection_id(connection_id)
+            .build();
+        
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.enqueue(dest_node, packet, priority);
+            return Ok(());
+        }
+        
+        // 将虚拟网络的优先级映射到底层网络的DSCP，随数据包一起发送
+        let dscp = if self.dscp_marking { dscp_for_priority(priority) } else { constants::DSCP_CS0 };
+        send_marked_to_peer(&self.sockets, &self.peers, dest_node, &packet, dscp).await
+    }
+    
+    /// 转发从虚拟设备读取的一帧数据
+    ///
+    /// 按帧中IPv4数据包的目标地址查找对等节点单播转发，TAP模式下转发包含以太网帧头的完整帧。
+    /// TAP模式下广播、组播、非IPv4以及目标未知的帧泛洪到所有在线节点，由对端设备自行过滤；
+    /// TUN模式下仅泛洪IPv6组播数据包（路由器请求与通告经此跨越隧道），其余返回错误。
+    pub async fn forward_frame(&self, frame: &[u8], mode: DeviceMode) -> Result<(), VpnetError> {
+        let protocol = frame_protocol(mode, frame)
+            .ok_or(VpnetError::Other("Malformed frame"))?;
+        let destination = frame_payload(mode, frame)
+            .filter(|_| protocol == pnet::packet::ethernet::EtherTypes::Ipv4.0)
+            .and_then(parse_ipv4_packet)
+            .map(|packet| packet.get_destination());
+        let unicast = match destination {
+            Some(ip) if !ip.is_broadcast() && !ip.is_multicast() => self.find_peer_by_virtual_ip(ip).await,
+            _ => None,
+        };
+        if let Some(peer) = unicast {
+            return self.forward_data(&peer.node_id, frame, protocol, constants::DEFAULT_DATA_PRIORITY).await;
+        }
+        if mode != DeviceMode::Tap && !is_ipv6_multicast(mode, frame) {
+            return Err(VpnetError::Other("No peer for frame destination"));
+        }
+        
+        let targets: Vec<String> = self.peers.read().await.values()
+            .filter(|peer| peer.status == NodeStatus::Online && !peer.paused)
+            .map(|peer| peer.node_id.clone())
+            .collect();
+        for node_id in targets {
+            if let Err(e) = self.forward_data(&node_id, frame, protocol, constants::DEFAULT_DATA_PRIORITY).await {
+                log::debug!("Failed to flood frame to {}: {}", node_id, e);
+            }
+        }
+        Ok(())
+    }
+    
+    /// 向指定地址发送握手请求
+    ///
+    /// 未指定`server_nonce`且设置了随机数来源时先从来源获取，获取失败时不携带随机数，
+    /// 由服务端决定是否接受。每次握手都携带新的临时公钥，会话密钥由双方的临时密钥协商。
+    pub async fn send_handshake_request(
+        &self,
+        addr: SocketAddr,
+        server_nonce: Option<Vec<u8>>,
+        virtual_ip: Option<String>
+    ) -> Result<(), &'static str> {
+        let server_nonce = match (server_nonce, &self.nonce_provider) {
+            (None, Some(provider)) => match provider.fetch_nonce(addr).await {
+                Ok(nonce) => Some(nonce),
+                Err(e) => {
+                    log::warn!("Failed to fetch handshake nonce for {}: {}", addr, e);
+                    None
+                }
+            },
+            (server_nonce, _) => server_nonce,
+        };
+        let exchange = KeyExchange::new()?;
+        
+        let req = HandshakeRequest {
+            version: PROTOCOL_VERSION,
+            public_key: self.public_key.clone(),
+            node_id: self.node_id.clone(),
+            node_name: self.node_name.clone(),
+            supported_protocols: vec![PROTOCOL_VERSION],
+            capabilities: self.capabilities.bits(),
+            server_nonce,
+            virtual_ip,
+            group_id: None,
+            // 票据只能使用一次，无论恢复是否成功都不再保留
+            session_ticket: self.received_tickets.lock().await.remove(&addr),
+            client_version: crate::VERSION.to_string(),
+            ephemeral_key: Some(exchange.public_key().to_vec()),
+        };
+        
+        let req_data = serde_json::to_vec(&req).map_err(|_| "Serialization failed")?;
+        let packet = PacketBuilder::new(MessageType::HandshakeRequest, req_data)
+            .flags(supported_payload_flags())
+            .build();
+        
+        // 已知节点沿用其关联的套接字
+        let local_addr = self.peers.read().await
+            .values()
+            .find(|peer| peer.address == addr)
+            .and_then(|peer| peer.local_addr);
+        
+        let data = serde_json::to_vec(&packet).map_err(|_| "Serialization failed")?;
+        self.pending_exchanges.lock().await.insert(addr, exchange);
+        if self.sockets.for_handshake(local_addr).send_to(&data, addr).is_err() {
+            self.pending_exchanges.lock().await.remove(&addr);
+            return Err("Send failed");
+        }
+        Ok(())
+    }
+    
+    /// 向节点发送连接关闭消息
+    ///
+    /// 对端启用会话恢复时会回复会话票据，在其有效期内重新握手时自动携带。
+    pub async fn close_connection(&self, peer_id: &str, reason: &str) -> Result<(), VpnetError> {
+        let close = ConnectionClose {
+            node_id: self.node_id.clone(),
+            reason: reason.to_string(),
+        };
+        let close_data = serde_json::to_vec(&close)?;
+        let packet = PacketBuilder::new(MessageType::ConnectionClose, close_data).build();
+        self.send_packet(peer_id, &packet).await
+    }
+    
+    /// 正常断开与节点的连接，最多等待`wait`接收对端签发的会话票据
+    ///
+    /// 收到票据时返回`true`。客户端退出前调用，再用`session_tickets`保存票据，
+    /// 重新启动后以`restore_session_tickets`读回，在票据有效期内连接时即可恢复会话。
+    pub async fn disconnect(&self, peer_id: &str, reason: &str, wait: Duration) -> Result<bool, VpnetError> {
+        let address = self.peers.read().await.get(peer_id)
+            .map(|peer| peer.address)
+            .ok_or_else(|| VpnetError::PeerNotFound(peer_id.to_string()))?;
+        self.received_tickets.lock().await.remove(&address);
+        self.close_connection(peer_id, reason).await?;
+        
+        let deadline = Instant::now() + wait;
+        loop {
+            if self.received_tickets.lock().await.contains_key(&address) {
+                return Ok(true);
+            }
+            if Instant::now() >= deadline {
+                log::debug!("No session ticket from {} within {:?}", peer_id, wait);
+                return Ok(false);
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+    
+    /// 收到的会话票据，按签发的服务端地址索引
+    pub async fn session_tickets(&self) -> HashMap<SocketAddr, Vec<u8>> {
+        self.received_tickets.lock().await.clone()
+    }
+    
+    /// 读回之前保存的会话票据，下次向对应地址握手时携带
+    pub async fn restore_session_tickets(&self, tickets: HashMap<SocketAddr, Vec<u8>>) {
+        self.received_tickets.lock().await.extend(tickets);
+    }
+    
+    /// 主动与已知节点重新握手
+    ///
+    /// 节点在线时先发送连接关闭消息，然后向其最后已知地址发送携带新临时公钥的握手请求，
+    /// 并将节点状态置为连接中，收到握手响应后以协商出的会话密钥恢复在线。
+    pub async fn initiate_handshake(&self, peer_id: &str) -> Result<(), VpnetError> {
+        self.reconnect(peer_id, None).await
+    }
+    
+    /// 从`local_addr`上的套接字与已知节点重新握手
+    ///
+    /// 与`initiate_handshake`相同，但握手请求从指定的本地套接字发出，节点回复后其流量改经该套接字收发。
+    /// `local_addr`必须是创建时的监听地址或`add_socket`加入的地址。
+    pub async fn initiate_handshake_from(&self, peer_id: &str, local_addr: SocketAddr) -> Result<(), VpnetError> {
+        if !self.sockets.sockets.iter().any(|(bound, _)| *bound == local_addr) {
+            return Err(VpnetError::Other("Local address is not bound"));
+        }
+        self.reconnect(peer_id, Some(local_addr)).await
+    }
+    
+    /// 重新握手，指定`local_addr`时在关闭原连接后把节点关联到该套接字
+    async fn reconnect(&self, peer_id: &str, local_addr: Option<SocketAddr>) -> Result<(), VpnetError> {
+        let (address, status, virtual_ip) = {
+            let mut peers = self.peers.write().await;
+            let peer = peers.get_mut(peer_id)
+                .ok_or_else(|| VpnetError::PeerNotFound(peer_id.to_string()))?;
+            let previous = peer.status;
+            peer.status = NodeStatus::Connecting;
+            (peer.address, previous, peer.virtual_ip.clone())
+        };
+        
+        if status == NodeStatus::Online {
+            self.close_connection(peer_id, "reconnect").await?;
+        }
+        
+        // 连接关闭消息仍从原套接字发出，对端只接受来自已知地址的消息
+        if let Some(local_addr) = local_addr {
+            if let Some(peer) = self.peers.write().await.get_mut(peer_id) {
+                peer.local_addr = Some(local_addr);
+            }
+        }
+        
+        log::info!("Reconnecting to peer {} at {}", peer_id, address);
+        self.send_handshake_request(address, None, Some(virtual_ip)).await?;
+        Ok(())
+    }
+    
+    /// 向目标节点发送指定TTL的路径探测包，返回等待回复的接收端
+    ///
+    /// 探测包在TTL耗尽的中继节点或目标节点处被回复。调用方超时放弃时应调用`cancel_probe`。
+    pub async fn send_probe(
+        &self,
+        dest_node: &str,
+        ttl: u8,
+        seq: u32
+    ) -> Result<oneshot::Receiver<TtlExceededMessage>, VpnetError> {
+        let (tx, rx) = oneshot::channel();
+        self.probes.lock().await.insert(seq, (dest_node.to_string(), tx));
+        
+        let forward = DataForward {
+            source_node: self.node_id.clone(),
+            dest_node: dest_node.to_string(),
+            data: Vec::new(),
+            protocol: constants::PROBE_PROTOCOL,
+            ttl,
+            seq,
+            priority: 0,
+            compressed: false,
+            source_virtual_ip: Ipv4Addr::UNSPECIFIED,
+            ack_requested: false,
+        };
+        
+        let forward_data = serde_json::to_vec(&forward)?;
+        let packet = PacketBuilder::new(MessageType::DataForward, forward_data).build();
+        
+        if let Err(e) = self.send_packet(dest_node, &packet).await {
+            self.cancel_probe(seq).await;
+            return Err(e);
+        }
+        Ok(rx)
+    }
+    
+    /// 放弃等待路径探测回复
+    pub async fn cancel_probe(&self, seq: u32) {
+        self.probes.lock().await.remove(&seq);
+    }
+    
+    /// 按虚拟IP查找对等节点
+    pub async fn find_peer_by_virtual_ip(&self, ip: Ipv4Addr) -> Option<Peer> {
+        let ip = ip.to_string();
+        self.peers.read().await
+            .values()
+            .find(|peer| peer.virtual_ip == ip)
+            .cloned()
+    }
+    
+    /// 作为信令通道为两个节点提议直连
+    ///
+    /// 将双方观测到的地址分别告知对方，双方收到后互相发送直连探测。
+    /// 直连流量不再经过本节点的中继过滤，转发过滤不允许双方互相访问时拒绝提议。
+    pub async fn offer_direct_path(&self, node_a: &str, node_b: &str) -> Result<(), VpnetError> {
+        if let Some(filter) = &self.forward_filter {
+            if !filter.allow(node_a, node_b).await || !filter.allow(node_b, node_a).await {
+                return Err(VpnetError::Other("Direct path denied by forwarding policy"));
+            }
+        }
+        
+        let (address_a, address_b) = {
+            let peers = self.peers.read().await;
+            let address = |node_id: &str| peers.get(node_id)
+                .map(|peer| peer.address)
+                .ok_or_else(|| VpnetError::PeerNotFound(node_id.to_string()));
+            (address(node_a)?, address(node_b)?)
+        };
+        
+        let token: [u8; 16] = SecureRng::new().gen();
+        for (target, peer_id, address) in [(node_a, node_b, address_b), (node_b, node_a, address_a)] {
+            let offer = DirectPathOffer {
+                peer_id: peer_id.to_string(),
+                address,
+                token,
+            };
+            let offer_data = serde_json::to_vec(&offer)?;
+            let packet = PacketBuilder::new(MessageType::DirectPathOffer, offer_data).build();
+            self.send_packet(target, &packet).await?;
+        }
+        
+        Ok(())
+    }
+    
+    /// 通知节点其虚拟IP已被重新分配
+    pub async fn send_ip_assignment(
+        &self,
+        peer_id: &str,
+        virtual_ip: Ipv4Addr,
+        subnet: Ipv4Addr
+    ) -> Result<(), VpnetError> {
+        let assignment = IpAssignment {
+            virtual_ip: virtual_ip.to_string(),
+            subnet: subnet.to_string(),
+        };
+        let assignment_data = serde_json::to_vec(&assignment)?;
+        let packet = PacketBuilder::new(MessageType::IpAssignment, assignment_data).build();
+        
+        self.send_with_retry(peer_id, &packet, &RetryPolicy::default()).await?;
+        if let Some(peer) = self.peers.write().await.get_mut(peer_id) {
+            peer.virtual_ip = assignment.virtual_ip;
+        }
+        Ok(())
+    }
+    
+    /// 订阅服务端下发的虚拟IP分配
+    pub fn subscribe_ip_assignments(&self) -> broadcast::Receiver<IpAssignment> {
+        self.ip_assignments.subscribe()
+    }
+    
+    /// 订阅对等节点的连接、断开和迁移事件
+    pub fn subscribe_peer_events(&self) -> broadcast::Receiver<PeerEvent> {
+        self.peer_events.subscribe()
+    }
+    
+    /// 经节点`via`（通常为服务端）向`target_peer_id`发送时延探测，返回探测ID
+    ///
+    /// 由`via`测量并记录两个节点之间的往返时延，同一对节点的探测每`LATENCY_PROBE_INTERVAL`秒只转发一次。
+    pub async fn send_latency_probe(&self, via: &str, target_peer_id: &str) -> Result<u32, VpnetError> {
+        self.latency_prober().send(via, target_peer_id).await
+    }
+    
+    /// 发送时延探测的句柄，使用时不需要持有网络管理器
+    pub fn latency_prober(&self) -> LatencyProber {
+        LatencyProber {
+            sockets: self.sockets.clone(),
+            peers: self.peers.clone(),
+        }
+    }
+    
+    /// 订阅本节点作为服务端测得的节点间往返时延
+    pub fn subscribe_latency_samples(&self) -> broadcast::Receiver<LatencySample> {
+        self.latency_samples.subscribe()
+    }
+    
+    /// 获取所有监听地址
+    pub fn listen_addrs(&self) -> Vec<SocketAddr> {
+        self.sockets.sockets.iter().map(|(addr, _)| *addr).collect()
+    }
+    
+    /// 获取节点ID
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+    
+    /// 添加路由到指定路由表
+    pub async fn add_route(&self, table: u8, route: Route) {
+        self.routing.write().await.table_mut(table).add_route(route);
+    }
+    
+    /// 添加策略路由规则
+    pub async fn add_policy_route(&self, rule: PolicyRoute) {
+        self.routing.write().await.add_rule(rule);
+    }
+    
+    /// 获取所有策略路由规则
+    pub async fn get_policy_routes(&self) -> Vec<PolicyRoute> {
+        self.routing.read().await.rules().to_vec()
+    }
+    
+    /// 删除指定优先级的策略路由规则
+    pub async fn remove_policy_route(&self, priority: u32) -> Option<PolicyRoute> {
+        self.routing.write().await.remove_rule(priority)
+    }
+    
+    /// 按源地址、目的地址和协议进行策略路由查找
+    pub async fn route_packet_with_policy(
+        &self,
+        src: Ipv4Addr,
+        dst: Ipv4Addr,
+        proto: u8
+    ) -> Option<Route> {
+        self.routing.read().await.route(src, dst, proto).cloned()
+    }
+    
+    /// 发现节点
+    pub async fn discover_nodes(&self, discovery_addr: SocketAddr) -> Result<(), VpnetError> {
+        let data = discovery_message()?;
+        self.sockets.primary().send_to(&data, discovery_addr)?;
+        Ok(())
+    }
+    
+    /// 在局域网内广播节点发现
+    ///
+    /// 发往`subnet`的广播地址，未指定网段时发往`255.255.255.255`，端口为本节点的监听端口。
+    /// 同一广播地址每10秒最多广播一次，过于频繁时返回可重试的错误。
+    /// 收到发现请求的节点回复节点信息，由接收任务加入节点表。
+    pub async fn send_broadcast_discovery(&self, subnet: Option<IpCidr>) -> Result<(), VpnetError> {
+        let broadcast_ip = subnet.map_or(Ipv4Addr::BROADCAST, |subnet| subnet.broadcast());
+        let interval = Duration::from_secs(constants::BROADCAST_DISCOVERY_INTERVAL);
+        {
+            let mut sent = self.broadcast_discoveries.lock().await;
+            let now = Instant::now();
+            sent.retain(|_, sent_at| now.duration_since(*sent_at) < interval);
+            if let Some(sent_at) = sent.get(&broadcast_ip) {
+                return Err(VpnetError::Retry {
+                    reason: format!("broadcast discovery to {} rate limited", broadcast_ip),
+                    retry_after: Some(interval - now.duration_since(*sent_at)),
+                });
+            }
+            sent.insert(broadcast_ip, now);
+        }
+        
+        let socket = self.sockets.primary();
+        if let Some(udp_socket) = socket.as_udp_socket() {
+            socket2::SockRef::from(udp_socket).set_broadcast(true)?;
+        }
+        let port = self.sockets.sockets[0].0.port();
+        let data = discovery_message()?;
+        socket.send_to(&data, SocketAddr::from((broadcast_ip, port)))?;
+        log::debug!("Broadcast node discovery to {}:{}", broadcast_ip, port);
+        Ok(())
+    }
+    
+    /// 获取所有对等节点
+    pub async fn get_peers(&self) -> Vec<Peer> {
+        let peers = self.peers.read().await;
+        peers.values().cloned().collect()
+    }
+    
+    /// 获取指定的对等节点
+    pub async fn get_peer(&self, node_id: &str) -> Option<Peer> {
+        self.peers.read().await.get(node_id).cloned()
+    }
+    
+    /// 获取节点最近`window`内的平均收发速率和丢包率，节点不存在或尚未采样时返回`None`
+    pub async fn peer_window_stats(&self, node_id: &str, window: Duration) -> Option<WindowStats> {
+        self.stats_history.lock().await
+            .get(node_id)
+            .map(|history| history.window_stats(window))
+    }
+    
+    /// 移除对等节点，节点需要重新握手才能再次通信
+    pub async fn remove_peer(&self, node_id: &str) -> Option<Peer> {
+        // 被移除的节点不能凭此前的票据恢复会话
+        if let Some(keys) = &self.session_tickets {
+            keys.lock().await.revoke(node_id);
+        }
+        self.peers.write().await.remove(node_id)
+    }
+    
+    /// 暂停节点，用于维护窗口
+    ///
+    /// 暂停期间向该节点发送数据返回`VpnetError::PeerPaused`，经本节点转发给它的数据包被丢弃。
+    /// 不通知对方，对方继续发送心跳，注册保持有效。
+    pub async fn pause_peer(&self, peer_id: &str) -> Result<(), VpnetError> {
+        let mut peers = self.peers.write().await;
+        let peer = peers.get_mut(peer_id)
+            .ok_or_else(|| VpnetError::PeerNotFound(peer_id.to_string()))?;
+        if !peer.paused {
+            peer.paused = true;
+            peer.paused_since = Some(Instant::now());
+            log::info!("Peer {} paused", peer_id);
+        }
+        Ok(())
+    }
+    
+    /// 恢复暂停的节点
+    pub async fn resume_peer(&self, peer_id: &str) -> Result<(), VpnetError> {
+        let mut peers = self.peers.write().await;
+        let peer = peers.get_mut(peer_id)
+            .ok_or_else(|| VpnetError::PeerNotFound(peer_id.to_string()))?;
+        if let Some(since) = peer.paused_since.take() {
+            log::info!("Peer {} resumed after {:?}", peer_id, since.elapsed());
+        }
+        peer.paused = false;
+        Ok(())
+    }
+    
+    /// 设置节点的标签，替换原有的全部标签
+    pub async fn set_peer_tags(&self, peer_id: &str, tags: HashMap<String, String>) -> Result<(), VpnetError> {
+        let mut peers = self.peers.write().await;
+        let peer = peers.get_mut(peer_id)
+            .ok_or_else(|| VpnetError::PeerNotFound(peer_id.to_string()))?;
+        peer.tags = tags;
+        Ok(())
+    }
+    
+    /// 获取所有路由表，按表编号排序
+    pub async fn get_routing_tables(&self) -> Vec<(u8, Vec<Route>)> {
+        let routing = self.routing.read().await;
+        let mut tables: Vec<_> = routing.tables()
+            .map(|(id, table)| (id, table.list_routes()))
+            .collect();
+        tables.sort_by_key(|(id, _)| *id);
+        tables
+    }
+    
+    /// 各应用协议的数据包数，未启用应用协议识别时返回`None`
+    pub fn protocol_stats(&self) -> Option<BTreeMap<AppProtocol, u64>> {
+        self.inspector.as_ref().map(|inspector| inspector.counts())
+    }
+    
+    /// 经隧道收发的IP数据包大小分布，不含隧道的封装开销
+    pub fn packet_size_stats(&self) -> PacketSizeStats {
+        self.packet_sizes.stats()
+    }
+    
+    /// 清空数据包大小分布
+    pub fn reset_packet_size_stats(&self) {
+        self.packet_sizes.reset();
+    }
+    
+    /// 获取加解密统计，为与所有节点的会话统计之和
+    pub async fn crypto_stats(&self) -> CryptoStats {
+        let sessions: Vec<_> = self.peers.read().await.values()
+            .filter_map(|peer| peer.crypto.clone())
+            .collect();
+        let mut total = CryptoStats {
+            algorithm: CryptoAlgorithm::AesGcm256.name(),
+            packets_encrypted: 0,
+            packets_decrypted: 0,
+            decrypt_failures: 0,
+        };
+        for session in sessions {
+            let stats = session.lock().await.stats();
+            total.packets_encrypted += stats.packets_encrypted;
+            total.packets_decrypted += stats.packets_decrypted;
+            total.decrypt_failures += stats.decrypt_failures;
+        }
+        total
+    }
+    
+    /// 立即执行一次垃圾回收
+    ///
+    /// 清理超时节点、已取消的探测和过期的握手随机数，不必等待下一次心跳。
+    pub async fn collect_garbage(&self) -> GcStats {
+        let peers_removed = cleanup_timeout_peers(&self.peers, &self.peer_events).await;
+        
+        let probes_removed = {
+            let mut probes = self.probes.lock().await;
+            let before = probes.len();
+            probes.retain(|_, (_, waiter)| !waiter.is_closed());
+            before - probes.len()
+        };
+        
+        let nonces_removed = match &self.nonce_store {
+            Some(nonce_store) => nonce_store.lock().await.purge_expired(),
+            None => 0,
+        };
+        
+        GcStats { peers_removed, probes_removed, nonces_removed }
+    }
+    
+    /// 获取本地节点信息
+    pub async fn get_local_info(&self) -> NodeInfo {
+        self.node_info_cache.read().await.info().clone()
+    }
+    
+    /// 获取本节点视角下的网络拓扑
+    ///
+    /// 包括本节点与各对等节点的连接、节点报告的直连，以及经本节点中继的连接。
+    pub async fn get_topology(&self) -> NetworkTopology {
+        let local = self.get_local_info().await;
+        let peers = self.peers.read().await;
+        
+        let mut topology = NetworkTopology::default();
+        topology.nodes.push(NodeSnapshot {
+            node_id: local.node_id.clone(),
+            node_name: local.node_name,
+            virtual_ip: local.virtual_ip,
+            address: local.address.to_string(),
+            status: NodeStatus::Online,
+            local: true,
+        });
+        
+        let mut sorted: Vec<&Peer> = peers.values().collect();
+        sorted.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        
+        let mut direct_pairs = HashSet::new();
+        for peer in &sorted {
+            topology.nodes.push(NodeSnapshot {
+                node_id: peer.node_id.clone(),
+                node_name: peer.node_name.clone(),
+                virtual_ip: peer.virtual_ip.clone(),
+                address: peer.send_address().to_string(),
+                status: peer.status,
+                local: false,
+            });
+            topology.edges.push(Edge {
+                src: local.node_id.clone(),
+                dst: peer.node_id.clone(),
+                rtt_ms: peer.stats.rtt_ms,
+                direct: true,
+                bytes_per_sec: peer.stats.bytes_per_sec,
+            });
+            
+            // 两端可能都报告了同一条直连，只保留一条
+            for other in &peer.direct_peers {
+                if !peers.contains_key(other) {
+                    continue;
+                }
+                let pair = if peer.node_id < *other {
+                    (peer.node_id.clone(), other.clone())
+                } else {
+                    (other.clone(), peer.node_id.clone())
+                };
+                if direct_pairs.insert(pair) {
+                    topology.edges.push(Edge {
+                        src: peer.node_id.clone(),
+                        dst: other.clone(),
+                        rtt_ms: 0.0,
+                        direct: true,
+                        bytes_per_sec: 0.0,
+                    });
+                }
+            }
+        }
+        
+        for peer in &sorted {
+            let mut relayed: Vec<&String> = peer.relay_peers.keys().collect();
+            relayed.sort();
+            for other in relayed {
+                if let Some(dest) = peers.get(other) {
+                    topology.edges.push(Edge {
+                        src: peer.node_id.clone(),
+                        dst: other.clone(),
+                        rtt_ms: peer.stats.rtt_ms + dest.stats.rtt_ms,
+                        direct: false,
+                        bytes_per_sec: 0.0,
+                    });
+                }
+            }
+        }
+        
+        topology
+    }
+    
+    /// 更新本地节点信息
+    ///
+    /// 本地状态（虚拟IP、能力标志等）变化时调用，立即刷新节点信息缓存。
+    pub async fn update_local_info(&self, info: NodeInfo) {
+        self.node_info_cache.write().await.update(info);
+    }
+}
+
+impl Peer {
+    /// 创建新的在线对等节点
+    pub fn new(
+        node_id: String,
+        node_name: String,
+        address: SocketAddr,
+        virtual_ip: String,
+        public_key: Vec<u8>,
+        capabilities: u32
+    ) -> Self {
+        Self {
+            node_id,
+            node_name,
+            address,
+            virtual_ip,
+            public_key,
+            status: NodeStatus::Online,
+            last_seen: current_unix_timestamp(),
+            capabilities,
+            stats: PeerStats::default(),
+            tx_seq: 0,
+            loss_estimator: LossEstimator::new(),
+            payload_flags: 0,
+            address_updated_at: Instant::now(),
+            metadata: HashMap::new(),
+            local_addr: None,
+            direct_path: None,
+            direct_path_seen: None,
+            direct_peers: HashSet::new(),
+            connection_id: 0,
+            relay_peers: HashMap::new(),
+            last_heartbeat: None,
+            rate_sample: None,
+            paused: false,
+            paused_since: None,
+            multipath: None,
+            session_key: Vec::new(),
+            crypto: None,
+            tags: HashMap::new(),
+            last_ack: None,
+            upstream: false,
+        }
+    }
+    
+    /// 启用握手确定的会话密钥，每次握手都新建加密上下文，加密随机数从0开始
+    ///
+    /// `initiator`为本节点是否主动发起了这次握手，双方据此使用不同的随机数前缀。
+    pub fn set_session_key(&mut self, session_key: Vec<u8>, initiator: bool) -> Result<(), VpnetError> {
+        let crypto = CryptoContext::for_session(&session_key, initiator).map_err(VpnetError::Other)?;
+        self.crypto = Some(Arc::new(Mutex::new(crypto)));
+        self.session_key = session_key;
+        Ok(())
+    }
+    
+    /// 发送数据时使用的地址，优先使用直连路径
+    pub fn send_address(&self) -> SocketAddr {
+        self.direct_path.unwrap_or(self.address)
+    }
+    
+    /// `addr`是否为该节点的已知地址（注册地址或直连路径）
+    pub fn is_at(&self, addr: SocketAddr) -> bool {
+        self.address == addr || self.direct_path == Some(addr)
+    }
+    
+    /// 是否可以为其他节点中继数据：本节点的上游服务端或声明了中继能力的节点
+    pub fn can_relay(&self) -> bool {
+        self.upstream || Capabilities::from_bits(self.capabilities).contains(Capabilities::CAN_RELAY)
+    }
+}
+
+impl LossEstimator {
+    /// 窗口大小
+    const WINDOW_SIZE: u32 = 64;
+    
+    /// 创建新的丢包估计器
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// 记录收到的序列号，窗口内已经收到过该序列号时返回`false`
+    pub fn record(&mut self, seq: u32) -> bool {
+        if self.first_seq.is_none() {
+            self.first_seq = Some(seq);
+            self.highest_seq = seq;
+            self.window = 1;
+            return true;
+        }
+        
+        // 使用环绕差值处理序列号回绕
+        let diff = seq.wrapping_sub(self.highest_seq) as i32;
+        if diff > 0 {
+            let shift = diff as u32;
+            self.window = if shift >= Self::WINDOW_SIZE {
+                0
+            } else {
+                self.window << shift
+            };
+            self.window |= 1;
+            self.highest_seq = seq;
+            true
+        } else {
+            // 乱序到达的旧数据包，仍在窗口内则补记
+            let back = diff.unsigned_abs();
+            if back >= Self::WINDOW_SIZE {
+                return true;
+            }
+            let bit = 1 << back;
+            let duplicate = self.window & bit != 0;
+            self.window |= bit;
+            !duplicate
+        }
+    }
+    
+    /// 窗口内丢失数据包的比例（0.0 - 1.0）
+    pub fn loss_rate(&self) -> f64 {
+        let first_seq = match self.first_seq {
+            Some(seq) => seq,
+            None => return 0.0,
+        };
+        
+        // 尚未收满一个窗口时，只统计已经经过的序列号
+        let span = self.highest_seq.wrapping_sub(first_seq).saturating_add(1)
+            .min(Self::WINDOW_SIZE);
+        let mask = if span >= Self::WINDOW_SIZE {
+            u64::MAX
+        } else {
+            (1u64 << span) - 1
+        };
+        let received = (self.window & mask).count_ones();
+        
+        (span - received) as f64 / span as f64
+    }
+    
+    /// 收到的最大序列号，尚未收到数据包时返回`None`
+    pub fn highest_seq(&self) -> Option<u32> {
+        self.first_seq.map(|_| self.highest_seq)
+    }
+    
+    /// 以最大序列号为最低位的接收位图，第i位表示序列号`highest_seq - i`已收到
+    pub fn bitmap(&self) -> u64 {
+        self.window
+    }
+}
+
+impl ConnectionManager {
+    /// 创建新的连接管理器
+    pub fn new(location: Option<GeoLocation>, prefer_nearest: bool) -> Self {
+        Self {
+            relay_score: NodeScore::new(RelaySelectionWeights::default(), location.clone()),
+            location,
+            prefer_nearest,
+        }
+    }
+    
+    /// 设置中继选择的评分权重
+    pub fn set_relay_weights(&mut self, weights: RelaySelectionWeights) {
+        self.relay_score = NodeScore::new(weights, self.location.clone());
+    }
+    
+    /// 从候选节点中选择分数最低的中继
+    ///
+    /// `stats`为各候选节点的统计，没有统计的节点按未测量处理。分数相同时选择排在前面的节点。
+    pub fn select_relay<'a>(
+        &self,
+        candidates: &'a [NodeInfo],
+        stats: &HashMap<String, PeerStats>
+    ) -> Option<&'a NodeInfo> {
+        let unmeasured = PeerStats::default();
+        candidates.iter()
+            .filter(|node| Capabilities::from_bits(node.capabilities).contains(Capabilities::CAN_RELAY))
+            .map(|node| {
+                let node_stats = stats.get(&node.node_id).unwrap_or(&unmeasured);
+                (node, self.relay_score.compute(node, node_stats))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(node, _)| node)
+    }
+    
+    /// 本地位置到节点的距离（千米），任一方位置未知时返回`None`
+    pub fn distance_km(&self, node: &NodeInfo) -> Option<f64> {
+        Some(self.location.as_ref()?.distance_km(node.geographic_location.as_ref()?))
+    }
+    
+    /// 从候选节点中选择要连接的服务器
+    ///
+    /// 启用就近优先且本地位置已知时，选择距离最近的服务器；
+    /// 未配置位置的服务器排在最后。否则按候选顺序选择第一个。
+    pub fn select_server<'a>(&self, candidates: &'a [NodeInfo]) -> Option<&'a NodeInfo> {
+        let origin = match (&self.location, self.prefer_nearest) {
+            (Some(origin), true) => origin,
+            _ => return candidates.first(),
+        };
+        
+        candidates.iter().min_by(|a, b| {
+            let da = a.geographic_location.as_ref()
+                .map(|loc| origin.distance_km(loc))
+                .unwrap_or(f64::INFINITY);
+            let db = b.geographic_location.as_ref()
+                .map(|loc| origin.distance_km(loc))
+                .unwrap_or(f64::INFINITY);
+            da.total_cmp(&db)
+        })
+    }
+}
+
+impl NodeInfoCache {
+    /// 创建新的节点信息缓存
+    pub fn new(info: NodeInfo) -> Result<Self, VpnetError> {
+        let packet_data = serialize_node_info(&info)?;
+        
+        Ok(Self {
+            info,
+            packet_data,
+            refreshed_at: Instant::now(),
+            last_responses: HashMap::new(),
+        })
+    }
+    
+    /// 获取缓存的节点信息
+    pub fn info(&self) -> &NodeInfo {
+        &self.info
+    }
+    
+    /// 缓存是否已超过刷新间隔
+    pub fn is_stale(&self) -> bool {
+        self.refreshed_at.elapsed() >= Duration::from_secs(constants::NODE_INFO_REFRESH_INTERVAL)
+    }
+    
+    /// 替换节点信息并重新序列化
+    pub fn update(&mut self, info: NodeInfo) {
+        self.info = info;
+        self.refresh();
+    }
+    
+    /// 刷新时间戳并重新序列化，序列化失败时继续使用原有的数据
+    pub fn refresh(&mut self) {
+        self.info.last_seen = current_unix_timestamp();
+        match serialize_node_info(&self.info) {
+            Ok(packet_data) => self.packet_data = packet_data,
+            Err(e) => log::warn!("Failed to serialize node info: {}", e),
+        }
+        self.refreshed_at = Instant::now();
+        
+        // 清理已过限速窗口的记录，防止表无限增长
+        let window = Duration::from_secs(constants::DISCOVERY_RATE_LIMIT);
+        self.last_responses.retain(|_, sent_at| sent_at.elapsed() < window);
+    }
+    
+    /// 获取发往指定来源的响应数据
+    ///
+    /// 同一来源IP在限速窗口内只响应一次，超出限制时返回None。
+    pub fn response_for(&mut self, ip: IpAddr) -> Option<&[u8]> {
+        let window = Duration::from_secs(constants::DISCOVERY_RATE_LIMIT);
+        let now = Instant::now();
+        
+        if let Some(sent_at) = self.last_responses.get(&ip) {
+            if now.duration_since(*sent_at) < window {
+                return None;
+            }
+        }
+        
+        self.last_responses.insert(ip, now);
+        Some(&self.packet_data)
+    }
+}
+
+/// 根据数据转发优先级计算DSCP值
+pub fn dscp_for_priority(priority: u8) -> u8 {
+    match priority {
+        0 => constants::DSCP_EF,
+        1..=3 => constants::DSCP_AF41,
+        _ => constants::DSCP_CS0,
+    }
+}
+
+/// 构建本地节点信息
+fn build_local_info(
+    node_id: &str,
+    node_name: &str,
+    public_key: &[u8],
+    local_addr: SocketAddr
+) -> NodeInfo {
+    NodeInfo {
+        node_id: node_id.to_string(),
+        node_name: node_name.to_string(),
+        public_key: public_key.to_vec(),
+        address: local_addr,
+        virtual_ip: "10.0.0.1".to_string(), // 默认虚拟IP，实际应从配置获取
+        subnet: "255.255.255.0".to_string(),
+        online: true,
+        last_seen: current_unix_timestamp(),
+        capabilities: 0,
+        geographic_location: None,
+        tags: HashMap::new(),
+    }
+}
+
+/// 将节点信息序列化为完整的NodeInfo数据包
+fn serialize_node_info(info: &NodeInfo) -> Result<Vec<u8>, VpnetError> {
+    let node_info_data = serde_json::to_vec(info)?;
+    let packet = PacketBuilder::new(MessageType::NodeInfo, node_info_data).build();
+    
+    Ok(serde_json::to_vec(&packet)?)
+}
+
+/// 处理UDP数据包
+async fn handle_udp_packet(
+    data: Vec<u8>,
+    addr: SocketAddr,
+    ctx: HandlerContext
+) {
+    // 解析数据包
+    if let Ok(packet) = Packet::decode(&data) {
+        // 验证魔术字和版本
+        if packet.magic != constants::MAGIC || packet.version != PROTOCOL_VERSION {
+            return;
+        }
+        
+        // 验证校验和
+        if !verify_checksum(&packet.data, packet.checksum) {
+            log::warn!("Invalid checksum from {}", addr);
+            return;
+        }
+        
+        // 根据消息类型处理
+        match packet.msg_type {
+            MessageType::HandshakeRequest => {
+                if let Err(e) = handle_handshake_request(packet, addr, &ctx).await {
+                    log::warn!("Failed to handle handshake request from {}: {}", addr, e);
+                }
+            }
+            MessageType::HandshakeResponse => {
+                handle_handshake_response(packet, addr, &ctx).await;
+            }
+            MessageType::NodeDiscovery => {
+                handle_node_discovery(addr, ctx.udp_socket, ctx.node_info_cache).await;
+            }
+            MessageType::NodeInfo => {
+                handle_node_info(packet, addr, ctx.local_addr, &ctx.node_id, ctx.peers).await;
+            }
+            MessageType::Heartbeat => {
+                if let Err(e) = check_connection_migration(&packet, addr, &ctx).await {
+                    log::warn!("Failed to check connection migration from {}: {}", addr, e);
+                }
+                handle_heartbeat(packet, addr, ctx.peers).await;
+            }
+            MessageType::DataForward => {
+                if let Err(e) = check_connection_migration(&packet, addr, &ctx).await {
+                    log::warn!("Failed to check connection migration from {}: {}", addr, e);
+                }
+                handle_data_forward(
+                    packet,
+                    addr,
+                    ctx.sockets,
+                    ctx.peers,
+                    ctx.node_id,
+                    ctx.forward_filter,
+                    ctx.scheduler,
+                    ctx.bandwidth_limiter,
+                    ctx.mirror,
+                    ctx.inspector,
+                    ctx.relay_limiter,
+                    ctx.device_tx,
+                    &ctx.packet_sizes,
+                    ctx.device_mode
+                ).await;
+            }
+            MessageType::ConnectionClose => {
+                handle_connection_close(packet, addr, &ctx).await;
+            }
+            MessageType::SessionTicket => {
+                handle_session_ticket(packet, addr, &ctx).await;
+            }
+            MessageType::DataAck => {
+                handle_data_ack(packet, addr, &ctx).await;
+            }
+            MessageType::TtlExceeded => {
+                handle_ttl_exceeded(packet, addr, &ctx).await;
+            }
+            MessageType::DirectPathOffer => {
+                handle_direct_path_offer(packet, addr, &ctx).await;
+            }
+            MessageType::DirectPathProbe => {
+                handle_direct_path_probe(packet, addr, &ctx).await;
+            }
+            MessageType::DirectPathEstablished => {
+                handle_direct_path_established(packet, addr, ctx.peers).await;
+            }
+            MessageType::IpAssignment => {
+                handle_ip_assignment(packet, addr, ctx.peers, ctx.ip_assignments).await;
+            }
+            MessageType::MigrationChallenge => {
+                if let Err(e) = handle_migration_challenge(packet, addr, &ctx).await {
+                    log::warn!("Failed to answer migration challenge from {}: {}", addr, e);
+                }
+            }
+            MessageType::MigrationResponse => {
+                handle_migration_response(packet, addr, &ctx).await;
+            }
+            MessageType::RouteUpdate => {
+                handle_route_update(packet, addr, &ctx).await;
+            }
+            MessageType::LatencyProbe => {
+                handle_latency_probe(packet, addr, &ctx).await;
+            }
+            MessageType::LatencyProbeReply => {
+                handle_latency_probe_reply(packet, addr, &ctx).await;
+            }
+            _ => {
+                log::debug!("Received unhandled message type: {:?} from {}", packet.msg_type, addr);
+            }
+        }
+    } else {
+        log::warn!("Failed to parse packet from {}", addr);
+    }
+}
+
+/// 处理握手请求
+async fn handle_handshake_request(
+    packet: Packet,
+    addr: SocketAddr,
+    ctx: &HandlerContext
+) -> Result<(), VpnetError> {
+    // 解析握手请求
+    let req = match serde_json::from_slice::<HandshakeRequest>(&packet.data) {
+        Ok(req) => req,
+        Err(_) => return Ok(()),
+    };
+    
+    // 按客户端版本判断兼容性，不兼容的直接拒绝
+    let compatibility = match &ctx.compat_matrix {
+        Some(matrix) => matrix.check(&req.client_version),
+        None => Compatibility::Compatible,
+    };
+    
+    // 协商负载编码：双方都支持时才使用bincode，降级的客户端不协商其不支持的功能
+    let payload_flags = compatibility.restrict_payload_flags(packet.flags & supported_payload_flags());
+    
+    if compatibility == Compatibility::Incompatible {
+        log::warn!("Rejecting handshake from {} ({}): incompatible client version {:?}",
+                   req.node_id, addr, req.client_version);
+        let message = format!("Client version {:?} is not supported by server {}",
+                              req.client_version, crate::VERSION);
+        let resp = handshake_rejection(&ctx.node_id, status::VERSION_INCOMPATIBLE, &message, None);
+        send_handshake_response(&ctx.udp_socket, addr, &resp, payload_flags)?;
+        return Ok(());
+    }
+    if let Compatibility::Degraded(features) = &compatibility {
+        log::info!("Client {} version {:?} is degraded, disabling {:?}",
+                   req.node_id, req.client_version, features);
+    }
+    
+    // 携带有效会话票据的节点直接恢复原会话，无需随机数和准入检查
+    let resume_allowed = !compatibility.disables(DisabledFeature::SessionResume);
+    if let Some(ticket) = req.session_ticket.as_ref().filter(|_| resume_allowed) {
+        if resume_session(&req, ticket, addr, payload_flags, ctx).await? {
+            return Ok(());
+        }
+    }
+    
+    // 校验服务端签发的随机数，防止握手重放
+    if let Some(nonce_store) = &ctx.nonce_store {
+        let valid = match &req.server_nonce {
+            Some(nonce) => nonce_store.lock().await.consume(nonce),
+            None => false,
+        };
+        if !valid {
+            log::warn!("Rejecting handshake from {} ({}): missing, expired or reused nonce",
+                       req.node_id, addr);
+            let resp = handshake_rejection(&ctx.node_id, status::INVALID_NONCE,
+                                           "Missing, expired or reused nonce", None);
+            send_handshake_response(&ctx.udp_socket, addr, &resp, payload_flags)?;
+            return Ok(());
+        }
+    }
+    
+    // 准入检查，确定节点的虚拟IP
+    let virtual_ip = match &ctx.handshake_validator {
+        Some(validator) => match validator.validate(&req, addr).await {
+            HandshakeVerdict::Accept { virtual_ip } => virtual_ip.to_string(),
+            HandshakeVerdict::Reject { status, message, conflict } => {
+                log::warn!("Rejecting handshake from {} ({}): {}", req.node_id, addr, message);
+                let resp = handshake_rejection(&ctx.node_id, status, &message, conflict);
+                send_handshake_response(&ctx.udp_socket, addr, &resp, payload_flags)?;
+                return Ok(());
+            }
+        },
+        None => req.virtual_ip.clone().unwrap_or_else(|| "10.0.0.2".to_string()),
+    };
+    
+    // 同一密钥的节点重新握手时沿用原连接ID，否则分配新的
+    let (connection_id, migrating) = {
+        let peers = ctx.peers.read().await;
+        let known = peers.get(&req.node_id).filter(|peer| peer.public_key == req.public_key);
+        let connection_id = known
+            .map(|peer| peer.connection_id)
+            .filter(|&connection_id| connection_id != 0)
+            .unwrap_or_else(|| rand::thread_rng().gen_range(1..=u64::MAX));
+        (connection_id, known.is_some_and(|peer| requires_migration_challenge(peer, addr, ctx.migration_grace_period)))
+    };
+    
+    // 生成会话密钥，请求携带临时公钥时由双方的临时密钥协商，不在响应中下发
+    let (session_key, ephemeral_key) = match &req.ephemeral_key {
+        Some(peer_key) => {
+            let exchange = KeyExchange::new()?;
+            let ephemeral_key = exchange.public_key().to_vec();
+            let kdf = ctx.kdf.read().await.clone();
+            (exchange.derive_session_key_with(peer_key, kdf.as_ref())?, Some(ephemeral_key))
+        }
+        None => (random_key(), None),
+    };
+    
+    // 公钥是公开的，不能证明请求来自节点本身。从新地址握手的已知节点在响应中收到迁移验证，
+    // 签名回复后才更新地址并启用新的会话密钥
+    let migration_challenge = if migrating {
+        match pending_migration_challenge(&req.node_id, addr, Some(session_key.clone()), ctx).await {
+            Some((nonce, _)) => Some(nonce),
+            None => {
+                let resp = handshake_rejection(&ctx.node_id, status::CAPACITY_EXCEEDED, "Too many pending migrations, retry later", None);
+                send_handshake_response(&ctx.udp_socket, addr, &resp, payload_flags)?;
+                return Ok(());
+            }
+        }
+    } else {
+        None
+    };
+    
+    // 创建握手响应
+    let resp = HandshakeResponse {
+        version: PROTOCOL_VERSION,
+        public_key: random_key(),
+        node_id: ctx.node_id.clone(),
+        node_name: "VPNet Server".to_string(),
+        status: status::OK,
+        message: "Handshake successful".to_string(),
+        session_key: if ephemeral_key.is_some() { Vec::new() } else { session_key.clone() },
+        virtual_ip: Some(virtual_ip.clone()),
+        conflict: None,
+        connection_id,
+        resumed: false,
+        server_version: crate::VERSION.to_string(),
+        migration_challenge,
+        ephemeral_key,
+    };
+    
+    // 发送响应
+    send_handshake_response(&ctx.udp_socket, addr, &resp, payload_flags)?;
+    
+    // 宽限期内重新握手的已知节点保留其状态，从新地址握手时等待迁移验证
+    let mut peers_guard = ctx.peers.write().await;
+    if let Some(peer) = peers_guard.get_mut(&req.node_id) {
+        let now = current_unix_timestamp();
+        let within_grace = now.saturating_sub(peer.last_seen) <= ctx.migration_grace_period.as_secs();
+        
+        if peer.public_key == req.public_key && requires_migration_challenge(peer, addr, ctx.migration_grace_period) {
+            return Ok(());
+        }
+        if within_grace && peer.public_key == req.public_key {
+            peer.local_addr = Some(ctx.local_addr);
+            refresh_multipath(peer, &ctx.sockets);
+            peer.last_seen = now;
+            peer.status = NodeStatus::Online;
+            peer.payload_flags = payload_flags;
+            peer.virtual_ip = virtual_ip;
+            peer.connection_id = connection_id;
+            peer.set_session_key(session_key, false)?;
+            return Ok(());
+        }
+        
+        if peer.public_key != req.public_key {
+            log::warn!("Peer {} presented a different public key from {}, replacing record",
+                       req.node_id, addr);
+        }
+    }
+    
+    // 添加对等节点
+    let mut peer = Peer::new(
+        req.node_id.clone(),
+        req.node_name.clone(),
+        addr,
+        virtual_ip,
+        req.public_key.clone(),
+        req.capabilities
+    );
+    peer.payload_flags = payload_flags;
+    peer.local_addr = Some(ctx.local_addr);
+    peer.connection_id = connection_id;
+    peer.set_session_key(session_key, false)?;
+    let _ = ctx.peer_events.send(PeerEvent::PeerConnected {
+        node_id: peer.node_id.clone(),
+        node_name: peer.node_name.clone(),
+        address: addr,
+        virtual_ip: peer.virtual_ip.clone(),
+    });
+    replace_peer(&mut peers_guard, peer);
+    Ok(())
+}
+
+/// 构建拒绝握手的响应
+fn handshake_rejection(
+    node_id: &str,
+    status: u8,
+    message: &str,
+    conflict: Option<ConflictDetails>
+) -> HandshakeResponse {
+    HandshakeResponse {
+        version: PROTOCOL_VERSION,
+        public_key: Vec::new(),
+        node_id: node_id.to_string(),
+        node_name: "VPNet Server".to_string(),
+        status,
+        message: message.to_string(),
+        session_key: Vec::new(),
+        virtual_ip: None,
+        conflict,
+        connection_id: 0,
+        resumed: false,
+        server_version: crate::VERSION.to_string(),
+        migration_challenge: None,
+        ephemeral_key: None,
+    }
+}
+
+/// 凭会话票据恢复节点的会话
+///
+/// 票据无效、已过期或不属于该节点时返回`false`，由调用方继续完整握手。
+async fn resume_session(
+    req: &HandshakeRequest,
+    ticket: &[u8],
+    addr: SocketAddr,
+    payload_flags: u8,
+    ctx: &HandlerContext
+) -> Result<bool, VpnetError> {
+    let state = match &ctx.session_tickets {
+        Some(keys) => keys.lock().await.redeem(ticket),
+        None => None,
+    };
+    let state = match state {
+        Some(state) if state.node_id == req.node_id && state.public_key == req.public_key => state,
+        _ => {
+            log::debug!("Session ticket from {} ({}) rejected, falling back to full handshake",
+                        req.node_id, addr);
+            return Ok(false);
+        }
+    };
+    
+    // 票据只免去密钥协商，准入检查照常进行：节点可能已被移除、更换了密钥或服务端已满
+    let virtual_ip = match &ctx.handshake_validator {
+        Some(validator) => {
+            let mut admission = req.clone();
+            admission.virtual_ip = Some(state.virtual_ip.clone());
+            match validator.validate(&admission, addr).await {
+                HandshakeVerdict::Accept { virtual_ip } => virtual_ip.to_string(),
+                HandshakeVerdict::Reject { status, message, conflict } => {
+                    log::warn!("Rejecting session resume from {} ({}): {}", req.node_id, addr, message);
+                    let resp = handshake_rejection(&ctx.node_id, status, &message, conflict);
+                    send_handshake_response(&ctx.udp_socket, addr, &resp, payload_flags)?;
+                    return Ok(true);
+                }
+            }
+        }
+        None => state.virtual_ip.clone(),
+    };
+    
+    let resp = HandshakeResponse {
+        version: PROTOCOL_VERSION,
+        public_key: Vec::new(),
+        node_id: ctx.node_id.clone(),
+        node_name: "VPNet Server".to_string(),
+        status: status::OK,
+        message: "Session resumed".to_string(),
+        session_key: state.session_key.clone(),
+        virtual_ip: Some(virtual_ip.clone()),
+        conflict: None,
+        connection_id: state.connection_id,
+        resumed: true,
+        server_version: crate::VERSION.to_string(),
+        migration_challenge: None,
+        ephemeral_key: None,
+    };
+    send_handshake_response(&ctx.udp_socket, addr, &resp, payload_flags)?;
+    log::info!("Peer {} resumed its session from {}", req.node_id, addr);
+    
+    let mut peers_guard = ctx.peers.write().await;
+    let peer = peers_guard.entry(req.node_id.clone()).or_insert_with(|| {
+        let _ = ctx.peer_events.send(PeerEvent::PeerConnected {
+            node_id: req.node_id.clone(),
+            node_name: req.node_name.clone(),
+            address: addr,
+            virtual_ip: virtual_ip.clone(),
+        });
+        Peer::new(
+            req.node_id.clone(),
+            req.node_name.clone(),
+            addr,
+            virtual_ip.clone(),
+            req.public_key.clone(),
+            req.capabilities
+        )
+    });
+    peer.address = addr;
+    peer.local_addr = Some(ctx.local_addr);
+    peer.public_key = state.public_key;
+    peer.last_seen = current_unix_timestamp();
+    peer.status = NodeStatus::Online;
+    peer.payload_flags = payload_flags;
+    peer.virtual_ip = virtual_ip;
+    peer.connection_id = state.connection_id;
+    peer.set_session_key(state.session_key, false)?;
+    refresh_multipath(peer, &ctx.sockets);
+    Ok(true)
+}
+
+/// 发送握手响应
+fn send_handshake_response(
+    udp_socket: &dyn DatagramSocket,
+    addr: SocketAddr,
+    resp: &HandshakeResponse,
+    flags: u8
+) -> Result<(), VpnetError> {
+    let resp_data = serde_json::to_vec(resp)?;
+    let resp_packet = PacketBuilder::new(MessageType::HandshakeResponse, resp_data)
+        .flags(flags)
+        .build();
+    
+    let resp_packet_data = serde_json::to_vec(&resp_packet)?;
+    if let Err(e) = udp_socket.send_to(&resp_packet_data, addr) {
+        log::warn!("Failed to send handshake response to {}: {}", addr, e);
+    }
+    Ok(())
+}
+
+/// 处理握手响应
+async fn handle_handshake_response(packet: Packet, addr: SocketAddr, ctx: &HandlerContext) {
+    // 解析握手响应
+    if let Ok(resp) = serde_json::from_slice::<HandshakeResponse>(&packet.data) {
+        if resp.status != status::OK {
+            match &resp.conflict {
+                Some(conflict) => log::error!(
+                    "Handshake with {} rejected: {} (conflicts with node {})",
+                    addr, resp.message, conflict.conflicting_node_id
+                ),
+                None => log::error!("Handshake with {} rejected: {}", addr, resp.message),
+            }
+            
+            if resp.status == status::CAPACITY_EXCEEDED {
+                let mut peers_guard = ctx.peers.write().await;
+                if let Some(peer) = peers_guard.values_mut().find(|peer| peer.address == addr) {
+                    peer.status = NodeStatus::CapacityExceeded;
+                }
+            }
+            return;
+        }
+        
+        // 更新对等节点，对端在响应中确认双方都支持的负载编码
+        let mut peer = Peer::new(
+            resp.node_id.clone(),
+            resp.node_name.clone(),
+            addr,
+            "10.0.0.1".to_string(), // 默认虚拟IP，实际应从配置获取
+            resp.public_key.clone(),
+            0
+        );
+        peer.payload_flags = packet.flags & supported_payload_flags();
+        peer.local_addr = Some(ctx.local_addr);
+        peer.connection_id = resp.connection_id;
+        peer.upstream = true;
+        let exchange = ctx.pending_exchanges.lock().await.remove(&addr);
+        let session_key = match (exchange, &resp.ephemeral_key) {
+            (Some(exchange), Some(peer_key)) => {
+                let kdf = ctx.kdf.read().await.clone();
+                match exchange.derive_session_key_with(peer_key, kdf.as_ref()) {
+                    Ok(session_key) => session_key,
+                    Err(e) => {
+                        log::warn!("Failed to derive session key with {}: {}", addr, e);
+                        return;
+                    }
+                }
+            }
+            _ => resp.session_key.clone(),
+        };
+        if let Err(e) = peer.set_session_key(session_key, true) {
+            log::warn!("Rejecting session key from {}: {}", addr, e);
+            return;
+        }
+        if resp.resumed {
+            log::info!("Resumed session with {} using a session ticket", addr);
+        }
+        if !resp.server_version.is_empty() {
+            log::debug!("Server {} is running version {}", addr, resp.server_version);
+        }
+        
+        replace_peer(&mut *ctx.peers.write().await, peer);
+        
+        // 从新地址重新握手时，服务端要求签名回应迁移验证后才更新本节点的地址
+        if let Some(nonce) = resp.migration_challenge {
+            if let Err(e) = answer_migration_challenge(nonce, addr, ctx) {
+                log::warn!("Failed to answer migration challenge from {}: {}", addr, e);
+            }
+        }
+    }
+}
+
+/// 节点发现请求，不带负载
+fn discovery_message() -> Result<Vec<u8>, VpnetError> {
+    let discovery_msg = PacketBuilder::new(MessageType::NodeDiscovery, Vec::new()).build();
+    Ok(serde_json::to_vec(&discovery_msg)?)
+}
+
+/// 向`addr`发送节点发现请求，等待其回复的节点信息
+///
+/// 使用临时套接字，不经过网络管理器，用于连接之前查询服务器的位置等信息。
+pub async fn query_node_info(addr: SocketAddr, timeout: Duration) -> Result<NodeInfo, VpnetError> {
+    let bind_addr = match addr {
+        SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+        SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+    };
+    let socket = tokio::net::UdpSocket::bind(bind_addr).await?;
+    socket.send_to(&discovery_message()?, addr).await?;
+    
+    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+    let reply = async {
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).await?;
+            if from != addr {
+                continue;
+            }
+            match Packet::decode(&buf[..len]) {
+                Ok(packet) if packet.msg_type == MessageType::NodeInfo => {
+                    return Ok::<_, VpnetError>(serde_json::from_slice(&packet.data)?);
+                }
+                _ => continue,
+            }
+        }
+    };
+    tokio::time::timeout(timeout, reply).await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, format!("no node info from {}", addr)))?
+}
+
+/// 处理节点发现
+async fn handle_node_discovery(
+    addr: SocketAddr,
+    udp_socket: Arc<dyn DatagramSocket>,
+    node_info_cache: Arc<RwLock<NodeInfoCache>>
+) {
+    // 使用缓存的节点信息响应，同一来源限速
+    let mut cache = node_info_cache.write().await;
+    match cache.response_for(addr.ip()) {
+        Some(resp_packet_data) => {
+            if let Err(e) = udp_socket.send_to(resp_packet_data, addr) {
+                log::warn!("Failed to send node info to {}: {}", addr, e);
+            }
+        }
+        None => {
+            log::debug!("Rate limiting node discovery response to {}", addr);
+        }
+    }
+}
+
+/// 处理节点信息
+async fn handle_node_info(
+    packet: Packet,
+    addr: SocketAddr,
+    local_addr: SocketAddr,
+    node_id: &str,
+    peers: Arc<RwLock<HashMap<String, Peer>>>
+) {
+    // 解析节点信息
+    if let Ok(node_info) = serde_json::from_slice::<NodeInfo>(&packet.data) {
+        // 广播的节点发现也会收到本节点自己的回复
+        if node_info.node_id == node_id {
+            return;
+        }
+        
+        // 节点信息未经认证，已知节点的记录和会话状态只由握手更新，不被局域网内任意主机的广播覆盖
+        peers.write().await.entry(node_info.node_id.clone()).or_insert_with(|| {
+            let mut peer = Peer::new(
+                node_info.node_id.clone(),
+                node_info.node_name.clone(),
+                addr,
+                node_info.virtual_ip.clone(),
+                node_info.public_key.clone(),
+                node_info.capabilities
+            );
+            peer.local_addr = Some(local_addr);
+            peer
+        });
+    }
+}
+
+/// 处理心跳包
+async fn handle_heartbeat(
+    packet: Packet,
+    addr: SocketAddr,
+    peers: Arc<RwLock<HashMap<String, Peer>>>
+) {
+    // 解析心跳包
+    if let Ok(heartbeat) = serde_json::from_slice::<Heartbeat>(&packet.data) {
+        let mut peers_guard = peers.write().await;
+        if let Some(peer) = peers_guard.get_mut(&heartbeat.node_id) {
+            // 心跳未经认证，只接受来自节点已知地址的，否则任何主机都能让离线节点保持在线或改写其元数据
+            if !peer.is_at(addr) {
+                log::debug!("Ignoring heartbeat for {} from {}", heartbeat.node_id, addr);
+                return;
+            }
+            peer.last_seen = current_unix_timestamp();
+            peer.status = NodeStatus::Online;
+            peer.stats.remote_packet_loss_pct = heartbeat.packet_loss_pct;
+            peer.stats.load = heartbeat.load;
+            peer.metadata = heartbeat.extensions;
+            if peer.direct_path == Some(addr) {
+                peer.direct_path_seen = Some(Instant::now());
+            }
+            if let Some(multipath) = &peer.multipath {
+                multipath.record_reply(addr);
+            }
+            if heartbeat.timestamp_ms != 0 {
+                peer.last_heartbeat = Some((heartbeat.timestamp_ms, Instant::now()));
+            }
+            // 按TCP的方式平滑往返时延，新样本权重1/8
+            if let Some(echo) = heartbeat.echo {
+                let now_ms = current_unix_timestamp_millis();
+                let sample = now_ms.saturating_sub(echo.timestamp_ms).saturating_sub(echo.delay_ms) as f64;
+                peer.stats.rtt_ms = if peer.stats.rtt_ms == 0.0 {
+                    sample
+                } else {
+                    peer.stats.rtt_ms * 0.875 + sample * 0.125
+                };
+            }
+        }
+    }
+}
+
+/// 检查携带连接ID的数据包的来源地址
+///
+/// 连接ID属于已知节点但来源地址与记录不符时，向新地址发送迁移验证；
+/// 节点签名回复后才更新其地址，在此之前发往该节点的数据仍使用原地址。
+async fn check_connection_migration(packet: &Packet, addr: SocketAddr, ctx: &HandlerContext) -> Result<(), VpnetError> {
+    if packet.connection_id == 0 {
+        return Ok(());
+    }
+    
+    let node_id = match ctx.peers.read().await.values()
+        .find(|peer| peer.connection_id == packet.connection_id)
+    {
+        Some(peer) if peer.address != addr && peer.direct_path != Some(addr) => peer.node_id.clone(),
+        _ => return Ok(()),
+    };
+    send_migration_challenge(&node_id, addr, ctx).await
+}
+
+/// 已知节点从`addr`握手时是否需要先通过迁移验证
+///
+/// 地址不变、已关闭连接且超过宽限期的节点直接完整握手，否则任何知道其公钥的人都能改写其地址。
+fn requires_migration_challenge(peer: &Peer, addr: SocketAddr, grace_period: Duration) -> bool {
+    let within_grace = current_unix_timestamp().saturating_sub(peer.last_seen) <= grace_period.as_secs();
+    peer.address != addr && (within_grace || peer.status != NodeStatus::Offline)
+}
+
+/// 节点迁移到新地址的验证随机数，沿用同一地址未过期的验证，新签发时返回true
+///
+/// `session_key`为从新地址握手时下发的会话密钥，验证通过后启用。
+/// 等待验证的迁移达到上限时返回`None`。
+async fn pending_migration_challenge(
+    node_id: &str,
+    addr: SocketAddr,
+    session_key: Option<Vec<u8>>,
+    ctx: &HandlerContext
+) -> Option<([u8; 16], bool)> {
+    let timeout = Duration::from_secs(constants::MIGRATION_CHALLENGE_TIMEOUT);
+    let mut pending = ctx.pending_migrations.lock().await;
+    pending.retain(|_, migration| migration.issued_at.elapsed() < timeout);
+    let existing = pending.iter_mut()
+        .find(|(_, migration)| migration.node_id == node_id && migration.address == addr);
+    if let Some((nonce, migration)) = existing {
+        if session_key.is_some() {
+            migration.session_key = session_key;
+        }
+        return Some((*nonce, false));
+    }
+    if pending.len() >= constants::MAX_PENDING_MIGRATIONS {
+        log::warn!("Too many pending migrations, not challenging {} at {}", node_id, addr);
+        return None;
+    }
+    
+    let nonce: [u8; 16] = SecureRng::new().gen();
+    log::debug!("Peer {} appeared at {}, challenging the migration", node_id, addr);
+    pending.insert(nonce, PendingMigration {
+        node_id: node_id.to_string(),
+        address: addr,
+        local_addr: ctx.local_addr,
+        session_key,
+        issued_at: Instant::now(),
+    });
+    Some((nonce, true))
+}
+
+/// 向节点出现的新地址发送迁移验证，同一地址已有未过期的验证时不重复发送
+async fn send_migration_challenge(node_id: &str, addr: SocketAddr, ctx: &HandlerContext) -> Result<(), VpnetError> {
+    let Some((nonce, true)) = pending_migration_challenge(node_id, addr, None, ctx).await else {
+        return Ok(());
+    };
+    
+    let challenge_data = encode_message(MessageType::MigrationChallenge, &MigrationChallenge { nonce }, 0)?;
+    if let Err(e) = ctx.udp_socket.send_to(&challenge_data, addr) {
+        log::debug!("Failed to send migration challenge to {}: {}", addr, e);
+    }
+    Ok(())
+}
+
+/// 处理连接迁移验证，使用签名密钥签名随机数后回复
+async fn handle_migration_challenge(packet: Packet, addr: SocketAddr, ctx: &HandlerContext) -> Result<(), VpnetError> {
+    let challenge = match serde_json::from_slice::<MigrationChallenge>(&packet.data) {
+        Ok(challenge) => challenge,
+        Err(_) => return Ok(()),
+    };
+    
+    // 只回应已知节点（服务端）的验证
+    if !ctx.peers.read().await.values().any(|peer| peer.address == addr) {
+        log::debug!("Ignoring migration challenge from unknown address {}", addr);
+        return Ok(());
+    }
+    answer_migration_challenge(challenge.nonce, addr, ctx)
+}
+
+/// 签名迁移验证随机数后回复
+fn answer_migration_challenge(nonce: [u8; 16], addr: SocketAddr, ctx: &HandlerContext) -> Result<(), VpnetError> {
+    let signing_key = match &ctx.signing_key {
+        Some(signing_key) => signing_key,
+        None => {
+            log::warn!("Cannot answer migration challenge from {}: no signing key configured", addr);
+            return Ok(());
+        }
+    };
+    
+    let response = MigrationResponse {
+        nonce,
+        signature: signing_key.sign(&MigrationResponse::signing_payload(&nonce, &ctx.node_id)),
+    };
+    let response_data = encode_message(MessageType::MigrationResponse, &response, 0)?;
+    if let Err(e) = ctx.udp_socket.send_to(&response_data, addr) {
+        log::debug!("Failed to send migration response to {}: {}", addr, e);
+    }
+    Ok(())
+}
+
+/// 处理连接迁移验证响应，签名有效时将节点地址更新为新地址
+async fn handle_migration_response(packet: Packet, addr: SocketAddr, ctx: &HandlerContext) {
+    let response = match serde_json::from_slice::<MigrationResponse>(&packet.data) {
+        Ok(response) => response,
+        Err(_) => return,
+    };
+    
+    let migration = match ctx.pending_migrations.lock().await.remove(&response.nonce) {
+        Some(migration) => migration,
+        None => {
+            log::debug!("Ignoring unexpected migration response from {}", addr);
+            return;
+        }
+    };
+    if migration.address != addr
+        || migration.issued_at.elapsed() >= Duration::from_secs(constants::MIGRATION_CHALLENGE_TIMEOUT)
+    {
+        log::warn!("Rejecting migration of {} to {}: response from wrong address or too late",
+                   migration.node_id, addr);
+        return;
+    }
+    
+    let mut peers_guard = ctx.peers.write().await;
+    let peer = match peers_guard.get_mut(&migration.node_id) {
+        Some(peer) => peer,
+        None => return,
+    };
+    
+    let payload = MigrationResponse::signing_payload(&response.nonce, &peer.node_id);
+    if !KeyPair::verify(&peer.public_key, &payload, &response.signature) {
+        log::warn!("Rejecting migration of {} to {}: invalid signature", peer.node_id, addr);
+        return;
+    }
+    
+    log::info!("Peer {} migrated from {} to {}", peer.node_id, peer.address, addr);
+    let _ = ctx.peer_events.send(PeerEvent::PeerMigrated {
+        node_id: peer.node_id.clone(),
+        old_address: peer.address,
+        new_address: addr,
+    });
+    peer.address = addr;
+    peer.address_updated_at = Instant::now();
+    peer.local_addr = Some(migration.local_addr);
+    if let Some(session_key) = migration.session_key {
+        if let Err(e) = peer.set_session_key(session_key, false) {
+            log::warn!("Failed to switch {} to its new session key: {}", peer.node_id, e);
+        }
+    }
+    refresh_multipath(peer, &ctx.sockets);
+    peer.last_seen = current_unix_timestamp();
+    peer.status = NodeStatus::Online;
+}
+
+/// 处理连接关闭
+async fn handle_connection_close(packet: Packet, addr: SocketAddr, ctx: &HandlerContext) {
+    let close = match serde_json::from_slice::<ConnectionClose>(&packet.data) {
+        Ok(close) => close,
+        Err(_) => return,
+    };
+    
+    let state = {
+        let mut peers_guard = ctx.peers.write().await;
+        // 只接受来自节点当前地址的关闭消息
+        let peer = match peers_guard.get_mut(&close.node_id).filter(|p| p.address == addr) {
+            Some(peer) => peer,
+            None => return,
+        };
+        log::info!("Peer {} closed the connection: {}", close.node_id, close.reason);
+        // 重复的关闭消息不再触发事件
+        if peer.status != NodeStatus::Offline {
+            peer.status = NodeStatus::Offline;
+            let _ = ctx.peer_events.send(PeerEvent::PeerDisconnected {
+                node_id: close.node_id,
+                reason: close.reason,
+            });
+        }
+        
+        SessionState {
+            node_id: peer.node_id.clone(),
+            public_key: peer.public_key.clone(),
+            virtual_ip: peer.virtual_ip.clone(),
+            session_key: peer.session_key.clone(),
+            connection_id: peer.connection_id,
+            issued_at: current_unix_timestamp(),
+        }
+    };
+    
+    // 启用会话恢复时为经过握手的节点签发票据
+    let keys = match &ctx.session_tickets {
+        Some(keys) if !state.session_key.is_empty() => keys,
+        _ => return,
+    };
+    let message = {
+        let mut keys = keys.lock().await;
+        match keys.issue(&state) {
+            Ok(ticket) => SessionTicket { ticket, lifetime: keys.lifetime().as_secs() },
+            Err(e) => {
+                log::warn!("Failed to issue session ticket for {}: {}", state.node_id, e);
+                return;
+            }
+        }
+    };
+    send_message(&ctx.udp_socket, addr, MessageType::SessionTicket, &message);
+}
+
+/// 处理会话票据，只接受来自已知节点当前地址的票据
+async fn handle_session_ticket(packet: Packet, addr: SocketAddr, ctx: &HandlerContext) {
+    let message = match serde_json::from_slice::<SessionTicket>(&packet.data) {
+        Ok(message) => message,
+        Err(_) => return,
+    };
+    
+    if !ctx.peers.read().await.values().any(|peer| peer.address == addr) {
+        log::debug!("Ignoring session ticket from unknown address {}", addr);
+        return;
+    }
+    
+    log::debug!("Received session ticket from {}, valid for {} seconds", addr, message.lifetime);
+    ctx.received_tickets.lock().await.insert(addr, message.ticket);
+}
+
+/// 处理直连路径提议，向对端的观测地址发送探测以打通NAT
+async fn handle_direct_path_offer(packet: Packet, addr: SocketAddr, ctx: &HandlerContext) {
+    let offer = match serde_json::from_slice::<DirectPathOffer>(&packet.data) {
+        Ok(offer) => offer,
+        Err(_) => return,
+    };
+    
+    // 只接受已知节点（服务端）转来的提议
+    if !ctx.peers.read().await.values().any(|peer| peer.address == addr) {
+        log::debug!("Ignoring direct path offer from unknown address {}", addr);
+        return;
+    }
+    
+    log::info!("Trying direct path to {} at {}", offer.peer_id, offer.address);
+    ctx.path_offers.lock().await.insert(offer.peer_id.clone(), PendingPathOffer {
+        signaling_addr: addr,
+        address: offer.address,
+        token: offer.token,
+    });
+    
+    let probe = DirectPathProbe { node_id: ctx.node_id.clone(), token: offer.token };
+    let udp_socket = ctx.udp_socket.clone();
+    tokio::spawn(async move {
+        for _ in 0..constants::DIRECT_PATH_PROBES {
+            send_message(&udp_socket, offer.address, MessageType::DirectPathProbe, &probe);
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    });
+}
+
+/// 处理直连路径探测
+///
+/// 只接受与待建立的提议匹配的探测：来自提议中的地址，且携带提议的令牌。
+/// 首次收到时记录直连路径，回复一次探测使对端也能建立，并通知提议直连的服务端。
+async fn handle_direct_path_probe(packet: Packet, addr: SocketAddr, ctx: &HandlerContext) {
+    let probe = match serde_json::from_slice::<DirectPathProbe>(&packet.data) {
+        Ok(probe) => probe,
+        Err(_) => return,
+    };
+    
+    let (signaling_addr, token) = match ctx.path_offers.lock().await.get(&probe.node_id) {
+        Some(offer) if offer.address == addr && offer.token == probe.token => (offer.signaling_addr, offer.token),
+        _ => {
+            log::debug!("Ignoring unsolicited direct path probe for {} from {}", probe.node_id, addr);
+            return;
+        }
+    };
+    
+    let established = {
+        let mut peers_guard = ctx.peers.write().await;
+        let peer = match peers_guard.get_mut(&probe.node_id) {
+            Some(peer) if peer.address != addr => peer,
+            _ => return,
+        };
+        
+        peer.direct_path_seen = Some(Instant::now());
+        if peer.direct_path == Some(addr) {
+            false
+        } else {
+            log::info!("Direct path to {} established via {}", probe.node_id, addr);
+            peer.direct_path = Some(addr);
+            refresh_multipath(peer, &ctx.sockets);
+            true
+        }
+    };
+    
+    if !established {
+        return;
+    }
+    
+    let reply = DirectPathProbe { node_id: ctx.node_id.clone(), token };
+    send_message(&ctx.udp_socket, addr, MessageType::DirectPathProbe, &reply);
+    
+    ctx.path_offers.lock().await.remove(&probe.node_id);
+    let notice = DirectPathEstablished {
+        node_id: ctx.node_id.clone(),
+        peer_id: probe.node_id,
+    };
+    send_message(&ctx.udp_socket, signaling_addr, MessageType::DirectPathEstablished, &notice);
+}
+
+/// 处理直连路径已建立通知
+async fn handle_direct_path_established(
+    packet: Packet,
+    addr: SocketAddr,
+    peers: Arc<RwLock<HashMap<String, Peer>>>
+) {
+    if let Ok(notice) = serde_json::from_slice::<DirectPathEstablished>(&packet.data) {
+        let mut peers_guard = peers.write().await;
+        if let Some(peer) = peers_guard.get_mut(&notice.node_id).filter(|p| p.address == addr) {
+            log::info!("Peer {} reports a direct path to {}, relay no longer needed",
+                       notice.node_id, notice.peer_id);
+            peer.relay_peers.remove(&notice.peer_id);
+            peer.direct_peers.insert(notice.peer_id);
+        }
+    }
+}
+
+/// 处理路由更新
+///
+/// 只接受已握手节点发出、签名与其握手时出示的公钥一致且未过期的更新，
+/// 验证通过后将路由加入主路由表，下一跳为发送节点。
+async fn handle_route_update(packet: Packet, addr: SocketAddr, ctx: &HandlerContext) {
+    let update = match serde_json::from_slice::<RouteUpdate>(&packet.data) {
+        Ok(update) => update,
+        Err(_) => return,
+    };
+    
+    let (public_key, virtual_ip) = match ctx.peers.read().await.get(&update.node_id) {
+        Some(peer) => (peer.public_key.clone(), peer.virtual_ip.parse::<Ipv4Addr>().ok()),
+        None => {
+            log::warn!("Dropping route update from unknown node {} ({})", update.node_id, addr);
+            return;
+        }
+    };
+    
+    if !update.verify_signature(&public_key) {
+        log::warn!("Dropping route update from {} ({}): invalid signature", update.node_id, addr);
+        return;
+    }
+    
+    if current_unix_timestamp().abs_diff(update.timestamp) > constants::ROUTE_UPDATE_MAX_SKEW {
+        log::warn!("Dropping route update from {} ({}): timestamp out of range", update.node_id, addr);
+        return;
+    }
+    
+    // 签名只证明更新来自哪个节点。节点只能宣告自己的虚拟IP或管理员允许的网段，
+    // 先检查全部条目，有任一无效或越权时整体丢弃
+    let own_route = virtual_ip.and_then(|ip| IpCidr::new(ip, 32).ok());
+    let allowed = ctx.route_prefixes.read().await.get(&update.node_id).cloned().unwrap_or_default();
+    let mut routes = Vec::with_capacity(update.routes.len());
+    for entry in &update.routes {
+        let route = match route_from_entry(entry, &update.node_id) {
+            Ok(route) => route,
+            Err(e) => {
+                log::warn!("Dropping route update from {}: {} in {}/{}", update.node_id, e, entry.network, entry.mask);
+                return;
+            }
+        };
+        if own_route != Some(route.destination) && !allowed.iter().any(|prefix| prefix.covers(&route.destination)) {
+            log::warn!("Dropping route update from {}: {} is not an allowed prefix", update.node_id, route.destination);
+            return;
+        }
+        routes.push(route);
+    }
+    
+    let mut routing = ctx.routing.write().await;
+    let table = routing.table_mut(MAIN_TABLE);
+    if let Some(existing) = table.iter().find(|existing| {
+        existing.next_hop != update.node_id && routes.iter().any(|route| route.destination == existing.destination)
+    }) {
+        log::warn!("Dropping route update from {}: {} is already routed via {:?}",
+                   update.node_id, existing.destination, existing.next_hop);
+        return;
+    }
+    log::info!("Accepted {} routes from {}", routes.len(), update.node_id);
+    for route in routes {
+        table.add_route(route);
+    }
+}
+
+/// 将路由更新中的条目转换为经`next_hop`的路由
+fn route_from_entry(entry: &RouteEntry, next_hop: &str) -> Result<Route, &'static str> {
+    let network: Ipv4Addr = entry.network.parse().map_err(|_| "invalid network")?;
+    let mask: Ipv4Addr = entry.mask.parse().map_err(|_| "invalid netmask")?;
+    let gateway: Ipv4Addr = entry.gateway.parse().map_err(|_| "invalid gateway")?;
+    
+    Ok(Route {
+        destination: IpCidr::from_netmask(network, mask)?,
+        gateway,
+        next_hop: next_hop.to_string(),
+        metric: entry.metric,
+    })
+}
+
+/// 处理虚拟IP分配，转交给订阅者更新虚拟设备
+async fn handle_ip_assignment(
+    packet: Packet,
+    addr: SocketAddr,
+    peers: Arc<RwLock<HashMap<String, Peer>>>,
+    ip_assignments: broadcast::Sender<IpAssignment>
+) {
+    if let Ok(assignment) = serde_json::from_slice::<IpAssignment>(&packet.data) {
+        // 只接受本节点主动握手的服务端下发的分配
+        if !peers.read().await.values().any(|peer| peer.upstream && peer.address == addr) {
+            log::warn!("Ignoring IP assignment from {}, not an upstream server", addr);
+            return;
+        }
+        
+        log::info!("Server assigned virtual IP {}/{}", assignment.virtual_ip, assignment.subnet);
+        if ip_assignments.send(assignment).is_err() {
+            log::warn!("No subscriber for IP assignment, virtual device not updated");
+        }
+    }
+}
+
+/// 将控制消息JSON编码后封装为完整的数据包
+fn encode_message<T: Serialize>(msg_type: MessageType, message: &T, connection_id: u64) -> Result<Vec<u8>, VpnetError> {
+    let message_data = serde_json::to_vec(message)?;
+    let packet = PacketBuilder::new(msg_type, message_data)
+        .connection_id(connection_id)
+        .build();
+    Ok(packet.encode()?)
+}
+
+/// 发送JSON编码的控制消息
+fn send_message<T: Serialize>(udp_socket: &dyn DatagramSocket, addr: SocketAddr, msg_type: MessageType, message: &T) {
+    let message_data = match encode_message(msg_type, message, 0) {
+        Ok(data) => data,
+        Err(e) => {
+            log::warn!("Failed to encode {:?}: {}", msg_type, e);
+            return;
+        }
+    };
+    
+    if let Err(e) = udp_socket.send_to(&message_data, addr) {
+        log::debug!("Failed to send {:?} to {}: {}", msg_type, addr, e);
+    }
+}
+
+/// 处理TTL耗尽通知
+///
+/// 只接受来自已知节点地址、且与本节点发出的探测（源节点、目标节点和序列号）一致的回复，
+/// 防止伪造的回复冒充路径上的节点。
+async fn handle_ttl_exceeded(packet: Packet, addr: SocketAddr, ctx: &HandlerContext) {
+    let message = match serde_json::from_slice::<TtlExceededMessage>(&packet.data) {
+        Ok(message) if message.original_src == ctx.node_id => message,
+        _ => return,
+    };
+    
+    let known_sender = ctx.peers.read().await
+        .values()
+        .any(|peer| peer.address == addr || peer.send_address() == addr);
+    if !known_sender {
+        log::debug!("Ignoring TTL exceeded from unknown address {}", addr);
+        return;
+    }
+    
+    // 没有等待者说明探测已超时放弃，或并非本节点发起
+    let mut probes = ctx.probes.lock().await;
+    if probes.get(&message.seq).is_some_and(|(dest_node, _)| *dest_node == message.original_dst) {
+        if let Some((_, waiter)) = probes.remove(&message.seq) {
+            let _ = waiter.send(message);
+        }
+    }
+}
+
+/// 向数据转发的源节点回复TTL耗尽通知
+async fn send_ttl_exceeded(
+    forward: &DataForward,
+    sockets: &Arc<SocketSet>,
+    peers: &Arc<RwLock<HashMap<String, Peer>>>,
+    node_id: &str
+) -> Result<(), VpnetError> {
+    let (udp_socket, source_addr) = match peers.read().await.get(&forward.source_node) {
+        Some(peer) => (sockets.for_peer(peer).clone(), peer.send_address()),
+        None => return Ok(()),
+    };
+    
+    let message = TtlExceededMessage {
+        original_src: forward.source_node.clone(),
+        original_dst: forward.dest_node.clone(),
+        hop_node_id: node_id.to_string(),
+        seq: forward.seq,
+    };
+    
+    let message_data = encode_message(MessageType::TtlExceeded, &message, 0)?;
+    if let Err(e) = udp_socket.send_to(&message_data, source_addr) {
+        log::debug!("Failed to send TTL exceeded to {}: {}", forward.source_node, e);
+    }
+    Ok(())
+}
+
+/// 处理数据转发
+async fn handle_data_forward(
+    packet: Packet,
+    addr: SocketAddr,
+    sockets: Arc<SocketSet>,
+    peers: Arc<RwLock<HashMap<String, Peer>>>,
+    node_id: String,
+    forward_filter: Option<Arc<dyn ForwardFilter>>,
+    scheduler: Option<Arc<dyn PacketScheduler>>,
+    bandwidth_limiter: Option<Arc<GroupBandwidthLimiter>>,
+    mirror: Option<Arc<TrafficMirror>>,
+    inspector: Option<Arc<PacketInspector>>,
+    relay_limiter: Option<Arc<RelayLimiter>>,
+    device_tx: Option<mpsc::Sender<Vec<u8>>>,
+    packet_sizes: &PacketSizeHistogram,
+    device_mode: DeviceMode
+) {
+    // 解析数据转发消息
+    if let Ok(mut forward) = decode_data_forward(&packet.data, packet.flags) {
+        // 数据包中的源节点未经认证，以发送地址确认后才中继、按分组过滤或回复TTL超时，否则任何主机
+        // 都能冒充其他节点的虚拟IP、冒充允许的分组绕过隔离，或让本节点向受害者发送回复
+        let from_source = peers.read().await.get(&forward.source_node)
+            .is_some_and(|peer| peer.is_at(addr));
+        
+        // 目标不是本节点，作为中继转发到下一跳
+        if forward.dest_node != node_id {
+            if !from_source {
+                log::debug!("Refusing to relay from {}: {} is not its address",
+                            forward.source_node, addr);
+                return;
+            }
+            if let Some(filter) = &forward_filter {
+                if !filter.allow(&forward.source_node, &forward.dest_node).await {
+                    log::debug!("Forwarding from {} to {} denied, dropping packet",
+                                forward.source_node, forward.dest_node);
+                    return;
+                }
+            }
+            relay_data_forward(
+                &mut forward,
+                &sockets,
+                &peers,
+                &node_id,
+                scheduler.as_deref(),
+                bandwidth_limiter.as_deref(),
+                relay_limiter.as_deref()
+            ).await;
+            return;
+        }
+        
+        // 路径探测到达目标，回复源节点。经中继到达的探测由中继确认过源节点
+        if forward.protocol == constants::PROBE_PROTOCOL {
+            let via_relay = peers.read().await.values()
+                .any(|peer| peer.can_relay() && peer.is_at(addr));
+            if !from_source && !via_relay {
+                log::debug!("Ignoring path probe from {}: {} is not its address",
+                            forward.source_node, addr);
+                return;
+            }
+            if let Err(e) = send_ttl_exceeded(&forward, &sockets, &peers, &node_id).await {
+                log::warn!("Failed to answer path probe from {}: {}", forward.source_node, e);
+            }
+            return;
+        }
+        
+        // 数据用与源节点的会话密钥加密，只有与源节点握手过才能解密，经中继到达的也是如此
+        let (crypto, recorded_ip) = {
+            let peers_guard = peers.read().await;
+            match peers_guard.get(&forward.source_node) {
+                Some(Peer { crypto: Some(crypto), virtual_ip, .. }) => (crypto.clone(), virtual_ip.parse::<Ipv4Addr>().ok()),
+                _ => {
+                    log::debug!("Dropping data from {} ({}): no session with the source node",
+                                forward.source_node, addr);
+                    return;
+                }
+            }
+        };
+        
+        // 源IP以本地记录为准，携带的源IP与记录不符视为伪造
+        if let Some(recorded) = recorded_ip {
+            if forward.source_virtual_ip.is_unspecified() {
+                forward.source_virtual_ip = recorded;
+            } else if forward.source_virtual_ip != recorded {
+                log::warn!("Dropping data from {}: source IP {} does not match {}",
+                           forward.source_node, forward.source_virtual_ip, recorded);
+                return;
+            }
+        }
+        
+        if forward.source_virtual_ip.is_unspecified() {
+            log::debug!("Dropping data from {}: unknown source IP", forward.source_node);
+            return;
+        }
+        
+        // 解密数据
+        let decrypted = crypto.lock().await.decrypt(&forward.data, &[]);
+        if let Ok(mut plaintext) = decrypted {
+            // 序列号不受加密保护，解密成功后才计入丢包统计和去重，伪造的数据包不会占用序列号
+            {
+                let mut peers_guard = peers.write().await;
+                let Some(peer) = peers_guard.get_mut(&forward.source_node) else {
+                    return;
+                };
+                if let Some(multipath) = &peer.multipath {
+                    multipath.record_reply(addr);
+                }
+                let fresh = peer.loss_estimator.record(forward.seq);
+                // 只有多路径绑定会让同一数据包经直连和中继各到达一份，只保留先到的。
+                // 未启用多路径时不去重，序列号为0表示发送方不编号
+                if !fresh && forward.seq != 0 && peer.multipath.is_some() {
+                    log::trace!("Dropping duplicate data from {} (seq {})", forward.source_node, forward.seq);
+                    return;
+                }
+                peer.stats.packets_received += 1;
+                peer.stats.bytes_received += forward.data.len() as u64;
+                peer.stats.packet_loss_pct = (peer.loss_estimator.loss_rate() * 100.0) as f32;
+                
+                if forward.ack_requested {
+                    send_data_ack(&peers_guard, &forward.source_node, addr, &sockets, &node_id);
+                }
+            }
+            
+            if forward.compressed {
+                plaintext = match crate::compression::decompress(&plaintext) {
+                    Ok(decompressed) => decompressed,
+                    Err(e) => {
+                        log::debug!("Dropping data from {}: {}", forward.source_node, e);
+                        return;
+                    }
+                };
+            }
+            
+            if let Some(mirror) = &mirror {
+                mirror.mirror(&forward.source_node, &forward.dest_node, &plaintext);
+            }
+            
+            if let Some(inspector) = &inspector {
+                let protocol = inspector.inspect(&plaintext);
+                log::trace!("Data from {} identified as {:?}", forward.source_node, protocol);
+            }
+            
+            // 将数据转发到虚拟设备，TAP模式下包含以太网帧头的完整帧
+            log::debug!("Forwarding data from {} ({}) to {} ({} bytes)", 
+                        forward.source_node, forward.source_virtual_ip, forward.dest_node, plaintext.len());
+            packet_sizes.record(ip_packet_len(device_mode, &plaintext));
+            if let Some(device_tx) = &device_tx {
+                if device_tx.send(plaintext).await.is_err() {
+                    log::warn!("Virtual device closed, dropping data from {}", forward.source_node);
+                }
+            }
+        }
+    }
+}
+
+/// 生成32字节的随机密钥
+fn random_key() -> Vec<u8> {
+    let key: [u8; 32] = SecureRng::new().gen();
+    key.to_vec()
+}
+
+/// 帧中IP数据包的长度，TAP模式下不含以太网帧头
+fn ip_packet_len(mode: DeviceMode, frame: &[u8]) -> usize {
+    frame_payload(mode, frame).map_or(0, <[u8]>::len)
+}
+
+/// 向数据转发的源节点回复确认
+///
+/// 数据转发从源节点的已知地址到达时直接回复；经中继`addr`到达时回复给该中继，由其转发给源节点。
+fn send_data_ack(peers: &HashMap<String, Peer>, source_node: &str, addr: SocketAddr, sockets: &SocketSet, node_id: &str) {
+    let Some(peer) = peers.get(source_node) else {
+        return;
+    };
+    let Some(seq) = peer.loss_estimator.highest_seq() else {
+        return;
+    };
+    let ack = DataAck {
+        node_id: node_id.to_string(),
+        dest_node: source_node.to_string(),
+        seq,
+        received_count: peer.stats.packets_received as u32,
+        received_bitmap: peer.loss_estimator.bitmap(),
+    };
+    
+    if peer.is_at(addr) {
+        send_message(sockets.for_peer(peer), peer.send_address(), MessageType::DataAck, &ack);
+    } else if let Some(relay) = peers.values().find(|relay| relay.is_at(addr) && relay.can_relay()) {
+        send_message(sockets.for_peer(relay), addr, MessageType::DataAck, &ack);
+    }
+}
+
+/// 处理数据转发确认
+///
+/// 发给本节点的确认只接受来自确认方已知地址或经能够中继的已知节点转发的；
+/// 发给其他节点的确认由`relay_data_ack`转发，不计入本节点与确认方之间链路的丢包。
+async fn handle_data_ack(packet: Packet, addr: SocketAddr, ctx: &HandlerContext) {
+    if let Ok(ack) = serde_json::from_slice::<DataAck>(&packet.data) {
+        if !ack.dest_node.is_empty() && ack.dest_node != ctx.node_id {
+            relay_data_ack(&ack, addr, ctx).await;
+            return;
+        }
+        
+        let mut peers_guard = ctx.peers.write().await;
+        let via_relay = peers_guard.values().any(|relay| relay.is_at(addr) && relay.can_relay());
+        if let Some(peer) = peers_guard.get_mut(&ack.node_id)
+            .filter(|p| p.is_at(addr) || via_relay)
+        {
+            if let Some(multipath) = &peer.multipath {
+                multipath.record_reply(addr);
+            }
+            peer.stats.acked_loss_pct = (ack.loss_rate() * 100.0) as f32;
+            log::trace!("Peer {} acknowledged up to seq {} ({:.1}% loss)",
+                        ack.node_id, ack.seq, peer.stats.acked_loss_pct);
+            peer.last_ack = Some(ack);
+        }
+    }
+}
+
+/// 转发发给其他节点的数据转发确认
+///
+/// 确认方须从其已知地址发来，且本节点正在为目标节点向确认方中继数据，否则丢弃。
+async fn relay_data_ack(ack: &DataAck, addr: SocketAddr, ctx: &HandlerContext) {
+    let peers_guard = ctx.peers.read().await;
+    let from_acker = peers_guard.get(&ack.node_id).is_some_and(|peer| peer.is_at(addr));
+    let Some(dest) = peers_guard.get(&ack.dest_node)
+        .filter(|dest| from_acker && dest.relay_peers.contains_key(&ack.node_id))
+    else {
+        log::debug!("Dropping data ack from {} to {}: not relaying between them", ack.node_id, ack.dest_node);
+        return;
+    };
+    send_message(ctx.sockets.for_peer(dest), dest.send_address(), MessageType::DataAck, ack);
+}
+
+/// 处理节点间时延探测
+///
+/// 目标为本节点时直接回复；否则本节点作为服务端将探测转发给目标节点，
+/// 同一对节点在`LATENCY_PROBE_INTERVAL`内只转发一次。
+async fn handle_latency_probe(packet: Packet, addr: SocketAddr, ctx: &HandlerContext) {
+    let probe = match serde_json::from_slice::<LatencyProbe>(&packet.data) {
+        Ok(probe) => probe,
+        Err(_) => return,
+    };
+    
+    if probe.target_peer_id == ctx.node_id {
+        send_message(&ctx.udp_socket, addr, MessageType::LatencyProbeReply, &LatencyProbeReply { probe_id: probe.probe_id });
+        return;
+    }
+    
+    let peers_guard = ctx.peers.read().await;
+    let source = match peers_guard.values().find(|peer| peer.address == addr) {
+        Some(peer) => peer.node_id.clone(),
+        None => {
+            log::debug!("Ignoring latency probe from unknown address {}", addr);
+            return;
+        }
+    };
+    let target = match peers_guard.get(&probe.target_peer_id) {
+        Some(peer) => peer,
+        None => {
+            log::debug!("Latency probe from {} for unknown peer {}", source, probe.target_peer_id);
+            return;
+        }
+    };
+    
+    if !ctx.latency_probes.lock().await.admit(&source, &target.node_id, probe.probe_id) {
+        log::trace!("Throttling latency probe from {} to {}", source, target.node_id);
+        return;
+    }
+    send_message(ctx.sockets.for_peer(target), target.send_address(), MessageType::LatencyProbe, &probe);
+}
+
+/// 处理节点间时延探测回复
+///
+/// 回复对应本节点转发的探测时，记录两个节点之间的往返时延并将回复转给源节点。
+async fn handle_latency_probe_reply(packet: Packet, addr: SocketAddr, ctx: &HandlerContext) {
+    let reply = match serde_json::from_slice::<LatencyProbeReply>(&packet.data) {
+        Ok(reply) => reply,
+        Err(_) => return,
+    };
+    
+    let peers_guard = ctx.peers.read().await;
+    let target = match peers_guard.values().find(|peer| peer.address == addr || peer.direct_path == Some(addr)) {
+        Some(peer) => peer,
+        None => return,
+    };
+    let (source_id, elapsed) = match ctx.latency_probes.lock().await.complete(&target.node_id, reply.probe_id) {
+        Some(pending) => pending,
+        None => {
+            log::trace!("Latency probe {} answered by {}", reply.probe_id, target.node_id);
+            return;
+        }
+    };
+    let source = match peers_guard.get(&source_id) {
+        Some(peer) => peer,
+        None => return,
+    };
+    
+    let sample = LatencySample {
+        source_peer_id: source_id.clone(),
+        target_peer_id: target.node_id.clone(),
+        rtt_ms: elapsed.as_secs_f64() * 1000.0 + source.stats.rtt_ms,
+        measured_at: current_unix_timestamp(),
+    };
+    log::debug!("Latency between {} and {}: {:.1}ms", sample.source_peer_id, sample.target_peer_id, sample.rtt_ms);
+    // 没有订阅者时丢弃
+    let _ = ctx.latency_samples.send(sample);
+    
+    send_message(ctx.sockets.for_peer(source), source.send_address(), MessageType::LatencyProbeReply, &reply);
+}
+
+/// 中继转发数据到目标节点
+async fn relay_data_forward(
+    forward: &mut DataForward,
+    sockets: &Arc<SocketSet>,
+    peers: &Arc<RwLock<HashMap<String, Peer>>>,
+    node_id: &str,
+    scheduler: Option<&dyn PacketScheduler>,
+    bandwidth_limiter: Option<&GroupBandwidthLimiter>,
+    relay_limiter: Option<&RelayLimiter>
+) {
+    // 每经过一跳TTL减一，耗尽时丢弃以防止环路
+    forward.ttl = forward.ttl.saturating_sub(1);
+    if forward.ttl == 0 {
+        let mut peers_guard = peers.write().await;
+        if let Some(peer) = peers_guard.get_mut(&forward.source_node) {
+            peer.stats.ttl_exceeded_total += 1;
+        }
+        
+        let now = current_unix_timestamp();
+        let last = LAST_LOOP_WARNING.load(Ordering::Relaxed);
+        if now.saturating_sub(last) >= constants::LOOP_WARNING_INTERVAL {
+            LAST_LOOP_WARNING.store(now, Ordering::Relaxed);
+            log::warn!("loop_detected: dropping packet from {} to {}, TTL exceeded",
+                       forward.source_node, forward.dest_node);
+        }
+        drop(peers_guard);
+        
+        if let Err(e) = send_ttl_exceeded(forward, sockets, peers, node_id).await {
+            log::warn!("Failed to send TTL exceeded to {}: {}", forward.source_node, e);
+        }
+        return;
+    }
+    
+    let mut peers_guard = peers.write().await;
+    
+    // 超出中继会话数或带宽限制时丢弃
+    if let Some(limiter) = relay_limiter {
+        let new_session = !peers_guard.get(&forward.source_node)
+            .is_some_and(|source| source.relay_peers.contains_key(&forward.dest_node));
+        let active = peers_guard.values().map(|peer| peer.relay_peers.len()).sum();
+        if new_session && !limiter.admit_session(active) {
+            log::debug!("Relay session limit reached, refusing to relay from {} to {}",
+                        forward.source_node, forward.dest_node);
+            return;
+        }
+        if !limiter.admit_bytes(forward.data.len()) {
+            log::debug!("Relay bandwidth exhausted, dropping packet from {} to {}",
+                        forward.source_node, forward.dest_node);
+            return;
+        }
+    }
+    
+    // 节点重新经由中继发送，说明其直连路径已失效
+    if let Some(source) = peers_guard.get_mut(&forward.source_node) {
+        if source.direct_peers.remove(&forward.dest_node) {
+            log::info!("Peer {} fell back to relay for {}", forward.source_node, forward.dest_node);
+        }
+        source.relay_peers.insert(forward.dest_node.clone(), Instant::now());
+        // 按已验证的节点记录填写源IP，目标节点无需再查找源节点
+        if let Ok(ip) = source.virtual_ip.parse() {
+            forward.source_virtual_ip = ip;
+        }
+    }
+    drop(peers_guard);
+    
+    // 下一跳可能不支持入站数据包使用的编码，按其协商结果重新编码
+    let peers_guard = peers.read().await;
+    let (udp_socket, next_hop, payload_flags, connection_id) = match peers_guard.get(&forward.dest_node) {
+        Some(peer) if peer.paused => {
+            log::debug!("{} is paused, dropping forwarded packet", forward.dest_node);
+            return;
+        }
+        Some(peer) => (
+            sockets.for_peer(peer).clone(),
+            peer.send_address(),
+            peer.payload_flags,
+            peer.connection_id
+        ),
+        None => {
+            log::debug!("No route to {}, dropping forwarded packet", forward.dest_node);
+            return;
+        }
+    };
+    drop(peers_guard);
+    
+    if forward.compressed && payload_flags & constants::FLAG_COMPRESSED == 0 {
+        log::debug!("{} does not support compression, dropping forwarded packet", forward.dest_node);
+        return;
+    }
+    
+    let (forward_data, payload_flags) = match encode_data_forward(forward, payload_flags) {
+        Ok(encoded) => encoded,
+        Err(e) => {
+            log::warn!("Failed to encode relayed packet: {}", e);
+            return;
+        }
+    };
+    let relay_packet = PacketBuilder::new(MessageType::DataForward, forward_data)
+        .flags(payload_flags)
+        .connection_id(connection_id)
+        .build();
+    
+    // 源节点所在分组的带宽已用尽时排队，由分组带宽限制的发送任务稍后发出
+    let relay_packet = match bandwidth_limiter {
+        Some(limiter) => match limiter.admit(&forward.source_node, &forward.dest_node, relay_packet, forward.priority) {
+            Some(packet) => packet,
+            None => return,
+        },
+        None => relay_packet,
+    };
+    
+    if let Some(scheduler) = scheduler {
+        scheduler.enqueue(&forward.dest_node, relay_packet, forward.priority);
+        return;
+    }
+    
+    let relay_packet_data = match relay_packet.encode() {
+        Ok(data) => data,
+        Err(e) => {
+            log::warn!("Failed to encode relayed packet for {}: {}", forward.dest_node, e);
+            return;
+        }
+    };
+    if let Err(e) = udp_socket.send_to(&relay_packet_data, next_hop) {
+        log::warn!("Failed to relay packet to {}: {}", forward.dest_node, e);
+    }
+}
+
+/// 发送心跳包
+async fn send_heartbeat(
+    sockets: &Arc<SocketSet>,
+    node_id: &str,
+    peers: &Arc<RwLock<HashMap<String, Peer>>>,
+    extensions: &HashMap<String, serde_json::Value>
+) {
+    let timestamp_ms = current_unix_timestamp_millis();
+    let timestamp = timestamp_ms / 1000;
+    
+    // 先构建所有心跳包再发送，避免重试等待期间持有节点表的锁
+    let mut outgoing = Vec::new();
+    {
+        let peers_guard = peers.read().await;
+        for peer in peers_guard.values().filter(|peer| !peer.paused) {
+            // 每个节点的心跳携带本端观测到的该节点丢包率
+            let heartbeat = Heartbeat {
+                node_id: node_id.to_string(),
+                timestamp,
+                load: 0.0, // 实际应获取系统负载
+                uptime: 0, // 实际应获取系统运行时间
+                packet_loss_pct: peer.stats.packet_loss_pct,
+                extensions: extensions.clone(),
+                timestamp_ms,
+                echo: peer.last_heartbeat.map(|(timestamp_ms, received_at)| HeartbeatEcho {
+                    timestamp_ms,
+                    delay_ms: received_at.elapsed().as_millis() as u64,
+                }),
+            };
+            
+            let heartbeat_data = match serde_json::to_vec(&heartbeat) {
+                Ok(data) => data,
+                Err(e) => {
+                    log::warn!("Failed to encode heartbeat for {}: {}", peer.node_id, e);
+                    continue;
+                }
+            };
+            let packet = PacketBuilder::new(MessageType::Heartbeat, heartbeat_data)
+                .connection_id(peer.connection_id)
+                .build();
+            outgoing.push((peer.node_id.clone(), packet));
+        }
+    }
+    
+    // 各节点并发发送，与`send_with_retry`一样遇到可重试的错误时退避重试
+    let mut tasks = JoinSet::new();
+    for (peer_id, packet) in outgoing {
+        let sockets = sockets.clone();
+        let peers = peers.clone();
+        tasks.spawn(async move {
+            let result = send_to_peer_with_retry(&sockets, &peers, &peer_id, &packet, &RetryPolicy::default()).await;
+            (peer_id, result)
+        });
+    }
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((_, Ok(()))) => {}
+            Ok((peer_id, Err(e))) => log::warn!("Failed to send heartbeat to {}: {}", peer_id, e),
+            Err(e) => log::warn!("Heartbeat task failed: {}", e),
+        }
+    }
+}
+
+/// 一个待发送的数据：目标节点、发送使用的套接字、目标地址和编码后的数据包
+type Outgoing = (String, Arc<dyn DatagramSocket>, SocketAddr, Arc<Vec<u8>>);
+
+/// 并发向多个节点发送数据，每个节点在独立的任务中按策略重试
+async fn send_concurrently(
+    outgoing: Vec<Outgoing>,
+    policy: &RetryPolicy
+) -> BroadcastResult {
+    let mut tasks = JoinSet::new();
+    for (peer_id, udp_socket, address, data) in outgoing {
+        let policy = policy.clone();
+        tasks.spawn(async move {
+            let result = send_to_with_retry(&udp_socket, &data, address, &policy).await;
+            (peer_id, result)
+        });
+    }
+    
+    let mut result = BroadcastResult::default();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((_, Ok(()))) => result.sent += 1,
+            Ok((peer_id, Err(e))) => result.failed.push((peer_id, e)),
+            Err(e) => log::warn!("Broadcast task failed: {}", e),
+        }
+    }
+    
+    result
+}
+
+/// 向指定地址发送数据，遇到可重试的错误时按策略退避重试
+async fn send_to_with_retry(
+    udp_socket: &dyn DatagramSocket,
+    data: &[u8],
+    addr: SocketAddr,
+    policy: &RetryPolicy
+) -> Result<(), VpnetError> {
+    policy.retry(|| async {
+        udp_socket.send_to(data, addr).map(|_| ()).map_err(VpnetError::from)
+    }).await
+}
+
+/// 撤销超时的直连路径，回退到经服务端中继
+async fn expire_direct_paths(peers: &Arc<RwLock<HashMap<String, Peer>>>) {
+    let timeout = Duration::from_secs(constants::DIRECT_PATH_TIMEOUT);
+    let mut peers_guard = peers.write().await;
+    
+    for peer in peers_guard.values_mut() {
+        let expired = match peer.direct_path_seen {
+            Some(seen) => seen.elapsed() > timeout,
+            None => true,
+        };
+        if peer.direct_path.is_some() && expired {
+            log::warn!("Direct path to {} timed out, reverting to relay", peer.node_id);
+            peer.direct_path = None;
+            peer.direct_path_seen = None;
+            peer.multipath = None;
+        }
+    }
+}
+
+/// 移除空闲超时或目标节点已不在节点表中的中继会话
+async fn expire_relay_sessions(peers: &Arc<RwLock<HashMap<String, Peer>>>) {
+    let timeout = Duration::from_secs(constants::RELAY_SESSION_TIMEOUT);
+    let mut peers_guard = peers.write().await;
+    let known: HashSet<String> = peers_guard.keys().cloned().collect();
+    
+    for peer in peers_guard.values_mut() {
+        peer.relay_peers.retain(|dest, last_relayed| {
+            let keep = known.contains(dest) && last_relayed.elapsed() <= timeout;
+            if !keep {
+                log::debug!("Relay session from {} to {} ended", peer.node_id, dest);
+            }
+            keep
+        });
+    }
+}
+
+/// 根据上次采样以来的累计收发字节数更新各节点的速率
+async fn update_throughput(peers: &Arc<RwLock<HashMap<String, Peer>>>) {
+    let mut peers_guard = peers.write().await;
+    
+    for peer in peers_guard.values_mut() {
+        let total = peer.stats.bytes_sent + peer.stats.bytes_received;
+        let now = Instant::now();
+        if let Some((last_total, last_at)) = peer.rate_sample {
+            let elapsed = now.duration_since(last_at).as_secs_f64();
+            if elapsed > 0.0 {
+                peer.stats.bytes_per_sec = total.saturating_sub(last_total) as f64 / elapsed;
+            }
+        }
+        peer.rate_sample = Some((total, now));
+    }
+}
+
+/// 接受TCP隧道连接，将其中的数据报桥接到`target`
+async fn accept_tunnels(
+    listener: tokio::net::TcpListener,
+    target: SocketAddr,
+    config: TcpStreamConfig,
+    transport: Arc<dyn TransportFactory>
+) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::warn!("TCP tunnel accept error: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = config.apply(&stream) {
+            log::warn!("Failed to configure TCP tunnel from {}: {}", addr, e);
+        }
+        
+        let bridge = UdpTcpBridge::with_transport(stream, Some(target), transport.as_ref());
+        tokio::spawn(async move {
+            let bridge = match bridge {
+                Ok(bridge) => bridge,
+                Err(e) => {
+                    log::warn!("Failed to bridge TCP tunnel from {}: {}", addr, e);
+                    return;
+                }
+            };
+            log::info!("TCP tunnel from {} accepted", addr);
+            if let Err(e) = bridge.run().await {
+                log::debug!("TCP tunnel from {} closed: {}", addr, e);
+            }
+        });
+    }
+}
+
+/// 对各节点的累计统计采样，移除已不存在的节点的历史
+async fn sample_stats_history(
+    peers: &Arc<RwLock<HashMap<String, Peer>>>,
+    stats_history: &StatsHistory,
+    capacity: usize
+) {
+    let peers_guard = peers.read().await;
+    let mut history = stats_history.lock().await;
+    
+    history.retain(|node_id, _| peers_guard.contains_key(node_id));
+    for peer in peers_guard.values() {
+        history.entry(peer.node_id.clone())
+            .or_insert_with(|| RollingWindowStats::new(capacity))
+            .sample(&peer.stats);
+    }
+}
+
+/// 发送数据包到指定节点，遇到可重试的错误时按策略退避重试
+async fn send_to_peer_with_retry(
+    sockets: &SocketSet,
+    peers: &RwLock<HashMap<String, Peer>>,
+    peer_id: &str,
+    packet: &Packet,
+    policy: &RetryPolicy
+) -> Result<(), VpnetError> {
+    policy.retry(|| send_to_peer(sockets, peers, peer_id, packet)).await
+}
+
+/// 发送数据包到指定节点
+async fn send_to_peer(
+    sockets: &SocketSet,
+    peers: &RwLock<HashMap<String, Peer>>,
+    peer_id: &str,
+    packet: &Packet
+) -> Result<(), VpnetError> {
+    send_marked_to_peer(sockets, peers, peer_id, packet, 0).await
+}
+
+/// 以指定的DSCP标记发送数据包到指定节点，`dscp`为0时不标记
+async fn send_marked_to_peer(
+    sockets: &SocketSet,
+    peers: &RwLock<HashMap<String, Peer>>,
+    peer_id: &str,
+    packet: &Packet,
+    dscp: u8
+) -> Result<(), VpnetError> {
+    // DSCP占用TOS字节的高6位
+    let tos = dscp << 2;
+    let (udp_socket, address, multipath) = {
+        let peers_guard = peers.read().await;
+        let peer = peers_guard.get(peer_id)
+            .ok_or_else(|| VpnetError::PeerNotFound(peer_id.to_string()))?;
+        if peer.paused {
+            return Err(VpnetError::PeerPaused(peer_id.to_string()));
+        }
+        (sockets.for_peer(peer).clone(), peer.send_address(), peer.multipath.clone())
+    };
+    let data = packet.encode()?;
+    match multipath {
+        Some(forwarder) => forwarder.send_with_tos(&data, tos)?,
+        None if tos == 0 => {
+            udp_socket.send_to(&data, address)?;
+        }
+        None => {
+            udp_socket.send_to_with_tos(&data, address, tos)?;
+        }
+    }
+    Ok(())
+}
+
+/// 用新建的记录替换节点，保留运维设置的暂停状态和标签，节点重新握手不会解除暂停或丢失标签
+fn replace_peer(peers: &mut HashMap<String, Peer>, mut peer: Peer) {
+    if let Some(old) = peers.get(&peer.node_id) {
+        peer.paused = old.paused;
+        peer.paused_since = old.paused_since;
+        peer.tags = old.tags.clone();
+    }
+    peers.insert(peer.node_id.clone(), peer);
+}
+
+/// 按节点当前的直连路径和中继地址重建多路径转发器，未启用多路径或没有直连路径时清除
+fn refresh_multipath(peer: &mut Peer, sockets: &SocketSet) {
+    peer.multipath = match (sockets.multipath, peer.direct_path) {
+        (Some(strategy), Some(direct_path)) => {
+            let socket = sockets.get(peer.local_addr).clone();
+            let paths = vec![
+                ForwardPath::new(socket.clone(), direct_path),
+                ForwardPath::new(socket, peer.address),
+            ];
+            Some(Arc::new(MultiPathForwarder::new(paths, strategy)))
+        }
+        _ => None,
+    };
+}
+
+/// 恢复暂停超过`timeout`的节点
+async fn resume_expired_pauses(peers: &Arc<RwLock<HashMap<String, Peer>>>, timeout: Duration) {
+    let mut peers_guard = peers.write().await;
+    for peer in peers_guard.values_mut() {
+        if peer.paused_since.is_some_and(|since| since.elapsed() >= timeout) {
+            log::info!("Peer {} pause expired after {:?}, resuming", peer.node_id, timeout);
+            peer.paused = false;
+            peer.paused_since = None;
+        }
+    }
+}
+
+/// 清理超时节点，返回移除的节点数
+async fn cleanup_timeout_peers(
+    peers: &Arc<RwLock<HashMap<String, Peer>>>,
+    peer_events: &broadcast::Sender<PeerEvent>
+) -> usize {
+    let mut peers_guard = peers.write().await;
+    let now = current_unix_timestamp();
+    let before = peers_guard.len();
+    
+    peers_guard.retain(|_, peer| {
+        if now.saturating_sub(peer.last_seen) > constants::TIMEOUT {
+            log::info!("Removing timeout peer: {}", peer.node_id);
+            // 主动关闭的节点已经发送过断开事件；没有订阅者时发送失败，忽略即可
+            if peer.status != NodeStatus::Offline {
+                let _ = peer_events.send(PeerEvent::PeerDisconnected {
+                    node_id: peer.node_id.clone(),
+                    reason: "timeout".to_string(),
+                });
+            }
+            false
+        } else {
+            true
+        }
+    });
+    before - peers_guard.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compat::{CompatRule, CompatStatus};
+    
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+    
+    fn local_info(name: &str) -> NodeInfo {
+        build_local_info("node-1", name, &[1u8; 32], addr("192.0.2.1:51820"))
+    }
+    
+    fn decode_node_info(data: &[u8]) -> NodeInfo {
+        let packet = Packet::decode(data).unwrap();
+        assert_eq!(packet.msg_type, MessageType::NodeInfo);
+        serde_json::from_slice(&packet.data).unwrap()
+    }
+    
+    #[test]
+    fn node_info_cache_reuses_serialized_packet() {
+        let mut cache = NodeInfoCache::new(local_info("alpha")).unwrap();
+        let first = cache.response_for("198.51.100.1".parse().unwrap()).unwrap().to_vec();
+        let second = cache.response_for("198.51.100.2".parse().unwrap()).unwrap().to_vec();
+        assert_eq!(first, second);
+        
+        let info = decode_node_info(&first);
+        assert_eq!(info.node_name, "alpha");
+        assert_eq!(info.public_key, vec![1u8; 32]);
+    }
+    
+    #[test]
+    fn node_info_cache_rate_limits_per_source_ip() {
+        let mut cache = NodeInfoCache::new(local_info("alpha")).unwrap();
+        let source: IpAddr = "198.51.100.1".parse().unwrap();
+        assert!(cache.response_for(source).is_some());
+        assert!(cache.response_for(source).is_none());
+        assert!(cache.response_for("198.51.100.2".parse().unwrap()).is_some());
+        
+        // 超过限速窗口后再次响应
+        cache.last_responses.insert(source, Instant::now() - Duration::from_secs(constants::DISCOVERY_RATE_LIMIT));
+        assert!(cache.response_for(source).is_some());
+    }
+    
+    #[test]
+    fn node_info_cache_update_reserializes() {
+        let mut cache = NodeInfoCache::new(local_info("alpha")).unwrap();
+        assert!(!cache.is_stale());
+        
+        cache.update(local_info("beta"));
+        assert_eq!(cache.info().node_name, "beta");
+        let data = cache.response_for("198.51.100.1".parse().unwrap()).unwrap();
+        assert_eq!(decode_node_info(data).node_name, "beta");
+    }
+    
+    #[test]
+    fn node_info_cache_refresh_prunes_expired_rate_limit_entries() {
+        let mut cache = NodeInfoCache::new(local_info("alpha")).unwrap();
+        let expired = Instant::now() - Duration::from_secs(constants::DISCOVERY_RATE_LIMIT);
+        cache.last_responses.insert("198.51.100.1".parse().unwrap(), expired);
+        cache.last_responses.insert("198.51.100.2".parse().unwrap(), Instant::now());
+        
+        cache.refresh();
+        assert_eq!(cache.last_responses.len(), 1);
+    }
+    
+    fn server_info(node_id: &str, location: Option<(f64, f64)>) -> NodeInfo {
+        let mut info = build_local_info(node_id, node_id, &[1u8; 32], addr("192.0.2.1:51820"));
+        info.geographic_location = location.map(|(latitude, longitude)| GeoLocation {
+            latitude,
+            longitude,
+            country_code: String::new(),
+        });
+        info
+    }
+    
+    fn frankfurt() -> Option<GeoLocation> {
+        Some(GeoLocation { latitude: 50.1109, longitude: 8.6821, country_code: "DE".to_string() })
+    }
+    
+    #[test]
+    fn select_server_prefers_nearest() {
+        let candidates = vec![
+            server_info("tokyo", Some((35.6762, 139.6503))),
+            server_info("unknown", None),
+            server_info("paris", Some((48.8566, 2.3522))),
+        ];
+        let manager = ConnectionManager::new(frankfurt(), true);
+        assert_eq!(manager.select_server(&candidates).unwrap().node_id, "paris");
+        assert!(manager.distance_km(&candidates[1]).is_none());
+    }
+    
+    #[test]
+    fn select_server_keeps_order_without_prefer_nearest() {
+        let candidates = vec![
+            server_info("tokyo", Some((35.6762, 139.6503))),
+            server_info("paris", Some((48.8566, 2.3522))),
+        ];
+        assert_eq!(ConnectionManager::new(frankfurt(), false).select_server(&candidates).unwrap().node_id, "tokyo");
+        assert_eq!(ConnectionManager::new(None, true).select_server(&candidates).unwrap().node_id, "tokyo");
+        assert!(ConnectionManager::new(frankfurt(), true).select_server(&[]).is_none());
+    }
+    
+    #[tokio::test]
+    async fn query_node_info_reads_discovery_reply() {
+        let server = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let responder = std::thread::spawn(move || {
+            let mut cache = NodeInfoCache::new(server_info("server", Some((48.8566, 2.3522)))).unwrap();
+            let mut buf = [0u8; 1500];
+            let (len, from) = server.recv_from(&mut buf).unwrap();
+            assert_eq!(Packet::decode(&buf[..len]).unwrap().msg_type, MessageType::NodeDiscovery);
+            server.send_to(cache.response_for(from.ip()).unwrap(), from).unwrap();
+        });
+        
+        let info = query_node_info(server_addr, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(info.node_id, "server");
+        assert!(info.geographic_location.is_some());
+        responder.join().unwrap();
+    }
+    
+    #[tokio::test]
+    async fn query_node_info_times_out() {
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let result = query_node_info(silent.local_addr().unwrap(), Duration::from_millis(50)).await;
+        assert!(result.unwrap_err().is_retryable());
+    }
+    
+    #[test]
+    fn loss_estimator_reports_ten_percent_loss() {
+        use rand::{rngs::StdRng, SeedableRng};
+        
+        let mut rng = StdRng::seed_from_u64(1346);
+        let mut estimator = LossEstimator::new();
+        let mut samples = Vec::new();
+        for seq in 0..64_000u32 {
+            // 第一个数据包总是收到，作为窗口的起点
+            if seq == 0 || !rng.gen_bool(0.1) {
+                estimator.record(seq);
+            }
+            if seq % 64 == 63 {
+                samples.push(estimator.loss_rate());
+            }
+        }
+        
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        assert!((0.08..=0.12).contains(&mean), "estimated loss {}", mean);
+    }
+    
+    #[test]
+    fn loss_estimator_counts_every_tenth_packet_lost() {
+        let mut estimator = LossEstimator::new();
+        for seq in (0..1000u32).filter(|seq| seq % 10 != 9) {
+            estimator.record(seq);
+        }
+        let loss = estimator.loss_rate();
+        assert!((0.08..=0.12).contains(&loss), "estimated loss {}", loss);
+    }
+    
+    #[test]
+    fn loss_estimator_handles_reordering_and_duplicates() {
+        let mut estimator = LossEstimator::new();
+        assert!(estimator.record(10));
+        assert!(estimator.record(12));
+        assert!((estimator.loss_rate() - 1.0 / 3.0).abs() < 1e-9);
+        
+        // 迟到的数据包补记，不再计为丢失
+        assert!(estimator.record(11));
+        assert_eq!(estimator.loss_rate(), 0.0);
+        assert!(!estimator.record(11));
+        assert_eq!(estimator.highest_seq(), Some(12));
+        assert_eq!(estimator.bitmap() & 0b111, 0b111);
+    }
+    
+    #[test]
+    fn loss_estimator_handles_sequence_wraparound() {
+        let mut estimator = LossEstimator::new();
+        estimator.record(u32::MAX - 1);
+        estimator.record(u32::MAX);
+        estimator.record(0);
+        estimator.record(2);
+        assert_eq!(estimator.highest_seq(), Some(2));
+        assert!((estimator.loss_rate() - 0.2).abs() < 1e-9);
+    }
+    
+    
+    /// 前`failures`次发送返回`WouldBlock`的模拟传输，记录发送次数
+    struct MockTransport {
+        failures: u32,
+        sends: Arc<AtomicUsize>,
+    }
+    
+    struct MockSocket {
+        addr: SocketAddr,
+        failures: u32,
+        sends: Arc<AtomicUsize>,
+    }
+    
+    impl TransportFactory for MockTransport {
+        fn bind(&self, addr: SocketAddr) -> std::io::Result<Arc<dyn DatagramSocket>> {
+            Ok(Arc::new(MockSocket { addr, failures: self.failures, sends: self.sends.clone() }))
+        }
+    }
+    
+    impl DatagramSocket for MockSocket {
+        fn send_to(&self, buf: &[u8], _addr: SocketAddr) -> std::io::Result<usize> {
+            if self.sends.fetch_add(1, Ordering::SeqCst) < self.failures as usize {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            Ok(buf.len())
+        }
+        
+        fn recv_from(&self, _buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+            Err(std::io::ErrorKind::WouldBlock.into())
+        }
+        
+        fn local_addr(&self) -> std::io::Result<SocketAddr> {
+            Ok(self.addr)
+        }
+    }
+    
+    /// 测试中节点`peer`与本节点的会话密钥
+    const PEER_SESSION_KEY: [u8; 32] = [0x42; 32];
+    
+    /// 节点`peer`一方的加密上下文，由其发起握手
+    fn peer_crypto() -> CryptoContext {
+        CryptoContext::for_session(&PEER_SESSION_KEY, true).unwrap()
+    }
+    
+    /// 连接了节点`peer`的网络管理器，前`failures`次发送失败
+    async fn mock_network_manager(failures: u32) -> (NetworkManager, Arc<AtomicUsize>) {
+        let sends = Arc::new(AtomicUsize::new(0));
+        let transport = Arc::new(MockTransport { failures, sends: sends.clone() });
+        let manager = NetworkManager::with_transport(
+            vec![addr("192.0.2.1:51820")],
+            "node-1".to_string(),
+            "node-1".to_string(),
+            vec![1u8; 32],
+            transport
+        ).unwrap();
+        let mut peer = Peer::new(
+            "peer".to_string(),
+            "peer".to_string(),
+            addr("192.0.2.2:51820"),
+            "10.0.0.2".to_string(),
+            vec![2u8; 32],
+            0
+        );
+        peer.set_session_key(PEER_SESSION_KEY.to_vec(), false).unwrap();
+        manager.peers.write().await.insert("peer".to_string(), peer);
+        (manager, sends)
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn send_with_retry_succeeds_on_third_attempt() {
+        let (manager, sends) = mock_network_manager(2).await;
+        let packet = PacketBuilder::new(MessageType::Heartbeat, Vec::new()).build();
+        manager.send_with_retry("peer", &packet, &RetryPolicy::default()).await.unwrap();
+        assert_eq!(sends.load(Ordering::SeqCst), 3);
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn send_with_retry_gives_up_after_max_attempts() {
+        let (manager, sends) = mock_network_manager(3).await;
+        let packet = PacketBuilder::new(MessageType::Heartbeat, Vec::new()).build();
+        let result = manager.send_with_retry("peer", &packet, &RetryPolicy::default()).await;
+        assert!(matches!(result, Err(VpnetError::Retry { .. })));
+        assert_eq!(sends.load(Ordering::SeqCst), 3);
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn heartbeat_retries_transient_send_failures() {
+        let (manager, sends) = mock_network_manager(2).await;
+        send_heartbeat(&manager.sockets, "node-1", &manager.peers, &HashMap::new()).await;
+        assert_eq!(sends.load(Ordering::SeqCst), 3);
+    }
+    
+    /// 在`mock_network_manager`的基础上再连接`count`个节点
+    async fn add_mock_peers(manager: &NetworkManager, count: u8) {
+        let mut peers = manager.peers.write().await;
+        for i in 0..count {
+            let node_id = format!("peer-{}", i);
+            let peer = Peer::new(
+                node_id.clone(),
+                node_id.clone(),
+                addr(&format!("192.0.2.{}:51820", 10 + i)),
+                format!("10.0.0.{}", 10 + i),
+                vec![3u8; 32],
+                0
+            );
+            peers.insert(node_id, peer);
+        }
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn broadcast_reaches_every_peer() {
+        let (manager, sends) = mock_network_manager(0).await;
+        add_mock_peers(&manager, 2).await;
+        let packet = PacketBuilder::new(MessageType::Heartbeat, Vec::new()).build();
+        let result = manager.broadcast(&packet).await;
+        assert_eq!(result.sent, 3);
+        assert!(result.failed.is_empty());
+        assert_eq!(sends.load(Ordering::SeqCst), 3);
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn broadcast_reports_each_failed_peer() {
+        let (manager, sends) = mock_network_manager(u32::MAX).await;
+        add_mock_peers(&manager, 2).await;
+        let packet = PacketBuilder::new(MessageType::Heartbeat, Vec::new()).build();
+        let result = manager.broadcast(&packet).await;
+        assert_eq!(result.sent, 0);
+        let mut failed: Vec<&str> = result.failed.iter().map(|(peer_id, _)| peer_id.as_str()).collect();
+        failed.sort();
+        assert_eq!(failed, vec!["peer", "peer-0", "peer-1"]);
+        assert!(result.failed.iter().all(|(_, e)| matches!(e, VpnetError::Retry { .. })));
+        assert_eq!(sends.load(Ordering::SeqCst), 9);
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn watchdog_restarts_stuck_task_within_two_intervals() {
+        let interval = Duration::from_secs(5);
+        let starts = Arc::new(AtomicUsize::new(0));
+        let counter = starts.clone();
+        let supervisor = Watchdog::new(interval).supervise("stuck", move |keepalive| {
+            let counter = counter.clone();
+            Box::pin(async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let _ = keepalive.try_send(());
+                // 报告一次存活后永远卡住
+                std::future::pending::<()>().await;
+            })
+        });
+        
+        tokio::time::sleep(interval * 2).await;
+        assert!(starts.load(Ordering::SeqCst) >= 2);
+        supervisor.abort();
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn stopping_manager_aborts_supervised_tasks() {
+        let (manager, _) = mock_network_manager(0).await;
+        let alive = Arc::new(());
+        let held = alive.clone();
+        manager.supervise("holder", move |keepalive| {
+            let held = held.clone();
+            Box::pin(async move {
+                let _held = held;
+                loop {
+                    let _ = keepalive.try_send(());
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            })
+        });
+        
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        assert_eq!(Arc::strong_count(&alive), 3);
+        manager.stop();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(Arc::strong_count(&alive), 1);
+    }
+    
+    /// 网络管理器第一个监听套接字上的数据包处理上下文
+    fn mock_context(manager: &NetworkManager) -> HandlerContext {
+        let (local_addr, udp_socket) = &manager.sockets.sockets[0];
+        manager.handler_context(udp_socket.clone(), *local_addr)
+    }
+    
+    fn direct_path_probe(node_id: &str, token: [u8; 16]) -> Packet {
+        let probe = DirectPathProbe { node_id: node_id.to_string(), token };
+        PacketBuilder::new(MessageType::DirectPathProbe, serde_json::to_vec(&probe).unwrap()).build()
+    }
+    
+    #[tokio::test]
+    async fn direct_path_probe_requires_matching_offer() {
+        let (manager, _) = mock_network_manager(0).await;
+        let ctx = mock_context(&manager);
+        let offered = addr("198.51.100.7:4000");
+        
+        // 没有提议时的探测被忽略
+        handle_direct_path_probe(direct_path_probe("peer", [5u8; 16]), offered, &ctx).await;
+        assert_eq!(manager.get_peer("peer").await.unwrap().direct_path, None);
+        
+        ctx.path_offers.lock().await.insert("peer".to_string(), PendingPathOffer {
+            signaling_addr: addr("192.0.2.9:51820"),
+            address: offered,
+            token: [5u8; 16],
+        });
+        
+        // 令牌不符或来自其他地址的探测被忽略
+        handle_direct_path_probe(direct_path_probe("peer", [6u8; 16]), offered, &ctx).await;
+        handle_direct_path_probe(direct_path_probe("peer", [5u8; 16]), addr("203.0.113.1:4000"), &ctx).await;
+        assert_eq!(manager.get_peer("peer").await.unwrap().direct_path, None);
+        
+        handle_direct_path_probe(direct_path_probe("peer", [5u8; 16]), offered, &ctx).await;
+        assert_eq!(manager.get_peer("peer").await.unwrap().direct_path, Some(offered));
+        assert!(ctx.path_offers.lock().await.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn ip_assignment_only_accepted_from_upstream() {
+        let (manager, _) = mock_network_manager(0).await;
+        let ctx = mock_context(&manager);
+        let mut assignments = manager.subscribe_ip_assignments();
+        let assignment = IpAssignment { virtual_ip: "10.0.0.9".to_string(), subnet: "255.255.255.0".to_string() };
+        let packet = PacketBuilder::new(MessageType::IpAssignment, serde_json::to_vec(&assignment).unwrap()).build();
+        
+        // 普通节点下发的分配被忽略
+        handle_ip_assignment(packet.clone(), addr("192.0.2.2:51820"), ctx.peers.clone(), ctx.ip_assignments.clone()).await;
+        assert!(assignments.try_recv().is_err());
+        
+        manager.peers.write().await.get_mut("peer").unwrap().upstream = true;
+        handle_ip_assignment(packet, addr("192.0.2.2:51820"), ctx.peers.clone(), ctx.ip_assignments.clone()).await;
+        assert_eq!(assignments.try_recv().unwrap().virtual_ip, "10.0.0.9");
+    }
+    
+    /// 只允许`peer`访问`peer-0`，不允许反向访问
+    struct OneWayFilter;
+    
+    impl ForwardFilter for OneWayFilter {
+        fn allow<'a>(&'a self, source_node: &'a str, dest_node: &'a str) -> BoxFuture<'a, bool> {
+            Box::pin(async move { source_node == "peer" && dest_node == "peer-0" })
+        }
+    }
+    
+    #[tokio::test]
+    async fn direct_path_requires_forwarding_in_both_directions() {
+        let (mut manager, sends) = mock_network_manager(0).await;
+        add_mock_peers(&manager, 1).await;
+        manager.offer_direct_path("peer", "peer-0").await.unwrap();
+        assert_eq!(sends.load(Ordering::SeqCst), 2);
+        
+        manager.set_forward_filter(Arc::new(OneWayFilter));
+        assert!(manager.offer_direct_path("peer", "peer-0").await.is_err());
+        assert_eq!(sends.load(Ordering::SeqCst), 2);
+    }
+    
+    /// 绑定在`addrs`上、以`mode`聚合的套接字集合
+    fn bonded_sockets(addrs: &[SocketAddr], mode: BondingMode, failover_timeout: Duration) -> SocketSet {
+        let sockets: Vec<(SocketAddr, Arc<dyn DatagramSocket>)> = addrs.iter()
+            .map(|&local| {
+                let socket: Arc<dyn DatagramSocket> = Arc::new(MockSocket { addr: local, failures: 0, sends: Arc::new(AtomicUsize::new(0)) });
+                (local, socket)
+            })
+            .collect();
+        let links = sockets.iter()
+            .enumerate()
+            .map(|(i, (local, socket))| (format!("eth{}", i), *local, socket.clone()))
+            .collect();
+        SocketSet { sockets, bond: Some(LinkAggregation::new(links, mode, failover_timeout)), multipath: None }
+    }
+    
+    #[test]
+    fn bonded_sends_use_the_local_address_of_the_peer() {
+        let locals = [addr("192.0.2.1:51820"), addr("198.51.100.1:51820")];
+        let sockets = bonded_sockets(&locals, BondingMode::RoundRobin, Duration::from_secs(5));
+        let mut peer = Peer::new(
+            "peer".to_string(),
+            "peer".to_string(),
+            addr("203.0.113.2:51820"),
+            "10.0.0.2".to_string(),
+            vec![2u8; 32],
+            0
+        );
+        peer.local_addr = Some(locals[1]);
+        
+        for _ in 0..4 {
+            assert_eq!(sockets.for_peer(&peer).local_addr().unwrap(), locals[1]);
+        }
+        let stats = sockets.bond.as_ref().unwrap().stats();
+        assert_eq!((stats[0].packets_sent, stats[1].packets_sent), (0, 4));
+        
+        // 新节点在链路间轮流分配
+        assert_eq!(sockets.for_handshake(None).local_addr().unwrap(), locals[0]);
+        assert_eq!(sockets.for_handshake(None).local_addr().unwrap(), locals[1]);
+    }
+    
+    #[test]
+    fn active_backup_fails_over_when_the_active_link_times_out() {
+        let locals = [addr("192.0.2.1:51820"), addr("198.51.100.1:51820")];
+        let sockets = bonded_sockets(&locals, BondingMode::ActiveBackup, Duration::from_millis(1));
+        let bond = sockets.bond.as_ref().unwrap();
+        assert_eq!(sockets.for_handshake(None).local_addr().unwrap(), locals[0]);
+        
+        std::thread::sleep(Duration::from_millis(10));
+        bond.check_links();
+        
+        assert_eq!(bond.active_interface(), Some("eth1"));
+        let stats = bond.stats();
+        assert!(!stats[0].up && !stats[0].active);
+        assert_eq!(stats[0].failures, 1);
+        assert!(stats[1].up && stats[1].active);
+        
+        // 关联在失效链路上的节点重新握手时改用备用链路
+        assert_eq!(sockets.for_handshake(Some(locals[0])).local_addr().unwrap(), locals[1]);
+        assert_eq!(sockets.for_handshake(Some(locals[1])).local_addr().unwrap(), locals[1]);
+        
+        // 失效链路收到数据后恢复可用，但不自动切回
+        bond.record_received(locals[0], 64);
+        assert!(bond.stats()[0].up);
+        assert_eq!(bond.active_interface(), Some("eth1"));
+    }
+    
+    #[tokio::test]
+    async fn closed_peer_is_not_reported_again_on_timeout() {
+        let (manager, _) = mock_network_manager(0).await;
+        add_mock_peers(&manager, 1).await;
+        let mut events = manager.subscribe_peer_events();
+        {
+            let mut peers = manager.peers.write().await;
+            for peer in peers.values_mut() {
+                peer.last_seen = 0;
+            }
+            peers.get_mut("peer").unwrap().status = NodeStatus::Offline;
+        }
+        
+        assert_eq!(cleanup_timeout_peers(&manager.peers, &manager.peer_events).await, 2);
+        match events.try_recv() {
+            Ok(PeerEvent::PeerDisconnected { node_id, reason }) => {
+                assert_eq!(node_id, "peer-0");
+                assert_eq!(reason, "timeout");
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(events.try_recv().is_err());
+    }
+    
+    fn data_packet(len: usize) -> Packet {
+        PacketBuilder::new(MessageType::DataForward, vec![0u8; len]).build()
+    }
+    
+    #[tokio::test]
+    async fn wfq_shares_bandwidth_by_weight_under_load() {
+        let scheduler = WfqScheduler::new();
+        scheduler.set_weight("heavy", 3);
+        for _ in 0..100 {
+            scheduler.enqueue("light", data_packet(1000), 0);
+            scheduler.enqueue("heavy", data_packet(1000), 0);
+        }
+        
+        let mut sent = HashMap::new();
+        for _ in 0..80 {
+            let (peer_id, _, _) = scheduler.next_packet().await;
+            *sent.entry(peer_id).or_insert(0) += 1;
+        }
+        assert_eq!(sent["heavy"], 60);
+        assert_eq!(sent["light"], 20);
+    }
+    
+    #[tokio::test]
+    async fn wfq_does_not_starve_a_flow_behind_a_backlog() {
+        let scheduler = WfqScheduler::new();
+        for _ in 0..100 {
+            scheduler.enqueue("bulk", data_packet(1400), 0);
+        }
+        for _ in 0..10 {
+            scheduler.next_packet().await;
+        }
+        
+        // 新到达的节点从当前虚拟时间开始计算，不需要等待积压的数据包发完
+        scheduler.enqueue("interactive", data_packet(100), 0);
+        let next: Vec<String> = [scheduler.next_packet().await, scheduler.next_packet().await]
+            .into_iter()
+            .map(|(peer_id, _, _)| peer_id)
+            .collect();
+        assert!(next.contains(&"interactive".to_string()));
+    }
+    
+    #[tokio::test]
+    async fn priority_scheduler_sends_higher_priority_first() {
+        let scheduler = PriorityScheduler::new();
+        scheduler.enqueue("bulk", data_packet(100), 6);
+        scheduler.enqueue("bulk", data_packet(100), 6);
+        scheduler.enqueue("voice", data_packet(100), 0);
+        
+        let order: Vec<u8> = [
+            scheduler.next_packet().await,
+            scheduler.next_packet().await,
+            scheduler.next_packet().await,
+        ].into_iter().map(|(_, _, priority)| priority).collect();
+        assert_eq!(order, vec![0, 6, 6]);
+    }
+    
+    #[tokio::test]
+    async fn link_pacer_holds_packets_until_tokens_are_available() {
+        // 每秒150 000字节，令牌桶容量为一个完整的数据包
+        let mut pacer = link_pacer(150_000);
+        assert_eq!(pacer.burst(), MAX_PACKET_SIZE as u64);
+        
+        let start = Instant::now();
+        wait_for_tokens(&mut pacer, MAX_PACKET_SIZE as u64).await;
+        assert!(start.elapsed() < Duration::from_millis(5));
+        
+        assert!(pacer.consume(MAX_PACKET_SIZE as u64).is_ready());
+        wait_for_tokens(&mut pacer, MAX_PACKET_SIZE as u64).await;
+        assert!(start.elapsed() >= Duration::from_millis(9));
+    }
+    
+    fn handshake_request(node_id: &str, public_key: Vec<u8>) -> Packet {
+        let req = HandshakeRequest {
+            version: PROTOCOL_VERSION,
+            public_key,
+            node_id: node_id.to_string(),
+            node_name: node_id.to_string(),
+            supported_protocols: vec![PROTOCOL_VERSION],
+            capabilities: 0,
+            server_nonce: None,
+            virtual_ip: None,
+            group_id: None,
+            session_ticket: None,
+            client_version: crate::VERSION.to_string(),
+            ephemeral_key: None,
+        };
+        PacketBuilder::new(MessageType::HandshakeRequest, serde_json::to_vec(&req).unwrap()).build()
+    }
+    
+    #[tokio::test]
+    async fn handshake_from_new_address_requires_signed_migration() {
+        let (manager, _) = mock_network_manager(0).await;
+        let ctx = mock_context(&manager);
+        let key_pair = KeyPair::generate().unwrap();
+        let original = addr("192.0.2.2:51820");
+        let roamed = addr("198.51.100.9:4000");
+        {
+            let mut peers = manager.peers.write().await;
+            let peer = peers.get_mut("peer").unwrap();
+            peer.public_key = key_pair.public_key.clone();
+            peer.session_key = vec![1u8; 32];
+            // 超过宽限期但仍在线的节点同样需要验证
+            peer.last_seen = 0;
+        }
+        
+        // 只出示公钥的握手不会改写地址
+        handle_handshake_request(handshake_request("peer", key_pair.public_key.clone()), roamed, &ctx).await.unwrap();
+        let peer = manager.get_peer("peer").await.unwrap();
+        assert_eq!(peer.address, original);
+        assert_eq!(peer.session_key, vec![1u8; 32]);
+        
+        let (nonce, session_key) = {
+            let pending = ctx.pending_migrations.lock().await;
+            assert_eq!(pending.len(), 1);
+            let (nonce, migration) = pending.iter().next().unwrap();
+            assert_eq!(migration.address, roamed);
+            (*nonce, migration.session_key.clone().unwrap())
+        };
+        
+        // 签名错误的回复被拒绝
+        let forged = MigrationResponse { nonce, signature: vec![0u8; 64] };
+        let packet = PacketBuilder::new(MessageType::MigrationResponse, serde_json::to_vec(&forged).unwrap()).build();
+        handle_migration_response(packet, roamed, &ctx).await;
+        assert_eq!(manager.get_peer("peer").await.unwrap().address, original);
+        
+        // 重新握手后用节点的私钥签名回复，地址和会话密钥一起更新
+        handle_handshake_request(handshake_request("peer", key_pair.public_key.clone()), roamed, &ctx).await.unwrap();
+        let (nonce, session_key) = {
+            let pending = ctx.pending_migrations.lock().await;
+            let (nonce, migration) = pending.iter().next().unwrap();
+            assert_ne!(migration.session_key.as_ref(), Some(&session_key));
+            (*nonce, migration.session_key.clone().unwrap())
+        };
+        let response = MigrationResponse {
+            nonce,
+            signature: key_pair.sign(&MigrationResponse::signing_payload(&nonce, "peer")),
+        };
+        let packet = PacketBuilder::new(MessageType::MigrationResponse, serde_json::to_vec(&response).unwrap()).build();
+        handle_migration_response(packet, roamed, &ctx).await;
+        let peer = manager.get_peer("peer").await.unwrap();
+        assert_eq!(peer.address, roamed);
+        assert_eq!(peer.session_key, session_key);
+    }
+    
+    #[tokio::test]
+    async fn paused_peer_stays_paused_after_rehandshake() {
+        let (manager, _) = mock_network_manager(0).await;
+        let ctx = mock_context(&manager);
+        manager.pause_peer("peer").await.unwrap();
+        let paused_since = manager.get_peer("peer").await.unwrap().paused_since;
+        
+        // 同一地址重新握手和更换密钥后的握手都会新建记录
+        handle_handshake_request(handshake_request("peer", vec![2u8; 32]), addr("192.0.2.2:51820"), &ctx).await.unwrap();
+        let peer = manager.get_peer("peer").await.unwrap();
+        assert_eq!(peer.public_key, vec![2u8; 32]);
+        assert!(peer.paused);
+        assert_eq!(peer.paused_since, paused_since);
+        assert!(matches!(
+            manager.send_packet("peer", &data_packet(16)).await,
+            Err(VpnetError::PeerPaused(_))
+        ));
+        
+        manager.resume_peer("peer").await.unwrap();
+        handle_handshake_request(handshake_request("peer", vec![3u8; 32]), addr("192.0.2.2:51820"), &ctx).await.unwrap();
+        assert!(!manager.get_peer("peer").await.unwrap().paused);
+    }
+    
+    #[tokio::test]
+    async fn peer_tags_survive_rehandshake() {
+        let (manager, _) = mock_network_manager(0).await;
+        let ctx = mock_context(&manager);
+        let tags: HashMap<String, String> = [("site".to_string(), "berlin".to_string())].into();
+        manager.set_peer_tags("peer", tags.clone()).await.unwrap();
+        
+        handle_handshake_request(handshake_request("peer", vec![3u8; 32]), addr("192.0.2.2:51820"), &ctx).await.unwrap();
+        let peer = manager.get_peer("peer").await.unwrap();
+        assert_eq!(peer.public_key, vec![3u8; 32]);
+        assert_eq!(peer.tags, tags);
+    }
+    
+    #[tokio::test]
+    async fn pending_migrations_are_capped() {
+        let (manager, _) = mock_network_manager(0).await;
+        let ctx = mock_context(&manager);
+        for port in 0..constants::MAX_PENDING_MIGRATIONS as u16 {
+            let issued = pending_migration_challenge("peer", SocketAddr::new([198, 51, 100, 9].into(), 1000 + port), None, &ctx).await;
+            assert!(matches!(issued, Some((_, true))));
+        }
+        
+        assert!(pending_migration_challenge("peer", addr("203.0.113.1:4000"), None, &ctx).await.is_none());
+        // 已有的验证仍然可以沿用
+        assert!(matches!(
+            pending_migration_challenge("peer", addr("198.51.100.9:1000"), None, &ctx).await,
+            Some((_, false))
+        ));
+        assert_eq!(ctx.pending_migrations.lock().await.len(), constants::MAX_PENDING_MIGRATIONS);
+    }
+    
+    #[tokio::test]
+    async fn relay_sessions_expire_when_idle_or_target_leaves() {
+        let (manager, _) = mock_network_manager(0).await;
+        add_mock_peers(&manager, 2).await;
+        let idle = Instant::now().checked_sub(Duration::from_secs(constants::RELAY_SESSION_TIMEOUT + 1)).unwrap();
+        {
+            let mut peers = manager.peers.write().await;
+            let relay_peers = &mut peers.get_mut("peer").unwrap().relay_peers;
+            relay_peers.insert("peer-0".to_string(), Instant::now());
+            relay_peers.insert("peer-1".to_string(), idle);
+            relay_peers.insert("departed".to_string(), Instant::now());
+        }
+        
+        expire_relay_sessions(&manager.peers).await;
+        let relay_peers = manager.get_peer("peer").await.unwrap().relay_peers;
+        assert_eq!(relay_peers.keys().collect::<Vec<_>>(), vec!["peer-0"]);
+        
+        manager.remove_peer("peer-0").await;
+        expire_relay_sessions(&manager.peers).await;
+        assert!(manager.get_peer("peer").await.unwrap().relay_peers.is_empty());
+    }
+    
+    /// 以节点`peer`的会话密钥加密、来自`source_node`的数据转发
+    fn encrypted_forward(source_node: &str, source_ip: Ipv4Addr) -> Packet {
+        let data = peer_crypto().encrypt(&[0x45; 20], &[]).unwrap();
+        forward_packet(source_node, source_ip, 0, data)
+    }
+    
+    /// 来自`source_node`、序列号为`seq`的数据转发，`data`为密文
+    fn forward_packet(source_node: &str, source_ip: Ipv4Addr, seq: u32, data: Vec<u8>) -> Packet {
+        let forward = DataForward {
+            source_node: source_node.to_string(),
+            dest_node: "node-1".to_string(),
+            data,
+            protocol: 0x0800,
+            ttl: constants::DEFAULT_TTL,
+            seq,
+            priority: constants::DEFAULT_DATA_PRIORITY,
+            compressed: false,
+            source_virtual_ip: source_ip,
+            ack_requested: false,
+        };
+        let (data, flags) = encode_data_forward(&forward, constants::FLAG_RAW_FRAME | constants::FLAG_SOURCE_IP).unwrap();
+        PacketBuilder::new(MessageType::DataForward, data).flags(flags).build()
+    }
+    
+    /// 处理一个数据转发，返回是否写入了虚拟设备
+    async fn deliver(manager: &NetworkManager, packet: Packet, from: SocketAddr) -> bool {
+        let (device_tx, mut device_rx) = mpsc::channel(1);
+        handle_data_forward(
+            packet,
+            from,
+            manager.sockets.clone(),
+            manager.peers.clone(),
+            "node-1".to_string(),
+            None, None, None, None, None, None,
+            Some(device_tx),
+            &PacketSizeHistogram::new(),
+            DeviceMode::Tun
+        ).await;
+        device_rx.try_recv().is_ok()
+    }
+    
+    #[tokio::test]
+    async fn data_from_nodes_without_a_session_is_dropped() {
+        let (manager, _) = mock_network_manager(0).await;
+        let spoofed = Ipv4Addr::new(10, 0, 0, 99);
+        
+        // 未知源节点直接发来的数据包
+        let packet = encrypted_forward("stranger", spoofed);
+        assert!(!deliver(&manager, packet, addr("203.0.113.5:51820")).await);
+        
+        // 经上游服务端中继也无法解密
+        manager.peers.write().await.get_mut("peer").unwrap().upstream = true;
+        let packet = encrypted_forward("stranger", spoofed);
+        assert!(!deliver(&manager, packet, addr("192.0.2.2:51820")).await);
+        
+        // 已知但尚未完成握手的节点
+        manager.peers.write().await.get_mut("peer").unwrap().crypto = None;
+        let packet = encrypted_forward("peer", Ipv4Addr::new(10, 0, 0, 2));
+        assert!(!deliver(&manager, packet, addr("192.0.2.2:51820")).await);
+    }
+    
+    #[tokio::test]
+    async fn data_encrypted_under_another_key_is_dropped() {
+        let (manager, _) = mock_network_manager(0).await;
+        let data = CryptoContext::for_session(&[0x24; 32], true).unwrap().encrypt(&[0x45; 20], &[]).unwrap();
+        let packet = forward_packet("peer", Ipv4Addr::new(10, 0, 0, 2), 0, data);
+        assert!(!deliver(&manager, packet, addr("192.0.2.2:51820")).await);
+        assert_eq!(manager.crypto_stats().await.decrypt_failures, 1);
+    }
+    
+    #[tokio::test]
+    async fn known_source_must_match_its_recorded_ip() {
+        let (manager, _) = mock_network_manager(0).await;
+        let packet = encrypted_forward("peer", Ipv4Addr::new(10, 0, 0, 99));
+        assert!(!deliver(&manager, packet, addr("192.0.2.2:51820")).await);
+        
+        let packet = encrypted_forward("peer", Ipv4Addr::new(10, 0, 0, 2));
+        assert!(deliver(&manager, packet, addr("192.0.2.2:51820")).await);
+    }
+    
+    #[tokio::test]
+    async fn silent_peer_times_out_without_waiting() {
+        crate::utils::mock_clock::set(1_000_000);
+        let (manager, _) = mock_network_manager(0).await;
+        let (events, mut rx) = broadcast::channel(4);
+        
+        crate::utils::mock_clock::advance(constants::TIMEOUT);
+        assert_eq!(cleanup_timeout_peers(&manager.peers, &events).await, 0);
+        
+        crate::utils::mock_clock::advance(1);
+        assert_eq!(cleanup_timeout_peers(&manager.peers, &events).await, 1);
+        assert!(manager.get_peer("peer").await.is_none());
+        assert!(matches!(rx.try_recv(), Ok(PeerEvent::PeerDisconnected { reason, .. }) if reason == "timeout"));
+        crate::utils::mock_clock::reset();
+    }
+    
+    fn route_update(key_pair: &KeyPair, node_id: &str, networks: &[&str]) -> RouteUpdate {
+        let routes = networks.iter()
+            .map(|network| {
+                let cidr: IpCidr = network.parse().unwrap();
+                RouteEntry {
+                    network: cidr.addr.to_string(),
+                    mask: cidr.netmask().to_string(),
+                    gateway: "10.0.0.2".to_string(),
+                    metric: 1,
+                }
+            })
+            .collect();
+        RouteUpdate::signed(node_id, routes, key_pair, current_unix_timestamp())
+    }
+    
+    fn route_update_packet(update: &RouteUpdate) -> Packet {
+        PacketBuilder::new(MessageType::RouteUpdate, serde_json::to_vec(update).unwrap()).build()
+    }
+    
+    async fn routed_via(manager: &NetworkManager, network: &str) -> Option<String> {
+        let destination: IpCidr = network.parse().unwrap();
+        manager.routing.read().await.table(MAIN_TABLE)?
+            .iter()
+            .find(|route| route.destination == destination)
+            .map(|route| route.next_hop.clone())
+    }
+    
+    #[tokio::test]
+    async fn route_update_requires_a_valid_signature_from_a_known_node() {
+        let (manager, _) = mock_network_manager(0).await;
+        let ctx = mock_context(&manager);
+        let key_pair = KeyPair::generate().unwrap();
+        manager.peers.write().await.get_mut("peer").unwrap().public_key = key_pair.public_key.clone();
+        let from = addr("192.0.2.2:51820");
+        
+        // 签名后改动的路由被拒绝
+        let mut tampered = route_update(&key_pair, "peer", &["10.0.0.2/32"]);
+        tampered.routes[0].metric = 0;
+        handle_route_update(route_update_packet(&tampered), from, &ctx).await;
+        assert_eq!(routed_via(&manager, "10.0.0.2/32").await, None);
+        
+        // 未握手的节点即使签名正确也被拒绝
+        let stranger = KeyPair::generate().unwrap();
+        handle_route_update(route_update_packet(&route_update(&stranger, "stranger", &["10.0.0.9/32"])), from, &ctx).await;
+        assert_eq!(routed_via(&manager, "10.0.0.9/32").await, None);
+        
+        handle_route_update(route_update_packet(&route_update(&key_pair, "peer", &["10.0.0.2/32"])), from, &ctx).await;
+        assert_eq!(routed_via(&manager, "10.0.0.2/32").await.as_deref(), Some("peer"));
+    }
+    
+    #[tokio::test]
+    async fn route_update_is_limited_to_allowed_prefixes() {
+        let (manager, _) = mock_network_manager(0).await;
+        let ctx = mock_context(&manager);
+        let key_pair = KeyPair::generate().unwrap();
+        manager.peers.write().await.get_mut("peer").unwrap().public_key = key_pair.public_key.clone();
+        let from = addr("192.0.2.2:51820");
+        
+        // 正确签名的默认路由同样被拒绝，同一更新中的合法条目也不生效
+        handle_route_update(route_update_packet(&route_update(&key_pair, "peer", &["10.0.0.2/32", "0.0.0.0/0"])), from, &ctx).await;
+        assert_eq!(routed_via(&manager, "0.0.0.0/0").await, None);
+        assert_eq!(routed_via(&manager, "10.0.0.2/32").await, None);
+        
+        handle_route_update(route_update_packet(&route_update(&key_pair, "peer", &["192.168.5.0/24"])), from, &ctx).await;
+        assert_eq!(routed_via(&manager, "192.168.5.0/24").await, None);
+        
+        manager.set_allowed_prefixes("peer", vec!["192.168.0.0/16".parse().unwrap()]).await;
+        handle_route_update(route_update_packet(&route_update(&key_pair, "peer", &["192.168.5.0/24"])), from, &ctx).await;
+        assert_eq!(routed_via(&manager, "192.168.5.0/24").await.as_deref(), Some("peer"));
+        handle_route_update(route_update_packet(&route_update(&key_pair, "peer", &["192.0.0.0/8"])), from, &ctx).await;
+        assert_eq!(routed_via(&manager, "192.0.0.0/8").await, None);
+        
+        // 不能覆盖经其他节点的路由
+        manager.routing.write().await.table_mut(MAIN_TABLE).add_route(Route {
+            destination: "192.168.6.0/24".parse().unwrap(),
+            gateway: "10.0.0.3".parse().unwrap(),
+            next_hop: "other".to_string(),
+            metric: 10,
+        });
+        handle_route_update(route_update_packet(&route_update(&key_pair, "peer", &["192.168.6.0/24"])), from, &ctx).await;
+        assert_eq!(routed_via(&manager, "192.168.6.0/24").await.as_deref(), Some("other"));
+    }
+    
+    #[tokio::test]
+    async fn duplicates_are_dropped_only_after_decrypt_and_with_multipath() {
+        let (manager, _) = mock_network_manager(0).await;
+        let from = addr("192.0.2.2:51820");
+        let source_ip = Ipv4Addr::new(10, 0, 0, 2);
+        let ciphertext = peer_crypto().encrypt(&[0x45; 20], &[]).unwrap();
+        
+        // 未编号的数据包不去重
+        for _ in 0..3 {
+            assert!(deliver(&manager, forward_packet("peer", source_ip, 0, ciphertext.clone()), from).await);
+        }
+        // 未启用多路径时重复的序列号也照常接收
+        assert!(deliver(&manager, forward_packet("peer", source_ip, 1, ciphertext.clone()), from).await);
+        assert!(deliver(&manager, forward_packet("peer", source_ip, 1, ciphertext.clone()), from).await);
+        
+        let paths = vec![ForwardPath::new(manager.sockets.sockets[0].1.clone(), from)];
+        manager.peers.write().await.get_mut("peer").unwrap().multipath =
+            Some(Arc::new(MultiPathForwarder::new(paths, MultiPathStrategy::Bonding)));
+        
+        // 解密失败的伪造数据包不占用序列号
+        assert!(!deliver(&manager, forward_packet("peer", source_ip, 5, vec![0u8; 64]), from).await);
+        assert!(deliver(&manager, forward_packet("peer", source_ip, 5, ciphertext.clone()), from).await);
+        // 绑定模式下经另一条路径到达的第二份被丢弃
+        assert!(!deliver(&manager, forward_packet("peer", source_ip, 5, ciphertext.clone()), from).await);
+        assert_eq!(manager.get_peer("peer").await.unwrap().stats.packets_received, 6);
+    }
+    
+    /// 拒绝所有节点的准入检查
+    struct RejectAll;
+    
+    impl HandshakeValidator for RejectAll {
+        fn validate<'a>(&'a self, _req: &'a HandshakeRequest, _addr: SocketAddr) -> BoxFuture<'a, HandshakeVerdict> {
+            Box::pin(async {
+                HandshakeVerdict::Reject { status: status::KEY_MISMATCH, message: "rejected".to_string(), conflict: None }
+            })
+        }
+    }
+    
+    fn session_ticket_keys() -> Arc<Mutex<SessionTicketKeys>> {
+        Arc::new(Mutex::new(SessionTicketKeys::new(Duration::from_secs(SessionTicketKeys::DEFAULT_LIFETIME)).unwrap()))
+    }
+    
+    /// 为节点`peer`签发会话票据，会话密钥为`[9; 32]`
+    async fn issue_ticket(keys: &Mutex<SessionTicketKeys>) -> Vec<u8> {
+        keys.lock().await.issue(&SessionState {
+            node_id: "peer".to_string(),
+            public_key: vec![2u8; 32],
+            virtual_ip: "10.0.0.2".to_string(),
+            session_key: vec![9u8; 32],
+            connection_id: 42,
+            issued_at: current_unix_timestamp(),
+        }).unwrap()
+    }
+    
+    fn resume_request(ticket: Vec<u8>) -> Packet {
+        let mut req: HandshakeRequest = serde_json::from_slice(&handshake_request("peer", vec![2u8; 32]).data).unwrap();
+        req.session_ticket = Some(ticket);
+        PacketBuilder::new(MessageType::HandshakeRequest, serde_json::to_vec(&req).unwrap()).build()
+    }
+    
+    #[tokio::test]
+    async fn resumed_session_still_passes_admission() {
+        let (mut manager, _) = mock_network_manager(0).await;
+        let keys = session_ticket_keys();
+        manager.set_session_tickets(keys.clone());
+        let from = addr("192.0.2.2:51820");
+        
+        manager.peers.write().await.get_mut("peer").unwrap().status = NodeStatus::Offline;
+        handle_handshake_request(resume_request(issue_ticket(&keys).await), from, &mock_context(&manager)).await.unwrap();
+        let peer = manager.get_peer("peer").await.unwrap();
+        assert_eq!(peer.status, NodeStatus::Online);
+        assert_eq!(peer.session_key, vec![9u8; 32]);
+        assert_eq!(peer.connection_id, 42);
+        
+        // 准入检查拒绝的节点即使票据有效也不能恢复
+        manager.peers.write().await.get_mut("peer").unwrap().status = NodeStatus::Offline;
+        manager.set_handshake_validator(Arc::new(RejectAll));
+        handle_handshake_request(resume_request(issue_ticket(&keys).await), from, &mock_context(&manager)).await.unwrap();
+        assert_eq!(manager.get_peer("peer").await.unwrap().status, NodeStatus::Offline);
+    }
+    
+    #[tokio::test]
+    async fn removed_node_cannot_resume_its_session() {
+        let (mut manager, _) = mock_network_manager(0).await;
+        let keys = session_ticket_keys();
+        manager.set_session_tickets(keys.clone());
+        let ticket = issue_ticket(&keys).await;
+        manager.remove_peer("peer").await;
+        
+        // 票据被吊销，节点只能完整握手，得到新的会话密钥
+        handle_handshake_request(resume_request(ticket), addr("192.0.2.2:51820"), &mock_context(&manager)).await.unwrap();
+        let peer = manager.get_peer("peer").await.unwrap();
+        assert_ne!(peer.session_key, vec![9u8; 32]);
+    }
+    
+    /// 记录派生次数的HKDF-SHA256
+    #[derive(Default)]
+    struct CountingKdf(AtomicUsize);
+    
+    impl Kdf for CountingKdf {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+        
+        fn derive(&self, input: &[u8], salt: &[u8], info: &[u8], output_len: usize) -> Result<Vec<u8>, &'static str> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            HkdfSha256Kdf.derive(input, salt, info, output_len)
+        }
+    }
+    
+    #[tokio::test]
+    async fn resumption_skips_the_key_exchange() {
+        let (mut manager, _) = mock_network_manager(0).await;
+        let keys = session_ticket_keys();
+        manager.set_session_tickets(keys.clone());
+        let kdf = Arc::new(CountingKdf::default());
+        manager.set_kdf(kdf.clone()).await.unwrap();
+        let ctx = mock_context(&manager);
+        let from = addr("192.0.2.2:51820");
+        let ticket = issue_ticket(&keys).await;
+        let before = kdf.0.load(Ordering::SeqCst);
+        
+        // 完整握手由双方的临时密钥派生新的会话密钥
+        let mut req: HandshakeRequest = serde_json::from_slice(&handshake_request("peer", vec![2u8; 32]).data).unwrap();
+        req.ephemeral_key = Some(KeyExchange::new().unwrap().public_key().to_vec());
+        let packet = PacketBuilder::new(MessageType::HandshakeRequest, serde_json::to_vec(&req).unwrap()).build();
+        handle_handshake_request(packet, from, &ctx).await.unwrap();
+        let after_full = kdf.0.load(Ordering::SeqCst);
+        assert!(after_full > before);
+        assert_ne!(manager.get_peer("peer").await.unwrap().session_key, vec![9u8; 32]);
+        
+        // 携带有效票据时直接恢复票据中的会话，不再进行密钥交换
+        handle_handshake_request(resume_request(ticket), from, &ctx).await.unwrap();
+        assert_eq!(kdf.0.load(Ordering::SeqCst), after_full);
+        assert_eq!(manager.get_peer("peer").await.unwrap().session_key, vec![9u8; 32]);
+    }
+    
+    #[tokio::test]
+    async fn group_limit_caps_aggregate_throughput_of_its_peers() {
+        const RATE: u64 = 20_000;
+        const PEERS: usize = 5;
+        let limiter = Arc::new(GroupBandwidthLimiter::new(8));
+        limiter.set_group_limit("dept", Some(RATE));
+        for i in 0..PEERS {
+            limiter.set_peer_group(&format!("peer-{}", i), Some("dept"));
+        }
+        
+        // 各节点发出的字节数，测试中以发送节点作为目标节点，以便统计排队后放行的数据包
+        let sent: Arc<std::sync::Mutex<HashMap<String, u64>>> = Arc::default();
+        let record = |sent: &std::sync::Mutex<HashMap<String, u64>>, peer: &str, packet: &Packet| {
+            *sent.lock().unwrap().entry(peer.to_string()).or_default() += packet_cost(packet) as u64;
+        };
+        
+        let start = Instant::now();
+        let duration = Duration::from_secs(1);
+        let mut senders = Vec::new();
+        for i in 0..PEERS {
+            let (limiter, sent) = (limiter.clone(), sent.clone());
+            senders.push(tokio::spawn(async move {
+                let peer = format!("peer-{}", i);
+                while start.elapsed() < duration {
+                    if let Some(packet) = limiter.admit(&peer, &peer, data_packet(1000), 0) {
+                        record(&sent, &peer, &packet);
+                    }
+                    tokio::task::yield_now().await;
+                }
+            }));
+        }
+        let drain = {
+            let (limiter, sent) = (limiter.clone(), sent.clone());
+            tokio::spawn(async move {
+                loop {
+                    let (peer, packet, _) = limiter.next_packet().await;
+                    record(&sent, &peer, &packet);
+                }
+            })
+        };
+        for sender in senders {
+            sender.await.unwrap();
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+        drain.abort();
+        
+        let sent = sent.lock().unwrap().clone();
+        let total: u64 = sent.values().sum();
+        // 总量不超过一秒的突发加上按速率恢复的令牌
+        let allowed = GroupBucket::capacity(RATE) as f64 + RATE as f64 * elapsed;
+        assert!(total as f64 <= allowed, "sent {} bytes, allowed {}", total, allowed);
+        assert!(total as f64 >= RATE as f64 * elapsed * 0.8, "sent only {} bytes", total);
+        
+        // 带宽在分组内的节点之间平均分配
+        let fair_share = total as f64 / PEERS as f64;
+        for i in 0..PEERS {
+            let peer_sent = sent.get(&format!("peer-{}", i)).copied().unwrap_or(0) as f64;
+            assert!(peer_sent >= fair_share * 0.5, "peer-{} sent {} bytes of {}", i, peer_sent, total);
+        }
+    }
+    
+    #[test]
+    fn packets_beyond_queue_depth_are_dropped() {
+        let limiter = GroupBandwidthLimiter::new(2);
+        limiter.set_group_limit("dept", Some(1));
+        limiter.set_peer_group("peer", Some("dept"));
+        
+        // 令牌桶最多容纳一个最大的数据包
+        assert!(limiter.admit("peer", "dest", data_packet(MAX_PACKET_SIZE - constants::RAW_PACKET_HEADER_LEN), 0).is_some());
+        for _ in 0..3 {
+            assert!(limiter.admit("peer", "dest", data_packet(100), 0).is_none());
+        }
+        let state = limiter.state.lock().unwrap();
+        assert_eq!(state.groups["dept"].queues["peer"].len(), 2);
+        
+        // 不属于分组的节点不受影响
+        drop(state);
+        assert!(limiter.admit("other", "dest", data_packet(100), 0).is_some());
+    }
+    
+    /// 按`rates`依次以每段的字节速率发送（每个数据包100字节），采样间隔在0.9到1.1秒之间，
+    /// 返回统计和最后一次采样的时刻
+    fn constant_rate_history(capacity: usize, rates: &[(f64, u64)]) -> (RollingWindowStats, Instant) {
+        let mut history = RollingWindowStats::new(capacity);
+        let mut now = Instant::now();
+        let mut elapsed = 0.0;
+        let mut bytes = 0.0;
+        let mut stats = PeerStats { packet_loss_pct: 2.0, ..PeerStats::default() };
+        history.sample_at(&stats, now);
+        
+        let mut i = 0u64;
+        for &(rate, secs) in rates {
+            let end = elapsed + secs as f64;
+            while elapsed < end {
+                let interval = Duration::from_millis(900 + (i * 37) % 200);
+                i += 1;
+                now += interval;
+                elapsed += interval.as_secs_f64();
+                bytes += rate * interval.as_secs_f64();
+                stats.bytes_sent = bytes as u64;
+                stats.packets_sent = stats.bytes_sent / 100;
+                history.sample_at(&stats, now);
+            }
+        }
+        (history, now)
+    }
+    
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() <= expected * 0.01, "{} is not close to {}", actual, expected);
+    }
+    
+    #[test]
+    fn window_stats_converge_to_constant_rate() {
+        let (history, now) = constant_rate_history(RollingWindowStats::DEFAULT_CAPACITY, &[(1250.0, 900)]);
+        for window in [60, 300, 900] {
+            let stats = history.window_stats_at(Duration::from_secs(window), now);
+            assert_close(stats.bytes_per_sec, 1250.0);
+            assert_close(stats.packets_per_sec, 12.5);
+            assert_close(stats.loss_rate, 0.02);
+        }
+    }
+    
+    #[test]
+    fn window_stats_only_cover_recent_samples() {
+        let (history, now) = constant_rate_history(RollingWindowStats::DEFAULT_CAPACITY, &[(1000.0, 600), (3000.0, 300)]);
+        assert_close(history.window_stats_at(Duration::from_secs(300), now).bytes_per_sec, 3000.0);
+        // 窗口超过历史长度时使用全部样本
+        let all = history.window_stats_at(Duration::from_secs(3600), now);
+        assert_close(all.bytes_per_sec, (1000.0 * 600.0 + 3000.0 * 300.0) / 900.0);
+        // 之后没有新样本，窗口滑过全部历史后没有数据
+        let later = history.window_stats_at(Duration::from_secs(300), now + Duration::from_secs(400));
+        assert_eq!(later.bytes_per_sec, 0.0);
+    }
+    
+    #[test]
+    fn history_keeps_at_most_capacity_samples() {
+        let (history, now) = constant_rate_history(10, &[(1000.0, 60), (2000.0, 10)]);
+        assert_eq!(history.samples.len(), 10);
+        assert_close(history.window_stats_at(Duration::from_secs(3600), now).bytes_per_sec, 2000.0);
+        
+        let (empty, now) = constant_rate_history(0, &[(1000.0, 10)]);
+        assert_eq!(empty.window_stats_at(Duration::from_secs(60), now).bytes_per_sec, 0.0);
+    }
+    
+    #[tokio::test]
+    async fn handshake_applies_the_compat_matrix() {
+        let (mut manager, _) = mock_network_manager(0).await;
+        manager.set_compat_matrix(CompatMatrix::new(vec![
+            CompatRule {
+                min_version: None,
+                max_version: Some("1.0.0".parse().unwrap()),
+                status: CompatStatus::Incompatible,
+                disabled_features: Vec::new(),
+            },
+            CompatRule {
+                min_version: Some("1.0.0".parse().unwrap()),
+                max_version: Some("1.1.0".parse().unwrap()),
+                status: CompatStatus::Degraded,
+                disabled_features: vec![DisabledFeature::Compression],
+            },
+        ]).unwrap());
+        let ctx = mock_context(&manager);
+        let request = |node_id: &str, client_version: &str| {
+            let mut req: HandshakeRequest = serde_json::from_slice(&handshake_request(node_id, vec![3u8; 32]).data).unwrap();
+            req.client_version = client_version.to_string();
+            PacketBuilder::new(MessageType::HandshakeRequest, serde_json::to_vec(&req).unwrap())
+                .flags(supported_payload_flags())
+                .build()
+        };
+        
+        handle_handshake_request(request("old", "0.9.0"), addr("192.0.2.3:51820"), &ctx).await.unwrap();
+        assert!(manager.get_peer("old").await.is_none());
+        
+        handle_handshake_request(request("degraded", "1.0.2"), addr("192.0.2.4:51820"), &ctx).await.unwrap();
+        let degraded = manager.get_peer("degraded").await.unwrap();
+        assert_eq!(degraded.payload_flags & constants::FLAG_COMPRESSED, 0);
+        
+        handle_handshake_request(request("current", "1.1.0"), addr("192.0.2.5:51820"), &ctx).await.unwrap();
+        let current = manager.get_peer("current").await.unwrap();
+        assert_eq!(current.payload_flags, supported_payload_flags());
+    }
+    
+    /// 持锁的线程panic，使锁进入中毒状态
+    fn poison<T: Send>(lock: &std::sync::Mutex<T>) {
+        std::thread::scope(|scope| {
+            let _ = scope.spawn(|| {
+                let _guard = lock.lock().unwrap();
+                panic!("poisoning the lock");
+            }).join();
+        });
+        assert!(lock.is_poisoned());
+    }
+    
+    /// 序列化总是失败的消息
+    struct Unserializable;
+    
+    impl Serialize for Unserializable {
+        fn serialize<S: serde::Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("refusing to serialize"))
+        }
+    }
+    
+    #[tokio::test]
+    async fn schedulers_keep_working_after_a_lock_is_poisoned() {
+        let priority = PriorityScheduler::new();
+        poison(&priority.queues);
+        priority.enqueue("peer", data_packet(16), 3);
+        assert_eq!(priority.next_packet().await.0, "peer");
+        
+        let wfq = WfqScheduler::new();
+        poison(&wfq.state);
+        wfq.set_weight("peer", 4);
+        assert_eq!(wfq.weight("peer"), 4);
+        wfq.enqueue("peer", data_packet(16), 0);
+        assert_eq!(wfq.next_packet().await.0, "peer");
+        
+        let limiter = GroupBandwidthLimiter::new(4);
+        poison(&limiter.state);
+        limiter.set_group_limit("group", Some(1_000_000));
+        limiter.set_peer_group("peer", Some("group"));
+        assert_eq!(limiter.group_limit("group"), Some(1_000_000));
+        assert!(limiter.admit("peer", "other", data_packet(16), 0).is_some());
+    }
+    
+    #[test]
+    fn bucket_with_a_missing_queue_releases_nothing() {
+        let mut bucket = GroupBucket::new(Some(1_000_000));
+        bucket.waiting.push_back("peer".to_string());
+        assert!(matches!(bucket.release(), Release::Idle));
+        assert!(bucket.waiting.is_empty());
+    }
+    
+    #[test]
+    fn unserializable_messages_are_reported_instead_of_panicking() {
+        assert!(matches!(
+            encode_message(MessageType::Heartbeat, &Unserializable, 0),
+            Err(VpnetError::Serialization(_))
+        ));
+        
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let receiver = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_nonblocking(true).unwrap();
+        send_message(&socket, receiver.local_addr().unwrap(), MessageType::Heartbeat, &Unserializable);
+        assert!(receiver.recv_from(&mut [0u8; 64]).is_err());
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn spawn_logged_restarts_failed_and_panicked_tasks() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let factory_runs = runs.clone();
+        let handle = spawn_logged("flaky", true, move || {
+            let run = factory_runs.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                match run {
+                    0 => Err(VpnetError::Serialization("first run fails".to_string())),
+                    1 => panic!("second run panics"),
+                    _ => std::future::pending().await,
+                }
+            })
+        });
+        
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(1100)).await;
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert!(!handle.is_finished());
+        
+        handle.abort();
+        assert!(handle.await.unwrap_err().is_cancelled());
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn spawn_logged_without_restart_runs_once() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let factory_runs = runs.clone();
+        let handle = spawn_logged("once", false, move || {
+            factory_runs.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Err(VpnetError::Serialization("fails".to_string())) })
+        });
+        
+        handle.await.unwrap();
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+    
+    #[tokio::test]
+    async fn aborting_spawn_logged_stops_the_running_task() {
+        let alive = Arc::new(());
+        let task_alive = alive.clone();
+        let handle = spawn_logged("long-running", true, move || {
+            let task_alive = task_alive.clone();
+            Box::pin(async move {
+                let _alive = task_alive;
+                std::future::pending().await
+            })
+        });
+        
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(Arc::strong_count(&alive), 3);
+        handle.abort();
+        let _ = handle.await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        // 外层任务连同工厂闭包一起释放，只剩测试持有的引用
+        assert_eq!(Arc::strong_count(&alive), 1);
+    }
+    
+    mod relay_selection {
+        use super::*;
+        use proptest::prelude::*;
+        
+        fn relay(node_id: &str) -> NodeInfo {
+            let mut info = server_info(node_id, None);
+            info.capabilities = Capabilities::CAN_RELAY.bits();
+            info
+        }
+        
+        fn stats(rtt_ms: f64, loss_pct: f32, load: f32) -> PeerStats {
+            PeerStats { rtt_ms, packet_loss_pct: loss_pct, load, ..Default::default() }
+        }
+        
+        fn selected(manager: &ConnectionManager, candidates: &[NodeInfo], stats: &HashMap<String, PeerStats>) -> String {
+            manager.select_relay(candidates, stats).unwrap().node_id.clone()
+        }
+        
+        proptest! {
+            #[test]
+            fn lower_rtt_wins_and_the_choice_follows_rtt_changes(
+                fast in 1.0f64..500.0,
+                extra in 1.0f64..500.0,
+                loss in 0.0f32..20.0,
+                load in 0.0f32..2.0,
+            ) {
+                let manager = ConnectionManager::new(None, false);
+                let candidates = vec![relay("a"), relay("b")];
+                let mut table = HashMap::from([
+                    ("a".to_string(), stats(fast, loss, load)),
+                    ("b".to_string(), stats(fast + extra, loss, load)),
+                ]);
+                prop_assert_eq!(selected(&manager, &candidates, &table), "a");
+                
+                // 原来较快的中继变慢后改选另一个
+                table.get_mut("a").unwrap().rtt_ms = fast + 2.0 * extra;
+                prop_assert_eq!(selected(&manager, &candidates, &table), "b");
+            }
+            
+            #[test]
+            fn lower_loss_wins_and_the_choice_follows_loss_changes(
+                rtt in 1.0f64..500.0,
+                low in 0.0f32..20.0,
+                extra in 0.5f32..20.0,
+                load in 0.0f32..2.0,
+            ) {
+                let manager = ConnectionManager::new(None, false);
+                let candidates = vec![relay("a"), relay("b")];
+                let mut table = HashMap::from([
+                    ("a".to_string(), stats(rtt, low + extra, load)),
+                    ("b".to_string(), stats(rtt, low, load)),
+                ]);
+                prop_assert_eq!(selected(&manager, &candidates, &table), "b");
+                
+                // 对端报告的发送方向丢包同样计入
+                table.get_mut("b").unwrap().remote_packet_loss_pct = low + 2.0 * extra;
+                prop_assert_eq!(selected(&manager, &candidates, &table), "a");
+            }
+            
+            #[test]
+            fn equal_candidates_are_selected_deterministically(
+                rtt in 0.0f64..500.0,
+                loss in 0.0f32..20.0,
+                load in 0.0f32..2.0,
+                count in 2usize..8,
+            ) {
+                let manager = ConnectionManager::new(frankfurt(), false);
+                let candidates: Vec<_> = (0..count).map(|i| relay(&format!("relay-{}", i))).collect();
+                let table: HashMap<_, _> = candidates.iter()
+                    .map(|node| (node.node_id.clone(), stats(rtt, loss, load)))
+                    .collect();
+                for _ in 0..3 {
+                    prop_assert_eq!(selected(&manager, &candidates, &table), "relay-0");
+                }
+                
+                let reversed: Vec<_> = candidates.iter().rev().cloned().collect();
+                prop_assert_eq!(selected(&manager, &reversed, &table), format!("relay-{}", count - 1));
+            }
+            
+            #[test]
+            fn nodes_without_relay_capability_are_never_selected(
+                rtt in 1.0f64..500.0,
+                extra in 1.0f64..500.0,
+            ) {
+                let manager = ConnectionManager::new(None, false);
+                let candidates = vec![server_info("direct-only", None), relay("relay")];
+                let table = HashMap::from([
+                    ("direct-only".to_string(), stats(rtt, 0.0, 0.0)),
+                    ("relay".to_string(), stats(rtt + extra, 0.0, 0.0)),
+                ]);
+                prop_assert_eq!(selected(&manager, &candidates, &table), "relay");
+                prop_assert!(manager.select_relay(&candidates[..1], &table).is_none());
+            }
+        }
+        
+        #[test]
+        fn configured_weights_change_the_choice() {
+            let candidates = vec![relay("low-latency"), relay("low-loss")];
+            let table = HashMap::from([
+                ("low-latency".to_string(), stats(10.0, 5.0, 0.0)),
+                ("low-loss".to_string(), stats(80.0, 0.0, 0.0)),
+            ]);
+            let mut manager = ConnectionManager::new(None, false);
+            assert_eq!(selected(&manager, &candidates, &table), "low-latency");
+            
+            manager.set_relay_weights(RelaySelectionWeights {
+                rtt_weight: 0.0,
+                loss_weight: 1.0,
+                load_weight: 0.0,
+                distance_weight: 0.0,
+            });
+            assert_eq!(selected(&manager, &candidates, &table), "low-loss");
+        }
+    }
+    
+    /// 节点`node_id`广播的节点信息包
+    fn node_info_packet(node_id: &str, virtual_ip: &str) -> Packet {
+        let mut info = build_local_info(node_id, node_id, &[9u8; 32], addr("192.0.2.50:51820"));
+        info.virtual_ip = virtual_ip.to_string();
+        PacketBuilder::new(MessageType::NodeInfo, serde_json::to_vec(&info).unwrap()).build()
+    }
+    
+    #[tokio::test]
+    async fn node_info_adds_unknown_peers_only() {
+        let peers = Arc::new(RwLock::new(HashMap::new()));
+        let mut known = Peer::new(
+            "known".to_string(),
+            "known".to_string(),
+            addr("192.0.2.2:51820"),
+            "10.0.0.2".to_string(),
+            vec![2u8; 32],
+            0
+        );
+        known.session_key = vec![5u8; 32];
+        peers.write().await.insert("known".to_string(), known);
+        let local = addr("0.0.0.0:51820");
+        
+        let spoofed = node_info_packet("known", "10.0.0.99");
+        handle_node_info(spoofed, addr("192.0.2.66:51820"), local, "node-1", peers.clone()).await;
+        let fresh = node_info_packet("fresh", "10.0.0.3");
+        handle_node_info(fresh, addr("192.0.2.3:51820"), local, "node-1", peers.clone()).await;
+        let own = node_info_packet("node-1", "10.0.0.1");
+        handle_node_info(own, addr("192.0.2.1:51820"), local, "node-1", peers.clone()).await;
+        
+        let peers = peers.read().await;
+        assert_eq!(peers.len(), 2);
+        let known = &peers["known"];
+        assert_eq!(known.address, addr("192.0.2.2:51820"));
+        assert_eq!(known.virtual_ip, "10.0.0.2");
+        assert_eq!(known.session_key, vec![5u8; 32]);
+        assert_eq!(peers["fresh"].address, addr("192.0.2.3:51820"));
+        assert_eq!(peers["fresh"].local_addr, Some(local));
+    }
+    
+    /// 派生出固定字节的测试用密钥派生函数
+    struct FixedKdf;
+    
+    impl Kdf for FixedKdf {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+        
+        fn derive(&self, _input: &[u8], _salt: &[u8], _info: &[u8], output_len: usize) -> Result<Vec<u8>, &'static str> {
+            Ok(vec![0x5a; output_len])
+        }
+    }
+    
+    #[tokio::test]
+    async fn handshake_and_ticket_keys_use_the_configured_kdf() {
+        let (mut manager, _) = mock_network_manager(0).await;
+        let keys = session_ticket_keys();
+        manager.set_session_tickets(keys.clone());
+        let before = issue_ticket(&keys).await;
+        manager.set_kdf(Arc::new(FixedKdf)).await.unwrap();
+        
+        let mut req: HandshakeRequest = serde_json::from_slice(&handshake_request("peer", vec![2u8; 32]).data).unwrap();
+        req.ephemeral_key = Some(KeyExchange::new().unwrap().public_key().to_vec());
+        let packet = PacketBuilder::new(MessageType::HandshakeRequest, serde_json::to_vec(&req).unwrap()).build();
+        handle_handshake_request(packet, addr("192.0.2.2:51820"), &mock_context(&manager)).await.unwrap();
+        assert_eq!(manager.get_peer("peer").await.unwrap().session_key, vec![0x5a; 32]);
+        
+        // 票据密钥已轮换为新函数派生的密钥，轮换前签发的票据仍然有效
+        let mut keys = keys.lock().await;
+        let state = keys.redeem(&before).unwrap();
+        let after = keys.issue(&state).unwrap();
+        assert_ne!(after[..4], before[..4]);
+        assert!(keys.redeem(&after).is_some());
+    }
+    
+    #[test]
+    fn data_ack_bitmap_records_out_of_order_arrivals() {
+        let mut estimator = LossEstimator::new();
+        for seq in [1, 2, 4, 3, 6] {
+            estimator.record(seq);
+        }
+        // 第0位对应最大序列号6，5未收到
+        assert_eq!(estimator.bitmap() & 0b11_1111, 0b11_1101);
+        let ack = DataAck {
+            node_id: "peer".to_string(),
+            dest_node: "node-1".to_string(),
+            seq: estimator.highest_seq().unwrap(),
+            received_count: 5,
+            received_bitmap: estimator.bitmap(),
+        };
+        assert!((ack.loss_rate() - 1.0 / 6.0).abs() < 1e-9);
+        
+        // 5迟到后补记，不再计为丢失
+        estimator.record(5);
+        let ack = DataAck { received_bitmap: estimator.bitmap(), ..ack };
+        assert_eq!(ack.received_bitmap & 0b11_1111, 0b11_1111);
+        assert_eq!(ack.loss_rate(), 0.0);
+        
+        // 超出窗口的旧序列号不影响位图
+        estimator.record(200);
+        estimator.record(6);
+        assert_eq!(estimator.bitmap(), 1);
+    }
+    
+    /// 使用真实UDP套接字的网络管理器，记录节点`peer`和能够中继的节点`relay`
+    async fn udp_network_manager(peer: SocketAddr, relay: SocketAddr) -> NetworkManager {
+        let manager = NetworkManager::with_transport(
+            vec![addr("127.0.0.1:0")],
+            "node-1".to_string(),
+            "node-1".to_string(),
+            vec![1u8; 32],
+            Arc::new(crate::transport::UdpTransport)
+        ).unwrap();
+        let mut relay_peer = Peer::new("relay".to_string(), "relay".to_string(), relay, "10.0.0.3".to_string(), vec![3u8; 32], 0);
+        relay_peer.capabilities = Capabilities::CAN_RELAY.bits();
+        let mut known = Peer::new("peer".to_string(), "peer".to_string(), peer, "10.0.0.2".to_string(), vec![2u8; 32], 0);
+        known.set_session_key(PEER_SESSION_KEY.to_vec(), false).unwrap();
+        let mut peers = manager.peers.write().await;
+        peers.insert("peer".to_string(), known);
+        peers.insert("relay".to_string(), relay_peer);
+        drop(peers);
+        manager
+    }
+    
+    fn recv_data_ack(socket: &std::net::UdpSocket) -> DataAck {
+        socket.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let mut buf = [0u8; 2048];
+        let (len, _) = socket.recv_from(&mut buf).unwrap();
+        let packet = Packet::decode(&buf[..len]).unwrap();
+        assert_eq!(packet.msg_type, MessageType::DataAck);
+        decode_payload(&packet.data, packet.flags).unwrap()
+    }
+    
+    #[tokio::test]
+    async fn data_ack_returns_through_the_relay_the_data_came_from() {
+        let peer = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let relay = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let manager = udp_network_manager(peer.local_addr().unwrap(), relay.local_addr().unwrap()).await;
+        
+        let data = peer_crypto().encrypt(&[0x45; 20], &[]).unwrap();
+        let mut packet = forward_packet("peer", Ipv4Addr::new(10, 0, 0, 2), 1, data);
+        packet.flags |= constants::FLAG_ACK_REQUESTED;
+        assert!(deliver(&manager, packet.clone(), relay.local_addr().unwrap()).await);
+        let ack = recv_data_ack(&relay);
+        assert_eq!((ack.node_id.as_str(), ack.dest_node.as_str(), ack.seq), ("node-1", "peer", 1));
+        
+        // 直接到达的数据包直接确认
+        assert!(deliver(&manager, packet, peer.local_addr().unwrap()).await);
+        assert_eq!(recv_data_ack(&peer).dest_node, "peer");
+    }
+    
+    /// `source_node`发往`dest_node`、需要本节点中继的数据转发
+    fn relayed_packet(source_node: &str, dest_node: &str) -> Packet {
+        let forward = DataForward {
+            source_node: source_node.to_string(),
+            dest_node: dest_node.to_string(),
+            data: vec![0x45; 20],
+            protocol: 0x0800,
+            ttl: constants::DEFAULT_TTL,
+            seq: 0,
+            priority: constants::DEFAULT_DATA_PRIORITY,
+            compressed: false,
+            source_virtual_ip: Ipv4Addr::UNSPECIFIED,
+            ack_requested: false,
+        };
+        let (data, flags) = encode_data_forward(&forward, constants::FLAG_RAW_FRAME | constants::FLAG_SOURCE_IP).unwrap();
+        PacketBuilder::new(MessageType::DataForward, data).flags(flags).build()
+    }
+    
+    /// 以`filter`过滤，处理一个从`from`收到的数据转发
+    async fn relay_from(manager: &NetworkManager, packet: Packet, from: SocketAddr, filter: Option<Arc<dyn ForwardFilter>>) {
+        handle_data_forward(
+            packet,
+            from,
+            manager.sockets.clone(),
+            manager.peers.clone(),
+            "node-1".to_string(),
+            filter, None, None, None, None, None,
+            None,
+            &PacketSizeHistogram::new(),
+            DeviceMode::Tun
+        ).await;
+    }
+    
+    /// 在`timeout`内从`socket`收到的数据转发
+    fn recv_forward(socket: &std::net::UdpSocket, timeout: Duration) -> Option<DataForward> {
+        socket.set_read_timeout(Some(timeout)).unwrap();
+        let mut buf = [0u8; 2048];
+        let (len, _) = socket.recv_from(&mut buf).ok()?;
+        let packet = Packet::decode(&buf[..len]).unwrap();
+        assert_eq!(packet.msg_type, MessageType::DataForward);
+        Some(decode_data_forward(&packet.data, packet.flags).unwrap())
+    }
+    
+    #[tokio::test]
+    async fn relay_drops_data_not_sent_from_the_source_address() {
+        let peer = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let dest = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let manager = udp_network_manager(peer.local_addr().unwrap(), dest.local_addr().unwrap()).await;
+        let attacker = addr("203.0.113.5:51820");
+        
+        // 冒充已知节点或使用未知节点ID都不能借本节点之手发出带有他人虚拟IP的数据
+        relay_from(&manager, relayed_packet("peer", "relay"), attacker, None).await;
+        relay_from(&manager, relayed_packet("stranger", "relay"), attacker, None).await;
+        assert!(recv_forward(&dest, Duration::from_millis(200)).is_none());
+        assert!(manager.get_peer("peer").await.unwrap().relay_peers.is_empty());
+        
+        relay_from(&manager, relayed_packet("peer", "relay"), peer.local_addr().unwrap(), None).await;
+        let forward = recv_forward(&dest, Duration::from_secs(2)).unwrap();
+        assert_eq!((forward.source_node.as_str(), forward.source_virtual_ip), ("peer", Ipv4Addr::new(10, 0, 0, 2)));
+    }
+    
+    /// 只允许`peer`发出的数据
+    struct FromPeerFilter;
+    
+    impl ForwardFilter for FromPeerFilter {
+        fn allow<'a>(&'a self, source_node: &'a str, _dest_node: &'a str) -> BoxFuture<'a, bool> {
+            Box::pin(async move { source_node == "peer" })
+        }
+    }
+    
+    #[tokio::test]
+    async fn forward_filter_sees_the_authenticated_source() {
+        let peer = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let relay = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let other = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let manager = udp_network_manager(peer.local_addr().unwrap(), relay.local_addr().unwrap()).await;
+        manager.peers.write().await.insert(
+            "other".to_string(),
+            Peer::new("other".to_string(), "other".to_string(), other.local_addr().unwrap(), "10.0.0.4".to_string(), vec![4u8; 32], 0)
+        );
+        let filter: Arc<dyn ForwardFilter> = Arc::new(FromPeerFilter);
+        
+        // 被隔离的节点声称数据来自允许的节点
+        relay_from(&manager, relayed_packet("peer", "other"), relay.local_addr().unwrap(), Some(filter.clone())).await;
+        assert!(recv_forward(&other, Duration::from_millis(200)).is_none());
+        
+        relay_from(&manager, relayed_packet("peer", "other"), peer.local_addr().unwrap(), Some(filter)).await;
+        assert_eq!(recv_forward(&other, Duration::from_secs(2)).unwrap().source_node, "peer");
+    }
+
+    
+    /// 在`timeout`内从`socket`收到的TTL耗尽通知
+    fn recv_ttl_exceeded(socket: &std::net::UdpSocket, timeout: Duration) -> Option<TtlExceededMessage> {
+        socket.set_read_timeout(Some(timeout)).unwrap();
+        let mut buf = [0u8; 2048];
+        let (len, _) = socket.recv_from(&mut buf).ok()?;
+        let packet = Packet::decode(&buf[..len]).unwrap();
+        assert_eq!(packet.msg_type, MessageType::TtlExceeded);
+        Some(decode_payload(&packet.data, packet.flags).unwrap())
+    }
+    
+    #[tokio::test]
+    async fn forwarding_loop_is_broken_after_64_hops() {
+        let source = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        // 分别代表节点a、b、c接收转发的套接字
+        let hops: Vec<_> = (0..3).map(|_| std::net::UdpSocket::bind("127.0.0.1:0").unwrap()).collect();
+        let mut managers = Vec::new();
+        for (i, node_id) in ["a", "b", "c"].into_iter().enumerate() {
+            let manager = NetworkManager::with_transport(
+                vec![addr("127.0.0.1:0")],
+                node_id.to_string(),
+                node_id.to_string(),
+                vec![1u8; 32],
+                Arc::new(crate::transport::UdpTransport)
+            ).unwrap();
+            // 各节点到dest的路由都指向下一个节点，形成a→b→c→a的环路
+            let next = hops[(i + 1) % 3].local_addr().unwrap();
+            let mut peers = manager.peers.write().await;
+            peers.insert("source".to_string(), Peer::new("source".to_string(), "source".to_string(), source.local_addr().unwrap(), "10.0.0.2".to_string(), vec![2u8; 32], 0));
+            peers.insert("dest".to_string(), Peer::new("dest".to_string(), "dest".to_string(), next, "10.0.0.9".to_string(), vec![9u8; 32], 0));
+            drop(peers);
+            managers.push(manager);
+        }
+        
+        let mut forward = DataForward {
+            source_node: "source".to_string(),
+            dest_node: "dest".to_string(),
+            data: vec![0x45; 20],
+            protocol: 0x0800,
+            ttl: constants::DEFAULT_TTL,
+            seq: 0,
+            priority: constants::DEFAULT_DATA_PRIORITY,
+            compressed: false,
+            source_virtual_ip: Ipv4Addr::UNSPECIFIED,
+            ack_requested: false,
+        };
+        let mut hop = 0;
+        let mut relayed = 0u8;
+        loop {
+            let manager = &managers[hop];
+            relay_data_forward(&mut forward, &manager.sockets, &manager.peers, &manager.node_id, None, None, None).await;
+            hop = (hop + 1) % 3;
+            match recv_forward(&hops[hop], Duration::from_millis(200)) {
+                Some(next) => {
+                    forward = next;
+                    relayed += 1;
+                }
+                None => break,
+            }
+        }
+        
+        // 第64跳回到节点a时TTL耗尽，丢弃并通知源节点
+        assert_eq!(relayed, constants::DEFAULT_TTL - 1);
+        let mut exceeded = Vec::new();
+        for manager in &managers {
+            exceeded.push(manager.get_peer("source").await.unwrap().stats.ttl_exceeded_total);
+        }
+        assert_eq!(exceeded, vec![1, 0, 0]);
+        let message = recv_ttl_exceeded(&source, Duration::from_secs(2)).unwrap();
+        assert_eq!((message.hop_node_id.as_str(), message.original_dst.as_str()), ("a", "dest"));
+    }
+    
+    /// `source_node`发往`dest_node`、TTL为`ttl`的协议为`protocol`的数据转发
+    fn forward_with_ttl(source_node: &str, dest_node: &str, protocol: u16, ttl: u8) -> Packet {
+        let forward = DataForward {
+            source_node: source_node.to_string(),
+            dest_node: dest_node.to_string(),
+            data: Vec::new(),
+            protocol,
+            ttl,
+            seq: 7,
+            priority: 0,
+            compressed: false,
+            source_virtual_ip: Ipv4Addr::UNSPECIFIED,
+            ack_requested: false,
+        };
+        let (data, flags) = encode_data_forward(&forward, constants::FLAG_RAW_FRAME | constants::FLAG_SOURCE_IP).unwrap();
+        PacketBuilder::new(MessageType::DataForward, data).flags(flags).build()
+    }
+    
+    #[tokio::test]
+    async fn ttl_exceeded_is_only_sent_to_the_authenticated_source() {
+        let peer = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let relay = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let manager = udp_network_manager(peer.local_addr().unwrap(), relay.local_addr().unwrap()).await;
+        let attacker = addr("203.0.113.5:51820");
+        
+        // 冒充peer发出TTL即将耗尽的数据包或路径探测，不能让本节点向peer发送通知
+        relay_from(&manager, forward_with_ttl("peer", "relay", 0x0800, 1), attacker, None).await;
+        relay_from(&manager, forward_with_ttl("peer", "node-1", constants::PROBE_PROTOCOL, 1), attacker, None).await;
+        assert!(recv_ttl_exceeded(&peer, Duration::from_millis(200)).is_none());
+        assert_eq!(manager.get_peer("peer").await.unwrap().stats.ttl_exceeded_total, 0);
+        
+        // 源节点自己发出的和经中继到达的探测得到回复
+        relay_from(&manager, forward_with_ttl("peer", "relay", 0x0800, 1), peer.local_addr().unwrap(), None).await;
+        assert_eq!(recv_ttl_exceeded(&peer, Duration::from_secs(2)).unwrap().hop_node_id, "node-1");
+        relay_from(&manager, forward_with_ttl("peer", "node-1", constants::PROBE_PROTOCOL, 1), relay.local_addr().unwrap(), None).await;
+        assert_eq!(recv_ttl_exceeded(&peer, Duration::from_secs(2)).unwrap().seq, 7);
+    }
+    
+    fn data_ack_packet(node_id: &str, dest_node: &str) -> Packet {
+        let ack = DataAck { node_id: node_id.to_string(), dest_node: dest_node.to_string(), seq: 4, received_count: 3, received_bitmap: 0b1011 };
+        PacketBuilder::new(MessageType::DataAck, serde_json::to_vec(&ack).unwrap()).build()
+    }
+    
+    #[tokio::test]
+    async fn relay_forwards_data_acks_without_recording_loss() {
+        let peer = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let source = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        // 节点relay（地址为source）经本节点向peer发送数据
+        let manager = udp_network_manager(peer.local_addr().unwrap(), source.local_addr().unwrap()).await;
+        manager.peers.write().await.get_mut("relay").unwrap().relay_peers.insert("peer".to_string(), Instant::now());
+        let ctx = mock_context(&manager);
+        
+        handle_data_ack(data_ack_packet("peer", "relay"), peer.local_addr().unwrap(), &ctx).await;
+        let ack = recv_data_ack(&source);
+        assert_eq!((ack.node_id.as_str(), ack.dest_node.as_str(), ack.received_bitmap), ("peer", "relay", 0b1011));
+        assert!(manager.get_peer("peer").await.unwrap().last_ack.is_none());
+        
+        // 不在中继的数据流和冒充确认方的确认不转发
+        handle_data_ack(data_ack_packet("relay", "peer"), source.local_addr().unwrap(), &ctx).await;
+        handle_data_ack(data_ack_packet("peer", "relay"), addr("203.0.113.5:51820"), &ctx).await;
+        peer.set_nonblocking(true).unwrap();
+        source.set_nonblocking(true).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(peer.recv_from(&mut [0u8; 2048]).is_err());
+        assert!(source.recv_from(&mut [0u8; 2048]).is_err());
+    }
+    
+    #[tokio::test]
+    async fn relayed_data_ack_is_recorded_for_the_acking_peer() {
+        let (manager, _) = mock_network_manager(0).await;
+        let ctx = mock_context(&manager);
+        
+        // 不能中继的地址转发来的确认忽略
+        handle_data_ack(data_ack_packet("peer", "node-1"), addr("192.0.2.9:51820"), &ctx).await;
+        assert!(manager.get_peer("peer").await.unwrap().last_ack.is_none());
+        
+        let mut relay = Peer::new("relay".to_string(), "relay".to_string(), addr("192.0.2.9:51820"), "10.0.0.3".to_string(), vec![3u8; 32], 0);
+        relay.upstream = true;
+        manager.peers.write().await.insert("relay".to_string(), relay);
+        handle_data_ack(data_ack_packet("peer", "node-1"), addr("192.0.2.9:51820"), &ctx).await;
+        let peer = manager.get_peer("peer").await.unwrap();
+        assert_eq!(peer.last_ack.unwrap().seq, 4);
+        assert!((peer.stats.acked_loss_pct - 25.0).abs() < 1e-3);
+    }
+    
+    #[tokio::test]
+    async fn capabilities_reach_node_info_while_the_cache_is_busy() {
+        let (mut manager, _) = mock_network_manager(0).await;
+        let cache = manager.node_info_cache.clone();
+        let reader = cache.clone().read_owned().await;
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(reader);
+        });
+        
+        manager.set_capabilities(Capabilities::CAN_RELAY).await;
+        release.await.unwrap();
+        assert_eq!(cache.read().await.info().capabilities, Capabilities::CAN_RELAY.bits());
+        assert!(manager.capabilities().contains(Capabilities::CAN_RELAY));
+    }
+    
+    #[tokio::test]
+    async fn latency_prober_sends_while_the_manager_is_locked() {
+        let server = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let relay = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let manager = Arc::new(Mutex::new(udp_network_manager(server.local_addr().unwrap(), relay.local_addr().unwrap()).await));
+        let prober = manager.lock().await.latency_prober();
+        
+        let _guard = manager.lock().await;
+        assert_eq!(prober.peers().await.len(), 2);
+        let probe_id = tokio::time::timeout(Duration::from_secs(2), prober.send("peer", "relay")).await.unwrap().unwrap();
+        
+        server.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let mut buf = [0u8; 2048];
+        let (len, _) = server.recv_from(&mut buf).unwrap();
+        let packet = Packet::decode(&buf[..len]).unwrap();
+        assert_eq!(packet.msg_type, MessageType::LatencyProbe);
+        let probe: LatencyProbe = decode_payload(&packet.data, packet.flags).unwrap();
+        assert_eq!(probe.probe_id, probe_id);
+        assert_eq!(probe.target_peer_id, "relay");
+    }
+    
+    #[tokio::test]
+    async fn heartbeats_are_only_accepted_from_the_peer_address() {
+        let (manager, _) = mock_network_manager(0).await;
+        manager.peers.write().await.get_mut("peer").unwrap().status = NodeStatus::Offline;
+        let heartbeat = Heartbeat {
+            node_id: "peer".to_string(),
+            timestamp: current_unix_timestamp(),
+            load: 0.5,
+            uptime: 0,
+            packet_loss_pct: 40.0,
+            extensions: HashMap::from([("status".to_string(), serde_json::json!("busy"))]),
+            timestamp_ms: 0,
+            echo: None,
+        };
+        let packet = PacketBuilder::new(MessageType::Heartbeat, serde_json::to_vec(&heartbeat).unwrap()).build();
+        
+        handle_heartbeat(packet.clone(), addr("203.0.113.5:51820"), manager.peers.clone()).await;
+        let peer = manager.get_peer("peer").await.unwrap();
+        assert_eq!(peer.status, NodeStatus::Offline);
+        assert_eq!(peer.stats.remote_packet_loss_pct, 0.0);
+        assert!(peer.metadata.is_empty());
+        
+        handle_heartbeat(packet, addr("192.0.2.2:51820"), manager.peers.clone()).await;
+        let peer = manager.get_peer("peer").await.unwrap();
+        assert_eq!(peer.status, NodeStatus::Online);
+        assert_eq!(peer.stats.remote_packet_loss_pct, 40.0);
+        assert_eq!(peer.metadata["status"], "busy");
+    }
+}
@@ -1,211 +1,1415 @@
-/*!
-VPNet协议模块
-
-定义VPNet的网络协议，包括：
-- 消息类型和格式
-- 数据包结构
-- 协议常量
-- 状态码
-*/
-
-use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
-
-/// VPNet协议版本
-pub const PROTOCOL_VERSION: u8 = 1;
-
-/// 协议消息类型
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
-pub enum MessageType {
-    /// 握手请求
-    HandshakeRequest = 1,
-    /// 握手响应
-    HandshakeResponse = 2,
-    /// 节点发现
-    NodeDiscovery = 3,
-    /// 节点信息
-    NodeInfo = 4,
-    /// 数据转发
-    DataForward = 5,
-    /// 心跳包
-    Heartbeat = 6,
-    /// 路由更新
-    RouteUpdate = 7,
-    /// 连接关闭
-    ConnectionClose = 8,
-    /// 授权请求
-    AuthRequest = 9,
-    /// 授权响应
-    AuthResponse = 10,
-}
-
-/// 握手请求消息
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct HandshakeRequest {
-    pub version: u8,
-    pub public_key: Vec<u8>,
-    pub node_id: String,
-    pub node_name: String,
-    pub supported_protocols: Vec<u8>,
-    pub capabilities: u32,
-}
-
-/// 握手响应消息
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct HandshakeResponse {
-    pub version: u8,
-    pub public_key: Vec<u8>,
-    pub node_id: String,
-    pub node_name: String,
-    pub status: u8,
-    pub message: String,
-    pub session_key: Vec<u8>,
-}
-
-/// 节点信息
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct NodeInfo {
-    pub node_id: String,
-    pub node_name: String,
-    pub public_key: Vec<u8>,
-    pub address: SocketAddr,
-    pub virtual_ip: String,
-    pub subnet: String,
-    pub online: bool,
-    pub last_seen: u64,
-    pub capabilities: u32,
-}
-
-/// 数据转发消息
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DataForward {
-    pub source_node: String,
-    pub dest_node: String,
-    pub data: Vec<u8>,
-    pub protocol: u8, // 0x0800 for IPv4, 0x86DD for IPv6
-}
-
-/// 心跳包
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Heartbeat {
-    pub node_id: String,
-    pub timestamp: u64,
-    pub load: f32,
-    pub uptime: u64,
-}
-
-/// 路由更新
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct RouteUpdate {
-    pub node_id: String,
-    pub routes: Vec<RouteEntry>,
-}
-
-/// 路由条目
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct RouteEntry {
-    pub network: String,
-    pub mask: String,
-    pub gateway: String,
-    pub metric: u32,
-}
-
-/// 授权请求
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AuthRequest {
-    pub node_id: String,
-    pub public_key: Vec<u8>,
-    pub request_time: u64,
-    pub signature: Vec<u8>,
-}
-
-/// 授权响应
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AuthResponse {
-    pub node_id: String,
-    pub status: u8,
-    pub message: String,
-    pub token: Option<String>,
-    pub expires_at: Option<u64>,
-}
-
-/// VPNet数据包
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Packet {
-    pub magic: u32,          // 魔术字: VNET (0x564E4554)
-    pub version: u8,         // 协议版本
-    pub msg_type: MessageType, // 消息类型
-    pub flags: u8,           // 标志位
-    pub length: u16,         // 数据包长度
-    pub checksum: u16,       // 校验和
-    pub data: Vec<u8>,       // 数据包内容
-}
-
-/// 节点状态
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
-pub enum NodeStatus {
-    /// 离线
-    Offline = 0,
-    /// 在线
-    Online = 1,
-    /// 连接中
-    Connecting = 2,
-    /// 已授权
-    Authorized = 3,
-    /// 未授权
-    Unauthorized = 4,
-    /// 错误
-    Error = 5,
-}
-
-/// 协议常量
-pub mod constants {
-    /// 魔术字
-    pub const MAGIC: u32 = 0x564E4554; // "VNET"
-    
-    /// 最大消息长度
-    pub const MAX_MESSAGE_LENGTH: u16 = 16384;
-    
-    /// 心跳间隔（秒）
-    pub const HEARTBEAT_INTERVAL: u64 = 30;
-    
-    /// 超时时间（秒）
-    pub const TIMEOUT: u64 = 120;
-    
-    /// 重试次数
-    pub const MAX_RETRIES: u32 = 3;
-    
-    /// 默认MTU
-    pub const DEFAULT_MTU: u32 = 1420;
-}
-
-/// 计算数据包校验和
-pub fn calculate_checksum(data: &[u8]) -> u16 {
-    let mut sum: u32 = 0;
-    let mut i = 0;
-    let len = data.len();
-    
-    // 处理16位对齐的数据
-    while i < len - 1 {
-        sum += ((data[i] as u32) << 8) | data[i + 1] as u32;
-        i += 2;
-    }
-    
-    // 处理剩余的字节
-    if i < len {
-        sum += (data[i] as u32) << 8;
-    }
-    
-    // 折叠进位
-    while sum >> 16 != 0 {
-        sum = (sum & 0xFFFF) + (sum >> 16);
-    }
-    
-    // 取反
-    !sum as u16
-}
-
-/// 验证数据包校验和
-pub fn verify_checksum(data: &[u8], checksum: u16) -> bool {
-    calculate_checksum(data) == checksum
-}
+/*!
+VPNet协议模块
+
+定义VPNet的网络协议，包括：
+- 消息类型和格式
+- 数据包结构
+- 协议常量
+- 状态码
+*/
+
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+
+/// VPNet协议版本
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// 协议消息类型
+///
+/// 序列化名称是协议格式的一部分，保持变体原名以兼容旧版本节点；同时接受小写蛇形命名的别名。
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MessageType {
+    /// 握手请求
+    #[serde(alias = "handshake_request")]
+    HandshakeRequest = 1,
+    /// 握手响应
+    #[serde(alias = "handshake_response")]
+    HandshakeResponse = 2,
+    /// 节点发现
+    #[serde(alias = "node_discovery")]
+    NodeDiscovery = 3,
+    /// 节点信息
+    #[serde(alias = "node_info")]
+    NodeInfo = 4,
+    /// 数据转发
+    #[serde(alias = "data_forward")]
+    DataForward = 5,
+    /// 心跳包
+    #[serde(alias = "heartbeat")]
+    Heartbeat = 6,
+    /// 路由更新
+    #[serde(alias = "route_update")]
+    RouteUpdate = 7,
+    /// 连接关闭
+    #[serde(alias = "connection_close")]
+    ConnectionClose = 8,
+    /// 授权请求
+    #[serde(alias = "auth_request")]
+    AuthRequest = 9,
+    /// 授权响应
+    #[serde(alias = "auth_response")]
+    AuthResponse = 10,
+    /// TTL耗尽
+    #[serde(alias = "ttl_exceeded")]
+    TtlExceeded = 11,
+    /// 直连路径提议
+    #[serde(alias = "direct_path_offer")]
+    DirectPathOffer = 12,
+    /// 直连路径探测
+    #[serde(alias = "direct_path_probe")]
+    DirectPathProbe = 13,
+    /// 直连路径已建立
+    #[serde(alias = "direct_path_established")]
+    DirectPathEstablished = 14,
+    /// 虚拟IP分配
+    #[serde(alias = "ip_assignment")]
+    IpAssignment = 15,
+    /// 连接迁移验证
+    #[serde(alias = "migration_challenge")]
+    MigrationChallenge = 16,
+    /// 连接迁移验证响应
+    #[serde(alias = "migration_response")]
+    MigrationResponse = 17,
+    /// 会话票据
+    #[serde(alias = "session_ticket")]
+    SessionTicket = 18,
+    /// 数据转发确认
+    #[serde(alias = "data_ack")]
+    DataAck = 19,
+    /// 节点间时延探测
+    #[serde(alias = "latency_probe")]
+    LatencyProbe = 20,
+    /// 节点间时延探测回复
+    #[serde(alias = "latency_probe_reply")]
+    LatencyProbeReply = 21,
+}
+
+impl TryFrom<u8> for MessageType {
+    type Error = &'static str;
+    
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(MessageType::HandshakeRequest),
+            2 => Ok(MessageType::HandshakeResponse),
+            3 => Ok(MessageType::NodeDiscovery),
+            4 => Ok(MessageType::NodeInfo),
+            5 => Ok(MessageType::DataForward),
+            6 => Ok(MessageType::Heartbeat),
+            7 => Ok(MessageType::RouteUpdate),
+            8 => Ok(MessageType::ConnectionClose),
+            9 => Ok(MessageType::AuthRequest),
+            10 => Ok(MessageType::AuthResponse),
+            11 => Ok(MessageType::TtlExceeded),
+            12 => Ok(MessageType::DirectPathOffer),
+            13 => Ok(MessageType::DirectPathProbe),
+            14 => Ok(MessageType::DirectPathEstablished),
+            15 => Ok(MessageType::IpAssignment),
+            16 => Ok(MessageType::MigrationChallenge),
+            17 => Ok(MessageType::MigrationResponse),
+            18 => Ok(MessageType::SessionTicket),
+            19 => Ok(MessageType::DataAck),
+            20 => Ok(MessageType::LatencyProbe),
+            21 => Ok(MessageType::LatencyProbeReply),
+            _ => Err("Unknown message type"),
+        }
+    }
+}
+
+impl MessageType {
+    /// 全部消息类型
+    pub const ALL: [MessageType; 21] = [
+        MessageType::HandshakeRequest,
+        MessageType::HandshakeResponse,
+        MessageType::NodeDiscovery,
+        MessageType::NodeInfo,
+        MessageType::DataForward,
+        MessageType::Heartbeat,
+        MessageType::RouteUpdate,
+        MessageType::ConnectionClose,
+        MessageType::AuthRequest,
+        MessageType::AuthResponse,
+        MessageType::TtlExceeded,
+        MessageType::DirectPathOffer,
+        MessageType::DirectPathProbe,
+        MessageType::DirectPathEstablished,
+        MessageType::IpAssignment,
+        MessageType::MigrationChallenge,
+        MessageType::MigrationResponse,
+        MessageType::SessionTicket,
+        MessageType::DataAck,
+        MessageType::LatencyProbe,
+        MessageType::LatencyProbeReply,
+    ];
+    
+    /// 小写名称，用于命令行输出和管理API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageType::HandshakeRequest => "handshake_request",
+            MessageType::HandshakeResponse => "handshake_response",
+            MessageType::NodeDiscovery => "node_discovery",
+            MessageType::NodeInfo => "node_info",
+            MessageType::DataForward => "data_forward",
+            MessageType::Heartbeat => "heartbeat",
+            MessageType::RouteUpdate => "route_update",
+            MessageType::ConnectionClose => "connection_close",
+            MessageType::AuthRequest => "auth_request",
+            MessageType::AuthResponse => "auth_response",
+            MessageType::TtlExceeded => "ttl_exceeded",
+            MessageType::DirectPathOffer => "direct_path_offer",
+            MessageType::DirectPathProbe => "direct_path_probe",
+            MessageType::DirectPathEstablished => "direct_path_established",
+            MessageType::IpAssignment => "ip_assignment",
+            MessageType::MigrationChallenge => "migration_challenge",
+            MessageType::MigrationResponse => "migration_response",
+            MessageType::SessionTicket => "session_ticket",
+            MessageType::DataAck => "data_ack",
+            MessageType::LatencyProbe => "latency_probe",
+            MessageType::LatencyProbeReply => "latency_probe_reply",
+        }
+    }
+}
+
+impl fmt::Display for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromStr for MessageType {
+    type Err = &'static str;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter()
+            .find(|value| value.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or("Unknown message type")
+    }
+}
+
+/// 握手请求消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandshakeRequest {
+    pub version: u8,
+    pub public_key: Vec<u8>,
+    pub node_id: String,
+    pub node_name: String,
+    pub supported_protocols: Vec<u8>,
+    pub capabilities: u32,
+    #[serde(default)]
+    pub server_nonce: Option<Vec<u8>>, // 服务端签发的一次性随机数
+    #[serde(default)]
+    pub virtual_ip: Option<String>,    // 客户端期望使用的虚拟IP
+    #[serde(default)]
+    pub group_id: Option<String>,      // 旧版本客户端请求加入的节点分组，分组由管理员指定，服务端忽略该字段
+    #[serde(default)]
+    pub session_ticket: Option<Vec<u8>>, // 断开连接时服务端签发的会话票据
+    #[serde(default)]
+    pub client_version: String,        // 客户端软件版本，旧客户端不发送
+    #[serde(default)]
+    pub ephemeral_key: Option<Vec<u8>>, // 主动重新握手时携带的临时X25519公钥，双方据此协商会话密钥
+}
+
+/// 握手响应消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandshakeResponse {
+    pub version: u8,
+    pub public_key: Vec<u8>,
+    pub node_id: String,
+    pub node_name: String,
+    pub status: u8,
+    pub message: String,
+    pub session_key: Vec<u8>,
+    #[serde(default)]
+    pub virtual_ip: Option<String>,    // 服务端分配的虚拟IP
+    #[serde(default)]
+    pub conflict: Option<ConflictDetails>,
+    #[serde(default)]
+    pub connection_id: u64,            // 服务端分配的连接ID，0表示未分配
+    #[serde(default)]
+    pub resumed: bool,                 // 是否凭会话票据恢复了原会话
+    #[serde(default)]
+    pub server_version: String,        // 服务端软件版本
+    #[serde(default)]
+    pub migration_challenge: Option<[u8; 16]>, // 从新地址握手的已知节点需签名回应的迁移验证随机数
+    #[serde(default)]
+    pub ephemeral_key: Option<Vec<u8>>, // 回应请求中临时公钥的临时X25519公钥，此时不下发`session_key`
+}
+
+/// 虚拟IP冲突详情
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictDetails {
+    pub conflicting_node_id: String,
+}
+
+/// 节点能力标志
+///
+/// 握手请求、节点信息和对等节点中的`capabilities`字段按位组合这些标志，未知的标志位原样保留。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// 不具备任何能力
+    pub const NONE: Capabilities = Capabilities(0);
+    
+    /// 可以为其他节点中继流量
+    pub const CAN_RELAY: Capabilities = Capabilities(0x0000_0001);
+    
+    /// 由能力字段构造
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+    
+    /// 能力字段的值
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+    
+    /// 判断是否具备`other`中的全部能力
+    pub const fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+    
+    /// 具备的已知能力的名称，例如`can_relay`，未知的标志位不列出
+    pub fn names(self) -> Vec<&'static str> {
+        [(Self::CAN_RELAY, "can_relay")]
+            .into_iter()
+            .filter(|(capability, _)| self.contains(*capability))
+            .map(|(_, name)| name)
+            .collect()
+    }
+}
+
+impl std::ops::BitOr for Capabilities {
+    type Output = Self;
+    
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for Capabilities {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// 节点信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeInfo {
+    pub node_id: String,
+    pub node_name: String,
+    pub public_key: Vec<u8>,
+    pub address: SocketAddr,
+    pub virtual_ip: String,
+    pub subnet: String,
+    pub online: bool,
+    pub last_seen: u64,
+    pub capabilities: u32,
+    #[serde(default)]
+    pub geographic_location: Option<GeoLocation>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tags: HashMap<String, String>, // 管理员为节点设置的标签
+}
+
+/// 地理位置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeoLocation {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub country_code: String,
+}
+
+impl GeoLocation {
+    /// 地球平均半径（千米）
+    const EARTH_RADIUS_KM: f64 = 6371.0;
+    
+    /// 使用Haversine公式计算两地之间的大圆距离（千米）
+    pub fn distance_km(&self, other: &GeoLocation) -> f64 {
+        let lat1 = self.latitude.to_radians();
+        let lat2 = other.latitude.to_radians();
+        let delta_lat = (other.latitude - self.latitude).to_radians();
+        let delta_lon = (other.longitude - self.longitude).to_radians();
+        
+        let a = (delta_lat / 2.0).sin().powi(2)
+            + lat1.cos() * lat2.cos() * (delta_lon / 2.0).sin().powi(2);
+        let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+        
+        Self::EARTH_RADIUS_KM * c
+    }
+}
+
+/// 数据转发消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataForward {
+    pub source_node: String,
+    pub dest_node: String,
+    pub data: Vec<u8>,
+    pub protocol: u16, // EtherType: 0x0800 for IPv4, 0x86DD for IPv6
+    #[serde(default = "default_ttl")]
+    pub ttl: u8,     // 剩余跳数，每经过一跳减一，为零时丢弃
+    #[serde(default)]
+    pub seq: u32,    // 发送方对每个目标节点递增的序列号，用于丢包估计
+    #[serde(default)]
+    pub priority: u8, // 流量优先级，0最高
+    #[serde(default)]
+    pub compressed: bool, // 负载在加密前经过压缩
+    #[serde(default = "default_source_virtual_ip")]
+    pub source_virtual_ip: Ipv4Addr, // 源节点的虚拟IP，由发送方填写、中继按节点记录改写，未知时为0.0.0.0
+    #[serde(default)]
+    pub ack_requested: bool, // 请求接收方回复DataAck，用于估计发送方向的丢包
+}
+
+/// 数据转发确认
+///
+/// 收到`ack_requested`的数据转发后回复给源节点。`received_bitmap`的第i位表示序列号`seq - i`已收到，
+/// 乱序到达的数据包同样记入。数据转发经中继到达时确认发往该中继，由中继按`dest_node`转发。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataAck {
+    pub node_id: String,      // 确认方节点ID
+    #[serde(default)]
+    pub dest_node: String,    // 被确认的源节点ID，为空时即收到确认的节点
+    pub seq: u32,             // 确认方收到的最大序列号
+    pub received_count: u32,  // 确认方累计收到的数据包数
+    pub received_bitmap: u64, // 最近64个序列号的接收情况，第0位对应`seq`
+}
+
+/// 心跳包
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub node_id: String,
+    pub timestamp: u64,
+    pub load: f32,
+    pub uptime: u64,
+    #[serde(default)]
+    pub packet_loss_pct: f32, // 发送方观测到的来自接收方的丢包率
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub extensions: HashMap<String, serde_json::Value>, // 节点状态等扩展信息
+    #[serde(default)]
+    pub timestamp_ms: u64, // 发送时间（Unix毫秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub echo: Option<HeartbeatEcho>, // 回显对方最近一次心跳，用于测量往返时延
+}
+
+/// 心跳回显
+///
+/// 发送方据此计算往返时延：`当前时间 - timestamp_ms - delay_ms`。
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HeartbeatEcho {
+    pub timestamp_ms: u64, // 对方心跳的发送时间
+    pub delay_ms: u64,     // 收到该心跳到发出回显之间的间隔
+}
+
+/// TTL耗尽通知
+///
+/// 中继节点丢弃TTL耗尽的数据转发时回复给源节点；目标节点收到探测包时也以此回复，
+/// 此时`hop_node_id`即为目标节点。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TtlExceededMessage {
+    pub original_src: String,
+    pub original_dst: String,
+    pub hop_node_id: String,
+    #[serde(default)]
+    pub seq: u32,         // 被丢弃数据包的序列号，用于匹配探测请求
+}
+
+/// 直连路径提议
+///
+/// 服务端作为信令通道，将一个节点观测到的公网地址告知另一个节点，双方随后
+/// 互相发送直连探测以打通NAT。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectPathOffer {
+    pub peer_id: String,
+    pub address: SocketAddr,
+    /// 服务端为这次提议生成的随机令牌，双方的探测都需携带
+    pub token: [u8; 16],
+}
+
+/// 直连路径探测
+///
+/// 只有携带对应提议的令牌、且来自提议中地址的探测才会被接受。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectPathProbe {
+    pub node_id: String,
+    pub token: [u8; 16],
+}
+
+/// 直连路径已建立，发送给提议直连的服务端
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectPathEstablished {
+    pub node_id: String,
+    pub peer_id: String,
+}
+
+/// 节点间时延探测
+///
+/// 节点发送给服务端，服务端转发给`target_peer_id`，目标节点经服务端回复`LatencyProbeReply`，
+/// 服务端据此记录两个节点之间的往返时延。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyProbe {
+    pub probe_id: u32,
+    pub target_peer_id: String,
+}
+
+/// 节点间时延探测回复
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyProbeReply {
+    pub probe_id: u32,
+}
+
+/// 虚拟IP分配，服务端重新分配节点的虚拟IP时发送
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpAssignment {
+    pub virtual_ip: String,
+    pub subnet: String,
+}
+
+/// 连接迁移验证
+///
+/// 收到携带已知连接ID、但来自新地址的数据包时，向新地址发送随机数，
+/// 节点需用其签名私钥签名后回复，验证通过才更新节点地址。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationChallenge {
+    pub nonce: [u8; 16],
+}
+
+/// 连接迁移验证响应
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationResponse {
+    pub nonce: [u8; 16],
+    pub signature: Vec<u8>,
+}
+
+/// 连接关闭
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionClose {
+    pub node_id: String,
+    pub reason: String,
+}
+
+/// 会话票据
+///
+/// 节点正常断开连接时由服务端签发，节点在有效期内重新连接时放入握手请求，
+/// 服务端据此直接恢复原会话。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionTicket {
+    pub ticket: Vec<u8>,
+    /// 有效期（秒）
+    pub lifetime: u64,
+}
+
+/// 路由更新
+///
+/// 由发送节点的签名密钥签名，接收方用握手时记录的公钥验证，防止伪造路由。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteUpdate {
+    pub node_id: String,
+    pub routes: Vec<RouteEntry>,
+    #[serde(default)]
+    pub timestamp: u64,
+    #[serde(default)]
+    pub signature: Vec<u8>,
+}
+
+/// 路由条目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteEntry {
+    pub network: String,
+    pub mask: String,
+    pub gateway: String,
+    pub metric: u32,
+}
+
+/// 授权请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthRequest {
+    pub node_id: String,
+    pub public_key: Vec<u8>,
+    pub request_time: u64,
+    pub signature: Vec<u8>,
+}
+
+/// 授权响应
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthResponse {
+    pub node_id: String,
+    pub status: u8,
+    pub message: String,
+    pub token: Option<String>,
+    pub expires_at: Option<u64>,
+}
+
+/// VPNet数据包
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Packet {
+    pub magic: u32,          // 魔术字: VNET (0x564E4554)
+    pub version: u8,         // 协议版本
+    pub msg_type: MessageType, // 消息类型
+    pub flags: u8,           // 标志位
+    pub length: u16,         // 数据包长度
+    pub checksum: u16,       // 校验和
+    pub data: Vec<u8>,       // 数据包内容
+    #[serde(default)]
+    pub connection_id: u64,  // 连接ID，节点地址变化后据此识别节点，0表示未携带
+}
+
+/// 节点状态
+///
+/// 序列化名称与旧版本节点保存的数据一致，保持变体原名；同时接受小写蛇形命名的别名。
+/// 管理API以`lowercase_name`输出小写名称。
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NodeStatus {
+    /// 离线
+    #[serde(alias = "offline")]
+    Offline = 0,
+    /// 在线
+    #[serde(alias = "online")]
+    Online = 1,
+    /// 连接中
+    #[serde(alias = "connecting")]
+    Connecting = 2,
+    /// 已授权
+    #[serde(alias = "authorized")]
+    Authorized = 3,
+    /// 未授权
+    #[serde(alias = "unauthorized")]
+    Unauthorized = 4,
+    /// 错误
+    #[serde(alias = "error")]
+    Error = 5,
+    /// 服务端节点数已满
+    #[serde(alias = "capacity_exceeded")]
+    CapacityExceeded = 6,
+}
+
+impl NodeStatus {
+    /// 全部节点状态
+    pub const ALL: [NodeStatus; 7] = [
+        NodeStatus::Offline,
+        NodeStatus::Online,
+        NodeStatus::Connecting,
+        NodeStatus::Authorized,
+        NodeStatus::Unauthorized,
+        NodeStatus::Error,
+        NodeStatus::CapacityExceeded,
+    ];
+    
+    /// 小写名称，用于命令行输出和管理API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeStatus::Offline => "offline",
+            NodeStatus::Online => "online",
+            NodeStatus::Connecting => "connecting",
+            NodeStatus::Authorized => "authorized",
+            NodeStatus::Unauthorized => "unauthorized",
+            NodeStatus::Error => "error",
+            NodeStatus::CapacityExceeded => "capacity_exceeded",
+        }
+    }
+}
+
+impl fmt::Display for NodeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromStr for NodeStatus {
+    type Err = &'static str;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter()
+            .find(|value| value.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or("Unknown node status")
+    }
+}
+
+/// 以`Display`和`FromStr`读写枚举的小写名称
+///
+/// 用于管理API的响应，配合`#[serde(with = "vpnet::protocol::lowercase_name")]`使用；
+/// 协议消息中的枚举保持原来的序列化名称。
+pub mod lowercase_name {
+    use std::fmt::Display;
+    use std::str::FromStr;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    
+    pub fn serialize<T: Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+    
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+    }
+}
+
+/// 握手响应状态码
+pub mod status {
+    /// 握手成功
+    pub const OK: u8 = 0;
+    
+    /// 随机数缺失、过期或已被使用
+    pub const INVALID_NONCE: u8 = 1;
+    
+    /// 请求的虚拟IP已被其他节点占用
+    pub const VIRTUAL_IP_CONFLICT: u8 = 2;
+    
+    /// 没有可分配的虚拟IP
+    pub const NO_VIRTUAL_IP: u8 = 3;
+    
+    /// 请求的节点名称已被其他节点使用
+    pub const NAME_CONFLICT: u8 = 4;
+    
+    /// 节点出示的公钥与缓存的不一致
+    pub const KEY_MISMATCH: u8 = 5;
+    
+    /// 授权请求签名无效或已过期
+    pub const AUTH_FAILED: u8 = 6;
+    
+    /// 请求加入的节点分组不存在
+    pub const UNKNOWN_GROUP: u8 = 7;
+    
+    /// 服务端节点数已达上限
+    pub const CAPACITY_EXCEEDED: u8 = 8;
+    
+    /// 客户端版本不在服务端的兼容范围内
+    pub const VERSION_INCOMPATIBLE: u8 = 9;
+}
+
+/// 内置的心跳扩展字段
+pub mod extensions {
+    /// VPNet版本
+    pub const VPNET_VERSION: &str = "vpnet_version";
+    
+    /// 操作系统
+    pub const OS: &str = "os";
+    
+    /// 路由条目数量
+    pub const ROUTES_COUNT: &str = "routes_count";
+}
+
+/// 协议常量
+pub mod constants {
+    /// 魔术字
+    pub const MAGIC: u32 = 0x564E4554; // "VNET"
+    
+    /// 最大消息长度
+    pub const MAX_MESSAGE_LENGTH: u16 = 16384;
+    
+    /// 心跳间隔（秒）
+    pub const HEARTBEAT_INTERVAL: u64 = 30;
+    
+    /// 超时时间（秒）
+    pub const TIMEOUT: u64 = 120;
+    
+    /// 重试次数
+    pub const MAX_RETRIES: u32 = 3;
+    
+    /// 默认MTU
+    pub const DEFAULT_MTU: u32 = 1420;
+    
+    /// 节点信息缓存刷新间隔（秒）
+    pub const NODE_INFO_REFRESH_INTERVAL: u64 = 60;
+    
+    /// 同一来源的节点发现响应间隔（秒）
+    pub const DISCOVERY_RATE_LIMIT: u64 = 5;
+    
+    /// 向同一网段广播节点发现的最小间隔（秒）
+    pub const BROADCAST_DISCOVERY_INTERVAL: u64 = 10;
+    
+    /// 数据转发的默认TTL
+    pub const DEFAULT_TTL: u8 = 64;
+    
+    /// 环路告警日志的最小间隔（秒）
+    pub const LOOP_WARNING_INTERVAL: u64 = 10;
+    
+    /// DSCP加速转发（EF）
+    pub const DSCP_EF: u8 = 0x2E;
+    
+    /// DSCP确保转发（AF41）
+    pub const DSCP_AF41: u8 = 0x22;
+    
+    /// DSCP默认尽力而为（CS0）
+    pub const DSCP_CS0: u8 = 0x00;
+    
+    /// 从虚拟设备读取的数据默认按尽力而为的优先级转发
+    pub const DEFAULT_DATA_PRIORITY: u8 = 4;
+    
+    /// 标志位：负载使用bincode编码（握手包中表示支持bincode）
+    pub const FLAG_BINCODE: u8 = 0x01;
+    
+    /// 负载标志：数据包和数据转发使用定长二进制头部，不经过序列化
+    pub const FLAG_RAW_FRAME: u8 = 0x02;
+    
+    /// 标志位：数据转发的负载在加密前经过LZ4压缩（握手包中表示支持解压）
+    pub const FLAG_COMPRESSED: u8 = 0x08;
+    
+    /// 标志位：二进制头部之后紧跟8字节的连接ID
+    pub const FLAG_CONNECTION_ID: u8 = 0x04;
+    
+    /// 标志位：二进制数据转发头部之后紧跟4字节的源虚拟IP（握手包中表示支持）
+    pub const FLAG_SOURCE_IP: u8 = 0x10;
+    
+    /// 标志位：数据转发请求接收方回复DataAck
+    pub const FLAG_ACK_REQUESTED: u8 = 0x20;
+    
+    /// 默认每100个数据转发请求一次确认
+    pub const DEFAULT_ACK_SAMPLING_RATE: u32 = 100;
+    
+    /// 同一对节点之间时延探测的最小间隔（秒）
+    pub const LATENCY_PROBE_INTERVAL: u64 = 30;
+    
+    /// 服务端等待时延探测回复的最长时间（秒）
+    pub const LATENCY_PROBE_TIMEOUT: u64 = 10;
+    
+    /// 二进制数据转发头部中节点ID的定长字节数，不足时补零
+    pub const RAW_NODE_ID_LEN: usize = 32;
+    
+    /// 二进制数据转发头部长度：源节点、目标节点、协议、TTL、优先级、序列号
+    pub const RAW_FORWARD_HEADER_LEN: usize = RAW_NODE_ID_LEN * 2 + 2 + 1 + 1 + 4;
+    
+    /// 二进制数据包头部长度：魔术字、版本、类型、标志、长度、校验和
+    pub const RAW_PACKET_HEADER_LEN: usize = 4 + 1 + 1 + 1 + 2 + 2;
+    
+    /// 节点地址迁移宽限期（秒）
+    pub const MIGRATION_GRACE_PERIOD: u64 = 30;
+    
+    /// 路径探测数据包使用的协议类型（IEEE 802本地实验用EtherType）
+    pub const PROBE_PROTOCOL: u16 = 0x88B5;
+    
+    /// 直连路径超时（秒），超过该时间未经直连收到心跳时回退到中继
+    pub const DIRECT_PATH_TIMEOUT: u64 = HEARTBEAT_INTERVAL * 3;
+    
+    /// 多路径的故障切换时间（秒），路径上发出数据后超过该时间未收到回复时改用其他路径
+    pub const MULTIPATH_FAILOVER_TIMEOUT: u64 = HEARTBEAT_INTERVAL * 2;
+    
+    /// 中继会话空闲超时（秒），超过该时间没有中继数据包时结束会话
+    pub const RELAY_SESSION_TIMEOUT: u64 = 120;
+    
+    /// 链路聚合的默认故障切换时间（秒），主链路超过该时间未收到回复时切换到备用链路
+    pub const LINK_FAILOVER_TIMEOUT: u64 = HEARTBEAT_INTERVAL * 3;
+    
+    /// 数据包调度器中每个节点最多排队的数据包数，超出时丢弃新数据包
+    pub const SCHEDULER_QUEUE_LEN: usize = 1024;
+    
+    /// 滚动窗口统计的采样间隔（秒）
+    pub const STATS_SAMPLE_INTERVAL: u64 = 1;
+    
+    /// 连接迁移验证的有效期（秒）
+    pub const MIGRATION_CHALLENGE_TIMEOUT: u64 = 10;
+    
+    /// 同时等待验证的连接迁移数上限，超出后不再签发新的验证
+    pub const MAX_PENDING_MIGRATIONS: usize = 1024;
+    
+    /// 收到直连提议后发送的探测次数
+    pub const DIRECT_PATH_PROBES: u32 = 3;
+    
+    /// 看门狗超时（秒），需大于心跳间隔
+    pub const WATCHDOG_INTERVAL: u64 = HEARTBEAT_INTERVAL * 2;
+    
+    /// 路由更新的时间与本地时间允许的最大偏差（秒），超出时视为重放
+    pub const ROUTE_UPDATE_MAX_SKEW: u64 = 300;
+}
+
+/// 数据转发TTL的默认值，兼容未携带该字段的旧节点
+fn default_ttl() -> u8 {
+    constants::DEFAULT_TTL
+}
+
+/// 源虚拟IP的默认值，兼容未携带该字段的旧节点
+fn default_source_virtual_ip() -> Ipv4Addr {
+    Ipv4Addr::UNSPECIFIED
+}
+
+/// 计算数据转发的初始TTL
+pub fn initial_ttl(max_hops: u8) -> u8 {
+    max_hops.min(constants::DEFAULT_TTL)
+}
+
+/// 本节点支持的负载编码标志
+pub fn supported_payload_flags() -> u8 {
+    if cfg!(feature = "bincode-protocol") {
+        constants::FLAG_BINCODE | constants::FLAG_RAW_FRAME | constants::FLAG_COMPRESSED | constants::FLAG_SOURCE_IP
+    } else {
+        constants::FLAG_RAW_FRAME | constants::FLAG_COMPRESSED | constants::FLAG_SOURCE_IP
+    }
+}
+
+impl Packet {
+    /// 编码数据包，带有`FLAG_RAW_FRAME`标志时使用二进制头部，否则使用JSON
+    pub fn encode(&self) -> Result<Vec<u8>, &'static str> {
+        if self.flags & constants::FLAG_RAW_FRAME == 0 {
+            return serde_json::to_vec(self).map_err(|_| "Serialization failed");
+        }
+        
+        let mut flags = self.flags & !constants::FLAG_CONNECTION_ID;
+        if self.connection_id != 0 {
+            flags |= constants::FLAG_CONNECTION_ID;
+        }
+        
+        let mut buf = Vec::with_capacity(constants::RAW_PACKET_HEADER_LEN + 8 + self.data.len());
+        buf.extend_from_slice(&self.magic.to_be_bytes());
+        buf.push(self.version);
+        buf.push(self.msg_type as u8);
+        buf.push(flags);
+        buf.extend_from_slice(&self.length.to_be_bytes());
+        buf.extend_from_slice(&self.checksum.to_be_bytes());
+        if self.connection_id != 0 {
+            buf.extend_from_slice(&self.connection_id.to_be_bytes());
+        }
+        buf.extend_from_slice(&self.data);
+        Ok(buf)
+    }
+    
+    /// 解码数据包，以魔术字开头的为二进制格式，否则按JSON解析
+    pub fn decode(data: &[u8]) -> Result<Self, &'static str> {
+        if !data.starts_with(&constants::MAGIC.to_be_bytes()) {
+            return serde_json::from_slice(data).map_err(|_| "Deserialization failed");
+        }
+        
+        if data.len() < constants::RAW_PACKET_HEADER_LEN {
+            return Err("Packet too short");
+        }
+        
+        let flags = data[6];
+        let mut payload = &data[constants::RAW_PACKET_HEADER_LEN..];
+        let mut connection_id = 0;
+        if flags & constants::FLAG_CONNECTION_ID != 0 {
+            if payload.len() < 8 {
+                return Err("Packet too short");
+            }
+            let (id, rest) = payload.split_at(8);
+            connection_id = u64::from_be_bytes(id.try_into().unwrap());
+            payload = rest;
+        }
+        
+        Ok(Self {
+            magic: constants::MAGIC,
+            version: data[4],
+            msg_type: MessageType::try_from(data[5])?,
+            flags: flags & !constants::FLAG_CONNECTION_ID,
+            length: u16::from_be_bytes([data[7], data[8]]),
+            checksum: u16::from_be_bytes([data[9], data[10]]),
+            data: payload.to_vec(),
+            connection_id,
+        })
+    }
+}
+
+/// 数据包构造器
+///
+/// 自动填写魔术字、协议版本、长度和校验和，避免手工填写出错导致对端静默丢弃数据包。
+#[derive(Debug, Clone)]
+pub struct PacketBuilder {
+    msg_type: MessageType,
+    flags: u8,
+    data: Vec<u8>,
+    connection_id: u64,
+}
+
+impl PacketBuilder {
+    /// 以消息类型和负载创建构造器，标志位和连接ID默认为0
+    pub fn new(msg_type: MessageType, data: Vec<u8>) -> Self {
+        Self { msg_type, flags: 0, data, connection_id: 0 }
+    }
+    
+    /// 设置全部标志位
+    pub fn flags(mut self, flags: u8) -> Self {
+        self.flags = flags;
+        self
+    }
+    
+    /// 增加一个标志位，例如`constants::FLAG_COMPRESSED`
+    pub fn flag(mut self, flag: u8) -> Self {
+        self.flags |= flag;
+        self
+    }
+    
+    /// 设置连接ID
+    pub fn connection_id(mut self, connection_id: u64) -> Self {
+        self.connection_id = connection_id;
+        self
+    }
+    
+    /// 构造数据包，长度和校验和按负载计算
+    pub fn build(self) -> Packet {
+        Packet {
+            magic: constants::MAGIC,
+            version: PROTOCOL_VERSION,
+            msg_type: self.msg_type,
+            flags: self.flags,
+            length: self.data.len() as u16,
+            checksum: calculate_checksum(&self.data),
+            data: self.data,
+            connection_id: self.connection_id,
+        }
+    }
+}
+
+impl AuthRequest {
+    /// 创建授权请求，使用节点的签名密钥对请求内容签名
+    pub fn signed(node_id: &str, key_pair: &crate::crypto::KeyPair, request_time: u64) -> Self {
+        let mut req = Self {
+            node_id: node_id.to_string(),
+            public_key: key_pair.public_key.clone(),
+            request_time,
+            signature: Vec::new(),
+        };
+        req.signature = key_pair.sign(&req.signing_payload());
+        req
+    }
+    
+    /// 被签名的内容：节点ID、公钥和请求时间
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(self.node_id.len() + self.public_key.len() + 10);
+        payload.extend_from_slice(&(self.node_id.len() as u16).to_be_bytes());
+        payload.extend_from_slice(self.node_id.as_bytes());
+        payload.extend_from_slice(&self.public_key);
+        payload.extend_from_slice(&self.request_time.to_be_bytes());
+        payload
+    }
+    
+    /// 验证签名是否由请求中的公钥生成
+    pub fn verify_signature(&self) -> bool {
+        crate::crypto::KeyPair::verify(&self.public_key, &self.signing_payload(), &self.signature)
+    }
+}
+
+impl RouteUpdate {
+    /// 创建路由更新，使用节点的签名密钥签名
+    pub fn signed(node_id: &str, routes: Vec<RouteEntry>, key_pair: &crate::crypto::KeyPair, timestamp: u64) -> Self {
+        let mut update = Self {
+            node_id: node_id.to_string(),
+            routes,
+            timestamp,
+            signature: Vec::new(),
+        };
+        update.signature = key_pair.sign(&update.signing_payload());
+        update
+    }
+    
+    /// 被签名的内容：固定前缀、节点ID、路由条目和时间
+    pub fn signing_payload(&self) -> Vec<u8> {
+        // 路由条目是字段固定的结构体，JSON编码结果确定
+        let routes = serde_json::to_vec(&self.routes).unwrap_or_default();
+        let mut payload = Vec::with_capacity(20 + self.node_id.len() + routes.len() + 8);
+        payload.extend_from_slice(b"vpnet-route-update\0");
+        payload.extend_from_slice(&(self.node_id.len() as u16).to_be_bytes());
+        payload.extend_from_slice(self.node_id.as_bytes());
+        payload.extend_from_slice(&routes);
+        payload.extend_from_slice(&self.timestamp.to_be_bytes());
+        payload
+    }
+    
+    /// 验证签名是否由`public_key`对应的私钥生成
+    pub fn verify_signature(&self, public_key: &[u8]) -> bool {
+        crate::crypto::KeyPair::verify(public_key, &self.signing_payload(), &self.signature)
+    }
+}
+
+impl DataAck {
+    /// 确认窗口内发送方向的丢包率（0.0 - 1.0）
+    ///
+    /// 发送方的序列号从1开始递增，不超过`seq`的最近64个序列号中未在位图中出现的视为丢失。
+    pub fn loss_rate(&self) -> f64 {
+        let span = self.seq.min(64);
+        if span == 0 {
+            return 0.0;
+        }
+        let mask = if span >= 64 { u64::MAX } else { (1u64 << span) - 1 };
+        let received = (self.received_bitmap & mask).count_ones();
+        (span - received) as f64 / span as f64
+    }
+}
+
+impl MigrationResponse {
+    /// 被签名的内容：固定前缀、随机数和响应节点的ID
+    pub fn signing_payload(nonce: &[u8; 16], node_id: &str) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(16 + nonce.len() + node_id.len());
+        payload.extend_from_slice(b"vpnet-migration\0");
+        payload.extend_from_slice(nonce);
+        payload.extend_from_slice(node_id.as_bytes());
+        payload
+    }
+}
+
+impl DataForward {
+    /// 编码为定长二进制头部加加密负载，`with_source_ip`时头部之后附加源虚拟IP
+    pub fn encode_raw(&self, with_source_ip: bool) -> Result<Vec<u8>, &'static str> {
+        let mut buf = Vec::with_capacity(constants::RAW_FORWARD_HEADER_LEN + 4 + self.data.len());
+        write_raw_node_id(&mut buf, &self.source_node)?;
+        write_raw_node_id(&mut buf, &self.dest_node)?;
+        buf.extend_from_slice(&self.protocol.to_be_bytes());
+        buf.push(self.ttl);
+        buf.push(self.priority);
+        buf.extend_from_slice(&self.seq.to_be_bytes());
+        if with_source_ip {
+            buf.extend_from_slice(&self.source_virtual_ip.octets());
+        }
+        buf.extend_from_slice(&self.data);
+        Ok(buf)
+    }
+    
+    /// 从二进制格式解码
+    pub fn decode_raw(data: &[u8], with_source_ip: bool) -> Result<Self, &'static str> {
+        let header_len = if with_source_ip {
+            constants::RAW_FORWARD_HEADER_LEN + 4
+        } else {
+            constants::RAW_FORWARD_HEADER_LEN
+        };
+        if data.len() < header_len {
+            return Err("Raw frame too short");
+        }
+        
+        let id_len = constants::RAW_NODE_ID_LEN;
+        let header = &data[id_len * 2..header_len];
+        let source_virtual_ip = if with_source_ip {
+            Ipv4Addr::new(header[8], header[9], header[10], header[11])
+        } else {
+            Ipv4Addr::UNSPECIFIED
+        };
+        Ok(Self {
+            source_node: read_raw_node_id(&data[..id_len])?,
+            dest_node: read_raw_node_id(&data[id_len..id_len * 2])?,
+            protocol: u16::from_be_bytes([header[0], header[1]]),
+            ttl: header[2],
+            priority: header[3],
+            seq: u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+            data: data[header_len..].to_vec(),
+            compressed: false,
+            source_virtual_ip,
+            ack_requested: false,
+        })
+    }
+}
+
+fn write_raw_node_id(buf: &mut Vec<u8>, node_id: &str) -> Result<(), &'static str> {
+    let bytes = node_id.as_bytes();
+    if bytes.len() > constants::RAW_NODE_ID_LEN {
+        return Err("Node ID too long for raw frame");
+    }
+    
+    buf.extend_from_slice(bytes);
+    buf.resize(buf.len() + constants::RAW_NODE_ID_LEN - bytes.len(), 0);
+    Ok(())
+}
+
+fn read_raw_node_id(data: &[u8]) -> Result<String, &'static str> {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8(data[..end].to_vec()).map_err(|_| "Invalid node ID in raw frame")
+}
+
+/// 编码数据转发消息，返回编码结果和实际使用的负载标志
+///
+/// 协商了`FLAG_RAW_FRAME`时使用二进制格式；节点ID超长无法放入定长头部时回退到序列化编码。
+pub fn encode_data_forward(forward: &DataForward, flags: u8) -> Result<(Vec<u8>, u8), &'static str> {
+    // 二进制头部没有压缩和确认字段，由数据包标志位携带
+    let flags = if forward.compressed {
+        flags | constants::FLAG_COMPRESSED
+    } else {
+        flags & !constants::FLAG_COMPRESSED
+    };
+    let flags = if forward.ack_requested {
+        flags | constants::FLAG_ACK_REQUESTED
+    } else {
+        flags & !constants::FLAG_ACK_REQUESTED
+    };
+    
+    if flags & constants::FLAG_RAW_FRAME != 0 {
+        if let Ok(data) = forward.encode_raw(flags & constants::FLAG_SOURCE_IP != 0) {
+            return Ok((data, flags));
+        }
+    }
+    
+    let flags = flags & !constants::FLAG_RAW_FRAME;
+    Ok((encode_payload(forward, flags)?, flags))
+}
+
+/// 按数据包标志位解码数据转发消息
+pub fn decode_data_forward(data: &[u8], flags: u8) -> Result<DataForward, &'static str> {
+    if flags & constants::FLAG_RAW_FRAME != 0 {
+        let mut forward = DataForward::decode_raw(data, flags & constants::FLAG_SOURCE_IP != 0)?;
+        forward.compressed = flags & constants::FLAG_COMPRESSED != 0;
+        forward.ack_requested = flags & constants::FLAG_ACK_REQUESTED != 0;
+        Ok(forward)
+    } else {
+        decode_payload(data, flags)
+    }
+}
+
+/// 按数据包标志位编码负载
+pub fn encode_payload<T: Serialize>(value: &T, flags: u8) -> Result<Vec<u8>, &'static str> {
+    if flags & constants::FLAG_BINCODE != 0 {
+        #[cfg(feature = "bincode-protocol")]
+        return bincode::serde::encode_to_vec(value, bincode::config::standard())
+            .map_err(|_| "Serialization failed");
+        
+        #[cfg(not(feature = "bincode-protocol"))]
+        return Err("Unsupported payload encoding");
+    }
+    
+    serde_json::to_vec(value).map_err(|_| "Serialization failed")
+}
+
+/// 按数据包标志位解码负载
+pub fn decode_payload<T: DeserializeOwned>(data: &[u8], flags: u8) -> Result<T, &'static str> {
+    if flags & constants::FLAG_BINCODE != 0 {
+        #[cfg(feature = "bincode-protocol")]
+        return bincode::serde::decode_from_slice(data, bincode::config::standard())
+            .map(|(value, _)| value)
+            .map_err(|_| "Deserialization failed");
+        
+        #[cfg(not(feature = "bincode-protocol"))]
+        return Err("Unsupported payload encoding");
+    }
+    
+    serde_json::from_slice(data).map_err(|_| "Deserialization failed")
+}
+
+/// 计算数据包校验和
+pub fn calculate_checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    let mut i = 0;
+    let len = data.len();
+    
+    // 处理16位对齐的数据，空负载时不进入循环
+    while i + 1 < len {
+        sum += ((data[i] as u32) << 8) | data[i + 1] as u32;
+        i += 2;
+    }
+    
+    // 处理剩余的字节
+    if i < len {
+        sum += (data[i] as u32) << 8;
+    }
+    
+    // 折叠进位
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    
+    // 取反
+    !sum as u16
+}
+
+/// 验证数据包校验和
+pub fn verify_checksum(data: &[u8], checksum: u16) -> bool {
+    calculate_checksum(data) == checksum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn location(latitude: f64, longitude: f64) -> GeoLocation {
+        GeoLocation { latitude, longitude, country_code: String::new() }
+    }
+    
+    fn assert_distance(a: &GeoLocation, b: &GeoLocation, expected_km: f64) {
+        let distance = a.distance_km(b);
+        // 球面模型与大地测量结果相差不到0.5%
+        assert!((distance - expected_km).abs() / expected_km < 0.005, "expected ~{} km, got {}", expected_km, distance);
+    }
+    
+    #[test]
+    fn haversine_distance_between_known_cities() {
+        let london = location(51.5074, -0.1278);
+        let paris = location(48.8566, 2.3522);
+        let new_york = location(40.7128, -74.0060);
+        let los_angeles = location(34.0522, -118.2437);
+        let sydney = location(-33.8688, 151.2093);
+        let tokyo = location(35.6762, 139.6503);
+        
+        assert_distance(&london, &paris, 343.6);
+        assert_distance(&new_york, &los_angeles, 3936.0);
+        assert_distance(&london, &new_york, 5570.0);
+        assert_distance(&sydney, &tokyo, 7823.0);
+    }
+    
+    #[test]
+    fn haversine_distance_edge_cases() {
+        let beijing = location(39.9042, 116.4074);
+        assert_eq!(beijing.distance_km(&beijing), 0.0);
+        
+        let shanghai = location(31.2304, 121.4737);
+        assert_eq!(beijing.distance_km(&shanghai), shanghai.distance_km(&beijing));
+        
+        // 对跖点相距半个大圆
+        let antipode = location(-39.9042, 116.4074 - 180.0);
+        assert_distance(&beijing, &antipode, std::f64::consts::PI * GeoLocation::EARTH_RADIUS_KM);
+    }
+    
+    
+    fn data_forward(source_node: &str, payload_len: usize) -> DataForward {
+        DataForward {
+            source_node: source_node.to_string(),
+            dest_node: "node-b".to_string(),
+            data: (0..payload_len).map(|i| i as u8).collect(),
+            protocol: 0x0800,
+            ttl: 7,
+            seq: 0x0102_0304,
+            priority: 2,
+            compressed: true,
+            source_virtual_ip: Ipv4Addr::new(10, 0, 0, 2),
+            ack_requested: true,
+        }
+    }
+    
+    fn assert_same_forward(decoded: &DataForward, forward: &DataForward) {
+        assert_eq!(decoded.source_node, forward.source_node);
+        assert_eq!(decoded.dest_node, forward.dest_node);
+        assert_eq!(decoded.data, forward.data);
+        assert_eq!(decoded.protocol, forward.protocol);
+        assert_eq!(decoded.ttl, forward.ttl);
+        assert_eq!(decoded.seq, forward.seq);
+        assert_eq!(decoded.priority, forward.priority);
+        assert_eq!(decoded.compressed, forward.compressed);
+        assert_eq!(decoded.source_virtual_ip, forward.source_virtual_ip);
+        assert_eq!(decoded.ack_requested, forward.ack_requested);
+    }
+    
+    #[test]
+    fn raw_data_forward_round_trips() {
+        let forward = data_forward("node-a", 1400);
+        let (data, flags) = encode_data_forward(&forward, constants::FLAG_RAW_FRAME | constants::FLAG_SOURCE_IP).unwrap();
+        assert_ne!(flags & constants::FLAG_RAW_FRAME, 0);
+        assert_eq!(data.len(), constants::RAW_FORWARD_HEADER_LEN + 4 + 1400);
+        assert_same_forward(&decode_data_forward(&data, flags).unwrap(), &forward);
+    }
+    
+    #[test]
+    fn raw_data_forward_is_smaller_than_json() {
+        let forward = data_forward("node-a", 1400);
+        let (raw, _) = encode_data_forward(&forward, constants::FLAG_RAW_FRAME).unwrap();
+        let (json, flags) = encode_data_forward(&forward, 0).unwrap();
+        assert_eq!(flags & constants::FLAG_RAW_FRAME, 0);
+        assert_eq!(raw.len() - 1400, constants::RAW_FORWARD_HEADER_LEN);
+        assert!(json.len() > raw.len() * 2, "json {} bytes, raw {} bytes", json.len(), raw.len());
+    }
+    
+    #[test]
+    fn long_node_id_falls_back_to_serialized_encoding() {
+        let forward = data_forward(&"n".repeat(constants::RAW_NODE_ID_LEN + 1), 16);
+        let (data, flags) = encode_data_forward(&forward, constants::FLAG_RAW_FRAME).unwrap();
+        assert_eq!(flags & constants::FLAG_RAW_FRAME, 0);
+        assert_same_forward(&decode_data_forward(&data, flags).unwrap(), &forward);
+    }
+    
+    #[test]
+    fn truncated_raw_frame_is_rejected() {
+        let forward = data_forward("node-a", 0);
+        let (data, flags) = encode_data_forward(&forward, constants::FLAG_RAW_FRAME | constants::FLAG_SOURCE_IP).unwrap();
+        assert!(decode_data_forward(&data[..data.len() - 1], flags).is_err());
+    }
+    
+    #[test]
+    fn status_and_type_names_round_trip_through_display_and_from_str() {
+        for status in NodeStatus::ALL {
+            assert_eq!(status.to_string().parse::<NodeStatus>(), Ok(status));
+            assert_eq!(status.to_string().to_uppercase().parse::<NodeStatus>(), Ok(status));
+        }
+        for msg_type in MessageType::ALL {
+            assert_eq!(msg_type.to_string().parse::<MessageType>(), Ok(msg_type));
+        }
+        for nat_type in crate::NatType::ALL {
+            assert_eq!(nat_type.to_string().parse::<crate::NatType>(), Ok(nat_type));
+        }
+        for algorithm in crate::CryptoAlgorithm::ALL {
+            assert_eq!(algorithm.to_string().parse::<crate::CryptoAlgorithm>(), Ok(algorithm));
+        }
+        assert_eq!(NodeStatus::CapacityExceeded.to_string(), "capacity_exceeded");
+        assert!("busy".parse::<NodeStatus>().is_err());
+    }
+    
+    #[test]
+    fn wire_names_are_unchanged_and_snake_case_is_accepted() {
+        assert_eq!(serde_json::to_string(&MessageType::HandshakeRequest).unwrap(), "\"HandshakeRequest\"");
+        assert_eq!(serde_json::to_string(&NodeStatus::Online).unwrap(), "\"Online\"");
+        for msg_type in MessageType::ALL {
+            let alias = format!("\"{}\"", msg_type);
+            assert_eq!(serde_json::from_str::<MessageType>(&alias).unwrap(), msg_type);
+        }
+        for status in NodeStatus::ALL {
+            let alias = format!("\"{}\"", status);
+            assert_eq!(serde_json::from_str::<NodeStatus>(&alias).unwrap(), status);
+        }
+    }
+    
+    #[test]
+    fn api_responses_use_lowercase_names() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Summary {
+            #[serde(with = "lowercase_name")]
+            status: NodeStatus,
+        }
+        
+        let summary = Summary { status: NodeStatus::CapacityExceeded };
+        let json = serde_json::to_string(&summary).unwrap();
+        assert_eq!(json, r#"{"status":"capacity_exceeded"}"#);
+        assert_eq!(serde_json::from_str::<Summary>(&json).unwrap(), summary);
+        // 旧版本服务端返回的原名同样可以解析
+        assert_eq!(serde_json::from_str::<Summary>(r#"{"status":"Online"}"#).unwrap().status, NodeStatus::Online);
+        assert!(serde_json::from_str::<Summary>(r#"{"status":"busy"}"#).is_err());
+    }
+    
+    #[test]
+    fn built_packets_pass_verify_checksum() {
+        for len in [0, 1, 2, 3, 64, 1400, u16::MAX as usize] {
+            let data: Vec<u8> = (0..len).map(|i| (i * 31 + 7) as u8).collect();
+            let packet = PacketBuilder::new(MessageType::DataForward, data.clone()).build();
+            assert_eq!(packet.magic, constants::MAGIC);
+            assert_eq!(packet.version, PROTOCOL_VERSION);
+            assert_eq!(packet.length as usize, len);
+            assert_eq!(packet.data, data);
+            assert!(verify_checksum(&packet.data, packet.checksum), "checksum of {} bytes", len);
+        }
+    }
+    
+    #[test]
+    fn built_packet_checksum_detects_modified_payload() {
+        let mut packet = PacketBuilder::new(MessageType::Heartbeat, b"heartbeat".to_vec()).build();
+        packet.data[0] ^= 0xff;
+        assert!(!verify_checksum(&packet.data, packet.checksum));
+    }
+    
+    #[test]
+    fn builder_combines_flags_and_connection_id() {
+        let packet = PacketBuilder::new(MessageType::DataForward, vec![1, 2, 3])
+            .flags(constants::FLAG_BINCODE)
+            .flag(constants::FLAG_RAW_FRAME)
+            .flag(constants::FLAG_COMPRESSED)
+            .connection_id(42)
+            .build();
+        assert_eq!(packet.flags, constants::FLAG_BINCODE | constants::FLAG_RAW_FRAME | constants::FLAG_COMPRESSED);
+        assert_eq!(packet.connection_id, 42);
+        
+        let plain = PacketBuilder::new(MessageType::NodeDiscovery, Vec::new()).build();
+        assert_eq!(plain.flags, 0);
+        assert_eq!(plain.connection_id, 0);
+    }
+    
+    #[test]
+    fn built_packets_survive_encoding_with_valid_checksum() {
+        for flags in [0, constants::FLAG_RAW_FRAME] {
+            let packet = PacketBuilder::new(MessageType::DataForward, b"payload".to_vec())
+                .flags(flags)
+                .connection_id(7)
+                .build();
+            let decoded = Packet::decode(&packet.encode().unwrap()).unwrap();
+            assert_eq!(decoded.magic, constants::MAGIC);
+            assert_eq!(decoded.version, PROTOCOL_VERSION);
+            assert_eq!(decoded.msg_type, MessageType::DataForward);
+            assert_eq!(decoded.length, packet.length);
+            assert_eq!(decoded.connection_id, 7);
+            assert!(verify_checksum(&decoded.data, decoded.checksum));
+        }
+    }
+}
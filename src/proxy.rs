@@ -0,0 +1,386 @@
+/*!
+VPNet代理隧道模块
+
+网络只允许经HTTP代理出站时，通过代理的CONNECT隧道连接服务端，包括：
+- 向HTTP代理发起CONNECT请求，支持Basic认证
+- 在TCP隧道中以长度前缀分帧传输原本的UDP数据报
+- 在本地回环地址上提供UDP端点，网络管理器无需感知底层传输
+- 隧道断开后经代理重新建立，本地端点保持不变
+*/
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use crate::backoff::BackoffStrategy;
+use crate::transport::{DatagramSocket, TransportFactory, UdpTransport};
+use crate::utils::TcpStreamConfig;
+use crate::MAX_DATAGRAM_SIZE;
+
+/// 代理响应头部的最大长度
+const MAX_RESPONSE_HEADER_LEN: usize = 8192;
+
+/// 本地UDP端点没有待接收的数据报时的轮询间隔
+const RECV_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// HTTP代理配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    /// 代理地址，例如`http://proxy.corp:3128`
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+impl ProxyConfig {
+    /// 代理的`主机:端口`，未写端口时使用80
+    pub fn authority(&self) -> Result<String, &'static str> {
+        let rest = self.url.strip_prefix("http://")
+            .ok_or("Proxy URL must start with http://")?;
+        let authority = rest.trim_end_matches('/');
+        if authority.is_empty() || authority.contains('/') || authority.contains('@') {
+            return Err("Proxy URL must be http://host[:port]");
+        }
+        
+        // 带方括号的IPv6地址中的冒号不是端口分隔符
+        let has_port = match authority.rfind(']') {
+            Some(end) => authority[end..].contains(':'),
+            None => authority.contains(':'),
+        };
+        Ok(if has_port {
+            authority.to_string()
+        } else {
+            format!("{}:80", authority)
+        })
+    }
+    
+    /// `Proxy-Authorization`头部的值，未配置用户名时返回`None`
+    fn authorization(&self) -> Option<String> {
+        let username = self.username.as_deref()?;
+        let credentials = format!("{}:{}", username, self.password.as_deref().unwrap_or_default());
+        Some(format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(credentials)))
+    }
+}
+
+/// 经HTTP代理建立到`target`的TCP隧道
+pub async fn http_connect(proxy: &ProxyConfig, target: SocketAddr) -> io::Result<TcpStream> {
+    let authority = proxy.authority()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stream = TcpStream::connect(&authority).await?;
+    
+    let mut request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+    if let Some(authorization) = proxy.authorization() {
+        request.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    
+    // 逐字节读取响应头部，避免读走头部之后属于隧道的数据
+    let mut header = Vec::new();
+    while !header.ends_with(b"\r\n\r\n") {
+        if header.len() >= MAX_RESPONSE_HEADER_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "proxy response header too long"));
+        }
+        header.push(stream.read_u8().await?);
+    }
+    
+    let header = String::from_utf8_lossy(&header);
+    let status_line = header.lines().next().unwrap_or_default();
+    let status = status_line.split_whitespace().nth(1).unwrap_or_default();
+    match status {
+        "200" => {
+            log::info!("CONNECT tunnel to {} established via {}", target, authority);
+            Ok(stream)
+        }
+        "407" => Err(io::Error::new(io::ErrorKind::PermissionDenied, "proxy authentication required")),
+        _ => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("proxy rejected CONNECT: {}", status_line)
+        )),
+    }
+}
+
+/// TCP隧道与UDP之间的桥接
+///
+/// 在本地回环地址上绑定一个数据报套接字，收到的数据报加上2字节长度前缀写入隧道，
+/// 隧道中读出的每一帧作为数据报发回对端。对端地址未指定时使用第一个发来数据报的地址。
+pub struct UdpTcpBridge {
+    stream: TcpStream,
+    socket: Arc<dyn DatagramSocket>,
+    peer: Option<SocketAddr>,
+}
+
+impl UdpTcpBridge {
+    /// 创建桥接，`peer`为隧道中的数据报要送达的本地UDP地址
+    pub async fn new(stream: TcpStream, peer: Option<SocketAddr>) -> io::Result<Self> {
+        Self::with_transport(stream, peer, &UdpTransport)
+    }
+    
+    /// 通过指定的传输绑定本地端点，对端与网络管理器使用同一传输时才能互相送达
+    pub fn with_transport(stream: TcpStream, peer: Option<SocketAddr>, transport: &dyn TransportFactory) -> io::Result<Self> {
+        let socket = transport.bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))?;
+        Ok(Self { stream, socket, peer })
+    }
+    
+    /// 本地UDP端点的地址，发往该地址的数据报经隧道转发
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+    
+    /// 双向转发，直到任一方向关闭或出错
+    pub async fn run(self) -> io::Result<()> {
+        let peer = Arc::new(OnceLock::new());
+        if let Some(addr) = self.peer {
+            let _ = peer.set(addr);
+        }
+        forward(self.stream, self.socket, peer).await
+    }
+    
+    /// 双向转发，隧道关闭后经`proxy`重新建立到`target`的隧道继续转发
+    ///
+    /// 本地端点和对端地址保持不变，网络管理器无需感知隧道重建。每次重建最多重试
+    /// `max_attempts`次，间隔按`reconnect`策略，全部失败时返回最后一次的错误。
+    pub async fn run_reconnecting(
+        self,
+        proxy: &ProxyConfig,
+        target: SocketAddr,
+        reconnect: &BackoffStrategy,
+        max_attempts: usize
+    ) -> io::Result<()> {
+        let peer = Arc::new(OnceLock::new());
+        if let Some(addr) = self.peer {
+            let _ = peer.set(addr);
+        }
+        
+        let mut stream = self.stream;
+        loop {
+            match forward(stream, self.socket.clone(), peer.clone()).await {
+                Ok(()) => log::warn!("Proxy tunnel to {} closed, reconnecting", target),
+                Err(e) => log::warn!("Proxy tunnel to {} failed: {}, reconnecting", target, e),
+            }
+            
+            let mut delays = reconnect.iterator().take(max_attempts);
+            stream = loop {
+                let e = match http_connect(proxy, target).await {
+                    Ok(stream) => match TcpStreamConfig::default().apply(&stream) {
+                        Ok(()) => break stream,
+                        Err(e) => e,
+                    },
+                    Err(e) => e,
+                };
+                match delays.next() {
+                    Some(delay) => {
+                        log::warn!("Failed to reopen proxy tunnel to {}: {}, retrying in {:?}", target, e, delay);
+                        tokio::time::sleep(delay).await;
+                    }
+                    None => return Err(e),
+                }
+            };
+        }
+    }
+}
+
+/// 在隧道和本地UDP端点之间双向转发，直到任一方向关闭或出错
+async fn forward(stream: TcpStream, socket: Arc<dyn DatagramSocket>, peer: Arc<OnceLock<SocketAddr>>) -> io::Result<()> {
+    let (reader, writer) = stream.into_split();
+    
+    // 两个方向各一个任务，先结束的一方决定结果
+    let (done_tx, mut done_rx) = mpsc::channel(2);
+    let upstream = tokio::spawn({
+        let (socket, peer, done_tx) = (socket.clone(), peer.clone(), done_tx.clone());
+        async move {
+            let _ = done_tx.send(udp_to_tunnel(&socket, &peer, writer).await).await;
+        }
+    });
+    let downstream = tokio::spawn(async move {
+        let _ = done_tx.send(tunnel_to_udp(reader, &socket, &peer).await).await;
+    });
+    
+    let result = done_rx.recv().await.unwrap_or(Ok(()));
+    upstream.abort();
+    downstream.abort();
+    result
+}
+
+/// 将本地UDP端点收到的数据报分帧写入隧道
+async fn udp_to_tunnel(socket: &dyn DatagramSocket, peer: &OnceLock<SocketAddr>, mut writer: OwnedWriteHalf) -> io::Result<()> {
+    let mut datagram = vec![0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        let (len, from) = match socket.recv_from(&mut datagram) {
+            Ok(received) => received,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                tokio::time::sleep(RECV_POLL_INTERVAL).await;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if *peer.get_or_init(|| from) != from {
+            continue;
+        }
+        writer.write_all(&(len as u16).to_be_bytes()).await?;
+        writer.write_all(&datagram[..len]).await?;
+    }
+}
+
+/// 从隧道读出数据帧，作为数据报发给本地对端
+async fn tunnel_to_udp(mut reader: OwnedReadHalf, socket: &dyn DatagramSocket, peer: &OnceLock<SocketAddr>) -> io::Result<()> {
+    let mut frame = vec![0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        let mut frame_len = [0u8; 2];
+        reader.read_exact(&mut frame_len).await?;
+        let len = u16::from_be_bytes(frame_len) as usize;
+        if len > MAX_DATAGRAM_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "tunnel frame too large"));
+        }
+        reader.read_exact(&mut frame[..len]).await?;
+        
+        match peer.get() {
+            Some(peer) => {
+                socket.send_to(&frame[..len], *peer)?;
+            }
+            None => log::debug!("Dropping tunnel frame, no local peer yet"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+    use tokio::net::TcpListener;
+    
+    /// 本地HTTP代理桩，记录每个CONNECT请求的头部
+    ///
+    /// 要求`credentials`时，缺少对应的认证头部则返回407。隧道建立后原样回送收到的数据帧，
+    /// 每个隧道回送`frames_per_tunnel`帧后关闭。
+    async fn stub_proxy(credentials: Option<&str>, frames_per_tunnel: usize) -> (ProxyConfig, Arc<std::sync::Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let expected = credentials.map(|credentials| {
+            format!("Proxy-Authorization: Basic {}", base64::engine::general_purpose::STANDARD.encode(credentials))
+        });
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let (expected, recorded) = (expected.clone(), recorded.clone());
+                tokio::spawn(async move {
+                    let mut header = Vec::new();
+                    while !header.ends_with(b"\r\n\r\n") {
+                        header.push(stream.read_u8().await.unwrap());
+                    }
+                    let header = String::from_utf8(header).unwrap();
+                    recorded.lock().unwrap().push(header.clone());
+                    if expected.is_some_and(|expected| !header.contains(&expected)) {
+                        stream.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").await.unwrap();
+                        return;
+                    }
+                    stream.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await.unwrap();
+                    
+                    for _ in 0..frames_per_tunnel {
+                        let len = match stream.read_u16().await {
+                            Ok(len) => len,
+                            Err(_) => return,
+                        };
+                        let mut frame = vec![0u8; len as usize];
+                        stream.read_exact(&mut frame).await.unwrap();
+                        stream.write_u16(len).await.unwrap();
+                        stream.write_all(&frame).await.unwrap();
+                    }
+                });
+            }
+        });
+        
+        let (username, password) = match credentials.and_then(|credentials| credentials.split_once(':')) {
+            Some((username, password)) => (Some(username.to_string()), Some(password.to_string())),
+            None => (None, None),
+        };
+        (ProxyConfig { url, username, password }, requests)
+    }
+    
+    fn target() -> SocketAddr {
+        "192.0.2.10:51820".parse().unwrap()
+    }
+    
+    /// 经桥接的本地端点发送数据报，等待回送
+    async fn round_trip(client: &UdpSocket, bridge: SocketAddr, data: &[u8]) -> Vec<u8> {
+        client.send_to(data, bridge).unwrap();
+        let mut buf = [0u8; 64];
+        for _ in 0..200 {
+            match client.recv_from(&mut buf) {
+                Ok((len, _)) => return buf[..len].to_vec(),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => tokio::time::sleep(Duration::from_millis(10)).await,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        panic!("no reply through the tunnel");
+    }
+    
+    fn local_client() -> UdpSocket {
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.set_nonblocking(true).unwrap();
+        client
+    }
+    
+    #[tokio::test]
+    async fn connect_sends_target_and_credentials() {
+        let (proxy, requests) = stub_proxy(Some("alice:secret"), 0).await;
+        http_connect(&proxy, target()).await.unwrap();
+        let request = requests.lock().unwrap()[0].clone();
+        assert!(request.starts_with("CONNECT 192.0.2.10:51820 HTTP/1.1\r\n"), "{}", request);
+        assert!(request.contains("Host: 192.0.2.10:51820\r\n"), "{}", request);
+    }
+    
+    #[tokio::test]
+    async fn connect_reports_missing_credentials() {
+        let (mut proxy, _) = stub_proxy(Some("alice:secret"), 0).await;
+        proxy.password = Some("wrong".to_string());
+        let error = http_connect(&proxy, target()).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    }
+    
+    #[tokio::test]
+    async fn bridge_forwards_datagrams_through_the_tunnel() {
+        let (proxy, _) = stub_proxy(None, usize::MAX).await;
+        let bridge = UdpTcpBridge::new(http_connect(&proxy, target()).await.unwrap(), None).await.unwrap();
+        let bridge_addr = bridge.local_addr().unwrap();
+        tokio::spawn(bridge.run());
+        
+        let client = local_client();
+        assert_eq!(round_trip(&client, bridge_addr, b"hello").await, b"hello");
+        assert_eq!(round_trip(&client, bridge_addr, b"again").await, b"again");
+    }
+    
+    #[tokio::test]
+    async fn bridge_reopens_a_dropped_tunnel() {
+        // 每个隧道只回送一帧就断开
+        let (proxy, requests) = stub_proxy(None, 1).await;
+        let bridge = UdpTcpBridge::new(http_connect(&proxy, target()).await.unwrap(), None).await.unwrap();
+        let bridge_addr = bridge.local_addr().unwrap();
+        let reconnect = BackoffStrategy::Constant(Duration::from_millis(10));
+        tokio::spawn(async move { bridge.run_reconnecting(&proxy, target(), &reconnect, 3).await });
+        
+        let client = local_client();
+        assert_eq!(round_trip(&client, bridge_addr, b"first").await, b"first");
+        // 等待隧道重建，重建之前发出的数据报会丢失，需要重发
+        let mut reply = Vec::new();
+        for _ in 0..20 {
+            if requests.lock().unwrap().len() >= 2 {
+                reply = round_trip(&client, bridge_addr, b"second").await;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(reply, b"second");
+    }
+}
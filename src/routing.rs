@@ -0,0 +1,349 @@
+/*!
+VPNet路由模块
+
+管理虚拟网络的路由，包括：
+- CIDR网段表示
+- 路由表和最长前缀匹配
+- 基于源地址的策略路由
+*/
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+/// 主路由表编号（与Linux一致）
+pub const MAIN_TABLE: u8 = 254;
+
+/// IPv4网段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct IpCidr {
+    pub addr: Ipv4Addr,
+    pub prefix_len: u8,
+}
+
+/// 路由条目
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Route {
+    pub destination: IpCidr,
+    pub gateway: Ipv4Addr,
+    pub next_hop: String,
+    pub metric: u32,
+}
+
+/// 路由表
+#[derive(Debug, Clone, Default)]
+pub struct RoutingTable {
+    routes: Vec<Route>,
+}
+
+/// 策略路由规则
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyRoute {
+    pub priority: u32,
+    pub src_ip: Option<IpCidr>,
+    pub dst_ip: Option<IpCidr>,
+    pub proto: Option<u8>,
+    pub table: u8,
+}
+
+/// 路由策略
+///
+/// 按优先级依次匹配策略规则，在规则指定的路由表中查找目的地址；
+/// 所有规则都未命中时回退到主路由表。
+#[derive(Debug, Clone, Default)]
+pub struct RoutingPolicy {
+    tables: HashMap<u8, RoutingTable>,
+    rules: Vec<PolicyRoute>,
+}
+
+impl IpCidr {
+    /// 创建新的网段，主机位会被清零
+    pub fn new(addr: Ipv4Addr, prefix_len: u8) -> Result<Self, &'static str> {
+        if prefix_len > 32 {
+            return Err("Invalid prefix length");
+        }
+        
+        Ok(Self {
+            addr: Ipv4Addr::from(u32::from(addr) & Self::mask_bits(prefix_len)),
+            prefix_len,
+        })
+    }
+    
+    /// 由地址和子网掩码创建网段，掩码必须是连续的1
+    pub fn from_netmask(addr: Ipv4Addr, netmask: Ipv4Addr) -> Result<Self, &'static str> {
+        let bits = u32::from(netmask);
+        let prefix_len = bits.leading_ones() as u8;
+        if bits != Self::mask_bits(prefix_len) {
+            return Err("Invalid netmask");
+        }
+        Self::new(addr, prefix_len)
+    }
+    
+    /// 子网掩码
+    pub fn netmask(&self) -> Ipv4Addr {
+        Ipv4Addr::from(Self::mask_bits(self.prefix_len))
+    }
+    
+    /// 网段的广播地址
+    pub fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.addr) | !Self::mask_bits(self.prefix_len))
+    }
+    
+    /// 判断地址是否属于该网段
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        u32::from(ip) & Self::mask_bits(self.prefix_len) == u32::from(self.addr)
+    }
+    
+    /// 判断`other`是否整个落在该网段内
+    pub fn covers(&self, other: &IpCidr) -> bool {
+        other.prefix_len >= self.prefix_len && self.contains(other.addr)
+    }
+    
+    fn mask_bits(prefix_len: u8) -> u32 {
+        if prefix_len == 0 {
+            0
+        } else {
+            u32::MAX << (32 - prefix_len as u32)
+        }
+    }
+}
+
+impl fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl FromStr for IpCidr {
+    type Err = &'static str;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (
+                addr,
+                prefix_len.parse::<u8>().map_err(|_| "Invalid prefix length")?,
+            ),
+            None => (s, 32),
+        };
+        let addr = addr.parse::<Ipv4Addr>().map_err(|_| "Invalid IPv4 address")?;
+        
+        Self::new(addr, prefix_len)
+    }
+}
+
+impl TryFrom<String> for IpCidr {
+    type Error = &'static str;
+    
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<IpCidr> for String {
+    fn from(cidr: IpCidr) -> Self {
+        cidr.to_string()
+    }
+}
+
+impl RoutingTable {
+    /// 创建空路由表
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// 添加路由，目的网段相同的路由会被替换
+    ///
+    /// 路由按前缀长度从长到短、度量值从小到大排列，便于按顺序匹配。
+    pub fn add_route(&mut self, route: Route) {
+        self.routes.retain(|r| r.destination != route.destination);
+        let index = self.routes.partition_point(|r| {
+            (std::cmp::Reverse(r.destination.prefix_len), r.metric)
+                <= (std::cmp::Reverse(route.destination.prefix_len), route.metric)
+        });
+        self.routes.insert(index, route);
+    }
+    
+    /// 删除目的网段的路由
+    pub fn remove_route(&mut self, destination: &IpCidr) -> Option<Route> {
+        let index = self.routes.iter().position(|r| &r.destination == destination)?;
+        Some(self.routes.remove(index))
+    }
+    
+    /// 最长前缀匹配，前缀长度相同时选择度量值最小的路由
+    pub fn lookup(&self, dst: Ipv4Addr) -> Option<&Route> {
+        self.longest_prefix_match_iter(dst).next()
+    }
+    
+    /// 按匹配优先级依次返回所有包含目的地址的路由
+    ///
+    /// 前缀长度从长到短，前缀长度相同时度量值从小到大，第一个即为`lookup`的结果。
+    pub fn longest_prefix_match_iter(&self, dst: Ipv4Addr) -> impl Iterator<Item = &Route> + '_ {
+        self.routes.iter().filter(move |r| r.destination.contains(dst))
+    }
+    
+    /// 遍历所有路由
+    pub fn iter(&self) -> std::slice::Iter<'_, Route> {
+        self.routes.iter()
+    }
+    
+    /// 只保留满足条件的路由
+    pub fn retain(&mut self, f: impl FnMut(&Route) -> bool) {
+        self.routes.retain(f);
+    }
+    
+    /// 列出所有路由
+    pub fn list_routes(&self) -> Vec<Route> {
+        self.routes.clone()
+    }
+    
+    /// 路由数量
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+    
+    /// 路由表是否为空
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a RoutingTable {
+    type Item = &'a Route;
+    type IntoIter = std::slice::Iter<'a, Route>;
+    
+    fn into_iter(self) -> Self::IntoIter {
+        self.routes.iter()
+    }
+}
+
+impl PolicyRoute {
+    /// 判断数据包是否匹配该规则，未设置的条件视为匹配任意值
+    pub fn matches(&self, src: Ipv4Addr, dst: Ipv4Addr, proto: u8) -> bool {
+        self.src_ip.is_none_or(|cidr| cidr.contains(src))
+            && self.dst_ip.is_none_or(|cidr| cidr.contains(dst))
+            && self.proto.is_none_or(|p| p == proto)
+    }
+}
+
+impl RoutingPolicy {
+    /// 创建路由策略，只包含空的主路由表
+    pub fn new() -> Self {
+        let mut tables = HashMap::new();
+        tables.insert(MAIN_TABLE, RoutingTable::new());
+        
+        Self {
+            tables,
+            rules: Vec::new(),
+        }
+    }
+    
+    /// 获取路由表
+    pub fn table(&self, table: u8) -> Option<&RoutingTable> {
+        self.tables.get(&table)
+    }
+    
+    /// 遍历所有路由表及其编号
+    pub fn tables(&self) -> impl Iterator<Item = (u8, &RoutingTable)> + '_ {
+        self.tables.iter().map(|(id, table)| (*id, table))
+    }
+    
+    /// 获取路由表，不存在时创建
+    pub fn table_mut(&mut self, table: u8) -> &mut RoutingTable {
+        self.tables.entry(table).or_default()
+    }
+    
+    /// 添加策略规则，规则按优先级数值从小到大排列
+    pub fn add_rule(&mut self, rule: PolicyRoute) {
+        let index = self.rules.partition_point(|r| r.priority <= rule.priority);
+        self.rules.insert(index, rule);
+    }
+    
+    /// 删除指定优先级的策略规则
+    pub fn remove_rule(&mut self, priority: u32) -> Option<PolicyRoute> {
+        let index = self.rules.iter().position(|r| r.priority == priority)?;
+        Some(self.rules.remove(index))
+    }
+    
+    /// 所有路由表中的路由条目总数
+    pub fn route_count(&self) -> usize {
+        self.tables.values().map(RoutingTable::len).sum()
+    }
+    
+    /// 列出所有策略规则
+    pub fn rules(&self) -> &[PolicyRoute] {
+        &self.rules
+    }
+    
+    /// 按策略查找路由
+    ///
+    /// 命中规则但其路由表中没有匹配路由时，继续尝试后续规则。
+    pub fn route(&self, src: Ipv4Addr, dst: Ipv4Addr, proto: u8) -> Option<&Route> {
+        self.rules.iter()
+            .filter(|rule| rule.matches(src, dst, proto))
+            .find_map(|rule| self.tables.get(&rule.table)?.lookup(dst))
+            .or_else(|| self.tables.get(&MAIN_TABLE)?.lookup(dst))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    
+    fn route(addr: u32, prefix_len: u8, metric: u32) -> Route {
+        Route {
+            destination: IpCidr::new(Ipv4Addr::from(addr), prefix_len).unwrap(),
+            gateway: Ipv4Addr::new(10, 0, 0, 1),
+            next_hop: format!("hop-{}", metric),
+            metric,
+        }
+    }
+    
+    /// 一半路由取自目的地址的前缀，保证存在匹配的路由
+    fn routes_for(dst: u32) -> impl Strategy<Value = Vec<Route>> {
+        let covering = (0u8..=32, 0u32..8).prop_map(move |(prefix_len, metric)| route(dst, prefix_len, metric));
+        let random = (any::<u32>(), 0u8..=32, 0u32..8).prop_map(|(addr, prefix_len, metric)| route(addr, prefix_len, metric));
+        prop::collection::vec(prop_oneof![covering, random], 0..32)
+    }
+    
+    proptest! {
+        #[test]
+        fn longest_prefix_match_iter_returns_best_route_first(
+            (dst, routes) in any::<u32>().prop_flat_map(|dst| (Just(dst), routes_for(dst)))
+        ) {
+            let mut table = RoutingTable::new();
+            for route in routes {
+                table.add_route(route);
+            }
+            let dst = Ipv4Addr::from(dst);
+            
+            // 逐条检查所有路由得到的最佳路由：前缀最长，其次度量值最小
+            let expected = table.iter()
+                .filter(|r| r.destination.contains(dst))
+                .min_by_key(|r| (std::cmp::Reverse(r.destination.prefix_len), r.metric));
+            prop_assert_eq!(table.longest_prefix_match_iter(dst).next(), expected);
+            prop_assert_eq!(table.lookup(dst), expected);
+            
+            let matches: Vec<&Route> = table.longest_prefix_match_iter(dst).collect();
+            prop_assert!(matches.iter().all(|r| r.destination.contains(dst)));
+            prop_assert!(matches.windows(2).all(|pair| pair[0].destination.prefix_len >= pair[1].destination.prefix_len));
+            prop_assert_eq!(matches.len(), (&table).into_iter().filter(|r| r.destination.contains(dst)).count());
+        }
+    }
+    
+    #[test]
+    fn retain_removes_routes_in_bulk() {
+        let mut table = RoutingTable::new();
+        table.add_route(route(0x0a00_0000, 8, 1));
+        table.add_route(route(0x0a01_0000, 16, 1));
+        table.add_route(route(0xc0a8_0000, 16, 1));
+        
+        table.retain(|r| !IpCidr::new(Ipv4Addr::new(10, 0, 0, 0), 8).unwrap().contains(r.destination.addr));
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.lookup(Ipv4Addr::new(10, 1, 2, 3)), None);
+        assert!(table.lookup(Ipv4Addr::new(192, 168, 1, 1)).is_some());
+    }
+}
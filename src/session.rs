@@ -0,0 +1,261 @@
+/*!
+VPNet会话恢复模块
+
+节点正常断开后在短时间内重新连接时，凭服务端签发的会话票据恢复会话，包括：
+- 使用AES-256-GCM加密会话票据，票据内容只有服务端可以读取
+- 票据过期或已使用过时拒绝恢复，节点改为完整握手
+- 节点被移除时吊销此前签发给它的票据
+- 每天轮换票据密钥，轮换前签发的票据在下一次轮换前仍然有效
+*/
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, NONCE_LEN};
+use std::sync::Arc;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use crate::crypto::{HkdfSha256Kdf, Kdf};
+use crate::utils::current_unix_timestamp;
+
+/// 票据密钥的轮换间隔（秒）
+pub const TICKET_KEY_ROTATION_INTERVAL: u64 = 86400;
+
+/// 票据密钥ID的长度
+const KEY_ID_LEN: usize = 4;
+
+/// 派生票据密钥时使用的`info`
+const TICKET_KEY_INFO: &[u8] = b"vpnet session ticket key";
+
+/// 会话票据中保存的会话状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionState {
+    pub node_id: String,
+    pub public_key: Vec<u8>,
+    pub virtual_ip: String,
+    pub session_key: Vec<u8>,
+    pub connection_id: u64,
+    /// 票据签发时间（Unix秒）
+    pub issued_at: u64,
+}
+
+/// 票据密钥
+struct TicketKey {
+    id: [u8; KEY_ID_LEN],
+    key: LessSafeKey,
+    created_at: Instant,
+}
+
+/// 会话票据密钥
+///
+/// 票据格式为`密钥ID(4字节) | 随机数(12字节) | 密文`，密钥ID同时作为附加认证数据。
+/// 每张票据只能使用一次。
+pub struct SessionTicketKeys {
+    current: TicketKey,
+    previous: Option<TicketKey>,
+    lifetime: Duration,
+    rng: SystemRandom,
+    /// 由随机密钥材料派生票据密钥
+    kdf: Arc<dyn Kdf>,
+    /// 已使用的票据随机数及票据过期时间（Unix秒），防止票据被重放
+    redeemed: HashMap<[u8; NONCE_LEN], u64>,
+    /// 被吊销票据的节点及吊销时间（Unix秒），此前签发的票据不再有效
+    revoked: HashMap<String, u64>,
+}
+
+impl SessionTicketKeys {
+    /// 票据默认有效期（秒）
+    pub const DEFAULT_LIFETIME: u64 = 60;
+    
+    /// 创建票据密钥，签发的票据在`lifetime`内有效
+    pub fn new(lifetime: Duration) -> Result<Self, &'static str> {
+        let rng = SystemRandom::new();
+        let kdf: Arc<dyn Kdf> = Arc::new(HkdfSha256Kdf);
+        Ok(Self {
+            current: generate_key(&rng, kdf.as_ref())?,
+            previous: None,
+            lifetime,
+            rng,
+            kdf,
+            redeemed: HashMap::new(),
+            revoked: HashMap::new(),
+        })
+    }
+    
+    /// 票据有效期
+    pub fn lifetime(&self) -> Duration {
+        self.lifetime
+    }
+    
+    /// 轮换票据密钥，上一个密钥保留到下一次轮换
+    pub fn rotate(&mut self) -> Result<(), &'static str> {
+        let key = generate_key(&self.rng, self.kdf.as_ref())?;
+        self.previous = Some(std::mem::replace(&mut self.current, key));
+        log::info!("Session ticket key rotated");
+        Ok(())
+    }
+    
+    /// 替换派生票据密钥的函数，默认为`HkdfSha256Kdf`
+    ///
+    /// 立即轮换出由新函数派生的密钥，此前签发的票据在下一次轮换前仍然有效。
+    pub fn set_kdf(&mut self, kdf: Arc<dyn Kdf>) -> Result<(), &'static str> {
+        self.kdf = kdf;
+        self.rotate()
+    }
+    
+    /// 当前密钥使用超过轮换间隔时轮换
+    fn rotate_if_due(&mut self) -> Result<(), &'static str> {
+        if self.current.created_at.elapsed() >= Duration::from_secs(TICKET_KEY_ROTATION_INTERVAL) {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+    
+    /// 吊销此前签发给节点的所有票据，节点被移除时调用
+    pub fn revoke(&mut self, node_id: &str) {
+        self.revoked.insert(node_id.to_string(), current_unix_timestamp());
+    }
+    
+    /// 为会话状态签发票据
+    pub fn issue(&mut self, state: &SessionState) -> Result<Vec<u8>, &'static str> {
+        self.rotate_if_due()?;
+        
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce).map_err(|_| "Random generation failed")?;
+        
+        let mut ciphertext = serde_json::to_vec(state).map_err(|_| "Serialization failed")?;
+        self.current.key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(self.current.id),
+            &mut ciphertext
+        ).map_err(|_| "Encryption failed")?;
+        
+        let mut ticket = Vec::with_capacity(KEY_ID_LEN + NONCE_LEN + ciphertext.len());
+        ticket.extend_from_slice(&self.current.id);
+        ticket.extend_from_slice(&nonce);
+        ticket.extend_from_slice(&ciphertext);
+        Ok(ticket)
+    }
+    
+    /// 验证并使用票据，返回其中的会话状态
+    ///
+    /// 票据无法解密、已过期或已经使用过时返回`None`。
+    pub fn redeem(&mut self, ticket: &[u8]) -> Option<SessionState> {
+        if let Err(e) = self.rotate_if_due() {
+            log::warn!("Failed to rotate session ticket key: {}", e);
+        }
+        
+        let now = current_unix_timestamp();
+        let lifetime = self.lifetime.as_secs();
+        self.redeemed.retain(|_, expires_at| *expires_at >= now);
+        // 吊销前签发的票据都已过期后不再需要记录
+        self.revoked.retain(|_, revoked_at| revoked_at.saturating_add(lifetime) >= now);
+        
+        if ticket.len() < KEY_ID_LEN + NONCE_LEN {
+            return None;
+        }
+        let (key_id, rest) = ticket.split_at(KEY_ID_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().ok()?;
+        
+        let key = std::iter::once(&self.current)
+            .chain(self.previous.as_ref())
+            .find(|key| key.id == key_id)?;
+        
+        let mut plaintext = ciphertext.to_vec();
+        let plaintext = key.key.open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(key.id),
+            &mut plaintext
+        ).ok()?;
+        let state: SessionState = serde_json::from_slice(plaintext).ok()?;
+        
+        let expires_at = state.issued_at.saturating_add(self.lifetime.as_secs());
+        if expires_at < now {
+            log::debug!("Session ticket for {} expired", state.node_id);
+            return None;
+        }
+        if self.revoked.get(&state.node_id).is_some_and(|&revoked_at| state.issued_at <= revoked_at) {
+            log::warn!("Session ticket for {} was revoked", state.node_id);
+            return None;
+        }
+        if self.redeemed.insert(nonce, expires_at).is_some() {
+            log::warn!("Session ticket for {} was already used", state.node_id);
+            return None;
+        }
+        
+        Some(state)
+    }
+}
+
+/// 生成新的票据密钥，由`kdf`从随机密钥材料派生，密钥ID作为盐
+fn generate_key(rng: &SystemRandom, kdf: &dyn Kdf) -> Result<TicketKey, &'static str> {
+    let mut id = [0u8; KEY_ID_LEN];
+    let mut secret = [0u8; 32];
+    rng.fill(&mut id).map_err(|_| "Random generation failed")?;
+    rng.fill(&mut secret).map_err(|_| "Random generation failed")?;
+    let key_bytes = kdf.derive(&secret, &id, TICKET_KEY_INFO, 32)?;
+    
+    let key = UnboundKey::new(&aead::AES_256_GCM, &key_bytes).map_err(|_| "Invalid key")?;
+    Ok(TicketKey {
+        id,
+        key: LessSafeKey::new(key),
+        created_at: Instant::now(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::mock_clock;
+    
+    fn session_state() -> SessionState {
+        SessionState {
+            node_id: "node-1".to_string(),
+            public_key: vec![1u8; 32],
+            virtual_ip: "10.0.0.2".to_string(),
+            session_key: vec![2u8; 32],
+            connection_id: 7,
+            issued_at: current_unix_timestamp(),
+        }
+    }
+    
+    #[test]
+    fn ticket_expires_after_its_lifetime() {
+        mock_clock::set(1_000_000);
+        let mut keys = SessionTicketKeys::new(Duration::from_secs(SessionTicketKeys::DEFAULT_LIFETIME)).unwrap();
+        let fresh = keys.issue(&session_state()).unwrap();
+        let stale = keys.issue(&session_state()).unwrap();
+        
+        mock_clock::advance(SessionTicketKeys::DEFAULT_LIFETIME);
+        assert_eq!(keys.redeem(&fresh).unwrap().connection_id, 7);
+        
+        mock_clock::advance(1);
+        assert!(keys.redeem(&stale).is_none());
+        mock_clock::reset();
+    }
+    
+    #[test]
+    fn ticket_can_be_redeemed_only_once() {
+        mock_clock::set(1_000_000);
+        let mut keys = SessionTicketKeys::new(Duration::from_secs(SessionTicketKeys::DEFAULT_LIFETIME)).unwrap();
+        let ticket = keys.issue(&session_state()).unwrap();
+        assert!(keys.redeem(&ticket).is_some());
+        assert!(keys.redeem(&ticket).is_none());
+        mock_clock::reset();
+    }
+    
+    #[test]
+    fn revoked_node_cannot_redeem_earlier_tickets() {
+        mock_clock::set(1_000_000);
+        let mut keys = SessionTicketKeys::new(Duration::from_secs(SessionTicketKeys::DEFAULT_LIFETIME)).unwrap();
+        let ticket = keys.issue(&session_state()).unwrap();
+        keys.revoke("node-1");
+        assert!(keys.redeem(&ticket).is_none());
+        
+        // 吊销之后签发的票据不受影响
+        mock_clock::advance(1);
+        let ticket = keys.issue(&session_state()).unwrap();
+        assert!(keys.redeem(&ticket).is_some());
+        mock_clock::reset();
+    }
+}
@@ -0,0 +1,528 @@
+/*!
+VPNet流量整形模块
+
+按HTB（分层令牌桶）算法调度中继的数据包，包括：
+- 分类层级，分类按保证速率发送，超出部分向祖先分类借用空闲带宽直到上限
+- 叶子分类按节点分组划分流量
+- 优先级高的分类先发送，同优先级的分类之间按差额轮询分享带宽
+*/
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use crate::network::{packet_cost, BoxFuture, PacketScheduler};
+use crate::protocol::{constants, Packet};
+use crate::MAX_PACKET_SIZE;
+
+/// HTB分类
+///
+/// 没有子分类的是叶子分类，接收发往`group`分组节点的数据包；有子分类的分类只向子分类借出带宽。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HtbClass {
+    pub id: u32,
+    /// 父分类，`None`表示根分类
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<u32>,
+    /// 保证速率（字节/秒）
+    pub rate: u64,
+    /// 借用祖先分类带宽后的速率上限（字节/秒），不能低于`rate`
+    pub ceil: u64,
+    /// 令牌桶容量（字节），为0时按`rate`的1/10计算，且至少能容纳一个最大的数据包
+    #[serde(default)]
+    pub burst: u64,
+    /// 数值越小越优先
+    #[serde(default)]
+    pub priority: u8,
+    /// 叶子分类对应的节点分组
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+/// 分类的发送状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClassMode {
+    /// 未超过保证速率
+    CanSend,
+    /// 超过保证速率，未超过上限，可以向祖先借用
+    MayBorrow,
+    /// 超过上限
+    CantSend,
+}
+
+/// 分类的运行状态
+///
+/// 令牌以字节计，发送时从叶子分类到根分类逐级扣除，可以为负数，欠下的令牌按速率恢复。
+struct ClassState {
+    class: HtbClass,
+    is_leaf: bool,
+    capacity: f64,
+    tokens: f64,
+    ctokens: f64,
+    refilled_at: Instant,
+    /// 排队的数据包及其目标节点，只有叶子分类使用
+    queue: VecDeque<(String, Packet, u8)>,
+    deficit: f64,
+    quantum: f64,
+}
+
+impl ClassState {
+    fn new(class: HtbClass, is_leaf: bool) -> Self {
+        let capacity = if class.burst > 0 { class.burst } else { class.rate / 10 }
+            .max(MAX_PACKET_SIZE as u64) as f64;
+        Self {
+            is_leaf,
+            capacity,
+            tokens: capacity,
+            ctokens: capacity,
+            refilled_at: Instant::now(),
+            queue: VecDeque::new(),
+            deficit: 0.0,
+            // 每轮至少能发送一个最大的数据包
+            quantum: (class.rate / 10).max(MAX_PACKET_SIZE as u64) as f64,
+            class,
+        }
+    }
+    
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.class.rate as f64).min(self.capacity);
+        self.ctokens = (self.ctokens + elapsed * self.class.ceil as f64).min(self.capacity);
+        self.refilled_at = now;
+    }
+    
+    fn mode(&self) -> ClassMode {
+        if self.ctokens < 0.0 {
+            ClassMode::CantSend
+        } else if self.tokens >= 0.0 {
+            ClassMode::CanSend
+        } else {
+            ClassMode::MayBorrow
+        }
+    }
+    
+    /// 扣除发送的字节数，欠下的令牌不超过令牌桶容量
+    fn charge(&mut self, cost: f64) {
+        self.tokens = (self.tokens - cost).max(-self.capacity);
+        self.ctokens = (self.ctokens - cost).max(-self.capacity);
+    }
+    
+    /// 保证速率的令牌恢复到非负所需的时间
+    fn tokens_wait(&self) -> Duration {
+        debt_wait(self.tokens, self.class.rate)
+    }
+    
+    /// 上限的令牌恢复到非负所需的时间
+    fn ctokens_wait(&self) -> Duration {
+        debt_wait(self.ctokens, self.class.ceil)
+    }
+}
+
+fn debt_wait(tokens: f64, rate: u64) -> Duration {
+    if tokens >= 0.0 {
+        Duration::ZERO
+    } else if rate == 0 {
+        Duration::MAX
+    } else {
+        Duration::from_secs_f64(-tokens / rate as f64)
+    }
+}
+
+#[derive(Default)]
+struct HtbState {
+    classes: HashMap<u32, ClassState>,
+    /// 节点分组对应的叶子分类
+    group_leaves: HashMap<String, u32>,
+    /// 节点所在的分组
+    membership: HashMap<String, String>,
+    /// 有数据包排队的叶子分类，按优先级分开，按轮询顺序排列
+    active: BTreeMap<u8, VecDeque<u32>>,
+    /// 不属于任何叶子分类的数据包，不做整形，优先发送
+    unclassified: VecDeque<(String, Packet, u8)>,
+}
+
+/// HTB调度器
+///
+/// 发往某节点的数据包按节点所在分组进入对应的叶子分类排队。叶子分类在保证速率内直接发送；
+/// 超过保证速率后，若沿父分类向上找到仍在保证速率内的祖先，且途经的分类都未超过上限，
+/// 则借用该祖先的带宽发送。出队时优先选择无需借用的分类，其次是借用层级较低的分类，
+/// 再按优先级，同优先级的分类之间按差额轮询发送。发往不属于任何叶子分类的节点的数据包不做整形。
+pub struct HtbScheduler {
+    state: Mutex<HtbState>,
+    ready: tokio::sync::Notify,
+}
+
+impl HtbScheduler {
+    /// 按分类层级创建调度器，分类配置不合法时返回错误
+    pub fn new(classes: Vec<HtbClass>) -> Result<Self, &'static str> {
+        Self::validate_classes(&classes)?;
+        
+        let parents: HashSet<u32> = classes.iter().filter_map(|class| class.parent_id).collect();
+        let mut state = HtbState::default();
+        for class in classes {
+            if let Some(group) = &class.group {
+                state.group_leaves.insert(group.clone(), class.id);
+            }
+            let is_leaf = !parents.contains(&class.id);
+            state.classes.insert(class.id, ClassState::new(class, is_leaf));
+        }
+        
+        Ok(Self {
+            state: Mutex::new(state),
+            ready: tokio::sync::Notify::new(),
+        })
+    }
+    
+    /// 检查分类层级：分类ID唯一、父分类存在且没有环、速率和上限合法，
+    /// 分组只能指定给叶子分类且每个分组只对应一个分类
+    pub fn validate_classes(classes: &[HtbClass]) -> Result<(), &'static str> {
+        let mut by_id = HashMap::new();
+        for class in classes {
+            if by_id.insert(class.id, class).is_some() {
+                return Err("Duplicate HTB class id");
+            }
+        }
+        
+        let mut groups = HashSet::new();
+        for class in classes {
+            if class.rate == 0 {
+                return Err("HTB class rate must be greater than 0");
+            }
+            if class.ceil < class.rate {
+                return Err("HTB class ceil must not be lower than rate");
+            }
+            
+            // 沿父分类向上，步数超过分类数说明存在环
+            let mut parent_id = class.parent_id;
+            for _ in 0..classes.len() {
+                let Some(id) = parent_id else { break };
+                parent_id = by_id.get(&id).ok_or("Unknown HTB parent class")?.parent_id;
+            }
+            if parent_id.is_some() {
+                return Err("HTB class hierarchy contains a cycle");
+            }
+            
+            if let Some(group) = &class.group {
+                if classes.iter().any(|child| child.parent_id == Some(class.id)) {
+                    return Err("Only leaf HTB classes can be assigned a group");
+                }
+                if !groups.insert(group) {
+                    return Err("HTB group assigned to more than one class");
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// 设置节点所在的分组，`None`表示不属于任何分组
+    ///
+    /// 节点已在原分类排队的数据包仍按原分类发送。
+    pub fn set_peer_group(&self, peer_id: &str, group_id: Option<&str>) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        match group_id {
+            Some(group_id) => state.membership.insert(peer_id.to_string(), group_id.to_string()),
+            None => state.membership.remove(peer_id),
+        };
+    }
+    
+    /// 取出下一个可以发送的数据包；没有时返回需要等待的时间，没有排队的数据包时返回`None`
+    fn dequeue(&self) -> Result<(String, Packet, u8), Option<Duration>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(item) = state.unclassified.pop_front() {
+            return Ok(item);
+        }
+        
+        let now = Instant::now();
+        for class in state.classes.values_mut() {
+            class.refill(now);
+        }
+        
+        // 每个有数据包排队的叶子分类能够发送的借用层级，0表示按自身的保证速率发送
+        let mut levels = HashMap::new();
+        let mut best: Option<(usize, u8)> = None;
+        for (&priority, active) in &state.active {
+            for &id in active {
+                if let Some(level) = state.send_level(id) {
+                    levels.insert(id, level);
+                    let candidate = (level, priority);
+                    best = Some(best.map_or(candidate, |best| best.min(candidate)));
+                }
+            }
+        }
+        let Some((level, priority)) = best else {
+            return Err(state.next_ready());
+        };
+        
+        // 差额轮询：轮到的分类差额不足以发送队首数据包时补充一个quantum并排到队尾
+        let HtbState { classes, active, .. } = &mut *state;
+        let Some(active) = active.get_mut(&priority) else {
+            return Err(None);
+        };
+        loop {
+            let Some(index) = active.iter().position(|id| levels.get(id) == Some(&level)) else {
+                return Err(None);
+            };
+            let id = active[index];
+            let Some(class) = classes.get_mut(&id) else {
+                active.remove(index);
+                continue;
+            };
+            let Some(cost) = class.queue.front().map(|(_, packet, _)| packet_cost(packet)) else {
+                active.remove(index);
+                continue;
+            };
+            
+            if class.deficit < cost {
+                class.deficit += class.quantum;
+                active.remove(index);
+                active.push_back(id);
+                continue;
+            }
+            
+            class.deficit -= cost;
+            let Some(item) = class.queue.pop_front() else {
+                continue;
+            };
+            if class.queue.is_empty() {
+                class.deficit = 0.0;
+                active.remove(index);
+            }
+            
+            // 从叶子分类到根分类逐级扣除令牌
+            let mut current = Some(id);
+            while let Some(class) = current.and_then(|id| classes.get_mut(&id)) {
+                class.charge(cost);
+                current = class.class.parent_id;
+            }
+            return Ok(item);
+        }
+    }
+}
+
+impl HtbState {
+    /// 叶子分类当前能够发送的借用层级，不能发送时返回`None`
+    fn send_level(&self, id: u32) -> Option<usize> {
+        let mut level = 0;
+        let mut current = self.classes.get(&id)?;
+        loop {
+            match current.mode() {
+                ClassMode::CanSend => return Some(level),
+                ClassMode::CantSend => return None,
+                ClassMode::MayBorrow => {
+                    current = self.classes.get(&current.class.parent_id?)?;
+                    level += 1;
+                }
+            }
+        }
+    }
+    
+    /// 排队的数据包中最早可以发送的等待时间，没有排队的数据包时返回`None`
+    fn next_ready(&self) -> Option<Duration> {
+        self.active.values()
+            .flatten()
+            .filter_map(|&id| self.ready_in(id))
+            .min()
+    }
+    
+    /// 叶子分类自身或某个祖先的保证速率令牌恢复，且途经分类的上限令牌也恢复所需的时间
+    fn ready_in(&self, id: u32) -> Option<Duration> {
+        let mut earliest: Option<Duration> = None;
+        let mut ceil_wait = Duration::ZERO;
+        let mut current = Some(id);
+        while let Some(class) = current.and_then(|id| self.classes.get(&id)) {
+            ceil_wait = ceil_wait.max(class.ctokens_wait());
+            let wait = class.tokens_wait().max(ceil_wait);
+            earliest = Some(earliest.map_or(wait, |earliest| earliest.min(wait)));
+            current = class.class.parent_id;
+        }
+        earliest
+    }
+}
+
+impl PacketScheduler for HtbScheduler {
+    fn enqueue(&self, peer_id: &str, packet: Packet, priority: u8) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let HtbState { classes, group_leaves, membership, active, unclassified } = &mut *state;
+        let leaf = membership.get(peer_id)
+            .and_then(|group_id| group_leaves.get(group_id))
+            .and_then(|id| classes.get_mut(id))
+            .filter(|class| class.is_leaf);
+        
+        match leaf {
+            Some(class) => {
+                if class.queue.len() >= constants::SCHEDULER_QUEUE_LEN {
+                    log::debug!("HTB class {} queue full, dropping packet for {}", class.class.id, peer_id);
+                    return;
+                }
+                if class.queue.is_empty() {
+                    active.entry(class.class.priority).or_default().push_back(class.class.id);
+                }
+                class.queue.push_back((peer_id.to_string(), packet, priority));
+            }
+            None => {
+                if unclassified.len() >= constants::SCHEDULER_QUEUE_LEN {
+                    log::debug!("HTB unclassified queue full, dropping packet for {}", peer_id);
+                    return;
+                }
+                unclassified.push_back((peer_id.to_string(), packet, priority));
+            }
+        }
+        drop(state);
+        self.ready.notify_one();
+    }
+    
+    fn next_packet(&self) -> BoxFuture<'_, (String, Packet, u8)> {
+        Box::pin(async move {
+            loop {
+                let ready = self.ready.notified();
+                match self.dequeue() {
+                    Ok(item) => return item,
+                    // 令牌恢复后重新检查，期间有新数据包入队时提前检查
+                    Err(Some(wait)) => {
+                        let _ = tokio::time::timeout(wait.max(Duration::from_millis(1)), ready).await;
+                    }
+                    Err(None) => ready.await,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{MessageType, PacketBuilder};
+    
+    /// 整形开销恰好为1000字节的数据包
+    fn packet() -> Packet {
+        PacketBuilder::new(MessageType::DataForward, vec![0u8; 1000 - constants::RAW_PACKET_HEADER_LEN]).build()
+    }
+    
+    fn class(id: u32, parent_id: Option<u32>, rate: u64, ceil: u64) -> HtbClass {
+        HtbClass { id, parent_id, rate, ceil, burst: 0, priority: 0, group: None }
+    }
+    
+    fn leaf(id: u32, parent_id: u32, rate: u64, ceil: u64, group: &str) -> HtbClass {
+        HtbClass { group: Some(group.to_string()), ..class(id, Some(parent_id), rate, ceil) }
+    }
+    
+    fn scheduler(classes: Vec<HtbClass>) -> HtbScheduler {
+        let scheduler = HtbScheduler::new(classes).unwrap();
+        for group in ["a", "b"] {
+            scheduler.set_peer_group(group, Some(group));
+        }
+        scheduler
+    }
+    
+    /// 让令牌按经过`elapsed`恢复，不实际等待
+    fn advance(scheduler: &HtbScheduler, elapsed: Duration) {
+        let mut state = scheduler.state.lock().unwrap();
+        for class in state.classes.values_mut() {
+            class.refilled_at -= elapsed;
+        }
+    }
+    
+    /// 模拟`seconds`秒内节点`a`和`b`始终有数据包排队，返回各节点发送的字节数
+    fn run(scheduler: &HtbScheduler, seconds: u32) -> HashMap<String, u64> {
+        let mut sent = HashMap::new();
+        for _ in 0..seconds * 100 {
+            for peer_id in ["a", "b"] {
+                for _ in 0..4 {
+                    scheduler.enqueue(peer_id, packet(), 0);
+                }
+            }
+            advance(scheduler, Duration::from_millis(10));
+            while let Ok((peer_id, packet, _)) = scheduler.dequeue() {
+                *sent.entry(peer_id).or_insert(0) += packet_cost(&packet) as u64;
+            }
+        }
+        sent
+    }
+    
+    #[test]
+    fn leaf_borrows_idle_parent_bandwidth_up_to_ceil() {
+        // a的上限等于父分类的速率，b不能借用
+        let scheduler = scheduler(vec![
+            class(1, None, 100_000, 100_000),
+            leaf(2, 1, 10_000, 100_000, "a"),
+            leaf(3, 1, 10_000, 10_000, "b"),
+        ]);
+        let sent = run(&scheduler, 10);
+        
+        // 总量不超过父分类的速率，另加令牌桶容量、可以欠下的令牌和测试实际运行期间恢复的令牌
+        assert!((95_000..=115_000).contains(&sent["b"]), "b sent {}", sent["b"]);
+        assert!(sent["a"] >= 850_000, "a sent {}", sent["a"]);
+        assert!(sent["a"] + sent["b"] <= 1_030_000, "total {}", sent["a"] + sent["b"]);
+    }
+    
+    #[test]
+    fn borrowing_is_limited_by_ceil() {
+        let scheduler = scheduler(vec![
+            class(1, None, 100_000, 100_000),
+            leaf(2, 1, 10_000, 30_000, "a"),
+            leaf(3, 1, 10_000, 10_000, "b"),
+        ]);
+        let sent = run(&scheduler, 10);
+        
+        assert!((290_000..=315_000).contains(&sent["a"]), "a sent {}", sent["a"]);
+        assert!((95_000..=115_000).contains(&sent["b"]), "b sent {}", sent["b"]);
+    }
+    
+    #[test]
+    fn higher_priority_class_borrows_first_but_others_keep_their_rate() {
+        let mut low = leaf(3, 1, 10_000, 100_000, "b");
+        low.priority = 1;
+        let scheduler = scheduler(vec![
+            class(1, None, 100_000, 100_000),
+            leaf(2, 1, 10_000, 100_000, "a"),
+            low,
+        ]);
+        let sent = run(&scheduler, 10);
+        
+        assert!(sent["a"] >= 850_000, "a sent {}", sent["a"]);
+        assert!((95_000..=115_000).contains(&sent["b"]), "b sent {}", sent["b"]);
+    }
+    
+    #[test]
+    fn same_priority_classes_share_excess_bandwidth() {
+        let scheduler = scheduler(vec![
+            class(1, None, 100_000, 100_000),
+            leaf(2, 1, 10_000, 100_000, "a"),
+            leaf(3, 1, 10_000, 100_000, "b"),
+        ]);
+        let sent = run(&scheduler, 10);
+        
+        let (a, b) = (sent["a"] as f64, sent["b"] as f64);
+        assert!((a / b - 1.0).abs() < 0.1, "a sent {}, b sent {}", a, b);
+        assert!(a + b >= 950_000.0 && a + b <= 1_030_000.0, "total {}", a + b);
+    }
+    
+    #[test]
+    fn unclassified_packets_are_not_shaped() {
+        let scheduler = scheduler(vec![class(1, None, 1000, 1000)]);
+        for _ in 0..100 {
+            scheduler.enqueue("other", packet(), 0);
+        }
+        for _ in 0..100 {
+            assert_eq!(scheduler.dequeue().unwrap().0, "other");
+        }
+        assert_eq!(scheduler.dequeue().unwrap_err(), None);
+    }
+    
+    #[test]
+    fn rejects_invalid_class_hierarchies() {
+        let cases = [
+            (vec![class(1, None, 100, 100), class(1, None, 100, 100)], "Duplicate HTB class id"),
+            (vec![class(1, None, 0, 100)], "HTB class rate must be greater than 0"),
+            (vec![class(1, None, 100, 50)], "HTB class ceil must not be lower than rate"),
+            (vec![class(1, Some(9), 100, 100)], "Unknown HTB parent class"),
+            (vec![class(1, Some(2), 100, 100), class(2, Some(1), 100, 100)], "HTB class hierarchy contains a cycle"),
+            (vec![leaf(1, 2, 100, 100, "a"), HtbClass { group: Some("b".to_string()), ..class(2, None, 100, 100) }], "Only leaf HTB classes can be assigned a group"),
+            (vec![class(1, None, 100, 100), leaf(2, 1, 100, 100, "a"), leaf(3, 1, 100, 100, "a")], "HTB group assigned to more than one class"),
+        ];
+        for (classes, error) in cases {
+            assert_eq!(HtbScheduler::validate_classes(&classes), Err(error));
+        }
+    }
+}
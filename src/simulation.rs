@@ -0,0 +1,732 @@
+/*!
+网络模拟模块
+
+在单个进程内模拟节点之间的网络，用于可重复的多节点测试，包括：
+- 模拟套接字之间通过通道传递数据报，不经过系统网络
+- 可配置的时延、抖动、丢包率和带宽
+- 随机数使用固定种子，相同的种子和发送顺序得到相同的结果
+- 作为传输工厂传给`NetworkManager::with_transport`
+
+时延使用`tokio::time`，测试中配合`tokio::time::pause`可以跳过等待。
+*/
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use crate::transport::{DatagramSocket, TransportFactory};
+
+/// 自动分配端口的起始值
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// 模拟套接字接收的数据报和发送地址
+type Datagram = (Vec<u8>, SocketAddr);
+
+/// 模拟网络的状态
+struct NetworkState {
+    /// 已绑定的地址和对应套接字的接收通道
+    sockets: HashMap<SocketAddr, mpsc::UnboundedSender<Datagram>>,
+    latency: Duration,
+    jitter: Duration,
+    packet_loss_rate: f64,
+    /// 每个套接字的发送带宽（比特/秒），0表示不限
+    bandwidth_bps: u64,
+    rng: StdRng,
+    /// 每个发送地址的链路空闲时刻，用于按带宽排队
+    link_free_at: HashMap<SocketAddr, Instant>,
+    next_port: u16,
+}
+
+/// 进程内的模拟网络
+///
+/// 克隆得到的是同一个网络，通过它绑定的模拟套接字之间可以互相发送数据报。
+#[derive(Clone)]
+pub struct SimulatedNetwork {
+    state: Arc<Mutex<NetworkState>>,
+}
+
+impl Default for SimulatedNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedNetwork {
+    /// 创建没有时延、丢包和带宽限制的网络，随机数种子为0
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(NetworkState {
+                sockets: HashMap::new(),
+                latency: Duration::ZERO,
+                jitter: Duration::ZERO,
+                packet_loss_rate: 0.0,
+                bandwidth_bps: 0,
+                rng: StdRng::seed_from_u64(0),
+                link_free_at: HashMap::new(),
+                next_port: EPHEMERAL_PORT_START,
+            })),
+        }
+    }
+    
+    /// 设置单向时延
+    pub fn with_latency(self, latency: Duration) -> Self {
+        self.state().latency = latency;
+        self
+    }
+    
+    /// 设置抖动，每个数据报的时延额外增加`[0, jitter)`内的随机值，抖动可能导致乱序
+    pub fn with_jitter(self, jitter: Duration) -> Self {
+        self.state().jitter = jitter;
+        self
+    }
+    
+    /// 设置丢包率，取值限制在`[0, 1]`
+    pub fn with_packet_loss_rate(self, packet_loss_rate: f64) -> Self {
+        self.state().packet_loss_rate = packet_loss_rate.clamp(0.0, 1.0);
+        self
+    }
+    
+    /// 设置每个套接字的发送带宽（比特/秒），0表示不限
+    pub fn with_bandwidth(self, bandwidth_bps: u64) -> Self {
+        self.state().bandwidth_bps = bandwidth_bps;
+        self
+    }
+    
+    /// 设置随机数种子
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.state().rng = StdRng::seed_from_u64(seed);
+        self
+    }
+    
+    fn state(&self) -> std::sync::MutexGuard<'_, NetworkState> {
+        // 持有锁时不会panic，锁不会中毒
+        self.state.lock().unwrap()
+    }
+    
+    /// 投递数据报，按丢包率丢弃，按时延、抖动和带宽延迟到达
+    fn deliver(&self, data: &[u8], from: SocketAddr, to: SocketAddr) {
+        let mut state = self.state();
+        // 与UDP一致，目标地址没有绑定套接字时数据报丢失
+        let Some(sender) = state.sockets.get(&to).cloned() else {
+            return;
+        };
+        let packet_loss_rate = state.packet_loss_rate;
+        if packet_loss_rate > 0.0 && state.rng.gen_bool(packet_loss_rate) {
+            return;
+        }
+        
+        let mut delay = state.latency;
+        if !state.jitter.is_zero() {
+            let jitter = state.jitter;
+            delay += jitter.mul_f64(state.rng.gen::<f64>());
+        }
+        if state.bandwidth_bps > 0 {
+            let now = Instant::now();
+            let transmit = Duration::from_secs_f64(data.len() as f64 * 8.0 / state.bandwidth_bps as f64);
+            let start = state.link_free_at.get(&from).copied().filter(|free_at| *free_at > now).unwrap_or(now);
+            state.link_free_at.insert(from, start + transmit);
+            delay += start + transmit - now;
+        }
+        drop(state);
+        
+        let datagram = (data.to_vec(), from);
+        if delay.is_zero() {
+            let _ = sender.send(datagram);
+        } else {
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                let _ = sender.send(datagram);
+            });
+        }
+    }
+}
+
+impl TransportFactory for SimulatedNetwork {
+    fn bind(&self, addr: SocketAddr) -> io::Result<Arc<dyn DatagramSocket>> {
+        Ok(Arc::new(SimulatedSocket::bind(self, addr)?))
+    }
+}
+
+/// 绑定在模拟网络上的套接字，释放时解除绑定
+pub struct SimulatedSocket {
+    network: SimulatedNetwork,
+    local_addr: SocketAddr,
+    receiver: Mutex<mpsc::UnboundedReceiver<Datagram>>,
+}
+
+impl SimulatedSocket {
+    /// 在模拟网络上绑定地址，端口为0时自动分配，地址已被占用时返回`AddrInUse`
+    pub fn bind(network: &SimulatedNetwork, addr: SocketAddr) -> io::Result<Self> {
+        let mut state = network.state();
+        let mut local_addr = addr;
+        if addr.port() == 0 {
+            let port = (state.next_port..=u16::MAX)
+                .find(|port| !state.sockets.contains_key(&SocketAddr::new(addr.ip(), *port)))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::AddrNotAvailable, "no ephemeral port available"))?;
+            state.next_port = port.checked_add(1).unwrap_or(EPHEMERAL_PORT_START);
+            local_addr.set_port(port);
+        } else if state.sockets.contains_key(&addr) {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "address already bound in simulated network"));
+        }
+        
+        let (sender, receiver) = mpsc::unbounded_channel();
+        state.sockets.insert(local_addr, sender);
+        drop(state);
+        
+        Ok(Self {
+            network: network.clone(),
+            local_addr,
+            receiver: Mutex::new(receiver),
+        })
+    }
+}
+
+impl DatagramSocket for SimulatedSocket {
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        self.network.deliver(buf, self.local_addr, addr);
+        Ok(buf.len())
+    }
+    
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let mut receiver = self.receiver.lock().unwrap();
+        match receiver.try_recv() {
+            Ok((data, from)) => {
+                // 与UDP一致，超出缓冲区的部分被截断
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                Ok((len, from))
+            }
+            Err(_) => Err(io::Error::from(io::ErrorKind::WouldBlock)),
+        }
+    }
+    
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+}
+
+impl Drop for SimulatedSocket {
+    fn drop(&mut self) {
+        let mut state = self.network.state();
+        state.sockets.remove(&self.local_addr);
+        state.link_free_at.remove(&self.local_addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{BoxFuture, NetworkManager, NonceProvider};
+    use crate::{DeviceMode, KeyPair, MessageType, NodeStatus, NonceStore, PacketBuilder, TtlExceededMessage, VpnetError};
+    
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+    
+    fn recv(socket: &SimulatedSocket) -> io::Result<(Vec<u8>, SocketAddr)> {
+        let mut buf = [0u8; 64];
+        let (len, from) = socket.recv_from(&mut buf)?;
+        Ok((buf[..len].to_vec(), from))
+    }
+    
+    /// 发送`count`个数据报，返回到达的序号
+    async fn delivered(network: &SimulatedNetwork, count: u8) -> Vec<u8> {
+        let a = SimulatedSocket::bind(network, addr("10.0.0.1:1000")).unwrap();
+        let b = SimulatedSocket::bind(network, addr("10.0.0.2:1000")).unwrap();
+        for i in 0..count {
+            a.send_to(&[i], b.local_addr).unwrap();
+        }
+        tokio::task::yield_now().await;
+        std::iter::from_fn(|| recv(&b).ok().map(|(data, _)| data[0])).collect()
+    }
+    
+    #[tokio::test]
+    async fn delivers_datagrams_between_sockets() {
+        let network = SimulatedNetwork::new();
+        let a = SimulatedSocket::bind(&network, addr("10.0.0.1:1000")).unwrap();
+        let b = SimulatedSocket::bind(&network, addr("10.0.0.2:2000")).unwrap();
+        
+        assert_eq!(a.send_to(b"hello", addr("10.0.0.2:2000")).unwrap(), 5);
+        assert_eq!(recv(&b).unwrap(), (b"hello".to_vec(), addr("10.0.0.1:1000")));
+        assert_eq!(recv(&b).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        
+        // 目标地址没有绑定套接字时与UDP一致，发送成功但数据报丢失
+        assert!(a.send_to(b"lost", addr("10.0.0.3:3000")).is_ok());
+    }
+    
+    #[tokio::test]
+    async fn truncates_datagrams_larger_than_buffer() {
+        let network = SimulatedNetwork::new();
+        let a = SimulatedSocket::bind(&network, addr("10.0.0.1:1000")).unwrap();
+        let b = SimulatedSocket::bind(&network, addr("10.0.0.2:1000")).unwrap();
+        a.send_to(&[1u8; 100], b.local_addr).unwrap();
+        
+        let mut buf = [0u8; 10];
+        assert_eq!(b.recv_from(&mut buf).unwrap().0, 10);
+    }
+    
+    #[test]
+    fn rejects_address_in_use_until_dropped() {
+        let network = SimulatedNetwork::new();
+        let socket = SimulatedSocket::bind(&network, addr("10.0.0.1:1000")).unwrap();
+        let err = SimulatedSocket::bind(&network, addr("10.0.0.1:1000")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        
+        drop(socket);
+        assert!(SimulatedSocket::bind(&network, addr("10.0.0.1:1000")).is_ok());
+    }
+    
+    #[test]
+    fn assigns_ephemeral_ports() {
+        let network = SimulatedNetwork::new();
+        let first = SimulatedSocket::bind(&network, addr("10.0.0.1:0")).unwrap();
+        let second = SimulatedSocket::bind(&network, addr("10.0.0.1:0")).unwrap();
+        assert_eq!(first.local_addr().unwrap().port(), EPHEMERAL_PORT_START);
+        assert_eq!(second.local_addr().unwrap().port(), EPHEMERAL_PORT_START + 1);
+    }
+    
+    #[tokio::test]
+    async fn drops_everything_at_full_loss() {
+        let network = SimulatedNetwork::new().with_packet_loss_rate(1.0);
+        assert!(delivered(&network, 20).await.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn packet_loss_is_reproducible_with_same_seed() {
+        let first = delivered(&SimulatedNetwork::new().with_packet_loss_rate(0.5).with_seed(42), 100).await;
+        let second = delivered(&SimulatedNetwork::new().with_packet_loss_rate(0.5).with_seed(42), 100).await;
+        assert_eq!(first, second);
+        assert!(!first.is_empty() && first.len() < 100, "{} of 100 delivered", first.len());
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn delays_delivery_by_latency() {
+        let network = SimulatedNetwork::new().with_latency(Duration::from_millis(50));
+        let a = SimulatedSocket::bind(&network, addr("10.0.0.1:1000")).unwrap();
+        let b = SimulatedSocket::bind(&network, addr("10.0.0.2:1000")).unwrap();
+        a.send_to(b"ping", b.local_addr).unwrap();
+        
+        tokio::time::sleep(Duration::from_millis(49)).await;
+        assert_eq!(recv(&b).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        assert_eq!(recv(&b).unwrap().0, b"ping");
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn queues_datagrams_by_bandwidth() {
+        // 8000比特/秒，每个100字节的数据报需要100毫秒
+        let network = SimulatedNetwork::new().with_bandwidth(8000);
+        let a = SimulatedSocket::bind(&network, addr("10.0.0.1:1000")).unwrap();
+        let b = SimulatedSocket::bind(&network, addr("10.0.0.2:1000")).unwrap();
+        a.send_to(&[1u8; 100], b.local_addr).unwrap();
+        a.send_to(&[2u8; 100], b.local_addr).unwrap();
+        
+        tokio::time::sleep(Duration::from_millis(101)).await;
+        assert_eq!(recv(&b).unwrap().0[0], 1);
+        assert_eq!(recv(&b).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(recv(&b).unwrap().0[0], 2);
+    }
+    
+    fn manager(network: &SimulatedNetwork, node_id: &str, listen: &str) -> NetworkManager {
+        NetworkManager::with_transport(
+            vec![addr(listen)],
+            node_id.to_string(),
+            node_id.to_string(),
+            vec![0u8; 32],
+            Arc::new(network.clone())
+        ).unwrap()
+    }
+    
+    /// 等待`manager`认识`peer_id`，超时返回`false`
+    async fn wait_for_peer(manager: &NetworkManager, peer_id: &str, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if manager.get_peer(peer_id).await.is_some() {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        false
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn network_managers_handshake_over_simulated_network() {
+        let network = SimulatedNetwork::new().with_latency(Duration::from_millis(5));
+        let server = manager(&network, "server", "10.0.0.1:51820");
+        let client = manager(&network, "client", "10.0.0.2:51820");
+        server.start().await;
+        client.start().await;
+        
+        client.send_handshake_request(addr("10.0.0.1:51820"), None, None).await.unwrap();
+        assert!(wait_for_peer(&server, "client", Duration::from_secs(5)).await);
+        assert!(wait_for_peer(&client, "server", Duration::from_secs(5)).await);
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn handshake_times_out_when_all_packets_are_lost() {
+        let network = SimulatedNetwork::new().with_packet_loss_rate(1.0);
+        let server = manager(&network, "server", "10.0.0.1:51820");
+        let client = manager(&network, "client", "10.0.0.2:51820");
+        server.start().await;
+        client.start().await;
+        
+        client.send_handshake_request(addr("10.0.0.1:51820"), None, None).await.unwrap();
+        assert!(!wait_for_peer(&server, "client", Duration::from_millis(300)).await);
+        
+        // 网络恢复后重新握手成功
+        network.state().packet_loss_rate = 0.0;
+        client.send_handshake_request(addr("10.0.0.1:51820"), None, None).await.unwrap();
+        assert!(wait_for_peer(&server, "client", Duration::from_secs(5)).await);
+    }
+    
+    
+    /// 从服务端的随机数存储签发随机数，模拟客户端请求`GET /api/auth/nonce`
+    struct StoreNonceProvider(Arc<tokio::sync::Mutex<NonceStore>>);
+    
+    impl NonceProvider for StoreNonceProvider {
+        fn fetch_nonce(&self, _server: SocketAddr) -> BoxFuture<'_, Result<Vec<u8>, VpnetError>> {
+            Box::pin(async move {
+                Ok(self.0.lock().await.issue().map_err(VpnetError::Other)?.to_vec())
+            })
+        }
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn handshake_requires_fresh_server_nonce() {
+        let network = SimulatedNetwork::new();
+        let nonce_store = Arc::new(tokio::sync::Mutex::new(NonceStore::default()));
+        let mut server = manager(&network, "server", "10.0.0.1:51820");
+        server.set_nonce_store(nonce_store.clone());
+        let mut client = manager(&network, "client", "10.0.0.2:51820");
+        client.set_nonce_provider(Arc::new(StoreNonceProvider(nonce_store.clone())));
+        let intruder = manager(&network, "intruder", "10.0.0.3:51820");
+        server.start().await;
+        client.start().await;
+        intruder.start().await;
+        
+        // 没有随机数的握手被拒绝
+        intruder.send_handshake_request(addr("10.0.0.1:51820"), None, None).await.unwrap();
+        assert!(!wait_for_peer(&server, "intruder", Duration::from_millis(300)).await);
+        
+        // 客户端从随机数来源获取随机数后握手成功，随机数随即失效
+        client.send_handshake_request(addr("10.0.0.1:51820"), None, None).await.unwrap();
+        assert!(wait_for_peer(&server, "client", Duration::from_secs(5)).await);
+        assert!(nonce_store.lock().await.is_empty());
+        
+        // 重放已使用的随机数被拒绝
+        let nonce = nonce_store.lock().await.issue().unwrap();
+        assert!(nonce_store.lock().await.consume(&nonce));
+        intruder.send_handshake_request(addr("10.0.0.1:51820"), Some(nonce.to_vec()), None).await.unwrap();
+        assert!(!wait_for_peer(&server, "intruder", Duration::from_millis(300)).await);
+    }
+    
+    
+    /// 使用真实签名密钥的节点，能够回应迁移验证
+    fn signing_manager(network: &SimulatedNetwork, node_id: &str, listen: &str, key_pair: &KeyPair) -> NetworkManager {
+        let mut manager = NetworkManager::with_transport(
+            vec![addr(listen)],
+            node_id.to_string(),
+            node_id.to_string(),
+            key_pair.public_key.clone(),
+            Arc::new(network.clone())
+        ).unwrap();
+        manager.set_signing_key(KeyPair::from_pkcs8(key_pair.private_key()).unwrap());
+        manager
+    }
+    
+    /// 等待`manager`记录的`peer_id`地址变为`address`，超时返回`false`
+    async fn wait_for_address(manager: &NetworkManager, peer_id: &str, address: SocketAddr, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if manager.get_peer(peer_id).await.is_some_and(|peer| peer.address == address) {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        false
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn handshake_from_new_address_migrates_after_signed_challenge() {
+        let network = SimulatedNetwork::new();
+        let server = manager(&network, "server", "10.0.0.1:51820");
+        let key_pair = KeyPair::generate().unwrap();
+        let wifi = signing_manager(&network, "phone", "10.0.0.2:51820", &key_pair);
+        server.start().await;
+        wifi.start().await;
+        wifi.send_handshake_request(addr("10.0.0.1:51820"), None, None).await.unwrap();
+        assert!(wait_for_peer(&server, "phone", Duration::from_secs(5)).await);
+        let connection_id = server.get_peer("phone").await.unwrap().connection_id;
+        
+        // 只知道公钥的节点从其他地址握手，无法回应迁移验证，地址不变
+        let impostor = NetworkManager::with_transport(
+            vec![addr("10.0.0.9:51820")],
+            "phone".to_string(),
+            "phone".to_string(),
+            key_pair.public_key.clone(),
+            Arc::new(network.clone())
+        ).unwrap();
+        impostor.start().await;
+        impostor.send_handshake_request(addr("10.0.0.1:51820"), None, None).await.unwrap();
+        assert!(!wait_for_address(&server, "phone", addr("10.0.0.9:51820"), Duration::from_millis(300)).await);
+        
+        // 同一节点切换网络后从新地址握手，签名回应迁移验证后原地更新地址
+        let lte = signing_manager(&network, "phone", "10.0.0.3:51820", &key_pair);
+        lte.start().await;
+        lte.send_handshake_request(addr("10.0.0.1:51820"), None, None).await.unwrap();
+        assert!(wait_for_address(&server, "phone", addr("10.0.0.3:51820"), Duration::from_secs(5)).await);
+        
+        let peers = server.get_peers().await;
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].status, NodeStatus::Online);
+        assert_eq!(peers[0].connection_id, connection_id);
+    }
+    
+    
+    /// 广播ARP请求的以太网帧
+    fn arp_broadcast_frame() -> Vec<u8> {
+        let mut frame = vec![0xff; 6];
+        frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 0x02]);
+        frame.extend_from_slice(&[0x08, 0x06]);
+        frame.extend_from_slice(&[0u8; 28]);
+        frame
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn tap_frame_arrives_with_ethernet_header() {
+        let network = SimulatedNetwork::new().with_latency(Duration::from_millis(5));
+        let mut server = manager(&network, "server", "10.0.0.1:51820");
+        let client = manager(&network, "client", "10.0.0.2:51820");
+        let (device_tx, mut device_rx) = tokio::sync::mpsc::channel(8);
+        server.set_device_sender(device_tx, DeviceMode::Tap);
+        server.start().await;
+        client.start().await;
+        
+        client.send_handshake_request(addr("10.0.0.1:51820"), None, Some("10.8.0.2".to_string())).await.unwrap();
+        assert!(wait_for_peer(&server, "client", Duration::from_secs(5)).await);
+        assert!(wait_for_peer(&client, "server", Duration::from_secs(5)).await);
+        
+        let frame = arp_broadcast_frame();
+        client.forward_frame(&frame, DeviceMode::Tap).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(5), device_rx.recv()).await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, frame);
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn tun_mode_floods_ipv6_multicast() {
+        let network = SimulatedNetwork::new().with_latency(Duration::from_millis(5));
+        let mut server = manager(&network, "server", "10.0.0.1:51820");
+        let client = manager(&network, "client", "10.0.0.2:51820");
+        let (device_tx, mut device_rx) = tokio::sync::mpsc::channel(8);
+        server.set_device_sender(device_tx, DeviceMode::Tun);
+        server.start().await;
+        client.start().await;
+        
+        client.send_handshake_request(addr("10.0.0.1:51820"), None, Some("10.8.0.2".to_string())).await.unwrap();
+        assert!(wait_for_peer(&server, "client", Duration::from_secs(5)).await);
+        assert!(wait_for_peer(&client, "server", Duration::from_secs(5)).await);
+        
+        // 发往ff02::2的路由器请求，只填写报文头中用到的字段
+        let mut packet = vec![0u8; 48];
+        packet[0] = 0x60;
+        packet[6] = 58;
+        packet[7] = 255;
+        packet[24] = 0xff;
+        packet[25] = 0x02;
+        packet[39] = 0x02;
+        packet[40] = 133;
+        client.forward_frame(&packet, DeviceMode::Tun).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(5), device_rx.recv()).await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, packet);
+        
+        // 单播IPv6没有对应的节点
+        packet[24] = 0xfd;
+        assert!(client.forward_frame(&packet, DeviceMode::Tun).await.is_err());
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn packet_sizes_count_the_inner_ip_packet() {
+        let network = SimulatedNetwork::new().with_latency(Duration::from_millis(5));
+        let mut server = manager(&network, "server", "10.0.0.1:51820");
+        let mut client = manager(&network, "client", "10.0.0.2:51820");
+        let (device_tx, mut device_rx) = tokio::sync::mpsc::channel(8);
+        server.set_device_sender(device_tx, DeviceMode::Tap);
+        let (client_device_tx, _client_device_rx) = tokio::sync::mpsc::channel(8);
+        client.set_device_sender(client_device_tx, DeviceMode::Tap);
+        server.start().await;
+        client.start().await;
+        
+        client.send_handshake_request(addr("10.0.0.1:51820"), None, Some("10.8.0.2".to_string())).await.unwrap();
+        assert!(wait_for_peer(&server, "client", Duration::from_secs(5)).await);
+        assert!(wait_for_peer(&client, "server", Duration::from_secs(5)).await);
+        
+        // 1420字节的IP数据包，封装后的数据报超过1420字节
+        let mut frame = arp_broadcast_frame();
+        frame.resize(crate::ETHERNET_HEADER_LEN + 1420, 0);
+        client.forward_frame(&frame, DeviceMode::Tap).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), device_rx.recv()).await
+            .unwrap()
+            .unwrap();
+        
+        for stats in [client.packet_size_stats(), server.packet_size_stats()] {
+            assert_eq!(stats.count, 1);
+            assert_eq!(stats.buckets[5].range, "1281-1420");
+            assert_eq!(stats.buckets[5].count, 1);
+            assert_eq!(stats.mean, Some(1420.0));
+        }
+    }
+    
+    #[tokio::test]
+    async fn tun_mode_rejects_frames_without_peer() {
+        let network = SimulatedNetwork::new();
+        let client = manager(&network, "client", "10.0.0.2:51820");
+        let frame = arp_broadcast_frame();
+        assert!(client.forward_frame(&frame[crate::ETHERNET_HEADER_LEN..], DeviceMode::Tun).await.is_err());
+        assert!(client.forward_frame(&[], DeviceMode::Tap).await.is_err());
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn handshaken_managers_exchange_data_with_the_negotiated_key() {
+        let network = SimulatedNetwork::new().with_latency(Duration::from_millis(5));
+        let mut server = manager(&network, "server", "10.0.0.1:51820");
+        let mut client = manager(&network, "client", "10.0.0.2:51820");
+        let (server_device_tx, mut server_device_rx) = tokio::sync::mpsc::channel(8);
+        server.set_device_sender(server_device_tx, DeviceMode::Tap);
+        let (client_device_tx, mut client_device_rx) = tokio::sync::mpsc::channel(8);
+        client.set_device_sender(client_device_tx, DeviceMode::Tap);
+        server.start().await;
+        client.start().await;
+        
+        client.send_handshake_request(addr("10.0.0.1:51820"), None, Some("10.8.0.2".to_string())).await.unwrap();
+        assert!(wait_for_peer(&server, "client", Duration::from_secs(5)).await);
+        assert!(wait_for_peer(&client, "server", Duration::from_secs(5)).await);
+        let session_key = client.get_peer("server").await.unwrap().session_key;
+        assert_eq!(session_key.len(), 32);
+        assert_eq!(server.get_peer("client").await.unwrap().session_key, session_key);
+        
+        let frame = arp_broadcast_frame();
+        client.forward_data("server", &frame, 0x0806, 0).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(5), server_device_rx.recv()).await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, frame);
+        
+        server.forward_data("client", &frame, 0x0806, 0).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(5), client_device_rx.recv()).await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, frame);
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn reconnect_negotiates_new_session_key_from_ephemeral_keys() {
+        let network = SimulatedNetwork::new().with_latency(Duration::from_millis(5));
+        let server = manager(&network, "server", "10.0.0.1:51820");
+        let client = manager(&network, "client", "10.0.0.2:51820");
+        server.start().await;
+        client.start().await;
+        
+        client.send_handshake_request(addr("10.0.0.1:51820"), None, None).await.unwrap();
+        assert!(wait_for_peer(&server, "client", Duration::from_secs(5)).await);
+        assert!(wait_for_peer(&client, "server", Duration::from_secs(5)).await);
+        let initial = client.get_peer("server").await.unwrap();
+        assert_eq!(initial.status, NodeStatus::Online);
+        
+        client.initiate_handshake("server").await.unwrap();
+        assert_eq!(client.get_peer("server").await.unwrap().status, NodeStatus::Connecting);
+        
+        // 收到握手响应后恢复在线，会话密钥由双方的临时密钥重新协商
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let reconnected = loop {
+            let peer = client.get_peer("server").await.unwrap();
+            if peer.status == NodeStatus::Online {
+                break peer;
+            }
+            assert!(Instant::now() < deadline, "peer did not come back online");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(reconnected.session_key.len(), 32);
+        assert_ne!(reconnected.session_key, initial.session_key);
+        assert_eq!(server.get_peer("client").await.unwrap().session_key, reconnected.session_key);
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn reconnect_from_added_socket_moves_peer_traffic_to_it() {
+        let network = SimulatedNetwork::new().with_latency(Duration::from_millis(5));
+        let mut server = manager(&network, "server", "10.0.0.1:51820");
+        let mut client = manager(&network, "client", "10.0.0.2:51820");
+        let extra = server.add_socket(network.bind(addr("10.0.0.1:0")).unwrap()).unwrap();
+        let (device_tx, mut device_rx) = tokio::sync::mpsc::channel(8);
+        client.set_device_sender(device_tx, DeviceMode::Tap);
+        server.start().await;
+        client.start().await;
+        
+        client.send_handshake_request(addr("10.0.0.1:51820"), None, None).await.unwrap();
+        assert!(wait_for_peer(&server, "client", Duration::from_secs(5)).await);
+        assert!(wait_for_peer(&client, "server", Duration::from_secs(5)).await);
+        assert!(server.initiate_handshake_from("client", addr("10.0.0.1:1")).await.is_err());
+        
+        // 握手从新增的套接字发出，客户端此后把该地址记为服务端地址
+        server.initiate_handshake_from("client", extra).await.unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let peer = server.get_peer("client").await.unwrap();
+            let server_peer = client.get_peer("server").await.unwrap();
+            if peer.status == NodeStatus::Online && server_peer.address == extra {
+                assert_eq!(peer.local_addr, Some(extra));
+                break;
+            }
+            assert!(Instant::now() < deadline, "peer did not move to the added socket");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        
+        let frame = arp_broadcast_frame();
+        server.forward_data("client", &frame, 0x0806, 0).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(5), device_rx.recv()).await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, frame);
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn ttl_exceeded_from_unknown_address_is_ignored() {
+        let network = SimulatedNetwork::new().with_latency(Duration::from_millis(5));
+        let server = manager(&network, "server", "10.0.0.1:51820");
+        let client = manager(&network, "client", "10.0.0.2:51820");
+        server.start().await;
+        client.start().await;
+        client.send_handshake_request(addr("10.0.0.1:51820"), None, None).await.unwrap();
+        assert!(wait_for_peer(&client, "server", Duration::from_secs(5)).await);
+        
+        // 路径上的节点回复的TTL耗尽通知被接受
+        let reply = client.send_probe("server", 1, 41).await.unwrap();
+        let message = tokio::time::timeout(Duration::from_secs(5), reply).await.unwrap().unwrap();
+        assert_eq!(message.hop_node_id, "server");
+        
+        // 服务端停止后，未知地址伪造的回复不会完成探测
+        server.stop();
+        let mut reply = client.send_probe("server", 1, 42).await.unwrap();
+        let forged = TtlExceededMessage {
+            original_src: "client".to_string(),
+            original_dst: "server".to_string(),
+            hop_node_id: "intruder".to_string(),
+            seq: 42,
+        };
+        let packet = PacketBuilder::new(MessageType::TtlExceeded, serde_json::to_vec(&forged).unwrap()).build();
+        let intruder = SimulatedSocket::bind(&network, addr("10.0.0.3:51820")).unwrap();
+        intruder.send_to(&packet.encode().unwrap(), addr("10.0.0.2:51820")).unwrap();
+        
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(matches!(reply.try_recv(), Err(tokio::sync::oneshot::error::TryRecvError::Empty)));
+    }
+}
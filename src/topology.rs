@@ -0,0 +1,88 @@
+/*!
+VPNet网络拓扑模块
+
+描述本节点视角下的覆盖网络拓扑，包括：
+- 节点和连接快照
+- 导出Graphviz DOT格式
+*/
+
+use std::fmt::Write;
+use serde::Serialize;
+use crate::protocol::NodeStatus;
+
+/// 网络拓扑快照
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NetworkTopology {
+    pub nodes: Vec<NodeSnapshot>,
+    pub edges: Vec<Edge>,
+}
+
+/// 拓扑中的节点
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeSnapshot {
+    pub node_id: String,
+    pub node_name: String,
+    pub virtual_ip: String,
+    pub address: String,
+    #[serde(with = "crate::protocol::lowercase_name")]
+    pub status: NodeStatus,
+    /// 是否为生成快照的本地节点
+    pub local: bool,
+}
+
+/// 拓扑中的连接
+#[derive(Debug, Clone, Serialize)]
+pub struct Edge {
+    pub src: String,
+    pub dst: String,
+    /// 往返时延（毫秒），未测量时为0；经中继的连接为两段之和
+    pub rtt_ms: f64,
+    /// 是否为直连，否则由本节点中继
+    pub direct: bool,
+    /// 最近的收发速率（字节/秒），未知时为0
+    pub bytes_per_sec: f64,
+}
+
+impl NetworkTopology {
+    /// 导出为Graphviz DOT格式
+    ///
+    /// 直连为实线，中继为虚线，本地节点加粗显示。
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("graph vpnet {\n    node [shape=box];\n");
+        
+        for node in &self.nodes {
+            let label = format!("{}\\n{}", escape_dot(&node.node_name), escape_dot(&node.virtual_ip));
+            let style = if node.local { ", style=bold" } else { "" };
+            let _ = writeln!(dot, "    \"{}\" [label=\"{}\"{}];", escape_dot(&node.node_id), label, style);
+        }
+        
+        for edge in &self.edges {
+            let style = if edge.direct { "solid" } else { "dashed" };
+            let _ = writeln!(
+                dot,
+                "    \"{}\" -- \"{}\" [label=\"{:.1} ms\", style={}];",
+                escape_dot(&edge.src),
+                escape_dot(&edge.dst),
+                edge.rtt_ms,
+                style
+            );
+        }
+        
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// 转义DOT双引号字符串中的特殊字符
+fn escape_dot(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,288 @@
+/*!
+VPNet数据包变换模块
+
+在虚拟设备和网络之间对数据包进行自定义处理，包括：
+- 数据包变换接口
+- VLAN标签剥离
+- 目的地址转换（DNAT）
+- 拒绝发往指定网段的数据包
+*/
+
+use std::net::Ipv4Addr;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::routing::IpCidr;
+use crate::virtual_device::IcmpUnreachCode;
+
+/// 802.1Q VLAN标签的EtherType
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+/// IPv4的EtherType
+const ETHERTYPE_IPV4: u16 = 0x0800;
+
+/// 以太网帧中EtherType字段的偏移
+const ETHERTYPE_OFFSET: usize = 12;
+
+/// VLAN标签长度
+const VLAN_TAG_LEN: usize = 4;
+
+/// 数据包方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// 从虚拟设备发往网络
+    Outbound,
+    /// 从网络写入虚拟设备
+    Inbound,
+}
+
+/// 数据包变换错误
+#[derive(Error, Debug)]
+pub enum TransformError {
+    #[error("Malformed packet: {0}")]
+    Malformed(&'static str),
+    
+    #[error("Packet dropped: {0}")]
+    Dropped(String),
+    
+    /// 丢弃并向源地址回复ICMP目标不可达
+    #[error("Packet rejected: {0:?}")]
+    Rejected(IcmpUnreachCode),
+}
+
+/// 数据包变换
+///
+/// 发出的数据包按顺序经过所有变换，收到的数据包按相反顺序经过。
+pub trait PacketTransform: Send + Sync {
+    /// 原地修改数据包
+    fn transform(&self, packet: &mut Vec<u8>, direction: Direction) -> Result<(), TransformError>;
+}
+
+/// 数据包变换配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransformConfig {
+    /// 不做任何处理
+    Noop,
+    /// 剥离指定VLAN的标签，仅适用于TAP模式
+    VlanStrip {
+        vlan_id: u16,
+    },
+    /// 将发往`from`的数据包改写为发往`to`
+    Dnat {
+        from: Ipv4Addr,
+        to: Ipv4Addr,
+    },
+    /// 拒绝发往`destination`的数据包，指定`port`时只拒绝发往该TCP/UDP端口的数据包
+    Reject {
+        destination: IpCidr,
+        #[serde(default)]
+        port: Option<u16>,
+        #[serde(default)]
+        code: IcmpUnreachCode,
+    },
+}
+
+impl TransformConfig {
+    /// 根据配置创建变换
+    pub fn build(&self) -> Box<dyn PacketTransform> {
+        match self {
+            TransformConfig::Noop => Box::new(NoopTransform),
+            TransformConfig::VlanStrip { vlan_id } => Box::new(VlanStripTransform(*vlan_id)),
+            TransformConfig::Dnat { from, to } => Box::new(DnatTransform { from: *from, to: *to }),
+            TransformConfig::Reject { destination, port, code } => Box::new(RejectTransform {
+                destination: *destination,
+                port: *port,
+                code: *code,
+            }),
+        }
+    }
+}
+
+/// 空变换
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopTransform;
+
+impl PacketTransform for NoopTransform {
+    fn transform(&self, _packet: &mut Vec<u8>, _direction: Direction) -> Result<(), TransformError> {
+        Ok(())
+    }
+}
+
+/// VLAN标签剥离
+///
+/// 发出时去掉指定VLAN的802.1Q标签，收到时为未打标签的帧重新加上该标签。
+#[derive(Debug, Clone, Copy)]
+pub struct VlanStripTransform(pub u16);
+
+impl PacketTransform for VlanStripTransform {
+    fn transform(&self, packet: &mut Vec<u8>, direction: Direction) -> Result<(), TransformError> {
+        if packet.len() < ETHERTYPE_OFFSET + 2 {
+            return Err(TransformError::Malformed("frame shorter than Ethernet header"));
+        }
+        
+        let ethertype = read_u16(packet, ETHERTYPE_OFFSET);
+        match direction {
+            Direction::Outbound => {
+                if ethertype != ETHERTYPE_VLAN {
+                    return Ok(());
+                }
+                if packet.len() < ETHERTYPE_OFFSET + VLAN_TAG_LEN + 2 {
+                    return Err(TransformError::Malformed("truncated VLAN tag"));
+                }
+                
+                let vlan_id = read_u16(packet, ETHERTYPE_OFFSET + 2) & 0x0FFF;
+                if vlan_id == self.0 {
+                    packet.drain(ETHERTYPE_OFFSET..ETHERTYPE_OFFSET + VLAN_TAG_LEN);
+                }
+            }
+            Direction::Inbound => {
+                if ethertype == ETHERTYPE_VLAN {
+                    return Ok(());
+                }
+                
+                let mut tag = [0u8; VLAN_TAG_LEN];
+                tag[..2].copy_from_slice(&ETHERTYPE_VLAN.to_be_bytes());
+                tag[2..].copy_from_slice(&(self.0 & 0x0FFF).to_be_bytes());
+                packet.splice(ETHERTYPE_OFFSET..ETHERTYPE_OFFSET, tag);
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+/// 目的地址转换
+///
+/// 发出时将目的地址`from`改写为`to`，收到时将源地址`to`改回`from`，
+/// 同时更新IPv4头部以及TCP/UDP的校验和。TUN和TAP模式均适用。
+#[derive(Debug, Clone, Copy)]
+pub struct DnatTransform {
+    pub from: Ipv4Addr,
+    pub to: Ipv4Addr,
+}
+
+impl PacketTransform for DnatTransform {
+    fn transform(&self, packet: &mut Vec<u8>, direction: Direction) -> Result<(), TransformError> {
+        let offset = match locate_ipv4(packet) {
+            Some(offset) => offset,
+            None => return Ok(()),
+        };
+        
+        // 发出时改写目的地址，收到时改写源地址
+        let (field, old, new) = match direction {
+            Direction::Outbound => (offset + 16, self.from, self.to),
+            Direction::Inbound => (offset + 12, self.to, self.from),
+        };
+        
+        if packet[field..field + 4] != old.octets() {
+            return Ok(());
+        }
+        
+        let header_len = ((packet[offset] & 0x0F) as usize) * 4;
+        if header_len < 20 || packet.len() < offset + header_len {
+            return Err(TransformError::Malformed("invalid IPv4 header length"));
+        }
+        
+        packet[field..field + 4].copy_from_slice(&new.octets());
+        adjust_checksum(packet, offset + 10, old, new);
+        
+        // 只有首个分片携带传输层头部
+        let fragment_offset = read_u16(packet, offset + 6) & 0x1FFF;
+        if fragment_offset == 0 {
+            let l4 = offset + header_len;
+            match packet[offset + 9] {
+                // TCP
+                6 if packet.len() >= l4 + 18 => adjust_checksum(packet, l4 + 16, old, new),
+                // UDP，校验和为0表示未启用
+                17 if packet.len() >= l4 + 8 && read_u16(packet, l4 + 6) != 0 => {
+                    adjust_checksum(packet, l4 + 6, old, new)
+                }
+                _ => {}
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+/// 拒绝规则
+///
+/// 两个方向上发往`destination`的数据包都被拒绝，由虚拟设备向源地址回复ICMP目标不可达，
+/// 应用无需等待超时。
+#[derive(Debug, Clone, Copy)]
+pub struct RejectTransform {
+    pub destination: IpCidr,
+    pub port: Option<u16>,
+    pub code: IcmpUnreachCode,
+}
+
+impl PacketTransform for RejectTransform {
+    fn transform(&self, packet: &mut Vec<u8>, _direction: Direction) -> Result<(), TransformError> {
+        let offset = match locate_ipv4(packet) {
+            Some(offset) => offset,
+            None => return Ok(()),
+        };
+        
+        let destination = Ipv4Addr::new(packet[offset + 16], packet[offset + 17], packet[offset + 18], packet[offset + 19]);
+        if !self.destination.contains(destination) {
+            return Ok(());
+        }
+        
+        if let Some(port) = self.port {
+            // 非首个分片不含端口，直接放行，首个分片被拒绝后无法重组
+            let header_len = ((packet[offset] & 0x0F) as usize) * 4;
+            let l4 = offset + header_len;
+            let matches = matches!(packet[offset + 9], 6 | 17)
+                && read_u16(packet, offset + 6) & 0x1FFF == 0
+                && packet.len() >= l4 + 4
+                && read_u16(packet, l4 + 2) == port;
+            if !matches {
+                return Ok(());
+            }
+        }
+        
+        Err(TransformError::Rejected(self.code))
+    }
+}
+
+/// 查找数据包中IPv4头部的偏移
+///
+/// TUN模式下数据包本身就是IP数据包；TAP模式下跳过以太网帧头。
+pub(crate) fn locate_ipv4(packet: &[u8]) -> Option<usize> {
+    if packet.len() >= 20
+        && packet[0] >> 4 == 4
+        && read_u16(packet, 2) as usize == packet.len()
+    {
+        return Some(0);
+    }
+    
+    let offset = ETHERTYPE_OFFSET + 2;
+    if packet.len() >= offset + 20
+        && read_u16(packet, ETHERTYPE_OFFSET) == ETHERTYPE_IPV4
+        && packet[offset] >> 4 == 4
+    {
+        return Some(offset);
+    }
+    
+    None
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+/// 地址改变后增量更新校验和（RFC 1624）
+fn adjust_checksum(packet: &mut [u8], offset: usize, old: Ipv4Addr, new: Ipv4Addr) {
+    let mut sum = !read_u16(packet, offset) as u32;
+    for chunk in old.octets().chunks(2) {
+        sum += !u16::from_be_bytes([chunk[0], chunk[1]]) as u32;
+    }
+    for chunk in new.octets().chunks(2) {
+        sum += u16::from_be_bytes([chunk[0], chunk[1]]) as u32;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    
+    packet[offset..offset + 2].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+}
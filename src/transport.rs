@@ -0,0 +1,193 @@
+/*!
+数据报传输模块
+
+网络管理器通过传输工厂绑定收发节点间数据包的套接字，包括：
+- 非阻塞数据报套接字接口
+- 使用系统UDP套接字的默认传输
+- 启用`simulation`特性时可以换成进程内的模拟网络
+*/
+
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
+
+/// 非阻塞数据报套接字
+pub trait DatagramSocket: Send + Sync {
+    /// 发送数据报
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize>;
+    
+    /// 以指定的TOS字节（IPv6为流量类别）发送数据报，只影响本次发送
+    ///
+    /// 不支持逐个数据报设置TOS的平台和模拟套接字忽略`tos`。
+    fn send_to_with_tos(&self, buf: &[u8], addr: SocketAddr, tos: u8) -> io::Result<usize> {
+        let _ = tos;
+        self.send_to(buf, addr)
+    }
+    
+    /// 接收一个数据报，没有待接收的数据报时立即返回`WouldBlock`
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
+    
+    /// 实际绑定的本地地址
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+    
+    /// 底层的系统UDP套接字，用于调整缓冲区大小、DSCP标记等选项，模拟套接字返回`None`
+    fn as_udp_socket(&self) -> Option<&UdpSocket> {
+        None
+    }
+}
+
+impl DatagramSocket for UdpSocket {
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        UdpSocket::send_to(self, buf, addr)
+    }
+    
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn send_to_with_tos(&self, buf: &[u8], addr: SocketAddr, tos: u8) -> io::Result<usize> {
+        send_with_tos_cmsg(self, buf, addr, tos)
+    }
+    
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        UdpSocket::recv_from(self, buf)
+    }
+    
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        UdpSocket::local_addr(self)
+    }
+    
+    fn as_udp_socket(&self) -> Option<&UdpSocket> {
+        Some(self)
+    }
+}
+
+impl<T: DatagramSocket + ?Sized> DatagramSocket for Arc<T> {
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        (**self).send_to(buf, addr)
+    }
+    
+    fn send_to_with_tos(&self, buf: &[u8], addr: SocketAddr, tos: u8) -> io::Result<usize> {
+        (**self).send_to_with_tos(buf, addr, tos)
+    }
+    
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        (**self).recv_from(buf)
+    }
+    
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        (**self).local_addr()
+    }
+    
+    fn as_udp_socket(&self) -> Option<&UdpSocket> {
+        (**self).as_udp_socket()
+    }
+}
+
+/// 控制消息缓冲区，按`cmsghdr`的要求对齐
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[repr(C, align(8))]
+struct ControlBuffer([u8; 64]);
+
+/// 用`sendmsg`发送数据报，TOS经`IP_TOS`/`IPV6_TCLASS`控制消息携带
+///
+/// 与`setsockopt`不同，控制消息只作用于本次发送，并发发送不同优先级的数据包时不会相互覆盖。
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn send_with_tos_cmsg(socket: &UdpSocket, buf: &[u8], addr: SocketAddr, tos: u8) -> io::Result<usize> {
+    use socket2::{MsgHdr, SockAddr, SockRef};
+    use std::mem::size_of;
+    
+    let (level, kind) = match addr {
+        SocketAddr::V4(_) => (libc::IPPROTO_IP, libc::IP_TOS),
+        SocketAddr::V6(_) => (libc::IPPROTO_IPV6, libc::IPV6_TCLASS),
+    };
+    
+    let mut control = ControlBuffer([0; 64]);
+    // SAFETY: 缓冲区按cmsghdr对齐且足够容纳一条携带int的控制消息，CMSG_FIRSTHDR不会返回空指针
+    let control_len = unsafe {
+        let space = libc::CMSG_SPACE(size_of::<libc::c_int>() as u32) as usize;
+        let mut msg: libc::msghdr = std::mem::zeroed();
+        msg.msg_control = control.0.as_mut_ptr().cast();
+        msg.msg_controllen = space as _;
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = level;
+        (*cmsg).cmsg_type = kind;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<libc::c_int>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<libc::c_int>(), libc::c_int::from(tos));
+        space
+    };
+    
+    let addr = SockAddr::from(addr);
+    let bufs = [io::IoSlice::new(buf)];
+    let msg = MsgHdr::new()
+        .with_addr(&addr)
+        .with_buffers(&bufs)
+        .with_control(&control.0[..control_len]);
+    SockRef::from(socket).sendmsg(&msg, 0)
+}
+
+/// 传输工厂，网络管理器在每个监听地址上通过它绑定一个套接字
+pub trait TransportFactory: Send + Sync {
+    /// 在`addr`上绑定一个非阻塞数据报套接字，端口为0时自动分配
+    fn bind(&self, addr: SocketAddr) -> io::Result<Arc<dyn DatagramSocket>>;
+}
+
+/// 使用系统UDP套接字的传输
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UdpTransport;
+
+impl TransportFactory for UdpTransport {
+    fn bind(&self, addr: SocketAddr) -> io::Result<Arc<dyn DatagramSocket>> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Arc::new(socket))
+    }
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod tests {
+    use super::*;
+    use std::os::fd::AsRawFd;
+    
+    /// 接收一个数据报及其IP头中的TOS字节，接收端需要启用`IP_RECVTOS`
+    fn recv_with_tos(socket: &UdpSocket) -> (Vec<u8>, Option<u8>) {
+        let mut buf = [0u8; 64];
+        let mut control = ControlBuffer([0; 64]);
+        let mut iov = libc::iovec { iov_base: buf.as_mut_ptr().cast(), iov_len: buf.len() };
+        // SAFETY: msghdr指向的缓冲区在调用期间有效，控制消息由内核按格式写入
+        unsafe {
+            let mut msg: libc::msghdr = std::mem::zeroed();
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.0.as_mut_ptr().cast();
+            msg.msg_controllen = control.0.len() as _;
+            let len = libc::recvmsg(socket.as_raw_fd(), &mut msg, 0);
+            assert!(len >= 0, "recvmsg failed: {}", io::Error::last_os_error());
+            
+            let mut tos = None;
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::IPPROTO_IP && (*cmsg).cmsg_type == libc::IP_TOS {
+                    tos = Some(*libc::CMSG_DATA(cmsg));
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+            (buf[..len as usize].to_vec(), tos)
+        }
+    }
+    
+    #[test]
+    fn tos_applies_only_to_marked_datagram() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+        socket2::SockRef::from(&receiver).set_recv_tos(true).unwrap();
+        let target = receiver.local_addr().unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        
+        // DSCP EF（0x2E）位于TOS字节的高6位
+        assert_eq!(sender.send_to_with_tos(b"ef", target, 0x2E << 2).unwrap(), 2);
+        assert_eq!(recv_with_tos(&receiver), (b"ef".to_vec(), Some(0xB8)));
+        
+        // 套接字本身的IP_TOS不变，之后的普通发送不带标记
+        assert_eq!(socket2::SockRef::from(&sender).tos().unwrap(), 0);
+        DatagramSocket::send_to(&sender, b"cs0", target).unwrap();
+        assert_eq!(recv_with_tos(&receiver), (b"cs0".to_vec(), Some(0)));
+    }
+}
@@ -0,0 +1,735 @@
+/*!
+VPNet工具模块
+
+各模块共用的辅助函数，包括：
+- 当前Unix时间戳
+- 纳秒精度的令牌桶
+- UDP套接字缓冲区调优
+- TCP连接的`TCP_NODELAY`、缓冲区和保活参数
+- 数据包大小直方图和流式分位数估计
+*/
+
+use std::io;
+use std::sync::PoisonError;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::Poll;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// 当前Unix时间戳（秒）
+pub fn current_unix_timestamp() -> u64 {
+    unix_time().as_secs()
+}
+
+/// 当前Unix时间戳（毫秒）
+pub fn current_unix_timestamp_millis() -> u64 {
+    unix_time().as_millis() as u64
+}
+
+/// 系统时钟早于Unix纪元时返回0
+#[cfg(not(test))]
+fn unix_time() -> Duration {
+    system_unix_time()
+}
+
+/// 测试中设置了模拟时钟时返回模拟时间，否则返回系统时间
+#[cfg(test)]
+fn unix_time() -> Duration {
+    mock_clock::now().unwrap_or_else(system_unix_time)
+}
+
+fn system_unix_time() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// 测试用的可控时钟
+///
+/// 只影响当前线程，`#[tokio::test]`默认的单线程运行时中派生的任务也能看到。
+/// 超时、过期等基于Unix时间戳的逻辑可以直接拨动时钟测试，无需真正等待。
+#[cfg(test)]
+pub(crate) mod mock_clock {
+    use std::cell::Cell;
+    use std::time::Duration;
+    
+    thread_local! {
+        static NOW: Cell<Option<Duration>> = const { Cell::new(None) };
+    }
+    
+    /// 当前的模拟时间，未设置时为`None`
+    pub fn now() -> Option<Duration> {
+        NOW.with(Cell::get)
+    }
+    
+    /// 将时钟固定在Unix时间`secs`秒
+    pub fn set(secs: u64) {
+        NOW.with(|now| now.set(Some(Duration::from_secs(secs))));
+    }
+    
+    /// 时钟前进`secs`秒，未设置时从系统时间开始
+    pub fn advance(secs: u64) {
+        let now = now().unwrap_or_else(super::system_unix_time);
+        NOW.with(|cell| cell.set(Some(now + Duration::from_secs(secs))));
+    }
+    
+    /// 恢复使用系统时间
+    pub fn reset() {
+        NOW.with(|now| now.set(None));
+    }
+}
+
+/// 令牌桶，每个令牌代表一个字节
+///
+/// 按纳秒计算补充的令牌，只推进已经换成令牌的时间，不足一个令牌的时间留到下一次补充，
+/// 因此高频调用时既不会多发也不会丢失令牌。
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate_bytes_per_sec: u64,
+    burst_bytes: u64,
+    last_refill: Instant,
+    tokens: u64,
+    /// 超过容量的请求预支的令牌数，补充的令牌先用于偿还
+    debt: u64,
+    /// 最近一次`consume`未满足时还差的令牌数
+    deficit: u64,
+}
+
+impl TokenBucket {
+    /// 创建令牌桶，初始时令牌是满的
+    pub fn new(rate_bytes_per_sec: u64, burst_bytes: u64) -> Self {
+        Self {
+            rate_bytes_per_sec,
+            burst_bytes,
+            last_refill: Instant::now(),
+            tokens: burst_bytes,
+            debt: 0,
+            deficit: 0,
+        }
+    }
+    
+    /// 修改补充速率和容量，已有的令牌按原速率补充后保留，超过新容量的部分丢弃
+    pub fn set_rate(&mut self, rate_bytes_per_sec: u64, burst_bytes: u64) {
+        self.refill(Instant::now());
+        self.rate_bytes_per_sec = rate_bytes_per_sec;
+        self.burst_bytes = burst_bytes;
+        self.tokens = self.tokens.min(burst_bytes);
+        self.deficit = 0;
+    }
+    
+    /// 每秒补充的令牌数
+    pub fn rate(&self) -> u64 {
+        self.rate_bytes_per_sec
+    }
+    
+    /// 令牌桶容量
+    pub fn burst(&self) -> u64 {
+        self.burst_bytes
+    }
+    
+    /// 当前可用的令牌数
+    pub fn available(&mut self) -> u64 {
+        self.refill(Instant::now());
+        self.tokens
+    }
+    
+    /// 消耗`bytes`个令牌，令牌不足时不消耗并返回`Pending`
+    ///
+    /// 超过容量的请求在令牌桶满时放行，超出的部分记为欠额，还清之前不再放行其他请求，
+    /// 因此长期的平均速率不会超过设定值。
+    pub fn consume(&mut self, bytes: u64) -> Poll<()> {
+        self.refill(Instant::now());
+        let needed = bytes.min(self.burst_bytes);
+        if self.debt == 0 && self.tokens >= needed {
+            self.debt = bytes - needed;
+            self.tokens -= needed;
+            self.deficit = 0;
+            Poll::Ready(())
+        } else {
+            self.deficit = self.debt + needed - self.tokens;
+            Poll::Pending
+        }
+    }
+    
+    /// 上一次`consume`返回`Pending`后，令牌足够的最早时刻
+    ///
+    /// 令牌已经足够时返回上一次补充的时刻，补充速率为0时返回`None`。
+    pub fn next_available(&self) -> Option<Instant> {
+        if self.deficit == 0 {
+            return Some(self.last_refill);
+        }
+        if self.rate_bytes_per_sec == 0 {
+            return None;
+        }
+        
+        // 向上取整，保证到达该时刻时补充的令牌不少于缺少的数量
+        let rate = self.rate_bytes_per_sec as u128;
+        let nanos = (self.deficit as u128 * NANOS_PER_SEC).div_ceil(rate);
+        Some(self.last_refill + Duration::from_nanos(nanos.min(u64::MAX as u128) as u64))
+    }
+    
+    /// 按经过的时间补充令牌，先偿还欠额，最多补满容量
+    fn refill(&mut self, now: Instant) {
+        if (self.tokens >= self.burst_bytes && self.debt == 0) || self.rate_bytes_per_sec == 0 {
+            self.last_refill = now;
+            return;
+        }
+        
+        let rate = self.rate_bytes_per_sec as u128;
+        let elapsed = now.saturating_duration_since(self.last_refill).as_nanos();
+        let earned = elapsed * rate / NANOS_PER_SEC;
+        if earned == 0 {
+            return;
+        }
+        
+        let room = self.debt as u128 + (self.burst_bytes - self.tokens) as u128;
+        if earned >= room {
+            self.debt = 0;
+            self.tokens = self.burst_bytes;
+            self.last_refill = now;
+        } else {
+            let repaid = (earned as u64).min(self.debt);
+            self.debt -= repaid;
+            self.tokens += earned as u64 - repaid;
+            // 向上取整，宁可少算不足1纳秒的时间也不多发令牌
+            let used = (earned * NANOS_PER_SEC).div_ceil(rate);
+            self.last_refill += Duration::from_nanos(used as u64);
+        }
+    }
+}
+
+/// UDP套接字参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketConfig {
+    /// 发送缓冲区大小（字节，`SO_SNDBUF`），`None`表示使用系统默认值
+    pub send_buffer_size: Option<usize>,
+    /// 接收缓冲区大小（字节，`SO_RCVBUF`），`None`表示使用系统默认值
+    pub receive_buffer_size: Option<usize>,
+}
+
+impl SocketConfig {
+    /// 高吞吐环境下默认的缓冲区大小（4MB）
+    pub const DEFAULT_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+}
+
+impl Default for SocketConfig {
+    fn default() -> Self {
+        Self {
+            send_buffer_size: Some(Self::DEFAULT_BUFFER_SIZE),
+            receive_buffer_size: Some(Self::DEFAULT_BUFFER_SIZE),
+        }
+    }
+}
+
+/// 套接字实际生效的缓冲区大小
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketBuffers {
+    pub send_buffer_size: usize,
+    pub receive_buffer_size: usize,
+}
+
+/// UDP套接字调优
+///
+/// Linux默认的发送缓冲区只有约208KB，突发发送时容易返回`EAGAIN`。
+/// 内核会把请求的大小限制在`/proc/sys/net/core/{w,r}mem_max`以内（Linux上读回的值是设置值的两倍），
+/// 因此设置后读回实际值记录日志。Linux上同时开启`IP_RECVTOS`，接收时可以取得数据包的TOS字节。
+pub struct UdpSocketTuner;
+
+impl UdpSocketTuner {
+    /// 按配置设置套接字缓冲区并开启`IP_RECVTOS`，返回实际生效的缓冲区大小
+    pub fn configure(socket: &socket2::Socket, config: &SocketConfig) -> io::Result<SocketBuffers> {
+        if let Some(size) = config.send_buffer_size {
+            warn_above_system_max("wmem_max", size);
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = config.receive_buffer_size {
+            warn_above_system_max("rmem_max", size);
+            socket.set_recv_buffer_size(size)?;
+        }
+        // 只监听IPv6的套接字不支持IPv4层的选项，不影响收发
+        if let Err(e) = set_recv_tos(socket) {
+            log::debug!("Failed to enable IP_RECVTOS: {}", e);
+        }
+        let buffers = SocketBuffers {
+            send_buffer_size: socket.send_buffer_size()?,
+            receive_buffer_size: socket.recv_buffer_size()?,
+        };
+        log::info!(
+            "UDP socket buffers: send {} bytes (requested {:?}), receive {} bytes (requested {:?})",
+            buffers.send_buffer_size,
+            config.send_buffer_size,
+            buffers.receive_buffer_size,
+            config.receive_buffer_size
+        );
+        Ok(buffers)
+    }
+}
+
+/// 开启`IP_RECVTOS`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_recv_tos(socket: &socket2::Socket) -> io::Result<()> {
+    socket.set_recv_tos(true)
+}
+
+/// 其他平台不支持`IP_RECVTOS`
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn set_recv_tos(_socket: &socket2::Socket) -> io::Result<()> {
+    Ok(())
+}
+
+/// TCP保活参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpKeepaliveConfig {
+    /// 连接空闲多久后开始发送保活探测（`TCP_KEEPIDLE`）
+    pub time: Duration,
+    /// 保活探测的间隔（`TCP_KEEPINTVL`）
+    pub interval: Duration,
+    /// 连续多少次探测无响应后断开连接（`TCP_KEEPCNT`）
+    pub retries: u32,
+}
+
+impl Default for TcpKeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            time: Duration::from_secs(60),
+            interval: Duration::from_secs(10),
+            retries: 6,
+        }
+    }
+}
+
+/// TCP连接参数
+///
+/// 隧道中的心跳、握手等控制消息很小，不关闭Nagle算法时可能被延迟最多200毫秒，因此默认设置`TCP_NODELAY`。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpStreamConfig {
+    /// 关闭Nagle算法（`TCP_NODELAY`）
+    pub nodelay: bool,
+    /// 发送缓冲区大小（字节，`SO_SNDBUF`），`None`表示使用系统默认值
+    pub send_buffer_size: Option<usize>,
+    /// 接收缓冲区大小（字节，`SO_RCVBUF`），`None`表示使用系统默认值
+    pub receive_buffer_size: Option<usize>,
+    /// 保活参数，`None`表示不启用`SO_KEEPALIVE`
+    pub keepalive: Option<TcpKeepaliveConfig>,
+}
+
+impl Default for TcpStreamConfig {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            send_buffer_size: None,
+            receive_buffer_size: None,
+            keepalive: Some(TcpKeepaliveConfig::default()),
+        }
+    }
+}
+
+impl TcpStreamConfig {
+    /// 对接受或建立的连接设置参数
+    pub fn apply(&self, stream: &tokio::net::TcpStream) -> io::Result<()> {
+        let socket = socket2::SockRef::from(stream);
+        socket.set_nodelay(self.nodelay)?;
+        if let Some(size) = self.send_buffer_size {
+            warn_above_system_max("wmem_max", size);
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.receive_buffer_size {
+            warn_above_system_max("rmem_max", size);
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(keepalive) = &self.keepalive {
+            let params = socket2::TcpKeepalive::new().with_time(keepalive.time);
+            #[cfg(not(any(target_os = "openbsd", target_os = "haiku", target_os = "solaris", target_os = "redox")))]
+            let params = params.with_interval(keepalive.interval);
+            #[cfg(not(any(windows, target_os = "openbsd", target_os = "haiku", target_os = "solaris", target_os = "redox")))]
+            let params = params.with_retries(keepalive.retries);
+            socket.set_tcp_keepalive(&params)?;
+        }
+        Ok(())
+    }
+}
+
+/// 请求的缓冲区大小超过系统上限时记录警告，只在Linux上检查
+fn warn_above_system_max(sysctl: &str, size: usize) {
+    #[cfg(target_os = "linux")]
+    {
+        let path = format!("/proc/sys/net/core/{}", sysctl);
+        let max = std::fs::read_to_string(&path).ok()
+            .and_then(|value| value.trim().parse::<usize>().ok());
+        if let Some(max) = max.filter(|max| size > *max) {
+            log::warn!(
+                "Requested socket buffer size {} exceeds net.core.{} ({}), the kernel will cap it; raise it with sysctl -w net.core.{}={}",
+                size, sysctl, max, sysctl, size
+            );
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (sysctl, size);
+}
+
+/// 数据包大小分桶的上限（字节，含），最后一个桶收集超过1420字节的数据包
+const PACKET_SIZE_BUCKET_BOUNDS: [usize; 6] = [64, 256, 576, 1024, 1280, 1420];
+
+/// 分位数估计的误差，估计值的排名与真实排名相差不超过`ε·n`
+const QUANTILE_EPSILON: f64 = 0.005;
+
+/// 每隔多少个数据包取一个样本计入分位数摘要
+const QUANTILE_SAMPLE_INTERVAL: u64 = 16;
+
+/// Greenwald-Khanna摘要中的一个元组
+#[derive(Debug, Clone, Copy)]
+struct QuantileTuple {
+    value: usize,
+    /// 与前一个元组的最小排名之差
+    g: u64,
+    /// 最大排名与最小排名之差
+    delta: u64,
+}
+
+/// Greenwald-Khanna流式分位数摘要
+///
+/// 只保存`O(1/ε·log(εn))`个元组，任意分位数的排名误差不超过`ε·n`。
+#[derive(Debug, Clone, Default)]
+struct QuantileSummary {
+    tuples: Vec<QuantileTuple>,
+    count: u64,
+}
+
+impl QuantileSummary {
+    fn insert(&mut self, value: usize) {
+        let index = self.tuples.partition_point(|tuple| tuple.value <= value);
+        let delta = if index == 0 || index == self.tuples.len() {
+            0
+        } else {
+            (2.0 * QUANTILE_EPSILON * self.count as f64).floor() as u64
+        };
+        self.tuples.insert(index, QuantileTuple { value, g: 1, delta });
+        self.count += 1;
+        
+        if self.count.is_multiple_of((1.0 / (2.0 * QUANTILE_EPSILON)) as u64) {
+            self.compress();
+        }
+    }
+    
+    /// 合并相邻的元组，保留首尾元组以保证最小值和最大值准确
+    fn compress(&mut self) {
+        let threshold = (2.0 * QUANTILE_EPSILON * self.count as f64).floor() as u64;
+        let mut i = self.tuples.len().saturating_sub(2);
+        while i >= 1 {
+            let next = self.tuples[i + 1];
+            if self.tuples[i].g + next.g + next.delta <= threshold {
+                self.tuples[i + 1].g += self.tuples[i].g;
+                self.tuples.remove(i);
+            }
+            i -= 1;
+        }
+    }
+    
+    /// `q`分位数的估计值，没有数据时返回`None`
+    fn query(&self, q: f64) -> Option<usize> {
+        let last = self.tuples.last()?;
+        let rank = (q.clamp(0.0, 1.0) * self.count as f64).ceil();
+        let margin = QUANTILE_EPSILON * self.count as f64;
+        let mut min_rank = 0;
+        for (i, tuple) in self.tuples.iter().enumerate() {
+            min_rank += tuple.g;
+            if (min_rank + tuple.delta) as f64 > rank + margin {
+                return Some(self.tuples[i.saturating_sub(1)].value);
+            }
+        }
+        Some(last.value)
+    }
+}
+
+/// 一个数据包大小分桶的计数
+#[derive(Debug, Clone, Serialize)]
+pub struct PacketSizeBucket {
+    /// 大小范围，例如`"65-256"`或`">1420"`
+    pub range: String,
+    pub count: u64,
+}
+
+/// 数据包大小分布的快照
+#[derive(Debug, Clone, Serialize)]
+pub struct PacketSizeStats {
+    pub buckets: Vec<PacketSizeBucket>,
+    /// 数据包总数
+    pub count: u64,
+    /// 平均大小（字节），没有数据包时为`None`
+    pub mean: Option<f64>,
+    /// 分位数由抽样的数据包估计
+    pub p50: Option<usize>,
+    pub p90: Option<usize>,
+    pub p99: Option<usize>,
+}
+
+/// 数据包大小直方图
+///
+/// 按固定分桶计数，同时用流式摘要估计分位数，便于判断MTU设置是否合适。
+/// 分桶计数使用原子变量，每`QUANTILE_SAMPLE_INTERVAL`个数据包才取一个样本更新摘要，
+/// 摘要正被更新时跳过该样本，记录数据包不会阻塞。
+#[derive(Debug, Default)]
+pub struct PacketSizeHistogram {
+    counts: [AtomicU64; PACKET_SIZE_BUCKET_BOUNDS.len() + 1],
+    count: AtomicU64,
+    total_bytes: AtomicU64,
+    quantiles: std::sync::Mutex<QuantileSummary>,
+}
+
+impl PacketSizeHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// 记录一个数据包的大小（字节）
+    pub fn record(&self, size: usize) {
+        let bucket = PACKET_SIZE_BUCKET_BOUNDS.partition_point(|bound| *bound < size);
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.total_bytes.fetch_add(size as u64, Ordering::Relaxed);
+        if self.count.fetch_add(1, Ordering::Relaxed).is_multiple_of(QUANTILE_SAMPLE_INTERVAL) {
+            if let Ok(mut quantiles) = self.quantiles.try_lock() {
+                quantiles.insert(size);
+            }
+        }
+    }
+    
+    /// 清空所有计数
+    pub fn reset(&self) {
+        for count in &self.counts {
+            count.store(0, Ordering::Relaxed);
+        }
+        self.count.store(0, Ordering::Relaxed);
+        self.total_bytes.store(0, Ordering::Relaxed);
+        *self.quantiles.lock().unwrap_or_else(PoisonError::into_inner) = QuantileSummary::default();
+    }
+    
+    /// 当前的分桶计数、平均值和分位数
+    pub fn stats(&self) -> PacketSizeStats {
+        let mut lower = 0;
+        let mut buckets = Vec::with_capacity(self.counts.len());
+        for (i, count) in self.counts.iter().enumerate() {
+            let range = match PACKET_SIZE_BUCKET_BOUNDS.get(i) {
+                Some(upper) => format!("{}-{}", lower, upper),
+                None => format!(">{}", lower - 1),
+            };
+            lower = PACKET_SIZE_BUCKET_BOUNDS.get(i).map_or(lower, |upper| upper + 1);
+            buckets.push(PacketSizeBucket { range, count: count.load(Ordering::Relaxed) });
+        }
+        
+        let count = self.count.load(Ordering::Relaxed);
+        let quantiles = self.quantiles.lock().unwrap_or_else(PoisonError::into_inner);
+        PacketSizeStats {
+            buckets,
+            count,
+            mean: (count > 0).then(|| self.total_bytes.load(Ordering::Relaxed) as f64 / count as f64),
+            p50: quantiles.query(0.5),
+            p90: quantiles.query(0.9),
+            p99: quantiles.query(0.99),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn mock_clock_controls_unix_timestamp() {
+        mock_clock::set(1_000);
+        assert_eq!(current_unix_timestamp(), 1_000);
+        assert_eq!(current_unix_timestamp_millis(), 1_000_000);
+        
+        mock_clock::advance(30);
+        assert_eq!(current_unix_timestamp(), 1_030);
+        
+        mock_clock::reset();
+        assert!(current_unix_timestamp() > 1_030);
+    }
+    
+    #[test]
+    fn token_bucket_never_over_grants_at_high_call_rates() {
+        let rate = 1_000_000;
+        let burst = 1_000;
+        let mut bucket = TokenBucket::new(rate, burst);
+        let started = Instant::now();
+        let mut granted = 0u64;
+        let mut calls = 0u64;
+        while started.elapsed() < Duration::from_millis(50) {
+            calls += 1;
+            if bucket.consume(100).is_ready() {
+                granted += 100;
+            }
+        }
+        let elapsed = started.elapsed();
+        
+        let allowed = burst + (elapsed.as_nanos() * rate as u128 / NANOS_PER_SEC) as u64;
+        assert!(calls > 10_000, "only {} calls", calls);
+        assert!(granted <= allowed, "granted {} of {}", granted, allowed);
+        // 不足一个令牌的时间不会丢失，除去最后一个请求以内的误差应全部发出
+        assert!(granted + 100 >= allowed - burst, "granted {} of {}", granted, allowed);
+    }
+    
+    #[test]
+    fn oversized_requests_are_paid_back_before_the_next_grant() {
+        let mut bucket = TokenBucket::new(1_000, 100);
+        assert!(bucket.consume(1_000).is_ready());
+        assert!(bucket.consume(1).is_pending());
+        assert_eq!(bucket.available(), 0);
+        
+        // 超出容量的900个令牌还清之后才能再发送
+        let wait = bucket.next_available().unwrap().saturating_duration_since(Instant::now());
+        assert!(wait > Duration::from_millis(850), "{:?}", wait);
+        assert!(wait <= Duration::from_millis(901), "{:?}", wait);
+        
+        // 令牌桶不满时超过容量的请求不放行
+        let mut bucket = TokenBucket::new(1_000, 100);
+        assert!(bucket.consume(50).is_ready());
+        assert!(bucket.consume(1_000).is_pending());
+    }
+    
+    #[test]
+    fn next_available_is_when_enough_tokens_have_accrued() {
+        let mut bucket = TokenBucket::new(1_000_000, 1_000);
+        assert!(bucket.consume(1_000).is_ready());
+        assert!(bucket.consume(500).is_pending());
+        
+        let at = bucket.next_available().unwrap();
+        let wait = at.saturating_duration_since(Instant::now());
+        assert!(wait <= Duration::from_micros(500), "{:?}", wait);
+        
+        std::thread::sleep(wait);
+        assert!(bucket.consume(500).is_ready());
+        assert!(bucket.consume(1_000).is_pending());
+    }
+    
+    #[test]
+    fn token_bucket_rate_changes_keep_tokens_within_the_new_burst() {
+        let mut bucket = TokenBucket::new(1_000, 1_000);
+        bucket.set_rate(10, 100);
+        assert_eq!((bucket.rate(), bucket.burst()), (10, 100));
+        assert_eq!(bucket.available(), 100);
+        
+        assert!(bucket.consume(100).is_ready());
+        bucket.set_rate(0, 100);
+        assert!(bucket.consume(1).is_pending());
+        assert!(bucket.next_available().is_none());
+    }
+    
+    fn udp_socket() -> socket2::Socket {
+        let socket = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::DGRAM, None).unwrap();
+        socket.bind(&"127.0.0.1:0".parse::<std::net::SocketAddr>().unwrap().into()).unwrap();
+        socket
+    }
+    
+    #[test]
+    fn configured_buffer_sizes_are_read_back() {
+        let socket = udp_socket();
+        let config = SocketConfig { send_buffer_size: Some(64 * 1024), receive_buffer_size: Some(96 * 1024) };
+        let buffers = UdpSocketTuner::configure(&socket, &config).unwrap();
+        
+        assert_eq!(buffers.send_buffer_size, socket.send_buffer_size().unwrap());
+        assert_eq!(buffers.receive_buffer_size, socket.recv_buffer_size().unwrap());
+        // Linux读回的值是设置值的两倍，其他平台与设置值相同
+        assert!(buffers.send_buffer_size >= 64 * 1024, "{:?}", buffers);
+        assert!(buffers.receive_buffer_size >= 96 * 1024, "{:?}", buffers);
+    }
+    
+    #[test]
+    fn unset_buffer_sizes_keep_the_system_default() {
+        let socket = udp_socket();
+        let default_send = socket.send_buffer_size().unwrap();
+        let default_receive = socket.recv_buffer_size().unwrap();
+        
+        let config = SocketConfig { send_buffer_size: None, receive_buffer_size: None };
+        let buffers = UdpSocketTuner::configure(&socket, &config).unwrap();
+        assert_eq!(buffers, SocketBuffers { send_buffer_size: default_send, receive_buffer_size: default_receive });
+    }
+    
+    fn bucket_counts(stats: &PacketSizeStats) -> Vec<(String, u64)> {
+        stats.buckets.iter().map(|bucket| (bucket.range.clone(), bucket.count)).collect()
+    }
+    
+    #[test]
+    fn packet_sizes_land_in_the_inclusive_bucket() {
+        let histogram = PacketSizeHistogram::new();
+        let sizes = [0, 64, 65, 256, 257, 576, 577, 1024, 1025, 1280, 1281, 1420, 1421, 9000];
+        for size in sizes {
+            histogram.record(size);
+        }
+        
+        let stats = histogram.stats();
+        assert_eq!(bucket_counts(&stats), vec![
+            ("0-64".to_string(), 2),
+            ("65-256".to_string(), 2),
+            ("257-576".to_string(), 2),
+            ("577-1024".to_string(), 2),
+            ("1025-1280".to_string(), 2),
+            ("1281-1420".to_string(), 2),
+            (">1420".to_string(), 2),
+        ]);
+        assert_eq!(stats.count, 14);
+        assert_eq!(stats.mean, Some(sizes.iter().sum::<usize>() as f64 / 14.0));
+    }
+    
+    #[test]
+    fn empty_and_reset_histograms_have_no_statistics() {
+        let histogram = PacketSizeHistogram::new();
+        let stats = histogram.stats();
+        assert_eq!((stats.count, stats.mean, stats.p50), (0, None, None));
+        assert!(stats.buckets.iter().all(|bucket| bucket.count == 0));
+        
+        histogram.record(1500);
+        histogram.reset();
+        let stats = histogram.stats();
+        assert_eq!((stats.count, stats.mean, stats.p99), (0, None, None));
+        assert!(stats.buckets.iter().all(|bucket| bucket.count == 0));
+    }
+    
+    #[test]
+    fn sampled_quantiles_track_the_distribution() {
+        let histogram = PacketSizeHistogram::new();
+        // 按固定步长打乱1..=1500，每个大小出现10次
+        for i in 0..15_000usize {
+            histogram.record(i * 7919 % 1500 + 1);
+        }
+        
+        let stats = histogram.stats();
+        assert_eq!(stats.count, 15_000);
+        for (estimate, expected) in [(stats.p50, 750), (stats.p90, 1350), (stats.p99, 1485)] {
+            let estimate = estimate.unwrap();
+            assert!(estimate.abs_diff(expected) <= 60, "{} vs {}", estimate, expected);
+        }
+    }
+    
+    #[test]
+    fn concurrent_records_are_all_counted() {
+        let histogram = std::sync::Arc::new(PacketSizeHistogram::new());
+        let threads: Vec<_> = (0..4).map(|_| {
+            let histogram = histogram.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10_000 {
+                    histogram.record(100);
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        
+        let stats = histogram.stats();
+        assert_eq!(stats.count, 40_000);
+        assert_eq!(stats.buckets[1].count, 40_000);
+        assert_eq!(stats.p50, Some(100));
+    }
+    
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn configure_enables_recv_tos() {
+        let socket = udp_socket();
+        assert!(!socket.recv_tos().unwrap());
+        UdpSocketTuner::configure(&socket, &SocketConfig::default()).unwrap();
+        assert!(socket.recv_tos().unwrap());
+    }
+}
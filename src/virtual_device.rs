@@ -1,334 +1,2009 @@
-/*!
-VPNet虚拟设备模块
-
-管理虚拟网络接口，包括：
-- 虚拟网卡的创建和配置
-- 数据包的发送和接收
-- 设备状态管理
-- 跨平台支持
-*/
-
-use std::sync::Arc;
-use tokio::sync::{Mutex, mpsc};
-use std::net::Ipv4Addr;
-use pnet::datalink::{self, NetworkInterface};
-use pnet::datalink::Channel::Ethernet;
-use pnet::packet::ethernet::{EthernetPacket, MutableEthernetPacket};
-use pnet::packet::ipv4::{Ipv4Packet, MutableIpv4Packet};
-use pnet::packet::tcp::{TcpPacket, MutableTcpPacket};
-use pnet::packet::udp::{UdpPacket, MutableUdpPacket};
-use pnet::packet::{MutablePacket, Packet};
-use std::collections::HashMap;
-use std::time::Duration;
-
-/// 虚拟设备配置
-pub struct VirtualDeviceConfig {
-    pub name: String,
-    pub ip: Ipv4Addr,
-    pub subnet: Ipv4Addr,
-    pub gateway: Ipv4Addr,
-    pub mtu: u32,
-    pub mac: Option<[u8; 6]>,
-}
-
-/// 虚拟设备
-pub struct VirtualDevice {
-    config: VirtualDeviceConfig,
-    interface: Option<NetworkInterface>,
-    send_channel: Option<Arc<Mutex<dyn datalink::DataLinkSender>>>,
-    recv_channel: Option<Arc<Mutex<dyn datalink::DataLinkReceiver>>>,
-    packet_tx: mpsc::Sender<Vec<u8>>,
-    packet_rx: mpsc::Receiver<Vec<u8>>,
-    device_id: String,
-    is_running: bool,
-}
-
-/// 设备状态
-pub enum DeviceStatus {
-    Up,
-    Down,
-    Error(String),
-}
-
-impl VirtualDevice {
-    /// 创建新的虚拟设备
-    pub fn new(config: VirtualDeviceConfig, device_id: String) -> Result<Self, &'static str> {
-        let (packet_tx, packet_rx) = mpsc::channel(1024);
-        
-        Ok(Self {
-            config,
-            interface: None,
-            send_channel: None,
-            recv_channel: None,
-            packet_tx,
-            packet_rx,
-            device_id,
-            is_running: false,
-        })
-    }
-    
-    /// 启动虚拟设备
-    pub async fn start(&mut self) -> Result<(), &'static str> {
-        // 在实际实现中，这里应该创建虚拟网卡
-        // 例如，在Linux上使用tun/tap设备，在Windows上使用Wintun或OpenVPN虚拟网卡
-        
-        // 目前是模拟实现，实际需要根据不同平台调用相应的API
-        self.is_running = true;
-        
-        // 查找或创建虚拟网卡
-        let interfaces = datalink::interfaces();
-        let interface = interfaces.into_iter()
-            .find(|iface| iface.name == self.config.name)
-            .or_else(|| {
-                // 实际实现中，这里应该创建新的虚拟网卡
-                log::warn!("Virtual interface {} not found, creating a new one...", self.config.name);
-                None
-            });
-        
-        if let Some(iface) = interface {
-            self.interface = Some(iface);
-            
-            // 配置虚拟网卡
-            self.configure_interface().await?;
-            
-            // 启动数据传输任务
-            self.start_data_transfer().await;
-        }
-        
-        Ok(())
-    }
-    
-    /// 配置虚拟设备
-    async fn configure_interface(&mut self) -> Result<(), &'static str> {
-        // 实际实现中，这里应该配置虚拟网卡的IP、子网掩码、网关等
-        log::info!("Configuring interface {} with IP: {}/{}", 
-                  self.config.name, self.config.ip, self.config.subnet);
-        
-        Ok(())
-    }
-    
-    /// 启动数据传输任务
-    async fn start_data_transfer(&mut self) {
-        // 启动接收任务
-        let recv_channel = self.recv_channel.clone();
-        let packet_tx = self.packet_tx.clone();
-        
-        tokio::spawn(async move {
-            if let Some(recv) = recv_channel {
-                let mut buf = [0u8; 1500];
-                loop {
-                    match recv.lock().await.next() {
-                        Ok(packet) => {
-                            if let Err(e) = packet_tx.send(packet.to_vec()).await {
-                                log::error!("Failed to send packet: {}", e);
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                            log::error!("Failed to receive packet: {}", e);
-                            break;
-                        }
-                    }
-                }
-            }
-        });
-    }
-    
-    /// 从虚拟设备接收数据包
-    pub async fn recv(&mut self) -> Result<Vec<u8>, &'static str> {
-        self.packet_rx.recv().await
-            .map_err(|_| "Failed to receive packet")
-    }
-    
-    /// 发送数据包到虚拟设备
-    pub async fn send(&mut self, data: &[u8]) -> Result<(), &'static str> {
-        if !self.is_running {
-            return Err("Device is not running");
-        }
-        
-        // 实际实现中，这里应该将数据发送到虚拟网卡
-        log::debug!("Sending packet to virtual device {} ({} bytes)", 
-                  self.config.name, data.len());
-        
-        if let Some(send) = &self.send_channel {
-            send.lock().await.send_to(data, None)
-                .map_err(|_| "Failed to send packet")?;
-        }
-        
-        Ok(())
-    }
-    
-    /// 停止虚拟设备
-    pub async fn stop(&mut self) -> Result<(), &'static str> {
-        self.is_running = false;
-        // 实际实现中，这里应该关闭虚拟网卡
-        log::info!("Stopping virtual device {}", self.config.name);
-        Ok(())
-    }
-    
-    /// 获取设备状态
-    pub async fn get_status(&self) -> DeviceStatus {
-        if self.is_running {
-            DeviceStatus::Up
-        } else {
-            DeviceStatus::Down
-        }
-    }
-    
-    /// 获取设备配置
-    pub async fn get_config(&self) -> &VirtualDeviceConfig {
-        &self.config
-    }
-    
-    /// 获取设备ID
-    pub async fn get_device_id(&self) -> &str {
-        &self.device_id
-    }
-    
-    /// 重置设备
-    pub async fn reset(&mut self) -> Result<(), &'static str> {
-        self.stop().await?;
-        self.start().await
-    }
-    
-    /// 更新设备配置
-    pub async fn update_config(&mut self, new_config: VirtualDeviceConfig) -> Result<(), &'static str> {
-        self.stop().await?;
-        self.config = new_config;
-        self.start().await
-    }
-}
-
-/// 设备管理器
-pub struct DeviceManager {
-    devices: Arc<Mutex<HashMap<String, Arc<Mutex<VirtualDevice>>>>},
-    device_counter: u32,
-}
-
-impl DeviceManager {
-    /// 创建新的设备管理器
-    pub fn new() -> Self {
-        Self {
-            devices: Arc::new(Mutex::new(HashMap::new())),
-            device_counter: 0,
-        }
-    }
-    
-    /// 创建虚拟设备
-    pub async fn create_device(
-        &mut self, 
-        config: VirtualDeviceConfig
-    ) -> Result<String, &'static str> {
-        let device_id = format!("device_{}", self.device_counter);
-        self.device_counter += 1;
-        
-        let device = VirtualDevice::new(config, device_id.clone())?;
-        let device = Arc::new(Mutex::new(device));
-        
-        let mut devices = self.devices.lock().await;
-        devices.insert(device_id.clone(), device);
-        
-        Ok(device_id)
-    }
-    
-    /// 获取虚拟设备
-    pub async fn get_device(
-        &self, 
-        device_id: &str
-    ) -> Result<Arc<Mutex<VirtualDevice>>, &'static str> {
-        let devices = self.devices.lock().await;
-        devices.get(device_id)
-            .cloned()
-            .ok_or("Device not found")
-    }
-    
-    /// 启动虚拟设备
-    pub async fn start_device(
-        &self, 
-        device_id: &str
-    ) -> Result<(), &'static str> {
-        let device = self.get_device(device_id).await?;
-        let mut device = device.lock().await;
-        device.start().await
-    }
-    
-    /// 停止虚拟设备
-    pub async fn stop_device(
-        &self, 
-        device_id: &str
-    ) -> Result<(), &'static str> {
-        let device = self.get_device(device_id).await?;
-        let mut device = device.lock().await;
-        device.stop().await
-    }
-    
-    /// 删除虚拟设备
-    pub async fn delete_device(
-        &self, 
-        device_id: &str
-    ) -> Result<(), &'static str> {
-        let mut devices = self.devices.lock().await;
-        devices.remove(device_id);
-        Ok(())
-    }
-    
-    /// 获取所有设备
-    pub async fn get_all_devices(
-        &self
-    ) -> HashMap<String, Arc<Mutex<VirtualDevice>>> {
-        let devices = self.devices.lock().await;
-        devices.clone()
-    }
-    
-    /// 获取设备状态
-    pub async fn get_device_status(
-        &self, 
-        device_id: &str
-    ) -> Result<DeviceStatus, &'static str> {
-        let device = self.get_device(device_id).await?;
-        let device = device.lock().await;
-        Ok(device.get_status().await)
-    }
-}
-
-/// 默认虚拟设备配置
-pub fn default_config(name: String, ip: Ipv4Addr) -> VirtualDeviceConfig {
-    VirtualDeviceConfig {
-        name,
-        ip,
-        subnet: Ipv4Addr::new(255, 255, 255, 0),
-        gateway: Ipv4Addr::new(10, 0, 0, 1),
-        mtu: 1420,
-        mac: None,
-    }
-}
-
-/// 生成随机MAC地址
-pub fn generate_random_mac() -> [u8; 6] {
-    let mut mac = [0u8; 6];
-    mac[0] = 0x02; // 本地管理地址
-    for i in 1..6 {
-        mac[i] = rand::random();
-    }
-    mac
-}
-
-/// 解析以太网数据包
-pub fn parse_ethernet_packet(data: &[u8]) -> Option<EthernetPacket> {
-    EthernetPacket::new(data)
-}
-
-/// 解析IPv4数据包
-pub fn parse_ipv4_packet(data: &[u8]) -> Option<Ipv4Packet> {
-    Ipv4Packet::new(data)
-}
-
-/// 解析TCP数据包
-pub fn parse_tcp_packet(data: &[u8]) -> Option<TcpPacket> {
-    TcpPacket::new(data)
-}
-
-/// 解析UDP数据包
-pub fn parse_udp_packet(data: &[u8]) -> Option<UdpPacket> {
-    UdpPacket::new(data)
-}
+/*!
+VPNet虚拟设备模块
+
+管理虚拟网络接口，包括：
+- 虚拟网卡的创建和配置
+- 数据包的发送和接收
+- 设备状态管理
+- 跨平台支持
+*/
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{Mutex, OwnedMutexGuard, broadcast, mpsc, oneshot};
+use tokio::task::JoinHandle;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use pnet::datalink::{self, NetworkInterface};
+use pnet::datalink::Channel::Ethernet;
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::icmpv6::{self, Icmpv6Code, Icmpv6Packet, Icmpv6Types};
+use pnet::packet::icmpv6::ndp::{MutableRouterAdvertPacket, NdpOption, NdpOptionTypes};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv6::{Ipv6Packet, MutableIpv6Packet};
+use pnet::packet::ipv4::{Ipv4Packet, MutableIpv4Packet};
+use pnet::packet::tcp::{TcpPacket, MutableTcpPacket};
+use pnet::packet::udp::{UdpPacket, MutableUdpPacket};
+use pnet::packet::{MutablePacket, Packet};
+use std::collections::HashMap;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use rand::RngCore;
+use crate::capture::PacketCapture;
+use crate::crypto::SecureRng;
+use crate::routing::IpCidr;
+use crate::transform::{Direction, PacketTransform, TransformConfig, TransformError};
+
+/// 以太网帧头长度
+pub const ETHERNET_HEADER_LEN: usize = 14;
+
+/// 设备工作模式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum DeviceMode {
+    /// 三层设备，只收发IP数据包
+    #[default]
+    Tun,
+    /// 二层设备，收发带以太网帧头的完整帧
+    Tap,
+}
+
+/// 虚拟设备配置
+#[derive(Debug, Clone, PartialEq)]
+pub struct VirtualDeviceConfig {
+    pub name: String,
+    pub ip: Ipv4Addr,
+    pub subnet: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    pub mtu: u32,
+    pub mac: Option<[u8; 6]>,
+    /// 启用IPv6，启动时按MAC地址配置链路本地地址
+    pub enable_ipv6: bool,
+    /// 虚拟网络的IPv6 /64前缀，启用IPv6时在路由器通告中发布，供对端以SLAAC配置地址
+    pub ipv6_prefix: Option<Ipv6Addr>,
+    pub device_mode: DeviceMode,
+    pub max_restart_attempts: u32,
+    pub restart_cooldown: Duration,
+    pub transforms: Vec<TransformConfig>,
+    pub flush_timeout: Duration,
+    pub routes: Vec<StaticRoute>,
+}
+
+/// 静态路由，设备启动时添加到系统路由表
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticRoute {
+    pub network: String,
+    pub prefix_len: u8,
+    pub gateway: String,
+    #[serde(default)]
+    pub metric: u32,
+}
+
+/// 系统路由管理
+///
+/// 通过平台命令（Linux `ip route`、macOS `route`、Windows `netsh`）维护经虚拟网卡的路由，
+/// 并记录已添加的路由以便设备停止时清理。
+#[derive(Debug)]
+pub struct RouteManager {
+    applied: Vec<StaticRoute>,
+    run_command: fn(&[String], &'static str) -> Result<(), &'static str>,
+}
+
+impl Default for RouteManager {
+    fn default() -> Self {
+        Self {
+            applied: Vec::new(),
+            run_command,
+        }
+    }
+}
+
+/// 虚拟设备
+pub struct VirtualDevice {
+    config: VirtualDeviceConfig,
+    interface: Option<NetworkInterface>,
+    send_channel: Option<Arc<Mutex<dyn datalink::DataLinkSender>>>,
+    recv_channel: Option<Arc<Mutex<dyn datalink::DataLinkReceiver>>>,
+    packet_tx: mpsc::Sender<Vec<u8>>,
+    packet_rx: mpsc::Receiver<Vec<u8>>,
+    /// 待写入虚拟网卡的数据包，由写入任务取出
+    inbound_tx: mpsc::Sender<Vec<u8>>,
+    inbound_rx: Arc<Mutex<mpsc::Receiver<Vec<u8>>>>,
+    inbound_task: Option<JoinHandle<()>>,
+    /// 请求写入任务立即写出队列中的数据包，完成后回复写出的数量
+    flush_tx: Option<mpsc::Sender<oneshot::Sender<usize>>>,
+    device_id: String,
+    is_running: bool,
+    error: Option<String>,
+    events: broadcast::Sender<DeviceEvent>,
+    transforms: Arc<Vec<Box<dyn PacketTransform>>>,
+    route_manager: RouteManager,
+    /// 抓取从虚拟网卡读出的数据包
+    capture: Option<Arc<PacketCapture>>,
+    /// 配置了IPv6前缀时发送路由器通告
+    router_advertiser: Option<Arc<RouterAdvertisementSender>>,
+    router_advertisement_task: Option<JoinHandle<()>>,
+    /// 网卡不存在时创建网卡，测试中替换为不修改系统的实现
+    create_interface: fn(&str, DeviceMode) -> Result<(), &'static str>,
+}
+
+/// 虚拟设备写入器
+///
+/// 以`AsyncWrite`的方式向虚拟网卡写入数据包，每次写入对应一个完整的数据包，
+/// 可配合`tokio::io::copy`等工具使用。同时实现了同步的`std::io::Write`，供期望阻塞接口的代码使用。
+pub struct VirtualDeviceWriter {
+    send_channel: Arc<Mutex<dyn datalink::DataLinkSender>>,
+    /// 等待中的写入锁，锁释放时唤醒写入方
+    pending_lock: Option<PendingSendLock>,
+}
+
+/// 等待虚拟网卡写入锁的future
+type PendingSendLock = Pin<Box<dyn Future<Output = OwnedMutexGuard<dyn datalink::DataLinkSender>> + Send>>;
+
+/// 虚拟设备读取器
+///
+/// 以`AsyncRead`的方式读取从虚拟网卡读出的数据包，与`recv`一样按顺序应用所有变换。
+/// 缓冲区足够大时每次读取对应一个完整的数据包，否则剩余部分留到下一次读取。
+/// 同时实现了同步的`std::io::Read`，供期望阻塞接口的代码使用。
+pub struct VirtualDeviceReader {
+    packet_rx: mpsc::Receiver<Vec<u8>>,
+    /// 被变换拒绝的数据包的差错报文写回虚拟网卡
+    inbound_tx: mpsc::Sender<Vec<u8>>,
+    transforms: Arc<Vec<Box<dyn PacketTransform>>>,
+    /// 应答读出的路由器请求
+    router_advertiser: Option<Arc<RouterAdvertisementSender>>,
+    /// 上一个数据包尚未读出的部分
+    pending: Vec<u8>,
+    offset: usize,
+}
+
+/// 设备状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DeviceStatus {
+    Up,
+    Down,
+    Error(String),
+}
+
+/// 设备事件
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum DeviceEvent {
+    /// 设备发生不可恢复的错误
+    DeviceError {
+        device_id: String,
+        reason: String,
+    },
+    /// 设备的虚拟IP已变更
+    IpChanged {
+        device_id: String,
+        old: Ipv4Addr,
+        new: Ipv4Addr,
+    },
+}
+
+/// 设备监视器
+///
+/// 定期检查虚拟网卡是否仍然存在，被外部删除时自动重建。
+/// 只监视已经绑定网卡的设备，没有找到网卡的设备不会被反复重启。
+pub struct DeviceWatcher {
+    device: Arc<Mutex<VirtualDevice>>,
+    poll_interval: Duration,
+    interface_exists: fn(&str) -> bool,
+}
+
+impl VirtualDevice {
+    /// 创建新的虚拟设备
+    pub fn new(mut config: VirtualDeviceConfig, device_id: String) -> Result<Self, &'static str> {
+        let (packet_tx, packet_rx) = mpsc::channel(1024);
+        let (inbound_tx, inbound_rx) = mpsc::channel(1024);
+        let (events, _) = broadcast::channel(16);
+        let transforms = Arc::new(config.transforms.iter().map(TransformConfig::build).collect());
+        let router_advertiser = RouterAdvertisementSender::for_config(&mut config).map(Arc::new);
+        
+        Ok(Self {
+            config,
+            interface: None,
+            send_channel: None,
+            recv_channel: None,
+            packet_tx,
+            packet_rx,
+            inbound_tx,
+            inbound_rx: Arc::new(Mutex::new(inbound_rx)),
+            inbound_task: None,
+            flush_tx: None,
+            device_id,
+            is_running: false,
+            error: None,
+            events,
+            transforms,
+            route_manager: RouteManager::default(),
+            capture: None,
+            router_advertiser,
+            router_advertisement_task: None,
+            create_interface,
+        })
+    }
+    
+    /// 设置数据包变换，替换配置中的变换
+    ///
+    /// 已启动的设备经`get_packet_sender`写入的数据包在重启后才使用新的变换。
+    pub fn set_transforms(&mut self, transforms: Vec<Box<dyn PacketTransform>>) {
+        self.transforms = Arc::new(transforms);
+    }
+    
+    /// 设置抓包，从虚拟网卡读出的数据包在变换前写入其中
+    ///
+    /// 已启动的设备在重启后才开始抓包，过滤表达式可以随时通过`PacketCapture::set_filter`替换。
+    pub fn set_capture(&mut self, capture: Option<Arc<PacketCapture>>) {
+        self.capture = capture;
+    }
+    
+    /// 启动虚拟设备
+    pub async fn start(&mut self) -> Result<(), &'static str> {
+        // 在实际实现中，这里应该创建虚拟网卡
+        // 例如，在Linux上使用tun/tap设备，在Windows上使用Wintun或OpenVPN虚拟网卡
+        
+        // 目前是模拟实现，实际需要根据不同平台调用相应的API
+        self.is_running = true;
+        self.error = None;
+        
+        // 查找或创建虚拟网卡，TAP模式下创建二层设备
+        let find_interface = |name: &str| datalink::interfaces().into_iter().find(|iface| iface.name == name);
+        let interface = find_interface(&self.config.name).or_else(|| {
+            log::warn!("Virtual interface {} not found, creating a new one...", self.config.name);
+            if let Err(e) = (self.create_interface)(&self.config.name, self.config.device_mode) {
+                log::error!("{}: {}", e, self.config.name);
+                return None;
+            }
+            find_interface(&self.config.name)
+        });
+        
+        if let Some(iface) = interface {
+            self.interface = Some(iface);
+            
+            // 配置虚拟网卡，失败时撤销已添加的路由
+            if let Err(e) = self.configure_interface().await {
+                self.cleanup_routes();
+                self.is_running = false;
+                self.error = Some(e.to_string());
+                return Err(e);
+            }
+            
+            // 启动数据传输任务
+            self.start_data_transfer().await;
+        }
+        
+        // 定期发送路由器通告，与读出的数据包一起经网络发往所有节点
+        if let Some(advertiser) = self.router_advertiser.clone() {
+            if let Some(task) = self.router_advertisement_task.take() {
+                task.abort();
+            }
+            log::info!("Advertising IPv6 prefix {}/64 on {}", advertiser.prefix(), self.config.name);
+            self.router_advertisement_task = Some(advertiser.spawn(self.packet_tx.clone()));
+        }
+        
+        Ok(())
+    }
+    
+    /// 配置虚拟设备
+    async fn configure_interface(&mut self) -> Result<(), &'static str> {
+        // 实际实现中，这里应该配置虚拟网卡的IP、子网掩码、网关等
+        log::info!("Configuring interface {} with IP: {}/{}", 
+                  self.config.name, self.config.ip, self.config.subnet);
+        
+        if self.config.device_mode == DeviceMode::Tap {
+            // TAP设备需要MAC地址，未配置时生成本地管理地址
+            if self.config.mac.is_none() {
+                self.config.mac = Some(generate_random_mac());
+            }
+            
+            // 开启ARP代理，使对端虚拟IP的ARP请求由本机应答
+            #[cfg(target_os = "linux")]
+            {
+                let path = format!("/proc/sys/net/ipv4/conf/{}/proxy_arp", self.config.name);
+                if let Err(e) = std::fs::write(&path, "1") {
+                    log::warn!("Failed to enable ARP proxy on {}: {}", self.config.name, e);
+                }
+            }
+        }
+        
+        // 按MAC地址生成IPv6链路本地地址，TUN设备没有MAC地址时生成一个仅用于推导地址
+        if self.config.enable_ipv6 {
+            let mac = *self.config.mac.get_or_insert_with(generate_random_mac);
+            let link_local = generate_ipv6_link_local(mac);
+            log::info!("Configuring IPv6 link-local address {} on {}", link_local, self.config.name);
+            
+            #[cfg(target_os = "linux")]
+            {
+                let path = format!("/proc/sys/net/ipv6/conf/{}/disable_ipv6", self.config.name);
+                if let Err(e) = std::fs::write(&path, "0") {
+                    log::warn!("Failed to enable IPv6 on {}: {}", self.config.name, e);
+                }
+            }
+            
+            run_command(
+                &link_local_command(&self.config.name, link_local),
+                "Failed to configure IPv6 link-local address"
+            )?;
+        }
+        
+        // 添加配置的静态路由
+        for route in &self.config.routes {
+            self.route_manager.add(&self.config.name, route)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// 在不重建网卡的情况下更换虚拟IP
+    ///
+    /// 清除网卡上的现有地址后添加新地址，并广播`IpChanged`事件。设备未运行时只更新配置。
+    pub fn set_ip(&mut self, new_ip: Ipv4Addr, new_subnet: Ipv4Addr) -> Result<(), &'static str> {
+        let mask = u32::from(new_subnet);
+        if mask.leading_ones() != mask.count_ones() {
+            return Err("Invalid subnet mask");
+        }
+        let prefix_len = mask.count_ones() as u8;
+        
+        let old_ip = self.config.ip;
+        if self.is_running {
+            for cmd in address_commands(&self.config.name, new_ip, new_subnet, prefix_len) {
+                run_command(&cmd, "Address command failed")?;
+            }
+        }
+        
+        self.config.ip = new_ip;
+        self.config.subnet = new_subnet;
+        log::info!("Interface {} address changed from {} to {}/{}",
+                  self.config.name, old_ip, new_ip, prefix_len);
+        
+        let _ = self.events.send(DeviceEvent::IpChanged {
+            device_id: self.device_id.clone(),
+            old: old_ip,
+            new: new_ip,
+        });
+        
+        Ok(())
+    }
+    
+    /// 删除设备启动时添加的静态路由
+    pub fn cleanup_routes(&mut self) {
+        self.route_manager.remove_all(&self.config.name);
+    }
+    
+    /// 启动数据传输任务
+    async fn start_data_transfer(&mut self) {
+        // 启动写入任务
+        let send_channel = self.send_channel.clone();
+        let inbound_rx = self.inbound_rx.clone();
+        let transforms = self.transforms.clone();
+        let name = self.config.name.clone();
+        let advertiser = self.router_advertiser.clone();
+        
+        if let Some(task) = self.inbound_task.take() {
+            task.abort();
+        }
+        let reject_tx = self.packet_tx.clone();
+        let (flush_tx, mut flush_rx) = mpsc::channel::<oneshot::Sender<usize>>(1);
+        self.flush_tx = Some(flush_tx);
+        self.inbound_task = Some(tokio::spawn(async move {
+            let mut inbound_rx = inbound_rx.lock().await;
+            let write = |packet| write_inbound_packet(&name, &transforms, advertiser.as_deref(), send_channel.as_ref(), &reject_tx, packet);
+            loop {
+                tokio::select! {
+                    packet = inbound_rx.recv() => match packet {
+                        Some(packet) => write(packet).await,
+                        None => break,
+                    },
+                    Some(done) = flush_rx.recv() => {
+                        let mut flushed = 0;
+                        while let Ok(packet) = inbound_rx.try_recv() {
+                            write(packet).await;
+                            flushed += 1;
+                        }
+                        let _ = done.send(flushed);
+                    }
+                }
+            }
+        }));
+        
+        // 启动接收任务
+        let recv_channel = self.recv_channel.clone();
+        let packet_tx = self.packet_tx.clone();
+        let capture = self.capture.clone();
+        
+        tokio::spawn(async move {
+            if let Some(recv) = recv_channel {
+                let mut buf = [0u8; 1500];
+                loop {
+                    match recv.lock().await.next() {
+                        Ok(packet) => {
+                            if let Some(capture) = &capture {
+                                capture.record(packet);
+                            }
+                            if let Err(e) = packet_tx.send(packet.to_vec()).await {
+                                log::error!("Failed to send packet: {}", e);
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            log::error!("Failed to receive packet: {}", e);
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+    }
+    
+    /// 从虚拟设备接收数据包，按顺序应用所有变换
+    pub async fn recv(&mut self) -> Result<Vec<u8>, &'static str> {
+        loop {
+            let mut packet = self.packet_rx.recv().await
+                .ok_or("Failed to receive packet")?;
+            
+            // 路由器请求由本机应答，不发往网络
+            if let Some(advertiser) = &self.router_advertiser {
+                if let Some(reply) = advertiser.handle_solicitation(&packet) {
+                    let _ = self.inbound_tx.try_send(reply);
+                    continue;
+                }
+            }
+            
+            match self.apply_transforms(&mut packet, Direction::Outbound) {
+                Ok(()) => return Ok(packet),
+                Err(e) => {
+                    log::debug!("Dropping outbound packet on {}: {}", self.config.name, e);
+                    // 被拒绝的数据包来自本机应用，差错报文写回虚拟网卡
+                    if let TransformError::Rejected(code) = e {
+                        send_reject(&self.inbound_tx, &packet, code);
+                    }
+                }
+            }
+        }
+    }
+    
+    /// 发送数据包到虚拟设备
+    pub async fn send(&mut self, data: &[u8]) -> Result<(), &'static str> {
+        if !self.is_running {
+            return Err("Device is not running");
+        }
+        
+        // 按相反顺序应用变换
+        let mut packet = data.to_vec();
+        if let Err(e) = self.apply_transforms(&mut packet, Direction::Inbound) {
+            log::debug!("Dropping inbound packet on {}: {}", self.config.name, e);
+            if let TransformError::Rejected(code) = e {
+                if let Some(reply) = IcmpErrorGenerator::generate_reject(data, code) {
+                    self.inject_packet(reply);
+                }
+            }
+            return Err("Packet transform failed");
+        }
+        
+        // 实际实现中，这里应该将数据发送到虚拟网卡
+        log::debug!("Sending packet to virtual device {} ({} bytes)", 
+                  self.config.name, packet.len());
+        
+        if let Some(send) = &self.send_channel {
+            if let Some(Err(_)) = send.lock().await.send_to(&packet, None) {
+                return Err("Failed to send packet");
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// 将数据包当作从虚拟网卡读出的数据包，由`recv`取出发往网络
+    ///
+    /// 用于向远端回复差错报文等本地生成的数据包，通道已满时丢弃。
+    pub fn inject_packet(&self, packet: Vec<u8>) {
+        if self.packet_tx.try_send(packet).is_err() {
+            log::debug!("Packet queue of {} full, dropping injected packet", self.config.name);
+        }
+    }
+    
+    /// 获取向虚拟设备写入数据包的通道
+    ///
+    /// 与`send`相比，调用方直接交出数据包的所有权，变换在原缓冲区上进行，省去一次复制，
+    /// 适用于转发解密后数据包的热路径。数据包由设备启动后的写入任务按顺序写入虚拟网卡，
+    /// 设备未运行时留在通道中，通道满时发送方等待。
+    pub fn get_packet_sender(&self) -> mpsc::Sender<Vec<u8>> {
+        self.inbound_tx.clone()
+    }
+    
+    /// 应用数据包变换，发出时按顺序，收到时按相反顺序
+    fn apply_transforms(
+        &self,
+        packet: &mut Vec<u8>,
+        direction: Direction
+    ) -> Result<(), crate::transform::TransformError> {
+        apply_transforms(&self.transforms, packet, direction)
+    }
+    
+    /// 将待写入虚拟网卡的数据包立即写出
+    ///
+    /// 由写入任务写出`get_packet_sender`等途径排队的数据包，超过`flush_timeout`仍未写完时返回错误，
+    /// 剩余的数据包留在队列中。设备未启动时没有写入任务，直接返回。
+    pub async fn flush(&mut self) -> Result<(), &'static str> {
+        let Some(flush_tx) = self.flush_tx.clone() else {
+            return Ok(());
+        };
+        
+        let request = async move {
+            let (done_tx, done_rx) = oneshot::channel();
+            flush_tx.send(done_tx).await.map_err(|_| "Device writer stopped")?;
+            done_rx.await.map_err(|_| "Device writer stopped")
+        };
+        
+        match tokio::time::timeout(self.config.flush_timeout, request).await {
+            Ok(Ok(flushed)) => {
+                log::debug!("Flushed {} pending packets on {}", flushed, self.config.name);
+                Ok(())
+            }
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("Flush timed out"),
+        }
+    }
+    
+    /// 获取虚拟网卡的异步写入器，设备尚未打开时返回None
+    pub fn writer(&self) -> Option<VirtualDeviceWriter> {
+        self.send_channel.clone().map(|send_channel| VirtualDeviceWriter { send_channel, pending_lock: None })
+    }
+    
+    /// 取出从虚拟网卡读出数据包的读取器
+    ///
+    /// 读取器独占接收队列，取出后`recv`不再得到数据包，设备重启后读取器仍然有效。
+    pub fn reader(&mut self) -> VirtualDeviceReader {
+        let (_, closed_rx) = mpsc::channel(1);
+        VirtualDeviceReader {
+            packet_rx: std::mem::replace(&mut self.packet_rx, closed_rx),
+            inbound_tx: self.inbound_tx.clone(),
+            transforms: self.transforms.clone(),
+            router_advertiser: self.router_advertiser.clone(),
+            pending: Vec::new(),
+            offset: 0,
+        }
+    }
+    
+    /// 拆分为读取器和写入器，可以用`tokio::io::copy`在虚拟网卡和网络之间双向复制
+    ///
+    /// 设备尚未打开时返回None，此时不取出读取器。
+    pub fn split(&mut self) -> Option<(VirtualDeviceReader, VirtualDeviceWriter)> {
+        let writer = self.writer()?;
+        Some((self.reader(), writer))
+    }
+    
+    /// 停止虚拟设备
+    pub async fn stop(&mut self) -> Result<(), &'static str> {
+        // 关闭前写出缓冲中的数据包
+        if let Err(e) = self.flush().await {
+            log::warn!("Failed to flush virtual device {}: {}", self.config.name, e);
+        }
+        
+        self.is_running = false;
+        self.flush_tx = None;
+        if let Some(task) = self.inbound_task.take() {
+            task.abort();
+        }
+        if let Some(task) = self.router_advertisement_task.take() {
+            task.abort();
+        }
+        self.cleanup_routes();
+        // 重新启动时重新查找网卡
+        self.interface = None;
+        // 实际实现中，这里应该关闭虚拟网卡
+        log::info!("Stopping virtual device {}", self.config.name);
+        Ok(())
+    }
+    
+    /// 获取设备状态
+    pub async fn get_status(&self) -> DeviceStatus {
+        if let Some(reason) = &self.error {
+            DeviceStatus::Error(reason.clone())
+        } else if self.is_running {
+            DeviceStatus::Up
+        } else {
+            DeviceStatus::Down
+        }
+    }
+    
+    /// 获取设备配置
+    pub async fn get_config(&self) -> &VirtualDeviceConfig {
+        &self.config
+    }
+    
+    /// 获取设备ID
+    pub async fn get_device_id(&self) -> &str {
+        &self.device_id
+    }
+    
+    /// 订阅设备事件
+    pub fn subscribe(&self) -> broadcast::Receiver<DeviceEvent> {
+        self.events.subscribe()
+    }
+    
+    /// 将设备标记为错误状态并广播错误事件
+    pub fn mark_error(&mut self, reason: &str) {
+        self.is_running = false;
+        self.error = Some(reason.to_string());
+        
+        // 没有订阅者时发送失败，忽略即可
+        let _ = self.events.send(DeviceEvent::DeviceError {
+            device_id: self.device_id.clone(),
+            reason: reason.to_string(),
+        });
+    }
+    
+    /// 重置设备
+    pub async fn reset(&mut self) -> Result<(), &'static str> {
+        self.stop().await?;
+        self.start().await
+    }
+    
+    /// 新配置是否修改了需要重启设备才能生效的字段
+    ///
+    /// 网卡名称、地址、MTU、工作模式和静态路由等在启动时应用；新配置未指定MAC地址时沿用当前的MAC地址，不视为修改。
+    pub fn config_changed(&self, new_config: &VirtualDeviceConfig) -> bool {
+        let current = &self.config;
+        current.name != new_config.name
+            || current.ip != new_config.ip
+            || current.subnet != new_config.subnet
+            || current.gateway != new_config.gateway
+            || current.mtu != new_config.mtu
+            || current.device_mode != new_config.device_mode
+            || current.enable_ipv6 != new_config.enable_ipv6
+            || current.ipv6_prefix != new_config.ipv6_prefix
+            || current.routes != new_config.routes
+            || new_config.mac.is_some_and(|mac| current.mac != Some(mac))
+    }
+    
+    /// 更新设备配置
+    ///
+    /// 只有修改了需要重启才能生效的字段（见`config_changed`）时才重启设备，其余字段直接替换。
+    /// 数据包变换在创建设备时构建，更新配置不会改变。
+    pub async fn update_config(&mut self, mut new_config: VirtualDeviceConfig) -> Result<(), &'static str> {
+        if !self.config_changed(&new_config) {
+            new_config.mac = new_config.mac.or(self.config.mac);
+            self.config = new_config;
+            log::debug!("Updated configuration of {} without restart", self.config.name);
+            return Ok(());
+        }
+        
+        self.stop().await?;
+        self.config = new_config;
+        self.router_advertiser = RouterAdvertisementSender::for_config(&mut self.config).map(Arc::new);
+        self.start().await
+    }
+}
+
+impl StaticRoute {
+    /// 解析目的网段
+    pub fn cidr(&self) -> Result<IpCidr, &'static str> {
+        let network: Ipv4Addr = self.network.parse().map_err(|_| "Invalid route network")?;
+        IpCidr::new(network, self.prefix_len)
+    }
+    
+    /// 解析网关地址
+    pub fn gateway(&self) -> Result<Ipv4Addr, &'static str> {
+        self.gateway.parse().map_err(|_| "Invalid route gateway")
+    }
+}
+
+impl RouteManager {
+    /// 添加经指定网卡的路由
+    pub fn add(&mut self, interface: &str, route: &StaticRoute) -> Result<(), &'static str> {
+        let cidr = route.cidr()?;
+        let gateway = route.gateway()?;
+        
+        (self.run_command)(&route_command(RouteAction::Add, interface, cidr, gateway, route.metric), "Route command failed")?;
+        log::info!("Added route {} via {} on {}", cidr, gateway, interface);
+        self.applied.push(route.clone());
+        Ok(())
+    }
+    
+    /// 删除之前添加的所有路由，失败时记录日志并继续
+    pub fn remove_all(&mut self, interface: &str) {
+        for route in self.applied.drain(..).rev() {
+            let (cidr, gateway) = match (route.cidr(), route.gateway()) {
+                (Ok(cidr), Ok(gateway)) => (cidr, gateway),
+                _ => continue,
+            };
+            
+            let cmd = route_command(RouteAction::Delete, interface, cidr, gateway, route.metric);
+            match (self.run_command)(&cmd, "Route command failed") {
+                Ok(()) => log::info!("Removed route {} via {} on {}", cidr, gateway, interface),
+                Err(e) => log::warn!("Failed to remove route {} on {}: {}", cidr, interface, e),
+            }
+        }
+    }
+    
+    /// 已添加的路由
+    pub fn applied(&self) -> &[StaticRoute] {
+        &self.applied
+    }
+}
+
+/// ICMP目标不可达的代码
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum IcmpUnreachCode {
+    /// 主机不可达
+    HostUnreachable = 1,
+    /// 端口不可达
+    PortUnreachable = 3,
+    /// 被管理策略禁止
+    #[default]
+    AdminProhibited = 13,
+}
+
+/// ICMPv4差错报文生成器
+pub struct IcmpErrorGenerator;
+
+impl IcmpErrorGenerator {
+    /// 差错报文中引用的原始负载字节数
+    const QUOTED_PAYLOAD_LEN: usize = 8;
+    
+    /// 为被拒绝的数据包生成发回源地址的ICMP目标不可达报文
+    ///
+    /// 报文以原目的地址为源地址，携带原IP头部和最多8字节负载；TAP模式下同时交换以太网地址。
+    /// 原数据包不是合法的IPv4数据包、不是首个分片、本身是ICMP差错报文，或源地址为广播、
+    /// 组播地址时不生成（RFC 1122），返回`None`。
+    pub fn generate_reject(original_packet: &[u8], code: IcmpUnreachCode) -> Option<Vec<u8>> {
+        let offset = crate::transform::locate_ipv4(original_packet)?;
+        let ip = &original_packet[offset..];
+        let header_len = ((ip[0] & 0x0F) as usize) * 4;
+        if header_len < 20 || ip.len() < header_len {
+            return None;
+        }
+        
+        let source = Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15]);
+        let destination = Ipv4Addr::new(ip[16], ip[17], ip[18], ip[19]);
+        if source.is_unspecified() || source.is_broadcast() || source.is_multicast()
+            || destination.is_broadcast() || destination.is_multicast()
+        {
+            return None;
+        }
+        if u16::from_be_bytes([ip[6], ip[7]]) & 0x1FFF != 0 {
+            return None;
+        }
+        // 不为ICMP差错报文生成差错报文，避免互相触发
+        if ip[9] == 1 && ip.get(header_len).is_some_and(|icmp_type| matches!(icmp_type, 3 | 4 | 5 | 11 | 12)) {
+            return None;
+        }
+        
+        // 原数据包不足8字节负载时引用全部负载
+        let quoted_len = header_len + (ip.len() - header_len).min(Self::QUOTED_PAYLOAD_LEN);
+        let total_len = 20 + 8 + quoted_len;
+        
+        let mut reply = Vec::with_capacity(offset + total_len);
+        if offset > 0 {
+            // 以太网帧头：交换目的和源MAC地址
+            reply.extend_from_slice(&original_packet[6..12]);
+            reply.extend_from_slice(&original_packet[0..6]);
+            reply.extend_from_slice(&EtherTypes::Ipv4.0.to_be_bytes());
+        }
+        
+        let ip_start = reply.len();
+        reply.extend_from_slice(&[0x45, 0]);
+        reply.extend_from_slice(&(total_len as u16).to_be_bytes());
+        reply.extend_from_slice(&[0, 0, 0, 0, 64, 1, 0, 0]);
+        reply.extend_from_slice(&destination.octets());
+        reply.extend_from_slice(&source.octets());
+        let ip_checksum = pnet::packet::util::checksum(&reply[ip_start..], 5);
+        reply[ip_start + 10..ip_start + 12].copy_from_slice(&ip_checksum.to_be_bytes());
+        
+        let icmp_start = reply.len();
+        reply.extend_from_slice(&[3, code as u8, 0, 0, 0, 0, 0, 0]);
+        reply.extend_from_slice(&ip[..quoted_len]);
+        let icmp_checksum = pnet::packet::util::checksum(&reply[icmp_start..], 1);
+        reply[icmp_start + 2..icmp_start + 4].copy_from_slice(&icmp_checksum.to_be_bytes());
+        
+        Some(reply)
+    }
+}
+
+/// IPv6路由器通告发送器（RFC 4861）
+///
+/// 在虚拟网络上发布IPv6 /64前缀，对端据此以SLAAC（RFC 4862）自动配置地址：
+/// 定期经隧道向所有节点组播通告，并应答本机读出的和经隧道收到的路由器请求。路由器生存期为0，
+/// 对端不会把虚拟网卡当作IPv6默认路由。
+#[derive(Debug, Clone)]
+pub struct RouterAdvertisementSender {
+    prefix: Ipv6Addr,
+    /// 通告的源地址，即本机的链路本地地址
+    source: Ipv6Addr,
+    mac: [u8; 6],
+    mtu: u32,
+    mode: DeviceMode,
+}
+
+impl RouterAdvertisementSender {
+    /// 定期通告的间隔
+    pub const INTERVAL: Duration = Duration::from_secs(200);
+    
+    /// 前缀的有效期（秒）
+    const VALID_LIFETIME: u32 = 2_592_000;
+    
+    /// 前缀的首选期（秒）
+    const PREFERRED_LIFETIME: u32 = 604_800;
+    
+    /// 所有节点组播地址`ff02::1`
+    const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+    
+    /// 以本机MAC地址创建发送器，`prefix`的主机部分被清零
+    pub fn new(prefix: Ipv6Addr, mac: [u8; 6], mtu: u32, mode: DeviceMode) -> Self {
+        let prefix = Ipv6Addr::from(u128::from(prefix) & !(u64::MAX as u128));
+        Self {
+            prefix,
+            source: generate_ipv6_link_local(mac),
+            mac,
+            mtu,
+            mode,
+        }
+    }
+    
+    /// 启用了IPv6且配置了前缀时按设备配置创建，没有MAC地址时生成一个并写回配置
+    fn for_config(config: &mut VirtualDeviceConfig) -> Option<Self> {
+        let prefix = config.ipv6_prefix.filter(|_| config.enable_ipv6)?;
+        let mac = *config.mac.get_or_insert_with(generate_random_mac);
+        Some(Self::new(prefix, mac, config.mtu, config.device_mode))
+    }
+    
+    /// 发布的前缀
+    pub fn prefix(&self) -> Ipv6Addr {
+        self.prefix
+    }
+    
+    /// 发往所有节点的通告
+    pub fn unsolicited(&self) -> Vec<u8> {
+        self.advertisement(Self::ALL_NODES, [0x33, 0x33, 0, 0, 0, 1])
+    }
+    
+    /// 数据包是路由器请求时返回应答的通告，否则返回`None`
+    ///
+    /// 请求的源地址未指定时以组播应答（RFC 4861 6.2.6），跳数限制不是255的请求被忽略。
+    pub fn handle_solicitation(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        let source = self.solicitation_source(frame)?;
+        if source.is_unspecified() {
+            return Some(self.unsolicited());
+        }
+        let mut destination_mac = [0u8; 6];
+        if self.mode == DeviceMode::Tap {
+            destination_mac.copy_from_slice(&frame[6..12]);
+        }
+        Some(self.advertisement(source, destination_mac))
+    }
+    
+    /// 经隧道收到的数据包是路由器请求时返回应答的通告，否则返回`None`
+    ///
+    /// 对端的链路本地地址不经隧道路由，应答总是组播给所有节点（RFC 4861 6.2.6）。
+    pub fn handle_remote_solicitation(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        self.solicitation_source(frame).map(|_| self.unsolicited())
+    }
+    
+    /// 数据包是路由器请求时返回请求的源地址
+    fn solicitation_source(&self, frame: &[u8]) -> Option<Ipv6Addr> {
+        if frame_protocol(self.mode, frame)? != EtherTypes::Ipv6.0 {
+            return None;
+        }
+        let ip = Ipv6Packet::new(frame_payload(self.mode, frame)?)?;
+        if ip.get_next_header() != IpNextHeaderProtocols::Icmpv6 || ip.get_hop_limit() != 255 {
+            return None;
+        }
+        let icmp = Icmpv6Packet::new(ip.payload())?;
+        if icmp.get_icmpv6_type() != Icmpv6Types::RouterSolicit {
+            return None;
+        }
+        Some(ip.get_source())
+    }
+    
+    /// 每隔`INTERVAL`向通道写入一次通告，启动时立即发送第一次
+    pub fn spawn(self: Arc<Self>, tx: mpsc::Sender<Vec<u8>>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Self::INTERVAL);
+            loop {
+                ticker.tick().await;
+                if tx.send(self.unsolicited()).await.is_err() {
+                    break;
+                }
+            }
+        })
+    }
+    
+    /// 构造发往`destination`的通告，TAP模式下带以太网帧头，TUN模式下为IPv6数据包
+    fn advertisement(&self, destination: Ipv6Addr, destination_mac: [u8; 6]) -> Vec<u8> {
+        let icmp = self.icmp_advertisement(destination);
+        
+        let mut ip_buf = vec![0u8; MutableIpv6Packet::minimum_packet_size() + icmp.len()];
+        {
+            // 缓冲区按最小长度分配，不会失败
+            let mut ip = MutableIpv6Packet::new(&mut ip_buf).unwrap();
+            ip.set_version(6);
+            ip.set_payload_length(icmp.len() as u16);
+            ip.set_next_header(IpNextHeaderProtocols::Icmpv6);
+            ip.set_hop_limit(255);
+            ip.set_source(self.source);
+            ip.set_destination(destination);
+            ip.set_payload(&icmp);
+        }
+        
+        match self.mode {
+            DeviceMode::Tun => ip_buf,
+            DeviceMode::Tap => {
+                let mut frame = Vec::with_capacity(ETHERNET_HEADER_LEN + ip_buf.len());
+                frame.extend_from_slice(&destination_mac);
+                frame.extend_from_slice(&self.mac);
+                frame.extend_from_slice(&EtherTypes::Ipv6.0.to_be_bytes());
+                frame.extend_from_slice(&ip_buf);
+                frame
+            }
+        }
+    }
+    
+    /// 构造ICMPv6路由器通告报文，带前缀信息和MTU选项，TAP模式下另带源链路层地址选项
+    fn icmp_advertisement(&self, destination: Ipv6Addr) -> Vec<u8> {
+        // 前缀信息：前缀长度、L和A标志、有效期、首选期、保留字段、前缀
+        let mut prefix_info = vec![64, 0xc0];
+        prefix_info.extend_from_slice(&Self::VALID_LIFETIME.to_be_bytes());
+        prefix_info.extend_from_slice(&Self::PREFERRED_LIFETIME.to_be_bytes());
+        prefix_info.extend_from_slice(&[0; 4]);
+        prefix_info.extend_from_slice(&self.prefix.octets());
+        
+        let mut mtu = vec![0, 0];
+        mtu.extend_from_slice(&self.mtu.to_be_bytes());
+        
+        // 选项长度以8字节为单位，包括类型和长度字段
+        let mut options = vec![
+            NdpOption { option_type: NdpOptionTypes::PrefixInformation, length: 4, data: prefix_info },
+            NdpOption { option_type: NdpOptionTypes::MTU, length: 1, data: mtu },
+        ];
+        if self.mode == DeviceMode::Tap {
+            options.push(NdpOption { option_type: NdpOptionTypes::SourceLLAddr, length: 1, data: self.mac.to_vec() });
+        }
+        
+        let options_len: usize = options.iter().map(|option| option.length as usize * 8).sum();
+        let mut buf = vec![0u8; MutableRouterAdvertPacket::minimum_packet_size() + options_len];
+        {
+            let mut ra = MutableRouterAdvertPacket::new(&mut buf).unwrap();
+            ra.set_icmpv6_type(Icmpv6Types::RouterAdvert);
+            ra.set_icmpv6_code(Icmpv6Code(0));
+            ra.set_hop_limit(64);
+            ra.set_flags(0);
+            ra.set_lifetime(0);
+            ra.set_reachable_time(0);
+            ra.set_retrans_time(0);
+            ra.set_options(&options);
+        }
+        let checksum = icmpv6::checksum(&Icmpv6Packet::new(&buf).unwrap(), &self.source, &destination);
+        buf[2..4].copy_from_slice(&checksum.to_be_bytes());
+        buf
+    }
+}
+
+/// 解析IPv6 /64前缀，例如`fd00:1234::/64`，SLAAC只支持64位前缀
+pub fn parse_ipv6_prefix(prefix: &str) -> Result<Ipv6Addr, &'static str> {
+    let (addr, len) = prefix.split_once('/').ok_or("IPv6 prefix must be in the form <address>/64")?;
+    let addr: Ipv6Addr = addr.parse().map_err(|_| "Invalid IPv6 prefix address")?;
+    if len != "64" {
+        return Err("SLAAC requires a /64 prefix");
+    }
+    Ok(Ipv6Addr::from(u128::from(addr) & !(u64::MAX as u128)))
+}
+
+/// 为被拒绝的数据包生成ICMP差错报文，放入发回源地址方向的通道
+fn send_reject(tx: &mpsc::Sender<Vec<u8>>, packet: &[u8], code: IcmpUnreachCode) {
+    if let Some(reply) = IcmpErrorGenerator::generate_reject(packet, code) {
+        let _ = tx.try_send(reply);
+    }
+}
+
+/// 按方向依次应用数据包变换，发出时按顺序，收到时按相反顺序
+fn apply_transforms(
+    transforms: &[Box<dyn PacketTransform>],
+    packet: &mut Vec<u8>,
+    direction: Direction
+) -> Result<(), crate::transform::TransformError> {
+    match direction {
+        Direction::Outbound => transforms.iter()
+            .try_for_each(|t| t.transform(packet, direction)),
+        Direction::Inbound => transforms.iter().rev()
+            .try_for_each(|t| t.transform(packet, direction)),
+    }
+}
+
+/// 路由操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RouteAction {
+    Add,
+    Delete,
+}
+
+/// 生成当前平台的路由命令
+fn route_command(
+    action: RouteAction,
+    interface: &str,
+    cidr: IpCidr,
+    gateway: Ipv4Addr,
+    metric: u32
+) -> Vec<String> {
+    let destination = cidr.to_string();
+    let gateway = gateway.to_string();
+    let metric = metric.to_string();
+    
+    if cfg!(target_os = "windows") {
+        let verb = if action == RouteAction::Add { "add" } else { "delete" };
+        let mut cmd = vec![
+            "netsh".to_string(), "interface".to_string(), "ipv4".to_string(),
+            verb.to_string(), "route".to_string(),
+            destination, interface.to_string(), gateway,
+        ];
+        if action == RouteAction::Add {
+            cmd.push(format!("metric={}", metric));
+        }
+        cmd
+    } else if cfg!(target_os = "macos") {
+        let verb = if action == RouteAction::Add { "add" } else { "delete" };
+        vec![
+            "route".to_string(), "-n".to_string(), verb.to_string(),
+            "-net".to_string(), destination, gateway,
+        ]
+    } else {
+        let verb = if action == RouteAction::Add { "add" } else { "del" };
+        vec![
+            "ip".to_string(), "route".to_string(), verb.to_string(),
+            destination, "via".to_string(), gateway,
+            "dev".to_string(), interface.to_string(),
+            "metric".to_string(), metric,
+        ]
+    }
+}
+
+/// 创建虚拟网卡
+fn create_interface(interface: &str, mode: DeviceMode) -> Result<(), &'static str> {
+    for cmd in create_interface_commands(interface, mode) {
+        run_command(&cmd, "Failed to create virtual interface")?;
+    }
+    Ok(())
+}
+
+/// 生成当前平台创建虚拟网卡的命令
+///
+/// Linux上以`ip tuntap`创建TUN或TAP设备（对应`IFF_TUN`/`IFF_TAP`）并启用；
+/// 其他平台的虚拟网卡由驱动创建，返回空列表。
+fn create_interface_commands(interface: &str, mode: DeviceMode) -> Vec<Vec<String>> {
+    if !cfg!(target_os = "linux") {
+        return Vec::new();
+    }
+    let mode = match mode {
+        DeviceMode::Tun => "tun",
+        DeviceMode::Tap => "tap",
+    };
+    vec![
+        vec![
+            "ip".to_string(), "tuntap".to_string(), "add".to_string(),
+            "dev".to_string(), interface.to_string(), "mode".to_string(), mode.to_string(),
+        ],
+        vec![
+            "ip".to_string(), "link".to_string(), "set".to_string(),
+            interface.to_string(), "up".to_string(),
+        ],
+    ]
+}
+
+/// 生成当前平台替换网卡地址的命令
+fn address_commands(
+    interface: &str,
+    ip: Ipv4Addr,
+    subnet: Ipv4Addr,
+    prefix_len: u8
+) -> Vec<Vec<String>> {
+    if cfg!(target_os = "windows") {
+        vec![vec![
+            "netsh".to_string(), "interface".to_string(), "ipv4".to_string(),
+            "set".to_string(), "address".to_string(), format!("name={}", interface),
+            "static".to_string(), ip.to_string(), subnet.to_string(),
+        ]]
+    } else if cfg!(target_os = "macos") {
+        vec![vec![
+            "ifconfig".to_string(), interface.to_string(), "inet".to_string(),
+            ip.to_string(), "netmask".to_string(), subnet.to_string(),
+        ]]
+    } else {
+        vec![
+            vec![
+                "ip".to_string(), "-4".to_string(), "addr".to_string(), "flush".to_string(),
+                "dev".to_string(), interface.to_string(),
+            ],
+            vec![
+                "ip".to_string(), "addr".to_string(), "add".to_string(),
+                format!("{}/{}", ip, prefix_len), "dev".to_string(), interface.to_string(),
+            ],
+        ]
+    }
+}
+
+/// 生成当前平台为网卡添加IPv6链路本地地址的命令
+fn link_local_command(interface: &str, addr: Ipv6Addr) -> Vec<String> {
+    if cfg!(target_os = "windows") {
+        vec![
+            "netsh".to_string(), "interface".to_string(), "ipv6".to_string(),
+            "add".to_string(), "address".to_string(), format!("interface={}", interface),
+            format!("address={}", addr), "type=unicast".to_string(),
+        ]
+    } else if cfg!(target_os = "macos") {
+        vec![
+            "ifconfig".to_string(), interface.to_string(), "inet6".to_string(),
+            format!("{}%{}", addr, interface), "prefixlen".to_string(), "64".to_string(),
+        ]
+    } else {
+        vec![
+            "ip".to_string(), "-6".to_string(), "addr".to_string(), "add".to_string(),
+            format!("{}/64", addr), "dev".to_string(), interface.to_string(),
+            "scope".to_string(), "link".to_string(),
+        ]
+    }
+}
+
+/// 对发往虚拟网卡的数据包应用变换后写入，被拒绝的数据包回复差错报文
+///
+/// `reject_tx`发往网络。对端的路由器请求由本机应答，不写入虚拟网卡。
+async fn write_inbound_packet(
+    name: &str,
+    transforms: &[Box<dyn PacketTransform>],
+    advertiser: Option<&RouterAdvertisementSender>,
+    send_channel: Option<&Arc<Mutex<dyn datalink::DataLinkSender>>>,
+    reject_tx: &mpsc::Sender<Vec<u8>>,
+    mut packet: Vec<u8>
+) {
+    if let Some(reply) = advertiser.and_then(|advertiser| advertiser.handle_remote_solicitation(&packet)) {
+        let _ = reject_tx.try_send(reply);
+        return;
+    }
+    
+    if let Err(e) = apply_transforms(transforms, &mut packet, Direction::Inbound) {
+        log::debug!("Dropping inbound packet on {}: {}", name, e);
+        if let TransformError::Rejected(code) = e {
+            send_reject(reject_tx, &packet, code);
+        }
+        return;
+    }
+    
+    if let Some(send) = send_channel {
+        if let Some(Err(e)) = send.lock().await.send_to(&packet, None) {
+            log::warn!("Failed to write packet to {}: {}", name, e);
+        }
+    }
+}
+
+/// 执行系统命令，失败时返回`error`
+fn run_command(cmd: &[String], error: &'static str) -> Result<(), &'static str> {
+    let status = std::process::Command::new(&cmd[0])
+        .args(&cmd[1..])
+        .status()
+        .map_err(|_| "Failed to run command")?;
+    
+    if status.success() {
+        Ok(())
+    } else {
+        log::warn!("Command failed: {}", cmd.join(" "));
+        Err(error)
+    }
+}
+
+impl AsyncWrite for VirtualDeviceWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8]
+    ) -> Poll<io::Result<usize>> {
+        // 其他任务正在写入时等待锁释放
+        let this = self.get_mut();
+        let mut lock = this.pending_lock.take()
+            .unwrap_or_else(|| Box::pin(this.send_channel.clone().lock_owned()));
+        let mut send = match lock.as_mut().poll(cx) {
+            Poll::Ready(send) => send,
+            Poll::Pending => {
+                this.pending_lock = Some(lock);
+                return Poll::Pending;
+            }
+        };
+        
+        match send.send_to(buf, None) {
+            Some(Ok(())) => Poll::Ready(Ok(buf.len())),
+            Some(Err(e)) => Poll::Ready(Err(e)),
+            None => Poll::Ready(Err(io::Error::other("send buffer unavailable"))),
+        }
+    }
+    
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // 每次写入都直接发送到虚拟网卡，没有额外的缓冲
+        Poll::Ready(Ok(()))
+    }
+    
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl io::Write for VirtualDeviceWriter {
+    /// 阻塞直到数据包写入虚拟网卡，不能在异步任务中调用
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.send_channel.blocking_lock().send_to(buf, None) {
+            Some(Ok(())) => Ok(buf.len()),
+            Some(Err(e)) => Err(e),
+            None => Err(io::Error::other("send buffer unavailable")),
+        }
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl VirtualDeviceReader {
+    /// 对收到的数据包应用变换，通过的数据包留待读出
+    fn accept(&mut self, mut packet: Vec<u8>) {
+        if let Some(reply) = self.router_advertiser.as_ref().and_then(|advertiser| advertiser.handle_solicitation(&packet)) {
+            let _ = self.inbound_tx.try_send(reply);
+            return;
+        }
+        
+        match apply_transforms(&self.transforms, &mut packet, Direction::Outbound) {
+            Ok(()) => {
+                self.pending = packet;
+                self.offset = 0;
+            }
+            Err(e) => {
+                log::debug!("Dropping outbound packet: {}", e);
+                if let TransformError::Rejected(code) = e {
+                    send_reject(&self.inbound_tx, &packet, code);
+                }
+            }
+        }
+    }
+    
+    /// 将尚未读出的数据复制到`buf`，返回复制的字节数
+    fn take_pending(&mut self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.pending.len() - self.offset);
+        buf[..len].copy_from_slice(&self.pending[self.offset..self.offset + len]);
+        self.offset += len;
+        len
+    }
+    
+    fn has_pending(&self) -> bool {
+        self.offset < self.pending.len()
+    }
+}
+
+impl AsyncRead for VirtualDeviceReader {
+    /// 接收队列关闭后返回0字节，表示读到末尾
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>
+    ) -> Poll<io::Result<()>> {
+        while !self.has_pending() {
+            match self.packet_rx.poll_recv(cx) {
+                Poll::Ready(Some(packet)) => self.accept(packet),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        
+        let len = self.take_pending(buf.initialize_unfilled());
+        buf.advance(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl io::Read for VirtualDeviceReader {
+    /// 阻塞直到读出数据包，不能在异步任务中调用
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.has_pending() {
+            match self.packet_rx.blocking_recv() {
+                Some(packet) => self.accept(packet),
+                None => return Ok(0),
+            }
+        }
+        Ok(self.take_pending(buf))
+    }
+}
+
+impl DeviceWatcher {
+    /// 默认检查间隔（秒）
+    pub const DEFAULT_POLL_INTERVAL: u64 = 5;
+    
+    /// 创建新的设备监视器
+    pub fn new(device: Arc<Mutex<VirtualDevice>>) -> Self {
+        Self {
+            device,
+            poll_interval: Duration::from_secs(Self::DEFAULT_POLL_INTERVAL),
+            interface_exists,
+        }
+    }
+    
+    /// 设置检查间隔
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+    
+    /// 在后台任务中运行`run`
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(self.run())
+    }
+    
+    /// 运行监视循环，虚拟网卡无法重建时返回
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.poll_interval);
+        loop {
+            interval.tick().await;
+            
+            let (name, max_attempts, cooldown) = {
+                let device = self.device.lock().await;
+                if !device.is_running || device.interface.is_none() {
+                    continue;
+                }
+                (
+                    device.config.name.clone(),
+                    device.config.max_restart_attempts,
+                    device.config.restart_cooldown,
+                )
+            };
+            
+            if (self.interface_exists)(&name) {
+                continue;
+            }
+            
+            log::error!("Virtual interface {} disappeared, restarting device", name);
+            if !self.restart(&name, max_attempts, cooldown).await {
+                log::error!("Giving up on virtual interface {} after {} attempts", name, max_attempts);
+                self.device.lock().await.mark_error("interface lost");
+                break;
+            }
+        }
+    }
+    
+    /// 尝试重建虚拟网卡，成功时返回true
+    async fn restart(&self, name: &str, max_attempts: u32, cooldown: Duration) -> bool {
+        for attempt in 1..=max_attempts {
+            // 每次尝试之间释放锁，避免冷却期间阻塞其他使用者
+            if let Err(e) = self.device.lock().await.reset().await {
+                log::warn!("Restart attempt {}/{} for {} failed: {}", attempt, max_attempts, name, e);
+            } else if (self.interface_exists)(name) {
+                log::info!("Virtual interface {} restored after {} attempt(s)", name, attempt);
+                return true;
+            }
+            
+            tokio::time::sleep(cooldown).await;
+        }
+        
+        false
+    }
+}
+
+/// 设备管理器
+pub struct DeviceManager {
+    devices: Arc<Mutex<HashMap<String, Arc<Mutex<VirtualDevice>>>>>,
+    device_counter: u32,
+}
+
+impl DeviceManager {
+    /// 创建新的设备管理器
+    pub fn new() -> Self {
+        Self {
+            devices: Arc::new(Mutex::new(HashMap::new())),
+            device_counter: 0,
+        }
+    }
+    
+    /// 创建虚拟设备
+    pub async fn create_device(
+        &mut self, 
+        config: VirtualDeviceConfig
+    ) -> Result<String, &'static str> {
+        let device_id = format!("device_{}", self.device_counter);
+        self.device_counter += 1;
+        
+        let device = VirtualDevice::new(config, device_id.clone())?;
+        let device = Arc::new(Mutex::new(device));
+        
+        let mut devices = self.devices.lock().await;
+        devices.insert(device_id.clone(), device);
+        
+        Ok(device_id)
+    }
+    
+    /// 获取虚拟设备
+    pub async fn get_device(
+        &self, 
+        device_id: &str
+    ) -> Result<Arc<Mutex<VirtualDevice>>, &'static str> {
+        let devices = self.devices.lock().await;
+        devices.get(device_id)
+            .cloned()
+            .ok_or("Device not found")
+    }
+    
+    /// 启动虚拟设备
+    pub async fn start_device(
+        &self, 
+        device_id: &str
+    ) -> Result<(), &'static str> {
+        let device = self.get_device(device_id).await?;
+        let mut device = device.lock().await;
+        device.start().await
+    }
+    
+    /// 停止虚拟设备
+    pub async fn stop_device(
+        &self, 
+        device_id: &str
+    ) -> Result<(), &'static str> {
+        let device = self.get_device(device_id).await?;
+        let mut device = device.lock().await;
+        device.stop().await
+    }
+    
+    /// 删除虚拟设备
+    pub async fn delete_device(
+        &self, 
+        device_id: &str
+    ) -> Result<(), &'static str> {
+        let mut devices = self.devices.lock().await;
+        devices.remove(device_id);
+        Ok(())
+    }
+    
+    /// 获取所有设备
+    pub async fn get_all_devices(
+        &self
+    ) -> HashMap<String, Arc<Mutex<VirtualDevice>>> {
+        let devices = self.devices.lock().await;
+        devices.clone()
+    }
+    
+    /// 获取设备状态
+    pub async fn get_device_status(
+        &self, 
+        device_id: &str
+    ) -> Result<DeviceStatus, &'static str> {
+        let device = self.get_device(device_id).await?;
+        let device = device.lock().await;
+        Ok(device.get_status().await)
+    }
+}
+
+/// 默认虚拟设备配置
+pub fn default_config(name: String, ip: Ipv4Addr) -> VirtualDeviceConfig {
+    VirtualDeviceConfig {
+        name,
+        ip,
+        subnet: Ipv4Addr::new(255, 255, 255, 0),
+        gateway: Ipv4Addr::new(10, 0, 0, 1),
+        mtu: 1420,
+        mac: None,
+        enable_ipv6: false,
+        ipv6_prefix: None,
+        device_mode: DeviceMode::Tun,
+        max_restart_attempts: 3,
+        restart_cooldown: Duration::from_secs(10),
+        transforms: Vec::new(),
+        flush_timeout: Duration::from_secs(2),
+        routes: Vec::new(),
+    }
+}
+
+/// 检查指定名称的网卡是否存在
+pub fn interface_exists(name: &str) -> bool {
+    datalink::interfaces()
+        .iter()
+        .any(|iface| iface.name == name)
+}
+
+/// 生成随机MAC地址，随机部分取自操作系统熵源
+pub fn generate_random_mac() -> [u8; 6] {
+    let mut mac = [0u8; 6];
+    mac[0] = 0x02; // 本地管理地址
+    SecureRng::new().fill_bytes(&mut mac[1..]);
+    mac
+}
+
+/// 按EUI-64由MAC地址生成IPv6链路本地地址（RFC 4291附录A、RFC 4862）
+///
+/// 在MAC地址中间插入`ff:fe`并翻转全局/本地位，作为`fe80::/64`的接口标识，
+/// 例如`00:00:5e:00:53:01`生成`fe80::200:5eff:fe00:5301`。
+pub fn generate_ipv6_link_local(mac: [u8; 6]) -> Ipv6Addr {
+    let interface_id = [
+        mac[0] ^ 0x02, mac[1], mac[2], 0xff,
+        0xfe, mac[3], mac[4], mac[5],
+    ];
+    
+    let mut octets = [0u8; 16];
+    octets[0] = 0xfe;
+    octets[1] = 0x80;
+    octets[8..].copy_from_slice(&interface_id);
+    Ipv6Addr::from(octets)
+}
+
+/// 解析以太网数据包
+pub fn parse_ethernet_packet(data: &[u8]) -> Option<EthernetPacket> {
+    EthernetPacket::new(data)
+}
+
+/// 获取帧的协议类型（EtherType）
+///
+/// TAP模式下直接读取以太网帧头中的EtherType；
+/// TUN模式下根据IP版本号推断。无法识别时返回None。
+pub fn frame_protocol(mode: DeviceMode, frame: &[u8]) -> Option<u16> {
+    match mode {
+        DeviceMode::Tap => parse_ethernet_packet(frame)
+            .map(|eth| eth.get_ethertype().0),
+        DeviceMode::Tun => match frame.first().map(|b| b >> 4) {
+            Some(4) => Some(EtherTypes::Ipv4.0),
+            Some(6) => Some(EtherTypes::Ipv6.0),
+            _ => None,
+        },
+    }
+}
+
+/// 获取帧中的IP数据包部分
+///
+/// TAP模式下跳过以太网帧头，TUN模式下原样返回。
+pub fn frame_payload(mode: DeviceMode, frame: &[u8]) -> Option<&[u8]> {
+    match mode {
+        DeviceMode::Tap => frame.get(ETHERNET_HEADER_LEN..),
+        DeviceMode::Tun => Some(frame),
+    }
+}
+
+/// 判断帧是否为发往IPv6组播地址（ff00::/8）的数据包
+pub fn is_ipv6_multicast(mode: DeviceMode, frame: &[u8]) -> bool {
+    frame_protocol(mode, frame) == Some(EtherTypes::Ipv6.0)
+        && frame_payload(mode, frame)
+            .and_then(Ipv6Packet::new)
+            .is_some_and(|packet| packet.get_destination().is_multicast())
+}
+
+/// 解析IPv4数据包
+pub fn parse_ipv4_packet(data: &[u8]) -> Option<Ipv4Packet> {
+    Ipv4Packet::new(data)
+}
+
+/// 解析TCP数据包
+pub fn parse_tcp_packet(data: &[u8]) -> Option<TcpPacket> {
+    TcpPacket::new(data)
+}
+
+/// 解析UDP数据包
+pub fn parse_udp_packet(data: &[u8]) -> Option<UdpPacket> {
+    UdpPacket::new(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    
+    fn test_device(name: &str) -> Arc<Mutex<VirtualDevice>> {
+        let mut config = default_config(name.to_string(), Ipv4Addr::new(10, 0, 0, 2));
+        config.restart_cooldown = Duration::from_secs(1);
+        let mut device = VirtualDevice::new(config, "device_0".to_string()).unwrap();
+        device.create_interface = |_, _| Err("Interface creation disabled in tests");
+        Arc::new(Mutex::new(device))
+    }
+    
+    /// 模拟设备已经绑定到网卡
+    fn bind_interface(device: &mut VirtualDevice) {
+        device.is_running = true;
+        device.interface = Some(NetworkInterface {
+            name: device.config.name.clone(),
+            description: String::new(),
+            index: 0,
+            mac: None,
+            ips: Vec::new(),
+            flags: 0,
+        });
+    }
+    
+    fn watcher(device: &Arc<Mutex<VirtualDevice>>, interface_exists: fn(&str) -> bool) -> DeviceWatcher {
+        DeviceWatcher {
+            interface_exists,
+            ..DeviceWatcher::new(device.clone()).with_poll_interval(Duration::from_secs(5))
+        }
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn watcher_ignores_device_without_interface() {
+        let device = test_device("vpnet-watch0");
+        device.lock().await.start().await.unwrap();
+        assert!(device.lock().await.interface.is_none());
+        
+        let handle = watcher(&device, |_| false).spawn();
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(device.lock().await.get_status().await, DeviceStatus::Up);
+        handle.abort();
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn watcher_restarts_deleted_interface() {
+        static PROBES: AtomicU32 = AtomicU32::new(0);
+        let device = test_device("vpnet-watch1");
+        bind_interface(&mut *device.lock().await);
+        
+        // 第一次检查时网卡已被删除，重启后恢复
+        let handle = watcher(&device, |_| PROBES.fetch_add(1, Ordering::SeqCst) > 0).spawn();
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        
+        assert_eq!(PROBES.load(Ordering::SeqCst), 2);
+        let device = device.lock().await;
+        assert_eq!(device.get_status().await, DeviceStatus::Up);
+        // 重启时重新查找了网卡
+        assert!(device.interface.is_none());
+        handle.abort();
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn watcher_marks_error_after_restart_attempts() {
+        let device = test_device("vpnet-watch2");
+        bind_interface(&mut *device.lock().await);
+        let mut events = device.lock().await.subscribe();
+        
+        watcher(&device, |_| false).spawn().await.unwrap();
+        
+        assert_eq!(device.lock().await.get_status().await, DeviceStatus::Error("interface lost".to_string()));
+        assert!(matches!(events.recv().await, Ok(DeviceEvent::DeviceError { reason, .. }) if reason == "interface lost"));
+    }
+    
+    
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn creates_interface_in_configured_mode() {
+        let commands = create_interface_commands("vpnet0", DeviceMode::Tap);
+        assert_eq!(commands[0].join(" "), "ip tuntap add dev vpnet0 mode tap");
+        assert_eq!(commands[1].join(" "), "ip link set vpnet0 up");
+        assert!(create_interface_commands("vpnet0", DeviceMode::Tun)[0].ends_with(&["tun".to_string()]));
+    }
+    
+    #[test]
+    fn reads_protocol_and_payload_by_mode() {
+        let mut frame = vec![0xff; 12];
+        frame.extend_from_slice(&[0x08, 0x00]);
+        frame.extend_from_slice(&[0x45, 0, 0, 20]);
+        assert_eq!(frame_protocol(DeviceMode::Tap, &frame), Some(0x0800));
+        assert_eq!(frame_payload(DeviceMode::Tap, &frame), Some(&frame[ETHERNET_HEADER_LEN..]));
+        
+        let packet = &frame[ETHERNET_HEADER_LEN..];
+        assert_eq!(frame_protocol(DeviceMode::Tun, packet), Some(0x0800));
+        assert_eq!(frame_payload(DeviceMode::Tun, packet), Some(packet));
+        assert_eq!(frame_protocol(DeviceMode::Tun, &[0x00]), None);
+        assert_eq!(frame_protocol(DeviceMode::Tap, &frame[..10]), None);
+    }
+    
+    
+    /// 记录写入虚拟网卡的数据包
+    struct RecordingSender(Arc<std::sync::Mutex<Vec<Vec<u8>>>>);
+    
+    impl datalink::DataLinkSender for RecordingSender {
+        fn build_and_send(
+            &mut self,
+            _num_packets: usize,
+            _packet_size: usize,
+            _func: &mut dyn FnMut(&mut [u8])
+        ) -> Option<io::Result<()>> {
+            None
+        }
+        
+        fn send_to(&mut self, packet: &[u8], _dst: Option<NetworkInterface>) -> Option<io::Result<()>> {
+            self.0.lock().unwrap().push(packet.to_vec());
+            Some(Ok(()))
+        }
+    }
+    
+    /// 已打开的设备，写入虚拟网卡的数据包记录在返回的列表中
+    async fn recording_device(name: &str) -> (VirtualDevice, Arc<std::sync::Mutex<Vec<Vec<u8>>>>) {
+        recording_device_with(default_config(name.to_string(), Ipv4Addr::new(10, 0, 0, 2))).await
+    }
+    
+    async fn recording_device_with(config: VirtualDeviceConfig) -> (VirtualDevice, Arc<std::sync::Mutex<Vec<Vec<u8>>>>) {
+        let mut device = VirtualDevice::new(config, "device_0".to_string()).unwrap();
+        let written = Arc::new(std::sync::Mutex::new(Vec::new()));
+        device.send_channel = Some(Arc::new(Mutex::new(RecordingSender(written.clone()))));
+        device.is_running = true;
+        device.start_data_transfer().await;
+        (device, written)
+    }
+    
+    #[tokio::test]
+    async fn stop_writes_all_queued_packets() {
+        let (mut device, written) = recording_device("vpnet-flush0").await;
+        let sender = device.get_packet_sender();
+        for i in 0..100u8 {
+            sender.send(vec![0x45, i]).await.unwrap();
+        }
+        
+        device.stop().await.unwrap();
+        let written = written.lock().unwrap();
+        assert_eq!(written.len(), 100);
+        assert!(written.iter().enumerate().all(|(i, packet)| packet[1] == i as u8));
+    }
+    
+    #[tokio::test]
+    async fn writer_waits_for_lock_without_spinning() {
+        use tokio::io::AsyncWriteExt;
+        
+        let (device, written) = recording_device("vpnet-write0").await;
+        let mut writer = device.writer().unwrap();
+        let guard = device.send_channel.clone().unwrap().lock_owned().await;
+        
+        let write = tokio::spawn(async move { writer.write_all(&[0x45, 1]).await });
+        tokio::task::yield_now().await;
+        assert!(!write.is_finished());
+        assert!(written.lock().unwrap().is_empty());
+        
+        drop(guard);
+        write.await.unwrap().unwrap();
+        assert_eq!(*written.lock().unwrap(), vec![vec![0x45, 1]]);
+    }
+    
+    thread_local! {
+        static ROUTE_COMMANDS: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+    
+    /// 记录路由命令，网关为`10.0.0.99`的命令失败
+    fn record_route_command(cmd: &[String], error: &'static str) -> Result<(), &'static str> {
+        ROUTE_COMMANDS.with(|commands| commands.borrow_mut().push(cmd.join(" ")));
+        if cmd.iter().any(|arg| arg == "10.0.0.99") {
+            return Err(error);
+        }
+        Ok(())
+    }
+    
+    fn static_route(network: &str, gateway: &str) -> StaticRoute {
+        StaticRoute {
+            network: network.to_string(),
+            prefix_len: 16,
+            gateway: gateway.to_string(),
+            metric: 10,
+        }
+    }
+    
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn route_manager_adds_and_removes_routes_in_reverse_order() {
+        ROUTE_COMMANDS.with(|commands| commands.borrow_mut().clear());
+        let mut manager = RouteManager { run_command: record_route_command, ..RouteManager::default() };
+        manager.add("vpnet0", &static_route("10.20.0.0", "10.0.0.1")).unwrap();
+        manager.add("vpnet0", &static_route("10.30.0.0", "10.0.0.1")).unwrap();
+        assert_eq!(manager.applied().len(), 2);
+        
+        manager.remove_all("vpnet0");
+        assert!(manager.applied().is_empty());
+        assert_eq!(ROUTE_COMMANDS.with(|commands| commands.take()), vec![
+            "ip route add 10.20.0.0/16 via 10.0.0.1 dev vpnet0 metric 10",
+            "ip route add 10.30.0.0/16 via 10.0.0.1 dev vpnet0 metric 10",
+            "ip route del 10.30.0.0/16 via 10.0.0.1 dev vpnet0 metric 10",
+            "ip route del 10.20.0.0/16 via 10.0.0.1 dev vpnet0 metric 10",
+        ]);
+    }
+    
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn failed_start_rolls_back_applied_routes() {
+        ROUTE_COMMANDS.with(|commands| commands.borrow_mut().clear());
+        let mut config = default_config("lo".to_string(), Ipv4Addr::new(10, 0, 0, 2));
+        config.routes = vec![static_route("10.20.0.0", "10.0.0.1"), static_route("10.30.0.0", "10.0.0.99")];
+        let mut device = VirtualDevice::new(config, "device_0".to_string()).unwrap();
+        device.route_manager.run_command = record_route_command;
+        
+        assert!(device.start().await.is_err());
+        assert!(!device.is_running);
+        assert!(device.route_manager.applied().is_empty());
+        let commands = ROUTE_COMMANDS.with(|commands| commands.take());
+        assert_eq!(commands.last().unwrap(), "ip route del 10.20.0.0/16 via 10.0.0.1 dev lo metric 10");
+    }
+    
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn address_commands_only_flush_ipv4_addresses() {
+        let commands = address_commands("vpnet0", Ipv4Addr::new(10, 0, 0, 9), Ipv4Addr::new(255, 255, 255, 0), 24);
+        assert_eq!(commands[0], ["ip", "-4", "addr", "flush", "dev", "vpnet0"]);
+        assert_eq!(commands[1], ["ip", "addr", "add", "10.0.0.9/24", "dev", "vpnet0"]);
+    }
+    
+    /// 10.0.0.2:40000发往10.0.0.9:53的UDP数据包，带`payload_len`字节负载
+    fn udp_datagram(payload_len: usize) -> Vec<u8> {
+        let mut packet = vec![0u8; 28 + payload_len];
+        let mut ip = MutableIpv4Packet::new(&mut packet).unwrap();
+        ip.set_version(4);
+        ip.set_header_length(5);
+        ip.set_total_length((28 + payload_len) as u16);
+        ip.set_ttl(64);
+        ip.set_next_level_protocol(IpNextHeaderProtocols::Udp);
+        ip.set_source(Ipv4Addr::new(10, 0, 0, 2));
+        ip.set_destination(Ipv4Addr::new(10, 0, 0, 9));
+        let checksum = pnet::packet::ipv4::checksum(&ip.to_immutable());
+        ip.set_checksum(checksum);
+        packet[20..22].copy_from_slice(&40000u16.to_be_bytes());
+        packet[22..24].copy_from_slice(&53u16.to_be_bytes());
+        packet[24..26].copy_from_slice(&((8 + payload_len) as u16).to_be_bytes());
+        for (i, byte) in packet[28..].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        packet
+    }
+    
+    /// 检查`reply`是发回源地址、校验和正确的ICMP目标不可达报文，返回其中引用的原数据包
+    fn parse_unreachable(reply: &[u8], code: IcmpUnreachCode) -> Vec<u8> {
+        use pnet::packet::icmp::{self, IcmpPacket, IcmpTypes};
+        use pnet::packet::icmp::destination_unreachable::DestinationUnreachablePacket;
+        
+        let ip = Ipv4Packet::new(reply).unwrap();
+        assert_eq!(ip.get_version(), 4);
+        assert_eq!(ip.get_total_length() as usize, reply.len());
+        assert_eq!(ip.get_next_level_protocol(), IpNextHeaderProtocols::Icmp);
+        assert_eq!(ip.get_source(), Ipv4Addr::new(10, 0, 0, 9));
+        assert_eq!(ip.get_destination(), Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(ip.get_checksum(), pnet::packet::ipv4::checksum(&ip));
+        
+        let icmp = IcmpPacket::new(ip.payload()).unwrap();
+        assert_eq!(icmp.get_icmp_type(), IcmpTypes::DestinationUnreachable);
+        assert_eq!(icmp.get_icmp_code().0, code as u8);
+        assert_eq!(icmp.get_checksum(), icmp::checksum(&icmp));
+        DestinationUnreachablePacket::new(ip.payload()).unwrap().payload().to_vec()
+    }
+    
+    #[test]
+    fn reject_quotes_original_header_and_eight_payload_bytes() {
+        let original = udp_datagram(20);
+        for code in [IcmpUnreachCode::PortUnreachable, IcmpUnreachCode::AdminProhibited, IcmpUnreachCode::HostUnreachable] {
+            let reply = IcmpErrorGenerator::generate_reject(&original, code).unwrap();
+            assert_eq!(parse_unreachable(&reply, code), original[..28]);
+        }
+    }
+    
+    #[test]
+    fn reject_quotes_short_payload_entirely() {
+        // UDP头部只剩3字节
+        let mut original = udp_datagram(0)[..23].to_vec();
+        original[2..4].copy_from_slice(&23u16.to_be_bytes());
+        let reply = IcmpErrorGenerator::generate_reject(&original, IcmpUnreachCode::PortUnreachable).unwrap();
+        assert_eq!(parse_unreachable(&reply, IcmpUnreachCode::PortUnreachable), original);
+    }
+    
+    #[test]
+    fn reject_is_not_generated_for_invalid_or_exempt_packets() {
+        let code = IcmpUnreachCode::AdminProhibited;
+        // 不足一个IP头部
+        assert!(IcmpErrorGenerator::generate_reject(&udp_datagram(0)[..12], code).is_none());
+        assert!(IcmpErrorGenerator::generate_reject(&[], code).is_none());
+        
+        // 非首个分片
+        let mut fragment = udp_datagram(8);
+        fragment[6..8].copy_from_slice(&1u16.to_be_bytes());
+        assert!(IcmpErrorGenerator::generate_reject(&fragment, code).is_none());
+        
+        // 广播源地址
+        let mut broadcast = udp_datagram(8);
+        broadcast[12..16].copy_from_slice(&[255, 255, 255, 255]);
+        assert!(IcmpErrorGenerator::generate_reject(&broadcast, code).is_none());
+        
+        // ICMP差错报文本身
+        let error = IcmpErrorGenerator::generate_reject(&udp_datagram(8), code).unwrap();
+        assert!(IcmpErrorGenerator::generate_reject(&error, code).is_none());
+    }
+    
+    #[test]
+    fn reject_in_tap_mode_swaps_ethernet_addresses() {
+        let mut frame = vec![0x02, 0, 0, 0, 0, 0x01, 0x02, 0, 0, 0, 0, 0x02];
+        frame.extend_from_slice(&EtherTypes::Ipv4.0.to_be_bytes());
+        frame.extend_from_slice(&udp_datagram(20));
+        
+        let reply = IcmpErrorGenerator::generate_reject(&frame, IcmpUnreachCode::PortUnreachable).unwrap();
+        let ethernet = EthernetPacket::new(&reply).unwrap();
+        assert_eq!(ethernet.get_destination().octets(), [0x02, 0, 0, 0, 0, 0x02]);
+        assert_eq!(ethernet.get_source().octets(), [0x02, 0, 0, 0, 0, 0x01]);
+        assert_eq!(ethernet.get_ethertype(), EtherTypes::Ipv4);
+        assert_eq!(parse_unreachable(ethernet.payload(), IcmpUnreachCode::PortUnreachable), frame[14..42]);
+    }
+    
+    #[test]
+    fn rejected_packet_error_is_injected_towards_source() {
+        let (tx, mut rx) = mpsc::channel(1);
+        send_reject(&tx, &udp_datagram(20), IcmpUnreachCode::AdminProhibited);
+        let reply = rx.try_recv().unwrap();
+        parse_unreachable(&reply, IcmpUnreachCode::AdminProhibited);
+        
+        send_reject(&tx, &udp_datagram(20)[..10], IcmpUnreachCode::AdminProhibited);
+        assert!(rx.try_recv().is_err());
+    }
+    
+    /// 发布`fd00:1234::/64`的TUN设备
+    async fn advertising_device(name: &str) -> (VirtualDevice, Arc<std::sync::Mutex<Vec<Vec<u8>>>>) {
+        let mut config = default_config(name.to_string(), Ipv4Addr::new(10, 0, 0, 2));
+        config.enable_ipv6 = true;
+        config.ipv6_prefix = Some("fd00:1234::".parse().unwrap());
+        recording_device_with(config).await
+    }
+    
+    /// TUN模式下的ICMPv6路由器请求
+    fn router_solicitation(source: Ipv6Addr) -> Vec<u8> {
+        let mut packet = vec![0u8; MutableIpv6Packet::minimum_packet_size() + 8];
+        let mut ip = MutableIpv6Packet::new(&mut packet).unwrap();
+        ip.set_version(6);
+        ip.set_payload_length(8);
+        ip.set_next_header(IpNextHeaderProtocols::Icmpv6);
+        ip.set_hop_limit(255);
+        ip.set_source(source);
+        ip.set_destination(Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 2));
+        ip.set_payload(&[Icmpv6Types::RouterSolicit.0, 0, 0, 0, 0, 0, 0, 0]);
+        packet
+    }
+    
+    /// 数据包是路由器通告时返回目的地址
+    fn advertisement_destination(packet: &[u8]) -> Option<Ipv6Addr> {
+        let ip = Ipv6Packet::new(packet)?;
+        let icmp = Icmpv6Packet::new(ip.payload())?;
+        (icmp.get_icmpv6_type() == Icmpv6Types::RouterAdvert).then(|| ip.get_destination())
+    }
+    
+    #[tokio::test]
+    async fn local_router_solicitation_is_answered_into_the_device() {
+        let (mut device, written) = advertising_device("vpnet-ra0").await;
+        let source: Ipv6Addr = "fe80::2".parse().unwrap();
+        device.inject_packet(router_solicitation(source));
+        device.inject_packet(vec![0x45, 1]);
+        
+        // 请求不发往网络
+        assert_eq!(device.recv().await.unwrap(), vec![0x45, 1]);
+        device.stop().await.unwrap();
+        let written = written.lock().unwrap();
+        assert_eq!(written.len(), 1);
+        assert_eq!(advertisement_destination(&written[0]), Some(source));
+    }
+    
+    #[tokio::test]
+    async fn remote_router_solicitation_is_answered_over_the_tunnel() {
+        let (mut device, written) = advertising_device("vpnet-ra1").await;
+        device.get_packet_sender().send(router_solicitation("fe80::3".parse().unwrap())).await.unwrap();
+        
+        let reply = tokio::time::timeout(Duration::from_secs(5), device.recv()).await.unwrap().unwrap();
+        assert_eq!(advertisement_destination(&reply), Some(RouterAdvertisementSender::ALL_NODES));
+        assert!(is_ipv6_multicast(DeviceMode::Tun, &reply));
+        device.stop().await.unwrap();
+        assert!(written.lock().unwrap().is_empty());
+    }
+    
+    #[tokio::test]
+    async fn periodic_advertisements_are_read_for_the_network() {
+        let (mut device, _written) = advertising_device("vpnet-ra2").await;
+        let task = device.router_advertiser.clone().unwrap().spawn(device.packet_tx.clone());
+        
+        let advertisement = tokio::time::timeout(Duration::from_secs(5), device.recv()).await.unwrap().unwrap();
+        assert_eq!(advertisement_destination(&advertisement), Some(RouterAdvertisementSender::ALL_NODES));
+        task.abort();
+    }
+    
+    #[test]
+    fn ipv6_multicast_detection() {
+        let solicitation = router_solicitation(Ipv6Addr::UNSPECIFIED);
+        assert!(is_ipv6_multicast(DeviceMode::Tun, &solicitation));
+        assert!(!is_ipv6_multicast(DeviceMode::Tun, &[0x45, 0]));
+        
+        let mut unicast = solicitation.clone();
+        unicast[24..40].copy_from_slice(&"fd00:1234::1".parse::<Ipv6Addr>().unwrap().octets());
+        assert!(!is_ipv6_multicast(DeviceMode::Tun, &unicast));
+        
+        let mut frame = vec![0x33, 0x33, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 0x02, 0x86, 0xdd];
+        frame.extend_from_slice(&solicitation);
+        assert!(is_ipv6_multicast(DeviceMode::Tap, &frame));
+    }
+}
@@ -0,0 +1,111 @@
+/*!
+VPNet CLI 服务端API客户端
+
+向服务端管理API发送请求，服务端返回的错误信息原样报告。
+*/
+
+use reqwest::{Method, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+/// API错误
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("Invalid server API url: {0}")]
+    InvalidUrl(String),
+    
+    #[error("Server API request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    
+    #[error("{message} ({status})")]
+    Upstream { status: StatusCode, message: String },
+}
+
+impl ApiError {
+    /// 服务端是否因资源已存在或冲突而拒绝请求
+    pub fn is_conflict(&self) -> bool {
+        matches!(self, ApiError::Upstream { status: StatusCode::CONFLICT, .. })
+    }
+}
+
+/// 服务端管理API客户端
+pub struct ApiClient {
+    base_url: Url,
+    client: reqwest::Client,
+    token: Option<String>,
+}
+
+impl ApiClient {
+    /// 创建API客户端，`base_url`为服务端管理API的地址，例如`http://127.0.0.1:51821`，
+    /// `token`为服务端配置的管理令牌
+    pub fn new(base_url: &str, token: Option<String>) -> Result<Self, ApiError> {
+        let url = Url::parse(base_url).map_err(|_| ApiError::InvalidUrl(base_url.to_string()))?;
+        if url.cannot_be_a_base() {
+            return Err(ApiError::InvalidUrl(base_url.to_string()));
+        }
+        
+        Ok(Self {
+            base_url: url,
+            client: reqwest::Client::new(),
+            token,
+        })
+    }
+    
+    /// 发送GET请求
+    pub async fn get<T: DeserializeOwned>(&self, segments: &[&str]) -> Result<T, ApiError> {
+        self.request(Method::GET, segments, &[], None::<&()>).await
+    }
+    
+    /// 发送带查询参数的GET请求
+    pub async fn get_with_query<T: DeserializeOwned>(
+        &self,
+        segments: &[&str],
+        query: &[(&str, &str)]
+    ) -> Result<T, ApiError> {
+        self.request(Method::GET, segments, query, None::<&()>).await
+    }
+    
+    /// 发送POST请求
+    pub async fn post<B: Serialize, T: DeserializeOwned>(&self, segments: &[&str], body: &B) -> Result<T, ApiError> {
+        self.request(Method::POST, segments, &[], Some(body)).await
+    }
+    
+    async fn request<B: Serialize, T: DeserializeOwned>(
+        &self,
+        method: Method,
+        segments: &[&str],
+        query: &[(&str, &str)],
+        body: Option<&B>
+    ) -> Result<T, ApiError> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| ApiError::InvalidUrl(self.base_url.to_string()))?
+            .pop_if_empty()
+            .push("api")
+            .extend(segments);
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        
+        let mut request = self.client.request(method, url);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.bytes().await?;
+            let message = serde_json::from_slice::<serde_json::Value>(&body).ok()
+                .and_then(|value| value.get("error")?.as_str().map(str::to_string))
+                .unwrap_or_else(|| status.canonical_reason().unwrap_or("server API request failed").to_string());
+            return Err(ApiError::Upstream { status, message });
+        }
+        
+        Ok(response.json().await?)
+    }
+}
@@ -0,0 +1,25 @@
+/*!
+VPNet CLI 网络诊断
+
+通过服务端管理API执行覆盖网络层面的诊断，包括：
+- 追踪服务端到指定虚拟IP的路径，逐跳列出节点和往返时延
+*/
+
+use std::net::Ipv4Addr;
+use vpnet::HopInfo;
+use crate::client::{ApiClient, ApiError};
+
+/// 追踪到达虚拟IP的路径，并以表格打印每一跳
+pub async fn traceroute(client: &ApiClient, dst: Ipv4Addr) -> Result<Vec<HopInfo>, ApiError> {
+    let dst = dst.to_string();
+    let hops: Vec<HopInfo> = client.get_with_query(&["diagnostics", "traceroute"], &[("dst", &dst)]).await?;
+
+    println!("traceroute to {}, {} hops", dst, hops.len());
+    for hop in &hops {
+        match &hop.node_id {
+            Some(node_id) => println!("{:>3}  {:<15}  {}  {:.3} ms", hop.hop, hop.virtual_ip, node_id, hop.rtt_ms),
+            None => println!("{:>3}  *", hop.hop),
+        }
+    }
+    Ok(hops)
+}
@@ -0,0 +1,105 @@
+/*!
+VPNet CLI - 服务端管理命令行工具
+
+通过服务端管理API执行运维操作，包括：
+- 列出节点及其状态
+- 导出节点注册和策略路由
+- 将导出文件导入到新的服务端
+- 追踪到指定虚拟IP的覆盖网络路径
+*/
+
+use std::net::Ipv4Addr;
+use clap::{Parser, Subcommand};
+
+mod client;
+mod diagnostics;
+mod node;
+
+use client::ApiClient;
+
+/// 命令行参数
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// 服务端管理API地址
+    #[arg(long, env = "VPNET_API_URL", default_value = "http://127.0.0.1:51821")]
+    api_url: String,
+    
+    /// 服务端管理令牌，对应服务端配置的`api.admin_token`
+    #[arg(long, env = "VPNET_API_TOKEN")]
+    api_token: Option<String>,
+    
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 节点管理
+    Node {
+        #[command(subcommand)]
+        command: NodeCommand,
+    },
+    /// 追踪服务端到指定虚拟IP的覆盖网络路径
+    Traceroute {
+        /// 目标虚拟IP
+        dst: Ipv4Addr,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum NodeCommand {
+    /// 列出节点及其状态
+    List,
+    /// 导出节点（含公钥和分组）及策略路由
+    Export {
+        /// 输出文件，未指定时写到标准输出
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// 从导出文件导入节点和策略路由，已存在的条目跳过
+    Import {
+        /// 导出文件
+        #[arg(short, long)]
+        file: String,
+        
+        /// 只打印将要导入的内容，不修改服务端
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        dry_run: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let client = ApiClient::new(&args.api_url, args.api_token)?;
+    
+    match args.command {
+        Command::Node { command: NodeCommand::List } => {
+            let nodes = node::list(&client).await?;
+            println!("{} nodes", nodes.len());
+        }
+        Command::Node { command: NodeCommand::Export { output } } => {
+            let export = node::export(&client, output.as_deref()).await?;
+            if let Some(output) = output {
+                println!("Exported {} nodes and {} routes to {}", export.nodes.len(), export.routes.len(), output);
+            }
+        }
+        Command::Node { command: NodeCommand::Import { file, dry_run } } => {
+            let summary = node::import(&client, &file, dry_run).await?;
+            println!(
+                "{} {} nodes ({} skipped) and {} routes ({} skipped)",
+                if dry_run { "Would import" } else { "Imported" },
+                summary.nodes_imported,
+                summary.nodes_skipped,
+                summary.routes_imported,
+                summary.routes_skipped
+            );
+        }
+        Command::Traceroute { dst } => {
+            diagnostics::traceroute(&client, dst).await?;
+        }
+    }
+    
+    Ok(())
+}
@@ -0,0 +1,333 @@
+/*!
+VPNet CLI 节点管理
+
+查看节点，并在服务端之间迁移节点注册和策略路由，包括：
+- 以表格列出节点及其状态
+- 导出节点（含公钥和分组）及策略路由到JSON文件
+- 从导出文件导入，跳过已存在或冲突的条目
+*/
+
+use std::collections::HashSet;
+use std::fs;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use vpnet::NodeStatus;
+use crate::client::{ApiClient, ApiError};
+
+/// 导出文件格式版本
+const EXPORT_VERSION: u32 = 1;
+
+/// 导出文件
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NodeExport {
+    pub version: u32,
+    pub nodes: Vec<ExportedNode>,
+    /// 策略路由规则，按服务端API的格式原样保存
+    pub routes: Vec<Value>,
+}
+
+/// 导出的节点
+///
+/// 包含公钥，导入后节点握手时公钥与目录一致，无需重新认证。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedNode {
+    pub id: String,
+    pub name: String,
+    pub address: String,
+    pub virtual_ip: String,
+    pub public_key: String,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub priority: u8,
+}
+
+/// 节点列表中的节点
+#[derive(Debug, Clone, Deserialize)]
+pub struct NodeSummary {
+    pub id: String,
+    pub name: String,
+    pub virtual_ip: String,
+    #[serde(with = "vpnet::protocol::lowercase_name")]
+    pub status: NodeStatus,
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+/// 导入结果
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub nodes_imported: usize,
+    pub nodes_skipped: usize,
+    pub routes_imported: usize,
+    pub routes_skipped: usize,
+}
+
+/// 导入导出错误
+#[derive(thiserror::Error, Debug)]
+pub enum TransferError {
+    #[error(transparent)]
+    Api(#[from] ApiError),
+    
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    
+    #[error("Invalid export file: {0}")]
+    Json(#[from] serde_json::Error),
+    
+    #[error("Unsupported export file version {0}")]
+    UnsupportedVersion(u32),
+}
+
+/// 以表格列出服务端的节点，按ID排序
+pub async fn list(client: &ApiClient) -> Result<Vec<NodeSummary>, ApiError> {
+    let mut nodes: Vec<NodeSummary> = client.get(&["nodes"]).await?;
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    
+    let id_width = column_width("ID", nodes.iter().map(|node| node.id.as_str()));
+    let name_width = column_width("NAME", nodes.iter().map(|node| node.name.as_str()));
+    let ip_width = column_width("VIRTUAL IP", nodes.iter().map(|node| node.virtual_ip.as_str()));
+    println!("{:<id_width$}  {:<name_width$}  {:<ip_width$}  {:<17}  GROUP", "ID", "NAME", "VIRTUAL IP", "STATUS");
+    for node in &nodes {
+        println!(
+            "{:<id_width$}  {:<name_width$}  {:<ip_width$}  {:<17}  {}",
+            node.id,
+            node.name,
+            node.virtual_ip,
+            node.status,
+            node.group.as_deref().unwrap_or("-")
+        );
+    }
+    Ok(nodes)
+}
+
+/// 表格列宽，取表头和所有值中最长者
+fn column_width<'a>(header: &str, values: impl Iterator<Item = &'a str>) -> usize {
+    values.map(|value| value.chars().count()).fold(header.len(), usize::max)
+}
+
+/// 从服务端导出节点和策略路由，`output`为空时写到标准输出
+pub async fn export(client: &ApiClient, output: Option<&str>) -> Result<NodeExport, TransferError> {
+    let mut nodes: Vec<ExportedNode> = client.get(&["nodes"]).await?;
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    let routes: Vec<Value> = client.get(&["routes", "policy"]).await?;
+    
+    let export = NodeExport {
+        version: EXPORT_VERSION,
+        nodes,
+        routes,
+    };
+    
+    let json = serde_json::to_string_pretty(&export)?;
+    match output {
+        Some(path) => fs::write(path, json)?,
+        None => println!("{}", json),
+    }
+    Ok(export)
+}
+
+/// 将导出文件导入服务端
+///
+/// 服务端已有的节点和同优先级的路由规则跳过，服务端报告冲突的条目也跳过。
+/// `dry_run`时只打印将要导入的内容，不修改服务端。
+pub async fn import(client: &ApiClient, file: &str, dry_run: bool) -> Result<ImportSummary, TransferError> {
+    let export: NodeExport = serde_json::from_str(&fs::read_to_string(file)?)?;
+    if export.version != EXPORT_VERSION {
+        return Err(TransferError::UnsupportedVersion(export.version));
+    }
+    
+    let existing_nodes: Vec<ExportedNode> = client.get(&["nodes"]).await?;
+    let existing_nodes: HashSet<String> = existing_nodes.into_iter().map(|node| node.id).collect();
+    let existing_routes: Vec<Value> = client.get(&["routes", "policy"]).await?;
+    let existing_priorities: HashSet<u64> = existing_routes.iter().filter_map(route_priority).collect();
+    
+    let mut summary = ImportSummary::default();
+    
+    for node in &export.nodes {
+        if existing_nodes.contains(&node.id) {
+            println!("skip node {} ({}): already exists", node.id, node.virtual_ip);
+            summary.nodes_skipped += 1;
+            continue;
+        }
+        if dry_run {
+            println!("would import node {} ({}) as {}", node.id, node.name, node.virtual_ip);
+            summary.nodes_imported += 1;
+            continue;
+        }
+        
+        let body = serde_json::json!({
+            "id": node.id,
+            "name": node.name,
+            "address": node.address,
+            "virtual_ip": node.virtual_ip,
+            "public_key": node.public_key,
+            "group": node.group,
+            "priority": node.priority,
+        });
+        match client.post::<_, Value>(&["nodes"], &body).await {
+            Ok(_) => {
+                println!("imported node {} ({})", node.id, node.virtual_ip);
+                summary.nodes_imported += 1;
+            }
+            Err(e) if e.is_conflict() => {
+                println!("skip node {}: {}", node.id, e);
+                summary.nodes_skipped += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    
+    for route in &export.routes {
+        let priority = route_priority(route);
+        if priority.is_some_and(|priority| existing_priorities.contains(&priority)) {
+            println!("skip route {}: priority already in use", route);
+            summary.routes_skipped += 1;
+            continue;
+        }
+        if dry_run {
+            println!("would import route {}", route);
+            summary.routes_imported += 1;
+            continue;
+        }
+        
+        match client.post::<_, Value>(&["routes", "policy"], route).await {
+            Ok(_) => {
+                println!("imported route {}", route);
+                summary.routes_imported += 1;
+            }
+            Err(e) if e.is_conflict() => {
+                println!("skip route {}: {}", route, e);
+                summary.routes_skipped += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    
+    Ok(summary)
+}
+
+fn route_priority(route: &Value) -> Option<u64> {
+    route.get("priority")?.as_u64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::State;
+    use axum::http::{header, HeaderMap, StatusCode};
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    
+    const TOKEN: &str = "admin-token";
+    
+    /// 模拟服务端的节点和策略路由
+    #[derive(Clone, Default)]
+    struct Server {
+        nodes: Arc<Mutex<Vec<Value>>>,
+        routes: Arc<Mutex<Vec<Value>>>,
+    }
+    
+    type Reply = Result<Json<Value>, (StatusCode, Json<Value>)>;
+    
+    fn authorize(headers: &HeaderMap) -> Result<(), (StatusCode, Json<Value>)> {
+        match headers.get(header::AUTHORIZATION).and_then(|value| value.to_str().ok()) {
+            Some(value) if value == format!("Bearer {}", TOKEN) => Ok(()),
+            _ => Err((StatusCode::UNAUTHORIZED, Json(json!({ "error": "admin token required" })))),
+        }
+    }
+    
+    /// 以`key`字段去重插入，已存在时返回409
+    fn insert(entries: &Mutex<Vec<Value>>, key: &str, entry: Value) -> Reply {
+        let mut entries = entries.lock().unwrap();
+        if entries.iter().any(|existing| existing[key] == entry[key]) {
+            return Err((StatusCode::CONFLICT, Json(json!({ "error": format!("{} already exists", entry[key]) }))));
+        }
+        entries.push(entry.clone());
+        Ok(Json(entry))
+    }
+    
+    /// 启动模拟服务端，返回其地址
+    async fn start(server: Server) -> String {
+        let app = Router::new()
+            .route("/api/nodes", get(|State(server): State<Server>, headers: HeaderMap| async move {
+                authorize(&headers)?;
+                Ok::<_, (StatusCode, Json<Value>)>(Json(Value::Array(server.nodes.lock().unwrap().clone())))
+            }).post(|State(server): State<Server>, headers: HeaderMap, Json(node): Json<Value>| async move {
+                authorize(&headers)?;
+                insert(&server.nodes, "id", node)
+            }))
+            .route("/api/routes/policy", get(|State(server): State<Server>, headers: HeaderMap| async move {
+                authorize(&headers)?;
+                Ok::<_, (StatusCode, Json<Value>)>(Json(Value::Array(server.routes.lock().unwrap().clone())))
+            }).post(|State(server): State<Server>, headers: HeaderMap, Json(route): Json<Value>| async move {
+                authorize(&headers)?;
+                insert(&server.routes, "priority", route)
+            }))
+            .with_state(server);
+        
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+    
+    fn seed(server: &Server) {
+        let mut nodes = server.nodes.lock().unwrap();
+        for i in 1..=3 {
+            nodes.push(json!({
+                "id": format!("node-{}", i),
+                "name": format!("node-{}", i),
+                "address": format!("192.0.2.{}:51820", i),
+                "virtual_ip": format!("10.0.0.{}", i + 1),
+                "public_key": "AAAA",
+                "group": if i == 1 { Some("ops") } else { None },
+                "priority": i,
+            }));
+        }
+        let mut routes = server.routes.lock().unwrap();
+        routes.push(json!({ "priority": 10, "table": 100 }));
+        routes.push(json!({ "priority": 20, "table": 200 }));
+    }
+    
+    #[tokio::test]
+    async fn export_then_import_restores_every_node() {
+        let server = Server::default();
+        seed(&server);
+        let client = ApiClient::new(&start(server.clone()).await, Some(TOKEN.to_string())).unwrap();
+        let file = std::env::temp_dir().join(format!("vpnet-export-{}.json", std::process::id()));
+        let file = file.to_str().unwrap();
+        
+        let export = export(&client, Some(file)).await.unwrap();
+        assert_eq!(export.nodes.len(), 3);
+        assert_eq!(export.nodes[0].group.as_deref(), Some("ops"));
+        
+        // 清空服务端后演练导入，不修改服务端
+        server.nodes.lock().unwrap().clear();
+        server.routes.lock().unwrap().clear();
+        let summary = import(&client, file, true).await.unwrap();
+        assert_eq!(summary.nodes_imported, 3);
+        assert!(server.nodes.lock().unwrap().is_empty());
+        
+        let summary = import(&client, file, false).await.unwrap();
+        assert_eq!((summary.nodes_imported, summary.routes_imported), (3, 2));
+        assert_eq!(server.nodes.lock().unwrap().len(), 3);
+        assert_eq!(server.routes.lock().unwrap().len(), 2);
+        
+        // 再次导入时全部跳过
+        let summary = import(&client, file, false).await.unwrap();
+        assert_eq!((summary.nodes_skipped, summary.routes_skipped), (3, 2));
+        assert_eq!(server.nodes.lock().unwrap().len(), 3);
+        let _ = fs::remove_file(file);
+    }
+    
+    #[tokio::test]
+    async fn requests_without_the_admin_token_are_rejected() {
+        let anonymous = ApiClient::new(&start(Server::default()).await, None).unwrap();
+        let result = export(&anonymous, None).await;
+        assert!(matches!(result, Err(TransferError::Api(ApiError::Upstream { status: reqwest::StatusCode::UNAUTHORIZED, .. }))));
+    }
+}
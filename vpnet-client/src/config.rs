@@ -1,301 +1,996 @@
-/*!
-VPNet Client 配置模块
-
-定义和加载客户端配置，包括：
-- 客户端基本配置
-- 服务器配置
-- 虚拟设备配置
-- 认证配置
-- 监控配置
-*/
-
-use serde::{Deserialize, Serialize};
-use std::fs::{File, create_dir_all};
-use std::io::{Read, Write};
-use std::path::Path;
-use thiserror::Error;
-use rand::Rng;
-use base64::Engine;
-
-/// 配置错误
-#[derive(Error, Debug)]
-pub enum ConfigError {
-    #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
-    
-    #[error("Toml parsing error: {0}")]
-    Toml(#[from] toml::de::Error),
-    
-    #[error("Invalid configuration: {0}")]
-    Invalid(String),
-    
-    #[error("Missing required configuration: {0}")]
-    Missing(String),
-}
-
-/// 客户端配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct ClientConfig {
-    pub client: Client,
-    pub server: Server,
-    pub virtual_device: VirtualDevice,
-    pub auth: Auth,
-    pub monitor: Monitor,
-}
-
-/// 客户端基本配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Client {
-    pub id: String,
-    pub name: String,
-    pub port: u16,
-    pub key_file: String,
-    pub enable_auto_connect: bool,
-    pub reconnect_interval: u64,
-    pub max_reconnect_attempts: u32,
-}
-
-/// 服务器配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Server {
-    pub address: String,
-    pub timeout: u64,
-    pub enable_encryption: bool,
-    pub enable_compression: bool,
-}
-
-/// 虚拟设备配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct VirtualDevice {
-    pub name: String,
-    pub ip: String,
-    pub subnet: String,
-    pub gateway: String,
-    pub mtu: u32,
-    pub enable_ipv6: bool,
-    pub ipv6_address: Option<String>,
-    pub auto_config: bool,
-}
-
-/// 认证配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Auth {
-    pub username: Option<String>,
-    pub password: Option<String>,
-    pub token: Option<String>,
-    pub token_file: String,
-    pub enable_auto_login: bool,
-    pub auth_timeout: u64,
-}
-
-/// 监控配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Monitor {
-    pub enable: bool,
-    pub interval: u64,
-    pub log_level: String,
-    pub enable_stats: bool,
-    pub stats_file: Option<String>,
-    pub stats_interval: u64,
-}
-
-/// 生成默认配置
-pub fn default_config() -> ClientConfig {
-    let mut rng = rand::thread_rng();
-    
-    ClientConfig {
-        client: Client {
-            id: format!("client_{:x}", rng.gen::<u64>()),
-            name: format!("Client-{:x}", rng.gen::<u32>()),
-            port: 51820,
-            key_file: "vpnet-client-key.json".to_string(),
-            enable_auto_connect: true,
-            reconnect_interval: 5,
-            max_reconnect_attempts: 10,
-        },
-        server: Server {
-            address: "127.0.0.1:51820".to_string(),
-            timeout: 30,
-            enable_encryption: true,
-            enable_compression: true,
-        },
-        virtual_device: VirtualDevice {
-            name: "vpnet0".to_string(),
-            ip: "10.0.0.2".to_string(),
-            subnet: "255.255.255.0".to_string(),
-            gateway: "10.0.0.1".to_string(),
-            mtu: 1420,
-            enable_ipv6: false,
-            ipv6_address: None,
-            auto_config: true,
-        },
-        auth: Auth {
-            username: None,
-            password: None,
-            token: None,
-            token_file: "vpnet-token.json".to_string(),
-            enable_auto_login: true,
-            auth_timeout: 60,
-        },
-        monitor: Monitor {
-            enable: true,
-            interval: 30,
-            log_level: "info".to_string(),
-            enable_stats: true,
-            stats_file: Some("vpnet-stats.json".to_string()),
-            stats_interval: 60,
-        },
-    }
-}
-
-/// 保存配置到文件
-pub fn save_config(config: &ClientConfig, path: &str) -> Result<(), ConfigError> {
-    let toml_str = toml::to_string_pretty(config)?;
-    let mut file = File::create(path)?;
-    file.write_all(toml_str.as_bytes())?;
-    Ok(())
-}
-
-/// 加载或生成配置
-pub fn load_or_generate_config(path: &str) -> Result<ClientConfig, ConfigError> {
-    if Path::new(path).exists() {
-        // 加载现有配置
-        let mut file = File::open(path)?;
-        let mut content = String::new();
-        file.read_to_string(&mut content)?;
-        let config: ClientConfig = toml::from_str(&content)?;
-        Ok(config)
-    } else {
-        // 生成新配置
-        let config = default_config();
-        save_config(&config, path)?;
-        Ok(config)
-    }
-}
-
-/// 加载或生成密钥对
-pub fn load_or_generate_keys(config: &ClientConfig) -> Result<(Vec<u8>, Vec<u8>), ConfigError> {
-    let key_path = Path::new(&config.client.key_file);
-    
-    if key_path.exists() {
-        // 加载现有密钥
-        let mut file = File::open(key_path)?;
-        let mut content = String::new();
-        file.read_to_string(&mut content)?;
-        
-        let keys: serde_json::Value = serde_json::from_str(&content)?;
-        let public_key = base64::engine::general_purpose::STANDARD.decode(
-            keys["public_key"].as_str().ok_or(ConfigError::Missing("public_key".to_string()))?
-        )?;
-        let private_key = base64::engine::general_purpose::STANDARD.decode(
-            keys["private_key"].as_str().ok_or(ConfigError::Missing("private_key".to_string()))?
-        )?;
-        
-        Ok((public_key, private_key))
-    } else {
-        // 生成新密钥对
-        let mut rng = rand::thread_rng();
-        let public_key = rng.gen::<[u8; 32]>().to_vec();
-        let private_key = rng.gen::<[u8; 32]>().to_vec();
-        
-        // 保存密钥到文件
-        let keys = serde_json::json!({"public_key": base64::engine::general_purpose::STANDARD.encode(&public_key),"private_key": base64::engine::general_purpose::STANDARD.encode(&private_key),"generated_at": chrono::Utc::now().to_rfc3339()});
-        
-        let keys_str = serde_json::to_string_pretty(&keys)?;
-        let mut file = File::create(key_path)?;
-        file.write_all(keys_str.as_bytes())?;
-        
-        Ok((public_key, private_key))
-    }
-}
-
-/// 验证配置
-pub fn validate_config(config: &ClientConfig) -> Result<(), ConfigError> {
-    // 验证客户端配置
-    if config.client.id.is_empty() {
-        return Err(ConfigError::Missing("client.id".to_string()));
-    }
-    
-    if config.client.name.is_empty() {
-        return Err(ConfigError::Missing("client.name".to_string()));
-    }
-    
-    if config.client.port == 0 {
-        return Err(ConfigError::Invalid("client.port must be greater than 0".to_string()));
-    }
-    
-    // 验证服务器配置
-    if config.server.address.is_empty() {
-        return Err(ConfigError::Missing("server.address".to_string()));
-    }
-    
-    // 验证虚拟设备配置
-    if config.virtual_device.name.is_empty() {
-        return Err(ConfigError::Missing("virtual_device.name".to_string()));
-    }
-    
-    if config.virtual_device.ip.is_empty() {
-        return Err(ConfigError::Missing("virtual_device.ip".to_string()));
-    }
-    
-    if config.virtual_device.subnet.is_empty() {
-        return Err(ConfigError::Missing("virtual_device.subnet".to_string()));
-    }
-    
-    if config.virtual_device.gateway.is_empty() {
-        return Err(ConfigError::Missing("virtual_device.gateway".to_string()));
-    }
-    
-    // 验证认证配置
-    if config.auth.token_file.is_empty() {
-        return Err(ConfigError::Missing("auth.token_file".to_string()));
-    }
-    
-    // 验证监控配置
-    if config.monitor.log_level.is_empty() {
-        return Err(ConfigError::Missing("monitor.log_level".to_string()));
-    }
-    
-    Ok(())
-}
-
-/// 保存认证令牌
-pub fn save_auth_token(config: &ClientConfig, token: &str) -> Result<(), ConfigError> {
-    let token_path = Path::new(&config.auth.token_file);
-    let token_data = serde_json::json!({"token": token,"expires_at": chrono::Utc::now().timestamp() + config.auth.auth_timeout as i64});
-    
-    let token_str = serde_json::to_string_pretty(&token_data)?;
-    let mut file = File::create(token_path)?;
-    file.write_all(token_str.as_bytes())?;
-    Ok(())
-}
-
-/// 加载认证令牌
-pub fn load_auth_token(config: &ClientConfig) -> Result<Option<String>, ConfigError> {
-    let token_path = Path::new(&config.auth.token_file);
-    
-    if token_path.exists() {
-        let mut file = File::open(token_path)?;
-        let mut content = String::new();
-        file.read_to_string(&mut content)?;
-        
-        let token_data: serde_json::Value = serde_json::from_str(&content)?;
-        let token = token_data["token"].as_str().map(|s| s.to_string());
-        let expires_at = token_data["expires_at"].as_i64();
-        
-        // 检查令牌是否过期
-        if let (Some(token), Some(expires_at)) = (token.clone(), expires_at) {
-            if chrono::Utc::now().timestamp() < expires_at {
-                Ok(Some(token))
-            } else {
-                // 令牌已过期，删除令牌文件
-                std::fs::remove_file(token_path)?;
-                Ok(None)
-            }
-        } else {
-            Ok(token)
-        }
-    } else {
-        Ok(None)
-    }
-}
+/*!
+VPNet Client 配置模块
+
+定义和加载客户端配置，包括：
+- 客户端基本配置
+- 服务器配置
+- 虚拟设备配置
+- 认证配置
+- 监控配置
+*/
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, create_dir_all};
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use thiserror::Error;
+use rand::Rng;
+use vpnet::config::{check_duration, check_file_or_parent, check_ipv4, closest_match, migrate_seconds_to_duration, NetworkConfig, ValidationReport};
+use vpnet::{normalize_enum_value, BackoffStrategy, Compression, ConfigMigrator, DeviceMode, KeyPair, MigrationError, MultiPathStrategy, ProxyConfig, RelaySelectionWeights, StaticRoute, TransformConfig};
+
+/// 配置错误
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    
+    #[error("Toml parsing error: {0}")]
+    Toml(#[from] toml::de::Error),
+    
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    
+    #[error("Invalid configuration: {0}")]
+    Invalid(String),
+    
+    #[error("Missing required configuration: {0}")]
+    Missing(String),
+    
+    #[error("Config migration error: {0}")]
+    Migration(#[from] MigrationError),
+    
+    #[error("Key file {path} is not a valid Ed25519 key pair ({reason}), a copy was saved to {backup}; restore a valid key or delete the file to generate a new node identity")]
+    InvalidKeyFile { path: String, backup: String, reason: String },
+}
+
+/// 当前配置格式版本
+pub const SCHEMA_VERSION: u32 = 4;
+
+/// 客户端配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ClientConfig {
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
+    pub client: Client,
+    pub server: Server,
+    pub virtual_device: VirtualDevice,
+    pub auth: Auth,
+    pub monitor: Monitor,
+    #[serde(default)]
+    pub geo: Option<Geo>,
+    /// 有多个中继可用时的评分权重
+    #[serde(default)]
+    pub relay_selection: RelaySelectionWeights,
+    /// 为其他节点中继
+    #[serde(default)]
+    pub relay: RelayConfig,
+    /// UDP套接字缓冲区，多路径转发时直连和中继路径共用该套接字
+    #[serde(default)]
+    pub network: NetworkConfig,
+}
+
+/// 客户端基本配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Client {
+    pub id: String,
+    pub name: String,
+    pub port: u16,
+    pub key_file: String,
+    pub enable_auto_connect: bool,
+    #[serde(with = "vpnet::config::duration")]
+    pub reconnect_interval: Duration,
+    pub max_reconnect_attempts: u32,
+    /// 重连间隔策略，未配置时每隔`reconnect_interval`重试一次
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reconnect: Option<BackoffStrategy>,
+    /// 心跳间隔策略
+    #[serde(default)]
+    pub heartbeat: BackoffStrategy,
+    /// 与其他节点建立直连后经直连和中继两条路径发送的策略，不填写表示只使用直连路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multipath: Option<MultiPathStrategy>,
+    /// 本地SOCKS5代理监听的端口，只监听回环地址，不填写表示不启用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socks5_port: Option<u16>,
+    /// SOCKS5代理要求的用户名和密码，不填写表示不要求认证
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socks5_auth: Option<Socks5Auth>,
+}
+
+/// SOCKS5代理的用户名/密码认证
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Socks5Auth {
+    pub username: String,
+    pub password: String,
+}
+
+impl Client {
+    /// 实际使用的重连间隔策略
+    pub fn reconnect_strategy(&self) -> BackoffStrategy {
+        self.reconnect.clone().unwrap_or_else(|| {
+            BackoffStrategy::Constant(self.reconnect_interval)
+        })
+    }
+}
+
+/// 服务器配置
+///
+/// 时长字段在配置文件中写作`"30s"`、`"2m30s"`等形式，下同。
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Server {
+    pub address: String,
+    #[serde(with = "vpnet::config::duration")]
+    pub timeout: Duration,
+    pub enable_encryption: bool,
+    /// 加密前压缩会泄露明文长度信息，默认关闭，见`vpnet::Compression`
+    pub enable_compression: bool,
+    /// `enable_compression`开启时使用的压缩模式，默认为自适应压缩
+    #[serde(default = "default_compression")]
+    pub compression: Compression,
+    /// 出站连接必须经过的HTTP代理，配置后经CONNECT隧道连接服务端的TCP端口
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<ProxyConfig>,
+    /// 其他可选的服务器地址，连接前查询各服务器的节点信息，启用`geo.prefer_nearest`时连接距离最近的一台，
+    /// 否则按`[relay_selection]`为声明了中继能力的服务器评分
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternate_addresses: Vec<String>,
+}
+
+/// 虚拟设备配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct VirtualDevice {
+    pub name: String,
+    pub ip: String,
+    pub subnet: String,
+    pub gateway: String,
+    pub mtu: u32,
+    pub enable_ipv6: bool,
+    pub ipv6_address: Option<String>,
+    pub auto_config: bool,
+    #[serde(default)]
+    pub device_mode: DeviceMode,
+    #[serde(default = "default_max_restart_attempts")]
+    pub max_restart_attempts: u32,
+    #[serde(default = "default_restart_cooldown", with = "vpnet::config::duration")]
+    pub restart_cooldown: Duration,
+    #[serde(default)]
+    pub transforms: Vec<TransformConfig>,
+    #[serde(default = "default_flush_timeout", with = "vpnet::config::duration")]
+    pub flush_timeout: Duration,
+    #[serde(default)]
+    pub routes: Vec<StaticRoute>,
+}
+
+/// 认证配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Auth {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub token: Option<String>,
+    pub token_file: String,
+    /// 退出时保存服务端签发的会话票据的文件，下次启动时读回以恢复会话
+    #[serde(default = "default_session_ticket_file")]
+    pub session_ticket_file: String,
+    pub enable_auto_login: bool,
+    #[serde(with = "vpnet::config::duration")]
+    pub auth_timeout: Duration,
+}
+
+/// 监控配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Monitor {
+    pub enable: bool,
+    #[serde(with = "vpnet::config::duration")]
+    pub interval: Duration,
+    pub log_level: String,
+    pub enable_stats: bool,
+    pub stats_file: Option<String>,
+    #[serde(with = "vpnet::config::duration")]
+    pub stats_interval: Duration,
+}
+
+/// 地理位置配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Geo {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub country_code: String,
+    #[serde(default)]
+    pub prefer_nearest: bool,
+}
+
+/// 中继配置
+///
+/// 启用后本节点在握手时声明可以中继，为其他节点转发数据；未启用时拒绝中继。
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct RelayConfig {
+    #[serde(default)]
+    pub enable: bool,
+    /// 同时中继的会话数上限，0表示不限制
+    #[serde(default)]
+    pub max_concurrent_sessions: u32,
+    /// 中继的总带宽上限（Mbps），0表示不限制
+    #[serde(default)]
+    pub max_bandwidth_mbps: u32,
+}
+
+/// 客户端配置覆盖
+///
+/// 字段与`ClientConfig`一一对应，只有为`Some`的字段会在合并时覆盖原配置，
+/// 用于命令行参数、环境变量等多个覆盖来源。
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct ClientConfigOverride {
+    #[serde(default)]
+    pub client: ClientOverride,
+    #[serde(default)]
+    pub server: ServerOverride,
+    #[serde(default)]
+    pub virtual_device: VirtualDeviceOverride,
+    #[serde(default)]
+    pub auth: AuthOverride,
+    #[serde(default)]
+    pub monitor: MonitorOverride,
+    pub geo: Option<Geo>,
+}
+
+/// 客户端基本配置覆盖
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct ClientOverride {
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub port: Option<u16>,
+    pub key_file: Option<String>,
+    pub enable_auto_connect: Option<bool>,
+    #[serde(default, with = "vpnet::config::duration::option")]
+    pub reconnect_interval: Option<Duration>,
+    pub max_reconnect_attempts: Option<u32>,
+    pub socks5_port: Option<u16>,
+}
+
+/// 服务器配置覆盖
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct ServerOverride {
+    pub address: Option<String>,
+    #[serde(default, with = "vpnet::config::duration::option")]
+    pub timeout: Option<Duration>,
+    pub enable_encryption: Option<bool>,
+    pub enable_compression: Option<bool>,
+}
+
+/// 虚拟设备配置覆盖
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct VirtualDeviceOverride {
+    pub name: Option<String>,
+    pub ip: Option<String>,
+    pub subnet: Option<String>,
+    pub gateway: Option<String>,
+    pub mtu: Option<u32>,
+    pub enable_ipv6: Option<bool>,
+    pub ipv6_address: Option<String>,
+    pub auto_config: Option<bool>,
+    pub device_mode: Option<DeviceMode>,
+    pub max_restart_attempts: Option<u32>,
+    #[serde(default, with = "vpnet::config::duration::option")]
+    pub restart_cooldown: Option<Duration>,
+    pub transforms: Option<Vec<TransformConfig>>,
+    #[serde(default, with = "vpnet::config::duration::option")]
+    pub flush_timeout: Option<Duration>,
+    pub routes: Option<Vec<StaticRoute>>,
+}
+
+/// 认证配置覆盖
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct AuthOverride {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub token: Option<String>,
+    pub token_file: Option<String>,
+    pub session_ticket_file: Option<String>,
+    pub enable_auto_login: Option<bool>,
+    #[serde(default, with = "vpnet::config::duration::option")]
+    pub auth_timeout: Option<Duration>,
+}
+
+/// 监控配置覆盖
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct MonitorOverride {
+    pub enable: Option<bool>,
+    #[serde(default, with = "vpnet::config::duration::option")]
+    pub interval: Option<Duration>,
+    pub log_level: Option<String>,
+    pub enable_stats: Option<bool>,
+    pub stats_file: Option<String>,
+    #[serde(default, with = "vpnet::config::duration::option")]
+    pub stats_interval: Option<Duration>,
+}
+
+impl ClientConfig {
+    /// 合并配置覆盖，只应用为`Some`的字段
+    pub fn merge(&mut self, overrides: ClientConfigOverride) {
+        let ClientConfigOverride { client, server, virtual_device, auth, monitor, geo } = overrides;
+        
+        apply(&mut self.client.id, client.id);
+        apply(&mut self.client.name, client.name);
+        apply(&mut self.client.port, client.port);
+        apply(&mut self.client.key_file, client.key_file);
+        apply(&mut self.client.enable_auto_connect, client.enable_auto_connect);
+        apply(&mut self.client.reconnect_interval, client.reconnect_interval);
+        apply(&mut self.client.max_reconnect_attempts, client.max_reconnect_attempts);
+        apply_option(&mut self.client.socks5_port, client.socks5_port);
+        
+        apply(&mut self.server.address, server.address);
+        apply(&mut self.server.timeout, server.timeout);
+        apply(&mut self.server.enable_encryption, server.enable_encryption);
+        apply(&mut self.server.enable_compression, server.enable_compression);
+        
+        let device = &mut self.virtual_device;
+        apply(&mut device.name, virtual_device.name);
+        apply(&mut device.ip, virtual_device.ip);
+        apply(&mut device.subnet, virtual_device.subnet);
+        apply(&mut device.gateway, virtual_device.gateway);
+        apply(&mut device.mtu, virtual_device.mtu);
+        apply(&mut device.enable_ipv6, virtual_device.enable_ipv6);
+        apply_option(&mut device.ipv6_address, virtual_device.ipv6_address);
+        apply(&mut device.auto_config, virtual_device.auto_config);
+        apply(&mut device.device_mode, virtual_device.device_mode);
+        apply(&mut device.max_restart_attempts, virtual_device.max_restart_attempts);
+        apply(&mut device.restart_cooldown, virtual_device.restart_cooldown);
+        apply(&mut device.transforms, virtual_device.transforms);
+        apply(&mut device.flush_timeout, virtual_device.flush_timeout);
+        apply(&mut device.routes, virtual_device.routes);
+        
+        apply_option(&mut self.auth.username, auth.username);
+        apply_option(&mut self.auth.password, auth.password);
+        apply_option(&mut self.auth.token, auth.token);
+        apply(&mut self.auth.token_file, auth.token_file);
+        apply(&mut self.auth.session_ticket_file, auth.session_ticket_file);
+        apply(&mut self.auth.enable_auto_login, auth.enable_auto_login);
+        apply(&mut self.auth.auth_timeout, auth.auth_timeout);
+        
+        apply(&mut self.monitor.enable, monitor.enable);
+        apply(&mut self.monitor.interval, monitor.interval);
+        apply(&mut self.monitor.log_level, monitor.log_level);
+        apply(&mut self.monitor.enable_stats, monitor.enable_stats);
+        apply_option(&mut self.monitor.stats_file, monitor.stats_file);
+        apply(&mut self.monitor.stats_interval, monitor.stats_interval);
+        
+        apply_option(&mut self.geo, geo);
+    }
+}
+
+impl ClientConfigOverride {
+    /// 从`VPNET_`前缀的环境变量读取覆盖
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        
+        Self {
+            client: ClientOverride {
+                id: var("VPNET_CLIENT_ID"),
+                name: var("VPNET_CLIENT_NAME"),
+                ..Default::default()
+            },
+            server: ServerOverride {
+                address: var("VPNET_SERVER"),
+                ..Default::default()
+            },
+            virtual_device: VirtualDeviceOverride {
+                ip: var("VPNET_VIRTUAL_IP"),
+                ..Default::default()
+            },
+            auth: AuthOverride {
+                token: var("VPNET_AUTH_TOKEN"),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// 覆盖值为`Some`时替换原值
+fn apply<T>(target: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *target = value;
+    }
+}
+
+/// 覆盖值为`Some`时替换可选的原值
+fn apply_option<T>(target: &mut Option<T>, value: Option<T>) {
+    if value.is_some() {
+        *target = value;
+    }
+}
+
+/// 默认虚拟网卡最大重建次数
+fn default_max_restart_attempts() -> u32 {
+    3
+}
+
+/// 默认虚拟网卡重建冷却时间
+fn default_restart_cooldown() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// 默认虚拟网卡关闭时写出缓冲数据包的超时时间
+fn default_flush_timeout() -> Duration {
+    Duration::from_secs(2)
+}
+
+/// 开启压缩时默认的压缩模式
+fn default_compression() -> Compression {
+    Compression::Adaptive { ratio_threshold: Compression::DEFAULT_RATIO_THRESHOLD }
+}
+
+/// 未写版本号的配置视为版本1
+fn default_session_ticket_file() -> String {
+    "vpnet-session.json".to_string()
+}
+
+fn default_schema_version() -> u32 {
+    1
+}
+
+/// 生成默认配置
+pub fn default_config() -> ClientConfig {
+    let mut rng = rand::thread_rng();
+    
+    ClientConfig {
+        schema_version: SCHEMA_VERSION,
+        client: Client {
+            id: format!("client_{:x}", rng.gen::<u64>()),
+            name: format!("Client-{:x}", rng.gen::<u32>()),
+            port: 51820,
+            key_file: "vpnet-client-key.json".to_string(),
+            enable_auto_connect: true,
+            reconnect_interval: Duration::from_secs(5),
+            max_reconnect_attempts: 10,
+            reconnect: None,
+            heartbeat: BackoffStrategy::default(),
+            multipath: None,
+            socks5_port: None,
+            socks5_auth: None,
+        },
+        server: Server {
+            address: "127.0.0.1:51820".to_string(),
+            timeout: Duration::from_secs(30),
+            enable_encryption: true,
+            enable_compression: false,
+            compression: default_compression(),
+            proxy: None,
+            alternate_addresses: Vec::new(),
+        },
+        virtual_device: VirtualDevice {
+            name: "vpnet0".to_string(),
+            ip: "10.0.0.2".to_string(),
+            subnet: "255.255.255.0".to_string(),
+            gateway: "10.0.0.1".to_string(),
+            mtu: 1420,
+            enable_ipv6: false,
+            ipv6_address: None,
+            device_mode: DeviceMode::Tun,
+            max_restart_attempts: default_max_restart_attempts(),
+            restart_cooldown: default_restart_cooldown(),
+            transforms: Vec::new(),
+            flush_timeout: default_flush_timeout(),
+            routes: Vec::new(),
+            auto_config: true,
+        },
+        auth: Auth {
+            username: None,
+            password: None,
+            token: None,
+            token_file: "vpnet-token.json".to_string(),
+            session_ticket_file: default_session_ticket_file(),
+            enable_auto_login: true,
+            auth_timeout: Duration::from_secs(60),
+        },
+        monitor: Monitor {
+            enable: true,
+            interval: Duration::from_secs(30),
+            log_level: "info".to_string(),
+            enable_stats: true,
+            stats_file: Some("vpnet-stats.json".to_string()),
+            stats_interval: Duration::from_secs(60),
+        },
+        geo: None,
+        relay_selection: RelaySelectionWeights::default(),
+        relay: RelayConfig::default(),
+        network: NetworkConfig::default(),
+    }
+}
+
+/// 保存配置到文件
+pub fn save_config(config: &ClientConfig, path: &str) -> Result<(), ConfigError> {
+    let toml_str = toml::to_string_pretty(config)?;
+    let mut file = File::create(path)?;
+    file.write_all(toml_str.as_bytes())?;
+    Ok(())
+}
+
+/// 包含所有迁移步骤的配置迁移器
+fn config_migrator() -> ConfigMigrator {
+    ConfigMigrator::new(vec![migrate_v1_to_v2, migrate_v2_to_v3, migrate_v3_to_v4])
+}
+
+/// 版本1到2：补齐后来改为必填的虚拟设备和监控字段
+fn migrate_v1_to_v2(table: &mut toml::Table) -> Result<(), String> {
+    let device = table.get_mut("virtual_device")
+        .and_then(toml::Value::as_table_mut)
+        .ok_or("missing [virtual_device] section")?;
+    device.entry("auto_config").or_insert(toml::Value::Boolean(true));
+    
+    let monitor = table.get_mut("monitor")
+        .and_then(toml::Value::as_table_mut)
+        .ok_or("missing [monitor] section")?;
+    monitor.entry("stats_interval").or_insert(toml::Value::Integer(60));
+    Ok(())
+}
+
+/// 版本2到3：枚举取值统一为小写
+fn migrate_v2_to_v3(table: &mut toml::Table) -> Result<(), String> {
+    normalize_enum_value(table, "virtual_device", "device_mode");
+    Ok(())
+}
+
+/// 版本3到4：以秒为单位的整数时长改为`"30s"`形式的字符串
+fn migrate_v3_to_v4(table: &mut toml::Table) -> Result<(), String> {
+    const DURATION_FIELDS: &[(&str, &str)] = &[
+        ("client", "reconnect_interval"),
+        ("server", "timeout"),
+        ("virtual_device", "restart_cooldown"),
+        ("virtual_device", "flush_timeout"),
+        ("auth", "auth_timeout"),
+        ("monitor", "interval"),
+        ("monitor", "stats_interval"),
+    ];
+    
+    for (section, key) in DURATION_FIELDS {
+        migrate_seconds_to_duration(table, section, key)?;
+    }
+    Ok(())
+}
+
+/// 加载配置
+///
+/// 配置版本低于当前版本时先执行迁移，原文件备份为`.bak`后写回迁移结果。
+pub fn load_config(path: &str) -> Result<ClientConfig, ConfigError> {
+    let mut content = String::new();
+    File::open(path)?.read_to_string(&mut content)?;
+    
+    let version = ConfigMigrator::detect_version(&content)?;
+    if version < SCHEMA_VERSION {
+        let migrated = config_migrator().migrate(&content, version)?;
+        std::fs::copy(path, format!("{}.bak", path))?;
+        std::fs::write(path, &migrated)?;
+        log::info!("Migrated config {} from schema version {} to {}", path, version, SCHEMA_VERSION);
+        content = migrated;
+    } else if version > SCHEMA_VERSION {
+        return Err(MigrationError::Unsupported { version, supported: SCHEMA_VERSION }.into());
+    }
+    
+    Ok(toml::from_str(&content)?)
+}
+
+/// 加载或生成配置
+pub fn load_or_generate_config(path: &str) -> Result<ClientConfig, ConfigError> {
+    if Path::new(path).exists() {
+        // 加载现有配置
+        load_config(path)
+    } else {
+        // 生成新配置
+        let config = default_config();
+        save_config(&config, path)?;
+        Ok(config)
+    }
+}
+
+/// 加载或生成密钥对
+///
+/// 返回Ed25519公钥和PKCS#8格式的私钥。密钥文件无效（例如旧版本生成的随机字节密钥）时不会重新生成，
+/// 否则节点身份改变，固定了公钥的对端将拒绝握手：先将文件复制为`<key_file>.bak`，再返回`ConfigError::InvalidKeyFile`。
+pub fn load_or_generate_keys(config: &ClientConfig) -> Result<(Vec<u8>, Vec<u8>), ConfigError> {
+    let key_path = Path::new(&config.client.key_file);
+    
+    if key_path.exists() {
+        // 加载现有密钥
+        let mut file = File::open(key_path)?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        
+        let keys: serde_json::Value = serde_json::from_str(&content)?;
+        let public_key = keys["public_key"].as_str().ok_or(ConfigError::Missing("public_key".to_string()))?;
+        let private_key = keys["private_key"].as_str().ok_or(ConfigError::Missing("private_key".to_string()))?;
+        
+        return match KeyPair::from_base64(public_key, private_key) {
+            Ok(key_pair) => Ok((key_pair.public_key.clone(), key_pair.private_key().to_vec())),
+            Err(e) => {
+                let backup = format!("{}.bak", key_path.display());
+                std::fs::copy(key_path, &backup)?;
+                Err(ConfigError::InvalidKeyFile {
+                    path: key_path.display().to_string(),
+                    backup,
+                    reason: e.to_string(),
+                })
+            }
+        };
+    }
+    
+    // 生成新密钥对
+    let key_pair = KeyPair::generate().map_err(|e| ConfigError::Invalid(e.to_string()))?;
+    let (public_b64, private_b64) = key_pair.to_base64();
+    
+    // 保存密钥到文件
+    let keys = serde_json::json!({
+        "public_key": public_b64,
+        "private_key": private_b64,
+        "generated_at": chrono::Utc::now().to_rfc3339()
+    });
+    
+    let keys_str = serde_json::to_string_pretty(&keys)?;
+    let mut file = File::create(key_path)?;
+    file.write_all(keys_str.as_bytes())?;
+    
+    Ok((key_pair.public_key.clone(), key_pair.private_key().to_vec()))
+}
+
+/// 可用的日志级别
+const LOG_LEVELS: &[&str] = &["off", "error", "warn", "info", "debug", "trace"];
+
+/// 验证配置
+///
+/// 检查所有字段而不是在第一个错误处停止，同时给出不影响启动的警告。
+pub fn validate_config(config: &ClientConfig) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let heartbeat_interval = vpnet::constants::HEARTBEAT_INTERVAL;
+    
+    // 验证客户端配置
+    if config.client.id.is_empty() {
+        report.missing("client.id");
+    }
+    
+    if config.client.name.is_empty() {
+        report.missing("client.name");
+    }
+    
+    if config.client.port == 0 {
+        report.error("client.port", "must be greater than 0")
+            .suggest(format!("Did you mean {}?", vpnet::DEFAULT_PORT));
+    }
+    
+    check_duration("client.reconnect_interval", config.client.reconnect_interval, Duration::from_secs(1), Duration::from_secs(3600), &mut report);
+    
+    if let Err(e) = config.client.reconnect_strategy().validate() {
+        report.error("client.reconnect", e);
+    }
+    
+    if let Err(e) = config.client.heartbeat.validate() {
+        report.error("client.heartbeat", e);
+    }
+    
+    if config.client.socks5_port == Some(0) {
+        report.error("client.socks5_port", "must be greater than 0");
+    }
+    
+    // RFC 1929限制用户名和密码为1到255字节
+    if let Some(auth) = &config.client.socks5_auth {
+        if config.client.socks5_port.is_none() {
+            report.warn("client.socks5_auth", "ignored because client.socks5_port is not set");
+        }
+        for (field, value) in [("client.socks5_auth.username", &auth.username), ("client.socks5_auth.password", &auth.password)] {
+            if value.is_empty() || value.len() > 255 {
+                report.error(field, "must be between 1 and 255 bytes");
+            }
+        }
+    }
+    
+    if config.client.heartbeat.max_delay() > Duration::from_secs(heartbeat_interval) {
+        report.error(
+            "client.heartbeat",
+            format!("must not exceed {}s, otherwise peers time out", heartbeat_interval)
+        );
+    }
+    
+    // 验证服务器配置
+    if config.server.address.is_empty() {
+        report.missing("server.address")
+            .suggest(format!("Use the form 203.0.113.1:{}", vpnet::DEFAULT_PORT));
+    }
+    
+    check_duration("server.timeout", config.server.timeout, Duration::from_secs(1), Duration::from_secs(600), &mut report);
+    
+    if let Compression::Adaptive { ratio_threshold } = config.server.compression {
+        if !(ratio_threshold > 0.0 && ratio_threshold <= 1.0) {
+            report.error("server.compression.ratio_threshold", "must be in (0, 1]");
+        }
+    }
+    if config.server.enable_compression && config.server.compression != Compression::None {
+        report.warn("server.enable_compression", "compressing before encryption leaks plaintext length and allows length-oracle attacks (VORACLE)");
+    }
+    
+    if let Some(proxy) = &config.server.proxy {
+        if let Err(e) = proxy.authority() {
+            report.error("server.proxy.url", e).suggest("Use the form http://proxy.example.com:3128");
+        }
+        if proxy.password.is_some() {
+            if proxy.username.is_none() {
+                report.missing("server.proxy.username");
+            }
+            report.warn("server.proxy.password", "the proxy password is stored in plain text");
+        }
+    }
+    
+    // 验证虚拟设备配置
+    if config.virtual_device.name.is_empty() {
+        report.missing("virtual_device.name");
+    }
+    
+    if config.virtual_device.ip.is_empty() {
+        report.missing("virtual_device.ip");
+    }
+    
+    if config.virtual_device.subnet.is_empty() {
+        report.missing("virtual_device.subnet");
+    }
+    
+    if config.virtual_device.gateway.is_empty() {
+        report.missing("virtual_device.gateway");
+    }
+    
+    check_duration("virtual_device.restart_cooldown", config.virtual_device.restart_cooldown, Duration::ZERO, Duration::from_secs(3600), &mut report);
+    check_duration("virtual_device.flush_timeout", config.virtual_device.flush_timeout, Duration::ZERO, Duration::from_secs(60), &mut report);
+    
+    for (i, route) in config.virtual_device.routes.iter().enumerate() {
+        if route.cidr().is_err() {
+            report.error(
+                format!("virtual_device.routes[{}]", i),
+                format!("invalid CIDR {}/{}", route.network, route.prefix_len)
+            );
+        }
+        
+        if route.gateway().is_err() {
+            report.error(
+                format!("virtual_device.routes[{}].gateway", i),
+                format!("invalid gateway {}", route.gateway)
+            );
+        }
+    }
+    
+    // 验证认证配置
+    if config.auth.token_file.is_empty() {
+        report.missing("auth.token_file");
+    }
+    
+    check_duration("auth.auth_timeout", config.auth.auth_timeout, Duration::from_secs(1), Duration::from_secs(600), &mut report);
+    
+    // 验证监控配置
+    if config.monitor.log_level.is_empty() {
+        report.missing("monitor.log_level").suggest("Did you mean info?");
+    } else if !LOG_LEVELS.contains(&config.monitor.log_level.to_ascii_lowercase().as_str()) {
+        let error = report.error(
+            "monitor.log_level",
+            format!("must be one of: {}", LOG_LEVELS.join(", "))
+        );
+        if let Some(level) = closest_match(&config.monitor.log_level.to_ascii_lowercase(), LOG_LEVELS) {
+            error.suggest(format!("Did you mean {}?", level));
+        }
+    }
+    
+    check_duration("monitor.interval", config.monitor.interval, Duration::from_secs(1), Duration::from_secs(3600), &mut report);
+    check_duration("monitor.stats_interval", config.monitor.stats_interval, Duration::from_secs(1), Duration::from_secs(86400), &mut report);
+    
+    if config.monitor.enable_stats && config.monitor.stats_interval < Duration::from_secs(5) {
+        report.warn("monitor.stats_interval", "writing statistics more often than every 5s wears flash storage");
+    }
+    
+    // 验证地理位置配置
+    if let Some(geo) = &config.geo {
+        if !(-90.0..=90.0).contains(&geo.latitude) {
+            report.error("geo.latitude", "must be between -90 and 90");
+        }
+        
+        if !(-180.0..=180.0).contains(&geo.longitude) {
+            report.error("geo.longitude", "must be between -180 and 180");
+        }
+    }
+    if !config.server.alternate_addresses.is_empty() && !config.geo.as_ref().is_some_and(|geo| geo.prefer_nearest) {
+        report.warn("server.alternate_addresses", "without geo.prefer_nearest servers that do not advertise relaying are tried in order");
+    }
+    
+    if let Err(e) = config.relay_selection.validate() {
+        report.error("relay_selection", e);
+    }
+    
+    if !config.relay.enable && (config.relay.max_concurrent_sessions > 0 || config.relay.max_bandwidth_mbps > 0) {
+        report.warn("relay", "relay limits are ignored because relay.enable is false");
+    }
+    
+    config.network.validate(&mut report);
+    
+    report
+}
+
+/// 保存认证令牌
+pub fn save_auth_token(config: &ClientConfig, token: &str) -> Result<(), ConfigError> {
+    let token_path = Path::new(&config.auth.token_file);
+    let token_data = serde_json::json!({"token": token,"expires_at": chrono::Utc::now().timestamp() + config.auth.auth_timeout.as_secs() as i64});
+    
+    let token_str = serde_json::to_string_pretty(&token_data)?;
+    let mut file = File::create(token_path)?;
+    file.write_all(token_str.as_bytes())?;
+    Ok(())
+}
+
+/// 保存会话票据
+pub fn save_session_tickets(config: &ClientConfig, tickets: &HashMap<SocketAddr, Vec<u8>>) -> Result<(), ConfigError> {
+    let tickets_str = serde_json::to_string_pretty(tickets)?;
+    let mut file = File::create(&config.auth.session_ticket_file)?;
+    file.write_all(tickets_str.as_bytes())?;
+    Ok(())
+}
+
+/// 加载会话票据，读取后删除文件
+///
+/// 票据只能使用一次，删除后即使这次没有恢复会话，下次启动也不会再携带。
+pub fn load_session_tickets(config: &ClientConfig) -> Result<HashMap<SocketAddr, Vec<u8>>, ConfigError> {
+    let tickets_path = Path::new(&config.auth.session_ticket_file);
+    if !tickets_path.exists() {
+        return Ok(HashMap::new());
+    }
+    
+    let mut content = String::new();
+    File::open(tickets_path)?.read_to_string(&mut content)?;
+    std::fs::remove_file(tickets_path)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// 加载认证令牌
+pub fn load_auth_token(config: &ClientConfig) -> Result<Option<String>, ConfigError> {
+    let token_path = Path::new(&config.auth.token_file);
+    
+    if token_path.exists() {
+        let mut file = File::open(token_path)?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        
+        let token_data: serde_json::Value = serde_json::from_str(&content)?;
+        let token = token_data["token"].as_str().map(|s| s.to_string());
+        let expires_at = token_data["expires_at"].as_i64();
+        
+        // 检查令牌是否过期
+        if let (Some(token), Some(expires_at)) = (token.clone(), expires_at) {
+            if chrono::Utc::now().timestamp() < expires_at {
+                Ok(Some(token))
+            } else {
+                // 令牌已过期，删除令牌文件
+                std::fs::remove_file(token_path)?;
+                Ok(None)
+            }
+        } else {
+            Ok(token)
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+/// 完整检查配置文件
+///
+/// 除`validate_config`外还会解析所有地址，并检查引用的文件是否存在且可读。
+/// 收集所有错误而不是在第一个错误处停止。
+pub fn check(path: &str) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    
+    let mut content = String::new();
+    if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut content)) {
+        report.error("", format!("cannot read {}: {}", path, e));
+        return report;
+    }
+    
+    // 旧版本配置在内存中迁移后再检查，不写回文件
+    let migrated = ConfigMigrator::detect_version(&content)
+        .and_then(|version| config_migrator().migrate(&content, version));
+    let content = match migrated {
+        Ok(migrated) => migrated,
+        Err(e) => {
+            report.error("", format!("cannot migrate {}: {}", path, e));
+            return report;
+        }
+    };
+    
+    let config: ClientConfig = match toml::from_str(&content) {
+        Ok(config) => config,
+        Err(e) => {
+            report.error("", format!("cannot parse {}: {}", path, e));
+            return report;
+        }
+    };
+    
+    report = validate_config(&config);
+    
+    // 地址
+    if config.server.address.parse::<std::net::SocketAddr>().is_err() {
+        report.error("server.address", format!("invalid socket address: {}", config.server.address))
+            .suggest(format!("Use the form 203.0.113.1:{}", vpnet::DEFAULT_PORT));
+    }
+    for (i, address) in config.server.alternate_addresses.iter().enumerate() {
+        if address.parse::<std::net::SocketAddr>().is_err() {
+            report.error(format!("server.alternate_addresses[{}]", i), format!("invalid socket address: {}", address));
+        }
+    }
+    check_ipv4("virtual_device.ip", &config.virtual_device.ip, &mut report);
+    check_ipv4("virtual_device.subnet", &config.virtual_device.subnet, &mut report);
+    check_ipv4("virtual_device.gateway", &config.virtual_device.gateway, &mut report);
+    if let Some(ipv6) = config.virtual_device.ipv6_address.as_ref().filter(|_| config.virtual_device.enable_ipv6) {
+        if ipv6.parse::<std::net::Ipv6Addr>().is_err() {
+            report.error("virtual_device.ipv6_address", format!("invalid IPv6 address: {}", ipv6));
+        }
+    }
+    
+    // 引用的文件
+    check_file_or_parent("client.key_file", &config.client.key_file, &mut report);
+    check_file_or_parent("auth.token_file", &config.auth.token_file, &mut report);
+    if let Some(stats_file) = &config.monitor.stats_file {
+        check_file_or_parent("monitor.stats_file", stats_file, &mut report);
+    }
+    
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// 将默认配置改写为版本1的格式
+    fn v1_config() -> toml::Table {
+        let mut table: toml::Table = toml::from_str(&toml::to_string(&default_config()).unwrap()).unwrap();
+        table.remove("schema_version");
+        let device = table["virtual_device"].as_table_mut().unwrap();
+        device.remove("auto_config");
+        device.insert("device_mode".to_string(), toml::Value::String("Tun".to_string()));
+        device.insert("restart_cooldown".to_string(), toml::Value::Integer(5));
+        table["monitor"].as_table_mut().unwrap().remove("stats_interval");
+        table["monitor"].as_table_mut().unwrap()
+            .insert("interval".to_string(), toml::Value::Integer(30));
+        table["client"].as_table_mut().unwrap()
+            .insert("reconnect_interval".to_string(), toml::Value::Integer(10));
+        table
+    }
+    
+    #[test]
+    fn migrates_v1_config_to_current_version() {
+        let raw = toml::to_string(&v1_config()).unwrap();
+        let migrated = config_migrator().migrate(&raw, 1).unwrap();
+        let config: ClientConfig = toml::from_str(&migrated).unwrap();
+        
+        assert_eq!(config.schema_version, SCHEMA_VERSION);
+        assert!(config.virtual_device.auto_config);
+        assert_eq!(config.virtual_device.device_mode, DeviceMode::Tun);
+        assert_eq!(config.virtual_device.restart_cooldown, Duration::from_secs(5));
+        assert_eq!(config.monitor.interval, Duration::from_secs(30));
+        assert_eq!(config.monitor.stats_interval, Duration::from_secs(60));
+        assert_eq!(config.client.reconnect_interval, Duration::from_secs(10));
+    }
+    
+    #[test]
+    fn v3_migration_rejects_negative_durations() {
+        let mut table = v1_config();
+        table.insert("schema_version".to_string(), toml::Value::Integer(3));
+        table["client"].as_table_mut().unwrap()
+            .insert("reconnect_interval".to_string(), toml::Value::Integer(-1));
+        
+        let result = config_migrator().migrate(&toml::to_string(&table).unwrap(), 3);
+        assert!(matches!(result, Err(MigrationError::Step { from: 3, .. })));
+    }
+    
+    #[test]
+    fn durations_parse_from_compound_and_fractional_units() {
+        let mut table: toml::Table = toml::from_str(&toml::to_string(&default_config()).unwrap()).unwrap();
+        let monitor = table["monitor"].as_table_mut().unwrap();
+        monitor.insert("interval".to_string(), toml::Value::String("30s".to_string()));
+        monitor.insert("stats_interval".to_string(), toml::Value::String("0.5m".to_string()));
+        let config: ClientConfig = toml::from_str(&toml::to_string(&table).unwrap()).unwrap();
+        assert_eq!(config.monitor.interval, config.monitor.stats_interval);
+    }
+}
@@ -0,0 +1,21 @@
+/*!
+VPNet Client 管理API客户端
+
+创建请求服务端管理API的HTTP客户端，配置了`server.proxy`时经该HTTP代理发送。
+*/
+
+use std::time::Duration;
+use vpnet::ProxyConfig;
+
+/// 创建超时为`timeout`的HTTP客户端，`proxy`不为空时所有请求经代理发送
+pub fn api_client(timeout: Duration, proxy: Option<&ProxyConfig>) -> Result<reqwest::Client, reqwest::Error> {
+    let mut builder = reqwest::Client::builder().timeout(timeout);
+    if let Some(proxy) = proxy {
+        let mut proxy_settings = reqwest::Proxy::all(&proxy.url)?;
+        if let Some(username) = &proxy.username {
+            proxy_settings = proxy_settings.basic_auth(username, proxy.password.as_deref().unwrap_or_default());
+        }
+        builder = builder.proxy(proxy_settings);
+    }
+    builder.build()
+}
@@ -1,192 +1,519 @@
-/*!
-VPNet Client - 轻量级、快捷的虚拟局域网客户端
-
-客户端主要功能：
-- 连接到VPNet网络
-- 与服务端进行认证和授权
-- 管理本地虚拟设备
-- 处理网络数据转发
-- 跨平台支持
-- 轻便快捷的运行
-*/
-
-use clap::Parser;
-use env_logger::Builder;
-use log::LevelFilter;
-use std::fs::File;
-use std::io::Read;
-use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio::sync::{Mutex, RwLock};
-use tokio::time::Duration;
-use vpnet::{NetworkManager, DeviceManager, VirtualDeviceConfig};
-use vpnet_client::config::ClientConfig;
-use vpnet_client::auth::AuthClient;
-use vpnet_client::device::setup_virtual_device;
-use vpnet_client::network::connect_to_server;
-use vpnet_client::monitor::start_monitor;
-
-mod config;
-mod auth;
-mod device;
-mod network;
-mod monitor;
-mod utils;
-
-/// 命令行参数
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// 配置文件路径
-    #[arg(short, long, default_value = "vpnet-client.toml")]
-    config: String,
-    
-    /// 启用调试日志
-    #[arg(short, long, action = clap::ArgAction::SetTrue)]
-    debug: bool,
-    
-    /// 连接到指定服务器
-    #[arg(short, long)]
-    server: Option<String>,
-    
-    /// 虚拟IP地址
-    #[arg(long)]
-    virtual_ip: Option<String>,
-    
-    /// 以守护进程模式运行
-    #[arg(short, long, action = clap::ArgAction::SetTrue)]
-    daemon: bool,
-}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // 解析命令行参数
-    let args = Args::parse();
-    
-    // 初始化日志
-    let mut logger = Builder::new();
-    logger.filter(None, if args.debug {
-        LevelFilter::Debug
-    } else {
-        LevelFilter::Info
-    });
-    logger.init();
-    
-    log::info!("VPNet Client starting...");
-    
-    // 加载配置
-    let mut config_file = File::open(&args.config)?;
-    let mut config_content = String::new();
-    config_file.read_to_string(&mut config_content)?;
-    let mut config: ClientConfig = toml::from_str(&config_content)?;
-    
-    // 从命令行参数覆盖配置
-    if let Some(server) = args.server {
-        config.server.address = server;
-    }
-    if let Some(virtual_ip) = args.virtual_ip {
-        config.virtual_device.ip = virtual_ip;
-    }
-    
-    log::debug!("Config loaded: {:?}", config);
-    
-    // 解析服务器地址
-    let server_addr: SocketAddr = config.server.address.parse()?;
-    
-    // 初始化认证客户端
-    let auth_client = Arc::new(Mutex::new(AuthClient::new(
-        config.auth.clone(),
-        server_addr
-    )?));
-    
-    // 连接到服务器并进行认证
-    let auth_token = auth_client.lock().await.authenticate().await?;
-    log::info!("Authenticated with server successfully");
-    
-    // 初始化设备管理器
-    let mut device_manager = DeviceManager::new();
-    
-    // 创建虚拟设备
-    let virtual_ip = config.virtual_device.ip.parse()?;
-    let device_config = VirtualDeviceConfig {
-        name: config.virtual_device.name.clone(),
-        ip: virtual_ip,
-        subnet: config.virtual_device.subnet.parse()?,
-        gateway: config.virtual_device.gateway.parse()?,
-        mtu: config.virtual_device.mtu,
-        mac: None,
-    };
-    
-    let device_id = device_manager.create_device(device_config).await?;
-    let device = device_manager.get_device(&device_id).await?;
-    
-    // 启动虚拟设备
-    device.lock().await.start().await?;
-    log::info!("Virtual device {} started successfully", config.virtual_device.name);
-    
-    // 初始化网络管理器
-    let local_addr: SocketAddr = format!("0.0.0.0:{}", config.client.port)
-        .parse()?;
-    
-    let network_manager = Arc::new(Mutex::new(NetworkManager::new(
-        local_addr,
-        config.client.id.clone(),
-        config.client.name.clone(),
-        auth_client.lock().await.get_public_key().await,
-        auth_client.lock().await.get_private_key().await.as_ref()
-    )?));
-    
-    // 启动网络服务
-    network_manager.lock().await.start().await;
-    log::info!("Network service started on {}", local_addr);
-    
-    // 连接到服务器
-    let connection = connect_to_server(
-        network_manager.clone(),
-        server_addr,
-        auth_token.clone(),
-        config.server.clone()
-    ).await?;
-    log::info!("Connected to server {}", server_addr);
-    
-    // 启动监控任务
-    let monitor_handle = start_monitor(
-        network_manager.clone(),
-        device.clone(),
-        config.monitor.interval
-    );
-    
-    // 设置路由
-    if let Err(e) = device::setup_routes(&config.virtual_device).await {
-        log::warn!("Failed to setup routes: {}", e);
-    }
-    
-    log::info!("VPNet Client started successfully");
-    log::info!("Virtual IP: {}", config.virtual_device.ip);
-    log::info!("Subnet: {}", config.virtual_device.subnet);
-    log::info!("Connected to server: {}", config.server.address);
-    
-    // 主循环 - 处理信号和优雅关闭
-    let signal = tokio::signal::ctrl_c()
-        .await
-        .expect("Failed to listen for Ctrl+C");
-    
-    log::info!("Received shutdown signal, stopping services...");
-    
-    // 清理路由
-    if let Err(e) = device::cleanup_routes(&config.virtual_device).await {
-        log::warn!("Failed to cleanup routes: {}", e);
-    }
-    
-    // 关闭虚拟设备
-    device.lock().await.stop().await?;
-    
-    // 等待监控任务结束
-    if let Some(handle) = monitor_handle {
-        handle.abort();
-    }
-    
-    log::info!("VPNet Client stopped successfully");
-    
-    Ok(())
-}
+/*!
+VPNet Client - 轻量级、快捷的虚拟局域网客户端
+
+客户端主要功能：
+- 连接到VPNet网络
+- 与服务端进行认证和授权
+- 管理本地虚拟设备
+- 处理网络数据转发
+- 跨平台支持
+- 轻便快捷的运行
+*/
+
+use clap::Parser;
+use env_logger::Builder;
+use log::LevelFilter;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::sync::{Mutex, RwLock};
+use vpnet::{Capabilities, ConnectionManager, GeoLocation, NetworkManager, DeviceManager, DeviceWatcher, PeerStats, RelayLimiter, VirtualDeviceConfig};
+use vpnet_client::config::{ClientConfig, ClientConfigOverride, ClientOverride, Server, ServerOverride, VirtualDeviceOverride};
+use vpnet_client::auth::AuthClient;
+use vpnet_client::device::setup_virtual_device;
+use vpnet_client::network::connect_to_server;
+use vpnet_client::monitor::start_monitor;
+use vpnet_client::nonce::ApiNonceProvider;
+use vpnet_client::socks5::Socks5Server;
+use vpnet_client::version_check::run_version_check;
+
+mod config;
+mod auth;
+mod device;
+mod network;
+mod monitor;
+mod http;
+mod nonce;
+mod socks5;
+mod utils;
+mod version_check;
+
+/// 命令行参数
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// 配置文件路径
+    #[arg(short, long, default_value = "vpnet-client.toml")]
+    config: String,
+    
+    /// 检查配置文件后退出
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    check: bool,
+    
+    /// 启用调试日志
+    #[arg(short, long, action = clap::ArgAction::SetTrue)]
+    debug: bool,
+    
+    /// 连接到指定服务器
+    #[arg(short, long)]
+    server: Option<String>,
+    
+    /// 虚拟IP地址
+    #[arg(long)]
+    virtual_ip: Option<String>,
+    
+    /// 在本地回环地址的指定端口上启用SOCKS5代理
+    #[arg(long)]
+    socks5_port: Option<u16>,
+    
+    /// 以守护进程模式运行
+    #[arg(short, long, action = clap::ArgAction::SetTrue)]
+    daemon: bool,
+    
+    /// 检查与服务端的版本兼容性后退出，退出码0兼容、1不兼容、2无法查询
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    version_check: bool,
+    
+    /// 服务端管理API地址，默认为服务器地址的51821端口
+    #[arg(long)]
+    api_url: Option<String>,
+}
+
+/// 查询每台服务器节点信息的超时
+const SERVER_QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 从配置的服务器中选择要连接的一台
+///
+/// 只配置了`server.address`时直接使用；配置了其他服务器时查询各服务器的节点信息，
+/// 由连接管理器选择，没有回复的服务器不参与选择，都没有回复时使用`server.address`。
+/// 未启用就近优先时，按查询的往返时延等指标为声明了中继能力的服务器评分，选择分数最低的一台。
+async fn select_server(
+    connection_manager: &ConnectionManager,
+    server: &Server,
+    prefer_nearest: bool
+) -> Result<SocketAddr, Box<dyn std::error::Error>> {
+    let primary: SocketAddr = server.address.parse()?;
+    if server.alternate_addresses.is_empty() {
+        return Ok(primary);
+    }
+    
+    let mut addrs = vec![primary];
+    for address in &server.alternate_addresses {
+        addrs.push(address.parse()?);
+    }
+    let mut reachable = Vec::with_capacity(addrs.len());
+    let mut candidates = Vec::with_capacity(addrs.len());
+    let mut stats = HashMap::with_capacity(addrs.len());
+    for addr in addrs {
+        let started = Instant::now();
+        match vpnet::query_node_info(addr, SERVER_QUERY_TIMEOUT.min(server.timeout)).await {
+            Ok(info) => {
+                // 节点发现的一次往返作为尚未连接的服务器的往返时延
+                stats.insert(info.node_id.clone(), PeerStats {
+                    rtt_ms: started.elapsed().as_secs_f64() * 1000.0,
+                    ..Default::default()
+                });
+                reachable.push(addr);
+                candidates.push(info);
+            }
+            Err(e) => log::warn!("Server {} did not answer discovery: {}", addr, e),
+        }
+    }
+    
+    let scored = if prefer_nearest { None } else { connection_manager.select_relay(&candidates, &stats) };
+    let selected = scored.or_else(|| connection_manager.select_server(&candidates))
+        .and_then(|selected| candidates.iter().position(|info| std::ptr::eq(info, selected)));
+    match selected {
+        Some(index) => {
+            match connection_manager.distance_km(&candidates[index]) {
+                Some(distance) => log::info!("Selected server {} ({:.0} km away)", reachable[index], distance),
+                None => log::info!("Selected server {}", reachable[index]),
+            }
+            Ok(reachable[index])
+        }
+        None => Ok(primary),
+    }
+}
+
+/// 默认的服务端管理API地址
+fn default_api_url(server_address: &str) -> String {
+    let host = server_address.rsplit_once(':').map_or(server_address, |(host, _)| host);
+    format!("http://{}:51821", host)
+}
+
+/// 从命令行参数提取配置覆盖
+fn overrides_from_args(args: &Args) -> ClientConfigOverride {
+    ClientConfigOverride {
+        client: ClientOverride {
+            socks5_port: args.socks5_port,
+            ..Default::default()
+        },
+        server: ServerOverride {
+            address: args.server.clone(),
+            ..Default::default()
+        },
+        virtual_device: VirtualDeviceOverride {
+            ip: args.virtual_ip.clone(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // 解析命令行参数
+    let args = Args::parse();
+    
+    // 只检查配置
+    if args.check {
+        let report = config::check(&args.config);
+        for warning in &report.warnings {
+            eprintln!("warning: {}", warning);
+        }
+        if report.is_valid() {
+            println!("Configuration {} is valid", args.config);
+            std::process::exit(0);
+        }
+        
+        eprintln!("Configuration {} is invalid:", args.config);
+        for error in &report.errors {
+            eprintln!("  - {}", error);
+            if let Some(suggestion) = &error.suggestion {
+                eprintln!("    {}", suggestion);
+            }
+        }
+        std::process::exit(1);
+    }
+    
+    // 初始化日志
+    let mut logger = Builder::new();
+    logger.filter(None, if args.debug {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
+    });
+    logger.init();
+    
+    log::info!("VPNet Client starting...");
+    
+    // 加载配置
+    let mut config: ClientConfig = config::load_config(&args.config)?;
+    
+    // 依次应用环境变量和命令行参数的覆盖，命令行参数优先
+    config.merge(ClientConfigOverride::from_env());
+    config.merge(overrides_from_args(&args));
+    
+    log::debug!("Config loaded: {:?}", config);
+    
+    // 只检查与服务端的版本兼容性
+    if args.version_check {
+        let api_url = args.api_url.clone().unwrap_or_else(|| default_api_url(&config.server.address));
+        std::process::exit(run_version_check(&api_url, config.server.proxy.as_ref()).await);
+    }
+    
+    // 本地位置用于就近选择服务器和为中继评分
+    let location = config.geo.as_ref().map(|geo| GeoLocation {
+        latitude: geo.latitude,
+        longitude: geo.longitude,
+        country_code: geo.country_code.clone(),
+    });
+    let prefer_nearest = config.geo.as_ref().is_some_and(|geo| geo.prefer_nearest);
+    let mut connection_manager = ConnectionManager::new(location, prefer_nearest);
+    connection_manager.set_relay_weights(config.relay_selection);
+    
+    // 解析服务器地址，配置了多台服务器时按连接管理器的选择
+    let mut server_addr = select_server(&connection_manager, &config.server, prefer_nearest).await?;
+    // 经代理时`server_addr`改为本地桥接端点，HTTP请求仍发往服务端的实际地址
+    let upstream_addr = server_addr;
+    
+    // 握手随机数经所选服务器的管理API获取
+    let api_url = args.api_url.clone().unwrap_or_else(|| default_api_url(&server_addr.to_string()));
+    
+    // 经HTTP代理时，通过CONNECT隧道连接服务端的TCP端口，本地回环上的桥接端点代替服务端地址，
+    // 隧道断开后按重连策略重新建立
+    let tunnel_handle = match &config.server.proxy {
+        Some(proxy) => {
+            let stream = vpnet::http_connect(proxy, upstream_addr).await?;
+            vpnet::TcpStreamConfig::default().apply(&stream)?;
+            let bridge = vpnet::UdpTcpBridge::new(stream, None).await?;
+            server_addr = bridge.local_addr()?;
+            let proxy = proxy.clone();
+            let reconnect = config.client.reconnect_strategy();
+            let max_attempts = config.client.max_reconnect_attempts as usize;
+            Some(tokio::spawn(async move {
+                if let Err(e) = bridge.run_reconnecting(&proxy, upstream_addr, &reconnect, max_attempts).await {
+                    log::error!("Proxy tunnel closed: {}", e);
+                }
+            }))
+        }
+        None => None,
+    };
+    
+    // 初始化认证客户端
+    let auth_client = Arc::new(Mutex::new(AuthClient::new(
+        config.auth.clone(),
+        upstream_addr
+    )?));
+    
+    // 连接到服务器并进行认证
+    let auth_token = auth_client.lock().await.authenticate().await?;
+    log::info!("Authenticated with server successfully");
+    
+    // 初始化设备管理器
+    let mut device_manager = DeviceManager::new();
+    
+    // 创建虚拟设备
+    let virtual_ip = config.virtual_device.ip.parse()?;
+    let device_config = VirtualDeviceConfig {
+        name: config.virtual_device.name.clone(),
+        ip: virtual_ip,
+        subnet: config.virtual_device.subnet.parse()?,
+        gateway: config.virtual_device.gateway.parse()?,
+        mtu: config.virtual_device.mtu,
+        mac: None,
+        enable_ipv6: config.virtual_device.enable_ipv6,
+        // 客户端不发布前缀，经隧道接收服务端的路由器通告自动配置IPv6地址
+        ipv6_prefix: None,
+        device_mode: config.virtual_device.device_mode,
+        max_restart_attempts: config.virtual_device.max_restart_attempts,
+        restart_cooldown: config.virtual_device.restart_cooldown,
+        transforms: config.virtual_device.transforms.clone(),
+        flush_timeout: config.virtual_device.flush_timeout,
+        routes: config.virtual_device.routes.clone(),
+    };
+    
+    let device_id = device_manager.create_device(device_config).await?;
+    let device = device_manager.get_device(&device_id).await?;
+    
+    // 启动虚拟设备
+    device.lock().await.start().await?;
+    log::info!("Virtual device {} started successfully", config.virtual_device.name);
+    
+    // 监视虚拟网卡，被外部删除时自动重建
+    let watcher_handle = DeviceWatcher::new(device.clone()).spawn();
+    
+    // 初始化网络管理器
+    let local_addr: SocketAddr = format!("0.0.0.0:{}", config.client.port)
+        .parse()?;
+    
+    let network_manager = Arc::new(Mutex::new(NetworkManager::new(
+        vec![local_addr],
+        config.client.id.clone(),
+        config.client.name.clone(),
+        auth_client.lock().await.get_public_key().await
+    )?));
+    network_manager.lock().await.configure_sockets(&config.network.socket_config())?;
+    
+    network_manager.lock().await.set_nonce_provider(Arc::new(ApiNonceProvider::new(&api_url, config.server.proxy.as_ref())?));
+    network_manager.lock().await.set_heartbeat_strategy(config.client.heartbeat.clone());
+    if config.server.enable_compression {
+        network_manager.lock().await.set_compression(config.server.compression);
+    }
+    if let Some(strategy) = config.client.multipath {
+        network_manager.lock().await.set_multipath_strategy(strategy)?;
+        log::info!("Multipath forwarding enabled ({:?})", strategy);
+    }
+    
+    // 未启用中继时不声明中继能力，并拒绝为其他节点中继
+    let relay_limiter = if config.relay.enable {
+        network_manager.lock().await.set_capabilities(Capabilities::CAN_RELAY).await;
+        let max_sessions = Some(config.relay.max_concurrent_sessions).filter(|&max| max > 0);
+        let bytes_per_sec = Some(config.relay.max_bandwidth_mbps as u64 * 1_000_000 / 8).filter(|&rate| rate > 0);
+        log::info!("Relaying for other nodes enabled");
+        RelayLimiter::new(max_sessions, bytes_per_sec)
+    } else {
+        RelayLimiter::disabled()
+    };
+    network_manager.lock().await.set_relay_limiter(Arc::new(relay_limiter));
+    
+    // 网络切换后用签名密钥回应服务端的迁移验证，无需重新握手
+    let signing_key = vpnet::KeyPair::from_pkcs8(auth_client.lock().await.get_private_key().await.as_ref())?;
+    network_manager.lock().await.set_signing_key(signing_key);
+    
+    // 发往本节点的数据解密后写入虚拟网卡
+    let device_sender = device.lock().await.get_packet_sender();
+    network_manager.lock().await.set_device_sender(device_sender, config.virtual_device.device_mode);
+    
+    // 启动网络服务
+    network_manager.lock().await.start().await;
+    log::info!("Network service started on {}", local_addr);
+    
+    // 从虚拟网卡读出的数据包按目标虚拟IP转发，TAP模式下转发完整的以太网帧
+    // 转发任务由看门狗监督，空闲时也每秒报告存活，卡在转发中超过看门狗超时会被重启
+    let device_mode = config.virtual_device.device_mode;
+    let forwarder_device = device.clone();
+    let forwarder_manager = network_manager.clone();
+    network_manager.lock().await.supervise("device-forwarder", move |keepalive| {
+        let (device, network_manager) = (forwarder_device.clone(), forwarder_manager.clone());
+        Box::pin(async move {
+            let mut device_reader = device.lock().await.reader();
+            let mut ticker = tokio::time::interval(Duration::from_secs(1));
+            let mut buf = vec![0u8; vpnet::MAX_DATAGRAM_SIZE];
+            loop {
+                let len = tokio::select! {
+                    _ = ticker.tick() => {
+                        let _ = keepalive.try_send(());
+                        continue;
+                    }
+                    read = device_reader.read(&mut buf) => match read {
+                        Ok(0) => return,
+                        Ok(len) => len,
+                        Err(e) => {
+                            log::error!("Failed to read from virtual device: {}", e);
+                            return;
+                        }
+                    },
+                };
+                if let Err(e) = network_manager.lock().await.forward_frame(&buf[..len], device_mode).await {
+                    log::debug!("Failed to forward packet from virtual device: {}", e);
+                }
+            }
+        })
+    });
+    
+    // 服务端重新分配虚拟IP时就地更新虚拟网卡
+    let mut ip_assignments = network_manager.lock().await.subscribe_ip_assignments();
+    let assignment_device = device.clone();
+    let assignment_handle = tokio::spawn(async move {
+        while let Ok(assignment) = ip_assignments.recv().await {
+            let (ip, subnet) = match (assignment.virtual_ip.parse(), assignment.subnet.parse()) {
+                (Ok(ip), Ok(subnet)) => (ip, subnet),
+                _ => {
+                    log::warn!("Invalid IP assignment: {}/{}", assignment.virtual_ip, assignment.subnet);
+                    continue;
+                }
+            };
+            if let Err(e) = assignment_device.lock().await.set_ip(ip, subnet) {
+                log::error!("Failed to apply virtual IP {}: {}", ip, e);
+            }
+        }
+    });
+    
+    // 上次正常退出时保存的会话票据，有效期内连接可以跳过完整握手
+    match config::load_session_tickets(&config) {
+        Ok(tickets) if !tickets.is_empty() => {
+            network_manager.lock().await.restore_session_tickets(tickets).await;
+        }
+        Ok(_) => {}
+        Err(e) => log::warn!("Failed to load session tickets: {}", e),
+    }
+    
+    // 连接到服务器，失败时按重连策略重试`max_reconnect_attempts`次
+    let mut reconnect_delays = config.client.reconnect_strategy()
+        .iterator()
+        .take(config.client.max_reconnect_attempts as usize);
+    let connection = loop {
+        match connect_to_server(
+            network_manager.clone(),
+            server_addr,
+            auth_token.clone(),
+            config.server.clone()
+        ).await {
+            Ok(connection) => break connection,
+            Err(e) => match reconnect_delays.next() {
+                Some(delay) => {
+                    log::warn!("Failed to connect to server {}: {}, retrying in {:?}", server_addr, e, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                None => return Err(e.into()),
+            },
+        }
+    };
+    log::info!("Connected to server {}", server_addr);
+    
+    // 定期经服务端探测与其他节点之间的往返时延，由服务端汇总为时延矩阵。
+    // 探测经共享节点表的句柄发送，不占用网络管理器的锁
+    let latency_prober = network_manager.lock().await.latency_prober();
+    let latency_handle = tokio::spawn(async move {
+        let interval = std::time::Duration::from_secs(vpnet::constants::LATENCY_PROBE_INTERVAL);
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let peers = latency_prober.peers().await;
+            let server_id = match peers.iter().find(|peer| peer.address == server_addr) {
+                Some(server) => server.node_id.clone(),
+                None => continue,
+            };
+            for peer in peers.iter().filter(|peer| peer.node_id != server_id) {
+                if let Err(e) = latency_prober.send(&server_id, &peer.node_id).await {
+                    log::debug!("Failed to send latency probe for {}: {}", peer.node_id, e);
+                }
+            }
+        }
+    });
+    
+    // 不能使用虚拟网卡的应用经本地SOCKS5代理访问虚拟网络
+    let socks5_handle = match config.client.socks5_port {
+        Some(port) => {
+            let socks5_addr = SocketAddr::from(([127, 0, 0, 1], port));
+            let socks5 = Socks5Server::bind(socks5_addr, config.client.socks5_auth.clone()).await?;
+            log::info!("SOCKS5 proxy listening on {}", socks5_addr);
+            Some(tokio::spawn(socks5.run()))
+        }
+        None => None,
+    };
+    
+    // 启动监控任务
+    let monitor_handle = start_monitor(
+        network_manager.clone(),
+        device.clone(),
+        config.monitor.interval.as_secs()
+    );
+    
+    log::info!("VPNet Client started successfully");
+    log::info!("Virtual IP: {}", config.virtual_device.ip);
+    log::info!("Subnet: {}", config.virtual_device.subnet);
+    log::info!("Connected to server: {}", config.server.address);
+    
+    // 主循环 - 处理信号和优雅关闭
+    let signal = tokio::signal::ctrl_c()
+        .await
+        .expect("Failed to listen for Ctrl+C");
+    
+    log::info!("Received shutdown signal, stopping services...");
+    
+    // 正常断开与服务器的连接，保存其签发的会话票据
+    {
+        let network_manager = network_manager.lock().await;
+        let server_id = network_manager.get_peers().await
+            .into_iter()
+            .find(|peer| peer.address == server_addr)
+            .map(|server| server.node_id);
+        if let Some(server_id) = server_id {
+            match network_manager.disconnect(&server_id, "shutdown", Duration::from_secs(1)).await {
+                Ok(true) => {
+                    if let Err(e) = config::save_session_tickets(&config, &network_manager.session_tickets().await) {
+                        log::warn!("Failed to save session tickets: {}", e);
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => log::warn!("Failed to disconnect from server: {}", e),
+            }
+        }
+    }
+    
+    // 关闭虚拟设备，同时删除配置的静态路由
+    network_manager.lock().await.stop();
+    assignment_handle.abort();
+    latency_handle.abort();
+    watcher_handle.abort();
+    device.lock().await.stop().await?;
+    
+    // 等待监控任务结束
+    if let Some(handle) = monitor_handle {
+        handle.abort();
+    }
+    if let Some(handle) = tunnel_handle {
+        handle.abort();
+    }
+    if let Some(handle) = socks5_handle {
+        handle.abort();
+    }
+    
+    log::info!("VPNet Client stopped successfully");
+    
+    Ok(())
+}
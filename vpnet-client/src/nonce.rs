@@ -0,0 +1,90 @@
+/*!
+VPNet Client 握手随机数模块
+
+服务端启用认证时，握手请求必须携带服务端签发的一次性随机数以防止重放：
+- 每次握手前通过服务端管理API的`GET /api/auth/nonce`获取随机数
+- 随机数只能使用一次，重连时重新获取
+*/
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use base64::Engine;
+use serde::Deserialize;
+use vpnet::{BoxFuture, NonceProvider, ProxyConfig, VpnetError};
+use crate::http::api_client;
+
+/// 获取随机数的超时
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 服务端`GET /api/auth/nonce`的响应
+#[derive(Debug, Clone, Deserialize)]
+pub struct NonceResponse {
+    /// Base64编码的32字节随机数
+    pub nonce: String,
+    /// 有效期（秒）
+    pub expires_in: u64,
+}
+
+/// 通过服务端管理API获取握手随机数
+pub struct ApiNonceProvider {
+    api_url: String,
+    client: reqwest::Client,
+}
+
+impl ApiNonceProvider {
+    /// 创建随机数来源，`api_url`为管理API地址，例如`http://10.0.0.1:51821`，`proxy`不为空时经该代理请求
+    pub fn new(api_url: &str, proxy: Option<&ProxyConfig>) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            client: api_client(REQUEST_TIMEOUT, proxy)?,
+        })
+    }
+    
+    /// 请求一个新的随机数
+    pub async fn fetch(&self) -> Result<Vec<u8>, VpnetError> {
+        let url = format!("{}/api/auth/nonce", self.api_url);
+        let response = self.client.get(url).send().await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| VpnetError::Retry {
+                reason: format!("nonce request failed: {}", e),
+                retry_after: None,
+            })?;
+        let body: NonceResponse = response.json().await
+            .map_err(|e| VpnetError::Serialization(e.to_string()))?;
+        decode_nonce(&body.nonce)
+    }
+}
+
+impl NonceProvider for ApiNonceProvider {
+    fn fetch_nonce(&self, _server: SocketAddr) -> BoxFuture<'_, Result<Vec<u8>, VpnetError>> {
+        Box::pin(self.fetch())
+    }
+}
+
+/// 解码响应中的随机数，长度必须为32字节
+fn decode_nonce(encoded: &str) -> Result<Vec<u8>, VpnetError> {
+    let nonce = base64::engine::general_purpose::STANDARD.decode(encoded)
+        .map_err(|_| VpnetError::Other("Invalid nonce encoding"))?;
+    if nonce.len() != 32 {
+        return Err(VpnetError::Other("Invalid nonce length"));
+    }
+    Ok(nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn decodes_32_byte_nonce() {
+        let encoded = base64::engine::general_purpose::STANDARD.encode([7u8; 32]);
+        assert_eq!(decode_nonce(&encoded).unwrap(), vec![7u8; 32]);
+    }
+    
+    #[test]
+    fn rejects_malformed_nonce() {
+        let short = base64::engine::general_purpose::STANDARD.encode([7u8; 16]);
+        assert!(decode_nonce(&short).is_err());
+        assert!(decode_nonce("not base64!").is_err());
+    }
+}
@@ -0,0 +1,479 @@
+/*!
+VPNet Client SOCKS5代理模块
+
+无法使用虚拟网卡的应用可以经本地SOCKS5代理访问虚拟网络，包括：
+- RFC 1928的CONNECT命令，目标可以是IPv4、IPv6地址或域名
+- 无认证和RFC 1929用户名/密码认证
+- 目标连接按系统路由表发出，虚拟网络内的地址经虚拟网卡进入VPN
+- 握手超时，未在限定时间内发出请求的连接会被关闭
+*/
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use crate::config::Socks5Auth;
+
+/// 认证协商和读取请求的超时时间
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 接受连接失败（如文件描述符耗尽）后重试前的等待时间
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// SOCKS协议版本
+const SOCKS_VERSION: u8 = 0x05;
+
+/// 用户名/密码认证子协商版本
+const AUTH_VERSION: u8 = 0x01;
+
+/// 认证方式：无认证
+const METHOD_NO_AUTH: u8 = 0x00;
+
+/// 认证方式：用户名/密码
+const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+
+/// 认证方式：没有可接受的方式
+const METHOD_NO_ACCEPTABLE: u8 = 0xff;
+
+/// 命令：CONNECT
+const CMD_CONNECT: u8 = 0x01;
+
+/// 地址类型
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// 应答码
+const REP_SUCCEEDED: u8 = 0x00;
+const REP_GENERAL_FAILURE: u8 = 0x01;
+const REP_HOST_UNREACHABLE: u8 = 0x04;
+const REP_CONNECTION_REFUSED: u8 = 0x05;
+const REP_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const REP_ADDRESS_TYPE_NOT_SUPPORTED: u8 = 0x08;
+
+/// SOCKS5代理错误
+#[derive(Error, Debug)]
+pub enum Socks5Error {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    
+    #[error("Unsupported SOCKS version: {0}")]
+    UnsupportedVersion(u8),
+    
+    #[error("No acceptable authentication method")]
+    NoAcceptableMethod,
+    
+    #[error("Authentication failed for user {0}")]
+    AuthFailed(String),
+    
+    #[error("Unsupported command: {0}")]
+    UnsupportedCommand(u8),
+    
+    #[error("Unsupported address type: {0}")]
+    UnsupportedAddressType(u8),
+    
+    #[error("Handshake timed out")]
+    HandshakeTimeout,
+}
+
+/// CONNECT请求的目标
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TargetAddr {
+    Ip(SocketAddr),
+    Domain(String, u16),
+}
+
+impl TargetAddr {
+    async fn connect(&self) -> io::Result<TcpStream> {
+        match self {
+            TargetAddr::Ip(addr) => TcpStream::connect(addr).await,
+            TargetAddr::Domain(host, port) => TcpStream::connect((host.as_str(), *port)).await,
+        }
+    }
+}
+
+impl std::fmt::Display for TargetAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TargetAddr::Ip(addr) => write!(f, "{}", addr),
+            TargetAddr::Domain(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+/// 本地SOCKS5代理服务器
+pub struct Socks5Server {
+    listener: TcpListener,
+    auth: Option<Arc<Socks5Auth>>,
+    handshake_timeout: Duration,
+}
+
+impl Socks5Server {
+    /// 在`addr`上监听，`auth`为`None`时不要求认证
+    pub async fn bind(addr: SocketAddr, auth: Option<Socks5Auth>) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            auth: auth.map(Arc::new),
+            handshake_timeout: HANDSHAKE_TIMEOUT,
+        })
+    }
+    
+    /// 实际监听的地址
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+    
+    /// 接受连接，每个连接在单独的任务中处理
+    ///
+    /// 接受连接失败时记录日志后继续监听，不会因单次失败停止代理。
+    pub async fn run(self) {
+        loop {
+            let (stream, peer_addr) = match self.listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    log::warn!("Failed to accept SOCKS5 connection: {}", e);
+                    tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+            let auth = self.auth.clone();
+            let handshake_timeout = self.handshake_timeout;
+            tokio::spawn(async move {
+                if let Err(e) = handle_client(stream, auth.as_deref(), handshake_timeout).await {
+                    log::debug!("SOCKS5 connection from {} failed: {}", peer_addr, e);
+                }
+            });
+        }
+    }
+}
+
+/// 处理一个客户端连接：协商认证方式、读取请求、连接目标并双向转发
+async fn handle_client(mut stream: TcpStream, auth: Option<&Socks5Auth>, handshake_timeout: Duration) -> Result<(), Socks5Error> {
+    let target = match tokio::time::timeout(handshake_timeout, handshake(&mut stream, auth)).await {
+        Ok(target) => target,
+        Err(_) => return Err(Socks5Error::HandshakeTimeout),
+    };
+    let target = match target {
+        Ok(target) => target,
+        Err(e) => {
+            let rep = match e {
+                Socks5Error::UnsupportedCommand(_) => REP_COMMAND_NOT_SUPPORTED,
+                Socks5Error::UnsupportedAddressType(_) => REP_ADDRESS_TYPE_NOT_SUPPORTED,
+                _ => return Err(e),
+            };
+            write_reply(&mut stream, rep, None).await?;
+            return Err(e);
+        }
+    };
+    
+    let mut upstream = match target.connect().await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            let rep = match e.kind() {
+                io::ErrorKind::ConnectionRefused => REP_CONNECTION_REFUSED,
+                io::ErrorKind::TimedOut => REP_HOST_UNREACHABLE,
+                _ => REP_GENERAL_FAILURE,
+            };
+            write_reply(&mut stream, rep, None).await?;
+            return Err(e.into());
+        }
+    };
+    write_reply(&mut stream, REP_SUCCEEDED, upstream.local_addr().ok()).await?;
+    log::debug!("SOCKS5 connected to {}", target);
+    
+    tokio::io::copy_bidirectional(&mut stream, &mut upstream).await?;
+    Ok(())
+}
+
+/// 协商认证方式并读取请求
+async fn handshake(stream: &mut TcpStream, auth: Option<&Socks5Auth>) -> Result<TargetAddr, Socks5Error> {
+    negotiate(stream, auth).await?;
+    read_request(stream).await
+}
+
+/// 协商认证方式，配置了认证时只接受用户名/密码认证
+async fn negotiate(stream: &mut TcpStream, auth: Option<&Socks5Auth>) -> Result<(), Socks5Error> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header).await?;
+    if header[0] != SOCKS_VERSION {
+        return Err(Socks5Error::UnsupportedVersion(header[0]));
+    }
+    let mut methods = vec![0u8; header[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    
+    let method = if auth.is_some() { METHOD_USERNAME_PASSWORD } else { METHOD_NO_AUTH };
+    if !methods.contains(&method) {
+        stream.write_all(&[SOCKS_VERSION, METHOD_NO_ACCEPTABLE]).await?;
+        return Err(Socks5Error::NoAcceptableMethod);
+    }
+    stream.write_all(&[SOCKS_VERSION, method]).await?;
+    
+    match auth {
+        Some(auth) => authenticate(stream, auth).await,
+        None => Ok(()),
+    }
+}
+
+/// RFC 1929用户名/密码认证
+async fn authenticate(stream: &mut TcpStream, auth: &Socks5Auth) -> Result<(), Socks5Error> {
+    let version = stream.read_u8().await?;
+    if version != AUTH_VERSION {
+        return Err(Socks5Error::UnsupportedVersion(version));
+    }
+    let username = read_field(stream).await?;
+    let password = read_field(stream).await?;
+    
+    let accepted = constant_time_eq(&username, auth.username.as_bytes())
+        & constant_time_eq(&password, auth.password.as_bytes());
+    stream.write_all(&[AUTH_VERSION, if accepted { 0x00 } else { 0x01 }]).await?;
+    if accepted {
+        Ok(())
+    } else {
+        Err(Socks5Error::AuthFailed(String::from_utf8_lossy(&username).into_owned()))
+    }
+}
+
+/// 读取单字节长度前缀的字段
+async fn read_field(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let len = stream.read_u8().await?;
+    let mut field = vec![0u8; len as usize];
+    stream.read_exact(&mut field).await?;
+    Ok(field)
+}
+
+/// 读取请求，只支持CONNECT命令
+async fn read_request(stream: &mut TcpStream) -> Result<TargetAddr, Socks5Error> {
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    let [version, command, _, address_type] = header;
+    if version != SOCKS_VERSION {
+        return Err(Socks5Error::UnsupportedVersion(version));
+    }
+    if command != CMD_CONNECT {
+        return Err(Socks5Error::UnsupportedCommand(command));
+    }
+    
+    let target = match address_type {
+        ATYP_IPV4 => {
+            let mut octets = [0u8; 4];
+            stream.read_exact(&mut octets).await?;
+            TargetAddr::Ip(SocketAddr::new(Ipv4Addr::from(octets).into(), stream.read_u16().await?))
+        }
+        ATYP_IPV6 => {
+            let mut octets = [0u8; 16];
+            stream.read_exact(&mut octets).await?;
+            TargetAddr::Ip(SocketAddr::new(Ipv6Addr::from(octets).into(), stream.read_u16().await?))
+        }
+        ATYP_DOMAIN => {
+            let host = read_field(stream).await?;
+            let port = stream.read_u16().await?;
+            TargetAddr::Domain(String::from_utf8_lossy(&host).into_owned(), port)
+        }
+        other => return Err(Socks5Error::UnsupportedAddressType(other)),
+    };
+    Ok(target)
+}
+
+/// 发送应答，`bound`为连接目标使用的本地地址，未知时填写0.0.0.0:0
+async fn write_reply(stream: &mut TcpStream, rep: u8, bound: Option<SocketAddr>) -> io::Result<()> {
+    let bound = bound.unwrap_or_else(|| SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0));
+    let mut reply = vec![SOCKS_VERSION, rep, 0x00];
+    match bound {
+        SocketAddr::V4(addr) => {
+            reply.push(ATYP_IPV4);
+            reply.extend_from_slice(&addr.ip().octets());
+        }
+        SocketAddr::V6(addr) => {
+            reply.push(ATYP_IPV6);
+            reply.extend_from_slice(&addr.ip().octets());
+        }
+    }
+    reply.extend_from_slice(&bound.port().to_be_bytes());
+    stream.write_all(&reply).await
+}
+
+/// 比较耗时与内容无关的字节串比较
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// 启动代理，返回其监听地址
+    async fn start_proxy(auth: Option<Socks5Auth>, handshake_timeout: Duration) -> SocketAddr {
+        let mut server = Socks5Server::bind("127.0.0.1:0".parse().unwrap(), auth).await.unwrap();
+        server.handshake_timeout = handshake_timeout;
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+        addr
+    }
+    
+    /// 启动只应答一个请求的HTTP服务器
+    async fn start_http_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            assert!(request.starts_with(b"GET /hello HTTP/1.1\r\n"));
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello").await.unwrap();
+        });
+        addr
+    }
+    
+    fn connect_request(target: SocketAddr) -> Vec<u8> {
+        let SocketAddr::V4(target) = target else { panic!("IPv4 target expected") };
+        let mut request = vec![SOCKS_VERSION, CMD_CONNECT, 0x00, ATYP_IPV4];
+        request.extend_from_slice(&target.ip().octets());
+        request.extend_from_slice(&target.port().to_be_bytes());
+        request
+    }
+    
+    /// 读取CONNECT应答（IPv4绑定地址），返回应答码
+    async fn read_reply(stream: &mut TcpStream) -> u8 {
+        let mut reply = [0u8; 10];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[0], SOCKS_VERSION);
+        reply[1]
+    }
+    
+    /// 经已建立的代理连接发送HTTP请求，返回完整响应
+    async fn http_get(mut stream: TcpStream) -> String {
+        stream.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+    
+    fn credentials() -> Socks5Auth {
+        Socks5Auth { username: "user".to_string(), password: "secret".to_string() }
+    }
+    
+    fn auth_request(username: &str, password: &str) -> Vec<u8> {
+        let mut request = vec![AUTH_VERSION, username.len() as u8];
+        request.extend_from_slice(username.as_bytes());
+        request.push(password.len() as u8);
+        request.extend_from_slice(password.as_bytes());
+        request
+    }
+    
+    #[tokio::test]
+    async fn http_request_reaches_target_without_auth() {
+        let target = start_http_server().await;
+        let proxy = start_proxy(None, HANDSHAKE_TIMEOUT).await;
+        
+        let mut stream = TcpStream::connect(proxy).await.unwrap();
+        stream.write_all(&[SOCKS_VERSION, 1, METHOD_NO_AUTH]).await.unwrap();
+        let mut choice = [0u8; 2];
+        stream.read_exact(&mut choice).await.unwrap();
+        assert_eq!(choice, [SOCKS_VERSION, METHOD_NO_AUTH]);
+        
+        stream.write_all(&connect_request(target)).await.unwrap();
+        assert_eq!(read_reply(&mut stream).await, REP_SUCCEEDED);
+        
+        let response = http_get(stream).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("hello"));
+    }
+    
+    #[tokio::test]
+    async fn http_request_reaches_domain_target_with_auth() {
+        let target = start_http_server().await;
+        let proxy = start_proxy(Some(credentials()), HANDSHAKE_TIMEOUT).await;
+        
+        let mut stream = TcpStream::connect(proxy).await.unwrap();
+        stream.write_all(&[SOCKS_VERSION, 2, METHOD_NO_AUTH, METHOD_USERNAME_PASSWORD]).await.unwrap();
+        let mut choice = [0u8; 2];
+        stream.read_exact(&mut choice).await.unwrap();
+        assert_eq!(choice, [SOCKS_VERSION, METHOD_USERNAME_PASSWORD]);
+        
+        stream.write_all(&auth_request("user", "secret")).await.unwrap();
+        let mut status = [0u8; 2];
+        stream.read_exact(&mut status).await.unwrap();
+        assert_eq!(status, [AUTH_VERSION, 0x00]);
+        
+        let host = b"127.0.0.1";
+        let mut request = vec![SOCKS_VERSION, CMD_CONNECT, 0x00, ATYP_DOMAIN, host.len() as u8];
+        request.extend_from_slice(host);
+        request.extend_from_slice(&target.port().to_be_bytes());
+        stream.write_all(&request).await.unwrap();
+        assert_eq!(read_reply(&mut stream).await, REP_SUCCEEDED);
+        
+        assert!(http_get(stream).await.ends_with("hello"));
+    }
+    
+    #[tokio::test]
+    async fn wrong_password_is_rejected() {
+        let proxy = start_proxy(Some(credentials()), HANDSHAKE_TIMEOUT).await;
+        
+        let mut stream = TcpStream::connect(proxy).await.unwrap();
+        stream.write_all(&[SOCKS_VERSION, 1, METHOD_USERNAME_PASSWORD]).await.unwrap();
+        let mut choice = [0u8; 2];
+        stream.read_exact(&mut choice).await.unwrap();
+        stream.write_all(&auth_request("user", "wrong")).await.unwrap();
+        let mut status = [0u8; 2];
+        stream.read_exact(&mut status).await.unwrap();
+        assert_eq!(status, [AUTH_VERSION, 0x01]);
+        
+        let mut rest = Vec::new();
+        assert_eq!(stream.read_to_end(&mut rest).await.unwrap(), 0);
+    }
+    
+    #[tokio::test]
+    async fn client_without_acceptable_method_is_refused() {
+        let proxy = start_proxy(Some(credentials()), HANDSHAKE_TIMEOUT).await;
+        
+        let mut stream = TcpStream::connect(proxy).await.unwrap();
+        stream.write_all(&[SOCKS_VERSION, 1, METHOD_NO_AUTH]).await.unwrap();
+        let mut choice = [0u8; 2];
+        stream.read_exact(&mut choice).await.unwrap();
+        assert_eq!(choice, [SOCKS_VERSION, METHOD_NO_ACCEPTABLE]);
+    }
+    
+    #[tokio::test]
+    async fn refused_target_and_unsupported_command_are_reported() {
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let proxy = start_proxy(None, HANDSHAKE_TIMEOUT).await;
+        
+        let mut stream = TcpStream::connect(proxy).await.unwrap();
+        stream.write_all(&[SOCKS_VERSION, 1, METHOD_NO_AUTH]).await.unwrap();
+        stream.read_exact(&mut [0u8; 2]).await.unwrap();
+        stream.write_all(&connect_request(closed)).await.unwrap();
+        assert_eq!(read_reply(&mut stream).await, REP_CONNECTION_REFUSED);
+        
+        let mut stream = TcpStream::connect(proxy).await.unwrap();
+        stream.write_all(&[SOCKS_VERSION, 1, METHOD_NO_AUTH]).await.unwrap();
+        stream.read_exact(&mut [0u8; 2]).await.unwrap();
+        let mut bind = connect_request(closed);
+        bind[1] = 0x02;
+        stream.write_all(&bind).await.unwrap();
+        assert_eq!(read_reply(&mut stream).await, REP_COMMAND_NOT_SUPPORTED);
+    }
+    
+    #[tokio::test]
+    async fn idle_handshake_is_closed_after_timeout() {
+        let proxy = start_proxy(None, Duration::from_millis(100)).await;
+        
+        let mut stream = TcpStream::connect(proxy).await.unwrap();
+        stream.write_all(&[SOCKS_VERSION, 1, METHOD_NO_AUTH]).await.unwrap();
+        let mut choice = [0u8; 2];
+        stream.read_exact(&mut choice).await.unwrap();
+        
+        let mut rest = Vec::new();
+        let read = tokio::time::timeout(Duration::from_secs(2), stream.read_to_end(&mut rest)).await;
+        assert_eq!(read.unwrap().unwrap(), 0);
+    }
+}
@@ -0,0 +1,161 @@
+/*!
+VPNet Client 版本检查模块
+
+客户端与服务端的版本不一致时可能出现协议不兼容，连接前可以先检查：
+- 通过服务端管理API的`GET /api/version`查询服务端版本
+- 主版本号相同视为兼容，次版本号不同时给出警告；1.0之前次版本号不同即不兼容
+- 以退出码表示结果：0兼容，1不兼容，2无法查询
+*/
+
+use std::time::Duration;
+use serde::Deserialize;
+use thiserror::Error;
+use semver::Version;
+use vpnet::ProxyConfig;
+use crate::http::api_client;
+
+/// 查询服务端版本的超时
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 版本检查错误
+#[derive(Error, Debug)]
+pub enum VersionCheckError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    
+    #[error("Invalid server version: {0}")]
+    InvalidVersion(String),
+}
+
+/// 服务端`GET /api/version`的响应
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerVersion {
+    pub version: String,
+    #[serde(default)]
+    pub protocol_version: u8,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+/// 客户端与服务端版本的兼容性
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionCompatibility {
+    /// 主版本号和次版本号都相同
+    Compatible,
+    /// 主版本号相同，次版本号不同，部分功能可能不可用
+    MinorMismatch,
+    /// 主版本号不同，或主版本号为0时次版本号不同
+    Incompatible,
+}
+
+impl VersionCompatibility {
+    /// 按语义化版本规则比较，主版本号为0时次版本号的变化也是不兼容的
+    pub fn between(client: &Version, server: &Version) -> Self {
+        if client.major != server.major || (client.major == 0 && client.minor != server.minor) {
+            VersionCompatibility::Incompatible
+        } else if client.minor != server.minor {
+            VersionCompatibility::MinorMismatch
+        } else {
+            VersionCompatibility::Compatible
+        }
+    }
+    
+    /// 进程退出码，不兼容时为1
+    pub fn exit_code(self) -> i32 {
+        match self {
+            VersionCompatibility::Compatible | VersionCompatibility::MinorMismatch => 0,
+            VersionCompatibility::Incompatible => 1,
+        }
+    }
+}
+
+/// 查询服务端版本，`api_url`为管理API地址，例如`http://10.0.0.1:51821`，`proxy`不为空时经该代理请求
+pub async fn fetch_server_version(api_url: &str, proxy: Option<&ProxyConfig>) -> Result<ServerVersion, VersionCheckError> {
+    let url = format!("{}/api/version", api_url.trim_end_matches('/'));
+    let client = api_client(REQUEST_TIMEOUT, proxy)?;
+    let response = client.get(url).send().await?.error_for_status()?;
+    Ok(response.json().await?)
+}
+
+/// 检查与服务端的版本兼容性并打印结果，返回进程退出码
+pub async fn run_version_check(api_url: &str, proxy: Option<&ProxyConfig>) -> i32 {
+    let client_version = match Version::parse(vpnet::VERSION) {
+        Ok(version) => version,
+        Err(e) => {
+            eprintln!("Invalid client version {}: {}", vpnet::VERSION, e);
+            return 2;
+        }
+    };
+    
+    let server = match fetch_server_version(api_url, proxy).await {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Failed to query server version from {}: {}", api_url, e);
+            return 2;
+        }
+    };
+    let server_version = match Version::parse(&server.version) {
+        Ok(version) => version,
+        Err(_) => {
+            eprintln!("{}", VersionCheckError::InvalidVersion(server.version));
+            return 2;
+        }
+    };
+    
+    let compatibility = VersionCompatibility::between(&client_version, &server_version);
+    match compatibility {
+        VersionCompatibility::Compatible => {
+            println!("Client {} is compatible with server {}", client_version, server_version);
+        }
+        VersionCompatibility::MinorMismatch => {
+            println!(
+                "warning: client {} and server {} differ in minor version, some features may be unavailable",
+                client_version, server_version
+            );
+        }
+        VersionCompatibility::Incompatible => {
+            println!(
+                "Client {} is incompatible with server {}: {} versions differ, upgrade the {}",
+                client_version,
+                server_version,
+                if client_version.major != server_version.major { "major" } else { "pre-1.0 minor" },
+                if client_version < server_version { "client" } else { "server" }
+            );
+        }
+    }
+    if server.protocol_version != vpnet::PROTOCOL_VERSION {
+        println!(
+            "warning: server uses protocol version {}, client uses {}",
+            server.protocol_version, vpnet::PROTOCOL_VERSION
+        );
+    }
+    if !server.capabilities.is_empty() {
+        println!("Server capabilities: {}", server.capabilities.join(", "));
+    }
+    
+    compatibility.exit_code()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn between(client: &str, server: &str) -> VersionCompatibility {
+        VersionCompatibility::between(&Version::parse(client).unwrap(), &Version::parse(server).unwrap())
+    }
+    
+    #[test]
+    fn minor_mismatch_is_compatible_from_1_0() {
+        assert_eq!(between("1.2.0", "1.2.5"), VersionCompatibility::Compatible);
+        assert_eq!(between("1.2.0", "1.3.0"), VersionCompatibility::MinorMismatch);
+        assert_eq!(between("1.2.0", "1.3.0").exit_code(), 0);
+        assert_eq!(between("1.2.0", "2.0.0"), VersionCompatibility::Incompatible);
+    }
+    
+    #[test]
+    fn minor_mismatch_is_incompatible_before_1_0() {
+        assert_eq!(between("0.1.0", "0.1.3"), VersionCompatibility::Compatible);
+        assert_eq!(between("0.1.0", "0.2.0"), VersionCompatibility::Incompatible);
+        assert_eq!(between("0.1.0", "0.2.0").exit_code(), 1);
+    }
+}
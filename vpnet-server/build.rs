@@ -0,0 +1,6 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "debug-grpc")]
+    tonic_build::compile_protos("proto/debug.proto")?;
+    
+    Ok(())
+}
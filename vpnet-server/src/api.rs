@@ -0,0 +1,1355 @@
+/*!
+VPNet Server API模块
+
+提供HTTP管理接口，包括：
+- 健康检查和就绪检查
+- 认证接口
+- 节点查询和管理
+- 节点分组管理
+- 虚拟设备管理
+- 运行统计
+- 路由管理
+- 网络拓扑
+- 节点间时延
+- 网络配置的导出和导入
+*/
+
+use axum::{Router, routing::{get, post, put}, extract::{ConnectInfo, FromRequest, Multipart, Path, Query, Request, State}, http::{header, Method, StatusCode}, middleware::{self, Next}, response::{IntoResponse, Response}, Json};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tower_http::cors::CorsLayer;
+use vpnet::{AppProtocol, AuthRequest, AuthResponse, BpfError, NetworkManager, NetworkDiagnostics, DeviceManager, DeviceStatus, HopInfo, NetworkTopology, Peer, PacketCapture, PolicyRoute, VirtualDevice, VpnetError, WindowStats};
+use crate::auth::{AuthError, AuthManager, PasswordVerdict};
+use crate::config::{Api, AuthMode, PeerGroup};
+use crate::connection_pool::{PeerConnectionPool, PoolStats};
+use crate::latency::{LatencyEntry, PeerLatencyMatrix};
+use crate::network_map::{ImportReport, MapFormat, NetworkMap, NetworkMapError, ServerState};
+use crate::node::{evict_idle, idle_threshold_from_days, IpPoolError, IpPoolSummary, Node, NodeError, NodeImport, NodeManager};
+use crate::replica::ReplicationStatus;
+use crate::tasks::ShutdownSignal;
+
+/// API共享状态
+#[derive(Clone)]
+pub struct ApiState {
+    pub auth_manager: Arc<Mutex<AuthManager>>,
+    pub node_manager: Arc<Mutex<NodeManager>>,
+    pub network_manager: Arc<Mutex<NetworkManager>>,
+    pub device_manager: Arc<Mutex<DeviceManager>>,
+    /// 虚拟网卡抓包，未配置`debug.capture_file`时为None
+    pub capture: Option<Arc<PacketCapture>>,
+    pub latency_matrix: Arc<RwLock<PeerLatencyMatrix>>,
+    /// 服务端主动重连节点时使用的端口池，未启用`connection_pool`时为None
+    pub connection_pool: Option<Arc<PeerConnectionPool>>,
+    pub config: Api,
+    /// 所有服务启动完成后置位，关闭时清除
+    pub ready: Arc<AtomicBool>,
+}
+
+/// 错误响应
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// API处理结果
+pub type ApiResult<T> = Result<Json<T>, (StatusCode, Json<ErrorResponse>)>;
+
+/// 整体健康状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    /// 所有子系统正常
+    Healthy,
+    /// 虚拟设备或认证异常，仍可中继流量
+    Degraded,
+    /// 网络或存储异常，无法提供服务
+    Unhealthy,
+}
+
+/// 健康检查响应
+///
+/// 每个子系统的结果为`ok`或`error: 原因`。
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    pub status: HealthStatus,
+    pub subsystems: BTreeMap<&'static str, String>,
+}
+
+/// 版本信息响应
+#[derive(Debug, Serialize)]
+pub struct VersionResponse {
+    pub version: &'static str,
+    pub protocol_version: u8,
+    pub capabilities: Vec<&'static str>,
+}
+
+/// 口令登录请求
+#[derive(Deserialize)]
+pub struct PasswordAuthRequest {
+    pub username: String,
+    pub password: String,
+}
+
+/// 握手随机数响应
+#[derive(Debug, Serialize)]
+pub struct NonceResponse {
+    pub nonce: String,
+    pub expires_in: u64,
+}
+
+/// 节点详情响应
+#[derive(Debug, Serialize)]
+pub struct NodeResponse {
+    pub id: String,
+    pub name: String,
+    pub address: String,
+    pub virtual_ip: String,
+    pub registered_at: u64,
+    pub online: bool,
+    /// 对等节点状态，未连接的节点为`offline`
+    #[serde(with = "vpnet::protocol::lowercase_name")]
+    pub status: vpnet::NodeStatus,
+    pub last_seen: Option<u64>,
+    pub metadata: HashMap<String, serde_json::Value>,
+    pub group: Option<String>,
+    pub priority: u8,
+    pub tags: HashMap<String, String>,
+    /// Base64编码的节点公钥
+    pub public_key: String,
+    /// 是否处于维护暂停状态
+    pub paused: bool,
+    /// 接收方向的丢包率（百分比），未连接时为空
+    pub packet_loss_pct: Option<f32>,
+    /// 对端在心跳中报告的发送方向丢包率（百分比），未连接时为空
+    pub remote_packet_loss_pct: Option<f32>,
+}
+
+impl NodeResponse {
+    /// 由节点记录和对等节点状态构造响应
+    fn new(node: Node, peer: Option<Peer>) -> Self {
+        Self {
+            id: node.id,
+            name: node.name,
+            address: node.address.to_string(),
+            virtual_ip: node.virtual_ip.to_string(),
+            registered_at: node.registered_at,
+            online: peer.as_ref().is_some_and(|p| p.status == vpnet::NodeStatus::Online),
+            status: peer.as_ref().map_or(vpnet::NodeStatus::Offline, |p| p.status),
+            last_seen: peer.as_ref().map(|p| p.last_seen),
+            paused: peer.as_ref().is_some_and(|p| p.paused),
+            packet_loss_pct: peer.as_ref().map(|p| p.stats.packet_loss_pct),
+            remote_packet_loss_pct: peer.as_ref().map(|p| p.stats.remote_packet_loss_pct),
+            metadata: peer.map(|p| p.metadata).unwrap_or_default(),
+            group: node.group,
+            priority: node.priority,
+            tags: node.tags,
+            public_key: base64::engine::general_purpose::STANDARD.encode(&node.public_key),
+        }
+    }
+}
+
+/// 运行统计响应
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    pub nodes: u32,
+    pub online_nodes: u32,
+    pub max_peers: Option<u32>,
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// 在线节点接收方向丢包率的平均值（百分比）
+    pub packet_loss_pct: f32,
+    /// 各应用协议的数据包数，启用`debug.enable_dpi`时才有
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocols: Option<BTreeMap<AppProtocol, u64>>,
+}
+
+/// 虚拟设备响应
+#[derive(Debug, Serialize)]
+pub struct DeviceResponse {
+    pub id: String,
+    pub name: String,
+    pub ip: String,
+    pub subnet: String,
+    pub gateway: String,
+    pub mtu: u32,
+    pub status: String,
+    pub error: Option<String>,
+}
+
+impl DeviceResponse {
+    /// 由虚拟设备构造响应
+    async fn new(id: String, device: &VirtualDevice) -> Self {
+        let config = device.get_config().await;
+        let (status, error) = match device.get_status().await {
+            DeviceStatus::Up => ("up", None),
+            DeviceStatus::Down => ("down", None),
+            DeviceStatus::Error(reason) => ("error", Some(reason)),
+            _ => ("unknown", None),
+        };
+        
+        Self {
+            id,
+            name: config.name.clone(),
+            ip: config.ip.to_string(),
+            subnet: config.subnet.to_string(),
+            gateway: config.gateway.to_string(),
+            mtu: config.mtu,
+            status: status.to_string(),
+            error,
+        }
+    }
+}
+
+/// 虚拟设备更新请求
+#[derive(Debug, Deserialize)]
+pub struct UpdateDeviceRequest {
+    pub ip: Ipv4Addr,
+    pub subnet: Ipv4Addr,
+}
+
+/// 节点公钥响应
+#[derive(Debug, Serialize)]
+pub struct PublicKeyResponse {
+    pub node_id: String,
+    pub public_key: String,
+    pub first_seen: u64,
+    pub last_seen: u64,
+}
+
+/// 节点导入请求
+///
+/// 用于在新服务端上恢复导出的节点，公钥会同时写入公钥目录，节点无需重新认证。
+#[derive(Debug, Deserialize)]
+pub struct CreateNodeRequest {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    pub address: Option<SocketAddr>,
+    pub virtual_ip: Option<Ipv4Addr>,
+    /// Base64编码的节点公钥
+    pub public_key: String,
+    pub group: Option<String>,
+    #[serde(default)]
+    pub priority: u8,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+/// 节点重命名请求
+#[derive(Debug, Deserialize)]
+pub struct RenameNodeRequest {
+    pub name: String,
+}
+
+/// 节点虚拟IP重新分配请求
+#[derive(Debug, Deserialize)]
+pub struct AssignVirtualIpRequest {
+    pub virtual_ip: Ipv4Addr,
+    pub subnet: Ipv4Addr,
+}
+
+/// 节点数响应，`max`为空表示不限制
+#[derive(Debug, Serialize)]
+pub struct CapacityResponse {
+    pub current: u32,
+    pub max: Option<u32>,
+}
+
+/// 节点优先级设置请求
+#[derive(Debug, Deserialize)]
+pub struct NodePriorityRequest {
+    pub priority: u8,
+}
+
+/// 节点标签设置请求
+#[derive(Debug, Deserialize)]
+pub struct NodeTagsRequest {
+    pub tags: HashMap<String, String>,
+}
+
+/// 分组成员设置请求
+#[derive(Debug, Deserialize)]
+pub struct GroupMembersRequest {
+    pub members: Vec<String>,
+}
+
+/// 分组带宽设置请求
+#[derive(Debug, Deserialize)]
+pub struct GroupBandwidthRequest {
+    /// 组内节点共用的带宽上限（Mbps），`null`表示取消限制
+    pub bandwidth_mbps: Option<u32>,
+}
+
+/// 直连提议请求
+#[derive(Debug, Deserialize)]
+pub struct DirectPathRequest {
+    pub peer_id: String,
+}
+
+/// 路径追踪查询参数
+#[derive(Debug, Deserialize)]
+pub struct TracerouteQuery {
+    pub dst: Ipv4Addr,
+}
+
+/// 节点统计历史查询参数
+#[derive(Debug, Deserialize)]
+pub struct StatsHistoryQuery {
+    /// 统计窗口（秒），默认60秒
+    #[serde(default = "default_stats_window")]
+    pub window: u64,
+}
+
+fn default_stats_window() -> u64 {
+    60
+}
+
+/// 节点统计历史响应
+#[derive(Debug, Serialize)]
+pub struct StatsHistoryResponse {
+    pub node_id: String,
+    pub window: u64,
+    #[serde(flatten)]
+    pub stats: WindowStats,
+}
+
+/// 清理离线节点查询参数
+#[derive(Debug, Deserialize)]
+pub struct EvictIdleQuery {
+    /// 离线超过该天数的节点被清理，默认30天
+    #[serde(default = "default_threshold_days")]
+    pub threshold_days: u64,
+}
+
+fn default_threshold_days() -> u64 {
+    30
+}
+
+/// 抓包过滤表达式，`filter`为空或不填表示记录全部数据包
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CaptureFilterRequest {
+    #[serde(default)]
+    pub filter: Option<String>,
+}
+
+/// 启动API服务器，收到`shutdown`后停止接受新连接，处理完正在进行的请求后返回
+pub async fn start_api_server(
+    addr: SocketAddr,
+    auth_manager: Arc<Mutex<AuthManager>>,
+    node_manager: Arc<Mutex<NodeManager>>,
+    network_manager: Arc<Mutex<NetworkManager>>,
+    device_manager: Arc<Mutex<DeviceManager>>,
+    capture: Option<Arc<PacketCapture>>,
+    latency_matrix: Arc<RwLock<PeerLatencyMatrix>>,
+    connection_pool: Option<Arc<PeerConnectionPool>>,
+    config: Api,
+    ready: Arc<AtomicBool>,
+    shutdown: ShutdownSignal
+) -> Result<(), std::io::Error> {
+    let enable_cors = config.enable_cors;
+    let state = ApiState {
+        auth_manager,
+        node_manager,
+        network_manager,
+        device_manager,
+        capture,
+        latency_matrix,
+        connection_pool,
+        config,
+        ready,
+    };
+    
+    // 创建路由
+    let mut app = Router::new()
+        .route("/api/health", get(health_check))
+        .route("/api/ready", get(readiness_check))
+        .route("/api/version", get(get_version))
+        .route("/api/auth", post(authenticate))
+        .route("/api/auth/nonce", get(get_auth_nonce))
+        .route("/api/auth/password", post(authenticate_password))
+        .route("/api/stats", get(get_stats))
+        .route("/api/stats/peers/:id/history", get(get_peer_stats_history))
+        .route("/api/stats/packet-histogram", get(get_packet_histogram).delete(reset_packet_histogram))
+        .route("/api/nodes", get(get_nodes).post(create_node))
+        .route("/api/nodes/:id", get(get_node).delete(delete_node))
+        .route("/api/capacity", get(get_capacity))
+        .route("/api/ip-pool", get(get_ip_pool))
+        .route("/api/connection-pool", get(get_connection_pool))
+        .route("/api/relay-nodes", get(get_relay_nodes))
+        .route("/api/latency-matrix", get(get_latency_matrix))
+        .route("/api/latency-matrix.csv", get(get_latency_matrix_csv))
+        .route("/api/export", get(export_network_map))
+        .route("/api/import", post(import_network_map))
+        .route("/api/nodes/:id/name", put(rename_node))
+        .route("/api/nodes/:id/virtual-ip", put(assign_virtual_ip))
+        .route("/api/nodes/:id/priority", put(set_node_priority))
+        .route("/api/nodes/:id/tags", put(set_node_tags))
+        .route("/api/nodes/:id/reconnect", post(reconnect_node))
+        .route("/api/nodes/:id/pause", post(pause_node))
+        .route("/api/nodes/:id/resume", post(resume_node))
+        .route("/api/nodes/:id/direct-path", post(offer_direct_path))
+        .route("/api/nodes/:id/public-key", get(get_node_public_key).delete(revoke_node_public_key))
+        .route("/api/groups", get(get_groups).post(add_group))
+        .route("/api/groups/:id/members", put(set_group_members))
+        .route("/api/groups/:id/bandwidth", put(set_group_bandwidth))
+        .route("/api/devices", get(get_devices))
+        .route("/api/devices/:id", get(get_device).put(update_device).delete(delete_device))
+        .route("/api/devices/:id/restart", post(restart_device))
+        .route("/api/diagnostics/traceroute", get(traceroute))
+        .route("/api/topology", get(get_topology))
+        .route("/api/topology/dot", get(get_topology_dot))
+        .route("/api/routes/policy", get(get_policy_routes).post(add_policy_route))
+        .route("/api/routes/policy/:priority", put(update_policy_route).delete(delete_policy_route))
+        .route("/api/admin/evict-idle", post(evict_idle_nodes))
+        .route("/api/debug/capture/filter", get(get_capture_filter).post(set_capture_filter))
+        .route("/api/replication/status", get(get_replication_status))
+        .layer(middleware::from_fn_with_state(state.clone(), require_admin))
+        .with_state(state);
+    
+    if enable_cors {
+        app = app.layer(CorsLayer::permissive());
+    }
+    
+    // 启动服务器
+    log::info!("API server starting on {}", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown.wait())
+        .await
+}
+
+/// 无需管理令牌即可访问的接口：健康检查、版本查询和节点认证
+const PUBLIC_PATHS: &[&str] = &[
+    "/api/health",
+    "/api/ready",
+    "/api/version",
+    "/api/auth",
+    "/api/auth/nonce",
+    "/api/auth/password",
+];
+
+/// 管理接口访问控制
+///
+/// 配置了`api.admin_token`时，请求必须在`Authorization: Bearer`请求头中携带该令牌；
+/// 未配置时只接受来自本机回环地址的请求。未通过时返回401。
+async fn require_admin(
+    State(state): State<ApiState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next
+) -> Response {
+    if req.method() == Method::OPTIONS || PUBLIC_PATHS.contains(&req.uri().path()) {
+        return next.run(req).await;
+    }
+    
+    let authorized = match state.config.admin_token() {
+        Some(token) => req.headers().get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|presented| constant_time_eq(presented.as_bytes(), token.as_bytes())),
+        None => peer.ip().is_loopback(),
+    };
+    if !authorized {
+        log::warn!("Rejected unauthorized {} {} from {}", req.method(), req.uri().path(), peer);
+        return error_response(StatusCode::UNAUTHORIZED, "admin token required").into_response();
+    }
+    next.run(req).await
+}
+
+/// 比较耗时与内容无关的字节串比较
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// 构造错误响应
+pub fn error_response(status: StatusCode, error: impl Into<String>) -> (StatusCode, Json<ErrorResponse>) {
+    (status, Json(ErrorResponse { error: error.into() }))
+}
+
+/// 健康检查
+///
+/// 检查网络套接字、虚拟设备、令牌签发验证和公钥存储，网络或存储异常时返回503，供负载均衡探测。
+async fn health_check(State(state): State<ApiState>) -> (StatusCode, Json<HealthResponse>) {
+    let network = if state.network_manager.lock().await.listen_addrs().is_empty() {
+        Err("no UDP socket bound".to_string())
+    } else {
+        Ok(())
+    };
+    let device = check_devices(&state.device_manager).await;
+    let (auth, database) = {
+        let auth_manager = state.auth_manager.lock().await;
+        let (token, _) = auth_manager.issue_token("health-check");
+        let auth = match auth_manager.verify_token(&token) {
+            Some(_) => Ok(()),
+            None => Err("sample token verification failed".to_string()),
+        };
+        let database = auth_manager.key_directory().get("health-check")
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+        (auth, database)
+    };
+    
+    let status = if network.is_err() || database.is_err() {
+        HealthStatus::Unhealthy
+    } else if device.is_err() || auth.is_err() {
+        HealthStatus::Degraded
+    } else {
+        HealthStatus::Healthy
+    };
+    
+    let subsystems = [("network", network), ("device", device), ("auth", auth), ("database", database)]
+        .into_iter()
+        .map(|(name, result)| (name, result.map_or_else(|e| format!("error: {}", e), |_| "ok".to_string())))
+        .collect();
+    
+    let code = if status == HealthStatus::Unhealthy {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (code, Json(HealthResponse { status, subsystems }))
+}
+
+/// 服务端的版本、协议版本和能力，供客户端检查兼容性
+async fn get_version(State(state): State<ApiState>) -> Json<VersionResponse> {
+    let capabilities = state.network_manager.lock().await.capabilities();
+    Json(VersionResponse {
+        version: vpnet::VERSION,
+        protocol_version: vpnet::PROTOCOL_VERSION,
+        capabilities: capabilities.names(),
+    })
+}
+
+/// 检查虚拟设备，没有设备或任一设备未启动时报告异常
+async fn check_devices(device_manager: &Mutex<DeviceManager>) -> Result<(), String> {
+    let devices = device_manager.lock().await.get_all_devices().await;
+    if devices.is_empty() {
+        return Err("no virtual device".to_string());
+    }
+    
+    for (id, device) in devices {
+        match device.lock().await.get_status().await {
+            DeviceStatus::Up => {}
+            DeviceStatus::Error(reason) => return Err(format!("{}: {}", id, reason)),
+            _ => return Err(format!("interface {} down", id)),
+        }
+    }
+    Ok(())
+}
+
+/// 就绪检查，所有服务启动完成前返回503，供Kubernetes readinessProbe使用
+async fn readiness_check(State(state): State<ApiState>) -> (StatusCode, Json<serde_json::Value>) {
+    if state.ready.load(Ordering::Acquire) {
+        (StatusCode::OK, Json(serde_json::json!({ "status": "ready" })))
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({ "status": "starting" })))
+    }
+}
+
+/// 复制状态，主服务端没有复制延迟
+async fn get_replication_status() -> Json<ReplicationStatus> {
+    Json(ReplicationStatus::primary())
+}
+
+/// 验证节点签名的授权请求并签发访问令牌
+async fn authenticate(
+    State(state): State<ApiState>,
+    Json(req): Json<AuthRequest>
+) -> Result<Json<AuthResponse>, (StatusCode, Json<AuthResponse>)> {
+    let auth_manager = state.auth_manager.lock().await;
+    if let Err(e) = auth_manager.verify_auth_request(&req) {
+        log::warn!("Rejected auth request from {}: {}", req.node_id, e);
+        let status = match e {
+            AuthError::Storage(_) | AuthError::Record(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::UNAUTHORIZED,
+        };
+        return Err((status, Json(AuthResponse {
+            node_id: req.node_id,
+            status: vpnet::status::AUTH_FAILED,
+            message: e.to_string(),
+            token: None,
+            expires_at: None,
+        })));
+    }
+    
+    let (token, expires_at) = auth_manager.issue_token(&req.node_id);
+    Ok(Json(AuthResponse {
+        node_id: req.node_id,
+        status: vpnet::status::OK,
+        message: "authenticated".to_string(),
+        token: Some(token),
+        expires_at: Some(expires_at),
+    }))
+}
+
+/// 验证用户口令并签发访问令牌，只在`auth.mode = "password"`时可用
+async fn authenticate_password(
+    State(state): State<ApiState>,
+    Json(req): Json<PasswordAuthRequest>
+) -> Result<Json<AuthResponse>, (StatusCode, Json<AuthResponse>)> {
+    let rejection = |status: StatusCode, message: &str| (status, Json(AuthResponse {
+        node_id: req.username.clone(),
+        status: vpnet::status::AUTH_FAILED,
+        message: message.to_string(),
+        token: None,
+        expires_at: None,
+    }));
+    
+    let verifier = {
+        let auth_manager = state.auth_manager.lock().await;
+        if auth_manager.config().mode != AuthMode::Password {
+            return Err(rejection(StatusCode::NOT_FOUND, "password authentication is disabled"));
+        }
+        auth_manager.password_verifier(&req.username)
+    };
+    
+    // 派生密钥耗时较长，不占用异步工作线程，也不持有认证管理器的锁，以免阻塞握手随机数签发
+    let password = req.password.clone();
+    let verdict = tokio::task::spawn_blocking(move || verifier.verify(&password))
+        .await
+        .map_err(|_| rejection(StatusCode::INTERNAL_SERVER_ERROR, "password verification failed"))?;
+    if verdict == PasswordVerdict::Rejected {
+        log::warn!("Rejected password login for {}", req.username);
+        return Err(rejection(StatusCode::UNAUTHORIZED, "invalid username or password"));
+    }
+    
+    let mut auth_manager = state.auth_manager.lock().await;
+    auth_manager.apply_password_verdict(&req.username, &verdict);
+    let (token, expires_at) = auth_manager.issue_token(&req.username);
+    Ok(Json(AuthResponse {
+        node_id: req.username,
+        status: vpnet::status::OK,
+        message: "authenticated".to_string(),
+        token: Some(token),
+        expires_at: Some(expires_at),
+    }))
+}
+
+/// 签发握手随机数
+async fn get_auth_nonce(State(state): State<ApiState>) -> ApiResult<NonceResponse> {
+    let auth_manager = state.auth_manager.lock().await;
+    let nonce = auth_manager.issue_nonce().await
+        .map_err(|e| error_response(StatusCode::SERVICE_UNAVAILABLE, e.to_string()))?;
+    let expires_in = auth_manager.nonce_store().lock().await.ttl().as_secs();
+    
+    Ok(Json(NonceResponse {
+        nonce: base64::engine::general_purpose::STANDARD.encode(nonce),
+        expires_in,
+    }))
+}
+
+/// 获取节点详情，包括节点最近一次心跳上报的扩展信息
+async fn get_node(
+    State(state): State<ApiState>,
+    Path(id): Path<String>
+) -> ApiResult<NodeResponse> {
+    let node = state.node_manager.lock().await.get(&id).cloned()
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, format!("node {} not found", id)))?;
+    let peer = state.network_manager.lock().await.get_peer(&id).await;
+    
+    Ok(Json(NodeResponse::new(node, peer)))
+}
+
+/// 获取所有节点，可以用`tag.<键>=<值>`查询参数按标签过滤，多个标签须同时满足
+async fn get_nodes(
+    State(state): State<ApiState>,
+    Query(query): Query<HashMap<String, String>>
+) -> ApiResult<Vec<NodeResponse>> {
+    let tag_filter: HashMap<String, String> = query.into_iter()
+        .filter_map(|(key, value)| Some((key.strip_prefix("tag.")?.to_string(), value)))
+        .collect();
+    let nodes = state.node_manager.lock().await.find_by_tags(&tag_filter);
+    let network_manager = state.network_manager.lock().await;
+    
+    let mut responses = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let peer = network_manager.get_peer(&node.id).await;
+        responses.push(NodeResponse::new(node, peer));
+    }
+    
+    Ok(Json(responses))
+}
+
+/// 获取声明可以中继的节点
+async fn get_relay_nodes(State(state): State<ApiState>) -> ApiResult<Vec<NodeResponse>> {
+    let nodes = state.node_manager.lock().await.relay_nodes();
+    let network_manager = state.network_manager.lock().await;
+    
+    let mut responses = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let peer = network_manager.get_peer(&node.id).await;
+        responses.push(NodeResponse::new(node, peer));
+    }
+    
+    Ok(Json(responses))
+}
+
+/// 导入节点
+///
+/// 节点已存在或公钥与公钥目录中缓存的不一致时返回409。导入的节点在首次握手前保持离线。
+async fn create_node(
+    State(state): State<ApiState>,
+    Json(req): Json<CreateNodeRequest>
+) -> Result<(StatusCode, Json<NodeResponse>), (StatusCode, Json<ErrorResponse>)> {
+    if req.id.trim().is_empty() {
+        return Err(error_response(StatusCode::BAD_REQUEST, "id must not be empty"));
+    }
+    let public_key = base64::engine::general_purpose::STANDARD.decode(&req.public_key)
+        .map_err(|_| error_response(StatusCode::BAD_REQUEST, "public_key must be base64 encoded"))?;
+    
+    let key_directory = state.auth_manager.lock().await.key_directory();
+    let node = state.node_manager.lock().await
+        .import(&key_directory, NodeImport {
+            id: req.id,
+            name: req.name,
+            address: req.address,
+            virtual_ip: req.virtual_ip,
+            public_key,
+            group: req.group,
+            priority: req.priority,
+            tags: req.tags,
+        })
+        .map_err(|e| match e {
+            NodeError::Exists(_) | NodeError::KeyMismatch(_) | NodeError::VirtualIpConflict { .. }
+                | NodeError::NameTaken(_) | NodeError::IpPool(IpPoolError::Reserved { .. }) => {
+                error_response(StatusCode::CONFLICT, e.to_string())
+            }
+            NodeError::UnknownGroup(_) | NodeError::InvalidVirtualIp(_) => {
+                error_response(StatusCode::BAD_REQUEST, e.to_string())
+            }
+            NodeError::CapacityExceeded | NodeError::NoVirtualIp => {
+                error_response(StatusCode::SERVICE_UNAVAILABLE, e.to_string())
+            }
+            _ => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+    log::info!("Node {} imported with virtual IP {}", node.id, node.virtual_ip);
+    
+    Ok((StatusCode::CREATED, Json(NodeResponse::new(node, None))))
+}
+
+/// 注销节点并断开与其的连接
+async fn delete_node(
+    State(state): State<ApiState>,
+    Path(id): Path<String>
+) -> ApiResult<serde_json::Value> {
+    state.node_manager.lock().await.remove(&id)
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, format!("node {} not found", id)))?;
+    state.network_manager.lock().await.remove_peer(&id).await;
+    
+    Ok(Json(serde_json::json!({ "id": id, "deleted": true })))
+}
+
+/// 获取运行统计
+async fn get_stats(State(state): State<ApiState>) -> ApiResult<StatsResponse> {
+    let (nodes, max_peers) = {
+        let node_manager = state.node_manager.lock().await;
+        (node_manager.peer_count() as u32, node_manager.max_peers())
+    };
+    let (peers, protocols) = {
+        let network_manager = state.network_manager.lock().await;
+        (network_manager.get_peers().await, network_manager.protocol_stats())
+    };
+    
+    let online: Vec<_> = peers.iter().filter(|p| p.status == vpnet::NodeStatus::Online).collect();
+    let packet_loss_pct = if online.is_empty() {
+        0.0
+    } else {
+        online.iter().map(|p| p.stats.packet_loss_pct).sum::<f32>() / online.len() as f32
+    };
+    
+    Ok(Json(StatsResponse {
+        nodes,
+        online_nodes: online.len() as u32,
+        max_peers,
+        packets_sent: peers.iter().map(|p| p.stats.packets_sent).sum(),
+        packets_received: peers.iter().map(|p| p.stats.packets_received).sum(),
+        bytes_sent: peers.iter().map(|p| p.stats.bytes_sent).sum(),
+        bytes_received: peers.iter().map(|p| p.stats.bytes_received).sum(),
+        packet_loss_pct,
+        protocols,
+    }))
+}
+
+/// 获取节点最近一段时间内的平均收发速率和丢包率
+async fn get_peer_stats_history(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Query(query): Query<StatsHistoryQuery>
+) -> ApiResult<StatsHistoryResponse> {
+    if query.window == 0 {
+        return Err(error_response(StatusCode::BAD_REQUEST, "window must be greater than 0"));
+    }
+    
+    let stats = state.network_manager.lock().await
+        .peer_window_stats(&id, Duration::from_secs(query.window)).await
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, format!("no statistics for {}", id)))?;
+    
+    Ok(Json(StatsHistoryResponse {
+        node_id: id,
+        window: query.window,
+        stats,
+    }))
+}
+
+/// 获取UDP套接字收发的数据包大小分布
+async fn get_packet_histogram(State(state): State<ApiState>) -> ApiResult<vpnet::PacketSizeStats> {
+    Ok(Json(state.network_manager.lock().await.packet_size_stats()))
+}
+
+/// 清空数据包大小分布
+async fn reset_packet_histogram(State(state): State<ApiState>) -> ApiResult<serde_json::Value> {
+    state.network_manager.lock().await.reset_packet_size_stats();
+    Ok(Json(serde_json::json!({ "reset": true })))
+}
+
+/// 重命名节点
+async fn rename_node(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(req): Json<RenameNodeRequest>
+) -> ApiResult<serde_json::Value> {
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(error_response(StatusCode::BAD_REQUEST, "name must not be empty"));
+    }
+    
+    state.node_manager.lock().await.rename(&id, name)
+        .map_err(|e| match e {
+            NodeError::NotFound(_) => error_response(StatusCode::NOT_FOUND, e.to_string()),
+            NodeError::NameTaken(_) => error_response(StatusCode::CONFLICT, e.to_string()),
+            _ => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+    
+    Ok(Json(serde_json::json!({ "id": id, "name": name })))
+}
+
+/// 重新分配节点的虚拟IP，并通知在线节点更新其虚拟网卡地址
+async fn assign_virtual_ip(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(req): Json<AssignVirtualIpRequest>
+) -> ApiResult<serde_json::Value> {
+    let old_ip = state.node_manager.lock().await.reassign_virtual_ip(&id, req.virtual_ip)
+        .map_err(|e| match e {
+            NodeError::NotFound(_) => error_response(StatusCode::NOT_FOUND, e.to_string()),
+            NodeError::VirtualIpConflict { .. } => error_response(StatusCode::CONFLICT, e.to_string()),
+            _ => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+    
+    // 节点离线时新地址在其下次握手时生效
+    let notified = match state.network_manager.lock().await.send_ip_assignment(&id, req.virtual_ip, req.subnet).await {
+        Ok(()) => true,
+        Err(e) => {
+            log::warn!("Failed to send IP assignment to {}: {}", id, e);
+            false
+        }
+    };
+    
+    Ok(Json(serde_json::json!({
+        "id": id,
+        "virtual_ip": req.virtual_ip.to_string(),
+        "previous_ip": old_ip.to_string(),
+        "notified": notified,
+    })))
+}
+
+/// 设置节点优先级
+async fn set_node_priority(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(req): Json<NodePriorityRequest>
+) -> ApiResult<serde_json::Value> {
+    state.node_manager.lock().await.set_priority(&id, req.priority);
+    Ok(Json(serde_json::json!({ "id": id, "priority": req.priority })))
+}
+
+/// 设置节点标签，替换原有的全部标签
+async fn set_node_tags(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(req): Json<NodeTagsRequest>
+) -> ApiResult<serde_json::Value> {
+    if req.tags.keys().any(|key| key.trim().is_empty()) {
+        return Err(error_response(StatusCode::BAD_REQUEST, "tag keys must not be empty"));
+    }
+    
+    state.node_manager.lock().await.set_tags(&id, req.tags.clone())
+        .map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    // 节点可能尚未连接，此时标签只保存在节点记录中
+    let _ = state.network_manager.lock().await.set_peer_tags(&id, req.tags.clone()).await;
+    Ok(Json(serde_json::json!({ "id": id, "tags": req.tags })))
+}
+
+/// 获取当前节点数和上限
+async fn get_capacity(State(state): State<ApiState>) -> ApiResult<CapacityResponse> {
+    let node_manager = state.node_manager.lock().await;
+    Ok(Json(CapacityResponse {
+        current: node_manager.peer_count() as u32,
+        max: node_manager.max_peers(),
+    }))
+}
+
+/// 获取地址池中已占用、已预留和空闲的地址，未配置地址池时返回404
+async fn get_ip_pool(State(state): State<ApiState>) -> ApiResult<IpPoolSummary> {
+    state.node_manager.lock().await.ip_pool_summary()
+        .map(Json)
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "IP pool is not configured"))
+}
+
+/// 获取节点连接端口池的使用情况
+async fn get_connection_pool(State(state): State<ApiState>) -> ApiResult<PoolStats> {
+    state.connection_pool.as_ref()
+        .map(|pool| Json(pool.stats()))
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "Connection pool is not configured"))
+}
+
+/// 触发与节点重新握手，立即返回202，重连在后台进行
+///
+/// 启用端口池时从池中分配的端口发起握手，端口用尽时排队等待。
+async fn reconnect_node(
+    State(state): State<ApiState>,
+    Path(id): Path<String>
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, Json<ErrorResponse>)> {
+    if state.network_manager.lock().await.get_peer(&id).await.is_none() {
+        return Err(error_response(StatusCode::NOT_FOUND, format!("peer {} not found", id)));
+    }
+    
+    let network_manager = state.network_manager.clone();
+    let connection_pool = state.connection_pool.clone();
+    let peer_id = id.clone();
+    tokio::spawn(async move {
+        let result = match &connection_pool {
+            Some(pool) => {
+                let local_addr = pool.acquire(&peer_id).await;
+                network_manager.lock().await.initiate_handshake_from(&peer_id, local_addr).await
+            }
+            None => network_manager.lock().await.initiate_handshake(&peer_id).await,
+        };
+        if let Err(e) = result {
+            if let Some(pool) = &connection_pool {
+                pool.release(&peer_id);
+            }
+            log::warn!("Failed to reconnect to {}: {}", peer_id, e);
+        }
+    });
+    
+    Ok((StatusCode::ACCEPTED, Json(serde_json::json!({ "id": id, "status": "connecting" }))))
+}
+
+/// 暂停节点，停止向其发送和转发流量，节点注册保持有效
+async fn pause_node(
+    State(state): State<ApiState>,
+    Path(id): Path<String>
+) -> ApiResult<serde_json::Value> {
+    state.network_manager.lock().await
+        .pause_peer(&id).await
+        .map_err(|e| match e {
+            VpnetError::PeerNotFound(peer) => error_response(StatusCode::NOT_FOUND, format!("peer {} not found", peer)),
+            e => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+    Ok(Json(serde_json::json!({ "id": id, "paused": true })))
+}
+
+/// 恢复暂停的节点
+async fn resume_node(
+    State(state): State<ApiState>,
+    Path(id): Path<String>
+) -> ApiResult<serde_json::Value> {
+    state.network_manager.lock().await
+        .resume_peer(&id).await
+        .map_err(|e| match e {
+            VpnetError::PeerNotFound(peer) => error_response(StatusCode::NOT_FOUND, format!("peer {} not found", peer)),
+            e => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+    Ok(Json(serde_json::json!({ "id": id, "paused": false })))
+}
+
+/// 清理长期离线的节点
+async fn evict_idle_nodes(
+    State(state): State<ApiState>,
+    Query(query): Query<EvictIdleQuery>
+) -> ApiResult<serde_json::Value> {
+    if query.threshold_days == 0 {
+        return Err(error_response(StatusCode::BAD_REQUEST, "threshold_days must be greater than 0"));
+    }
+    let threshold = idle_threshold_from_days(query.threshold_days)
+        .ok_or_else(|| error_response(StatusCode::BAD_REQUEST, "threshold_days is too large"))?;
+    
+    let evicted = evict_idle(&state.node_manager, &state.network_manager, threshold).await;
+    Ok(Json(serde_json::json!({ "evicted": evicted.len(), "threshold_days": query.threshold_days })))
+}
+
+/// 提议两个节点建立直连，成功后双方不再经由服务端中继
+async fn offer_direct_path(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(req): Json<DirectPathRequest>
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, Json<ErrorResponse>)> {
+    if id == req.peer_id {
+        return Err(error_response(StatusCode::BAD_REQUEST, "cannot offer a direct path to the node itself"));
+    }
+    
+    state.network_manager.lock().await
+        .offer_direct_path(&id, &req.peer_id).await
+        .map_err(|e| match e {
+            VpnetError::PeerNotFound(peer) => error_response(StatusCode::NOT_FOUND, format!("peer {} not found", peer)),
+            e @ VpnetError::Other(_) => error_response(StatusCode::FORBIDDEN, e.to_string()),
+            e => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+    
+    Ok((StatusCode::ACCEPTED, Json(serde_json::json!({ "id": id, "peer_id": req.peer_id, "status": "offered" }))))
+}
+
+/// 获取节点缓存的公钥
+async fn get_node_public_key(
+    State(state): State<ApiState>,
+    Path(id): Path<String>
+) -> ApiResult<PublicKeyResponse> {
+    let key_directory = state.auth_manager.lock().await.key_directory();
+    let record = key_directory.get(&id)
+        .map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, format!("no cached public key for {}", id)))?;
+    
+    Ok(Json(PublicKeyResponse {
+        node_id: id,
+        public_key: base64::engine::general_purpose::STANDARD.encode(&record.public_key),
+        first_seen: record.first_seen,
+        last_seen: record.last_seen,
+    }))
+}
+
+/// 撤销节点缓存的公钥
+async fn revoke_node_public_key(
+    State(state): State<ApiState>,
+    Path(id): Path<String>
+) -> ApiResult<serde_json::Value> {
+    let key_directory = state.auth_manager.lock().await.key_directory();
+    let revoked = key_directory.revoke(&id)
+        .map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    
+    if !revoked {
+        return Err(error_response(StatusCode::NOT_FOUND, format!("no cached public key for {}", id)));
+    }
+    
+    Ok(Json(serde_json::json!({ "node_id": id, "revoked": true })))
+}
+
+/// 获取所有节点分组
+async fn get_groups(State(state): State<ApiState>) -> ApiResult<Vec<PeerGroup>> {
+    Ok(Json(state.node_manager.lock().await.groups()))
+}
+
+/// 添加节点分组
+async fn add_group(
+    State(state): State<ApiState>,
+    Json(group): Json<PeerGroup>
+) -> Result<(StatusCode, Json<PeerGroup>), (StatusCode, Json<ErrorResponse>)> {
+    if group.group_id.trim().is_empty() {
+        return Err(error_response(StatusCode::BAD_REQUEST, "group_id must not be empty"));
+    }
+    
+    state.node_manager.lock().await.add_group(group.clone())
+        .map_err(|e| match e {
+            NodeError::GroupExists(_) => error_response(StatusCode::CONFLICT, e.to_string()),
+            _ => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+    
+    Ok((StatusCode::CREATED, Json(group)))
+}
+
+/// 设置分组成员
+async fn set_group_members(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(req): Json<GroupMembersRequest>
+) -> ApiResult<serde_json::Value> {
+    state.node_manager.lock().await.set_group_members(&id, &req.members)
+        .map_err(|e| match e {
+            NodeError::UnknownGroup(_) | NodeError::NotFound(_) => error_response(StatusCode::NOT_FOUND, e.to_string()),
+            _ => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+    
+    Ok(Json(serde_json::json!({ "group_id": id, "members": req.members })))
+}
+
+/// 设置分组带宽上限
+async fn set_group_bandwidth(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(req): Json<GroupBandwidthRequest>
+) -> ApiResult<serde_json::Value> {
+    if req.bandwidth_mbps == Some(0) {
+        return Err(error_response(StatusCode::BAD_REQUEST, "bandwidth_mbps must be greater than 0"));
+    }
+    
+    state.node_manager.lock().await.set_group_bandwidth(&id, req.bandwidth_mbps)
+        .map_err(|e| match e {
+            NodeError::UnknownGroup(_) => error_response(StatusCode::NOT_FOUND, e.to_string()),
+            _ => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+    
+    Ok(Json(serde_json::json!({ "group_id": id, "bandwidth_mbps": req.bandwidth_mbps })))
+}
+
+/// 获取所有虚拟设备
+async fn get_devices(State(state): State<ApiState>) -> ApiResult<Vec<DeviceResponse>> {
+    let devices = state.device_manager.lock().await.get_all_devices().await;
+    
+    let mut responses = Vec::with_capacity(devices.len());
+    for (id, device) in devices {
+        responses.push(DeviceResponse::new(id, &*device.lock().await).await);
+    }
+    
+    Ok(Json(responses))
+}
+
+/// 获取虚拟设备详情
+async fn get_device(
+    State(state): State<ApiState>,
+    Path(id): Path<String>
+) -> ApiResult<DeviceResponse> {
+    let device = state.device_manager.lock().await.get_device(&id).await
+        .map_err(|e| error_response(StatusCode::NOT_FOUND, e))?;
+    let device = device.lock().await;
+    
+    Ok(Json(DeviceResponse::new(id, &device).await))
+}
+
+/// 更新虚拟设备的地址，设备运行时立即生效
+async fn update_device(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(req): Json<UpdateDeviceRequest>
+) -> ApiResult<DeviceResponse> {
+    let device = state.device_manager.lock().await.get_device(&id).await
+        .map_err(|e| error_response(StatusCode::NOT_FOUND, e))?;
+    let mut device = device.lock().await;
+    device.set_ip(req.ip, req.subnet)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, e))?;
+    
+    Ok(Json(DeviceResponse::new(id, &device).await))
+}
+
+/// 停止并删除虚拟设备
+async fn delete_device(
+    State(state): State<ApiState>,
+    Path(id): Path<String>
+) -> ApiResult<serde_json::Value> {
+    let device_manager = state.device_manager.lock().await;
+    let device = device_manager.get_device(&id).await
+        .map_err(|e| error_response(StatusCode::NOT_FOUND, e))?;
+    
+    device.lock().await.stop().await
+        .map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    device_manager.delete_device(&id).await
+        .map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    
+    Ok(Json(serde_json::json!({ "id": id, "deleted": true })))
+}
+
+/// 重启虚拟设备
+async fn restart_device(
+    State(state): State<ApiState>,
+    Path(id): Path<String>
+) -> ApiResult<DeviceResponse> {
+    let device = state.device_manager.lock().await.get_device(&id).await
+        .map_err(|e| error_response(StatusCode::NOT_FOUND, e))?;
+    let mut device = device.lock().await;
+    device.reset().await
+        .map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    
+    Ok(Json(DeviceResponse::new(id, &device).await))
+}
+
+/// 追踪到达指定虚拟IP的覆盖网络路径
+async fn traceroute(
+    State(state): State<ApiState>,
+    Query(query): Query<TracerouteQuery>
+) -> ApiResult<Vec<HopInfo>> {
+    let diagnostics = NetworkDiagnostics::new(state.network_manager.clone());
+    let hops = diagnostics.traceroute(query.dst).await;
+    if hops.is_empty() {
+        return Err(error_response(StatusCode::NOT_FOUND, format!("no peer with virtual IP {}", query.dst)));
+    }
+    
+    Ok(Json(hops))
+}
+
+/// 获取网络拓扑
+async fn get_topology(State(state): State<ApiState>) -> ApiResult<NetworkTopology> {
+    let topology = state.network_manager.lock().await.get_topology().await;
+    Ok(Json(topology))
+}
+
+/// 以Graphviz DOT格式获取网络拓扑
+async fn get_topology_dot(State(state): State<ApiState>) -> impl IntoResponse {
+    let topology = state.network_manager.lock().await.get_topology().await;
+    ([(header::CONTENT_TYPE, "text/vnd.graphviz; charset=utf-8")], topology.to_dot())
+}
+
+/// 获取节点两两之间的往返时延
+async fn get_latency_matrix(State(state): State<ApiState>) -> ApiResult<Vec<LatencyEntry>> {
+    Ok(Json(state.latency_matrix.read().await.entries()))
+}
+
+/// 以CSV格式下载节点两两之间的往返时延
+async fn get_latency_matrix_csv(State(state): State<ApiState>) -> impl IntoResponse {
+    let csv = state.latency_matrix.read().await.to_csv();
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"latency-matrix.csv\""),
+        ],
+        csv
+    )
+}
+
+/// 导出网络映射的查询参数
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: MapFormat,
+}
+
+/// 用于导出和导入网络配置的服务端状态
+async fn server_state(state: &ApiState) -> ServerState {
+    let key_directory = state.auth_manager.lock().await.key_directory();
+    ServerState::new(state.node_manager.clone(), state.network_manager.clone(), key_directory)
+}
+
+/// 导出完整的网络配置，`?format=yaml`时返回YAML
+async fn export_network_map(
+    State(state): State<ApiState>,
+    Query(query): Query<ExportQuery>
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let map = server_state(&state).await.export().await;
+    let body = map.to_string(query.format)
+        .map_err(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let (content_type, filename) = match query.format {
+        MapFormat::Json => ("application/json", "attachment; filename=\"network-map.json\""),
+        MapFormat::Yaml => ("application/yaml", "attachment; filename=\"network-map.yaml\""),
+    };
+    Ok(([(header::CONTENT_TYPE, content_type), (header::CONTENT_DISPOSITION, filename)], body).into_response())
+}
+
+/// 导入网络配置
+///
+/// 请求体为JSON格式的网络映射，或在multipart表单的第一个文件字段中上传JSON或YAML文件。
+async fn import_network_map(State(state): State<ApiState>, request: Request) -> ApiResult<ImportReport> {
+    let multipart = request.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"));
+    
+    let map = if multipart {
+        let mut multipart = Multipart::from_request(request, &state).await
+            .map_err(|e| error_response(StatusCode::BAD_REQUEST, e.body_text()))?;
+        let field = multipart.next_field().await
+            .map_err(|e| error_response(StatusCode::BAD_REQUEST, e.body_text()))?
+            .ok_or_else(|| error_response(StatusCode::BAD_REQUEST, "missing network map file"))?;
+        let data = field.bytes().await
+            .map_err(|e| error_response(StatusCode::BAD_REQUEST, e.body_text()))?;
+        NetworkMap::parse(&data)
+            .map_err(|e| error_response(StatusCode::BAD_REQUEST, e.to_string()))?
+    } else {
+        let Json(map) = Json::<NetworkMap>::from_request(request, &state).await
+            .map_err(|e| error_response(StatusCode::BAD_REQUEST, e.body_text()))?;
+        map
+    };
+    
+    let report = server_state(&state).await
+        .import(map)
+        .await
+        .map_err(|e| match e {
+            NetworkMapError::UnsupportedVersion(_) => error_response(StatusCode::BAD_REQUEST, e.to_string()),
+            _ => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+    log::info!("Imported network map: {} added, {} updated, {} skipped, {} failed",
+               report.added.len(), report.updated.len(), report.skipped.len(), report.failed.len());
+    Ok(Json(report))
+}
+
+/// 获取策略路由规则
+async fn get_policy_routes(State(state): State<ApiState>) -> ApiResult<Vec<PolicyRoute>> {
+    let rules = state.network_manager.lock().await.get_policy_routes().await;
+    Ok(Json(rules))
+}
+
+/// 添加策略路由规则
+async fn add_policy_route(
+    State(state): State<ApiState>,
+    Json(rule): Json<PolicyRoute>
+) -> ApiResult<PolicyRoute> {
+    if rule.src_ip.is_none() && rule.dst_ip.is_none() && rule.proto.is_none() {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "policy route must match on at least one of src_ip, dst_ip or proto"
+        ));
+    }
+    
+    state.network_manager.lock().await.add_policy_route(rule.clone()).await;
+    Ok(Json(rule))
+}
+
+/// 替换指定优先级的策略路由规则
+async fn update_policy_route(
+    State(state): State<ApiState>,
+    Path(priority): Path<u32>,
+    Json(rule): Json<PolicyRoute>
+) -> ApiResult<PolicyRoute> {
+    if rule.src_ip.is_none() && rule.dst_ip.is_none() && rule.proto.is_none() {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "policy route must match on at least one of src_ip, dst_ip or proto"
+        ));
+    }
+    
+    let network_manager = state.network_manager.lock().await;
+    network_manager.remove_policy_route(priority).await
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, format!("no policy route with priority {}", priority)))?;
+    network_manager.add_policy_route(rule.clone()).await;
+    
+    Ok(Json(rule))
+}
+
+/// 删除指定优先级的策略路由规则
+async fn delete_policy_route(
+    State(state): State<ApiState>,
+    Path(priority): Path<u32>
+) -> ApiResult<PolicyRoute> {
+    state.network_manager.lock().await.remove_policy_route(priority).await
+        .map(Json)
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, format!("no policy route with priority {}", priority)))
+}
+
+/// 当前的抓包过滤表达式
+async fn get_capture_filter(State(state): State<ApiState>) -> ApiResult<CaptureFilterRequest> {
+    let capture = state.capture.as_ref()
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "packet capture is not enabled"))?;
+    Ok(Json(CaptureFilterRequest { filter: capture.filter_expression() }))
+}
+
+/// 替换抓包过滤表达式，立即生效，表达式无法编译时保留原来的过滤表达式
+async fn set_capture_filter(
+    State(state): State<ApiState>,
+    Json(req): Json<CaptureFilterRequest>
+) -> ApiResult<CaptureFilterRequest> {
+    let capture = state.capture.as_ref()
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "packet capture is not enabled"))?;
+    capture.set_filter(req.filter.as_deref()).map_err(|e| match e {
+        BpfError::Invalid { .. } => error_response(StatusCode::BAD_REQUEST, e.to_string()),
+        BpfError::Unsupported => error_response(StatusCode::NOT_IMPLEMENTED, e.to_string()),
+    })?;
+    Ok(Json(CaptureFilterRequest { filter: capture.filter_expression() }))
+}
@@ -0,0 +1,336 @@
+/*!
+VPNet Server 认证模块
+
+管理节点的认证和授权，包括：
+- 认证配置管理
+- 握手随机数签发
+- 节点公钥缓存（首次使用即信任）
+- 授权请求签名验证和令牌签发
+- 用户口令验证
+*/
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use base64::Engine;
+use vpnet::{AuthRequest, NonceStore, PasswordHasher};
+use crate::config::Auth;
+
+/// 认证错误
+#[derive(Error, Debug)]
+pub enum AuthError {
+    #[error("Nonce error: {0}")]
+    Nonce(&'static str),
+    
+    #[error("Key directory error: {0}")]
+    Storage(#[from] sled::Error),
+    
+    #[error("Invalid key record: {0}")]
+    Record(#[from] serde_json::Error),
+    
+    #[error("Invalid request signature")]
+    InvalidSignature,
+    
+    #[error("Request time is outside the allowed window")]
+    Expired,
+    
+    #[error("Public key does not match the key on record for {0}")]
+    KeyMismatch(String),
+}
+
+/// 授权请求时间与服务端时间允许的最大偏差（秒）
+const MAX_REQUEST_SKEW: u64 = 300;
+
+/// 认证管理器
+pub struct AuthManager {
+    config: Auth,
+    nonce_store: Arc<Mutex<NonceStore>>,
+    key_directory: Arc<PublicKeyDirectory>,
+    /// 用户的口令哈希记录，迭代次数升级后的记录只保存在内存中
+    users: HashMap<String, String>,
+    password_hasher: PasswordHasher,
+    /// 用户不存在时用于验证的哈希记录，使验证耗时与存在的用户一致
+    dummy_record: OnceLock<String>,
+}
+
+/// 验证一个用户口令所需的数据，可以在释放认证管理器的锁之后验证
+pub struct PasswordVerifier {
+    record: String,
+    known: bool,
+    hasher: PasswordHasher,
+}
+
+/// 口令验证结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PasswordVerdict {
+    Rejected,
+    Accepted,
+    /// 验证通过，并按当前迭代次数重新生成了哈希记录
+    Upgraded(String),
+}
+
+impl PasswordVerifier {
+    /// 验证口令，按哈希记录的迭代次数派生密钥，耗时较长，应在阻塞线程中调用
+    ///
+    /// 用户不存在时同样派生一次密钥再拒绝，不能从耗时判断用户是否存在。
+    pub fn verify(&self, password: &str) -> PasswordVerdict {
+        if !self.known {
+            self.hasher.verify(password, &self.record);
+            return PasswordVerdict::Rejected;
+        }
+        if let Some(upgraded) = self.hasher.upgrade_iterations(password, &self.record) {
+            return PasswordVerdict::Upgraded(upgraded);
+        }
+        if self.hasher.verify(password, &self.record) {
+            PasswordVerdict::Accepted
+        } else {
+            PasswordVerdict::Rejected
+        }
+    }
+}
+
+/// 缓存的节点公钥
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicKeyRecord {
+    pub public_key: Vec<u8>,
+    pub first_seen: u64,
+    pub last_seen: u64,
+}
+
+/// 公钥校验结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyCheck {
+    /// 首次出现的节点，已记录其公钥
+    New,
+    /// 与缓存的公钥一致
+    Match,
+    /// 与缓存的公钥不一致，可能是密钥被替换
+    Mismatch,
+}
+
+/// 节点公钥目录
+///
+/// 记录每个节点首次握手时出示的公钥，之后的握手与之比对以发现密钥替换。
+pub struct PublicKeyDirectory {
+    tree: sled::Tree,
+}
+
+impl PublicKeyDirectory {
+    /// 打开公钥目录
+    pub fn open(path: &str) -> Result<Self, AuthError> {
+        let db = sled::open(path)?;
+        Ok(Self {
+            tree: db.open_tree("public_keys")?,
+        })
+    }
+    
+    /// 校验节点出示的公钥，首次出现时记录，一致时更新最后出现时间
+    pub fn verify(&self, node_id: &str, public_key: &[u8]) -> Result<KeyCheck, AuthError> {
+        let now = unix_now();
+        
+        let check = match self.get(node_id)? {
+            Some(record) if record.public_key != public_key => return Ok(KeyCheck::Mismatch),
+            Some(mut record) => {
+                record.last_seen = now;
+                self.put(node_id, &record)?;
+                KeyCheck::Match
+            }
+            None => {
+                self.put(node_id, &PublicKeyRecord {
+                    public_key: public_key.to_vec(),
+                    first_seen: now,
+                    last_seen: now,
+                })?;
+                KeyCheck::New
+            }
+        };
+        
+        Ok(check)
+    }
+    
+    /// 获取节点缓存的公钥
+    pub fn get(&self, node_id: &str) -> Result<Option<PublicKeyRecord>, AuthError> {
+        match self.tree.get(node_id)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+    
+    /// 撤销节点缓存的公钥，节点下次握手时重新记录
+    pub fn revoke(&self, node_id: &str) -> Result<bool, AuthError> {
+        let removed = self.tree.remove(node_id)?.is_some();
+        self.tree.flush()?;
+        Ok(removed)
+    }
+    
+    fn put(&self, node_id: &str, record: &PublicKeyRecord) -> Result<(), AuthError> {
+        self.tree.insert(node_id, serde_json::to_vec(record)?)?;
+        self.tree.flush()?;
+        Ok(())
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl AuthManager {
+    /// 创建新的认证管理器
+    pub fn new(config: Auth) -> Result<Self, AuthError> {
+        let key_directory = Arc::new(PublicKeyDirectory::open(&config.key_directory)?);
+        
+        Ok(Self {
+            users: config.users.clone(),
+            password_hasher: PasswordHasher::new(config.password_iterations),
+            dummy_record: OnceLock::new(),
+            config,
+            nonce_store: Arc::new(Mutex::new(NonceStore::default())),
+            key_directory,
+        })
+    }
+    
+    /// 获取认证配置
+    pub fn config(&self) -> &Auth {
+        &self.config
+    }
+    
+    /// 获取握手随机数存储，供网络管理器校验握手请求
+    pub fn nonce_store(&self) -> Arc<Mutex<NonceStore>> {
+        self.nonce_store.clone()
+    }
+    
+    /// 获取节点公钥目录
+    pub fn key_directory(&self) -> Arc<PublicKeyDirectory> {
+        self.key_directory.clone()
+    }
+    
+    /// 验证授权请求
+    ///
+    /// 检查请求时间是否在允许范围内、签名是否由请求中的公钥生成，以及公钥是否与缓存一致。
+    pub fn verify_auth_request(&self, req: &AuthRequest) -> Result<(), AuthError> {
+        if unix_now().abs_diff(req.request_time) > MAX_REQUEST_SKEW {
+            return Err(AuthError::Expired);
+        }
+        
+        if !req.verify_signature() {
+            return Err(AuthError::InvalidSignature);
+        }
+        
+        if self.key_directory.verify(&req.node_id, &req.public_key)? == KeyCheck::Mismatch {
+            return Err(AuthError::KeyMismatch(req.node_id.clone()));
+        }
+        
+        Ok(())
+    }
+    
+    /// 取出验证用户口令所需的数据
+    ///
+    /// 派生密钥较慢，调用方应释放锁后在阻塞线程中调用`PasswordVerifier::verify`，
+    /// 再以`apply_password_verdict`保存升级后的哈希记录。用户不存在时使用当前迭代次数的
+    /// 虚拟记录，验证耗时与存在的用户相同。
+    pub fn password_verifier(&self, username: &str) -> PasswordVerifier {
+        let (record, known) = match self.users.get(username) {
+            Some(record) => (record.clone(), true),
+            None => {
+                let record = self.dummy_record.get_or_init(|| {
+                    self.password_hasher.hash("").unwrap_or_default()
+                });
+                (record.clone(), false)
+            }
+        };
+        PasswordVerifier { record, known, hasher: self.password_hasher }
+    }
+    
+    /// 保存验证时按`auth.password_iterations`重新生成的哈希记录
+    pub fn apply_password_verdict(&mut self, username: &str, verdict: &PasswordVerdict) {
+        if let (PasswordVerdict::Upgraded(upgraded), Some(record)) = (verdict, self.users.get_mut(username)) {
+            log::info!("Upgraded password hash for {} to {} iterations, update auth.users to keep it",
+                       username, self.password_hasher.iterations());
+            *record = upgraded.clone();
+        }
+    }
+    
+    /// 为通过验证的节点签发访问令牌，返回令牌和过期时间
+    pub fn issue_token(&self, node_id: &str) -> (String, u64) {
+        let expires_at = unix_now() + self.config.token_expiry.as_secs();
+        let claims = format!("{}:{}", node_id, expires_at);
+        let tag = vpnet::generate_hmac(self.config.secret_key.as_bytes(), claims.as_bytes());
+        
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let token = format!("{}.{}", engine.encode(&claims), engine.encode(tag));
+        (token, expires_at)
+    }
+    
+    /// 验证访问令牌，令牌有效且未过期时返回节点ID
+    pub fn verify_token(&self, token: &str) -> Option<String> {
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let (claims, tag) = token.split_once('.')?;
+        let claims = engine.decode(claims).ok()?;
+        let tag = engine.decode(tag).ok()?;
+        if !vpnet::verify_hmac(self.config.secret_key.as_bytes(), &claims, &tag) {
+            return None;
+        }
+        
+        let claims = String::from_utf8(claims).ok()?;
+        let (node_id, expires_at) = claims.rsplit_once(':')?;
+        if expires_at.parse::<u64>().ok()? < unix_now() {
+            return None;
+        }
+        Some(node_id.to_string())
+    }
+    
+    /// 签发新的握手随机数
+    pub async fn issue_nonce(&self) -> Result<[u8; 32], AuthError> {
+        self.nonce_store.lock().await
+            .issue()
+            .map_err(AuthError::Nonce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn auth_manager(users: &[(&str, &str)], iterations: u32) -> AuthManager {
+        let mut config = crate::config::default_config().auth;
+        config.key_directory = std::env::temp_dir()
+            .join(format!("vpnet-auth-test-{}-{}", std::process::id(), rand::random::<u64>()))
+            .to_string_lossy()
+            .into_owned();
+        config.password_iterations = iterations;
+        config.users = users.iter()
+            .map(|(username, password)| (username.to_string(), PasswordHasher::new(1).hash(password).unwrap()))
+            .collect();
+        AuthManager::new(config).unwrap()
+    }
+    
+    #[test]
+    fn unknown_users_and_wrong_passwords_are_rejected() {
+        let manager = auth_manager(&[("alice", "secret")], 1);
+        assert_eq!(manager.password_verifier("alice").verify("secret"), PasswordVerdict::Accepted);
+        assert_eq!(manager.password_verifier("alice").verify("wrong"), PasswordVerdict::Rejected);
+        assert_eq!(manager.password_verifier("bob").verify(""), PasswordVerdict::Rejected);
+        assert_eq!(manager.password_verifier("bob").verify("secret"), PasswordVerdict::Rejected);
+    }
+    
+    #[test]
+    fn unknown_users_take_as_many_iterations_as_known_ones() {
+        let manager = auth_manager(&[("alice", "secret")], 1000);
+        let dummy = manager.password_verifier("bob");
+        assert_eq!(PasswordHasher::record_iterations(&dummy.record), Some(1000));
+    }
+    
+    #[test]
+    fn upgraded_record_is_kept() {
+        let mut manager = auth_manager(&[("alice", "secret")], 2);
+        let verdict = manager.password_verifier("alice").verify("secret");
+        assert!(matches!(verdict, PasswordVerdict::Upgraded(_)));
+        manager.apply_password_verdict("alice", &verdict);
+        assert_eq!(manager.password_verifier("alice").verify("secret"), PasswordVerdict::Accepted);
+    }
+}
@@ -1,293 +1,1722 @@
-/*!
-VPNet Server 配置模块
-
-定义和加载服务器配置，包括：
-- 服务器基本配置
-- 虚拟设备配置
-- 节点配置
-- API配置
-- Web配置
-- 认证配置
-*/
-
-use serde::{Deserialize, Serialize};
-use std::fs::{File, create_dir_all};
-use std::io::{Read, Write};
-use std::path::Path;
-use thiserror::Error;
-use rand::Rng;
-use base64::Engine;
-
-/// 配置错误
-#[derive(Error, Debug)]
-pub enum ConfigError {
-    #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
-    
-    #[error("Toml parsing error: {0}")]
-    Toml(#[from] toml::de::Error),
-    
-    #[error("Invalid configuration: {0}")]
-    Invalid(String),
-    
-    #[error("Missing required configuration: {0}")]
-    Missing(String),
-}
-
-/// 服务器配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct ServerConfig {
-    pub server: Server,
-    pub virtual_device: VirtualDevice,
-    pub node: Node,
-    pub api: Api,
-    pub web: Web,
-    pub auth: Auth,
-}
-
-/// 服务器基本配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Server {
-    pub bind: String,
-    pub port: u16,
-    pub workers: u32,
-    pub timeout: u64,
-}
-
-/// 虚拟设备配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct VirtualDevice {
-    pub name: String,
-    pub ip: String,
-    pub subnet: String,
-    pub gateway: String,
-    pub mtu: u32,
-    pub enable_ipv6: bool,
-    pub ipv6_address: Option<String>,
-}
-
-/// 节点配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Node {
-    pub id: String,
-    pub name: String,
-    pub key_file: String,
-    pub auto_discovery: bool,
-    pub discovery_interval: u64,
-}
-
-/// API配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Api {
-    pub bind: String,
-    pub port: u16,
-    pub enable_cors: bool,
-    pub allowed_origins: Vec<String>,
-    pub rate_limit: u32,
-}
-
-/// Web配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Web {
-    pub bind: String,
-    pub port: u16,
-    pub enable_tls: bool,
-    pub tls_cert: Option<String>,
-    pub tls_key: Option<String>,
-    pub enable_compression: bool,
-}
-
-/// 认证配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Auth {
-    pub enable: bool,
-    pub secret_key: String,
-    pub token_expiry: u64,
-    pub allow_anonymous: bool,
-    pub whitelist: Vec<String>,
-    pub blacklist: Vec<String>,
-}
-
-/// 生成默认配置
-pub fn default_config() -> ServerConfig {
-    let mut rng = rand::thread_rng();
-    let secret_key = base64::engine::general_purpose::STANDARD.encode(
-        rng.gen::<[u8; 32]>(),
-    );
-    
-    ServerConfig {
-        server: Server {
-            bind: "0.0.0.0".to_string(),
-            port: 51820,
-            workers: 4,
-            timeout: 30,
-        },
-        virtual_device: VirtualDevice {
-            name: "vpnet0".to_string(),
-            ip: "10.0.0.1".to_string(),
-            subnet: "255.255.255.0".to_string(),
-            gateway: "10.0.0.1".to_string(),
-            mtu: 1420,
-            enable_ipv6: false,
-            ipv6_address: None,
-        },
-        node: Node {
-            id: format!("node_{:x}", rng.gen::<u64>()),
-            name: "VPNet Server".to_string(),
-            key_file: "vpnet-key.json".to_string(),
-            auto_discovery: true,
-            discovery_interval: 60,
-        },
-        api: Api {
-            bind: "0.0.0.0".to_string(),
-            port: 51821,
-            enable_cors: true,
-            allowed_origins: vec!["*".to_string()],
-            rate_limit: 100,
-        },
-        web: Web {
-            bind: "0.0.0.0".to_string(),
-            port: 51822,
-            enable_tls: false,
-            tls_cert: None,
-            tls_key: None,
-            enable_compression: true,
-        },
-        auth: Auth {
-            enable: true,
-            secret_key: secret_key,
-            token_expiry: 86400,
-            allow_anonymous: false,
-            whitelist: Vec::new(),
-            blacklist: Vec::new(),
-        },
-    }
-}
-
-/// 保存配置到文件
-pub fn save_config(config: &ServerConfig, path: &str) -> Result<(), ConfigError> {
-    let toml_str = toml::to_string_pretty(config)?;
-    let mut file = File::create(path)?;
-    file.write_all(toml_str.as_bytes())?;
-    Ok(())
-}
-
-/// 加载或生成配置
-pub fn load_or_generate_config(path: &str) -> Result<ServerConfig, ConfigError> {
-    if Path::new(path).exists() {
-        // 加载现有配置
-        let mut file = File::open(path)?;
-        let mut content = String::new();
-        file.read_to_string(&mut content)?;
-        let config: ServerConfig = toml::from_str(&content)?;
-        Ok(config)
-    } else {
-        // 生成新配置
-        let config = default_config();
-        save_config(&config, path)?;
-        Ok(config)
-    }
-}
-
-/// 加载或生成密钥对
-pub fn load_or_generate_keys(config: &ServerConfig) -> Result<(Vec<u8>, Vec<u8>), ConfigError> {
-    let key_path = Path::new(&config.node.key_file);
-    
-    if key_path.exists() {
-        // 加载现有密钥
-        let mut file = File::open(key_path)?;
-        let mut content = String::new();
-        file.read_to_string(&mut content)?;
-        
-        let keys: serde_json::Value = serde_json::from_str(&content)?;
-        let public_key = base64::engine::general_purpose::STANDARD.decode(
-            keys["public_key"].as_str().ok_or(ConfigError::Missing("public_key".to_string()))?
-        )?;
-        let private_key = base64::engine::general_purpose::STANDARD.decode(
-            keys["private_key"].as_str().ok_or(ConfigError::Missing("private_key".to_string()))?
-        )?;
-        
-        Ok((public_key, private_key))
-    } else {
-        // 生成新密钥对
-        let mut rng = rand::thread_rng();
-        let public_key = rng.gen::<[u8; 32]>().to_vec();
-        let private_key = rng.gen::<[u8; 32]>().to_vec();
-        
-        // 保存密钥到文件
-        let keys = serde_json::json!({
-            "public_key": base64::engine::general_purpose::STANDARD.encode(&public_key),
-            "private_key": base64::engine::general_purpose::STANDARD.encode(&private_key),
-            "generated_at": chrono::Utc::now().to_rfc3339()
-        });
-        
-        let keys_str = serde_json::to_string_pretty(&keys)?;
-        let mut file = File::create(key_path)?;
-        file.write_all(keys_str.as_bytes())?;
-        
-        Ok((public_key, private_key))
-    }
-}
-
-/// 验证配置
-pub fn validate_config(config: &ServerConfig) -> Result<(), ConfigError> {
-    // 验证服务器配置
-    if config.server.bind.is_empty() {
-        return Err(ConfigError::Missing("server.bind".to_string()));
-    }
-    
-    if config.server.port == 0 {
-        return Err(ConfigError::Invalid("server.port must be greater than 0".to_string()));
-    }
-    
-    // 验证虚拟设备配置
-    if config.virtual_device.name.is_empty() {
-        return Err(ConfigError::Missing("virtual_device.name".to_string()));
-    }
-    
-    if config.virtual_device.ip.is_empty() {
-        return Err(ConfigError::Missing("virtual_device.ip".to_string()));
-    }
-    
-    if config.virtual_device.subnet.is_empty() {
-        return Err(ConfigError::Missing("virtual_device.subnet".to_string()));
-    }
-    
-    if config.virtual_device.gateway.is_empty() {
-        return Err(ConfigError::Missing("virtual_device.gateway".to_string()));
-    }
-    
-    // 验证节点配置
-    if config.node.id.is_empty() {
-        return Err(ConfigError::Missing("node.id".to_string()));
-    }
-    
-    if config.node.name.is_empty() {
-        return Err(ConfigError::Missing("node.name".to_string()));
-    }
-    
-    // 验证API配置
-    if config.api.bind.is_empty() {
-        return Err(ConfigError::Missing("api.bind".to_string()));
-    }
-    
-    if config.api.port == 0 {
-        return Err(ConfigError::Invalid("api.port must be greater than 0".to_string()));
-    }
-    
-    // 验证Web配置
-    if config.web.bind.is_empty() {
-        return Err(ConfigError::Missing("web.bind".to_string()));
-    }
-    
-    if config.web.port == 0 {
-        return Err(ConfigError::Invalid("web.port must be greater than 0".to_string()));
-    }
-    
-    // 验证认证配置
-    if config.auth.secret_key.is_empty() {
-        return Err(ConfigError::Missing("auth.secret_key".to_string()));
-    }
-    
-    Ok(())
-}
+/*!
+VPNet Server 配置模块
+
+定义和加载服务器配置，包括：
+- 服务器基本配置
+- 虚拟设备配置
+- 节点配置
+- API配置
+- Web配置
+- 认证配置
+*/
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, create_dir_all};
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+use thiserror::Error;
+use rand::Rng;
+use base64::Engine;
+use vpnet::config::{check_duration, check_file_or_parent, check_ipv4, check_readable_file, closest_match, migrate_seconds_to_duration, NetworkConfig, ValidationReport};
+use vpnet::{normalize_enum_value, BackoffStrategy, BondingMode, CompatRule, Compression, ConfigMigrator, DeviceMode, HtbClass, HtbScheduler, IpCidr, KeyPair, LinkAggregation, MigrationError, PasswordHasher, SchedulerMode, SecureRng, StaticRoute, TcpKeepaliveConfig, TcpStreamConfig, TransformConfig};
+
+/// 配置错误
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    
+    #[error("Toml parsing error: {0}")]
+    Toml(#[from] toml::de::Error),
+    
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    
+    #[error("Invalid configuration: {0}")]
+    Invalid(String),
+    
+    #[error("Missing required configuration: {0}")]
+    Missing(String),
+    
+    #[error("Config migration error: {0}")]
+    Migration(#[from] MigrationError),
+    
+    #[error("Key file {path} is not a valid Ed25519 key pair ({reason}), a copy was saved to {backup}; restore a valid key or delete the file to generate a new node identity")]
+    InvalidKeyFile { path: String, backup: String, reason: String },
+}
+
+/// 当前配置格式版本
+pub const SCHEMA_VERSION: u32 = 4;
+
+/// 服务器配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ServerConfig {
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
+    pub server: Server,
+    pub virtual_device: VirtualDevice,
+    pub node: Node,
+    pub api: Api,
+    pub web: Web,
+    pub auth: Auth,
+    #[serde(default)]
+    pub geo: Option<Geo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<Hook>,
+    /// 客户端版本兼容矩阵，按顺序使用第一条匹配的规则
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compat: Vec<CompatRule>,
+    #[serde(default)]
+    pub debug: DebugConfig,
+    #[serde(default)]
+    pub mirror: MirrorConfig,
+    #[serde(default)]
+    pub registry: RegistryConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub ip_pool: IpPoolConfig,
+    #[serde(default)]
+    pub connection_pool: ConnectionPoolConfig,
+}
+
+/// 服务器基本配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Server {
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// 额外的监听地址，配置后取代`bind`和`port`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub listen: Vec<Listen>,
+    pub workers: u32,
+    #[serde(with = "vpnet::config::duration")]
+    pub timeout: Duration,
+    #[serde(default = "default_max_hops")]
+    pub max_hops: u8,
+    /// 每隔多少个数据转发请求对端确认一次，用于估计丢包，0表示不请求
+    #[serde(default = "default_ack_sampling_rate")]
+    pub ack_sampling_rate: u32,
+    #[serde(default)]
+    pub dscp_marking: bool,
+    #[serde(default = "default_migration_grace_period", with = "vpnet::config::duration")]
+    pub migration_grace_period: Duration,
+    #[serde(default = "default_watchdog_interval", with = "vpnet::config::duration")]
+    pub watchdog_interval: Duration,
+    /// 聚合的网卡，配置后在每个网卡的地址上监听`port`，取代`bind`和`listen`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub interfaces: Vec<String>,
+    #[serde(default)]
+    pub bonding_mode: BondingMode,
+    /// 链路超过该时间未收到回复时判定失效
+    #[serde(default = "default_failover_timeout", with = "vpnet::config::duration")]
+    pub failover_timeout: Duration,
+    /// 同时运行的事件钩子数上限
+    #[serde(default = "default_max_concurrent_hooks")]
+    pub max_concurrent_hooks: u32,
+    /// 转发数据包的调度策略
+    #[serde(default)]
+    pub scheduler: SchedulerMode,
+    /// `priority`和`wfq`调度下发送转发数据包的速率（Mbps），通常设为上行带宽，0表示不限速
+    #[serde(default)]
+    pub link_rate_mbps: u32,
+    /// `wfq`调度下各节点的权重，未列出的节点权重为1
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub peer_weights: HashMap<String, u32>,
+    /// `htb`调度下的分类层级，叶子分类通过`group`对应节点分组
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub htb_classes: Vec<HtbClass>,
+    /// 发往节点的数据的压缩模式，加密前压缩会泄露明文长度信息，默认不压缩
+    #[serde(default)]
+    pub compression: Compression,
+    /// 心跳间隔策略
+    #[serde(default)]
+    pub heartbeat: BackoffStrategy,
+    /// 节点维护暂停的最长时间，超过后自动恢复；未配置时需要手动恢复
+    #[serde(default, with = "vpnet::config::duration::option", skip_serializing_if = "Option::is_none")]
+    pub pause_timeout: Option<Duration>,
+    /// 会话票据有效期，配置后节点正常断开时签发票据，有效期内重新连接无需完整握手
+    #[serde(default, with = "vpnet::config::duration::option", skip_serializing_if = "Option::is_none")]
+    pub session_ticket_lifetime: Option<Duration>,
+    /// 分组带宽用尽时每个节点最多排队的数据包数，超出后丢弃
+    #[serde(default = "default_group_queue_depth")]
+    pub group_queue_depth: usize,
+    /// 每个节点保留的统计样本数，每秒采样一次
+    #[serde(default = "default_stats_history_capacity")]
+    pub stats_history_capacity: usize,
+    /// 在`port`的TCP端口上接受经HTTP代理CONNECT隧道连接的节点
+    #[serde(default)]
+    pub tcp_tunnel: bool,
+    /// TCP隧道连接关闭Nagle算法，避免心跳、握手等小消息被延迟
+    #[serde(default = "default_tcp_nodelay")]
+    pub tcp_nodelay: bool,
+    /// TCP隧道连接的发送缓冲区大小（字节），未设置时使用系统默认值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp_send_buffer: Option<usize>,
+    /// TCP隧道连接的接收缓冲区大小（字节），未设置时使用系统默认值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp_recv_buffer: Option<usize>,
+    /// TCP隧道连接空闲多久后开始发送保活探测，`"0s"`表示不启用保活
+    #[serde(default = "default_tcp_keepalive_time", with = "vpnet::config::duration")]
+    pub tcp_keepalive_time: Duration,
+    /// TCP保活探测的间隔
+    #[serde(default = "default_tcp_keepalive_interval", with = "vpnet::config::duration")]
+    pub tcp_keepalive_interval: Duration,
+    /// 连续多少次TCP保活探测无响应后断开连接
+    #[serde(default = "default_tcp_keepalive_retries")]
+    pub tcp_keepalive_retries: u32,
+    /// 运行模式，副本只从主服务端拉取状态，不接受节点连接
+    #[serde(default)]
+    pub mode: ServerMode,
+}
+
+impl Server {
+    /// TCP隧道连接的参数
+    pub fn tcp_stream_config(&self) -> TcpStreamConfig {
+        TcpStreamConfig {
+            nodelay: self.tcp_nodelay,
+            send_buffer_size: self.tcp_send_buffer,
+            receive_buffer_size: self.tcp_recv_buffer,
+            keepalive: (!self.tcp_keepalive_time.is_zero()).then(|| TcpKeepaliveConfig {
+                time: self.tcp_keepalive_time,
+                interval: self.tcp_keepalive_interval,
+                retries: self.tcp_keepalive_retries,
+            }),
+        }
+    }
+}
+
+/// 服务端运行模式
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMode {
+    /// 接受节点连接
+    Primary {
+        /// 副本的管理API地址，发现其中任一副本已提升为主服务端时本服务端停止运行
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        replicas: Vec<String>,
+    },
+    /// 只读副本，定期拉取主服务端的节点、策略路由和运行统计，通过API提供缓存的数据
+    Replica {
+        /// 主服务端管理API地址，例如`http://10.0.0.1:51821`
+        primary_api: String,
+        /// 拉取间隔
+        #[serde(default = "default_replica_poll_interval", with = "vpnet::config::duration")]
+        poll_interval: Duration,
+        /// 主服务端连续不可用且多数副本确认后自动提升为主服务端
+        #[serde(default)]
+        auto_promote: bool,
+        /// 其他副本的管理API地址，启用`auto_promote`时必须填写，用于确认主服务端不可用
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        witnesses: Vec<String>,
+        /// 提升优先级，多个副本同时确认主服务端不可用时由优先级最高的提升，各副本应互不相同
+        #[serde(default)]
+        priority: u32,
+    },
+}
+
+impl Default for ServerMode {
+    fn default() -> Self {
+        ServerMode::Primary { replicas: Vec::new() }
+    }
+}
+
+/// 监听地址配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Listen {
+    pub bind: String,
+    pub port: u16,
+}
+
+impl ServerConfig {
+    /// 获取所有UDP监听地址
+    ///
+    /// 配置了`server.interfaces`时使用各网卡的地址，配置了`[[server.listen]]`时使用其中的地址，
+    /// 否则使用`server.bind`和`server.port`。
+    pub fn listen_addresses(&self) -> Result<Vec<SocketAddr>, ConfigError> {
+        if !self.server.interfaces.is_empty() {
+            return LinkAggregation::resolve_interfaces(&self.server.interfaces, self.server.port)
+                .map_err(|e| ConfigError::Invalid(format!("server.interfaces: {}", e)));
+        }
+        
+        if self.server.listen.is_empty() {
+            return Ok(vec![parse_listen_address(&self.server.bind, self.server.port)?]);
+        }
+        
+        self.server.listen.iter()
+            .map(|listen| parse_listen_address(&listen.bind, listen.port))
+            .collect()
+    }
+}
+
+/// 解析监听地址
+fn parse_listen_address(bind: &str, port: u16) -> Result<SocketAddr, ConfigError> {
+    let ip: std::net::IpAddr = bind.parse()
+        .map_err(|_| ConfigError::Invalid(format!("invalid listen address: {}", bind)))?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// 虚拟设备配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct VirtualDevice {
+    pub name: String,
+    pub ip: String,
+    pub subnet: String,
+    pub gateway: String,
+    pub mtu: u32,
+    pub enable_ipv6: bool,
+    pub ipv6_address: Option<String>,
+    /// 在路由器通告中发布的IPv6 /64前缀，例如`fd00:1234::/64`，节点据此以SLAAC配置地址
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipv6_prefix: Option<String>,
+    #[serde(default)]
+    pub device_mode: DeviceMode,
+    #[serde(default = "default_max_restart_attempts")]
+    pub max_restart_attempts: u32,
+    #[serde(default = "default_restart_cooldown", with = "vpnet::config::duration")]
+    pub restart_cooldown: Duration,
+    #[serde(default)]
+    pub transforms: Vec<TransformConfig>,
+    #[serde(default = "default_flush_timeout", with = "vpnet::config::duration")]
+    pub flush_timeout: Duration,
+    #[serde(default)]
+    pub routes: Vec<StaticRoute>,
+}
+
+/// 节点配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Node {
+    pub id: String,
+    pub name: String,
+    pub key_file: String,
+    /// 是否在局域网内广播节点发现，默认关闭
+    #[serde(default)]
+    pub auto_discovery: bool,
+    #[serde(with = "vpnet::config::duration")]
+    pub discovery_interval: Duration,
+    /// 广播节点发现的网段，为空时广播到`255.255.255.255`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub discovery_subnets: Vec<IpCidr>,
+    #[serde(default)]
+    pub virtual_ip_conflict: ConflictStrategy,
+    #[serde(default)]
+    pub ip_pool: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<PeerGroup>,
+    /// 最多接纳的节点数，不填写表示不限制
+    #[serde(default)]
+    pub max_peers: Option<u32>,
+    /// 定期清理长期离线节点的cron表达式（含秒字段，按本地时间），例如`"0 0 3 * * *"`表示每天3点
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eviction_schedule: Option<String>,
+    /// 离线超过该天数的节点视为长期离线
+    #[serde(default = "default_idle_threshold_days")]
+    pub idle_threshold_days: u64,
+    /// 节点可以通过路由更新宣告的网段，按节点ID索引。未列出的节点只能宣告自己的虚拟IP
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub allowed_prefixes: HashMap<String, Vec<IpCidr>>,
+    /// 保存管理员设置的节点标签的数据库路径，服务端重启后恢复标签
+    #[serde(default = "default_tag_store")]
+    pub tag_store: String,
+}
+
+/// 虚拟IP冲突处理策略
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    /// 拒绝后加入的节点
+    #[default]
+    Reject,
+    /// 从地址池中为后加入的节点重新分配
+    AssignFromPool,
+}
+
+/// 节点分组
+///
+/// 组内节点可以互相访问；其他节点只有所在组列在`allowed_groups`中，
+/// 或节点本身列在`allowed_peers`中时，才能访问本组节点。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerGroup {
+    pub group_id: String,
+    /// 管理员指定的成员节点，节点注册时加入本组
+    #[serde(default)]
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub allowed_peers: Vec<String>,
+    #[serde(default)]
+    pub allowed_groups: Vec<String>,
+    /// 组内节点共用的中继带宽上限（Mbps），不填写表示不限制
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth_mbps: Option<u32>,
+}
+
+impl PeerGroup {
+    /// 判断节点能否访问本组节点
+    pub fn admits(&self, node_id: &str, group: Option<&str>) -> bool {
+        match group {
+            Some(group) if group == self.group_id || self.allowed_groups.iter().any(|g| g == group) => true,
+            _ => self.allowed_peers.iter().any(|peer| peer == node_id),
+        }
+    }
+    
+    /// 带宽上限换算为字节/秒
+    pub fn bandwidth_bytes_per_sec(&self) -> Option<u64> {
+        self.bandwidth_mbps.map(|mbps| u64::from(mbps) * 1_000_000 / 8)
+    }
+}
+
+/// API配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Api {
+    pub bind: String,
+    pub port: u16,
+    pub enable_cors: bool,
+    pub allowed_origins: Vec<String>,
+    pub rate_limit: u32,
+    /// 管理接口的访问令牌，请求需在`Authorization: Bearer`请求头中携带；
+    /// 为空时管理接口只接受来自本机回环地址的请求，节点认证和健康检查等接口不受影响
+    #[serde(default)]
+    pub admin_token: Option<String>,
+}
+
+impl Api {
+    /// 配置的管理令牌，空字符串视为未配置
+    pub fn admin_token(&self) -> Option<&str> {
+        self.admin_token.as_deref().filter(|token| !token.is_empty())
+    }
+}
+
+/// Web配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Web {
+    pub bind: String,
+    pub port: u16,
+    pub enable_tls: bool,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub enable_compression: bool,
+    /// 部署在负载均衡之后时启用，每个连接必须先发送PROXY协议v2头部，由vpnet-web读取
+    #[serde(default)]
+    pub proxy_protocol: bool,
+    /// Web管理界面的静态文件目录，副本模式下由服务端直接提供
+    #[serde(default = "default_web_static_dir")]
+    pub static_dir: String,
+}
+
+/// 认证配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Auth {
+    pub enable: bool,
+    pub secret_key: String,
+    #[serde(with = "vpnet::config::duration")]
+    pub token_expiry: Duration,
+    pub allow_anonymous: bool,
+    pub whitelist: Vec<String>,
+    pub blacklist: Vec<String>,
+    #[serde(default = "default_key_directory")]
+    pub key_directory: String,
+    #[serde(default)]
+    pub strict_key_pinning: bool,
+    #[serde(default)]
+    pub mode: AuthMode,
+    /// `password`模式下的用户及其口令哈希记录，可用`vpnet-server --hash-password`生成
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub users: HashMap<String, String>,
+    /// 口令哈希的PBKDF2迭代次数，调高后旧记录在用户下次登录时升级
+    #[serde(default = "default_password_iterations")]
+    pub password_iterations: u32,
+}
+
+/// 认证方式
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    /// 节点用密钥对签名授权请求
+    #[default]
+    Key,
+    /// 用户名和口令
+    Password,
+}
+
+/// 默认监听地址
+fn default_bind() -> String {
+    "0.0.0.0".to_string()
+}
+
+/// 默认监听端口
+fn default_port() -> u16 {
+    51820
+}
+
+/// 默认最大转发跳数
+fn default_max_hops() -> u8 {
+    vpnet::constants::DEFAULT_TTL
+}
+
+/// 默认每100个数据转发请求一次确认
+fn default_ack_sampling_rate() -> u32 {
+    vpnet::constants::DEFAULT_ACK_SAMPLING_RATE
+}
+
+/// 地理位置配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Geo {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub country_code: String,
+}
+
+/// 事件钩子配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Hook {
+    /// 触发钩子的事件，例如`peer_connected`
+    pub event: String,
+    /// 要执行的命令，事件数据以JSON格式写入其标准输入
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(default = "default_hook_timeout", with = "vpnet::config::duration")]
+    pub timeout: Duration,
+}
+
+/// 调试接口配置
+///
+/// gRPC调试接口需要以`debug-grpc`特性编译，默认关闭且只监听本机。
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DebugConfig {
+    #[serde(default)]
+    pub enable: bool,
+    #[serde(default = "default_debug_bind")]
+    pub bind: String,
+    #[serde(default = "default_grpc_port")]
+    pub grpc_port: u16,
+    /// 识别转发流量的应用协议并在`/api/stats`中按协议计数，不依赖gRPC调试接口
+    #[serde(default)]
+    pub enable_dpi: bool,
+    /// 将虚拟网卡收到的数据包写入该pcap文件，不依赖gRPC调试接口
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture_file: Option<String>,
+    /// 抓包的BPF过滤表达式，例如`tcp dst port 443`，需要以`pcap`特性编译
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture_filter: Option<String>,
+    /// 单个抓包文件的大小上限（MB），超过时轮转，0表示不限制
+    #[serde(default = "default_capture_max_size_mb")]
+    pub capture_max_size_mb: u64,
+    /// 轮转时最多保留的抓包文件数，包括正在写入的文件
+    #[serde(default = "default_capture_max_files")]
+    pub capture_max_files: u32,
+}
+
+impl DebugConfig {
+    /// 抓包文件的轮转设置，未限制文件大小时返回`None`
+    pub fn capture_rotation(&self) -> Option<vpnet::CaptureRotation> {
+        (self.capture_max_size_mb > 0).then(|| vpnet::CaptureRotation {
+            max_file_bytes: self.capture_max_size_mb.saturating_mul(1024 * 1024),
+            max_files: self.capture_max_files,
+        })
+    }
+}
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            bind: default_debug_bind(),
+            grpc_port: default_grpc_port(),
+            enable_dpi: false,
+            capture_file: None,
+            capture_filter: None,
+            capture_max_size_mb: default_capture_max_size_mb(),
+            capture_max_files: default_capture_max_files(),
+        }
+    }
+}
+
+/// 流量镜像配置
+///
+/// 开启后发往本节点的数据包解密后按`sample_rate`抽样，以UDP数据报发往`destination`供IDS/IPS分析。
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MirrorConfig {
+    #[serde(default)]
+    pub enable: bool,
+    #[serde(default)]
+    pub destination: Option<String>,
+    #[serde(default = "default_mirror_sample_rate")]
+    pub sample_rate: f32,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            destination: None,
+            sample_rate: default_mirror_sample_rate(),
+        }
+    }
+}
+
+/// 节点注册表配置
+///
+/// 多个服务端共用Redis注册表时，节点重新连接到其他服务端仍沿用原来的虚拟IP和分组。
+/// Redis注册表需要以`redis-registry`特性编译。
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RegistryConfig {
+    #[serde(default)]
+    pub backend: RegistryBackend,
+    /// Redis地址，例如`redis://127.0.0.1:6379`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redis_url: Option<String>,
+    /// Redis键前缀，共用同一Redis的不同网络应使用不同的前缀
+    #[serde(default = "default_registry_key_prefix")]
+    pub key_prefix: String,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            backend: RegistryBackend::default(),
+            redis_url: None,
+            key_prefix: default_registry_key_prefix(),
+        }
+    }
+}
+
+/// 地址池配置，地址池网段见`node.ip_pool`
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct IpPoolConfig {
+    /// 管理员为节点预留的地址
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reservations: Vec<IpReservation>,
+}
+
+/// 节点连接端口池配置
+///
+/// 开启后启动时在`server.bind`的临时端口上预先绑定`max_ports`个UDP套接字，服务端主动与节点重新握手时分配、
+/// 节点断开后归还。每个端口有一个接收任务，不宜设置过大。
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ConnectionPoolConfig {
+    #[serde(default)]
+    pub enable: bool,
+    #[serde(default = "default_connection_pool_ports")]
+    pub max_ports: u32,
+}
+
+impl Default for ConnectionPoolConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            max_ports: default_connection_pool_ports(),
+        }
+    }
+}
+
+/// 为节点预留的地址，节点注册时总是分配该地址
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct IpReservation {
+    pub node_id: String,
+    pub ip: Ipv4Addr,
+}
+
+/// 节点注册表后端
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistryBackend {
+    /// 保存在本进程内存中
+    #[default]
+    Local,
+    /// 保存在Redis中，多个服务端共用
+    Redis,
+}
+
+/// 默认每5秒拉取一次主服务端状态
+fn default_replica_poll_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_web_static_dir() -> String {
+    "static".to_string()
+}
+
+/// 默认套接字缓冲区4MB
+/// 默认Redis键前缀
+fn default_registry_key_prefix() -> String {
+    "vpnet".to_string()
+}
+
+/// 默认关闭Nagle算法
+fn default_tcp_nodelay() -> bool {
+    true
+}
+
+/// 默认TCP连接空闲60秒后开始保活探测
+fn default_tcp_keepalive_time() -> Duration {
+    vpnet::TcpKeepaliveConfig::default().time
+}
+
+/// 默认每10秒发送一次TCP保活探测
+fn default_tcp_keepalive_interval() -> Duration {
+    vpnet::TcpKeepaliveConfig::default().interval
+}
+
+/// 默认连续6次TCP保活探测无响应后断开
+fn default_tcp_keepalive_retries() -> u32 {
+    vpnet::TcpKeepaliveConfig::default().retries
+}
+
+/// 默认端口池绑定64个端口
+fn default_connection_pool_ports() -> u32 {
+    64
+}
+
+/// 默认镜像全部数据包
+fn default_mirror_sample_rate() -> f32 {
+    1.0
+}
+
+/// 默认每个节点最多排队1024个数据包
+fn default_group_queue_depth() -> usize {
+    vpnet::constants::SCHEDULER_QUEUE_LEN
+}
+
+/// 默认保留1小时的统计样本
+fn default_stats_history_capacity() -> usize {
+    vpnet::RollingWindowStats::DEFAULT_CAPACITY
+}
+
+/// 默认PBKDF2迭代次数
+fn default_password_iterations() -> u32 {
+    vpnet::PBKDF2_ITERATIONS
+}
+
+/// 默认离线30天后清理
+fn default_idle_threshold_days() -> u64 {
+    30
+}
+
+/// 默认调试接口绑定地址
+fn default_debug_bind() -> String {
+    "127.0.0.1".to_string()
+}
+
+/// 默认调试接口端口
+fn default_grpc_port() -> u16 {
+    51823
+}
+
+/// 默认节点地址迁移宽限期
+fn default_migration_grace_period() -> Duration {
+    Duration::from_secs(vpnet::constants::MIGRATION_GRACE_PERIOD)
+}
+
+/// 默认看门狗超时
+fn default_watchdog_interval() -> Duration {
+    Duration::from_secs(vpnet::constants::WATCHDOG_INTERVAL)
+}
+
+/// 默认链路故障切换时间
+fn default_failover_timeout() -> Duration {
+    Duration::from_secs(vpnet::constants::LINK_FAILOVER_TIMEOUT)
+}
+
+/// 默认同时运行的事件钩子数上限
+fn default_max_concurrent_hooks() -> u32 {
+    4
+}
+
+/// 默认事件钩子超时时间
+fn default_hook_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// 默认虚拟网卡最大重建次数
+fn default_max_restart_attempts() -> u32 {
+    3
+}
+
+/// 默认虚拟网卡重建冷却时间
+fn default_restart_cooldown() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// 默认虚拟网卡关闭时写出缓冲数据包的超时时间
+fn default_flush_timeout() -> Duration {
+    Duration::from_secs(2)
+}
+
+/// 默认节点公钥缓存路径
+fn default_key_directory() -> String {
+    "vpnet-keys.db".to_string()
+}
+
+/// 默认单个抓包文件的大小上限（MB）
+fn default_capture_max_size_mb() -> u64 {
+    100
+}
+
+/// 默认最多保留的抓包文件数
+fn default_capture_max_files() -> u32 {
+    5
+}
+
+/// 默认节点标签数据库路径
+fn default_tag_store() -> String {
+    "vpnet-tags.db".to_string()
+}
+
+/// 未写版本号的配置视为版本1
+fn default_schema_version() -> u32 {
+    1
+}
+
+/// 生成默认配置
+pub fn default_config() -> ServerConfig {
+    let mut rng = rand::thread_rng();
+    let secret_key = base64::engine::general_purpose::STANDARD.encode(
+        SecureRng::new().gen::<[u8; 32]>(),
+    );
+    
+    ServerConfig {
+        schema_version: SCHEMA_VERSION,
+        server: Server {
+            bind: default_bind(),
+            port: default_port(),
+            listen: Vec::new(),
+            workers: 4,
+            timeout: Duration::from_secs(30),
+            max_hops: default_max_hops(),
+            ack_sampling_rate: default_ack_sampling_rate(),
+            dscp_marking: false,
+            migration_grace_period: default_migration_grace_period(),
+            watchdog_interval: default_watchdog_interval(),
+            interfaces: Vec::new(),
+            bonding_mode: BondingMode::default(),
+            failover_timeout: default_failover_timeout(),
+            max_concurrent_hooks: default_max_concurrent_hooks(),
+            scheduler: SchedulerMode::default(),
+            link_rate_mbps: 0,
+            peer_weights: HashMap::new(),
+            htb_classes: Vec::new(),
+            compression: Compression::default(),
+            heartbeat: BackoffStrategy::default(),
+            pause_timeout: None,
+            session_ticket_lifetime: None,
+            group_queue_depth: default_group_queue_depth(),
+            stats_history_capacity: default_stats_history_capacity(),
+            tcp_tunnel: false,
+            tcp_nodelay: default_tcp_nodelay(),
+            tcp_send_buffer: None,
+            tcp_recv_buffer: None,
+            tcp_keepalive_time: default_tcp_keepalive_time(),
+            tcp_keepalive_interval: default_tcp_keepalive_interval(),
+            tcp_keepalive_retries: default_tcp_keepalive_retries(),
+            mode: ServerMode::default(),
+        },
+        virtual_device: VirtualDevice {
+            name: "vpnet0".to_string(),
+            ip: "10.0.0.1".to_string(),
+            subnet: "255.255.255.0".to_string(),
+            gateway: "10.0.0.1".to_string(),
+            mtu: 1420,
+            enable_ipv6: false,
+            ipv6_address: None,
+            ipv6_prefix: None,
+            device_mode: DeviceMode::Tun,
+            max_restart_attempts: default_max_restart_attempts(),
+            restart_cooldown: default_restart_cooldown(),
+            transforms: Vec::new(),
+            flush_timeout: default_flush_timeout(),
+            routes: Vec::new(),
+        },
+        node: Node {
+            id: format!("node_{:x}", rng.gen::<u64>()),
+            name: "VPNet Server".to_string(),
+            key_file: "vpnet-key.json".to_string(),
+            auto_discovery: false,
+            discovery_interval: Duration::from_secs(60),
+            discovery_subnets: Vec::new(),
+            virtual_ip_conflict: ConflictStrategy::Reject,
+            ip_pool: None,
+            groups: Vec::new(),
+            max_peers: None,
+            eviction_schedule: None,
+            idle_threshold_days: default_idle_threshold_days(),
+            allowed_prefixes: HashMap::new(),
+            tag_store: default_tag_store(),
+        },
+        api: Api {
+            bind: "0.0.0.0".to_string(),
+            port: 51821,
+            enable_cors: true,
+            allowed_origins: vec!["*".to_string()],
+            rate_limit: 100,
+            admin_token: None,
+        },
+        web: Web {
+            bind: "0.0.0.0".to_string(),
+            port: 51822,
+            enable_tls: false,
+            tls_cert: None,
+            tls_key: None,
+            enable_compression: true,
+            proxy_protocol: false,
+            static_dir: default_web_static_dir(),
+        },
+        auth: Auth {
+            enable: true,
+            secret_key: secret_key,
+            token_expiry: Duration::from_secs(86400),
+            allow_anonymous: false,
+            whitelist: Vec::new(),
+            blacklist: Vec::new(),
+            key_directory: default_key_directory(),
+            strict_key_pinning: false,
+            mode: AuthMode::default(),
+            users: HashMap::new(),
+            password_iterations: default_password_iterations(),
+        },
+        geo: None,
+        hooks: Vec::new(),
+        compat: Vec::new(),
+        debug: DebugConfig::default(),
+        mirror: MirrorConfig::default(),
+        registry: RegistryConfig::default(),
+        network: NetworkConfig::default(),
+        ip_pool: IpPoolConfig::default(),
+        connection_pool: ConnectionPoolConfig::default(),
+    }
+}
+
+/// 保存配置到文件
+pub fn save_config(config: &ServerConfig, path: &str) -> Result<(), ConfigError> {
+    let toml_str = toml::to_string_pretty(config)?;
+    let mut file = File::create(path)?;
+    file.write_all(toml_str.as_bytes())?;
+    Ok(())
+}
+
+/// 包含所有迁移步骤的配置迁移器
+fn config_migrator() -> ConfigMigrator {
+    ConfigMigrator::new(vec![migrate_v1_to_v2, migrate_v2_to_v3, migrate_v3_to_v4])
+}
+
+/// 版本1到2：补齐后来改为必填的认证名单
+fn migrate_v1_to_v2(table: &mut toml::Table) -> Result<(), String> {
+    let auth = table.get_mut("auth")
+        .and_then(toml::Value::as_table_mut)
+        .ok_or("missing [auth] section")?;
+    for key in ["whitelist", "blacklist"] {
+        auth.entry(key).or_insert_with(|| toml::Value::Array(Vec::new()));
+    }
+    Ok(())
+}
+
+/// 版本2到3：枚举取值统一为小写蛇形命名
+fn migrate_v2_to_v3(table: &mut toml::Table) -> Result<(), String> {
+    normalize_enum_value(table, "virtual_device", "device_mode");
+    normalize_enum_value(table, "node", "virtual_ip_conflict");
+    Ok(())
+}
+
+/// 版本3到4：以秒为单位的整数时长改为`"30s"`形式的字符串，钩子的`timeout_secs`改名为`timeout`
+fn migrate_v3_to_v4(table: &mut toml::Table) -> Result<(), String> {
+    const DURATION_FIELDS: &[(&str, &str)] = &[
+        ("server", "timeout"),
+        ("server", "migration_grace_period"),
+        ("server", "watchdog_interval"),
+        ("server", "failover_timeout"),
+        ("server", "pause_timeout"),
+        ("server", "session_ticket_lifetime"),
+        ("server", "tcp_keepalive_time"),
+        ("server", "tcp_keepalive_interval"),
+        ("server.mode", "poll_interval"),
+        ("virtual_device", "restart_cooldown"),
+        ("virtual_device", "flush_timeout"),
+        ("node", "discovery_interval"),
+        ("auth", "token_expiry"),
+    ];
+    
+    for (section, key) in DURATION_FIELDS {
+        migrate_seconds_to_duration(table, section, key)?;
+    }
+    
+    let hooks = table.get_mut("hooks").and_then(toml::Value::as_array_mut).into_iter().flatten();
+    for hook in hooks.filter_map(toml::Value::as_table_mut) {
+        if let Some(timeout) = hook.remove("timeout_secs") {
+            hook.insert("timeout".to_string(), timeout);
+        }
+        migrate_seconds_to_duration(hook, "", "timeout").map_err(|e| format!("hooks: {}", e))?;
+    }
+    Ok(())
+}
+
+/// 加载配置
+///
+/// 配置版本低于当前版本时先执行迁移，原文件备份为`.bak`后写回迁移结果。
+pub fn load_config(path: &str) -> Result<ServerConfig, ConfigError> {
+    let mut content = String::new();
+    File::open(path)?.read_to_string(&mut content)?;
+    
+    let version = ConfigMigrator::detect_version(&content)?;
+    if version < SCHEMA_VERSION {
+        let migrated = config_migrator().migrate(&content, version)?;
+        std::fs::copy(path, format!("{}.bak", path))?;
+        std::fs::write(path, &migrated)?;
+        log::info!("Migrated config {} from schema version {} to {}", path, version, SCHEMA_VERSION);
+        content = migrated;
+    } else if version > SCHEMA_VERSION {
+        return Err(MigrationError::Unsupported { version, supported: SCHEMA_VERSION }.into());
+    }
+    
+    Ok(toml::from_str(&content)?)
+}
+
+/// 加载或生成配置
+pub fn load_or_generate_config(path: &str) -> Result<ServerConfig, ConfigError> {
+    if Path::new(path).exists() {
+        // 加载现有配置
+        load_config(path)
+    } else {
+        // 生成新配置
+        let config = default_config();
+        save_config(&config, path)?;
+        Ok(config)
+    }
+}
+
+/// 加载或生成密钥对
+///
+/// 返回Ed25519公钥和PKCS#8格式的私钥。密钥文件无效（例如旧版本生成的随机字节密钥）时不会重新生成，
+/// 否则节点身份改变，固定了公钥的对端将拒绝握手：先将文件复制为`<key_file>.bak`，再返回`ConfigError::InvalidKeyFile`。
+pub fn load_or_generate_keys(config: &ServerConfig) -> Result<(Vec<u8>, Vec<u8>), ConfigError> {
+    let key_path = Path::new(&config.node.key_file);
+    
+    if key_path.exists() {
+        // 加载现有密钥
+        let mut file = File::open(key_path)?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        
+        let keys: serde_json::Value = serde_json::from_str(&content)?;
+        let public_key = keys["public_key"].as_str().ok_or(ConfigError::Missing("public_key".to_string()))?;
+        let private_key = keys["private_key"].as_str().ok_or(ConfigError::Missing("private_key".to_string()))?;
+        
+        return match KeyPair::from_base64(public_key, private_key) {
+            Ok(key_pair) => Ok((key_pair.public_key.clone(), key_pair.private_key().to_vec())),
+            Err(e) => {
+                let backup = format!("{}.bak", key_path.display());
+                std::fs::copy(key_path, &backup)?;
+                Err(ConfigError::InvalidKeyFile {
+                    path: key_path.display().to_string(),
+                    backup,
+                    reason: e.to_string(),
+                })
+            }
+        };
+    }
+    
+    // 生成新密钥对
+    let key_pair = KeyPair::generate().map_err(|e| ConfigError::Invalid(e.to_string()))?;
+    let (public_b64, private_b64) = key_pair.to_base64();
+    
+    // 保存密钥到文件
+    let keys = serde_json::json!({
+        "public_key": public_b64,
+        "private_key": private_b64,
+        "generated_at": chrono::Utc::now().to_rfc3339()
+    });
+    
+    let keys_str = serde_json::to_string_pretty(&keys)?;
+    let mut file = File::create(key_path)?;
+    file.write_all(keys_str.as_bytes())?;
+    
+    Ok((key_pair.public_key.clone(), key_pair.private_key().to_vec()))
+}
+
+/// 单个IP每分钟超过该次数的API限速视为过高
+const HIGH_RATE_LIMIT: u32 = 10_000;
+
+/// 验证配置
+///
+/// 检查所有字段而不是在第一个错误处停止，同时给出不影响启动的警告。
+pub fn validate_config(config: &ServerConfig) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let heartbeat_interval = Duration::from_secs(vpnet::constants::HEARTBEAT_INTERVAL);
+    
+    // 验证服务器配置
+    if config.server.listen.is_empty() {
+        if config.server.bind.is_empty() {
+            report.missing("server.bind").suggest("Use 0.0.0.0 to listen on all interfaces");
+        }
+        
+        if config.server.port == 0 {
+            report.error("server.port", "must be greater than 0")
+                .suggest(format!("Did you mean {}?", vpnet::DEFAULT_PORT));
+        }
+    }
+    
+    for (i, listen) in config.server.listen.iter().enumerate() {
+        if listen.bind.is_empty() {
+            report.missing(format!("server.listen[{}].bind", i));
+        }
+        
+        if listen.port == 0 {
+            report.error(format!("server.listen[{}].port", i), "must be greater than 0")
+                .suggest(format!("Did you mean {}?", vpnet::DEFAULT_PORT));
+        }
+    }
+    
+    if let ServerMode::Replica { primary_api, poll_interval, auto_promote, witnesses, .. } = &config.server.mode {
+        if primary_api.is_empty() {
+            report.missing("server.mode.primary_api");
+        } else if !primary_api.starts_with("http://") && !primary_api.starts_with("https://") {
+            report.error("server.mode.primary_api", format!("expected http:// or https:// URL: {}", primary_api))
+                .suggest(format!("Did you mean http://{}?", primary_api));
+        }
+        
+        check_duration("server.mode.poll_interval", *poll_interval, Duration::from_secs(1), Duration::from_secs(3600), &mut report);
+        
+        // 没有见证副本时无法区分主服务端宕机和网络分区，自动提升会导致出现两个主服务端
+        if *auto_promote && witnesses.is_empty() {
+            report.error("server.mode.witnesses", "auto_promote requires at least one witness replica to confirm the primary is down")
+                .suggest("Add the API address of another replica, e.g. witnesses = [\"http://10.0.0.3:51821\"]");
+        }
+        
+        for (i, witness) in witnesses.iter().enumerate() {
+            if !witness.starts_with("http://") && !witness.starts_with("https://") {
+                report.error(format!("server.mode.witnesses[{}]", i), format!("expected http:// or https:// URL: {}", witness))
+                    .suggest(format!("Did you mean http://{}?", witness));
+            }
+        }
+        
+        if config.web.enable_tls {
+            report.warn("web.enable_tls", "the replica web interface is served without TLS; terminate TLS in front of it");
+        }
+    }
+    
+    if let ServerMode::Primary { replicas } = &config.server.mode {
+        for (i, replica) in replicas.iter().enumerate() {
+            if !replica.starts_with("http://") && !replica.starts_with("https://") {
+                report.error(format!("server.mode.replicas[{}]", i), format!("expected http:// or https:// URL: {}", replica))
+                    .suggest(format!("Did you mean http://{}?", replica));
+            }
+        }
+    }
+    
+    if !config.server.interfaces.is_empty() {
+        if !config.server.listen.is_empty() {
+            report.error("server.interfaces", "cannot be used together with server.listen")
+                .suggest("Remove either server.interfaces or server.listen");
+        }
+        
+        if config.server.port == 0 {
+            report.error("server.port", "must be greater than 0")
+                .suggest(format!("Did you mean {}?", vpnet::DEFAULT_PORT));
+        }
+        
+        if config.server.failover_timeout <= heartbeat_interval {
+            report.error(
+                "server.failover_timeout",
+                format!("must be greater than the heartbeat interval ({})", humantime::format_duration(heartbeat_interval))
+            ).suggest(format!("Did you mean {}?", humantime::format_duration(heartbeat_interval * 3)));
+        }
+    }
+    
+    check_duration("server.timeout", config.server.timeout, Duration::from_secs(1), Duration::from_secs(3600), &mut report);
+    check_duration("server.migration_grace_period", config.server.migration_grace_period, Duration::ZERO, Duration::from_secs(3600), &mut report);
+    
+    if let Some(pause_timeout) = config.server.pause_timeout {
+        check_duration("server.pause_timeout", pause_timeout, Duration::from_secs(1), Duration::from_secs(7 * 86400), &mut report);
+    }
+    
+    if let Some(lifetime) = config.server.session_ticket_lifetime {
+        check_duration("server.session_ticket_lifetime", lifetime, Duration::from_secs(1), Duration::from_secs(86400), &mut report);
+    }
+    
+    if config.server.group_queue_depth == 0 {
+        report.error("server.group_queue_depth", "must be greater than 0")
+            .suggest(format!("Did you mean {}?", default_group_queue_depth()));
+    }
+    
+    if config.server.max_concurrent_hooks == 0 {
+        report.error("server.max_concurrent_hooks", "must be greater than 0");
+    }
+    
+    if let Compression::Adaptive { ratio_threshold } = config.server.compression {
+        if !(ratio_threshold > 0.0 && ratio_threshold <= 1.0) {
+            report.error("server.compression.ratio_threshold", "must be in (0, 1]");
+        }
+    }
+    if config.server.compression != Compression::None {
+        report.warn("server.compression", "compressing before encryption leaks plaintext length and allows length-oracle attacks (VORACLE)");
+    }
+    
+    for (node_id, _) in config.server.peer_weights.iter().filter(|(_, weight)| **weight == 0) {
+        report.error(format!("server.peer_weights.{}", node_id), "must be greater than 0");
+    }
+    
+    for (i, hook) in config.hooks.iter().enumerate() {
+        if !crate::hooks::HOOK_EVENTS.contains(&hook.event.as_str()) {
+            let error = report.error(
+                format!("hooks[{}].event", i),
+                format!("must be one of: {}", crate::hooks::HOOK_EVENTS.join(", "))
+            );
+            if let Some(event) = closest_match(&hook.event, crate::hooks::HOOK_EVENTS) {
+                error.suggest(format!("Did you mean {}?", event));
+            }
+        }
+        
+        if hook.command.is_empty() {
+            report.missing(format!("hooks[{}].command", i));
+        }
+        
+        check_duration(&format!("hooks[{}].timeout", i), hook.timeout, Duration::from_secs(1), Duration::from_secs(600), &mut report);
+    }
+    
+    for (i, rule) in config.compat.iter().enumerate() {
+        if let Err(e) = rule.validate() {
+            report.error(format!("compat[{}]", i), e);
+        }
+    }
+    
+    if config.server.max_hops == 0 {
+        report.error("server.max_hops", "must be greater than 0")
+            .suggest(format!("Did you mean {}?", default_max_hops()));
+    }
+    
+    if let Err(e) = config.server.heartbeat.validate() {
+        report.error("server.heartbeat", e);
+    }
+    
+    if config.server.heartbeat.max_delay() > heartbeat_interval {
+        report.error(
+            "server.heartbeat",
+            format!("must not exceed {}, otherwise peers time out", humantime::format_duration(heartbeat_interval))
+        );
+    }
+    
+    if config.server.watchdog_interval <= heartbeat_interval {
+        report.error(
+            "server.watchdog_interval",
+            format!("must be greater than the heartbeat interval ({})", humantime::format_duration(heartbeat_interval))
+        ).suggest(format!("Did you mean {}?", humantime::format_duration(default_watchdog_interval())));
+    }
+    
+    // 验证虚拟设备配置
+    if config.virtual_device.name.is_empty() {
+        report.missing("virtual_device.name");
+    }
+    
+    if config.virtual_device.ip.is_empty() {
+        report.missing("virtual_device.ip");
+    }
+    
+    if config.virtual_device.subnet.is_empty() {
+        report.missing("virtual_device.subnet");
+    }
+    
+    if config.virtual_device.gateway.is_empty() {
+        report.missing("virtual_device.gateway");
+    }
+    
+    check_duration("virtual_device.restart_cooldown", config.virtual_device.restart_cooldown, Duration::ZERO, Duration::from_secs(3600), &mut report);
+    check_duration("virtual_device.flush_timeout", config.virtual_device.flush_timeout, Duration::ZERO, Duration::from_secs(60), &mut report);
+    
+    for (i, route) in config.virtual_device.routes.iter().enumerate() {
+        if route.cidr().is_err() {
+            report.error(
+                format!("virtual_device.routes[{}]", i),
+                format!("invalid CIDR {}/{}", route.network, route.prefix_len)
+            );
+        }
+        
+        if route.gateway().is_err() {
+            report.error(
+                format!("virtual_device.routes[{}].gateway", i),
+                format!("invalid gateway {}", route.gateway)
+            );
+        }
+    }
+    
+    // 验证节点配置
+    if config.node.id.is_empty() {
+        report.missing("node.id");
+    }
+    
+    if config.node.name.is_empty() {
+        report.missing("node.name");
+    }
+    
+    if let Some(pool) = &config.node.ip_pool {
+        if pool.parse::<vpnet::IpCidr>().is_err() {
+            report.error("node.ip_pool", format!("is not a valid CIDR: {}", pool))
+                .suggest("Use the form 10.0.0.0/24");
+        }
+    } else if config.node.virtual_ip_conflict == ConflictStrategy::AssignFromPool {
+        report.missing("node.ip_pool")
+            .suggest("Set node.ip_pool or change node.virtual_ip_conflict to reject");
+    }
+    
+    let pool = config.node.ip_pool.as_deref().and_then(|pool| pool.parse::<vpnet::IpCidr>().ok());
+    let server_ip = config.virtual_device.ip.parse::<Ipv4Addr>().ok();
+    let mut reserved_ips = std::collections::HashSet::new();
+    let mut reserved_nodes = std::collections::HashSet::new();
+    for (i, reservation) in config.ip_pool.reservations.iter().enumerate() {
+        if config.node.ip_pool.is_none() {
+            report.missing("node.ip_pool")
+                .suggest("IP reservations are taken from node.ip_pool");
+            break;
+        }
+        if reservation.node_id.is_empty() {
+            report.missing(format!("ip_pool.reservations[{}].node_id", i));
+        } else if !reserved_nodes.insert(reservation.node_id.as_str()) {
+            report.error(format!("ip_pool.reservations[{}].node_id", i), format!("duplicate reservation for node {}", reservation.node_id));
+        }
+        if !reserved_ips.insert(reservation.ip) {
+            report.error(format!("ip_pool.reservations[{}].ip", i), format!("{} is reserved more than once", reservation.ip));
+        }
+        if let Some(pool) = pool.filter(|pool| !pool.contains(reservation.ip)) {
+            report.error(format!("ip_pool.reservations[{}].ip", i), format!("{} is outside node.ip_pool {}", reservation.ip, pool));
+        }
+        if server_ip == Some(reservation.ip) {
+            report.error(format!("ip_pool.reservations[{}].ip", i), format!("{} is the server's virtual_device.ip", reservation.ip));
+        }
+    }
+    
+    if config.node.max_peers == Some(0) {
+        report.error("node.max_peers", "must be greater than 0")
+            .suggest("Remove the field to accept any number of peers");
+    }
+    
+    if let Some(schedule) = &config.node.eviction_schedule {
+        if let Err(e) = schedule.parse::<cron::Schedule>() {
+            report.error("node.eviction_schedule", e.to_string())
+                .suggest("Use a cron expression with a seconds field, e.g. \"0 0 3 * * *\"");
+        }
+    }
+    
+    if config.node.auto_discovery {
+        check_duration(
+            "node.discovery_interval",
+            config.node.discovery_interval,
+            Duration::from_secs(vpnet::constants::BROADCAST_DISCOVERY_INTERVAL),
+            Duration::from_secs(86400),
+            &mut report
+        );
+    }
+    
+    if config.node.idle_threshold_days == 0 {
+        report.error("node.idle_threshold_days", "must be greater than 0")
+            .suggest(format!("Did you mean {}?", default_idle_threshold_days()));
+    } else if crate::node::idle_threshold_from_days(config.node.idle_threshold_days).is_none() {
+        report.error("node.idle_threshold_days", "is too large")
+            .suggest(format!("Did you mean {}?", default_idle_threshold_days()));
+    }
+    
+    let mut group_ids = std::collections::HashSet::new();
+    for (i, group) in config.node.groups.iter().enumerate() {
+        if group.group_id.is_empty() {
+            report.missing(format!("node.groups[{}].group_id", i));
+        } else if !group_ids.insert(group.group_id.as_str()) {
+            report.error(format!("node.groups[{}].group_id", i), format!("duplicate group {}", group.group_id));
+        }
+        
+        if group.bandwidth_mbps == Some(0) {
+            report.error(format!("node.groups[{}].bandwidth_mbps", i), "must be greater than 0")
+                .suggest("Remove the field to leave the group unlimited");
+        }
+    }
+    
+    if matches!(config.server.scheduler, SchedulerMode::Priority | SchedulerMode::Wfq) && config.server.link_rate_mbps == 0 {
+        report.warn("server.link_rate_mbps", "not set, queued packets are sent immediately and the scheduler has no effect; set it to the uplink bandwidth");
+    }
+    
+    if config.server.scheduler == SchedulerMode::Htb {
+        if config.server.htb_classes.is_empty() {
+            report.missing("server.htb_classes");
+        } else if let Err(e) = HtbScheduler::validate_classes(&config.server.htb_classes) {
+            report.error("server.htb_classes", e);
+        }
+        for (i, class) in config.server.htb_classes.iter().enumerate() {
+            if let Some(group) = class.group.as_deref().filter(|group| !group_ids.contains(group)) {
+                report.warn(format!("server.htb_classes[{}].group", i), format!("group {} is not defined in node.groups", group));
+            }
+        }
+    }
+    
+    // 验证API配置
+    if config.api.bind.is_empty() {
+        report.missing("api.bind");
+    }
+    
+    if config.api.port == 0 {
+        report.error("api.port", "must be greater than 0").suggest("Did you mean 51821?");
+    }
+    
+    if config.api.rate_limit > HIGH_RATE_LIMIT {
+        report.warn("api.rate_limit", "rate_limit is very high, this may allow abuse");
+    }
+    
+    if config.api.enable_cors && config.api.allowed_origins.iter().any(|origin| origin == "*") {
+        report.warn("api.allowed_origins", "any origin may call the API from a browser");
+    }
+    
+    if config.api.admin_token().is_none() && config.api.bind.parse::<std::net::IpAddr>().is_ok_and(|ip| !ip.is_loopback()) {
+        report.warn("api.admin_token", "not set, management endpoints only accept requests from localhost");
+    }
+    
+    // 验证Web配置
+    if config.web.bind.is_empty() {
+        report.missing("web.bind");
+    }
+    
+    if config.web.port == 0 {
+        report.error("web.port", "must be greater than 0").suggest("Did you mean 51822?");
+    }
+    
+    // 验证调试接口配置
+    if config.debug.enable {
+        if config.debug.bind.is_empty() {
+            report.missing("debug.bind").suggest(format!("Did you mean {}?", default_debug_bind()));
+        } else if config.debug.bind.parse::<std::net::IpAddr>().is_ok_and(|ip| !ip.is_loopback()) {
+            report.warn("debug.bind", "the debug interface is reachable from other hosts");
+        }
+        
+        if config.debug.grpc_port == 0 {
+            report.error("debug.grpc_port", "must be greater than 0")
+                .suggest(format!("Did you mean {}?", default_grpc_port()));
+        }
+    }
+    
+    if let Some(filter) = &config.debug.capture_filter {
+        if config.debug.capture_file.is_none() {
+            report.missing("debug.capture_file").suggest("debug.capture_filter has no effect without it");
+        }
+        if let Err(e) = vpnet::BpfFilter::compile_for(config.virtual_device.device_mode, filter) {
+            report.error("debug.capture_filter", e.to_string());
+        }
+    }
+    if config.debug.capture_max_size_mb > 0 && config.debug.capture_max_files == 0 {
+        report.error("debug.capture_max_files", "must be greater than 0")
+            .suggest(format!("Did you mean {}?", default_capture_max_files()));
+    }
+    
+    // 验证TCP隧道连接参数
+    if !config.server.tcp_keepalive_time.is_zero() {
+        check_duration("server.tcp_keepalive_time", config.server.tcp_keepalive_time, Duration::from_secs(1), Duration::from_secs(7200), &mut report);
+        check_duration("server.tcp_keepalive_interval", config.server.tcp_keepalive_interval, Duration::from_secs(1), Duration::from_secs(600), &mut report);
+    }
+    for (field, size) in [("server.tcp_send_buffer", config.server.tcp_send_buffer), ("server.tcp_recv_buffer", config.server.tcp_recv_buffer)] {
+        if size == Some(0) {
+            report.error(field, "must be greater than 0");
+        }
+    }
+    
+    // 验证流量镜像配置
+    if config.mirror.enable {
+        match &config.mirror.destination {
+            None => {
+                report.missing("mirror.destination");
+            }
+            Some(destination) if destination.parse::<SocketAddr>().is_err() => {
+                report.error("mirror.destination", format!("invalid socket address: {}", destination))
+                    .suggest("Use the form 192.168.1.200:4789");
+            }
+            Some(_) => {}
+        }
+        
+        if !(0.0..=1.0).contains(&config.mirror.sample_rate) {
+            report.error("mirror.sample_rate", "must be between 0 and 1");
+        }
+    }
+    
+    // 验证节点连接端口池配置
+    if config.connection_pool.enable {
+        if !config.server.interfaces.is_empty() {
+            report.error("connection_pool.enable", "cannot be combined with link aggregation (server.interfaces)");
+        }
+        if config.connection_pool.max_ports == 0 {
+            report.error("connection_pool.max_ports", "must be greater than 0");
+        } else if config.connection_pool.max_ports > 1024 {
+            report.warn("connection_pool.max_ports", "every pooled port runs its own receive task");
+        }
+    }
+    
+    // 验证节点注册表配置
+    if config.registry.backend == RegistryBackend::Redis {
+        match config.registry.redis_url.as_deref() {
+            None | Some("") => {
+                report.missing("registry.redis_url");
+            }
+            Some(url) if !url.starts_with("redis://") && !url.starts_with("rediss://") => {
+                report.error("registry.redis_url", format!("expected redis:// or rediss:// URL: {}", url))
+                    .suggest(format!("Did you mean redis://{}?", url.split("://").last().unwrap_or(url)));
+            }
+            Some(_) => {}
+        }
+        if config.registry.key_prefix.is_empty() {
+            report.missing("registry.key_prefix");
+        }
+    }
+    
+    // 验证套接字配置
+    config.network.validate(&mut report);
+    
+    // 验证认证配置
+    if config.auth.secret_key.is_empty() {
+        report.missing("auth.secret_key");
+    }
+    
+    if !config.auth.enable {
+        report.warn("auth.enable", "authentication is disabled, any node can join the network");
+    }
+    
+    check_duration("auth.token_expiry", config.auth.token_expiry, Duration::from_secs(60), Duration::from_secs(30 * 86400), &mut report);
+    
+    if config.auth.password_iterations == 0 {
+        report.error("auth.password_iterations", "must be greater than 0")
+            .suggest(format!("Did you mean {}?", vpnet::PBKDF2_ITERATIONS));
+    } else if config.auth.password_iterations < vpnet::PBKDF2_ITERATIONS {
+        report.warn(
+            "auth.password_iterations",
+            format!("fewer than {} iterations makes password hashes easier to brute-force", vpnet::PBKDF2_ITERATIONS)
+        );
+    }
+    
+    if config.auth.mode == AuthMode::Password && config.auth.users.is_empty() {
+        report.missing("auth.users").suggest("Generate a password hash with vpnet-server --hash-password");
+    }
+    
+    for (username, record) in &config.auth.users {
+        if PasswordHasher::record_iterations(record).is_none() {
+            report.error(format!("auth.users.{}", username), "invalid password hash")
+                .suggest("Generate a password hash with vpnet-server --hash-password");
+        }
+    }
+    
+    // 验证地理位置配置
+    if let Some(geo) = &config.geo {
+        if !(-90.0..=90.0).contains(&geo.latitude) {
+            report.error("geo.latitude", "must be between -90 and 90");
+        }
+        
+        if !(-180.0..=180.0).contains(&geo.longitude) {
+            report.error("geo.longitude", "must be between -180 and 180");
+        }
+    }
+    
+    report
+}
+
+/// 完整检查配置文件
+///
+/// 除`validate_config`外还会解析所有地址和网段，并检查引用的文件是否存在且可读。
+/// 收集所有错误而不是在第一个错误处停止。
+pub fn check(path: &str) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    
+    let mut content = String::new();
+    if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut content)) {
+        report.error("", format!("cannot read {}: {}", path, e));
+        return report;
+    }
+    
+    // 旧版本配置在内存中迁移后再检查，不写回文件
+    let migrated = ConfigMigrator::detect_version(&content)
+        .and_then(|version| config_migrator().migrate(&content, version));
+    let content = match migrated {
+        Ok(migrated) => migrated,
+        Err(e) => {
+            report.error("", format!("cannot migrate {}: {}", path, e));
+            return report;
+        }
+    };
+    
+    let config: ServerConfig = match toml::from_str(&content) {
+        Ok(config) => config,
+        Err(e) => {
+            report.error("", format!("cannot parse {}: {}", path, e));
+            return report;
+        }
+    };
+    
+    report = validate_config(&config);
+    
+    // 地址和网段
+    let addrs = [
+        ("server.bind", &config.server.bind),
+        ("api.bind", &config.api.bind),
+        ("web.bind", &config.web.bind),
+    ];
+    for (field, value) in addrs {
+        if value.parse::<std::net::IpAddr>().is_err() {
+            report.error(field, format!("invalid IP address: {}", value));
+        }
+    }
+    for (i, listen) in config.server.listen.iter().enumerate() {
+        if listen.bind.parse::<std::net::IpAddr>().is_err() {
+            report.error(format!("server.listen[{}].bind", i), format!("invalid IP address: {}", listen.bind));
+        }
+    }
+    check_ipv4("virtual_device.ip", &config.virtual_device.ip, &mut report);
+    check_ipv4("virtual_device.subnet", &config.virtual_device.subnet, &mut report);
+    check_ipv4("virtual_device.gateway", &config.virtual_device.gateway, &mut report);
+    if let Some(ipv6) = config.virtual_device.ipv6_address.as_ref().filter(|_| config.virtual_device.enable_ipv6) {
+        if ipv6.parse::<std::net::Ipv6Addr>().is_err() {
+            report.error("virtual_device.ipv6_address", format!("invalid IPv6 address: {}", ipv6));
+        }
+    }
+    if let Some(prefix) = &config.virtual_device.ipv6_prefix {
+        if !config.virtual_device.enable_ipv6 {
+            report.warn("virtual_device.ipv6_prefix", "ignored because enable_ipv6 is false");
+        } else if let Err(e) = vpnet::parse_ipv6_prefix(prefix) {
+            report.error("virtual_device.ipv6_prefix", e)
+                .suggest("Use the form fd00:1234::/64");
+        }
+    }
+    
+    // 引用的文件
+    check_file_or_parent("node.key_file", &config.node.key_file, &mut report);
+    check_file_or_parent("auth.key_directory", &config.auth.key_directory, &mut report);
+    check_file_or_parent("node.tag_store", &config.node.tag_store, &mut report);
+    if config.web.enable_tls {
+        match &config.web.tls_cert {
+            Some(cert) => check_readable_file("web.tls_cert", cert, &mut report),
+            None => {
+                report.error("web.tls_cert", "required when web.enable_tls is true");
+            }
+        }
+        match &config.web.tls_key {
+            Some(key) => check_readable_file("web.tls_key", key, &mut report),
+            None => {
+                report.error("web.tls_key", "required when web.enable_tls is true");
+            }
+        }
+    }
+    
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// 将默认配置改写为版本1的格式
+    fn v1_config() -> toml::Table {
+        let mut table: toml::Table = toml::from_str(&toml::to_string(&default_config()).unwrap()).unwrap();
+        table.remove("schema_version");
+        let auth = table["auth"].as_table_mut().unwrap();
+        auth.remove("whitelist");
+        auth.remove("blacklist");
+        table["virtual_device"].as_table_mut().unwrap()
+            .insert("device_mode".to_string(), toml::Value::String("Tun".to_string()));
+        table["node"].as_table_mut().unwrap()
+            .insert("virtual_ip_conflict".to_string(), toml::Value::String("AssignFromPool".to_string()));
+        table
+    }
+    
+    #[test]
+    fn migrates_v1_config_to_current_version() {
+        let raw = toml::to_string(&v1_config()).unwrap();
+        assert_eq!(ConfigMigrator::detect_version(&raw).unwrap(), 1);
+        
+        let migrated = config_migrator().migrate(&raw, 1).unwrap();
+        let config: ServerConfig = toml::from_str(&migrated).unwrap();
+        assert_eq!(config.schema_version, SCHEMA_VERSION);
+        assert!(config.auth.whitelist.is_empty() && config.auth.blacklist.is_empty());
+        assert_eq!(config.virtual_device.device_mode, DeviceMode::Tun);
+        assert_eq!(config.node.virtual_ip_conflict, ConflictStrategy::AssignFromPool);
+    }
+    
+    #[test]
+    fn migrates_v2_config_without_touching_auth() {
+        let mut table = v1_config();
+        table.insert("schema_version".to_string(), toml::Value::Integer(2));
+        table["auth"].as_table_mut().unwrap()
+            .insert("whitelist".to_string(), toml::Value::Array(vec![toml::Value::String("node-1".to_string())]));
+        table["auth"].as_table_mut().unwrap()
+            .insert("blacklist".to_string(), toml::Value::Array(Vec::new()));
+        
+        let migrated = config_migrator().migrate(&toml::to_string(&table).unwrap(), 2).unwrap();
+        let config: ServerConfig = toml::from_str(&migrated).unwrap();
+        assert_eq!(config.auth.whitelist, vec!["node-1".to_string()]);
+        assert_eq!(config.node.virtual_ip_conflict, ConflictStrategy::AssignFromPool);
+    }
+    
+    #[test]
+    fn v1_config_without_auth_section_fails_to_migrate() {
+        let mut table = v1_config();
+        table.remove("auth");
+        let result = config_migrator().migrate(&toml::to_string(&table).unwrap(), 1);
+        assert!(matches!(result, Err(MigrationError::Step { from: 1, .. })));
+    }
+    
+    #[test]
+    fn migrates_integer_seconds_to_durations() {
+        // 版本1的配置依次经过全部迁移，时长字段在版本4改为字符串
+        let mut table = v1_config();
+        let server = table["server"].as_table_mut().unwrap();
+        server.insert("timeout".to_string(), toml::Value::Integer(45));
+        server.insert("pause_timeout".to_string(), toml::Value::Integer(7200));
+        let mut hook = toml::Table::new();
+        hook.insert("event".to_string(), toml::Value::String("peer_connected".to_string()));
+        hook.insert("command".to_string(), toml::Value::String("/bin/true".to_string()));
+        hook.insert("timeout_secs".to_string(), toml::Value::Integer(5));
+        table.insert("hooks".to_string(), toml::Value::Array(vec![toml::Value::Table(hook)]));
+        
+        let migrated = config_migrator().migrate(&toml::to_string(&table).unwrap(), 1).unwrap();
+        let config: ServerConfig = toml::from_str(&migrated).unwrap();
+        assert_eq!(config.server.timeout, Duration::from_secs(45));
+        assert_eq!(config.server.pause_timeout, Some(Duration::from_secs(7200)));
+        assert_eq!(config.hooks[0].timeout, Duration::from_secs(5));
+    }
+    
+    #[test]
+    fn duration_fields_accept_fractional_units_and_are_range_checked() {
+        let mut table: toml::Table = toml::from_str(&toml::to_string(&default_config()).unwrap()).unwrap();
+        table["server"].as_table_mut().unwrap()
+            .insert("timeout".to_string(), toml::Value::String("0.5m".to_string()));
+        let config: ServerConfig = toml::from_str(&toml::to_string(&table).unwrap()).unwrap();
+        assert_eq!(config.server.timeout, Duration::from_secs(30));
+        assert_eq!(config.server.timeout, vpnet::config::parse_duration("30s").unwrap());
+        
+        let mut config = default_config();
+        config.server.timeout = Duration::from_secs(7200);
+        let report = validate_config(&config);
+        let error = report.errors.iter().find(|e| e.field == "server.timeout").unwrap();
+        assert_eq!(error.suggestion.as_deref(), Some("Did you mean 1h?"));
+    }
+    
+    #[test]
+    fn auto_promote_requires_witnesses() {
+        let mut config = default_config();
+        config.server.mode = ServerMode::Replica {
+            primary_api: "http://10.0.0.1:51821".to_string(),
+            poll_interval: Duration::from_secs(5),
+            auto_promote: true,
+            witnesses: Vec::new(),
+            priority: 0,
+        };
+        let report = validate_config(&config);
+        assert!(report.errors.iter().any(|e| e.field == "server.mode.witnesses"));
+        
+        if let ServerMode::Replica { witnesses, .. } = &mut config.server.mode {
+            witnesses.push("http://10.0.0.3:51821".to_string());
+        }
+        let report = validate_config(&config);
+        assert!(!report.errors.iter().any(|e| e.field.starts_with("server.mode")));
+    }
+    
+    #[test]
+    fn reservation_of_server_ip_is_rejected() {
+        let mut config = default_config();
+        config.node.ip_pool = Some("10.0.0.0/24".to_string());
+        config.virtual_device.ip = "10.0.0.1".to_string();
+        config.ip_pool.reservations = vec![IpReservation { node_id: "gateway-01".to_string(), ip: Ipv4Addr::new(10, 0, 0, 1) }];
+        let report = validate_config(&config);
+        assert!(report.errors.iter().any(|e| e.field == "ip_pool.reservations[0].ip" && e.message.contains("virtual_device.ip")));
+    }
+}
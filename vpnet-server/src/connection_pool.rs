@@ -0,0 +1,243 @@
+/*!
+VPNet Server 节点连接端口池
+
+服务端主动与节点重新握手时，每次使用新的本地端口可能在移动设备频繁重连时耗尽端口表。
+端口池在启动时预先绑定固定数量的UDP套接字，包括：
+- 套接字设置`SO_REUSEADDR`，全部加入网络管理器作为接收套接字
+- 重新握手前为节点分配一个空闲套接字，握手从该套接字发出，此后节点的流量经其收发
+- 节点断开或超时后归还套接字
+- 用信号量限制同时分配的数量，端口用尽时新连接排队等待
+*/
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex, MutexGuard};
+use serde::Serialize;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::sync::{broadcast, OwnedSemaphorePermit, Semaphore};
+use vpnet::PeerEvent;
+
+/// 分配给节点的套接字，许可随租约释放
+struct Lease {
+    local_addr: SocketAddr,
+    _permit: OwnedSemaphorePermit,
+}
+
+/// 端口池的状态
+#[derive(Default)]
+struct PoolState {
+    idle: Vec<SocketAddr>,
+    leases: HashMap<String, Lease>,
+    /// 端口用尽时排队等待的连接数
+    waiting: usize,
+}
+
+/// 端口池的使用情况
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStats {
+    pub max_ports: u32,
+    pub in_use: usize,
+    pub waiting: usize,
+}
+
+/// 预先绑定的节点连接套接字池
+pub struct PeerConnectionPool {
+    max_ports: u32,
+    sockets: Vec<Arc<UdpSocket>>,
+    semaphore: Arc<Semaphore>,
+    state: Mutex<PoolState>,
+}
+
+impl PeerConnectionPool {
+    /// 在`ip`的临时端口上绑定`max_ports`个非阻塞UDP套接字
+    pub fn bind(ip: IpAddr, max_ports: u32) -> io::Result<Self> {
+        let addr = SocketAddr::new(ip, 0);
+        let mut sockets = Vec::with_capacity(max_ports as usize);
+        for _ in 0..max_ports {
+            let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+            socket.set_reuse_address(true)?;
+            socket.set_nonblocking(true)?;
+            socket.bind(&addr.into())?;
+            sockets.push(Arc::new(UdpSocket::from(socket)));
+        }
+        let idle = sockets.iter()
+            .map(|socket| socket.local_addr())
+            .collect::<io::Result<_>>()?;
+        
+        Ok(Self {
+            max_ports,
+            sockets,
+            semaphore: Arc::new(Semaphore::new(max_ports as usize)),
+            state: Mutex::new(PoolState { idle, ..PoolState::default() }),
+        })
+    }
+    
+    /// 池中的全部套接字，启动前通过`NetworkManager::add_socket`加入网络管理器
+    pub fn sockets(&self) -> &[Arc<UdpSocket>] {
+        &self.sockets
+    }
+    
+    fn state(&self) -> MutexGuard<'_, PoolState> {
+        // 持有锁时不会panic，锁不会中毒
+        self.state.lock().unwrap()
+    }
+    
+    /// 为节点分配套接字，返回其本地地址，已分配时返回原地址
+    ///
+    /// 端口用尽时记录警告并排队，有节点归还后按请求顺序分配。
+    pub async fn acquire(&self, node_id: &str) -> SocketAddr {
+        if let Some(lease) = self.state().leases.get(node_id) {
+            return lease.local_addr;
+        }
+        
+        let permit = match self.semaphore.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                self.state().waiting += 1;
+                log::warn!(
+                    "Peer connection pool exhausted ({} ports), queuing peer {}",
+                    self.max_ports, node_id
+                );
+                // 信号量不会被关闭
+                let permit = self.semaphore.clone().acquire_owned().await.unwrap();
+                self.state().waiting -= 1;
+                log::info!("Assigned pooled port to queued peer {}", node_id);
+                permit
+            }
+        };
+        
+        let mut state = self.state();
+        // 排队期间同一节点可能已经分配到套接字，多余的许可随即释放
+        if let Some(lease) = state.leases.get(node_id) {
+            return lease.local_addr;
+        }
+        // 许可数与空闲套接字数一致
+        let local_addr = state.idle.pop().expect("idle socket for every permit");
+        state.leases.insert(node_id.to_string(), Lease { local_addr, _permit: permit });
+        local_addr
+    }
+    
+    /// 归还节点的套接字，节点没有分配到套接字时返回`false`
+    pub fn release(&self, node_id: &str) -> bool {
+        let mut state = self.state();
+        match state.leases.remove(node_id) {
+            Some(lease) => {
+                state.idle.push(lease.local_addr);
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// 当前的使用情况
+    pub fn stats(&self) -> PoolStats {
+        let state = self.state();
+        PoolStats {
+            max_ports: self.max_ports,
+            in_use: state.leases.len(),
+            waiting: state.waiting,
+        }
+    }
+}
+
+/// 节点断开或超时后归还其套接字，直到事件源关闭
+pub async fn run_connection_pool(pool: Arc<PeerConnectionPool>, mut events: broadcast::Receiver<PeerEvent>) {
+    loop {
+        match events.recv().await {
+            Ok(PeerEvent::PeerDisconnected { node_id, .. }) => {
+                if pool.release(&node_id) {
+                    log::debug!("Returned pooled port of peer {}", node_id);
+                }
+            }
+            Ok(_) => {}
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                log::warn!("Peer connection pool lagged behind, {} events skipped", skipped);
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+    
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    
+    fn disconnected(node_id: &str) -> PeerEvent {
+        PeerEvent::PeerDisconnected {
+            node_id: node_id.to_string(),
+            reason: "closed".to_string(),
+        }
+    }
+    
+    #[test]
+    fn binds_every_port_with_reuse_address() {
+        let pool = PeerConnectionPool::bind(LOCALHOST, 4).unwrap();
+        let addrs: HashSet<_> = pool.sockets().iter().map(|socket| socket.local_addr().unwrap()).collect();
+        assert_eq!(addrs.len(), 4);
+        for socket in pool.sockets() {
+            assert!(socket2::SockRef::from(socket.as_ref()).reuse_address().unwrap());
+        }
+    }
+    
+    #[tokio::test]
+    async fn exhausted_pool_queues_until_a_port_is_returned() {
+        let pool = Arc::new(PeerConnectionPool::bind(LOCALHOST, 1).unwrap());
+        let first = pool.acquire("a").await;
+        assert_eq!(pool.acquire("a").await, first);
+        
+        let queued = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.acquire("b").await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!queued.is_finished());
+        assert_eq!(pool.stats().waiting, 1);
+        
+        assert!(pool.release("a"));
+        assert!(!pool.release("a"));
+        let second = tokio::time::timeout(Duration::from_secs(1), queued).await.unwrap().unwrap();
+        assert_eq!(second, first);
+        let stats = pool.stats();
+        assert_eq!((stats.in_use, stats.waiting), (1, 0));
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn thousand_rapid_connections_do_not_exhaust_ports() {
+        const PORTS: u32 = 16;
+        const CONNECTIONS: usize = 1000;
+        let pool = Arc::new(PeerConnectionPool::bind(LOCALHOST, PORTS).unwrap());
+        let bound: HashSet<_> = pool.sockets().iter().map(|socket| socket.local_addr().unwrap()).collect();
+        let (events, _) = broadcast::channel(CONNECTIONS);
+        let releaser = tokio::spawn(run_connection_pool(pool.clone(), events.subscribe()));
+        
+        // 每个连接取得端口后立即断开，断开事件经事件流归还端口
+        let connections: Vec<_> = (0..CONNECTIONS).map(|i| {
+            let (pool, events) = (pool.clone(), events.clone());
+            tokio::spawn(async move {
+                let node_id = format!("peer-{}", i);
+                let local_addr = pool.acquire(&node_id).await;
+                events.send(disconnected(&node_id)).unwrap();
+                local_addr
+            })
+        }).collect();
+        
+        let mut used = HashSet::new();
+        for connection in connections {
+            let local_addr = tokio::time::timeout(Duration::from_secs(10), connection).await.unwrap().unwrap();
+            used.insert(local_addr);
+        }
+        assert!(used.is_subset(&bound));
+        
+        drop(events);
+        releaser.await.unwrap();
+        let stats = pool.stats();
+        assert_eq!((stats.in_use, stats.waiting), (0, 0));
+        assert_eq!(pool.state().idle.len(), PORTS as usize);
+    }
+}
@@ -0,0 +1,155 @@
+/*!
+VPNet Server 调试接口模块
+
+独立端口上的gRPC服务（`proto/debug.proto`），在HTTP管理API本身出现问题时
+仍可查询服务端内部状态，包括：
+- 对等节点表
+- 路由表和策略路由规则
+- 加解密统计
+- 手动触发垃圾回收
+
+需要启用`debug-grpc`特性编译。
+*/
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tonic::{Request, Response, Status};
+use vpnet::NetworkManager;
+
+/// 由`proto/debug.proto`生成的类型
+pub mod proto {
+    tonic::include_proto!("vpnet.debug");
+}
+
+use proto::vpnet_debug_server::{VpnetDebug, VpnetDebugServer};
+
+/// gRPC调试接口
+pub struct DebugOverlay {
+    network_manager: Arc<Mutex<NetworkManager>>,
+}
+
+impl DebugOverlay {
+    /// 创建调试接口
+    pub fn new(network_manager: Arc<Mutex<NetworkManager>>) -> Self {
+        Self { network_manager }
+    }
+    
+    /// 在指定地址上提供调试服务
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), tonic::transport::Error> {
+        log::info!("Debug gRPC interface available at {}", addr);
+        tonic::transport::Server::builder()
+            .add_service(VpnetDebugServer::new(self))
+            .serve(addr)
+            .await
+    }
+}
+
+#[tonic::async_trait]
+impl VpnetDebug for DebugOverlay {
+    async fn list_peers(
+        &self,
+        _request: Request<proto::ListPeersRequest>
+    ) -> Result<Response<proto::ListPeersResponse>, Status> {
+        let mut peers = self.network_manager.lock().await.get_peers().await;
+        peers.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        
+        Ok(Response::new(proto::ListPeersResponse {
+            peers: peers.iter().map(peer_to_proto).collect(),
+        }))
+    }
+    
+    async fn get_peer(
+        &self,
+        request: Request<proto::GetPeerRequest>
+    ) -> Result<Response<proto::Peer>, Status> {
+        let node_id = request.into_inner().node_id;
+        match self.network_manager.lock().await.get_peer(&node_id).await {
+            Some(peer) => Ok(Response::new(peer_to_proto(&peer))),
+            None => Err(Status::not_found(format!("Peer {} not found", node_id))),
+        }
+    }
+    
+    async fn get_routing_table(
+        &self,
+        request: Request<proto::GetRoutingTableRequest>
+    ) -> Result<Response<proto::GetRoutingTableResponse>, Status> {
+        let filter = request.into_inner().table;
+        let network_manager = self.network_manager.lock().await;
+        
+        let tables = network_manager.get_routing_tables().await
+            .into_iter()
+            .filter(|(table, _)| filter.is_none() || filter == Some(u32::from(*table)))
+            .map(|(table, routes)| proto::RoutingTable {
+                table: table.into(),
+                routes: routes.into_iter()
+                    .map(|route| proto::Route {
+                        destination: route.destination.to_string(),
+                        gateway: route.gateway.to_string(),
+                        next_hop: route.next_hop,
+                        metric: route.metric,
+                    })
+                    .collect(),
+            })
+            .collect();
+        
+        let rules = network_manager.get_policy_routes().await
+            .into_iter()
+            .map(|rule| proto::PolicyRule {
+                priority: rule.priority,
+                src_ip: rule.src_ip.map(|cidr| cidr.to_string()),
+                dst_ip: rule.dst_ip.map(|cidr| cidr.to_string()),
+                proto: rule.proto.map(u32::from),
+                table: rule.table.into(),
+            })
+            .collect();
+        
+        Ok(Response::new(proto::GetRoutingTableResponse { tables, rules }))
+    }
+    
+    async fn get_crypto_stats(
+        &self,
+        _request: Request<proto::GetCryptoStatsRequest>
+    ) -> Result<Response<proto::CryptoStats>, Status> {
+        let stats = self.network_manager.lock().await.crypto_stats().await;
+        
+        Ok(Response::new(proto::CryptoStats {
+            algorithm: stats.algorithm.to_string(),
+            packets_encrypted: stats.packets_encrypted,
+            packets_decrypted: stats.packets_decrypted,
+            decrypt_failures: stats.decrypt_failures,
+        }))
+    }
+    
+    async fn trigger_gc(
+        &self,
+        _request: Request<proto::TriggerGcRequest>
+    ) -> Result<Response<proto::TriggerGcResponse>, Status> {
+        let stats = self.network_manager.lock().await.collect_garbage().await;
+        log::info!("Garbage collection triggered via debug interface: {:?}", stats);
+        
+        Ok(Response::new(proto::TriggerGcResponse {
+            peers_removed: stats.peers_removed as u64,
+            probes_removed: stats.probes_removed as u64,
+            nonces_removed: stats.nonces_removed as u64,
+        }))
+    }
+}
+
+/// 转换对等节点
+fn peer_to_proto(peer: &vpnet::Peer) -> proto::Peer {
+    proto::Peer {
+        node_id: peer.node_id.clone(),
+        node_name: peer.node_name.clone(),
+        address: peer.address.to_string(),
+        virtual_ip: peer.virtual_ip.clone(),
+        status: format!("{:?}", peer.status),
+        last_seen: peer.last_seen,
+        packets_sent: peer.stats.packets_sent,
+        packets_received: peer.stats.packets_received,
+        bytes_sent: peer.stats.bytes_sent,
+        bytes_received: peer.stats.bytes_received,
+        packet_loss_pct: peer.stats.packet_loss_pct,
+        direct_path: peer.direct_path.map(|addr| addr.to_string()),
+    }
+}
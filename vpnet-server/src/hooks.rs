@@ -0,0 +1,129 @@
+/*!
+VPNet Server 事件钩子模块
+
+在节点事件发生时执行外部命令，例如更新DNS、添加防火墙规则或发送通知，包括：
+- 按事件名称匹配钩子
+- 通过标准输入传递JSON格式的事件数据
+- 超时终止和并发数限制
+*/
+
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::{broadcast, Semaphore};
+use tokio::task::JoinHandle;
+use vpnet::PeerEvent;
+use crate::config::Hook;
+
+/// 支持的钩子事件
+pub const HOOK_EVENTS: &[&str] = &["peer_connected", "peer_disconnected", "peer_migrated"];
+
+/// 钩子执行器
+pub struct HookRunner {
+    hooks: Vec<Hook>,
+    semaphore: Arc<Semaphore>,
+}
+
+impl HookRunner {
+    /// 创建钩子执行器，最多同时运行`max_concurrent`个钩子
+    pub fn new(hooks: Vec<Hook>, max_concurrent: u32) -> Self {
+        Self {
+            hooks,
+            semaphore: Arc::new(Semaphore::new(max_concurrent as usize)),
+        }
+    }
+    
+    /// 在后台任务中运行`run`
+    pub fn spawn(self, events: broadcast::Receiver<PeerEvent>) -> JoinHandle<()> {
+        tokio::spawn(self.run(events))
+    }
+    
+    /// 订阅节点事件并执行匹配的钩子，直到事件源关闭
+    pub async fn run(self, mut events: broadcast::Receiver<PeerEvent>) {
+        loop {
+            match events.recv().await {
+                Ok(event) => self.dispatch(&event),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Hook runner lagged behind, {} events skipped", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+    
+    /// 为事件启动所有匹配的钩子，钩子在后台运行，超过并发上限时排队等待
+    pub fn dispatch(&self, event: &PeerEvent) {
+        let payload = match serde_json::to_vec(event) {
+            Ok(payload) => Arc::new(payload),
+            Err(e) => {
+                log::error!("Failed to serialize {} event: {}", event.name(), e);
+                return;
+            }
+        };
+        
+        for hook in self.hooks.iter().filter(|hook| hook.event == event.name()) {
+            let hook = hook.clone();
+            let payload = payload.clone();
+            let semaphore = self.semaphore.clone();
+            tokio::spawn(async move {
+                // 信号量不会被关闭
+                let _permit = semaphore.acquire_owned().await;
+                run_hook(&hook, &payload).await;
+            });
+        }
+    }
+}
+
+/// 执行钩子命令，事件数据写入标准输入，输出记录到debug日志
+async fn run_hook(hook: &Hook, payload: &[u8]) {
+    let mut child = match Command::new(&hook.command)
+        .args(&hook.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            log::error!("Failed to start hook {} for {}: {}", hook.command, hook.event, e);
+            return;
+        }
+    };
+    
+    let stdin = child.stdin.take();
+    let run = async move {
+        // 写完后关闭标准输入，脚本才能读到EOF；不读取标准输入的脚本也不会让写入一直阻塞
+        if let Some(mut stdin) = stdin {
+            if let Err(e) = stdin.write_all(payload).await {
+                log::warn!("Failed to write event to hook {}: {}", hook.command, e);
+            }
+        }
+        child.wait_with_output().await
+    };
+    
+    // 写入和等待都计入超时，超时后丢弃子进程句柄，kill_on_drop会终止进程
+    let output = match tokio::time::timeout(hook.timeout, run).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            log::error!("Hook {} for {} failed: {}", hook.command, hook.event, e);
+            return;
+        }
+        Err(_) => {
+            log::warn!("Hook {} for {} timed out after {:?}", hook.command, hook.event, hook.timeout);
+            return;
+        }
+    };
+    
+    if !output.stdout.is_empty() {
+        log::debug!("Hook {} stdout: {}", hook.command, String::from_utf8_lossy(&output.stdout).trim_end());
+    }
+    if !output.stderr.is_empty() {
+        log::debug!("Hook {} stderr: {}", hook.command, String::from_utf8_lossy(&output.stderr).trim_end());
+    }
+    if !output.status.success() {
+        log::warn!("Hook {} for {} exited with {}", hook.command, hook.event, output.status);
+    }
+}
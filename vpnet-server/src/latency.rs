@@ -0,0 +1,102 @@
+/*!
+VPNet Server 节点间时延模块
+
+记录所有节点两两之间的往返时延，用于排查网状网络的性能问题，包括：
+- 节点经服务端互相发送时延探测，服务端测得的结果记入时延矩阵
+- 以JSON和CSV格式导出时延矩阵
+- 节点断开后清除其时延记录
+*/
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+use serde::Serialize;
+use tokio::sync::{broadcast, RwLock};
+use vpnet::{LatencySample, PeerEvent};
+
+/// 时延矩阵中的一项
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyEntry {
+    pub source_peer_id: String,
+    pub target_peer_id: String,
+    pub rtt_ms: f64,
+}
+
+/// 节点两两之间的往返时延
+#[derive(Debug, Default)]
+pub struct PeerLatencyMatrix {
+    /// 按(源节点ID, 目标节点ID)索引的最近一次往返时延（毫秒）
+    matrix: HashMap<(String, String), f64>,
+}
+
+impl PeerLatencyMatrix {
+    /// 创建空的时延矩阵
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// 记录一次测量结果，覆盖同一对节点的原有结果
+    pub fn record(&mut self, sample: &LatencySample) {
+        self.matrix.insert(
+            (sample.source_peer_id.clone(), sample.target_peer_id.clone()),
+            sample.rtt_ms
+        );
+    }
+    
+    /// 查询两个节点之间的往返时延
+    pub fn get(&self, source: &str, target: &str) -> Option<f64> {
+        self.matrix.get(&(source.to_string(), target.to_string())).copied()
+    }
+    
+    /// 清除与节点有关的所有记录
+    pub fn remove_peer(&mut self, node_id: &str) {
+        self.matrix.retain(|(source, target), _| source != node_id && target != node_id);
+    }
+    
+    /// 所有记录，按源节点ID、目标节点ID排序
+    pub fn entries(&self) -> Vec<LatencyEntry> {
+        let mut entries: Vec<LatencyEntry> = self.matrix.iter()
+            .map(|((source, target), rtt_ms)| LatencyEntry {
+                source_peer_id: source.clone(),
+                target_peer_id: target.clone(),
+                rtt_ms: *rtt_ms,
+            })
+            .collect();
+        entries.sort_by(|a, b| (&a.source_peer_id, &a.target_peer_id).cmp(&(&b.source_peer_id, &b.target_peer_id)));
+        entries
+    }
+    
+    /// 导出为CSV，每行一对节点
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("source_peer_id,target_peer_id,rtt_ms\n");
+        for entry in self.entries() {
+            // 写入String不会失败
+            let _ = writeln!(csv, "{},{},{:.3}", entry.source_peer_id, entry.target_peer_id, entry.rtt_ms);
+        }
+        csv
+    }
+}
+
+/// 将网络层测得的时延记入矩阵，节点断开时清除其记录，直到事件源关闭
+pub async fn run_latency_matrix(
+    matrix: Arc<RwLock<PeerLatencyMatrix>>,
+    mut samples: broadcast::Receiver<LatencySample>,
+    mut events: broadcast::Receiver<PeerEvent>
+) {
+    loop {
+        tokio::select! {
+            sample = samples.recv() => match sample {
+                Ok(sample) => matrix.write().await.record(&sample),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Latency matrix lagged behind, {} samples skipped", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            event = events.recv() => match event {
+                Ok(PeerEvent::PeerDisconnected { node_id, .. }) => matrix.write().await.remove_peer(&node_id),
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        }
+    }
+}
@@ -1,185 +1,608 @@
-/*!
-VPNet Server - 高性能去中心化虚拟局域网服务端
-
-服务端主要功能：
-- 管理客户端连接
-- 节点授权和认证
-- 虚拟网络管理
-- Web管理界面
-- 跨平台支持
-*/
-
-use clap::Parser;
-use env_logger::Builder;
-use log::LevelFilter;
-use std::fs::File;
-use std::io::Read;
-use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio::sync::{Mutex, RwLock};
-use tokio::time::Duration;
-use vpnet::{NetworkManager, DeviceManager, VirtualDeviceConfig, default_config};
-use vpnet_server::config::ServerConfig;
-use vpnet_server::auth::AuthManager;
-use vpnet_server::api::start_api_server;
-use vpnet_server::node::{NodeManager, Node};
-use vpnet_server::web::start_web_server;
-
-mod config;
-mod auth;
-mod api;
-mod node;
-mod web;
-mod utils;
-
-/// 命令行参数
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// 配置文件路径
-    #[arg(short, long, default_value = "vpnet-server.toml")]
-    config: String,
-    
-    /// 启用调试日志
-    #[arg(short, long, action = clap::ArgAction::SetTrue)]
-    debug: bool,
-    
-    /// 绑定地址
-    #[arg(short, long)]
-    bind: Option<String>,
-    
-    /// 端口
-    #[arg(short, long)]
-    port: Option<u16>,
-    
-    /// 虚拟IP地址
-    #[arg(long)]
-    virtual_ip: Option<String>,
-}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // 解析命令行参数
-    let args = Args::parse();
-    
-    // 初始化日志
-    let mut logger = Builder::new();
-    logger.filter(None, if args.debug {
-        LevelFilter::Debug
-    } else {
-        LevelFilter::Info
-    });
-    logger.init();
-    
-    log::info!("VPNet Server starting...");
-    
-    // 加载配置
-    let mut config_file = File::open(&args.config)?;
-    let mut config_content = String::new();
-    config_file.read_to_string(&mut config_content)?;
-    let mut config: ServerConfig = toml::from_str(&config_content)?;
-    
-    // 从命令行参数覆盖配置
-    if let Some(bind) = args.bind {
-        config.server.bind = bind;
-    }
-    if let Some(port) = args.port {
-        config.server.port = port;
-    }
-    if let Some(virtual_ip) = args.virtual_ip {
-        config.virtual_device.ip = virtual_ip;
-    }
-    
-    log::debug!("Config loaded: {:?}", config);
-    
-    // 生成或加载密钥对
-    let (public_key, private_key) = config::load_or_generate_keys(&config)?;
-    
-    // 初始化认证管理器
-    let auth_manager = Arc::new(Mutex::new(AuthManager::new(config.auth.clone())?));
-    
-    // 初始化节点管理器
-    let node_manager = Arc::new(Mutex::new(NodeManager::new(config.node.clone())?));
-    
-    // 初始化网络管理器
-    let local_addr: SocketAddr = format!("{}:{}", config.server.bind, config.server.port)
-        .parse()?;
-    
-    let network_manager = Arc::new(Mutex::new(NetworkManager::new(
-        local_addr,
-        config.node.id.clone(),
-        config.node.name.clone(),
-        public_key,
-        &private_key
-    )?));
-    
-    // 初始化设备管理器
-    let mut device_manager = DeviceManager::new();
-    
-    // 创建虚拟设备
-    let virtual_ip = config.virtual_device.ip.parse()?;
-    let device_config = VirtualDeviceConfig {
-        name: config.virtual_device.name.clone(),
-        ip: virtual_ip,
-        subnet: config.virtual_device.subnet.parse()?,
-        gateway: config.virtual_device.gateway.parse()?,
-        mtu: config.virtual_device.mtu,
-        mac: None,
-    };
-    
-    let device_id = device_manager.create_device(device_config).await?;
-    let device = device_manager.get_device(&device_id).await?;
-    
-    // 启动虚拟设备
-    device.lock().await.start().await?;
-    log::info!("Virtual device {} started successfully", config.virtual_device.name);
-    
-    // 启动网络服务
-    network_manager.lock().await.start().await;
-    log::info!("Network service started on {}", local_addr);
-    
-    // 启动API服务器
-    let api_addr: SocketAddr = format!("{}:{}", config.api.bind, config.api.port)
-        .parse()?;
-    let api_handle = tokio::spawn(start_api_server(
-        api_addr,
-        auth_manager.clone(),
-        node_manager.clone(),
-        network_manager.clone(),
-        Arc::new(Mutex::new(device_manager.clone())),
-        config.api.clone()
-    ));
-    
-    // 启动Web管理界面
-    let web_addr: SocketAddr = format!("{}:{}", config.web.bind, config.web.port)
-        .parse()?;
-    let web_handle = tokio::spawn(start_web_server(
-        web_addr,
-        auth_manager.clone(),
-        node_manager.clone(),
-        network_manager.clone(),
-        config.web.clone()
-    ));
-    
-    log::info!("VPNet Server started successfully");
-    log::info!("Web management interface available at http://{}", web_addr);
-    log::info!("API server available at http://{}", api_addr);
-    
-    // 主循环 - 处理信号和优雅关闭
-    let signal = tokio::signal::ctrl_c()
-        .await
-        .expect("Failed to listen for Ctrl+C");
-    
-    log::info!("Received shutdown signal, stopping services...");
-    
-    // 关闭虚拟设备
-    device.lock().await.stop().await?;
-    
-    // 等待API和Web服务器关闭
-    api_handle.await??;
-    web_handle.await??;
-    
-    log::info!("VPNet Server stopped successfully");
-    
-    Ok(())
-}
+/*!
+VPNet Server - 高性能去中心化虚拟局域网服务端
+
+服务端主要功能：
+- 管理客户端连接
+- 节点授权和认证
+- 虚拟网络管理
+- Web管理界面
+- 跨平台支持
+*/
+
+use clap::Parser;
+use env_logger::Builder;
+use log::LevelFilter;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::AsyncReadExt;
+use tokio::sync::{Mutex, RwLock};
+use tokio::time::Duration;
+use vpnet::{NetworkManager, DeviceManager, DeviceWatcher, VirtualDeviceConfig, GeoLocation, default_config};
+use vpnet::{Capabilities, CompatMatrix, GroupBandwidthLimiter, HtbScheduler, PacketCapture, PacketInspector, PasswordHasher, PriorityScheduler, SchedulerMode, SessionTicketKeys, TrafficMirror, WfqScheduler};
+use vpnet_server::config::{RegistryBackend, ServerConfig, ServerMode};
+use vpnet_server::auth::AuthManager;
+use vpnet_server::connection_pool::{run_connection_pool, PeerConnectionPool};
+use vpnet_server::hooks::HookRunner;
+use vpnet_server::latency::{run_latency_matrix, PeerLatencyMatrix};
+use vpnet_server::api::start_api_server;
+use vpnet_server::node::{NodeManager, NodeAdmission, GroupPolicy, Node, idle_threshold_from_days, run_capacity_tracker, run_idle_eviction, TagStore};
+use vpnet_server::registry::LocalPeerRegistry;
+use vpnet_server::replica::{start_replica_api_server, start_replica_web_server, watch_replicas, ReplicaPoller, ReplicaView, FENCING_INTERVAL};
+#[cfg(feature = "redis-registry")]
+use vpnet_server::registry::RedisPeerRegistry;
+use vpnet_server::tasks::BackgroundTasks;
+use vpnet_server::web::start_web_server;
+#[cfg(feature = "debug-grpc")]
+use vpnet_server::debug::DebugOverlay;
+
+mod config;
+mod auth;
+mod api;
+mod connection_pool;
+mod node;
+mod hooks;
+mod latency;
+mod network_map;
+mod registry;
+mod replica;
+mod tasks;
+#[cfg(feature = "debug-grpc")]
+mod debug;
+mod web;
+mod utils;
+
+/// 命令行参数
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// 配置文件路径
+    #[arg(short, long, default_value = "vpnet-server.toml")]
+    config: String,
+    
+    /// 检查配置文件后退出
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    check: bool,
+    
+    /// 从标准输入读取口令，输出用于`auth.users`的哈希记录后退出
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    hash_password: bool,
+    
+    /// 启用调试日志
+    #[arg(short, long, action = clap::ArgAction::SetTrue)]
+    debug: bool,
+    
+    /// 绑定地址
+    #[arg(short, long)]
+    bind: Option<String>,
+    
+    /// 端口
+    #[arg(short, long)]
+    port: Option<u16>,
+    
+    /// 虚拟IP地址
+    #[arg(long)]
+    virtual_ip: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // 解析命令行参数
+    let args = Args::parse();
+    
+    // 只检查配置
+    if args.check {
+        let report = config::check(&args.config);
+        for warning in &report.warnings {
+            eprintln!("warning: {}", warning);
+        }
+        if report.is_valid() {
+            println!("Configuration {} is valid", args.config);
+            std::process::exit(0);
+        }
+        
+        eprintln!("Configuration {} is invalid:", args.config);
+        for error in &report.errors {
+            eprintln!("  - {}", error);
+            if let Some(suggestion) = &error.suggestion {
+                eprintln!("    {}", suggestion);
+            }
+        }
+        std::process::exit(1);
+    }
+    
+    if args.hash_password {
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password)?;
+        let password = password.trim_end_matches(['\r', '\n']);
+        println!("{}", PasswordHasher::default().hash(password)?);
+        std::process::exit(0);
+    }
+    
+    // 初始化日志
+    let mut logger = Builder::new();
+    logger.filter(None, if args.debug {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
+    });
+    logger.init();
+    
+    log::info!("VPNet Server starting...");
+    
+    // 加载配置
+    let mut config: ServerConfig = config::load_config(&args.config)?;
+    
+    // 从命令行参数覆盖配置，指定地址或端口时只监听该地址
+    if args.bind.is_some() || args.port.is_some() {
+        config.server.listen.clear();
+    }
+    if let Some(bind) = args.bind {
+        config.server.bind = bind;
+    }
+    if let Some(port) = args.port {
+        config.server.port = port;
+    }
+    if let Some(virtual_ip) = args.virtual_ip {
+        config.virtual_device.ip = virtual_ip;
+    }
+    
+    log::debug!("Config loaded: {:?}", config);
+    
+    // 副本模式只拉取主服务端的状态，主服务端不可用且允许自动提升时继续以主服务端启动
+    if let ServerMode::Replica { primary_api, poll_interval, auto_promote, witnesses, priority } = &config.server.mode {
+        let view = ReplicaView::new(primary_api, *priority);
+        let mut poller = ReplicaPoller::new(view.clone(), *poll_interval, *auto_promote)?;
+        poller.set_witnesses(witnesses.clone());
+        if !run_replica(&config, view, poller).await? {
+            log::info!("VPNet Server replica stopped successfully");
+            return Ok(());
+        }
+        log::warn!("Replica promoted, starting as primary");
+    }
+    
+    // 生成或加载密钥对
+    let (public_key, _) = config::load_or_generate_keys(&config)?;
+    
+    // 初始化认证管理器
+    let auth_manager = Arc::new(Mutex::new(AuthManager::new(config.auth.clone())?));
+    
+    // 初始化节点管理器
+    let node_manager = Arc::new(Mutex::new(NodeManager::new(config.node.clone())?));
+    node_manager.lock().await.set_server_ip(config.virtual_device.ip.parse()?);
+    for reservation in &config.ip_pool.reservations {
+        node_manager.lock().await.reserve_pool_ip(&reservation.node_id, reservation.ip)?;
+    }
+    if !config.ip_pool.reservations.is_empty() {
+        log::info!("Reserved {} virtual IPs in the pool", config.ip_pool.reservations.len());
+    }
+    node_manager.lock().await.set_tag_store(TagStore::open(&config.node.tag_store)?)?;
+    
+    // 初始化节点注册表
+    match config.registry.backend {
+        RegistryBackend::Local => {
+            node_manager.lock().await.set_registry(Box::new(LocalPeerRegistry::new()));
+        }
+        #[cfg(feature = "redis-registry")]
+        RegistryBackend::Redis => {
+            let url = config.registry.redis_url.as_deref().unwrap_or_default();
+            let registry = RedisPeerRegistry::connect(url, &config.registry.key_prefix).await?;
+            node_manager.lock().await.set_registry(Box::new(registry));
+            log::info!("Using Redis node registry at {}", url);
+        }
+        #[cfg(not(feature = "redis-registry"))]
+        RegistryBackend::Redis => {
+            return Err("registry.backend is redis but the server was built without the redis-registry feature".into());
+        }
+    }
+    
+    // 初始化网络管理器
+    let listen_addrs = config.listen_addresses()?;
+    
+    let network_manager = Arc::new(Mutex::new(NetworkManager::new(
+        listen_addrs.clone(),
+        config.node.id.clone(),
+        config.node.name.clone(),
+        public_key
+    )?));
+    network_manager.lock().await.configure_sockets(&config.network.socket_config())?;
+    
+    // 初始化设备管理器
+    let mut device_manager = DeviceManager::new();
+    
+    // 创建虚拟设备
+    let virtual_ip = config.virtual_device.ip.parse()?;
+    let device_config = VirtualDeviceConfig {
+        name: config.virtual_device.name.clone(),
+        ip: virtual_ip,
+        subnet: config.virtual_device.subnet.parse()?,
+        gateway: config.virtual_device.gateway.parse()?,
+        mtu: config.virtual_device.mtu,
+        mac: None,
+        enable_ipv6: config.virtual_device.enable_ipv6,
+        ipv6_prefix: config.virtual_device.ipv6_prefix.as_deref().map(vpnet::parse_ipv6_prefix).transpose()?,
+        device_mode: config.virtual_device.device_mode,
+        max_restart_attempts: config.virtual_device.max_restart_attempts,
+        restart_cooldown: config.virtual_device.restart_cooldown,
+        transforms: config.virtual_device.transforms.clone(),
+        flush_timeout: config.virtual_device.flush_timeout,
+        routes: config.virtual_device.routes.clone(),
+    };
+    
+    let device_id = device_manager.create_device(device_config).await?;
+    let device = device_manager.get_device(&device_id).await?;
+    
+    // 抓取虚拟网卡收到的数据包，过滤表达式可通过API替换
+    let capture = match &config.debug.capture_file {
+        Some(path) => {
+            let capture = Arc::new(PacketCapture::with_rotation(
+                path,
+                config.virtual_device.device_mode,
+                config.debug.capture_rotation()
+            )?);
+            capture.set_filter(config.debug.capture_filter.as_deref())?;
+            log::info!("Capturing packets on {} to {}", config.virtual_device.name, path);
+            Some(capture)
+        }
+        None => None,
+    };
+    device.lock().await.set_capture(capture.clone());
+    
+    // 启动虚拟设备
+    device.lock().await.start().await?;
+    log::info!("Virtual device {} started successfully", config.virtual_device.name);
+    
+    // 所有后台任务，关闭时统一中止
+    let mut tasks = BackgroundTasks::new();
+    
+    // 监视虚拟网卡，被外部删除时自动重建
+    tasks.spawn("device-watcher", DeviceWatcher::new(device.clone()).run());
+    
+    // 保留本节点的虚拟IP，握手时由节点管理器分配虚拟IP并检测冲突
+    node_manager.lock().await.reserve_virtual_ip(&config.node.id, virtual_ip)?;
+    let key_directory = auth_manager.lock().await.key_directory();
+    network_manager.lock().await.set_handshake_validator(Arc::new(
+        NodeAdmission::new(node_manager.clone())
+            .with_key_directory(key_directory, config.auth.strict_key_pinning)
+    ));
+    network_manager.lock().await.set_forward_filter(Arc::new(
+        GroupPolicy::new(node_manager.clone())
+    ));
+    for (node_id, prefixes) in &config.node.allowed_prefixes {
+        network_manager.lock().await.set_allowed_prefixes(node_id, prefixes.clone()).await;
+    }
+    
+    // 启用认证时，握手请求需携带API签发的随机数
+    if config.auth.enable {
+        let nonce_store = auth_manager.lock().await.nonce_store();
+        network_manager.lock().await.set_nonce_store(nonce_store);
+    }
+    
+    // 在节点信息中公布配置的地理位置
+    if let Some(geo) = &config.geo {
+        let mut local_info = network_manager.lock().await.get_local_info().await;
+        local_info.geographic_location = Some(GeoLocation {
+            latitude: geo.latitude,
+            longitude: geo.longitude,
+            country_code: geo.country_code.clone(),
+        });
+        network_manager.lock().await.update_local_info(local_info).await;
+    }
+    
+    // 启动网络服务
+    // 服务端总是为节点中继，客户端按该能力从候选服务端中选择
+    network_manager.lock().await.set_capabilities(Capabilities::CAN_RELAY).await;
+    network_manager.lock().await.set_max_hops(config.server.max_hops);
+    network_manager.lock().await.set_ack_sampling_rate(config.server.ack_sampling_rate);
+    network_manager.lock().await.set_dscp_marking(config.server.dscp_marking);
+    network_manager.lock().await.set_compression(config.server.compression);
+    network_manager.lock().await.set_migration_grace_period(
+        config.server.migration_grace_period
+    );
+    network_manager.lock().await.set_watchdog_interval(
+        config.server.watchdog_interval
+    );
+    network_manager.lock().await.set_heartbeat_strategy(config.server.heartbeat.clone());
+    if let Some(pause_timeout) = config.server.pause_timeout {
+        network_manager.lock().await.set_pause_timeout(pause_timeout);
+    }
+    if let Some(lifetime) = config.server.session_ticket_lifetime {
+        let keys = SessionTicketKeys::new(lifetime)?;
+        network_manager.lock().await.set_session_tickets(Arc::new(Mutex::new(keys)));
+        log::info!("Session resumption enabled, tickets valid for {:?}", lifetime);
+    }
+    if !config.server.interfaces.is_empty() {
+        network_manager.lock().await.set_link_aggregation(
+            config.server.interfaces.clone(),
+            config.server.bonding_mode,
+            config.server.failover_timeout
+        )?;
+        log::info!("Link aggregation enabled on {} ({:?})",
+                   config.server.interfaces.join(", "), config.server.bonding_mode);
+    }
+    
+    if config.server.link_rate_mbps > 0 {
+        network_manager.lock().await.set_link_rate(config.server.link_rate_mbps as u64 * 1_000_000 / 8);
+    }
+    match config.server.scheduler {
+        SchedulerMode::Priority => {
+            network_manager.lock().await.set_scheduler(Arc::new(PriorityScheduler::new()));
+            log::info!("Priority packet scheduling enabled");
+        }
+        SchedulerMode::Wfq => {
+            network_manager.lock().await.set_scheduler(Arc::new(
+                WfqScheduler::with_weights(config.server.peer_weights.clone())
+            ));
+            log::info!("Weighted fair queuing enabled");
+        }
+        SchedulerMode::Htb => {
+            let scheduler = Arc::new(HtbScheduler::new(config.server.htb_classes.clone())?);
+            node_manager.lock().await.set_htb_scheduler(scheduler.clone());
+            network_manager.lock().await.set_scheduler(scheduler);
+            log::info!("HTB traffic shaping enabled with {} classes", config.server.htb_classes.len());
+        }
+        _ => {}
+    }
+    
+    network_manager.lock().await.set_stats_history_capacity(config.server.stats_history_capacity);
+    if !config.compat.is_empty() {
+        network_manager.lock().await.set_compat_matrix(CompatMatrix::new(config.compat.clone())?);
+        log::info!("Client version compatibility matrix loaded with {} rules", config.compat.len());
+    }
+    if config.server.tcp_tunnel {
+        network_manager.lock().await.start_tcp_listener(config.server.port)?;
+        network_manager.lock().await.set_tcp_stream_config(config.server.tcp_stream_config());
+        log::info!("Accepting TCP tunnels on port {}", config.server.port);
+    }
+    
+    // 分组带宽上限可以通过API随时调整，因此总是启用
+    let bandwidth_limiter = Arc::new(GroupBandwidthLimiter::new(config.server.group_queue_depth));
+    node_manager.lock().await.set_bandwidth_limiter(bandwidth_limiter.clone());
+    network_manager.lock().await.set_bandwidth_limiter(bandwidth_limiter);
+    
+    // 解密后的流量镜像到IDS/IPS
+    if config.mirror.enable {
+        if let Some(destination) = &config.mirror.destination {
+            let destination: SocketAddr = destination.parse()?;
+            let mirror = TrafficMirror::new(destination, config.mirror.sample_rate).await?;
+            network_manager.lock().await.set_traffic_mirror(Arc::new(mirror));
+            log::info!("Traffic mirror enabled to {} (sample rate {})", destination, config.mirror.sample_rate);
+        }
+    }
+    
+    if config.debug.enable_dpi {
+        network_manager.lock().await.set_packet_inspector(Arc::new(PacketInspector::new()));
+        log::info!("Application protocol identification enabled");
+    }
+    
+    // 节点事件触发配置的外部命令
+    let hook_runner = HookRunner::new(config.hooks.clone(), config.server.max_concurrent_hooks);
+    tasks.spawn("hooks", hook_runner.run(network_manager.lock().await.subscribe_peer_events()));
+    
+    // 节点断开后不再占用名额，满员时被替换的节点断开连接
+    tasks.spawn("capacity-tracker", run_capacity_tracker(
+        node_manager.clone(),
+        network_manager.clone(),
+        network_manager.lock().await.subscribe_peer_events()
+    ));
+    
+    // 记录节点经服务端探测的两两往返时延
+    let latency_matrix = Arc::new(RwLock::new(PeerLatencyMatrix::new()));
+    tasks.spawn("latency-matrix", run_latency_matrix(
+        latency_matrix.clone(),
+        network_manager.lock().await.subscribe_latency_samples(),
+        network_manager.lock().await.subscribe_peer_events()
+    ));
+    
+    // 发往本节点的数据解密后写入虚拟网卡
+    let device_sender = device.lock().await.get_packet_sender();
+    network_manager.lock().await.set_device_sender(device_sender, config.virtual_device.device_mode);
+    
+    // 预先绑定服务端主动重连节点时使用的端口，避免频繁重连时耗尽临时端口
+    let connection_pool = if config.connection_pool.enable {
+        let pool = Arc::new(PeerConnectionPool::bind(config.server.bind.parse()?, config.connection_pool.max_ports)?);
+        for socket in pool.sockets() {
+            network_manager.lock().await.add_socket(socket.clone())?;
+        }
+        log::info!("Peer connection pool bound {} ports on {}", config.connection_pool.max_ports, config.server.bind);
+        tasks.spawn("connection-pool", run_connection_pool(
+            pool.clone(),
+            network_manager.lock().await.subscribe_peer_events()
+        ));
+        Some(pool)
+    } else {
+        None
+    };
+    
+    network_manager.lock().await.start().await;
+    for addr in &listen_addrs {
+        log::info!("Network service started on {}", addr);
+    }
+    
+    // 从虚拟网卡读出的数据包按目标虚拟IP转发，TAP模式下转发完整的以太网帧
+    // 转发任务由看门狗监督，空闲时也每秒报告存活，卡在转发中超过看门狗超时会被重启
+    {
+        let device_mode = config.virtual_device.device_mode;
+        let forwarder_device = device.clone();
+        let forwarder_manager = network_manager.clone();
+        network_manager.lock().await.supervise("device-forwarder", move |keepalive| {
+            let (device, network_manager) = (forwarder_device.clone(), forwarder_manager.clone());
+            Box::pin(async move {
+                let mut device_reader = device.lock().await.reader();
+                let mut ticker = tokio::time::interval(Duration::from_secs(1));
+                let mut buf = vec![0u8; vpnet::MAX_DATAGRAM_SIZE];
+                loop {
+                    let len = tokio::select! {
+                        _ = ticker.tick() => {
+                            let _ = keepalive.try_send(());
+                            continue;
+                        }
+                        read = device_reader.read(&mut buf) => match read {
+                            Ok(0) => return,
+                            Ok(len) => len,
+                            Err(e) => {
+                                log::error!("Failed to read from virtual device: {}", e);
+                                return;
+                            }
+                        },
+                    };
+                    if let Err(e) = network_manager.lock().await.forward_frame(&buf[..len], device_mode).await {
+                        log::debug!("Failed to forward packet from virtual device: {}", e);
+                    }
+                }
+            })
+        });
+    }
+    
+    // 定期在局域网内广播节点发现
+    if config.node.auto_discovery {
+        let network_manager = network_manager.clone();
+        let subnets = config.node.discovery_subnets.clone();
+        let mut ticker = tokio::time::interval(config.node.discovery_interval);
+        tasks.spawn("discovery", async move {
+            loop {
+                ticker.tick().await;
+                let network_manager = network_manager.lock().await;
+                let targets: Vec<_> = if subnets.is_empty() {
+                    vec![None]
+                } else {
+                    subnets.iter().copied().map(Some).collect()
+                };
+                for subnet in targets {
+                    if let Err(e) = network_manager.send_broadcast_discovery(subnet).await {
+                        log::warn!("Failed to broadcast node discovery: {}", e);
+                    }
+                }
+            }
+        });
+    }
+    
+    // 按计划清理长期离线的节点，配置已校验过表达式
+    if let Some(schedule) = &config.node.eviction_schedule {
+        tasks.spawn("idle-eviction", run_idle_eviction(
+            node_manager.clone(),
+            network_manager.clone(),
+            schedule.parse()?,
+            idle_threshold_from_days(config.node.idle_threshold_days).unwrap_or(Duration::MAX)
+        ));
+    }
+    
+    // 副本提升后停止本服务端，副本未启用自动提升时不会出现
+    if let ServerMode::Primary { replicas } = &config.server.mode {
+        if !replicas.is_empty() {
+            tasks.spawn_fallible("fencing", watch_replicas(replicas.clone(), FENCING_INTERVAL));
+        }
+    }
+    
+    // 启动API服务器，所有服务启动完成后才报告就绪
+    let ready = Arc::new(AtomicBool::new(false));
+    let api_addr: SocketAddr = format!("{}:{}", config.api.bind, config.api.port)
+        .parse()?;
+    tasks.spawn_graceful("api", |shutdown| start_api_server(
+        api_addr,
+        auth_manager.clone(),
+        node_manager.clone(),
+        network_manager.clone(),
+        Arc::new(Mutex::new(device_manager.clone())),
+        capture.clone(),
+        latency_matrix.clone(),
+        connection_pool.clone(),
+        config.api.clone(),
+        ready.clone(),
+        shutdown
+    ));
+    
+    // 启动Web管理界面
+    let web_addr: SocketAddr = format!("{}:{}", config.web.bind, config.web.port)
+        .parse()?;
+    tasks.spawn_fallible("web", start_web_server(
+        web_addr,
+        auth_manager.clone(),
+        node_manager.clone(),
+        network_manager.clone(),
+        config.web.clone()
+    ));
+    
+    // 启动gRPC调试接口
+    #[cfg(feature = "debug-grpc")]
+    if config.debug.enable {
+        let debug_addr: SocketAddr = format!("{}:{}", config.debug.bind, config.debug.grpc_port)
+            .parse()?;
+        tasks.spawn_fallible("debug-grpc", DebugOverlay::new(network_manager.clone()).serve(debug_addr));
+    }
+    #[cfg(not(feature = "debug-grpc"))]
+    if config.debug.enable {
+        log::warn!("debug.enable is set but the server was built without the debug-grpc feature");
+    }
+    
+    ready.store(true, Ordering::Release);
+    log::info!("VPNet Server started successfully");
+    log::info!("Web management interface available at http://{}", web_addr);
+    log::info!("API server available at http://{}", api_addr);
+    
+    // 主循环 - 等待关闭信号，后台任务失败时同样关闭服务端
+    let failure = tokio::select! {
+        signal = tokio::signal::ctrl_c() => {
+            signal.expect("Failed to listen for Ctrl+C");
+            log::info!("Received shutdown signal, stopping services...");
+            None
+        }
+        error = tasks.failed() => {
+            log::error!("Background task failed, stopping services...");
+            Some(error)
+        }
+    };
+    ready.store(false, Ordering::Release);
+    
+    // API服务器处理完正在进行的请求后结束，其余后台任务直接中止，任务的错误在关闭设备后返回
+    let tasks_result = tasks.shutdown().await;
+    network_manager.lock().await.stop();
+    
+    // 关闭虚拟设备
+    device.lock().await.stop().await?;
+    if let Some(error) = failure {
+        return Err(error as Box<dyn std::error::Error>);
+    }
+    tasks_result.map_err(|e| e as Box<dyn std::error::Error>)?;
+    
+    log::info!("VPNet Server stopped successfully");
+    
+    Ok(())
+}
+
+/// 以只读副本运行，直到收到关闭信号或主服务端不可用需要提升
+///
+/// 返回是否需要提升为主服务端。
+async fn run_replica(
+    config: &ServerConfig,
+    view: ReplicaView,
+    poller: ReplicaPoller
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let api_addr: SocketAddr = format!("{}:{}", config.api.bind, config.api.port).parse()?;
+    let web_addr: SocketAddr = format!("{}:{}", config.web.bind, config.web.port).parse()?;
+    log::info!("Running as read-only replica, API available at http://{}, web interface at http://{}", api_addr, web_addr);
+    
+    let mut tasks = BackgroundTasks::new();
+    let enable_cors = config.api.enable_cors;
+    let api_view = view.clone();
+    tasks.spawn_graceful("replica-api", move |shutdown| start_replica_api_server(api_addr, api_view, enable_cors, shutdown));
+    let static_dir = config.web.static_dir.clone();
+    let enable_compression = config.web.enable_compression;
+    tasks.spawn_graceful("replica-web", move |shutdown| start_replica_web_server(web_addr, view, static_dir, enable_compression, shutdown));
+    
+    let promote = tokio::select! {
+        _ = poller.run() => Ok(true),
+        signal = tokio::signal::ctrl_c() => {
+            signal?;
+            log::info!("Received shutdown signal, stopping replica...");
+            Ok(false)
+        }
+        error = tasks.failed() => Err(error),
+    };
+    
+    // 停止副本的API和Web服务器，提升后由主服务端的服务器使用同样的端口
+    let tasks_result = tasks.shutdown().await;
+    let promote = promote.map_err(|e| e as Box<dyn std::error::Error>)?;
+    tasks_result.map_err(|e| e as Box<dyn std::error::Error>)?;
+    Ok(promote)
+}
@@ -0,0 +1,470 @@
+/*!
+VPNet Server 网络映射模块
+
+将服务端的完整网络配置导出为单个可移植的文件，迁移到新服务端时导入，包括：
+- 节点（名称、虚拟IP、公钥、分组、优先级、标签），导入的节点在首次握手前保持离线
+- 节点分组
+- 地址池和预留地址
+- 策略路由规则
+- JSON和YAML格式，导入是幂等的，重复导入同一份映射不产生变化
+*/
+
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use vpnet::{IpCidr, NetworkManager, PolicyRoute};
+use vpnet::utils::current_unix_timestamp;
+use crate::auth::PublicKeyDirectory;
+use crate::config::{IpReservation, PeerGroup};
+use crate::node::{Node, NodeImport, NodeManager};
+
+/// 当前的网络映射格式版本
+pub const NETWORK_MAP_VERSION: u32 = 1;
+
+/// 网络映射错误
+#[derive(Error, Debug)]
+pub enum NetworkMapError {
+    #[error("Unsupported network map version: {0}")]
+    UnsupportedVersion(u32),
+    
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    
+    #[error("YAML error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+/// 网络映射的序列化格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapFormat {
+    #[default]
+    Json,
+    Yaml,
+}
+
+/// 导出的节点
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeRecord {
+    pub id: String,
+    pub name: String,
+    pub virtual_ip: Ipv4Addr,
+    /// Base64编码的公钥
+    pub public_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub priority: u8,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+}
+
+impl From<&Node> for NodeRecord {
+    fn from(node: &Node) -> Self {
+        Self {
+            id: node.id.clone(),
+            name: node.name.clone(),
+            virtual_ip: node.virtual_ip,
+            public_key: base64::engine::general_purpose::STANDARD.encode(&node.public_key),
+            group: node.group.clone(),
+            priority: node.priority,
+            tags: node.tags.iter().map(|(key, value)| (key.clone(), value.clone())).collect(),
+        }
+    }
+}
+
+/// 导出的地址池
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpPoolRecord {
+    pub cidr: IpCidr,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reservations: Vec<IpReservation>,
+}
+
+/// 服务端的完整网络配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkMap {
+    pub version: u32,
+    /// 导出时间（Unix秒）
+    #[serde(default)]
+    pub exported_at: u64,
+    #[serde(default)]
+    pub nodes: Vec<NodeRecord>,
+    #[serde(default)]
+    pub groups: Vec<PeerGroup>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip_pool: Option<IpPoolRecord>,
+    #[serde(default)]
+    pub routes: Vec<PolicyRoute>,
+}
+
+impl NetworkMap {
+    /// 按指定格式序列化
+    pub fn to_string(&self, format: MapFormat) -> Result<String, NetworkMapError> {
+        Ok(match format {
+            MapFormat::Json => serde_json::to_string_pretty(self)?,
+            MapFormat::Yaml => serde_yaml::to_string(self)?,
+        })
+    }
+    
+    /// 解析JSON或YAML格式的网络映射
+    pub fn parse(data: &[u8]) -> Result<Self, NetworkMapError> {
+        match serde_json::from_slice(data) {
+            Ok(map) => Ok(map),
+            // YAML是JSON的超集，JSON解析失败时按YAML解析
+            Err(_) => Ok(serde_yaml::from_slice(data)?),
+        }
+    }
+}
+
+/// 导入失败的条目
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportFailure {
+    pub item: String,
+    pub error: String,
+}
+
+/// 导入结果，条目以`<类型>:<标识>`表示，例如`node:alice`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub skipped: Vec<String>,
+    pub failed: Vec<ImportFailure>,
+}
+
+impl ImportReport {
+    fn fail(&mut self, item: String, error: impl ToString) {
+        self.failed.push(ImportFailure { item, error: error.to_string() });
+    }
+}
+
+/// 服务端状态的导出和导入
+pub struct ServerState {
+    node_manager: Arc<Mutex<NodeManager>>,
+    network_manager: Arc<Mutex<NetworkManager>>,
+    key_directory: Arc<PublicKeyDirectory>,
+}
+
+impl ServerState {
+    /// 创建服务端状态的视图，导入节点时与`key_directory`中缓存的公钥比对
+    pub fn new(
+        node_manager: Arc<Mutex<NodeManager>>,
+        network_manager: Arc<Mutex<NetworkManager>>,
+        key_directory: Arc<PublicKeyDirectory>
+    ) -> Self {
+        Self { node_manager, network_manager, key_directory }
+    }
+    
+    /// 导出完整的网络配置，节点、分组和路由按标识排序
+    pub async fn export(&self) -> NetworkMap {
+        let (mut nodes, mut groups, ip_pool) = {
+            let node_manager = self.node_manager.lock().await;
+            let ip_pool = node_manager.ip_pool_cidr().map(|cidr| IpPoolRecord {
+                cidr,
+                reservations: node_manager.ip_reservations(),
+            });
+            let nodes: Vec<NodeRecord> = node_manager.list().iter().map(NodeRecord::from).collect();
+            (nodes, node_manager.groups(), ip_pool)
+        };
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        groups.sort_by(|a, b| a.group_id.cmp(&b.group_id));
+        let routes = self.network_manager.lock().await.get_policy_routes().await;
+        
+        NetworkMap {
+            version: NETWORK_MAP_VERSION,
+            exported_at: current_unix_timestamp(),
+            nodes,
+            groups,
+            ip_pool,
+            routes,
+        }
+    }
+    
+    /// 导入网络配置
+    ///
+    /// 依次导入分组、预留地址、节点和策略路由，与现有配置相同的条目跳过，
+    /// 因此重复导入同一份映射不产生变化。单个条目失败不影响其他条目。
+    /// 新节点与`POST /api/nodes`一样导入，公钥与公钥目录中缓存的不一致时记为失败。
+    /// 已注册节点的虚拟IP、公钥和分组属于其当前注册，与映射不一致时记为失败。
+    pub async fn import(&self, map: NetworkMap) -> Result<ImportReport, NetworkMapError> {
+        if map.version != NETWORK_MAP_VERSION {
+            return Err(NetworkMapError::UnsupportedVersion(map.version));
+        }
+        
+        let mut report = ImportReport::default();
+        {
+            let mut node_manager = self.node_manager.lock().await;
+            import_groups(&mut node_manager, map.groups, &mut report);
+            if let Some(ip_pool) = map.ip_pool {
+                import_ip_pool(&mut node_manager, ip_pool, &mut report);
+            }
+            for record in map.nodes {
+                import_node(&mut node_manager, &self.key_directory, record, &mut report);
+            }
+        }
+        
+        let network_manager = self.network_manager.lock().await;
+        let existing = network_manager.get_policy_routes().await;
+        for route in map.routes {
+            let item = format!("route:{}", route.priority);
+            match existing.iter().find(|r| r.priority == route.priority) {
+                Some(current) if *current == route => report.skipped.push(item),
+                Some(_) => {
+                    network_manager.remove_policy_route(route.priority).await;
+                    network_manager.add_policy_route(route).await;
+                    report.updated.push(item);
+                }
+                None => {
+                    network_manager.add_policy_route(route).await;
+                    report.added.push(item);
+                }
+            }
+        }
+        
+        Ok(report)
+    }
+}
+
+fn import_groups(node_manager: &mut NodeManager, groups: Vec<PeerGroup>, report: &mut ImportReport) {
+    let existing = node_manager.groups();
+    for group in groups {
+        let item = format!("group:{}", group.group_id);
+        match existing.iter().find(|g| g.group_id == group.group_id) {
+            Some(current) if *current == group => report.skipped.push(item),
+            Some(_) => match node_manager.update_group(group) {
+                Ok(()) => report.updated.push(item),
+                Err(e) => report.fail(item, e),
+            },
+            None => match node_manager.add_group(group) {
+                Ok(()) => report.added.push(item),
+                Err(e) => report.fail(item, e),
+            },
+        }
+    }
+}
+
+fn import_ip_pool(node_manager: &mut NodeManager, ip_pool: IpPoolRecord, report: &mut ImportReport) {
+    // 地址池网段来自配置文件，运行中不能修改
+    match node_manager.ip_pool_cidr() {
+        Some(cidr) if cidr == ip_pool.cidr => {}
+        Some(cidr) => {
+            report.fail(format!("ip_pool:{}", ip_pool.cidr), format!("server uses IP pool {}", cidr));
+            return;
+        }
+        None => {
+            report.fail(format!("ip_pool:{}", ip_pool.cidr), "IP pool is not configured");
+            return;
+        }
+    }
+    
+    let existing = node_manager.ip_reservations();
+    for reservation in ip_pool.reservations {
+        let item = format!("reservation:{}", reservation.node_id);
+        let current = existing.iter().find(|r| r.node_id == reservation.node_id).map(|r| r.ip);
+        if current == Some(reservation.ip) {
+            report.skipped.push(item);
+            continue;
+        }
+        match node_manager.reserve_pool_ip(&reservation.node_id, reservation.ip) {
+            Ok(()) if current.is_some() => report.updated.push(item),
+            Ok(()) => report.added.push(item),
+            Err(e) => report.fail(item, e),
+        }
+    }
+}
+
+fn import_node(node_manager: &mut NodeManager, key_directory: &PublicKeyDirectory, record: NodeRecord, report: &mut ImportReport) {
+    let item = format!("node:{}", record.id);
+    let public_key = match base64::engine::general_purpose::STANDARD.decode(&record.public_key) {
+        Ok(public_key) => public_key,
+        Err(_) => return report.fail(item, "public_key must be base64 encoded"),
+    };
+    
+    let current = match node_manager.get(&record.id) {
+        Some(node) => NodeRecord::from(node),
+        None => {
+            let result = node_manager.import(key_directory, NodeImport {
+                id: record.id,
+                name: record.name,
+                address: None,
+                virtual_ip: Some(record.virtual_ip),
+                public_key,
+                group: record.group,
+                priority: record.priority,
+                tags: record.tags.into_iter().collect(),
+            });
+            match result {
+                Ok(_) => report.added.push(item),
+                Err(e) => report.fail(item, e),
+            }
+            return;
+        }
+    };
+    
+    if current.virtual_ip != record.virtual_ip {
+        return report.fail(item, format!("registered with virtual IP {}", current.virtual_ip));
+    }
+    if current.public_key != record.public_key {
+        return report.fail(item, "registered with a different public key");
+    }
+    if current.group != record.group {
+        return report.fail(item, format!("registered in group {}", current.group.as_deref().unwrap_or("(none)")));
+    }
+    
+    let mut changed = false;
+    if current.name != record.name {
+        if let Err(e) = node_manager.rename(&record.id, &record.name) {
+            return report.fail(item, e);
+        }
+        changed = true;
+    }
+    if current.priority != record.priority {
+        node_manager.set_priority(&record.id, record.priority);
+        changed = true;
+    }
+    if current.tags != record.tags {
+        if let Err(e) = node_manager.set_tags(&record.id, record.tags.into_iter().collect()) {
+            return report.fail(item, e);
+        }
+        changed = true;
+    }
+    
+    if changed {
+        report.updated.push(item);
+    } else {
+        report.skipped.push(item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use crate::config;
+    
+    /// 使用地址池10.0.0.0/24的空服务端状态
+    fn server_state() -> ServerState {
+        let mut node_config = config::default_config().node;
+        node_config.ip_pool = Some("10.0.0.0/24".to_string());
+        let mut node_manager = NodeManager::new(node_config).unwrap();
+        node_manager.set_server_ip(Ipv4Addr::new(10, 0, 0, 1));
+        let network_manager = NetworkManager::new(
+            vec!["127.0.0.1:0".parse().unwrap()],
+            "server".to_string(),
+            "server".to_string(),
+            vec![0u8; 32]
+        ).unwrap();
+        let key_path = std::env::temp_dir()
+            .join(format!("vpnet-map-test-{}-{}", std::process::id(), rand::random::<u64>()));
+        let key_directory = PublicKeyDirectory::open(&key_path.to_string_lossy()).unwrap();
+        
+        ServerState::new(
+            Arc::new(Mutex::new(node_manager)),
+            Arc::new(Mutex::new(network_manager)),
+            Arc::new(key_directory)
+        )
+    }
+    
+    fn node_record(id: &str, last_octet: u8, group: Option<&str>) -> NodeRecord {
+        NodeRecord {
+            id: id.to_string(),
+            name: format!("{}-name", id),
+            virtual_ip: Ipv4Addr::new(10, 0, 0, last_octet),
+            public_key: base64::engine::general_purpose::STANDARD.encode([last_octet; 32]),
+            group: group.map(str::to_string),
+            priority: last_octet,
+            tags: BTreeMap::from([("site".to_string(), format!("site-{}", last_octet))]),
+        }
+    }
+    
+    fn sample_map() -> NetworkMap {
+        NetworkMap {
+            version: NETWORK_MAP_VERSION,
+            exported_at: 0,
+            nodes: vec![
+                node_record("alice", 10, Some("branch")),
+                node_record("bob", 11, None),
+                node_record("carol", 20, None),
+            ],
+            groups: vec![PeerGroup {
+                group_id: "branch".to_string(),
+                members: vec!["alice".to_string()],
+                allowed_peers: vec!["bob".to_string()],
+                allowed_groups: Vec::new(),
+                bandwidth_mbps: Some(100),
+            }],
+            ip_pool: Some(IpPoolRecord {
+                cidr: "10.0.0.0/24".parse().unwrap(),
+                reservations: vec![IpReservation { node_id: "carol".to_string(), ip: Ipv4Addr::new(10, 0, 0, 20) }],
+            }),
+            routes: vec![PolicyRoute {
+                priority: 100,
+                src_ip: Some("10.0.0.0/28".parse().unwrap()),
+                dst_ip: None,
+                proto: Some(6),
+                table: 2,
+            }],
+        }
+    }
+    
+    /// 除导出时间外的网络映射内容
+    fn contents(map: &NetworkMap) -> serde_json::Value {
+        let mut value = serde_json::to_value(map).unwrap();
+        value.as_object_mut().unwrap().remove("exported_at");
+        value
+    }
+    
+    #[tokio::test]
+    async fn export_wipe_import_reproduces_the_server_state() {
+        let original = server_state();
+        let report = original.import(sample_map()).await.unwrap();
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        let exported = original.export().await;
+        assert_eq!(contents(&exported), contents(&sample_map()));
+        
+        // 经JSON和YAML导出后导入到全新的服务端
+        for format in [MapFormat::Json, MapFormat::Yaml] {
+            let restored = server_state();
+            let map = NetworkMap::parse(exported.to_string(format).unwrap().as_bytes()).unwrap();
+            let report = restored.import(map).await.unwrap();
+            assert!(report.failed.is_empty(), "{:?}", report.failed);
+            assert_eq!(report.added.len(), 6);
+            assert_eq!(contents(&restored.export().await), contents(&exported));
+        }
+    }
+    
+    #[tokio::test]
+    async fn importing_the_same_map_twice_changes_nothing() {
+        let state = server_state();
+        state.import(sample_map()).await.unwrap();
+        let before = state.export().await;
+        
+        let report = state.import(sample_map()).await.unwrap();
+        assert!(report.added.is_empty());
+        assert!(report.updated.is_empty());
+        assert!(report.failed.is_empty());
+        assert_eq!(report.skipped.len(), 6);
+        assert_eq!(contents(&state.export().await), contents(&before));
+    }
+    
+    #[tokio::test]
+    async fn imported_nodes_are_offline_and_checked_against_pinned_keys() {
+        let state = server_state();
+        state.key_directory.verify("bob", &[1u8; 32]).unwrap();
+        
+        let report = state.import(sample_map()).await.unwrap();
+        let failed: Vec<_> = report.failed.iter().map(|failure| failure.item.as_str()).collect();
+        assert_eq!(failed, ["node:bob"]);
+        
+        let node_manager = state.node_manager.lock().await;
+        assert!(node_manager.get("bob").is_none());
+        assert_eq!(node_manager.get("alice").unwrap().address, SocketAddr::from(([0, 0, 0, 0], 0)));
+        assert_eq!(node_manager.peer_count(), 0);
+        assert_eq!(state.key_directory.get("alice").unwrap().unwrap().public_key, vec![10u8; 32]);
+    }
+}
@@ -0,0 +1,1721 @@
+/*!
+VPNet Server 节点管理模块
+
+管理加入虚拟网络的节点，包括：
+- 节点注册和注销，管理员导入的节点在首次握手前保持离线
+- 虚拟IP分配和管理员预留
+- 虚拟IP冲突检测和处理
+- 节点名称生成
+- 节点分组访问控制
+- 节点数上限和按优先级替换
+- 节点标签和按标签查询，标签保存在本地数据库中
+- 同步节点记录到共享注册表，节点可以重新连接到其他服务端
+- 清理长期离线的节点
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use thiserror::Error;
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinHandle;
+use vpnet::{status, BoxFuture, Capabilities, ConflictDetails, ForwardFilter, HandshakeRequest, HandshakeValidator, HandshakeVerdict, IpCidr};
+use vpnet::{GroupBandwidthLimiter, HtbScheduler, NetworkManager, NodeStatus, Peer, PeerEvent};
+use serde::{Deserialize, Serialize};
+use vpnet::utils::current_unix_timestamp;
+use crate::auth::{AuthError, KeyCheck, PublicKeyDirectory};
+use crate::config::{self, ConflictStrategy, PeerGroup};
+use crate::registry::PeerRegistry;
+
+/// 节点错误
+#[derive(Error, Debug)]
+pub enum NodeError {
+    #[error("Virtual IP {ip} is already used by node {node_id}")]
+    VirtualIpConflict { ip: Ipv4Addr, node_id: String },
+    
+    #[error("No virtual IP available")]
+    NoVirtualIp,
+    
+    #[error("Invalid virtual IP: {0}")]
+    InvalidVirtualIp(String),
+    
+    #[error("Invalid IP pool: {0}")]
+    InvalidPool(String),
+    
+    #[error("Node name {0} is already in use")]
+    NameTaken(String),
+    
+    #[error("Node not found: {0}")]
+    NotFound(String),
+    
+    #[error("Node {0} already exists")]
+    Exists(String),
+    
+    #[error("Node {0} has a different pinned public key")]
+    KeyMismatch(String),
+    
+    #[error("Public key directory error: {0}")]
+    KeyDirectory(#[from] AuthError),
+    
+    #[error("Unknown peer group: {0}")]
+    UnknownGroup(String),
+    
+    #[error("Peer group {0} already exists")]
+    GroupExists(String),
+    
+    #[error("Server is full")]
+    CapacityExceeded,
+    
+    #[error("IP pool is not configured")]
+    NoIpPool,
+    
+    #[error(transparent)]
+    IpPool(#[from] IpPoolError),
+    
+    #[error("Tag store error: {0}")]
+    TagStore(#[from] sled::Error),
+    
+    #[error("Invalid tag record: {0}")]
+    TagRecord(#[from] serde_json::Error),
+}
+
+/// 地址池错误
+#[derive(Error, Debug)]
+pub enum IpPoolError {
+    #[error("Virtual IP {ip} is reserved for or used by node {node_id}")]
+    Reserved { ip: Ipv4Addr, node_id: String },
+    
+    #[error("Virtual IP {0} is not a host address of the pool")]
+    OutOfRange(Ipv4Addr),
+    
+    #[error("Virtual IP {0} is the server's own address")]
+    ServerAddress(Ipv4Addr),
+    
+    #[error("No virtual IP available")]
+    Exhausted,
+}
+
+/// 已注册节点
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Node {
+    pub id: String,
+    pub name: String,
+    pub address: SocketAddr,
+    pub virtual_ip: Ipv4Addr,
+    pub public_key: Vec<u8>,
+    pub registered_at: u64,
+    /// 最后一次确认在线的时间，注册和清理离线节点时更新
+    pub last_seen: u64,
+    pub group: Option<String>,
+    /// 由管理员设置的优先级，服务端满员时高优先级节点可以替换低优先级节点
+    pub priority: u8,
+    /// 由管理员设置的标签，例如`owner = "alice"`
+    pub tags: HashMap<String, String>,
+    /// 名称由管理员通过API设置，节点再次握手时不被其配置的名称覆盖
+    #[serde(default)]
+    pub name_pinned: bool,
+    /// 最后接纳该节点的服务端ID，共享注册表中的记录只由该服务端删除
+    #[serde(default)]
+    pub server_id: String,
+}
+
+/// 管理员导入的节点，见`NodeManager::import`
+#[derive(Debug, Clone, Default)]
+pub struct NodeImport {
+    pub id: String,
+    /// 为空时自动生成
+    pub name: String,
+    /// 节点的地址，未知时为`None`，节点握手后更新
+    pub address: Option<SocketAddr>,
+    /// 为`None`时从地址池分配
+    pub virtual_ip: Option<Ipv4Addr>,
+    pub public_key: Vec<u8>,
+    pub group: Option<String>,
+    pub priority: u8,
+    pub tags: HashMap<String, String>,
+}
+
+/// 节点数上限管理
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NodeCapacityManager {
+    max_peers: Option<u32>,
+}
+
+impl NodeCapacityManager {
+    /// 创建节点数上限管理，`None`表示不限制
+    pub fn new(max_peers: Option<u32>) -> Self {
+        Self { max_peers }
+    }
+    
+    /// 节点数上限
+    pub fn max_peers(&self) -> Option<u32> {
+        self.max_peers
+    }
+    
+    /// 判断当前节点数下能否接纳新节点
+    pub fn can_accept(&self, peer_count: usize) -> bool {
+        match self.max_peers {
+            Some(max) => peer_count < max as usize,
+            None => true,
+        }
+    }
+    
+    /// 选择为新节点让出位置的节点
+    ///
+    /// 只替换优先级低于新节点的节点，优先替换优先级最低的，同优先级中替换最晚注册的。
+    pub fn bump_candidate<'a>(&self, nodes: impl Iterator<Item = &'a Node>, priority: u8) -> Option<&'a Node> {
+        nodes
+            .filter(|node| node.priority < priority)
+            .min_by_key(|node| (node.priority, std::cmp::Reverse(node.registered_at)))
+    }
+}
+
+/// 节点名称生成器
+///
+/// 由形容词、动物名和三位数字组成易读的名称，例如`brave-otter-491`。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NameGenerator;
+
+impl NameGenerator {
+    const ADJECTIVES: &'static [&'static str] = &[
+        "agile", "bold", "brave", "bright", "calm", "clever", "cosmic", "eager",
+        "fancy", "gentle", "happy", "jolly", "keen", "lucky", "mighty", "nimble",
+        "proud", "quick", "quiet", "rapid", "shiny", "silent", "smart", "steady",
+        "sunny", "swift", "tidy", "vivid", "warm", "wise", "witty", "zesty",
+    ];
+    
+    const ANIMALS: &'static [&'static str] = &[
+        "badger", "beaver", "bison", "crane", "dolphin", "eagle", "falcon", "ferret",
+        "fox", "gecko", "heron", "ibex", "jaguar", "koala", "lemur", "lynx",
+        "marmot", "moose", "narwhal", "ocelot", "otter", "panda", "puffin", "quail",
+        "raven", "salmon", "seal", "tapir", "tiger", "walrus", "wombat", "yak",
+    ];
+    
+    /// 生成随机名称
+    pub fn generate(&self) -> String {
+        let mut rng = rand::thread_rng();
+        format!(
+            "{}-{}-{:03}",
+            Self::ADJECTIVES.choose(&mut rng).unwrap(),
+            Self::ANIMALS.choose(&mut rng).unwrap(),
+            rng.gen_range(0..1000)
+        )
+    }
+}
+
+/// 分配给节点的虚拟IP
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IpAssignment {
+    pub ip: Ipv4Addr,
+    pub node_id: String,
+}
+
+/// 连续的地址段，包含两端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct IpRange {
+    pub start: Ipv4Addr,
+    pub end: Ipv4Addr,
+}
+
+/// 地址池使用情况
+#[derive(Debug, Clone, Serialize)]
+pub struct IpPoolSummary {
+    pub cidr: IpCidr,
+    /// 已被节点占用的地址，包括节点自行请求的地址
+    pub allocated: Vec<IpAssignment>,
+    /// 管理员预留的地址，预留的节点上线后同时出现在`allocated`中
+    pub reserved: Vec<IpAssignment>,
+    /// 既未占用也未预留的地址段
+    pub free: Vec<IpRange>,
+}
+
+/// 虚拟IP地址池
+#[derive(Debug, Clone)]
+pub struct IpPool {
+    cidr: IpCidr,
+    allocated: HashSet<Ipv4Addr>,
+    /// 管理员为节点预留的地址
+    reservations: HashMap<Ipv4Addr, String>,
+    /// 服务端自身的虚拟IP，不能分配或预留给节点
+    server_ip: Option<Ipv4Addr>,
+}
+
+impl IpPool {
+    /// 创建新的地址池
+    pub fn new(cidr: IpCidr) -> Self {
+        Self {
+            cidr,
+            allocated: HashSet::new(),
+            reservations: HashMap::new(),
+            server_ip: None,
+        }
+    }
+    
+    /// 设置服务端自身的虚拟IP，之后不再分配该地址，预留该地址时返回`IpPoolError::ServerAddress`
+    pub fn set_server_ip(&mut self, ip: Ipv4Addr) {
+        self.server_ip = Some(ip);
+    }
+    
+    /// 地址池网段
+    pub fn cidr(&self) -> IpCidr {
+        self.cidr
+    }
+    
+    /// 可分配的主机地址，不含网络地址和广播地址
+    fn hosts(&self) -> std::ops::Range<u32> {
+        let network = u32::from(self.cidr.addr);
+        let host_bits = 32 - self.cidr.prefix_len as u32;
+        let size = if host_bits >= 32 { u32::MAX } else { (1u32 << host_bits) - 1 };
+        network.saturating_add(1)..network.saturating_add(size)
+    }
+    
+    /// 为节点预留地址，节点之后分配地址时总是得到该地址
+    ///
+    /// 每个节点只保留一个预留地址，重复预留时替换原来的。地址已预留给其他节点时返回`IpPoolError::Reserved`。
+    pub fn reserve(&mut self, ip: Ipv4Addr, node_id: &str) -> Result<(), IpPoolError> {
+        if !self.hosts().contains(&u32::from(ip)) {
+            return Err(IpPoolError::OutOfRange(ip));
+        }
+        if self.server_ip == Some(ip) {
+            return Err(IpPoolError::ServerAddress(ip));
+        }
+        if let Some(owner) = self.reservations.get(&ip).filter(|owner| *owner != node_id) {
+            return Err(IpPoolError::Reserved { ip, node_id: owner.clone() });
+        }
+        
+        self.reservations.retain(|_, owner| owner != node_id);
+        self.reservations.insert(ip, node_id.to_string());
+        Ok(())
+    }
+    
+    /// 为节点预留的地址
+    pub fn reservation_for(&self, node_id: &str) -> Option<Ipv4Addr> {
+        self.reservations.iter()
+            .find(|(_, owner)| *owner == node_id)
+            .map(|(ip, _)| *ip)
+    }
+    
+    /// 预留了该地址的节点ID
+    pub fn reserved_by(&self, ip: Ipv4Addr) -> Option<&str> {
+        self.reservations.get(&ip).map(String::as_str)
+    }
+    
+    /// 为节点分配地址
+    ///
+    /// 节点有预留地址时返回预留地址，预留地址已被其他节点占用时返回`IpPoolError::Reserved`；
+    /// 否则分配一个空闲地址，跳过网络地址、广播地址、已被其他节点占用以及预留给其他节点的地址。
+    pub fn allocate(&mut self, node_id: &str, checker: &VirtualIpConflictChecker) -> Result<Ipv4Addr, IpPoolError> {
+        if let Some(ip) = self.reservation_for(node_id) {
+            if let Some(owner) = checker.owner_of(ip).filter(|owner| *owner != node_id) {
+                return Err(IpPoolError::Reserved { ip, node_id: owner.to_string() });
+            }
+            self.allocated.insert(ip);
+            return Ok(ip);
+        }
+        
+        let ip = self.hosts()
+            .map(Ipv4Addr::from)
+            .find(|ip| !self.allocated.contains(ip) && !checker.is_claimed(*ip) && !self.reservations.contains_key(ip) && self.server_ip != Some(*ip))
+            .ok_or(IpPoolError::Exhausted)?;
+        self.allocated.insert(ip);
+        Ok(ip)
+    }
+    
+    /// 归还地址，预留保持不变
+    pub fn release(&mut self, ip: Ipv4Addr) {
+        self.allocated.remove(&ip);
+    }
+    
+    /// 已占用、已预留和空闲的地址
+    pub fn summary(&self, checker: &VirtualIpConflictChecker) -> IpPoolSummary {
+        let hosts = self.hosts();
+        let mut allocated: Vec<IpAssignment> = checker.claims()
+            .filter(|(ip, _)| hosts.contains(&u32::from(*ip)))
+            .map(|(ip, node_id)| IpAssignment { ip, node_id: node_id.to_string() })
+            .collect();
+        allocated.sort_by_key(|assignment| assignment.ip);
+        let mut reserved: Vec<IpAssignment> = self.reservations.iter()
+            .map(|(ip, node_id)| IpAssignment { ip: *ip, node_id: node_id.clone() })
+            .collect();
+        reserved.sort_by_key(|assignment| assignment.ip);
+        
+        // 按顺序取出所有不空闲的地址，其间的空隙即为空闲地址段
+        let mut used: Vec<u32> = allocated.iter()
+            .chain(&reserved)
+            .map(|assignment| u32::from(assignment.ip))
+            .chain(self.allocated.iter().map(|ip| u32::from(*ip)))
+            .chain(self.server_ip.map(u32::from))
+            .collect();
+        used.sort_unstable();
+        used.dedup();
+        
+        let mut free = Vec::new();
+        let mut next = hosts.start;
+        for ip in used.into_iter().chain(std::iter::once(hosts.end)) {
+            if ip > next {
+                free.push(IpRange { start: Ipv4Addr::from(next), end: Ipv4Addr::from(ip - 1) });
+            }
+            next = next.max(ip.saturating_add(1));
+        }
+        
+        IpPoolSummary { cidr: self.cidr, allocated, reserved, free }
+    }
+}
+
+/// 虚拟IP冲突检测，记录每个虚拟IP的占用节点
+#[derive(Debug, Clone, Default)]
+pub struct VirtualIpConflictChecker {
+    owners: HashMap<Ipv4Addr, String>,
+}
+
+impl VirtualIpConflictChecker {
+    /// 检查节点能否使用虚拟IP，冲突时返回占用该地址的节点ID
+    pub fn check(&self, ip: Ipv4Addr, node_id: &str) -> Result<(), String> {
+        match self.owners.get(&ip) {
+            Some(owner) if owner != node_id => Err(owner.clone()),
+            _ => Ok(()),
+        }
+    }
+    
+    /// 记录节点占用虚拟IP
+    pub fn claim(&mut self, ip: Ipv4Addr, node_id: &str) {
+        self.owners.insert(ip, node_id.to_string());
+    }
+    
+    /// 释放虚拟IP
+    pub fn release(&mut self, ip: Ipv4Addr) {
+        self.owners.remove(&ip);
+    }
+    
+    /// 查询占用虚拟IP的节点ID
+    pub fn owner_of(&self, ip: Ipv4Addr) -> Option<&str> {
+        self.owners.get(&ip).map(String::as_str)
+    }
+    
+    /// 判断虚拟IP是否已被占用
+    pub fn is_claimed(&self, ip: Ipv4Addr) -> bool {
+        self.owners.contains_key(&ip)
+    }
+    
+    /// 所有被占用的虚拟IP及其占用节点ID
+    pub fn claims(&self) -> impl Iterator<Item = (Ipv4Addr, &str)> {
+        self.owners.iter().map(|(ip, node_id)| (*ip, node_id.as_str()))
+    }
+}
+
+/// 节点标签存储
+///
+/// 保存管理员设置的节点标签，每个节点一条记录，服务端重启后由`NodeManager::set_tag_store`恢复。
+pub struct TagStore {
+    tree: sled::Tree,
+}
+
+impl TagStore {
+    /// 打开标签存储
+    pub fn open(path: &str) -> Result<Self, NodeError> {
+        let db = sled::open(path)?;
+        Ok(Self {
+            tree: db.open_tree("tags")?,
+        })
+    }
+    
+    /// 读取所有节点的标签
+    pub fn load(&self) -> Result<HashMap<String, HashMap<String, String>>, NodeError> {
+        let mut tags = HashMap::new();
+        for entry in self.tree.iter() {
+            let (key, value) = entry?;
+            tags.insert(String::from_utf8_lossy(&key).into_owned(), serde_json::from_slice(&value)?);
+        }
+        Ok(tags)
+    }
+    
+    /// 保存节点的标签，标签为空时删除记录
+    pub fn put(&self, node_id: &str, tags: &HashMap<String, String>) -> Result<(), NodeError> {
+        if tags.is_empty() {
+            self.tree.remove(node_id)?;
+        } else {
+            self.tree.insert(node_id, serde_json::to_vec(tags)?)?;
+        }
+        self.tree.flush()?;
+        Ok(())
+    }
+}
+
+/// 节点管理器
+pub struct NodeManager {
+    config: config::Node,
+    nodes: HashMap<String, Node>,
+    conflict_checker: VirtualIpConflictChecker,
+    ip_pool: Option<IpPool>,
+    groups: HashMap<String, PeerGroup>,
+    capacity: NodeCapacityManager,
+    /// 管理员设置的节点优先级，节点注销后仍然保留
+    priorities: HashMap<String, u8>,
+    /// 管理员设置的节点标签，与优先级一样在节点注销后保留
+    tags: HashMap<String, HashMap<String, String>>,
+    /// 标签索引，`(键, 值)`到设置了该标签的节点ID
+    tag_index: HashMap<(String, String), HashSet<String>>,
+    /// 标签存储，设置后标签变化写入其中
+    tag_store: Option<TagStore>,
+    /// 管理员设置的节点名称，与标签一样在节点注销后保留
+    pinned_names: HashMap<String, String>,
+    bandwidth_limiter: Option<Arc<GroupBandwidthLimiter>>,
+    htb_scheduler: Option<Arc<HtbScheduler>>,
+    /// 握手时声明可以中继的节点ID
+    relay_nodes: HashSet<String>,
+    /// 已握手且尚未断开的节点ID，节点数上限只计入在线节点
+    online: HashSet<String>,
+    /// 管理员导入后尚未握手的节点ID，不写入共享注册表，也不作为长期离线节点清理
+    provisioned: HashSet<String>,
+    /// 满员时被高优先级节点替换的节点ID，由`run_capacity_tracker`关闭其会话
+    bumped: broadcast::Sender<String>,
+    /// 共享的节点注册表，节点记录变化时异步写入
+    registry: Option<Arc<dyn PeerRegistry>>,
+}
+
+impl NodeManager {
+    /// 创建新的节点管理器
+    pub fn new(config: config::Node) -> Result<Self, NodeError> {
+        let ip_pool = match &config.ip_pool {
+            Some(pool) => Some(IpPool::new(
+                pool.parse().map_err(|_| NodeError::InvalidPool(pool.clone()))?
+            )),
+            None => None,
+        };
+        
+        Ok(Self {
+            nodes: HashMap::new(),
+            conflict_checker: VirtualIpConflictChecker::default(),
+            groups: config.groups.iter()
+                .map(|group| (group.group_id.clone(), group.clone()))
+                .collect(),
+            capacity: NodeCapacityManager::new(config.max_peers),
+            priorities: HashMap::new(),
+            tags: HashMap::new(),
+            tag_index: HashMap::new(),
+            tag_store: None,
+            pinned_names: HashMap::new(),
+            bandwidth_limiter: None,
+            htb_scheduler: None,
+            relay_nodes: HashSet::new(),
+            online: HashSet::new(),
+            provisioned: HashSet::new(),
+            bumped: broadcast::channel(16).0,
+            registry: None,
+            config,
+            ip_pool,
+        })
+    }
+    
+    /// 设置分组带宽限制，之后分组的带宽上限和成员变化同步到其中
+    pub fn set_bandwidth_limiter(&mut self, limiter: Arc<GroupBandwidthLimiter>) {
+        for group in self.groups.values() {
+            limiter.set_group_limit(&group.group_id, group.bandwidth_bytes_per_sec());
+        }
+        for node in self.nodes.values() {
+            limiter.set_peer_group(&node.id, node.group.as_deref());
+        }
+        self.bandwidth_limiter = Some(limiter);
+    }
+    
+    /// 设置HTB调度器，之后节点的分组变化同步到其中
+    pub fn set_htb_scheduler(&mut self, scheduler: Arc<HtbScheduler>) {
+        for node in self.nodes.values() {
+            scheduler.set_peer_group(&node.id, node.group.as_deref());
+        }
+        self.htb_scheduler = Some(scheduler);
+    }
+    
+    /// 将节点所在的分组同步到分组带宽限制和HTB调度器
+    fn sync_peer_group(&self, node_id: &str, group_id: Option<&str>) {
+        if let Some(limiter) = &self.bandwidth_limiter {
+            limiter.set_peer_group(node_id, group_id);
+        }
+        if let Some(scheduler) = &self.htb_scheduler {
+            scheduler.set_peer_group(node_id, group_id);
+        }
+    }
+    
+    /// 设置节点注册表，已注册的节点立即写入其中
+    pub fn set_registry(&mut self, registry: Box<dyn PeerRegistry>) {
+        self.registry = Some(Arc::from(registry));
+        for node_id in self.nodes.keys() {
+            self.publish(node_id);
+        }
+    }
+    
+    /// 设置标签存储，恢复其中保存的标签，之后标签变化写入其中
+    pub fn set_tag_store(&mut self, store: TagStore) -> Result<(), NodeError> {
+        for (node_id, tags) in store.load()? {
+            self.apply_tags(&node_id, tags);
+        }
+        self.tag_store = Some(store);
+        Ok(())
+    }
+    
+    /// 节点注册表
+    pub fn registry(&self) -> Option<Arc<dyn PeerRegistry>> {
+        self.registry.clone()
+    }
+    
+    /// 将节点的当前记录写入注册表
+    ///
+    /// 节点管理器的方法在持有锁时同步调用，写入在后台任务中完成，失败时只记录日志。
+    /// 尚未握手的导入节点没有可连接的地址，不写入注册表。
+    fn publish(&self, node_id: &str) {
+        if self.provisioned.contains(node_id) {
+            return;
+        }
+        let (Some(registry), Some(node)) = (self.registry.clone(), self.nodes.get(node_id).cloned()) else {
+            return;
+        };
+        tokio::spawn(async move {
+            if let Err(e) = registry.register(&node).await {
+                log::warn!("Failed to publish node {} to registry: {}", node.id, e);
+            }
+        });
+    }
+    
+    /// 从注册表删除节点记录
+    ///
+    /// 节点已重新连接到其他服务端时，注册表中的记录属于该服务端，不删除。
+    fn unpublish(&self, node_id: &str) {
+        let Some(registry) = self.registry.clone() else {
+            return;
+        };
+        let node_id = node_id.to_string();
+        let server_id = self.config.id.clone();
+        tokio::spawn(async move {
+            if let Err(e) = registry.deregister(&node_id, &server_id).await {
+                log::warn!("Failed to remove node {} from registry: {}", node_id, e);
+            }
+        });
+    }
+    
+    /// 为本节点保留虚拟IP，防止被其他节点占用
+    pub fn reserve_virtual_ip(&mut self, node_id: &str, ip: Ipv4Addr) -> Result<(), NodeError> {
+        self.conflict_checker.check(ip, node_id)
+            .map_err(|owner| NodeError::VirtualIpConflict { ip, node_id: owner })?;
+        self.conflict_checker.claim(ip, node_id);
+        Ok(())
+    }
+    
+    /// 设置服务端自身的虚拟IP，地址池不再分配或预留该地址
+    pub fn set_server_ip(&mut self, ip: Ipv4Addr) {
+        if let Some(pool) = self.ip_pool.as_mut() {
+            pool.set_server_ip(ip);
+        }
+    }
+    
+    /// 在地址池中为节点预留地址，节点注册时总是分配该地址
+    ///
+    /// 地址已预留给其他节点或正被其他节点使用时返回`IpPoolError::Reserved`，
+    /// 是服务端自身的虚拟IP时返回`IpPoolError::ServerAddress`。
+    pub fn reserve_pool_ip(&mut self, node_id: &str, ip: Ipv4Addr) -> Result<(), NodeError> {
+        if let Some(owner) = self.conflict_checker.owner_of(ip).filter(|owner| *owner != node_id) {
+            return Err(IpPoolError::Reserved { ip, node_id: owner.to_string() }.into());
+        }
+        let pool = self.ip_pool.as_mut().ok_or(NodeError::NoIpPool)?;
+        pool.reserve(ip, node_id)?;
+        Ok(())
+    }
+    
+    /// 地址池使用情况，未配置地址池时返回`None`
+    pub fn ip_pool_summary(&self) -> Option<IpPoolSummary> {
+        self.ip_pool.as_ref().map(|pool| pool.summary(&self.conflict_checker))
+    }
+    
+    /// 地址池网段，未配置地址池时返回`None`
+    pub fn ip_pool_cidr(&self) -> Option<IpCidr> {
+        self.ip_pool.as_ref().map(IpPool::cidr)
+    }
+    
+    /// 地址池中为节点预留的地址，按节点ID排序
+    pub fn ip_reservations(&self) -> Vec<config::IpReservation> {
+        let mut reservations: Vec<config::IpReservation> = self.ip_pool.iter()
+            .flat_map(|pool| pool.reservations.iter())
+            .map(|(ip, node_id)| config::IpReservation { node_id: node_id.clone(), ip: *ip })
+            .collect();
+        reservations.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        reservations
+    }
+    
+    /// 为节点生成一个未被其他节点使用的名称
+    pub fn assign_node_name(&self, node_id: &str) -> String {
+        // 名称空间足够大，多次尝试仍冲突时附加节点ID保证唯一
+        const MAX_ATTEMPTS: usize = 16;
+        
+        let generator = NameGenerator;
+        for _ in 0..MAX_ATTEMPTS {
+            let name = generator.generate();
+            if !self.is_name_taken(&name, node_id) {
+                return name;
+            }
+        }
+        
+        format!("{}-{}", generator.generate(), node_id)
+    }
+    
+    /// 重命名节点
+    ///
+    /// 新名称保存在节点记录和注册表中，节点再次握手时沿用该名称，不被其配置的名称覆盖。
+    pub fn rename(&mut self, node_id: &str, name: &str) -> Result<(), NodeError> {
+        if self.is_name_taken(name, node_id) {
+            return Err(NodeError::NameTaken(name.to_string()));
+        }
+        
+        let node = self.nodes.get_mut(node_id)
+            .ok_or_else(|| NodeError::NotFound(node_id.to_string()))?;
+        node.name = name.to_string();
+        node.name_pinned = true;
+        self.pinned_names.insert(node_id.to_string(), name.to_string());
+        self.publish(node_id);
+        Ok(())
+    }
+    
+    /// 为已注册节点重新分配虚拟IP，返回节点原来的地址
+    ///
+    /// 新地址被其他节点占用或预留时返回`NodeError::VirtualIpConflict`。
+    pub fn reassign_virtual_ip(&mut self, node_id: &str, ip: Ipv4Addr) -> Result<Ipv4Addr, NodeError> {
+        let old_ip = self.nodes.get(node_id)
+            .map(|node| node.virtual_ip)
+            .ok_or_else(|| NodeError::NotFound(node_id.to_string()))?;
+        if old_ip == ip {
+            return Ok(old_ip);
+        }
+        self.check_ip(ip, node_id)
+            .map_err(|owner| NodeError::VirtualIpConflict { ip, node_id: owner })?;
+        
+        self.release_ip(old_ip);
+        self.conflict_checker.claim(ip, node_id);
+        if let Some(node) = self.nodes.get_mut(node_id) {
+            node.virtual_ip = ip;
+        }
+        self.publish(node_id);
+        Ok(old_ip)
+    }
+    
+    /// 沿用管理员在其他服务端为节点设置的名称，在节点注册前调用
+    pub fn restore_pinned_name(&mut self, node_id: &str, name: &str) {
+        self.pinned_names.entry(node_id.to_string())
+            .or_insert_with(|| name.to_string());
+    }
+    
+    /// 判断名称是否已被其他节点使用
+    fn is_name_taken(&self, name: &str, node_id: &str) -> bool {
+        self.nodes.values().any(|node| node.name == name && node.id != node_id)
+    }
+    
+    /// 注册节点并确定其虚拟IP
+    ///
+    /// 请求的虚拟IP已被其他节点占用时，按配置的冲突策略拒绝或从地址池重新分配。
+    /// 管理员设置过名称时沿用该名称，忽略节点请求的名称；否则未指定名称时自动生成，
+    /// 指定的名称已被其他节点使用时拒绝注册。
+    /// 分组只能由管理员指定：`group_id`来自管理员导入或共享注册表，未指定时保留节点原有的分组，
+    /// 新节点加入`members`中列出该节点的分组。
+    /// 节点数已达上限时，新节点替换一个优先级更低的节点，没有可替换的节点时拒绝注册。
+    pub fn register(
+        &mut self,
+        node_id: &str,
+        name: &str,
+        address: SocketAddr,
+        public_key: Vec<u8>,
+        requested_ip: Option<Ipv4Addr>,
+        group_id: Option<&str>
+    ) -> Result<Ipv4Addr, NodeError> {
+        self.insert(node_id, name, address, public_key, requested_ip, group_id, true)
+    }
+    
+    /// 导入管理员提供的节点
+    ///
+    /// 公钥与公钥目录中缓存的不一致或节点已存在时拒绝导入。导入的节点处于离线状态，
+    /// 不占用节点数名额，不写入共享注册表，也不会因长期离线被清理，直到节点首次握手。
+    /// 导入成功后在公钥目录中记录节点的公钥。
+    pub fn import(&mut self, key_directory: &PublicKeyDirectory, import: NodeImport) -> Result<Node, NodeError> {
+        let pinned = key_directory.get(&import.id)?;
+        if pinned.is_some_and(|record| record.public_key != import.public_key) {
+            return Err(NodeError::KeyMismatch(import.id));
+        }
+        if self.nodes.contains_key(&import.id) {
+            return Err(NodeError::Exists(import.id));
+        }
+        
+        self.insert(
+            &import.id,
+            &import.name,
+            import.address.unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 0))),
+            import.public_key.clone(),
+            import.virtual_ip,
+            import.group.as_deref(),
+            false
+        )?;
+        self.set_priority(&import.id, import.priority);
+        if let Err(e) = self.set_tags(&import.id, import.tags) {
+            log::warn!("Failed to save tags of imported node {}: {}", import.id, e);
+        }
+        if let Err(e) = key_directory.verify(&import.id, &import.public_key) {
+            log::warn!("Failed to pin public key of imported node {}: {}", import.id, e);
+        }
+        
+        self.nodes.get(&import.id).cloned().ok_or(NodeError::NotFound(import.id))
+    }
+    
+    /// 写入节点记录，`online`为false时节点作为尚未握手的导入节点保持离线
+    #[allow(clippy::too_many_arguments)]
+    fn insert(
+        &mut self,
+        node_id: &str,
+        name: &str,
+        address: SocketAddr,
+        public_key: Vec<u8>,
+        requested_ip: Option<Ipv4Addr>,
+        group_id: Option<&str>,
+        online: bool
+    ) -> Result<Ipv4Addr, NodeError> {
+        if let Some(group_id) = group_id.filter(|group_id| !self.groups.contains_key(*group_id)) {
+            return Err(NodeError::UnknownGroup(group_id.to_string()));
+        }
+        let group = group_id.map(str::to_string)
+            .or_else(|| self.nodes.get(node_id).and_then(|node| node.group.clone()))
+            .or_else(|| self.groups.values()
+                .find(|group| group.members.iter().any(|member| member == node_id))
+                .map(|group| group.group_id.clone()));
+        let priority = self.priorities.get(node_id).copied().unwrap_or_default();
+        let tags = self.tags.get(node_id).cloned().unwrap_or_default();
+        
+        if online && !self.online.contains(node_id) && !self.can_accept() {
+            let online = self.nodes.values().filter(|node| self.online.contains(&node.id));
+            let bumped = self.capacity.bump_candidate(online, priority)
+                .map(|node| node.id.clone())
+                .ok_or(NodeError::CapacityExceeded)?;
+            log::info!("Server is full, node {} bumped by higher priority node {}", bumped, node_id);
+            self.remove(&bumped);
+            let _ = self.bumped.send(bumped);
+        }
+        
+        let pinned_name = self.pinned_names.get(node_id).cloned();
+        let name_pinned = pinned_name.is_some();
+        let name = if let Some(pinned_name) = pinned_name {
+            pinned_name
+        } else if name.is_empty() {
+            match self.nodes.get(node_id) {
+                Some(node) => node.name.clone(),
+                None => self.assign_node_name(node_id),
+            }
+        } else if self.is_name_taken(name, node_id) {
+            return Err(NodeError::NameTaken(name.to_string()));
+        } else {
+            name.to_string()
+        };
+        
+        let current_ip = self.nodes.get(node_id).map(|node| node.virtual_ip);
+        
+        let reserved_ip = self.ip_pool.as_ref().and_then(|pool| pool.reservation_for(node_id));
+        
+        let virtual_ip = match requested_ip.or(current_ip) {
+            // 管理员预留的地址优先于节点请求的地址
+            _ if reserved_ip.is_some() => self.allocate_from_pool(node_id)?,
+            Some(ip) => match self.check_ip(ip, node_id) {
+                Err(owner) => {
+                    match self.config.virtual_ip_conflict {
+                        ConflictStrategy::Reject => {
+                            return Err(NodeError::VirtualIpConflict { ip, node_id: owner });
+                        }
+                        ConflictStrategy::AssignFromPool => {
+                            log::warn!("Virtual IP {} requested by {} is used by {}, assigning from pool",
+                                       ip, node_id, owner);
+                            self.allocate_from_pool(node_id)?
+                        }
+                    }
+                }
+                Ok(()) => ip,
+            },
+            None => self.allocate_from_pool(node_id)?,
+        };
+        
+        // 节点更换了虚拟IP时释放旧地址
+        if let Some(old_ip) = current_ip.filter(|old_ip| *old_ip != virtual_ip) {
+            self.release_ip(old_ip);
+        }
+        
+        self.conflict_checker.claim(virtual_ip, node_id);
+        self.sync_peer_group(node_id, group.as_deref());
+        let now = current_unix_timestamp();
+        self.nodes.insert(node_id.to_string(), Node {
+            id: node_id.to_string(),
+            name,
+            address,
+            virtual_ip,
+            public_key,
+            registered_at: now,
+            last_seen: now,
+            group,
+            priority,
+            tags,
+            name_pinned,
+            server_id: self.config.id.clone(),
+        });
+        if online {
+            self.provisioned.remove(node_id);
+            self.online.insert(node_id.to_string());
+        } else {
+            self.provisioned.insert(node_id.to_string());
+        }
+        self.publish(node_id);
+        
+        Ok(virtual_ip)
+    }
+    
+    /// 注销节点并释放其虚拟IP
+    pub fn remove(&mut self, node_id: &str) -> Option<Node> {
+        let node = self.nodes.remove(node_id)?;
+        self.release_ip(node.virtual_ip);
+        self.sync_peer_group(node_id, None);
+        self.relay_nodes.remove(node_id);
+        self.online.remove(node_id);
+        if !self.provisioned.remove(node_id) {
+            self.unpublish(node_id);
+        }
+        Some(node)
+    }
+    
+    /// 节点断开连接或超时，不再计入节点数，注册保持有效
+    pub fn set_offline(&mut self, node_id: &str) {
+        self.online.remove(node_id);
+    }
+    
+    /// 订阅满员时被替换的节点
+    pub fn subscribe_bumped(&self) -> broadcast::Receiver<String> {
+        self.bumped.subscribe()
+    }
+    
+    /// 清理长期离线的节点，返回被注销的节点ID
+    ///
+    /// `peers`为网络层当前的节点状态，先据此更新节点的最后在线时间，
+    /// 再一次性注销所有不在线且超过`idle_threshold`未出现的节点，尚未握手的导入节点除外。
+    /// 只注销节点记录，网络层的节点由调用方移除，见`evict_idle`。
+    pub fn evict_idle_nodes(&mut self, idle_threshold: Duration, peers: &[Peer]) -> Vec<String> {
+        let cutoff = current_unix_timestamp().saturating_sub(idle_threshold.as_secs());
+        
+        let mut online = HashSet::new();
+        for peer in peers {
+            if let Some(node) = self.nodes.get_mut(&peer.node_id) {
+                node.last_seen = node.last_seen.max(peer.last_seen);
+            }
+            if peer.status == NodeStatus::Online {
+                online.insert(peer.node_id.as_str());
+            }
+        }
+        
+        let stale: Vec<String> = self.nodes.values()
+            .filter(|node| !online.contains(node.id.as_str()) && node.last_seen < cutoff)
+            .filter(|node| !self.provisioned.contains(&node.id))
+            .map(|node| node.id.clone())
+            .collect();
+        for node_id in &stale {
+            log::info!("Evicting node {}, offline for more than {:?}", node_id, idle_threshold);
+            self.remove(node_id);
+        }
+        
+        stale
+    }
+    
+    /// 当前在线的节点数，离线节点保留注册但不占用名额
+    pub fn peer_count(&self) -> usize {
+        self.online.len()
+    }
+    
+    /// 节点数上限，`None`表示不限制
+    pub fn max_peers(&self) -> Option<u32> {
+        self.capacity.max_peers()
+    }
+    
+    /// 判断能否再接纳新节点
+    pub fn can_accept(&self) -> bool {
+        self.capacity.can_accept(self.peer_count())
+    }
+    
+    /// 设置节点优先级，节点尚未注册时在其注册后生效
+    pub fn set_priority(&mut self, node_id: &str, priority: u8) {
+        self.priorities.insert(node_id.to_string(), priority);
+        if let Some(node) = self.nodes.get_mut(node_id) {
+            node.priority = priority;
+            self.publish(node_id);
+        }
+    }
+    
+    /// 设置节点标签，替换原有的全部标签，节点尚未注册时在其注册后生效
+    ///
+    /// 设置了标签存储时同时写入其中，写入失败时内存中的标签已经更新。
+    pub fn set_tags(&mut self, node_id: &str, tags: HashMap<String, String>) -> Result<(), NodeError> {
+        self.apply_tags(node_id, tags.clone());
+        match &self.tag_store {
+            Some(store) => store.put(node_id, &tags),
+            None => Ok(()),
+        }
+    }
+    
+    /// 更新节点标签和标签索引
+    fn apply_tags(&mut self, node_id: &str, tags: HashMap<String, String>) {
+        if let Some(old_tags) = self.tags.remove(node_id) {
+            for entry in old_tags {
+                if let Some(ids) = self.tag_index.get_mut(&entry) {
+                    ids.remove(node_id);
+                    if ids.is_empty() {
+                        self.tag_index.remove(&entry);
+                    }
+                }
+            }
+        }
+        for (key, value) in &tags {
+            self.tag_index.entry((key.clone(), value.clone()))
+                .or_default()
+                .insert(node_id.to_string());
+        }
+        
+        if let Some(node) = self.nodes.get_mut(node_id) {
+            node.tags = tags.clone();
+            self.publish(node_id);
+        }
+        if !tags.is_empty() {
+            self.tags.insert(node_id.to_string(), tags);
+        }
+    }
+    
+    /// 查找设置了指定标签的已注册节点
+    ///
+    /// 使用标签索引，不遍历节点表。
+    pub fn find_by_tag(&self, key: &str, value: &str) -> Vec<Node> {
+        self.tag_index.get(&(key.to_string(), value.to_string()))
+            .into_iter()
+            .flatten()
+            .filter_map(|node_id| self.nodes.get(node_id))
+            .cloned()
+            .collect()
+    }
+    
+    /// 查找同时设置了所有指定标签的已注册节点，`filter`为空时返回所有节点
+    pub fn find_by_tags(&self, filter: &HashMap<String, String>) -> Vec<Node> {
+        let mut entries = filter.iter();
+        let Some((key, value)) = entries.next() else {
+            return self.list();
+        };
+        let mut nodes = self.find_by_tag(key, value);
+        for (key, value) in entries {
+            nodes.retain(|node| node.tags.get(key) == Some(value));
+        }
+        nodes
+    }
+    
+    /// 获取节点
+    pub fn get(&self, node_id: &str) -> Option<&Node> {
+        self.nodes.get(node_id)
+    }
+    
+    /// 获取所有节点
+    pub fn list(&self) -> Vec<Node> {
+        self.nodes.values().cloned().collect()
+    }
+    
+    /// 记录节点是否声明可以中继
+    pub fn set_relay_capable(&mut self, node_id: &str, capable: bool) {
+        if capable && self.nodes.contains_key(node_id) {
+            self.relay_nodes.insert(node_id.to_string());
+        } else {
+            self.relay_nodes.remove(node_id);
+        }
+    }
+    
+    /// 声明可以中继的已注册节点，按节点ID排序
+    pub fn relay_nodes(&self) -> Vec<Node> {
+        let mut nodes: Vec<Node> = self.relay_nodes.iter()
+            .filter_map(|node_id| self.nodes.get(node_id))
+            .cloned()
+            .collect();
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        nodes
+    }
+    
+    /// 查询占用虚拟IP的节点ID
+    pub fn owner_of(&self, ip: Ipv4Addr) -> Option<&str> {
+        self.conflict_checker.owner_of(ip)
+    }
+    
+    /// 按虚拟IP查找已注册的节点
+    ///
+    /// 使用冲突检测维护的虚拟IP索引，不遍历节点表。索引与节点表在注册和注销时同步更新，
+    /// 为本节点保留的虚拟IP不对应已注册的节点，返回`None`。
+    pub fn find_by_virtual_ip(&self, ip: Ipv4Addr) -> Option<&Node> {
+        self.owner_of(ip)
+            .and_then(|node_id| self.nodes.get(node_id))
+            .filter(|node| node.virtual_ip == ip)
+    }
+    
+    /// 添加节点分组
+    pub fn add_group(&mut self, group: PeerGroup) -> Result<(), NodeError> {
+        if self.groups.contains_key(&group.group_id) {
+            return Err(NodeError::GroupExists(group.group_id));
+        }
+        if let Some(limiter) = &self.bandwidth_limiter {
+            limiter.set_group_limit(&group.group_id, group.bandwidth_bytes_per_sec());
+        }
+        self.groups.insert(group.group_id.clone(), group);
+        Ok(())
+    }
+    
+    /// 替换已有的节点分组，成员不变
+    pub fn update_group(&mut self, group: PeerGroup) -> Result<(), NodeError> {
+        let existing = self.groups.get_mut(&group.group_id)
+            .ok_or_else(|| NodeError::UnknownGroup(group.group_id.clone()))?;
+        if let Some(limiter) = &self.bandwidth_limiter {
+            limiter.set_group_limit(&group.group_id, group.bandwidth_bytes_per_sec());
+        }
+        *existing = group;
+        Ok(())
+    }
+    
+    /// 获取所有节点分组
+    pub fn groups(&self) -> Vec<PeerGroup> {
+        self.groups.values().cloned().collect()
+    }
+    
+    /// 设置分组成员，列表中的节点加入该组，原先在组内但不在列表中的节点移出
+    pub fn set_group_members(&mut self, group_id: &str, members: &[String]) -> Result<(), NodeError> {
+        if !self.groups.contains_key(group_id) {
+            return Err(NodeError::UnknownGroup(group_id.to_string()));
+        }
+        if let Some(missing) = members.iter().find(|id| !self.nodes.contains_key(*id)) {
+            return Err(NodeError::NotFound(missing.clone()));
+        }
+        
+        // 记录在分组中，节点注销后再次注册时仍加入本组
+        for group in self.groups.values_mut() {
+            if group.group_id == group_id {
+                group.members = members.to_vec();
+            } else {
+                group.members.retain(|member| !members.contains(member));
+            }
+        }
+        
+        let mut changed = Vec::new();
+        for node in self.nodes.values_mut() {
+            if members.contains(&node.id) {
+                node.group = Some(group_id.to_string());
+            } else if node.group.as_deref() == Some(group_id) {
+                node.group = None;
+            } else {
+                continue;
+            }
+            changed.push(node.id.clone());
+        }
+        for node_id in &changed {
+            let group = self.nodes.get(node_id).and_then(|node| node.group.as_deref());
+            self.sync_peer_group(node_id, group);
+            self.publish(node_id);
+        }
+        Ok(())
+    }
+    
+    /// 设置分组的带宽上限（Mbps），`None`表示取消限制
+    pub fn set_group_bandwidth(&mut self, group_id: &str, bandwidth_mbps: Option<u32>) -> Result<(), NodeError> {
+        let group = self.groups.get_mut(group_id)
+            .ok_or_else(|| NodeError::UnknownGroup(group_id.to_string()))?;
+        group.bandwidth_mbps = bandwidth_mbps;
+        if let Some(limiter) = &self.bandwidth_limiter {
+            limiter.set_group_limit(group_id, group.bandwidth_bytes_per_sec());
+        }
+        Ok(())
+    }
+    
+    /// 判断源节点能否向目标节点发送数据
+    ///
+    /// 目标节点不属于任何分组时不做限制；未注册的节点视为不属于任何分组。
+    pub fn can_forward(&self, source_id: &str, dest_id: &str) -> bool {
+        let dest_group = match self.nodes.get(dest_id).and_then(|node| node.group.as_ref()) {
+            Some(group) => group,
+            None => return true,
+        };
+        let group = match self.groups.get(dest_group) {
+            Some(group) => group,
+            None => return true,
+        };
+        
+        let source_group = self.nodes.get(source_id).and_then(|node| node.group.as_deref());
+        group.admits(source_id, source_group)
+    }
+    
+    /// 检查节点能否使用虚拟IP，地址被其他节点占用或预留时返回该节点ID
+    fn check_ip(&self, ip: Ipv4Addr, node_id: &str) -> Result<(), String> {
+        self.conflict_checker.check(ip, node_id)?;
+        match self.ip_pool.as_ref().and_then(|pool| pool.reserved_by(ip)) {
+            Some(owner) if owner != node_id => Err(owner.to_string()),
+            _ => Ok(()),
+        }
+    }
+    
+    fn allocate_from_pool(&mut self, node_id: &str) -> Result<Ipv4Addr, NodeError> {
+        let pool = self.ip_pool.as_mut().ok_or(NodeError::NoVirtualIp)?;
+        match pool.allocate(node_id, &self.conflict_checker) {
+            Ok(ip) => Ok(ip),
+            Err(IpPoolError::Exhausted) => Err(NodeError::NoVirtualIp),
+            Err(e) => Err(e.into()),
+        }
+    }
+    
+    fn release_ip(&mut self, ip: Ipv4Addr) {
+        self.conflict_checker.release(ip);
+        if let Some(pool) = self.ip_pool.as_mut() {
+            pool.release(ip);
+        }
+    }
+}
+
+/// 握手准入，在握手时向节点管理器注册节点
+pub struct NodeAdmission {
+    node_manager: Arc<Mutex<NodeManager>>,
+    key_directory: Option<Arc<PublicKeyDirectory>>,
+    strict_key_pinning: bool,
+}
+
+impl NodeAdmission {
+    /// 创建新的握手准入检查
+    pub fn new(node_manager: Arc<Mutex<NodeManager>>) -> Self {
+        Self {
+            node_manager,
+            key_directory: None,
+            strict_key_pinning: false,
+        }
+    }
+    
+    /// 握手时将节点公钥与公钥目录比对，`strict`为true时拒绝公钥不一致的节点
+    pub fn with_key_directory(mut self, key_directory: Arc<PublicKeyDirectory>, strict: bool) -> Self {
+        self.key_directory = Some(key_directory);
+        self.strict_key_pinning = strict;
+        self
+    }
+}
+
+impl HandshakeValidator for NodeAdmission {
+    fn validate<'a>(&'a self, req: &'a HandshakeRequest, addr: SocketAddr) -> BoxFuture<'a, HandshakeVerdict> {
+        Box::pin(async move {
+            if let Some(key_directory) = &self.key_directory {
+                match key_directory.verify(&req.node_id, &req.public_key) {
+                    Ok(KeyCheck::Mismatch) => {
+                        log::warn!("KEY_MISMATCH: node {} ({}) presented a public key different from the cached one",
+                                   req.node_id, addr);
+                        if self.strict_key_pinning {
+                            return HandshakeVerdict::Reject {
+                                status: status::KEY_MISMATCH,
+                                message: "Public key does not match the pinned key".to_string(),
+                                conflict: None,
+                            };
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => log::warn!("Failed to verify public key of {}: {}", req.node_id, e),
+                }
+            }
+            
+            let requested_ip = match req.virtual_ip.as_deref().map(str::parse::<Ipv4Addr>) {
+                Some(Ok(ip)) => Some(ip),
+                Some(Err(_)) => {
+                    return HandshakeVerdict::Reject {
+                        status: status::NO_VIRTUAL_IP,
+                        message: NodeError::InvalidVirtualIp(req.virtual_ip.clone().unwrap_or_default()).to_string(),
+                        conflict: None,
+                    };
+                }
+                None => None,
+            };
+            
+            // 节点此前连接的可能是其他服务端，沿用共享注册表中的虚拟IP和分组
+            let (registry, known) = {
+                let node_manager = self.node_manager.lock().await;
+                (node_manager.registry(), node_manager.get(&req.node_id).is_some())
+            };
+            let shared = match registry.filter(|_| !known) {
+                Some(registry) => registry.lookup(&req.node_id).await.unwrap_or_else(|e| {
+                    log::warn!("Failed to look up node {} in registry: {}", req.node_id, e);
+                    None
+                }),
+                None => None,
+            };
+            let requested_ip = requested_ip.or(shared.as_ref().map(|node| node.virtual_ip));
+            let pinned_name = shared.as_ref().filter(|node| node.name_pinned).map(|node| node.name.clone());
+            // 分组由管理员指定，不采用节点在握手中自称的分组
+            if let Some(group_id) = &req.group_id {
+                log::debug!("Ignoring group {} requested by node {}", group_id, req.node_id);
+            }
+            let group_id = shared.and_then(|node| node.group);
+            
+            let result = {
+                let mut node_manager = self.node_manager.lock().await;
+                if let Some(name) = &pinned_name {
+                    node_manager.restore_pinned_name(&req.node_id, name);
+                }
+                let result = node_manager.register(
+                    &req.node_id,
+                    &req.node_name,
+                    addr,
+                    req.public_key.clone(),
+                    requested_ip,
+                    group_id.as_deref()
+                );
+                if result.is_ok() {
+                    let capabilities = Capabilities::from_bits(req.capabilities);
+                    node_manager.set_relay_capable(&req.node_id, capabilities.contains(Capabilities::CAN_RELAY));
+                }
+                result
+            };
+            
+            match result {
+                Ok(virtual_ip) => HandshakeVerdict::Accept { virtual_ip },
+                Err(NodeError::VirtualIpConflict { ip, node_id }) => HandshakeVerdict::Reject {
+                    status: status::VIRTUAL_IP_CONFLICT,
+                    message: format!("Virtual IP {} is already in use", ip),
+                    conflict: Some(ConflictDetails { conflicting_node_id: node_id }),
+                },
+                Err(e @ NodeError::NameTaken(_)) => HandshakeVerdict::Reject {
+                    status: status::NAME_CONFLICT,
+                    message: e.to_string(),
+                    conflict: None,
+                },
+                Err(e @ NodeError::UnknownGroup(_)) => HandshakeVerdict::Reject {
+                    status: status::UNKNOWN_GROUP,
+                    message: e.to_string(),
+                    conflict: None,
+                },
+                Err(e @ NodeError::CapacityExceeded) => HandshakeVerdict::Reject {
+                    status: status::CAPACITY_EXCEEDED,
+                    message: e.to_string(),
+                    conflict: None,
+                },
+                Err(e) => HandshakeVerdict::Reject {
+                    status: status::NO_VIRTUAL_IP,
+                    message: e.to_string(),
+                    conflict: None,
+                },
+            }
+        })
+    }
+}
+
+/// 按节点分组过滤中继的数据转发
+pub struct GroupPolicy {
+    node_manager: Arc<Mutex<NodeManager>>,
+}
+
+impl GroupPolicy {
+    /// 创建新的分组过滤
+    pub fn new(node_manager: Arc<Mutex<NodeManager>>) -> Self {
+        Self { node_manager }
+    }
+}
+
+impl ForwardFilter for GroupPolicy {
+    fn allow<'a>(&'a self, source_node: &'a str, dest_node: &'a str) -> BoxFuture<'a, bool> {
+        Box::pin(async move {
+            self.node_manager.lock().await.can_forward(source_node, dest_node)
+        })
+    }
+}
+
+/// 跟踪节点在线状态，并关闭满员时被替换的节点的会话，直到事件源关闭
+pub async fn run_capacity_tracker(
+    node_manager: Arc<Mutex<NodeManager>>,
+    network_manager: Arc<Mutex<NetworkManager>>,
+    mut events: broadcast::Receiver<PeerEvent>
+) {
+    let mut bumped = node_manager.lock().await.subscribe_bumped();
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(PeerEvent::PeerDisconnected { node_id, .. }) => node_manager.lock().await.set_offline(&node_id),
+                Ok(PeerEvent::PeerConnected { node_id, .. }) => {
+                    // 重新握手的节点记录由握手重建，标签以节点管理器中的为准
+                    let tags = node_manager.lock().await.get(&node_id).map(|node| node.tags.clone()).unwrap_or_default();
+                    if !tags.is_empty() {
+                        if let Err(e) = network_manager.lock().await.set_peer_tags(&node_id, tags).await {
+                            log::debug!("Failed to restore tags of node {}: {}", node_id, e);
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Capacity tracker lagged behind, {} events skipped", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            Ok(node_id) = bumped.recv() => {
+                let network_manager = network_manager.lock().await;
+                if let Err(e) = network_manager.close_connection(&node_id, "Replaced by a higher priority node").await {
+                    log::debug!("Failed to notify bumped node {}: {}", node_id, e);
+                }
+                network_manager.remove_peer(&node_id).await;
+            }
+        }
+    }
+}
+
+/// 按天数计算离线阈值，天数过大溢出时返回`None`
+pub fn idle_threshold_from_days(days: u64) -> Option<Duration> {
+    days.checked_mul(24 * 60 * 60).map(Duration::from_secs)
+}
+
+/// 清理长期离线的节点，同时从网络层移除，返回被注销的节点ID
+pub async fn evict_idle(
+    node_manager: &Mutex<NodeManager>,
+    network_manager: &Mutex<NetworkManager>,
+    idle_threshold: Duration
+) -> Vec<String> {
+    let network_manager = network_manager.lock().await;
+    let peers = network_manager.get_peers().await;
+    let evicted = node_manager.lock().await.evict_idle_nodes(idle_threshold, &peers);
+    for node_id in &evicted {
+        network_manager.remove_peer(node_id).await;
+    }
+    evicted
+}
+
+/// 按cron表达式定期清理长期离线的节点
+///
+/// 返回后台任务的句柄，服务端关闭时调用`abort`停止。
+pub fn spawn_idle_eviction(
+    node_manager: Arc<Mutex<NodeManager>>,
+    network_manager: Arc<Mutex<NetworkManager>>,
+    schedule: cron::Schedule,
+    idle_threshold: Duration
+) -> JoinHandle<()> {
+    tokio::spawn(run_idle_eviction(node_manager, network_manager, schedule, idle_threshold))
+}
+
+/// 按cron表达式定期清理长期离线的节点，表达式没有下一次执行时间时返回
+pub async fn run_idle_eviction(
+    node_manager: Arc<Mutex<NodeManager>>,
+    network_manager: Arc<Mutex<NetworkManager>>,
+    schedule: cron::Schedule,
+    idle_threshold: Duration
+) {
+    while let Some(next) = schedule.upcoming(chrono::Local).next() {
+        let delay = (next - chrono::Local::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(delay).await;
+        
+        let evicted = evict_idle(&node_manager, &network_manager, idle_threshold).await;
+        if !evicted.is_empty() {
+            log::info!("Evicted {} idle nodes", evicted.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    
+    fn node_ip(i: u32) -> Ipv4Addr {
+        Ipv4Addr::new(10, 1, (i / 250) as u8, (i % 250 + 1) as u8)
+    }
+    
+    fn manager_with_nodes(count: u32) -> NodeManager {
+        let mut manager = NodeManager::new(config::default_config().node).unwrap();
+        for i in 0..count {
+            let node_id = format!("node-{}", i);
+            let address = SocketAddr::new([192, 0, 2, 1].into(), 10_000 + i as u16);
+            manager.register(&node_id, &node_id, address, vec![i as u8; 32], Some(node_ip(i)), None).unwrap();
+        }
+        manager
+    }
+    
+    fn linear_scan(manager: &NodeManager, ip: Ipv4Addr) -> Option<&Node> {
+        manager.nodes.values().find(|node| node.virtual_ip == ip)
+    }
+    
+    #[test]
+    fn find_by_virtual_ip_follows_registration_changes() {
+        let mut manager = manager_with_nodes(1000);
+        for i in 0..1000 {
+            let node = manager.find_by_virtual_ip(node_ip(i)).unwrap();
+            assert_eq!(node.id, format!("node-{}", i));
+            assert_eq!(linear_scan(&manager, node_ip(i)).unwrap().id, node.id);
+        }
+        
+        // 节点更换虚拟IP后旧地址不再指向它
+        let address = manager.get("node-7").unwrap().address;
+        let moved = Ipv4Addr::new(10, 2, 0, 7);
+        manager.register("node-7", "node-7", address, vec![7u8; 32], Some(moved), None).unwrap();
+        assert!(manager.find_by_virtual_ip(node_ip(7)).is_none());
+        assert_eq!(manager.find_by_virtual_ip(moved).unwrap().id, "node-7");
+        
+        manager.remove("node-7");
+        assert!(manager.find_by_virtual_ip(moved).is_none());
+        assert!(linear_scan(&manager, moved).is_none());
+    }
+    
+    #[test]
+    fn find_by_virtual_ip_is_faster_than_a_linear_scan() {
+        let manager = manager_with_nodes(1000);
+        
+        let start = Instant::now();
+        for i in 0..1000 {
+            assert!(manager.find_by_virtual_ip(node_ip(i)).is_some());
+        }
+        let indexed = start.elapsed();
+        
+        let start = Instant::now();
+        for i in 0..1000 {
+            assert!(linear_scan(&manager, node_ip(i)).is_some());
+        }
+        let scanned = start.elapsed();
+        
+        println!("1000 lookups over 1000 nodes: index {:?}, linear scan {:?}", indexed, scanned);
+        assert!(indexed < scanned);
+    }
+    
+    fn peer(manager: &NodeManager, node_id: &str, status: NodeStatus, last_seen: u64) -> Peer {
+        let node = manager.get(node_id).unwrap();
+        let mut peer = Peer::new(
+            node.id.clone(),
+            node.name.clone(),
+            node.address,
+            node.virtual_ip.to_string(),
+            node.public_key.clone(),
+            0
+        );
+        peer.status = status;
+        peer.last_seen = last_seen;
+        peer
+    }
+    
+    #[test]
+    fn online_nodes_are_not_evicted_even_if_old() {
+        let mut manager = manager_with_nodes(3);
+        for node in manager.nodes.values_mut() {
+            node.last_seen = 0;
+        }
+        let now = current_unix_timestamp();
+        let peers = vec![
+            peer(&manager, "node-0", NodeStatus::Online, 0),
+            // 网络层最近见过的离线节点按其时间计算
+            peer(&manager, "node-1", NodeStatus::Offline, now),
+            peer(&manager, "node-2", NodeStatus::Offline, 0),
+        ];
+        
+        let evicted = manager.evict_idle_nodes(idle_threshold_from_days(30).unwrap(), &peers);
+        assert_eq!(evicted, vec!["node-2".to_string()]);
+        assert!(manager.get("node-0").is_some());
+        assert!(manager.get("node-1").is_some());
+        assert!(manager.get("node-2").is_none());
+        assert!(manager.find_by_virtual_ip(node_ip(2)).is_none());
+    }
+    
+    #[test]
+    fn idle_threshold_rejects_overflowing_days() {
+        assert_eq!(idle_threshold_from_days(30), Some(Duration::from_secs(30 * 86400)));
+        assert_eq!(idle_threshold_from_days(u64::MAX / 86400 + 1), None);
+        assert_eq!(idle_threshold_from_days(u64::MAX), None);
+    }
+    
+    fn tag_store_path() -> String {
+        std::env::temp_dir()
+            .join(format!("vpnet-tags-test-{}-{}", std::process::id(), rand::random::<u64>()))
+            .to_string_lossy()
+            .into_owned()
+    }
+    
+    fn tags(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+    
+    #[test]
+    fn tags_survive_a_restart() {
+        let path = tag_store_path();
+        {
+            let mut manager = manager_with_nodes(3);
+            manager.set_tag_store(TagStore::open(&path).unwrap()).unwrap();
+            manager.set_tags("node-0", tags(&[("site", "berlin"), ("role", "edge")])).unwrap();
+            manager.set_tags("node-1", tags(&[("site", "paris")])).unwrap();
+            manager.set_tags("node-2", tags(&[("site", "rome")])).unwrap();
+            manager.set_tags("node-2", HashMap::new()).unwrap();
+        }
+        
+        let mut manager = manager_with_nodes(3);
+        manager.set_tag_store(TagStore::open(&path).unwrap()).unwrap();
+        assert_eq!(manager.get("node-0").unwrap().tags, tags(&[("site", "berlin"), ("role", "edge")]));
+        assert_eq!(manager.get("node-1").unwrap().tags, tags(&[("site", "paris")]));
+        assert!(manager.get("node-2").unwrap().tags.is_empty());
+        assert_eq!(manager.find_by_tag("site", "berlin").len(), 1);
+        
+        let _ = std::fs::remove_dir_all(&path);
+    }
+    
+    #[test]
+    fn tag_filter_selects_matching_nodes() {
+        let mut manager = manager_with_nodes(4);
+        manager.set_tags("node-0", tags(&[("site", "berlin"), ("role", "edge")])).unwrap();
+        manager.set_tags("node-1", tags(&[("site", "berlin"), ("role", "core")])).unwrap();
+        manager.set_tags("node-2", tags(&[("site", "paris"), ("role", "edge")])).unwrap();
+        
+        let ids = |nodes: Vec<Node>| {
+            let mut ids: Vec<_> = nodes.into_iter().map(|node| node.id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(manager.find_by_tags(&HashMap::new())), ["node-0", "node-1", "node-2", "node-3"]);
+        assert_eq!(ids(manager.find_by_tags(&tags(&[("site", "berlin")]))), ["node-0", "node-1"]);
+        assert_eq!(ids(manager.find_by_tags(&tags(&[("site", "berlin"), ("role", "edge")]))), ["node-0"]);
+        assert!(manager.find_by_tags(&tags(&[("site", "rome")])).is_empty());
+        
+        manager.set_tags("node-0", tags(&[("site", "paris")])).unwrap();
+        assert_eq!(ids(manager.find_by_tags(&tags(&[("site", "berlin")]))), ["node-1"]);
+        assert_eq!(ids(manager.find_by_tags(&tags(&[("site", "paris")]))), ["node-0", "node-2"]);
+    }
+    
+    /// 等待后台任务完成注册表写入
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    
+    #[tokio::test]
+    async fn eviction_keeps_registry_records_owned_by_another_server() {
+        let mut manager = manager_with_nodes(2);
+        manager.set_registry(Box::new(crate::registry::LocalPeerRegistry::new()));
+        let registry = manager.registry().unwrap();
+        settle().await;
+        assert_eq!(registry.lookup("node-0").await.unwrap().unwrap().server_id, manager.config.id);
+        
+        // node-0重新连接到了另一台服务端
+        let mut moved = manager.get("node-0").unwrap().clone();
+        moved.server_id = "server-b".to_string();
+        registry.register(&moved).await.unwrap();
+        
+        for node in manager.nodes.values_mut() {
+            node.last_seen = 0;
+        }
+        let evicted = manager.evict_idle_nodes(Duration::from_secs(60), &[]);
+        assert_eq!(evicted.len(), 2);
+        settle().await;
+        
+        assert_eq!(registry.lookup("node-0").await.unwrap().unwrap().server_id, "server-b");
+        assert!(registry.lookup("node-1").await.unwrap().is_none());
+    }
+    
+    fn pool_manager() -> NodeManager {
+        let mut config = config::default_config().node;
+        config.ip_pool = Some("10.0.0.0/29".to_string());
+        let mut manager = NodeManager::new(config).unwrap();
+        manager.set_server_ip(Ipv4Addr::new(10, 0, 0, 1));
+        manager
+    }
+    
+    #[test]
+    fn server_ip_cannot_be_reserved_or_allocated() {
+        let mut manager = pool_manager();
+        let result = manager.reserve_pool_ip("gateway-01", Ipv4Addr::new(10, 0, 0, 1));
+        assert!(matches!(result, Err(NodeError::IpPool(IpPoolError::ServerAddress(_)))));
+        
+        let pool = manager.ip_pool.as_mut().unwrap();
+        let ip = pool.allocate("node-1", &VirtualIpConflictChecker::default()).unwrap();
+        assert_eq!(ip, Ipv4Addr::new(10, 0, 0, 2));
+        
+        let summary = manager.ip_pool_summary().unwrap();
+        assert_eq!(summary.free, vec![IpRange { start: Ipv4Addr::new(10, 0, 0, 3), end: Ipv4Addr::new(10, 0, 0, 6) }]);
+    }
+    
+    #[test]
+    fn reservations_detect_conflicts_and_are_returned_to_their_node() {
+        let mut manager = pool_manager();
+        let reserved = Ipv4Addr::new(10, 0, 0, 5);
+        manager.reserve_pool_ip("gateway-01", reserved).unwrap();
+        
+        let result = manager.reserve_pool_ip("gateway-02", reserved);
+        assert!(matches!(result, Err(NodeError::IpPool(IpPoolError::Reserved { node_id, .. })) if node_id == "gateway-01"));
+        let result = manager.reserve_pool_ip("gateway-02", Ipv4Addr::new(10, 0, 0, 7));
+        assert!(matches!(result, Err(NodeError::IpPool(IpPoolError::OutOfRange(_)))));
+        
+        // 其他节点分配时跳过预留地址，预留的节点总是得到预留地址
+        let checker = VirtualIpConflictChecker::default();
+        let pool = manager.ip_pool.as_mut().unwrap();
+        let allocated: Vec<Ipv4Addr> = (0..4).map(|i| pool.allocate(&format!("node-{}", i), &checker).unwrap()).collect();
+        assert!(!allocated.contains(&reserved));
+        assert!(matches!(pool.allocate("node-4", &checker), Err(IpPoolError::Exhausted)));
+        assert_eq!(pool.allocate("gateway-01", &checker).unwrap(), reserved);
+        
+        // 预留地址已被其他节点占用时不分配
+        let mut checker = VirtualIpConflictChecker::default();
+        checker.claim(reserved, "node-9");
+        assert!(matches!(pool.allocate("gateway-01", &checker), Err(IpPoolError::Reserved { node_id, .. }) if node_id == "node-9"));
+        
+        // 重新预留替换节点原来的地址
+        manager.reserve_pool_ip("gateway-01", Ipv4Addr::new(10, 0, 0, 6)).unwrap();
+        let pool = manager.ip_pool.as_ref().unwrap();
+        assert_eq!(pool.reservation_for("gateway-01"), Some(Ipv4Addr::new(10, 0, 0, 6)));
+        assert_eq!(pool.reserved_by(reserved), None);
+    }
+    
+    fn key_directory() -> PublicKeyDirectory {
+        let path = std::env::temp_dir()
+            .join(format!("vpnet-keys-test-{}-{}", std::process::id(), rand::random::<u64>()));
+        PublicKeyDirectory::open(&path.to_string_lossy()).unwrap()
+    }
+    
+    fn node_import(node_id: &str, public_key: Vec<u8>) -> NodeImport {
+        NodeImport {
+            id: node_id.to_string(),
+            virtual_ip: Some(Ipv4Addr::new(10, 0, 0, 5)),
+            public_key,
+            priority: 3,
+            tags: tags(&[("site", "berlin")]),
+            ..NodeImport::default()
+        }
+    }
+    
+    #[tokio::test]
+    async fn imported_nodes_stay_offline_until_their_first_handshake() {
+        let mut manager = pool_manager();
+        manager.set_registry(Box::new(crate::registry::LocalPeerRegistry::new()));
+        let registry = manager.registry().unwrap();
+        let keys = key_directory();
+        
+        let node = manager.import(&keys, node_import("branch-01", vec![5u8; 32])).unwrap();
+        assert_eq!(node.virtual_ip, Ipv4Addr::new(10, 0, 0, 5));
+        assert_eq!(node.priority, 3);
+        assert_eq!(node.tags, tags(&[("site", "berlin")]));
+        assert_eq!(keys.get("branch-01").unwrap().unwrap().public_key, vec![5u8; 32]);
+        assert_eq!(manager.peer_count(), 0);
+        settle().await;
+        assert!(registry.lookup("branch-01").await.unwrap().is_none());
+        
+        manager.nodes.get_mut("branch-01").unwrap().last_seen = 0;
+        assert!(manager.evict_idle_nodes(Duration::from_secs(60), &[]).is_empty());
+        assert!(manager.get("branch-01").is_some());
+        
+        // 首次握手后节点上线并写入注册表，之后按普通节点清理
+        let address = SocketAddr::new([192, 0, 2, 5].into(), 51820);
+        manager.register("branch-01", "", address, vec![5u8; 32], None, None).unwrap();
+        assert_eq!(manager.peer_count(), 1);
+        settle().await;
+        assert_eq!(registry.lookup("branch-01").await.unwrap().unwrap().address, address);
+        
+        manager.set_offline("branch-01");
+        manager.nodes.get_mut("branch-01").unwrap().last_seen = 0;
+        assert_eq!(manager.evict_idle_nodes(Duration::from_secs(60), &[]), ["branch-01"]);
+    }
+    
+    #[test]
+    fn import_rejects_existing_nodes_and_pinned_key_mismatches() {
+        let mut manager = pool_manager();
+        let keys = key_directory();
+        keys.verify("branch-01", &[1u8; 32]).unwrap();
+        
+        let result = manager.import(&keys, node_import("branch-01", vec![5u8; 32]));
+        assert!(matches!(result, Err(NodeError::KeyMismatch(_))));
+        assert!(manager.get("branch-01").is_none());
+        
+        manager.import(&keys, node_import("branch-01", vec![1u8; 32])).unwrap();
+        let result = manager.import(&keys, node_import("branch-01", vec![1u8; 32]));
+        assert!(matches!(result, Err(NodeError::Exists(_))));
+    }
+}
@@ -0,0 +1,246 @@
+/*!
+VPNet Server 节点注册表模块
+
+保存已注册节点的记录，供节点重新连接时查询，包括：
+- 节点注册表接口
+- 基于内存的本地注册表
+- 基于Redis的共享注册表，多个服务端实例共用同一份节点记录
+*/
+
+use std::collections::HashMap;
+use thiserror::Error;
+use tokio::sync::RwLock;
+use vpnet::BoxFuture;
+use crate::node::Node;
+
+/// 注册表错误
+#[derive(Error, Debug)]
+pub enum RegistryError {
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    
+    #[cfg(feature = "redis-registry")]
+    #[error("Redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+}
+
+/// 节点注册表
+///
+/// 节点管理器在节点注册和注销时同步记录到注册表，节点连接到本服务端之前的记录可以从注册表中查询。
+pub trait PeerRegistry: Send + Sync {
+    /// 保存节点记录，已存在时覆盖
+    fn register<'a>(&'a self, node: &'a Node) -> BoxFuture<'a, Result<(), RegistryError>>;
+    
+    /// 删除`server_id`所属的节点记录，记录已属于其他服务端时保留，返回是否删除了记录
+    fn deregister<'a>(&'a self, node_id: &'a str, server_id: &'a str) -> BoxFuture<'a, Result<bool, RegistryError>>;
+    
+    /// 查询节点记录
+    fn lookup<'a>(&'a self, node_id: &'a str) -> BoxFuture<'a, Result<Option<Node>, RegistryError>>;
+    
+    /// 所有节点记录，按最后在线时间从近到远排列
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Node>, RegistryError>>;
+}
+
+/// 判断节点记录是否属于`server_id`，未记录所属服务端的旧记录视为属于任何服务端
+fn owned_by(node: &Node, server_id: &str) -> bool {
+    node.server_id.is_empty() || node.server_id == server_id
+}
+
+/// 保存在本进程内存中的注册表，只适用于单个服务端
+#[derive(Debug, Default)]
+pub struct LocalPeerRegistry {
+    nodes: RwLock<HashMap<String, Node>>,
+}
+
+impl LocalPeerRegistry {
+    /// 创建空的本地注册表
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl PeerRegistry for LocalPeerRegistry {
+    fn register<'a>(&'a self, node: &'a Node) -> BoxFuture<'a, Result<(), RegistryError>> {
+        Box::pin(async move {
+            self.nodes.write().await.insert(node.id.clone(), node.clone());
+            Ok(())
+        })
+    }
+    
+    fn deregister<'a>(&'a self, node_id: &'a str, server_id: &'a str) -> BoxFuture<'a, Result<bool, RegistryError>> {
+        Box::pin(async move {
+            let mut nodes = self.nodes.write().await;
+            if !nodes.get(node_id).is_some_and(|node| owned_by(node, server_id)) {
+                return Ok(false);
+            }
+            nodes.remove(node_id);
+            Ok(true)
+        })
+    }
+    
+    fn lookup<'a>(&'a self, node_id: &'a str) -> BoxFuture<'a, Result<Option<Node>, RegistryError>> {
+        Box::pin(async move {
+            Ok(self.nodes.read().await.get(node_id).cloned())
+        })
+    }
+    
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Node>, RegistryError>> {
+        Box::pin(async move {
+            let mut nodes: Vec<Node> = self.nodes.read().await.values().cloned().collect();
+            nodes.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+            Ok(nodes)
+        })
+    }
+}
+
+/// 保存在Redis中的注册表，多个服务端实例共用
+///
+/// 节点记录以JSON保存在哈希`<前缀>:nodes`中，同时按最后在线时间记入有序集合`<前缀>:nodes:last_seen`。
+/// 需要以`redis-registry`特性编译。
+#[cfg(feature = "redis-registry")]
+#[derive(Clone)]
+pub struct RedisPeerRegistry {
+    connection: redis::aio::ConnectionManager,
+    nodes_key: String,
+    last_seen_key: String,
+}
+
+#[cfg(feature = "redis-registry")]
+impl RedisPeerRegistry {
+    /// 连接到Redis，`key_prefix`用于区分共用同一Redis的不同网络
+    pub async fn connect(url: &str, key_prefix: &str) -> Result<Self, RegistryError> {
+        let client = redis::Client::open(url)?;
+        let connection = client.get_connection_manager().await?;
+        Ok(Self {
+            connection,
+            nodes_key: format!("{}:nodes", key_prefix),
+            last_seen_key: format!("{}:nodes:last_seen", key_prefix),
+        })
+    }
+}
+
+#[cfg(feature = "redis-registry")]
+impl PeerRegistry for RedisPeerRegistry {
+    fn register<'a>(&'a self, node: &'a Node) -> BoxFuture<'a, Result<(), RegistryError>> {
+        Box::pin(async move {
+            let record = serde_json::to_string(node)?;
+            let mut connection = self.connection.clone();
+            redis::pipe()
+                .atomic()
+                .hset(&self.nodes_key, &node.id, record).ignore()
+                .zadd(&self.last_seen_key, &node.id, node.last_seen).ignore()
+                .query_async::<_, ()>(&mut connection)
+                .await?;
+            Ok(())
+        })
+    }
+    
+    fn deregister<'a>(&'a self, node_id: &'a str, server_id: &'a str) -> BoxFuture<'a, Result<bool, RegistryError>> {
+        Box::pin(async move {
+            // 在Redis中比较所属服务端并删除，避免与其他服务端的注册交错
+            let script = redis::Script::new(r#"
+                local record = redis.call('HGET', KEYS[1], ARGV[1])
+                if not record then
+                    return 0
+                end
+                local owner = cjson.decode(record)['server_id']
+                if type(owner) == 'string' and owner ~= '' and owner ~= ARGV[2] then
+                    return 0
+                end
+                redis.call('HDEL', KEYS[1], ARGV[1])
+                redis.call('ZREM', KEYS[2], ARGV[1])
+                return 1
+            "#);
+            let mut connection = self.connection.clone();
+            let removed: i64 = script
+                .key(&self.nodes_key)
+                .key(&self.last_seen_key)
+                .arg(node_id)
+                .arg(server_id)
+                .invoke_async(&mut connection)
+                .await?;
+            Ok(removed == 1)
+        })
+    }
+    
+    fn lookup<'a>(&'a self, node_id: &'a str) -> BoxFuture<'a, Result<Option<Node>, RegistryError>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let record: Option<String> = redis::cmd("HGET")
+                .arg(&self.nodes_key)
+                .arg(node_id)
+                .query_async(&mut connection)
+                .await?;
+            Ok(record.map(|record| serde_json::from_str(&record)).transpose()?)
+        })
+    }
+    
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Node>, RegistryError>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let node_ids: Vec<String> = redis::cmd("ZREVRANGE")
+                .arg(&self.last_seen_key)
+                .arg(0)
+                .arg(-1)
+                .query_async(&mut connection)
+                .await?;
+            if node_ids.is_empty() {
+                return Ok(Vec::new());
+            }
+            
+            let records: Vec<Option<String>> = redis::cmd("HMGET")
+                .arg(&self.nodes_key)
+                .arg(&node_ids)
+                .query_async(&mut connection)
+                .await?;
+            // 两次查询之间被注销的节点没有记录，跳过
+            records.into_iter()
+                .flatten()
+                .map(|record| serde_json::from_str(&record).map_err(RegistryError::from))
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    
+    fn node(id: &str, server_id: &str) -> Node {
+        Node {
+            id: id.to_string(),
+            name: id.to_string(),
+            address: SocketAddr::from(([192, 0, 2, 1], 51820)),
+            virtual_ip: [10, 0, 0, 2].into(),
+            public_key: vec![1; 32],
+            registered_at: 0,
+            last_seen: 0,
+            group: None,
+            priority: 0,
+            tags: HashMap::new(),
+            name_pinned: false,
+            server_id: server_id.to_string(),
+        }
+    }
+    
+    #[tokio::test]
+    async fn only_the_owning_server_deregisters_a_node() {
+        let registry = LocalPeerRegistry::new();
+        registry.register(&node("alpha", "server-b")).await.unwrap();
+        
+        assert!(!registry.deregister("alpha", "server-a").await.unwrap());
+        assert!(registry.lookup("alpha").await.unwrap().is_some());
+        
+        assert!(registry.deregister("alpha", "server-b").await.unwrap());
+        assert!(registry.lookup("alpha").await.unwrap().is_none());
+        assert!(!registry.deregister("alpha", "server-b").await.unwrap());
+    }
+    
+    #[tokio::test]
+    async fn records_without_an_owner_can_be_deregistered_by_any_server() {
+        let registry = LocalPeerRegistry::new();
+        registry.register(&node("alpha", "")).await.unwrap();
+        assert!(registry.deregister("alpha", "server-a").await.unwrap());
+    }
+}
@@ -0,0 +1,539 @@
+/*!
+VPNet Server 只读副本模块
+
+高可用部署中副本不接受节点连接，只观察主服务端的状态，包括：
+- 定期拉取主服务端的节点、策略路由和运行统计
+- 以只读API提供缓存的数据，响应头`X-Data-Age-Seconds`标明数据已缓存的时间
+- 以同样的只读数据提供Web管理界面
+- 报告与主服务端的复制延迟
+- 主服务端连续不可用且多数副本确认后通知提升为主服务端
+- 主服务端发现副本已提升后停止运行，避免同时存在两个主服务端
+*/
+
+use axum::{Router, routing::get, extract::State, http::{HeaderName, HeaderValue, StatusCode}, response::{IntoResponse, Response}, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tower_http::{cors::CorsLayer, compression::CompressionLayer, services::ServeDir};
+use vpnet::utils::current_unix_timestamp;
+use crate::api::error_response;
+use crate::tasks::ShutdownSignal;
+
+/// 缓存数据已缓存时间（秒）的响应头
+pub const DATA_AGE_HEADER: HeaderName = HeaderName::from_static("x-data-age-seconds");
+
+/// 连续拉取失败该次数后判定主服务端不可用
+pub const PROMOTE_AFTER_FAILURES: u32 = 3;
+
+/// 拉取单个接口的超时时间
+const POLL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 主服务端查询副本复制状态的间隔
+pub const FENCING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 副本错误
+#[derive(Error, Debug)]
+pub enum ReplicaError {
+    #[error("Failed to poll primary: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Unexpected replication status from witness: {0}")]
+    InvalidStatus(String),
+    #[error("Replica {0} has been promoted to primary, stepping down")]
+    Fenced(String),
+}
+
+/// 从主服务端拉取的数据
+#[derive(Debug, Default)]
+struct ReplicaData {
+    nodes: Option<Value>,
+    routes: Option<Value>,
+    stats: Option<Value>,
+    /// 最近一次成功拉取的时刻
+    synced_at: Option<Instant>,
+    /// 最近一次成功拉取的时间（Unix秒）
+    last_sync: Option<u64>,
+    consecutive_failures: u32,
+    last_error: Option<String>,
+}
+
+/// 复制状态
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicationStatus {
+    /// `primary`或`replica`
+    pub mode: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary_api: Option<String>,
+    /// 副本的提升优先级，多个副本同时确认主服务端不可用时由优先级最高的提升
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u32>,
+    /// 最近一次成功同步的时间（Unix秒）
+    pub last_sync: Option<u64>,
+    /// 距最近一次成功同步的秒数，尚未同步过时为空
+    pub lag_seconds: Option<u64>,
+    pub consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+impl ReplicationStatus {
+    /// 主服务端的复制状态
+    pub fn primary() -> Self {
+        Self {
+            mode: "primary",
+            primary_api: None,
+            priority: None,
+            last_sync: None,
+            lag_seconds: None,
+            consecutive_failures: 0,
+            last_error: None,
+        }
+    }
+}
+
+/// 副本缓存的只读视图
+#[derive(Clone)]
+pub struct ReplicaView {
+    primary_api: String,
+    priority: u32,
+    data: Arc<RwLock<ReplicaData>>,
+}
+
+impl ReplicaView {
+    /// 创建空的视图，`primary_api`为主服务端管理API地址，`priority`为本副本的提升优先级
+    pub fn new(primary_api: &str, priority: u32) -> Self {
+        Self {
+            primary_api: primary_api.trim_end_matches('/').to_string(),
+            priority,
+            data: Arc::new(RwLock::new(ReplicaData::default())),
+        }
+    }
+    
+    /// 距最近一次成功同步的时间，尚未同步过时为`None`
+    pub async fn data_age(&self) -> Option<Duration> {
+        self.data.read().await.synced_at.map(|synced_at| synced_at.elapsed())
+    }
+    
+    /// 当前的复制状态
+    pub async fn status(&self) -> ReplicationStatus {
+        let data = self.data.read().await;
+        ReplicationStatus {
+            mode: "replica",
+            primary_api: Some(self.primary_api.clone()),
+            priority: Some(self.priority),
+            last_sync: data.last_sync,
+            lag_seconds: data.synced_at.map(|synced_at| synced_at.elapsed().as_secs()),
+            consecutive_failures: data.consecutive_failures,
+            last_error: data.last_error.clone(),
+        }
+    }
+}
+
+/// 定期拉取主服务端状态
+pub struct ReplicaPoller {
+    client: reqwest::Client,
+    view: ReplicaView,
+    interval: Duration,
+    auto_promote: bool,
+    witnesses: Vec<String>,
+}
+
+impl ReplicaPoller {
+    /// 创建拉取任务，`auto_promote`时主服务端连续不可用后`run`返回
+    pub fn new(view: ReplicaView, interval: Duration, auto_promote: bool) -> Result<Self, ReplicaError> {
+        let client = reqwest::Client::builder()
+            .timeout(POLL_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            client,
+            view,
+            interval,
+            auto_promote,
+            witnesses: Vec::new(),
+        })
+    }
+    
+    /// 设置见证副本的管理API地址，自动提升前须有超过半数的副本（包括本副本）确认主服务端不可用
+    pub fn set_witnesses(&mut self, witnesses: Vec<String>) {
+        self.witnesses = witnesses.iter()
+            .map(|witness| witness.trim_end_matches('/').to_string())
+            .collect();
+    }
+    
+    /// 按间隔拉取主服务端状态
+    ///
+    /// 未启用自动提升时一直运行；启用时在连续`PROMOTE_AFTER_FAILURES`次拉取失败、
+    /// 且`confirm_promotion`确认后返回，由调用者将本服务端提升为主服务端。
+    /// 未获确认时继续拉取，每次失败后重新确认。
+    pub async fn run(self) {
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            ticker.tick().await;
+            
+            match self.poll().await {
+                Ok(()) => {
+                    let mut data = self.view.data.write().await;
+                    if data.consecutive_failures > 0 {
+                        log::info!("Primary {} reachable again", self.view.primary_api);
+                    }
+                    data.consecutive_failures = 0;
+                    data.last_error = None;
+                }
+                Err(e) => {
+                    let failures = {
+                        let mut data = self.view.data.write().await;
+                        data.consecutive_failures += 1;
+                        data.last_error = Some(e.to_string());
+                        data.consecutive_failures
+                    };
+                    log::warn!("Failed to poll primary {} ({} consecutive): {}", self.view.primary_api, failures, e);
+                    
+                    if self.auto_promote && failures >= PROMOTE_AFTER_FAILURES && self.confirm_promotion().await {
+                        log::warn!("Primary {} is down, promoting replica", self.view.primary_api);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+    
+    /// 拉取一次节点、策略路由和运行统计，全部成功时才更新缓存
+    async fn poll(&self) -> Result<(), ReplicaError> {
+        let (nodes, routes, stats) = tokio::try_join!(
+            self.fetch("nodes"),
+            self.fetch("routes/policy"),
+            self.fetch("stats")
+        )?;
+        
+        let mut data = self.view.data.write().await;
+        data.nodes = Some(nodes);
+        data.routes = Some(routes);
+        data.stats = Some(stats);
+        data.synced_at = Some(Instant::now());
+        data.last_sync = Some(current_unix_timestamp());
+        Ok(())
+    }
+    
+    /// 向见证副本确认是否可以提升
+    ///
+    /// 只有最近一次拉取也失败的副本才算确认主服务端不可用，本副本和确认的见证副本须超过
+    /// 全部副本的半数，因此与主服务端之间出现网络分区的少数副本不会提升。任一见证副本
+    /// 已经提升，或确认的见证副本中有优先级不低于本副本的，均放弃提升，避免多个副本同时提升。
+    async fn confirm_promotion(&self) -> bool {
+        let mut votes = 1;
+        for witness in &self.witnesses {
+            let status = match self.witness_status(witness).await {
+                Ok(status) => status,
+                Err(e) => {
+                    log::warn!("Witness {} unavailable: {}", witness, e);
+                    continue;
+                }
+            };
+            
+            if status.mode == "primary" {
+                log::warn!("Witness {} is already primary, not promoting", witness);
+                return false;
+            }
+            if status.consecutive_failures == 0 {
+                log::info!("Witness {} can still reach the primary", witness);
+                continue;
+            }
+            if status.priority >= self.view.priority {
+                log::info!("Witness {} has priority {} (own {}), leaving promotion to it", witness, status.priority, self.view.priority);
+                return false;
+            }
+            votes += 1;
+        }
+        
+        let replicas = self.witnesses.len() + 1;
+        if votes * 2 > replicas {
+            true
+        } else {
+            log::warn!("Only {} of {} replicas confirm the primary is down, not promoting", votes, replicas);
+            false
+        }
+    }
+    
+    async fn witness_status(&self, witness: &str) -> Result<WitnessStatus, ReplicaError> {
+        fetch_replication_status(&self.client, witness).await
+    }
+    
+    async fn fetch(&self, path: &str) -> Result<Value, ReplicaError> {
+        let url = format!("{}/api/{}", self.view.primary_api, path);
+        let value = self.client.get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(value)
+    }
+}
+
+/// 见证副本`/api/replication/status`中用于确认提升的字段
+#[derive(Debug, Deserialize)]
+struct WitnessStatus {
+    mode: String,
+    consecutive_failures: u32,
+    #[serde(default)]
+    priority: u32,
+}
+
+async fn fetch_replication_status(client: &reqwest::Client, api: &str) -> Result<WitnessStatus, ReplicaError> {
+    let url = format!("{}/api/replication/status", api);
+    let value: Value = client.get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    serde_json::from_value(value.clone()).map_err(|_| ReplicaError::InvalidStatus(value.to_string()))
+}
+
+/// 主服务端的隔离检查，按间隔查询各副本的复制状态
+///
+/// 发现任一副本已提升为主服务端时返回`ReplicaError::Fenced`，由调用者停止本服务端；
+/// 无法访问的副本忽略，下次继续查询。
+pub async fn watch_replicas(replicas: Vec<String>, interval: Duration) -> Result<(), ReplicaError> {
+    let client = reqwest::Client::builder()
+        .timeout(POLL_TIMEOUT)
+        .build()?;
+    let replicas: Vec<String> = replicas.iter()
+        .map(|replica| replica.trim_end_matches('/').to_string())
+        .collect();
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        
+        for replica in &replicas {
+            match fetch_replication_status(&client, replica).await {
+                Ok(status) if status.mode == "primary" => return Err(ReplicaError::Fenced(replica.clone())),
+                Ok(_) => {}
+                Err(e) => log::debug!("Failed to query replica {}: {}", replica, e),
+            }
+        }
+    }
+}
+
+/// 只读路由：节点、策略路由、运行统计的查询和复制状态，修改操作返回405
+fn replica_routes(view: ReplicaView) -> Router {
+    Router::new()
+        .route("/api/health", get(health_check))
+        .route("/api/ready", get(readiness_check))
+        .route("/api/nodes", get(get_nodes))
+        .route("/api/routes/policy", get(get_policy_routes))
+        .route("/api/stats", get(get_stats))
+        .route("/api/replication/status", get(get_replication_status))
+        .with_state(view)
+}
+
+/// 启动副本的只读API服务器
+///
+/// 只提供节点、策略路由、运行统计的查询和复制状态，修改操作返回405。
+pub async fn start_replica_api_server(
+    addr: SocketAddr,
+    view: ReplicaView,
+    enable_cors: bool,
+    shutdown: ShutdownSignal
+) -> Result<(), std::io::Error> {
+    let mut app = replica_routes(view);
+    
+    if enable_cors {
+        app = app.layer(CorsLayer::permissive());
+    }
+    
+    log::info!("Replica API server starting on {}", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app).with_graceful_shutdown(shutdown.wait()).await
+}
+
+/// 启动副本的Web管理界面
+///
+/// 提供`static_dir`中的静态文件，`/api`下为与副本API服务器相同的只读路由。
+pub async fn start_replica_web_server(
+    addr: SocketAddr,
+    view: ReplicaView,
+    static_dir: String,
+    enable_compression: bool,
+    shutdown: ShutdownSignal
+) -> Result<(), std::io::Error> {
+    let mut app = replica_routes(view)
+        .fallback_service(ServeDir::new(static_dir));
+    
+    if enable_compression {
+        app = app.layer(CompressionLayer::new());
+    }
+    
+    log::info!("Replica web server starting on {}", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app).with_graceful_shutdown(shutdown.wait()).await
+}
+
+/// 返回缓存的数据，附带`X-Data-Age-Seconds`，尚未同步过时返回503
+async fn cached(view: &ReplicaView, select: fn(&ReplicaData) -> Option<&Value>) -> Response {
+    let data = view.data.read().await;
+    match (select(&data), data.synced_at) {
+        (Some(value), Some(synced_at)) => {
+            let age = HeaderValue::from(synced_at.elapsed().as_secs());
+            ([(DATA_AGE_HEADER, age)], Json(value.clone())).into_response()
+        }
+        _ => error_response(StatusCode::SERVICE_UNAVAILABLE, "Replica has not synchronized with the primary yet")
+            .into_response(),
+    }
+}
+
+/// 健康检查，副本进程运行即为健康
+async fn health_check() -> Json<Value> {
+    Json(serde_json::json!({ "status": "healthy", "mode": "replica" }))
+}
+
+/// 就绪检查，首次同步完成后返回200
+async fn readiness_check(State(view): State<ReplicaView>) -> (StatusCode, Json<Value>) {
+    if view.data_age().await.is_some() {
+        (StatusCode::OK, Json(serde_json::json!({ "status": "ready" })))
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({ "status": "starting" })))
+    }
+}
+
+async fn get_nodes(State(view): State<ReplicaView>) -> Response {
+    cached(&view, |data| data.nodes.as_ref()).await
+}
+
+async fn get_policy_routes(State(view): State<ReplicaView>) -> Response {
+    cached(&view, |data| data.routes.as_ref()).await
+}
+
+async fn get_stats(State(view): State<ReplicaView>) -> Response {
+    cached(&view, |data| data.stats.as_ref()).await
+}
+
+/// 复制状态，包括距最近一次成功同步的秒数
+async fn get_replication_status(State(view): State<ReplicaView>) -> Json<ReplicationStatus> {
+    Json(view.status().await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// 一直返回固定复制状态的见证副本，返回其API地址
+    async fn witness(status: Value) -> String {
+        let app = Router::new()
+            .route("/api/replication/status", get(move || async move { Json(status) }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{}", addr)
+    }
+    
+    /// 已经关闭的端口，连接会被拒绝
+    async fn unreachable() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+    
+    fn replica_status(consecutive_failures: u32, priority: u32) -> Value {
+        serde_json::json!({ "mode": "replica", "consecutive_failures": consecutive_failures, "priority": priority })
+    }
+    
+    async fn poller(priority: u32, witnesses: Vec<String>) -> ReplicaPoller {
+        let view = ReplicaView::new(&unreachable().await, priority);
+        let mut poller = ReplicaPoller::new(view, Duration::from_millis(20), true).unwrap();
+        poller.set_witnesses(witnesses);
+        poller
+    }
+    
+    #[tokio::test]
+    async fn promotes_when_majority_confirms_primary_is_down() {
+        let witnesses = vec![witness(replica_status(3, 1)).await, unreachable().await];
+        assert!(poller(2, witnesses).await.confirm_promotion().await);
+    }
+    
+    #[tokio::test]
+    async fn partitioned_replica_does_not_promote() {
+        // 见证副本仍能访问主服务端，或见证副本本身不可达，本副本都只占少数
+        let witnesses = vec![witness(replica_status(0, 1)).await];
+        assert!(!poller(2, witnesses).await.confirm_promotion().await);
+        
+        let witnesses = vec![unreachable().await, unreachable().await];
+        assert!(!poller(2, witnesses).await.confirm_promotion().await);
+    }
+    
+    #[tokio::test]
+    async fn defers_to_promoted_or_higher_priority_witness() {
+        let witnesses = vec![witness(serde_json::json!({ "mode": "primary", "consecutive_failures": 0 })).await];
+        assert!(!poller(2, witnesses).await.confirm_promotion().await);
+        
+        let witnesses = vec![witness(replica_status(3, 5)).await];
+        assert!(!poller(2, witnesses).await.confirm_promotion().await);
+        
+        // 优先级相同时双方都放弃，不会同时提升
+        let witnesses = vec![witness(replica_status(3, 2)).await];
+        assert!(!poller(2, witnesses).await.confirm_promotion().await);
+    }
+    
+    #[tokio::test]
+    async fn run_returns_only_after_promotion_is_confirmed() {
+        let refused = poller(2, vec![witness(replica_status(0, 1)).await]).await;
+        assert!(tokio::time::timeout(Duration::from_millis(300), refused.run()).await.is_err());
+        
+        let confirmed = poller(2, vec![witness(replica_status(3, 1)).await]).await;
+        let view = confirmed.view.clone();
+        tokio::time::timeout(Duration::from_secs(5), confirmed.run()).await.unwrap();
+        assert!(view.status().await.consecutive_failures >= PROMOTE_AFTER_FAILURES);
+    }
+    
+    #[tokio::test]
+    async fn web_server_serves_static_files_and_read_only_api() {
+        let static_dir = std::env::temp_dir().join(format!("vpnet-replica-web-{}", std::process::id()));
+        std::fs::create_dir_all(&static_dir).unwrap();
+        std::fs::write(static_dir.join("index.html"), "<title>VPNet</title>").unwrap();
+        
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let view = ReplicaView::new("http://127.0.0.1:1", 0);
+        let dir = static_dir.to_string_lossy().into_owned();
+        let mut tasks = crate::tasks::BackgroundTasks::new();
+        tasks.spawn_graceful("replica-web", move |shutdown| start_replica_web_server(addr, view, dir, true, shutdown));
+        
+        let client = reqwest::Client::new();
+        let base = format!("http://{}", addr);
+        let mut index = None;
+        for _ in 0..50 {
+            if let Ok(response) = client.get(format!("{}/", base)).send().await {
+                index = Some(response);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(index.unwrap().text().await.unwrap(), "<title>VPNet</title>");
+        
+        let nodes = client.get(format!("{}/api/nodes", base)).send().await.unwrap();
+        assert_eq!(nodes.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let status: Value = client.get(format!("{}/api/replication/status", base)).send().await.unwrap()
+            .json().await.unwrap();
+        assert_eq!(status["mode"], "replica");
+        let update = client.delete(format!("{}/api/nodes", base)).send().await.unwrap();
+        assert_eq!(update.status(), StatusCode::METHOD_NOT_ALLOWED);
+        
+        tasks.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&static_dir).unwrap();
+    }
+    
+    #[tokio::test]
+    async fn primary_steps_down_once_a_replica_is_promoted() {
+        let replicas = vec![unreachable().await, witness(replica_status(0, 1)).await];
+        let watch = watch_replicas(replicas.clone(), Duration::from_millis(20));
+        assert!(tokio::time::timeout(Duration::from_millis(200), watch).await.is_err());
+        
+        let promoted = witness(serde_json::json!({ "mode": "primary", "consecutive_failures": 0 })).await;
+        let replicas = vec![unreachable().await, format!("{}/", promoted)];
+        let result = tokio::time::timeout(Duration::from_secs(5), watch_replicas(replicas, Duration::from_millis(20))).await.unwrap();
+        assert!(matches!(result, Err(ReplicaError::Fenced(replica)) if replica == promoted));
+    }
+}
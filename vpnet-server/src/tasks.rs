@@ -0,0 +1,306 @@
+/*!
+VPNet Server 后台任务模块
+
+统一管理服务端的后台任务，包括：
+- 以名称启动后台任务
+- 运行期间发现失败或panic的任务
+- 关闭时通知服务类任务完成正在处理的请求，中止其余任务并等待结束
+- 记录任务的错误和panic
+*/
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::{AbortHandle, Id, JoinError, JoinSet};
+
+/// 后台任务的返回值
+pub type TaskResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// 关闭时等待服务类任务完成正在处理的请求的最长时间
+pub const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// 关闭信号，任务集合开始关闭时完成
+#[derive(Clone)]
+pub struct ShutdownSignal(watch::Receiver<bool>);
+
+impl ShutdownSignal {
+    /// 等待关闭信号，任务集合已被丢弃时也视为关闭
+    pub async fn wait(mut self) {
+        let _ = self.0.wait_for(|shutdown| *shutdown).await;
+    }
+}
+
+/// 已启动的任务
+struct TaskInfo {
+    name: &'static str,
+    /// 收到关闭信号后自行结束，不立即中止
+    graceful: bool,
+    abort: AbortHandle,
+}
+
+/// 后台任务集合
+///
+/// `tokio::task::Builder`需要以`tokio_unstable`编译，任务名称由集合自己按任务ID记录。
+pub struct BackgroundTasks {
+    tasks: JoinSet<TaskResult>,
+    info: HashMap<Id, TaskInfo>,
+    shutdown: watch::Sender<bool>,
+    grace_period: Duration,
+}
+
+impl Default for BackgroundTasks {
+    fn default() -> Self {
+        Self {
+            tasks: JoinSet::new(),
+            info: HashMap::new(),
+            shutdown: watch::channel(false).0,
+            grace_period: SHUTDOWN_GRACE_PERIOD,
+        }
+    }
+}
+
+impl BackgroundTasks {
+    /// 创建空的任务集合
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// 设置关闭时等待服务类任务的最长时间，默认为`SHUTDOWN_GRACE_PERIOD`
+    pub fn set_grace_period(&mut self, grace_period: Duration) {
+        self.grace_period = grace_period;
+    }
+    
+    /// 启动不返回错误的后台任务
+    pub fn spawn<F>(&mut self, name: &'static str, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.spawn_fallible(name, async move {
+            task.await;
+            Ok::<_, std::convert::Infallible>(())
+        });
+    }
+    
+    /// 启动可能返回错误的后台任务，关闭时直接中止
+    pub fn spawn_fallible<F, E>(&mut self, name: &'static str, task: F)
+    where
+        F: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.insert(name, false, task);
+    }
+    
+    /// 启动服务类任务，例如HTTP服务器
+    ///
+    /// 任务收到`ShutdownSignal`后应停止接受新请求，完成正在处理的请求后结束；
+    /// 超过宽限期仍未结束时被中止。
+    pub fn spawn_graceful<F, T, E>(&mut self, name: &'static str, task: F)
+    where
+        F: FnOnce(ShutdownSignal) -> T,
+        T: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let signal = ShutdownSignal(self.shutdown.subscribe());
+        self.insert(name, true, task(signal));
+    }
+    
+    fn insert<F, E>(&mut self, name: &'static str, graceful: bool, task: F)
+    where
+        F: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let abort = self.tasks.spawn(async move { task.await.map_err(Into::into) });
+        self.info.insert(abort.id(), TaskInfo { name, graceful, abort });
+        log::debug!("Started background task {}", name);
+    }
+    
+    /// 正在运行的任务数量
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+    
+    /// 是否没有正在运行的任务
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+    
+    /// 等待任意任务失败或panic，返回其错误
+    ///
+    /// 正常结束的任务只记录日志。没有任务时一直等待，以便在`select!`中与关闭信号一起使用。
+    pub async fn failed(&mut self) -> Box<dyn std::error::Error + Send + Sync> {
+        loop {
+            match self.tasks.join_next_with_id().await {
+                Some(result) => {
+                    if let Some(error) = self.handle_result(result) {
+                        return error;
+                    }
+                }
+                None => std::future::pending().await,
+            }
+        }
+    }
+    
+    /// 关闭全部任务并等待结束，返回第一个失败任务的错误
+    ///
+    /// 先发出关闭信号并中止非服务类任务，服务类任务在宽限期内完成正在处理的请求后结束，
+    /// 超时后同样被中止。被中止的任务不算失败，其余错误和panic都会记录日志。
+    pub async fn shutdown(mut self) -> TaskResult {
+        self.shutdown.send_replace(true);
+        for task in self.info.values().filter(|task| !task.graceful) {
+            task.abort.abort();
+        }
+        
+        let mut first_error = None;
+        let deadline = tokio::time::Instant::now() + self.grace_period;
+        loop {
+            let result = match tokio::time::timeout_at(deadline, self.tasks.join_next_with_id()).await {
+                Ok(Some(result)) => result,
+                Ok(None) => break,
+                Err(_) => {
+                    for task in self.info.values() {
+                        log::warn!("Background task {} did not stop within {:?}, aborting", task.name, self.grace_period);
+                    }
+                    self.tasks.abort_all();
+                    continue;
+                }
+            };
+            if let Some(error) = self.handle_result(result) {
+                first_error.get_or_insert(error);
+            }
+        }
+        
+        first_error.map_or(Ok(()), Err)
+    }
+    
+    /// 记录任务结果，任务失败时返回错误
+    fn handle_result(
+        &mut self,
+        result: Result<(Id, TaskResult), JoinError>
+    ) -> Option<Box<dyn std::error::Error + Send + Sync>> {
+        match result {
+            Ok((id, Ok(()))) => {
+                log::debug!("Background task {} finished", self.name(id));
+                None
+            }
+            Ok((id, Err(e))) => {
+                log::error!("Background task {} failed: {}", self.name(id), e);
+                Some(e)
+            }
+            Err(e) if e.is_cancelled() => {
+                log::debug!("Background task {} stopped", self.name(e.id()));
+                None
+            }
+            Err(e) => {
+                let name = self.name(e.id());
+                log::error!("Background task {} panicked", name);
+                Some(format!("background task {} panicked", name).into())
+            }
+        }
+    }
+    
+    /// 任务名称，结束的任务同时移除记录
+    fn name(&mut self, id: Id) -> &'static str {
+        self.info.remove(&id).map_or("unknown", |task| task.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    
+    /// 被丢弃时计数，用于确认任务已被中止
+    struct DropCounter(Arc<AtomicUsize>);
+    
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    
+    #[tokio::test]
+    async fn shutdown_aborts_every_registered_task() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let mut tasks = BackgroundTasks::new();
+        for name in ["heartbeat", "discovery", "hooks", "latency-matrix", "idle-eviction"] {
+            let guard = DropCounter(dropped.clone());
+            tasks.spawn(name, async move {
+                let _guard = guard;
+                std::future::pending::<()>().await;
+            });
+        }
+        assert_eq!(tasks.len(), 5);
+        
+        tasks.shutdown().await.unwrap();
+        assert_eq!(dropped.load(Ordering::SeqCst), 5);
+    }
+    
+    #[tokio::test]
+    async fn graceful_tasks_finish_after_the_shutdown_signal() {
+        let drained = Arc::new(AtomicUsize::new(0));
+        let mut tasks = BackgroundTasks::new();
+        let counter = drained.clone();
+        tasks.spawn_graceful("api", move |shutdown| async move {
+            shutdown.wait().await;
+            // 完成正在处理的请求
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok::<_, std::io::Error>(())
+        });
+        tasks.spawn("heartbeat", std::future::pending());
+        
+        tasks.shutdown().await.unwrap();
+        assert_eq!(drained.load(Ordering::SeqCst), 1);
+    }
+    
+    #[tokio::test]
+    async fn graceful_tasks_are_aborted_after_the_grace_period() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let mut tasks = BackgroundTasks::new();
+        tasks.set_grace_period(Duration::from_millis(50));
+        let guard = DropCounter(dropped.clone());
+        tasks.spawn_graceful("web", move |_shutdown| async move {
+            let _guard = guard;
+            std::future::pending::<Result<(), std::io::Error>>().await
+        });
+        
+        let start = std::time::Instant::now();
+        tasks.shutdown().await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(dropped.load(Ordering::SeqCst), 1);
+    }
+    
+    #[tokio::test]
+    async fn failures_are_reported_while_running() {
+        let mut tasks = BackgroundTasks::new();
+        tasks.spawn("finished", async {});
+        tasks.spawn("heartbeat", std::future::pending());
+        tasks.spawn_fallible("api", async {
+            Err::<(), _>(std::io::Error::new(std::io::ErrorKind::AddrInUse, "address in use"))
+        });
+        
+        let error = tokio::time::timeout(Duration::from_secs(5), tasks.failed()).await.unwrap();
+        assert!(error.to_string().contains("address in use"));
+        assert_eq!(tasks.len(), 1);
+        tasks.shutdown().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn panicking_tasks_are_reported() {
+        let mut tasks = BackgroundTasks::new();
+        tasks.spawn("watchdog", async { panic!("watchdog failed") });
+        let error = tokio::time::timeout(Duration::from_secs(5), tasks.failed()).await.unwrap();
+        assert!(error.to_string().contains("watchdog"));
+        
+        // 失败已经报告过，关闭时不再返回
+        tasks.shutdown().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn failures_are_pending_without_tasks() {
+        let mut tasks = BackgroundTasks::new();
+        assert!(tokio::time::timeout(Duration::from_millis(20), tasks.failed()).await.is_err());
+    }
+}
@@ -0,0 +1,246 @@
+/*!
+VPNet Web API模块
+
+Web管理界面的后端状态，包括：
+- 转发管理请求到服务端API
+- 管理员登录令牌的签发、校验和注销
+*/
+
+use axum::{body::Bytes, http::StatusCode, response::{IntoResponse, Response}, Json};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use rand::Rng;
+use reqwest::{Method, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use vpnet::SecureRng;
+use crate::config::WebConfig;
+
+/// 令牌ID计数器
+static NEXT_TOKEN_ID: AtomicU64 = AtomicU64::new(0);
+
+/// API错误
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("Invalid server API url: {0}")]
+    InvalidUrl(String),
+    
+    #[error("Server API request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    
+    #[error("{message}")]
+    Upstream { status: StatusCode, message: String },
+    
+    #[error("Invalid username or password")]
+    InvalidCredentials,
+    
+    #[error("Missing or invalid token")]
+    Unauthorized,
+    
+    #[error("Token error: {0}")]
+    Token(#[from] jsonwebtoken::errors::Error),
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            ApiError::InvalidUrl(_) | ApiError::Token(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Request(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Upstream { status, .. } => *status,
+            ApiError::InvalidCredentials | ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+        };
+        (status, Json(serde_json::json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+/// 登录令牌声明
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// 用户名
+    pub sub: String,
+    /// 令牌ID，注销时按此撤销
+    pub jti: String,
+    pub iat: u64,
+    pub exp: u64,
+}
+
+/// Web API共享状态
+#[derive(Clone)]
+pub struct ApiState {
+    config: WebConfig,
+    api_url: Url,
+    client: reqwest::Client,
+    secret_key: Arc<Vec<u8>>,
+    /// 已注销的令牌ID及其过期时间
+    revoked: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl ApiState {
+    /// 创建Web API状态
+    pub fn new(config: WebConfig) -> Result<Self, ApiError> {
+        let api_url = Url::parse(&config.api_url)
+            .map_err(|_| ApiError::InvalidUrl(config.api_url.clone()))?;
+        if api_url.cannot_be_a_base() {
+            return Err(ApiError::InvalidUrl(config.api_url.clone()));
+        }
+        
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.api_timeout))
+            .build()?;
+        
+        let secret_key = if config.secret_key.is_empty() {
+            SecureRng::new().gen::<[u8; 32]>().to_vec()
+        } else {
+            config.secret_key.as_bytes().to_vec()
+        };
+        
+        Ok(Self {
+            config,
+            api_url,
+            client,
+            secret_key: Arc::new(secret_key),
+            revoked: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+    
+    /// 获取配置
+    pub fn config(&self) -> &WebConfig {
+        &self.config
+    }
+    
+    /// 将请求转发到服务端API
+    ///
+    /// `segments`为`/api`之后的路径段，逐段编码后拼接。服务端返回错误时，
+    /// 以相同的状态码和错误信息返回。
+    pub async fn forward(
+        &self,
+        method: Method,
+        segments: &[&str],
+        body: Option<&serde_json::Value>
+    ) -> Result<serde_json::Value, ApiError> {
+        let body = self.send(method, segments, body).await?;
+        serde_json::from_slice(&body)
+            .map_err(|_| ApiError::Upstream {
+                status: StatusCode::BAD_GATEWAY,
+                message: "invalid response from server API".to_string(),
+            })
+    }
+    
+    /// 将GET请求转发到服务端API，以文本形式返回响应，用于非JSON格式的接口
+    pub async fn forward_text(&self, segments: &[&str]) -> Result<String, ApiError> {
+        let body = self.send(Method::GET, segments, None).await?;
+        String::from_utf8(body.to_vec())
+            .map_err(|_| ApiError::Upstream {
+                status: StatusCode::BAD_GATEWAY,
+                message: "invalid response from server API".to_string(),
+            })
+    }
+    
+    /// 发送请求到服务端API，返回成功响应的响应体
+    async fn send(
+        &self,
+        method: Method,
+        segments: &[&str],
+        body: Option<&serde_json::Value>
+    ) -> Result<Bytes, ApiError> {
+        let mut url = self.api_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| ApiError::InvalidUrl(self.config.api_url.clone()))?
+            .pop_if_empty()
+            .push("api")
+            .extend(segments);
+        
+        let mut request = self.client.request(method, url);
+        if let Some(token) = &self.config.api_token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        
+        let response = request.send().await?;
+        let status = StatusCode::from_u16(response.status().as_u16())
+            .unwrap_or(StatusCode::BAD_GATEWAY);
+        let body = response.bytes().await?;
+        
+        if !status.is_success() {
+            // 服务端的错误响应带有`error`字段，其他错误（例如路由不存在）使用状态码的描述
+            let message = serde_json::from_slice::<serde_json::Value>(&body).ok()
+                .and_then(|value| value.get("error")?.as_str().map(str::to_string))
+                .unwrap_or_else(|| status.canonical_reason().unwrap_or("server API request failed").to_string());
+            return Err(ApiError::Upstream { status, message });
+        }
+        
+        Ok(body)
+    }
+    
+    /// 校验管理员凭据，未配置密码时一律拒绝
+    pub fn check_credentials(&self, username: &str, password: &str) -> bool {
+        !self.config.password.is_empty()
+            // 两项都比较完再合并结果，不因用户名不匹配而提前返回
+            && (constant_time_eq(username.as_bytes(), self.config.username.as_bytes())
+                & constant_time_eq(password.as_bytes(), self.config.password.as_bytes()))
+    }
+    
+    /// 签发登录令牌，返回令牌及其过期时间
+    pub fn issue_token(&self, username: &str) -> Result<(String, u64), ApiError> {
+        let iat = unix_now();
+        let claims = Claims {
+            sub: username.to_string(),
+            jti: format!("{:x}-{:x}", iat, NEXT_TOKEN_ID.fetch_add(1, Ordering::Relaxed)),
+            iat,
+            exp: iat + self.config.token_expiry,
+        };
+        
+        let token = jsonwebtoken::encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(&self.secret_key)
+        )?;
+        Ok((token, claims.exp))
+    }
+    
+    /// 校验登录令牌，令牌无效、过期或已注销时返回`Unauthorized`
+    pub async fn verify_token(&self, token: &str) -> Result<Claims, ApiError> {
+        let claims = jsonwebtoken::decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(&self.secret_key),
+            &Validation::default()
+        )
+        .map_err(|_| ApiError::Unauthorized)?
+        .claims;
+        
+        if self.revoked.lock().await.contains_key(&claims.jti) {
+            return Err(ApiError::Unauthorized);
+        }
+        Ok(claims)
+    }
+    
+    /// 注销登录令牌
+    pub async fn revoke_token(&self, claims: &Claims) {
+        let now = unix_now();
+        let mut revoked = self.revoked.lock().await;
+        // 过期的令牌本身已无法通过校验，无需继续记录
+        revoked.retain(|_, exp| *exp > now);
+        revoked.insert(claims.jti.clone(), claims.exp);
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// 比较耗时与内容无关的字节串比较
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,117 @@
+/*!
+VPNet Web 配置模块
+
+定义Web管理界面的配置，包括：
+- 监听地址
+- 服务端管理API地址
+- 管理员登录凭据
+
+配置读取自服务端配置文件的`[web]`部分，未填写的字段使用默认值。
+*/
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use thiserror::Error;
+
+/// 配置错误
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    
+    #[error("Parse error: {0}")]
+    Parse(#[from] toml::de::Error),
+}
+
+/// Web管理界面配置
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WebConfig {
+    pub bind: String,
+    pub port: u16,
+    /// 服务端管理API地址，管理请求均转发到该地址
+    pub api_url: String,
+    /// 请求服务端API的超时时间（秒）
+    pub api_timeout: u64,
+    /// 服务端管理API的访问令牌，对应服务端配置的`api.admin_token`
+    #[serde(default)]
+    pub api_token: Option<String>,
+    pub username: String,
+    /// 管理员密码，为空时禁止登录
+    pub password: String,
+    /// 签发登录令牌使用的密钥，为空时每次启动随机生成
+    pub secret_key: String,
+    /// 登录令牌有效期（秒）
+    pub token_expiry: u64,
+    /// 部署在负载均衡之后时启用，每个连接必须先发送PROXY协议v2头部
+    #[serde(default)]
+    pub proxy_protocol: bool,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0".to_string(),
+            port: 8080,
+            api_url: "http://127.0.0.1:51821".to_string(),
+            api_timeout: 10,
+            api_token: None,
+            username: "admin".to_string(),
+            password: String::new(),
+            secret_key: String::new(),
+            token_expiry: 3600,
+            proxy_protocol: false,
+        }
+    }
+}
+
+impl WebConfig {
+    /// 从服务端配置文件的`[web]`部分加载，没有该部分时使用默认配置
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path)?;
+        Self::from_server_config(&content)
+    }
+    
+    /// 解析服务端配置的`[web]`部分，服务端使用的其他字段忽略
+    pub fn from_server_config(content: &str) -> Result<Self, ConfigError> {
+        #[derive(Deserialize)]
+        struct ServerConfig {
+            #[serde(default)]
+            web: WebConfig,
+        }
+        
+        Ok(toml::from_str::<ServerConfig>(content)?.web)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn reads_web_section_of_server_config() {
+        let config = WebConfig::from_server_config(r#"
+            schema_version = 4
+            
+            [server]
+            port = 51820
+            
+            [web]
+            bind = "127.0.0.1"
+            port = 51822
+            enable_tls = false
+            proxy_protocol = true
+        "#).unwrap();
+        assert_eq!(config.bind, "127.0.0.1");
+        assert_eq!(config.port, 51822);
+        assert!(config.proxy_protocol);
+        assert_eq!(config.api_url, WebConfig::default().api_url);
+    }
+    
+    #[test]
+    fn missing_web_section_uses_defaults() {
+        let config = WebConfig::from_server_config("[server]\nport = 51820\n").unwrap();
+        assert_eq!(config.port, WebConfig::default().port);
+        assert!(!config.proxy_protocol);
+    }
+}
@@ -0,0 +1,234 @@
+/*!
+VPNet Web 请求处理模块
+
+Web管理界面的HTTP接口，包括：
+- 节点查询和管理
+- 运行统计
+- 虚拟设备管理
+- 路由管理
+- 网络拓扑
+- 管理员登录
+
+除登录相关接口外，请求均转发到服务端管理API处理。
+*/
+
+use axum::{extract::Path, http::{header, HeaderMap}, response::IntoResponse, Extension, Json};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::api::{ApiError, ApiState, Claims};
+use crate::middleware::RealIp;
+
+/// 处理结果
+pub type HandlerResult<T = Value> = Result<Json<T>, ApiError>;
+
+/// 节点更新请求，未填写的字段保持不变
+#[derive(Debug, Deserialize)]
+pub struct UpdateNodeRequest {
+    pub name: Option<String>,
+    pub priority: Option<u8>,
+}
+
+/// 登录请求
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+/// 登录令牌响应
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    pub token: String,
+    pub expires_at: u64,
+}
+
+/// 健康检查，同时报告服务端API是否可达
+pub async fn health_check(Extension(state): Extension<ApiState>) -> Json<Value> {
+    let server = state.forward(Method::GET, &["health"], None).await.is_ok();
+    Json(serde_json::json!({ "status": "ok", "server": server }))
+}
+
+/// 获取所有节点
+pub async fn get_nodes(Extension(state): Extension<ApiState>) -> HandlerResult {
+    state.forward(Method::GET, &["nodes"], None).await.map(Json)
+}
+
+/// 获取节点详情
+pub async fn get_node(
+    Extension(state): Extension<ApiState>,
+    Path(id): Path<String>
+) -> HandlerResult {
+    state.forward(Method::GET, &["nodes", &id], None).await.map(Json)
+}
+
+/// 更新节点名称和优先级
+pub async fn update_node(
+    Extension(state): Extension<ApiState>,
+    Path(id): Path<String>,
+    Json(req): Json<UpdateNodeRequest>
+) -> HandlerResult {
+    if let Some(name) = &req.name {
+        let body = serde_json::json!({ "name": name });
+        state.forward(Method::PUT, &["nodes", &id, "name"], Some(&body)).await?;
+    }
+    if let Some(priority) = req.priority {
+        let body = serde_json::json!({ "priority": priority });
+        state.forward(Method::PUT, &["nodes", &id, "priority"], Some(&body)).await?;
+    }
+    
+    state.forward(Method::GET, &["nodes", &id], None).await.map(Json)
+}
+
+/// 注销节点
+pub async fn delete_node(
+    Extension(state): Extension<ApiState>,
+    Path(id): Path<String>
+) -> HandlerResult {
+    state.forward(Method::DELETE, &["nodes", &id], None).await.map(Json)
+}
+
+/// 获取运行统计
+pub async fn get_stats(Extension(state): Extension<ApiState>) -> HandlerResult {
+    state.forward(Method::GET, &["stats"], None).await.map(Json)
+}
+
+/// 获取网络拓扑
+pub async fn get_topology(Extension(state): Extension<ApiState>) -> HandlerResult {
+    state.forward(Method::GET, &["topology"], None).await.map(Json)
+}
+
+/// 以Graphviz DOT格式获取网络拓扑
+pub async fn get_topology_dot(
+    Extension(state): Extension<ApiState>
+) -> Result<impl IntoResponse, ApiError> {
+    let dot = state.forward_text(&["topology", "dot"]).await?;
+    Ok(([(header::CONTENT_TYPE, "text/vnd.graphviz; charset=utf-8")], dot))
+}
+
+/// 获取所有虚拟设备
+pub async fn get_devices(Extension(state): Extension<ApiState>) -> HandlerResult {
+    state.forward(Method::GET, &["devices"], None).await.map(Json)
+}
+
+/// 获取虚拟设备详情
+pub async fn get_device(
+    Extension(state): Extension<ApiState>,
+    Path(id): Path<String>
+) -> HandlerResult {
+    state.forward(Method::GET, &["devices", &id], None).await.map(Json)
+}
+
+/// 更新虚拟设备的地址
+pub async fn update_device(
+    Extension(state): Extension<ApiState>,
+    Path(id): Path<String>,
+    Json(body): Json<Value>
+) -> HandlerResult {
+    state.forward(Method::PUT, &["devices", &id], Some(&body)).await.map(Json)
+}
+
+/// 删除虚拟设备
+pub async fn delete_device(
+    Extension(state): Extension<ApiState>,
+    Path(id): Path<String>
+) -> HandlerResult {
+    state.forward(Method::DELETE, &["devices", &id], None).await.map(Json)
+}
+
+/// 重启虚拟设备
+pub async fn restart_device(
+    Extension(state): Extension<ApiState>,
+    Path(id): Path<String>
+) -> HandlerResult {
+    state.forward(Method::POST, &["devices", &id, "restart"], None).await.map(Json)
+}
+
+/// 获取策略路由规则
+pub async fn get_routes(Extension(state): Extension<ApiState>) -> HandlerResult {
+    state.forward(Method::GET, &["routes", "policy"], None).await.map(Json)
+}
+
+/// 添加策略路由规则
+pub async fn add_route(
+    Extension(state): Extension<ApiState>,
+    Json(body): Json<Value>
+) -> HandlerResult {
+    state.forward(Method::POST, &["routes", "policy"], Some(&body)).await.map(Json)
+}
+
+/// 删除策略路由规则，路由ID即规则的优先级
+pub async fn delete_route(
+    Extension(state): Extension<ApiState>,
+    Path(id): Path<String>
+) -> HandlerResult {
+    state.forward(Method::DELETE, &["routes", "policy", &id], None).await.map(Json)
+}
+
+/// 替换策略路由规则，路由ID即规则的优先级
+pub async fn update_route(
+    Extension(state): Extension<ApiState>,
+    Path(id): Path<String>,
+    Json(body): Json<Value>
+) -> HandlerResult {
+    state.forward(Method::PUT, &["routes", "policy", &id], Some(&body)).await.map(Json)
+}
+
+/// 获取当前登录信息
+pub async fn get_auth(
+    Extension(state): Extension<ApiState>,
+    headers: HeaderMap
+) -> HandlerResult {
+    let claims = authorize(&state, &headers).await?;
+    Ok(Json(serde_json::json!({ "username": claims.sub, "expires_at": claims.exp })))
+}
+
+/// 校验管理员凭据并签发登录令牌
+///
+/// 启用PROXY协议时，登录失败的日志记录客户端的真实IP。
+pub async fn login(
+    Extension(state): Extension<ApiState>,
+    real_ip: Option<Extension<RealIp>>,
+    Json(req): Json<LoginRequest>
+) -> HandlerResult<TokenResponse> {
+    if !state.check_credentials(&req.username, &req.password) {
+        match real_ip {
+            Some(Extension(RealIp(ip))) => log::warn!("Failed login attempt for user {} from {}", req.username, ip),
+            None => log::warn!("Failed login attempt for user {}", req.username),
+        }
+        return Err(ApiError::InvalidCredentials);
+    }
+    
+    let (token, expires_at) = state.issue_token(&req.username)?;
+    Ok(Json(TokenResponse { token, expires_at }))
+}
+
+/// 注销当前登录令牌
+pub async fn logout(
+    Extension(state): Extension<ApiState>,
+    headers: HeaderMap
+) -> HandlerResult {
+    let claims = authorize(&state, &headers).await?;
+    state.revoke_token(&claims).await;
+    Ok(Json(serde_json::json!({ "logged_out": true })))
+}
+
+/// 以当前有效的令牌换取新令牌，旧令牌随即失效
+pub async fn refresh_token(
+    Extension(state): Extension<ApiState>,
+    headers: HeaderMap
+) -> HandlerResult<TokenResponse> {
+    let claims = authorize(&state, &headers).await?;
+    let (token, expires_at) = state.issue_token(&claims.sub)?;
+    state.revoke_token(&claims).await;
+    Ok(Json(TokenResponse { token, expires_at }))
+}
+
+/// 从`Authorization: Bearer`请求头中取出并校验登录令牌
+pub async fn authorize(state: &ApiState, headers: &HeaderMap) -> Result<Claims, ApiError> {
+    let token = headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(ApiError::Unauthorized)?;
+    state.verify_token(token).await
+}
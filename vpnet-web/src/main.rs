@@ -1,104 +1,305 @@
-/*!
-VPNet Web Management Interface
-
-现代化的Web管理界面，包括：
-- 透明玻璃UI设计
-- 完整的深色/浅色主题支持
-- 响应式设计
-- 实时数据刷新
-- 原生中文支持
-- 移动端和桌面端优化
-*/
-
-use axum::{Router, routing::get, Extension};
-use tower_http::compression::CompressionLayer;
-use tower_http::cors::CorsLayer;
-use tower_http::services::ServeDir;
-use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use vpnet_web::api::ApiState;
-use vpnet_web::config::WebConfig;
-use vpnet_web::handler::{health_check, get_nodes, get_node, update_node, delete_node, get_stats};
-use vpnet_web::handler::{get_devices, get_device, update_device, delete_device, restart_device};
-use vpnet_web::handler::{get_routes, add_route, delete_route, update_route};
-use vpnet_web::handler::{get_auth, login, logout, refresh_token};
-
-mod api;
-mod config;
-mod handler;
-mod utils;
-mod middleware;
-
-/// 启动Web服务器
-pub async fn start_web_server(
-    addr: SocketAddr,
-    state: ApiState
-) -> Result<(), Box<dyn std::error::Error>> {
-    // 创建CORS层
-    let cors = CorsLayer::permissive();
-    
-    // 创建压缩层
-    let compression = CompressionLayer::new();
-    
-    // 静态文件服务
-    let static_files = ServeDir::new("static");
-    
-    // 创建路由
-    let app = Router::new()
-        // API路由
-        .route("/api/health", get(health_check))
-        .route("/api/nodes", get(get_nodes))
-        .route("/api/nodes/:id", get(get_node))
-        .route("/api/nodes/:id", put(update_node))
-        .route("/api/nodes/:id", delete(delete_node))
-        .route("/api/stats", get(get_stats))
-        .route("/api/devices", get(get_devices))
-        .route("/api/devices/:id", get(get_device))
-        .route("/api/devices/:id", put(update_device))
-        .route("/api/devices/:id", delete(delete_device))
-        .route("/api/devices/:id/restart", post(restart_device))
-        .route("/api/routes", get(get_routes))
-        .route("/api/routes", post(add_route))
-        .route("/api/routes/:id", delete(delete_route))
-        .route("/api/routes/:id", put(update_route))
-        .route("/api/auth", get(get_auth))
-        .route("/api/auth/login", post(login))
-        .route("/api/auth/logout", post(logout))
-        .route("/api/auth/refresh", post(refresh_token))
-        // 静态文件服务
-        .nest_service("/", static_files)
-        // 应用中间件
-        .layer(cors)
-        .layer(compression)
-        .layer(Extension(state))
-        // 应用认证中间件
-        .layer(middleware::auth::AuthMiddleware::new());
-    
-    // 启动服务器
-    log::info!("Web server starting on {}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await?;
-    
-    Ok(())
-}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // 初始化日志
-    env_logger::init();
-    
-    // 加载配置
-    let config = WebConfig::default();
-    
-    // 解析地址
-    let addr: SocketAddr = format!("{}:{}", config.bind, config.port)
-        .parse()?;
-    
-    // 创建API状态
-    let state = ApiState::new(config.clone())?;
-    
-    // 启动Web服务器
-    start_web_server(addr, state).await
-}
+/*!
+VPNet Web Management Interface
+
+现代化的Web管理界面，包括：
+- 透明玻璃UI设计
+- 完整的深色/浅色主题支持
+- 响应式设计
+- 实时数据刷新
+- 原生中文支持
+- 移动端和桌面端优化
+*/
+
+use axum::{Router, routing::{delete, get, post, put}, Extension};
+use axum::http::{header, HeaderValue};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::CorsLayer;
+use tower_http::services::ServeDir;
+use tower_http::set_header::SetRequestHeaderLayer;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use vpnet_web::api::ApiState;
+use vpnet_web::config::WebConfig;
+use vpnet_web::handler::{health_check, get_nodes, get_node, update_node, delete_node, get_stats};
+use vpnet_web::handler::{get_topology, get_topology_dot};
+use vpnet_web::handler::{get_devices, get_device, update_device, delete_device, restart_device};
+use vpnet_web::handler::{get_routes, add_route, delete_route, update_route};
+use vpnet_web::handler::{get_auth, login, logout, refresh_token};
+
+mod api;
+mod config;
+mod handler;
+mod utils;
+mod middleware;
+
+/// 创建Web管理界面的路由
+///
+/// 除健康检查和登录外，所有API请求都需要登录令牌。
+pub fn app(state: ApiState) -> Router {
+    // 创建CORS层
+    let cors = CorsLayer::permissive();
+    
+    // 创建压缩层
+    let compression = CompressionLayer::new();
+    
+    // 静态文件服务
+    let static_files = ServeDir::new("static");
+    
+    // 创建路由
+    Router::new()
+        // API路由
+        .route("/api/health", get(health_check))
+        .route("/api/nodes", get(get_nodes))
+        .route("/api/nodes/:id", get(get_node))
+        .route("/api/nodes/:id", put(update_node))
+        .route("/api/nodes/:id", delete(delete_node))
+        .route("/api/stats", get(get_stats))
+        .route("/api/topology", get(get_topology))
+        .route("/api/topology/dot", get(get_topology_dot))
+        .route("/api/devices", get(get_devices))
+        .route("/api/devices/:id", get(get_device))
+        .route("/api/devices/:id", put(update_device))
+        .route("/api/devices/:id", delete(delete_device))
+        .route("/api/devices/:id/restart", post(restart_device))
+        .route("/api/routes", get(get_routes))
+        .route("/api/routes", post(add_route))
+        .route("/api/routes/:id", delete(delete_route))
+        .route("/api/routes/:id", put(update_route))
+        .route("/api/auth", get(get_auth))
+        .route("/api/auth/login", post(login))
+        .route("/api/auth/logout", post(logout))
+        .route("/api/auth/refresh", post(refresh_token))
+        // 静态文件服务
+        .nest_service("/", static_files)
+        // 应用中间件
+        .layer(cors)
+        .layer(compression)
+        .layer(Extension(state.clone()))
+        // 修改请求必须携带JSON请求体；未声明Accept的请求按接受JSON处理
+        .layer(middleware::RequireJsonLayer)
+        .layer(SetRequestHeaderLayer::if_not_present(
+            header::ACCEPT,
+            HeaderValue::from_static("application/json")
+        ))
+        // 应用认证中间件，在其他中间件之前拒绝未登录的请求
+        .layer(middleware::AuthLayer::new(state))
+}
+
+/// 启动Web服务器
+pub async fn start_web_server(
+    addr: SocketAddr,
+    state: ApiState
+) -> Result<(), Box<dyn std::error::Error>> {
+    let proxy_protocol = state.config().proxy_protocol;
+    let app = app(state);
+    
+    // 启动服务器
+    log::info!("Web server starting on {}", addr);
+    if proxy_protocol {
+        log::info!("PROXY protocol v2 header required on every connection");
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        middleware::proxy_protocol::serve(listener, app).await?;
+        return Ok(());
+    }
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await?;
+    
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // 初始化日志
+    env_logger::init();
+    
+    // 加载服务端配置文件的`[web]`部分
+    let config_path = std::env::args()
+        .skip_while(|arg| arg != "--config")
+        .nth(1)
+        .unwrap_or_else(|| "vpnet-server.toml".to_string());
+    let config = if std::path::Path::new(&config_path).exists() {
+        WebConfig::load(&config_path)?
+    } else {
+        log::warn!("Config file {} not found, using default web config", config_path);
+        WebConfig::default()
+    };
+    
+    // 解析地址
+    let addr: SocketAddr = format!("{}:{}", config.bind, config.port)
+        .parse()?;
+    
+    // 创建API状态
+    let state = ApiState::new(config.clone())?;
+    
+    // 启动Web服务器
+    start_web_server(addr, state).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode, Uri};
+    use axum::response::{IntoResponse, Response};
+    use axum::Json;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+    
+    /// 每个管理接口的方法、路径、请求体，以及转发到服务端API的方法和路径
+    const ROUTES: &[(&str, &str, bool, &str, &str)] = &[
+        ("GET", "/api/nodes", false, "GET", "/api/nodes"),
+        ("GET", "/api/nodes/n1", false, "GET", "/api/nodes/n1"),
+        ("PUT", "/api/nodes/n1", true, "GET", "/api/nodes/n1"),
+        ("DELETE", "/api/nodes/n1", false, "DELETE", "/api/nodes/n1"),
+        ("GET", "/api/stats", false, "GET", "/api/stats"),
+        ("GET", "/api/topology", false, "GET", "/api/topology"),
+        ("GET", "/api/devices", false, "GET", "/api/devices"),
+        ("GET", "/api/devices/d1", false, "GET", "/api/devices/d1"),
+        ("PUT", "/api/devices/d1", true, "PUT", "/api/devices/d1"),
+        ("DELETE", "/api/devices/d1", false, "DELETE", "/api/devices/d1"),
+        ("POST", "/api/devices/d1/restart", false, "POST", "/api/devices/d1/restart"),
+        ("GET", "/api/routes", false, "GET", "/api/routes/policy"),
+        ("POST", "/api/routes", true, "POST", "/api/routes/policy"),
+        ("DELETE", "/api/routes/10", false, "DELETE", "/api/routes/policy/10"),
+        ("PUT", "/api/routes/10", true, "PUT", "/api/routes/policy/10"),
+    ];
+    
+    /// 模拟服务端管理API，返回收到的方法、路径和管理令牌，路径含`missing`时返回404
+    async fn upstream(method: Method, uri: Uri, headers: axum::http::HeaderMap) -> Response {
+        if uri.path().contains("missing") {
+            return (StatusCode::NOT_FOUND, Json(json!({ "error": "node missing not found" }))).into_response();
+        }
+        if uri.path() == "/api/topology/dot" {
+            return "graph vpnet {}".into_response();
+        }
+        let authorization = headers.get(header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+        Json(json!({ "method": method.as_str(), "path": uri.path(), "authorization": authorization })).into_response()
+    }
+    
+    async fn test_app() -> Router {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().fallback(upstream)).await.unwrap();
+        });
+        
+        let state = ApiState::new(WebConfig {
+            api_url: format!("http://{}", addr),
+            password: "secret".to_string(),
+            api_token: Some("server-token".to_string()),
+            ..WebConfig::default()
+        }).unwrap();
+        app(state)
+    }
+    
+    fn request(method: &str, path: &str, token: Option<&str>, body: Option<Value>) -> Request<Body> {
+        let mut builder = Request::builder().method(method).uri(path);
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        match body {
+            Some(body) => builder
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+            None => builder.body(Body::empty()).unwrap(),
+        }
+    }
+    
+    async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+    
+    async fn login(app: &Router) -> String {
+        let body = json!({ "username": "admin", "password": "secret" });
+        let (status, body) = send(app, request("POST", "/api/auth/login", None, Some(body))).await;
+        assert_eq!(status, StatusCode::OK);
+        body["token"].as_str().unwrap().to_string()
+    }
+    
+    #[tokio::test]
+    async fn management_routes_require_login() {
+        let app = test_app().await;
+        for (method, path, body, _, _) in ROUTES {
+            let body = body.then(|| json!({ "name": "n1" }));
+            let (status, _) = send(&app, request(method, path, None, body.clone())).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{} {} without token", method, path);
+            let (status, _) = send(&app, request(method, path, Some("not-a-token"), body)).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{} {} with invalid token", method, path);
+        }
+        for path in ["/api/topology/dot", "/api/auth"] {
+            let (status, _) = send(&app, request("GET", path, None, None)).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "GET {} without token", path);
+        }
+    }
+    
+    #[tokio::test]
+    async fn management_routes_forward_to_server_api() {
+        let app = test_app().await;
+        let token = login(&app).await;
+        for (method, path, body, upstream_method, upstream_path) in ROUTES {
+            let body = body.then(|| json!({ "name": "n1" }));
+            let (status, body) = send(&app, request(method, path, Some(&token), body)).await;
+            assert_eq!(status, StatusCode::OK, "{} {}", method, path);
+            assert_eq!(body["method"], *upstream_method, "{} {}", method, path);
+            assert_eq!(body["path"], *upstream_path, "{} {}", method, path);
+            assert_eq!(body["authorization"], "Bearer server-token", "{} {}", method, path);
+        }
+        
+        let response = app.clone().oneshot(request("GET", "/api/topology/dot", Some(&token), None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/vnd.graphviz"));
+    }
+    
+    #[tokio::test]
+    async fn server_api_errors_are_passed_through() {
+        let app = test_app().await;
+        let token = login(&app).await;
+        for (method, path) in [("GET", "/api/nodes/missing"), ("DELETE", "/api/nodes/missing"), ("GET", "/api/devices/missing")] {
+            let (status, body) = send(&app, request(method, path, Some(&token), None)).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{} {}", method, path);
+            assert_eq!(body["error"], "node missing not found");
+        }
+        
+        let body = json!({ "name": "renamed" });
+        let (status, _) = send(&app, request("PUT", "/api/nodes/missing", Some(&token), Some(body))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+    
+    #[tokio::test]
+    async fn health_and_login_do_not_require_login() {
+        let app = test_app().await;
+        let (status, body) = send(&app, request("GET", "/api/health", None, None)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["server"], true);
+        
+        let wrong = json!({ "username": "admin", "password": "wrong" });
+        let (status, _) = send(&app, request("POST", "/api/auth/login", None, Some(wrong))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    
+    #[tokio::test]
+    async fn logout_revokes_token_and_refresh_replaces_it() {
+        let app = test_app().await;
+        let token = login(&app).await;
+        let (status, body) = send(&app, request("GET", "/api/auth", Some(&token), None)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["username"], "admin");
+        
+        let (status, body) = send(&app, request("POST", "/api/auth/refresh", Some(&token), None)).await;
+        assert_eq!(status, StatusCode::OK);
+        let refreshed = body["token"].as_str().unwrap().to_string();
+        let (status, _) = send(&app, request("GET", "/api/nodes", Some(&token), None)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        
+        let (status, _) = send(&app, request("POST", "/api/auth/logout", Some(&refreshed), None)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(&app, request("GET", "/api/nodes", Some(&refreshed), None)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,94 @@
+/*!
+管理接口登录校验
+
+除健康检查和登录外，所有API请求必须在`Authorization: Bearer`请求头中携带有效的登录令牌，
+否则直接返回`401 Unauthorized`。静态文件和OPTIONS预检请求不受影响。
+*/
+
+use axum::body::Body;
+use axum::http::{Method, Request};
+use axum::response::{IntoResponse, Response};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use crate::api::ApiState;
+use crate::handler::authorize;
+
+/// 需要校验的API路径前缀
+const API_PREFIX: &str = "/api/";
+
+/// 无需登录即可访问的API
+const PUBLIC_PATHS: &[&str] = &["/api/health", "/api/auth/login"];
+
+/// 登录校验层
+#[derive(Clone)]
+pub struct AuthLayer {
+    state: ApiState,
+}
+
+impl AuthLayer {
+    /// 创建登录校验层，使用`state`校验令牌
+    pub fn new(state: ApiState) -> Self {
+        Self { state }
+    }
+}
+
+impl<S> Layer<S> for AuthLayer {
+    type Service = AuthMiddleware<S>;
+    
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthMiddleware { inner, state: self.state.clone() }
+    }
+}
+
+/// 登录校验中间件
+///
+/// 校验通过后将令牌声明放入请求扩展，处理函数可以通过`Extension<Claims>`取得。
+#[derive(Clone)]
+pub struct AuthMiddleware<S> {
+    inner: S,
+    state: ApiState,
+}
+
+impl<S> Service<Request<Body>> for AuthMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+    
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+    
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        if !requires_auth(&req) {
+            return Box::pin(self.inner.call(req));
+        }
+        
+        // 使用已就绪的服务处理本次请求，留下克隆供下次使用
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let state = self.state.clone();
+        Box::pin(async move {
+            match authorize(&state, req.headers()).await {
+                Ok(claims) => {
+                    req.extensions_mut().insert(claims);
+                    inner.call(req).await
+                }
+                Err(e) => Ok(e.into_response()),
+            }
+        })
+    }
+}
+
+/// 判断请求是否需要登录
+fn requires_auth(req: &Request<Body>) -> bool {
+    let path = req.uri().path();
+    *req.method() != Method::OPTIONS
+        && path.starts_with(API_PREFIX)
+        && !PUBLIC_PATHS.contains(&path)
+}
@@ -0,0 +1,88 @@
+/*!
+JSON请求内容类型检查
+
+带请求体的API修改请求必须声明`Content-Type: application/json`，否则直接返回
+`415 Unsupported Media Type`，避免反序列化时报出难以理解的错误。
+*/
+
+use axum::body::{Body, HttpBody};
+use axum::http::{header, HeaderMap, Method, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// 需要检查的API路径前缀，静态文件不受影响
+const API_PREFIX: &str = "/api/";
+
+/// JSON内容类型检查层
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequireJsonLayer;
+
+impl<S> Layer<S> for RequireJsonLayer {
+    type Service = RequireJsonMiddleware<S>;
+    
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireJsonMiddleware { inner }
+    }
+}
+
+/// JSON内容类型检查中间件
+#[derive(Debug, Clone)]
+pub struct RequireJsonMiddleware<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for RequireJsonMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+    
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+    
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if requires_json(&req) && !is_json(req.headers()) {
+            return Box::pin(async { Ok(unsupported_media_type()) });
+        }
+        Box::pin(self.inner.call(req))
+    }
+}
+
+/// 判断请求是否需要检查内容类型
+///
+/// 只检查API的非GET请求；OPTIONS预检请求和没有请求体的请求（例如注销、重启设备）放行。
+fn requires_json(req: &Request<Body>) -> bool {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return false;
+    }
+    if !req.uri().path().starts_with(API_PREFIX) {
+        return false;
+    }
+    req.body().size_hint().exact() != Some(0)
+}
+
+/// 判断内容类型是否为JSON，忽略大小写和`charset`等参数
+fn is_json(headers: &HeaderMap) -> bool {
+    let content_type = match headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+        Some(content_type) => content_type,
+        None => return false,
+    };
+    
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    essence == "application/json" || (essence.starts_with("application/") && essence.ends_with("+json"))
+}
+
+fn unsupported_media_type() -> Response {
+    (
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        Json(serde_json::json!({ "error": "unsupported_media_type" })),
+    ).into_response()
+}
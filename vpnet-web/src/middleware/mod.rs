@@ -0,0 +1,16 @@
+/*!
+VPNet Web 中间件模块
+
+包括：
+- 管理接口登录校验
+- JSON请求内容类型检查
+- PROXY协议v2头部解析
+*/
+
+pub mod auth;
+pub mod json;
+pub mod proxy_protocol;
+
+pub use auth::{AuthLayer, AuthMiddleware};
+pub use json::{RequireJsonLayer, RequireJsonMiddleware};
+pub use proxy_protocol::{ProxyProtocolLayer, ProxyProtocolMiddleware, RealIp};
@@ -0,0 +1,266 @@
+/*!
+PROXY协议v2支持
+
+部署在AWS ALB、nginx等负载均衡之后时，TCP连接的对端地址是负载均衡的地址。
+启用后每个连接必须先发送PROXY协议v2的二进制头部，从中取出客户端的真实地址，
+以`RealIp`放入请求的扩展中，供日志等使用；没有合法头部的连接直接断开。
+*/
+
+use axum::http::Request;
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::net::TcpListener;
+use tower::{Layer, Service};
+
+/// PROXY协议v2头部的签名
+const SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// 等待PROXY头部的最长时间
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 接受连接失败（例如文件描述符用尽）后重试的最长间隔
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// 客户端的真实IP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RealIp(pub IpAddr);
+
+/// 为请求附加客户端真实IP的层，每个连接一个
+#[derive(Debug, Clone, Copy)]
+pub struct ProxyProtocolLayer {
+    real_ip: RealIp,
+}
+
+impl ProxyProtocolLayer {
+    /// 创建附加`real_ip`的层
+    pub fn new(real_ip: IpAddr) -> Self {
+        Self { real_ip: RealIp(real_ip) }
+    }
+}
+
+impl<S> Layer<S> for ProxyProtocolLayer {
+    type Service = ProxyProtocolMiddleware<S>;
+    
+    fn layer(&self, inner: S) -> Self::Service {
+        ProxyProtocolMiddleware { inner, real_ip: self.real_ip }
+    }
+}
+
+/// 为请求附加客户端真实IP的中间件
+#[derive(Debug, Clone)]
+pub struct ProxyProtocolMiddleware<S> {
+    inner: S,
+    real_ip: RealIp,
+}
+
+impl<S, B> Service<Request<B>> for ProxyProtocolMiddleware<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+    
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+    
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        req.extensions_mut().insert(self.real_ip);
+        self.inner.call(req)
+    }
+}
+
+/// 接受连接并在读取PROXY头部后处理HTTP请求
+///
+/// 接受连接失败时记录错误，等待一段时间后继续，间隔从10毫秒起倍增到1秒。
+pub async fn serve(listener: TcpListener, app: Router) -> std::io::Result<()> {
+    let mut backoff = Duration::from_millis(10);
+    loop {
+        let (mut stream, peer_addr) = match listener.accept().await {
+            Ok(accepted) => {
+                backoff = Duration::from_millis(10);
+                accepted
+            }
+            Err(e) => {
+                log::error!("Failed to accept connection: {}, retrying in {:?}", e, backoff);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                continue;
+            }
+        };
+        let app = app.clone();
+        
+        tokio::spawn(async move {
+            let source = match tokio::time::timeout(HEADER_TIMEOUT, read_header(&mut stream)).await {
+                Ok(Ok(source)) => source,
+                Ok(Err(e)) => {
+                    log::warn!("Rejecting connection from {}: {}", peer_addr, e);
+                    return;
+                }
+                Err(_) => {
+                    log::warn!("Rejecting connection from {}: timed out waiting for PROXY header", peer_addr);
+                    return;
+                }
+            };
+            
+            // LOCAL命令（例如负载均衡的健康检查）没有客户端地址，使用连接的对端地址
+            let real_ip = source.map_or(peer_addr.ip(), |source| source.ip());
+            let service = TowerToHyperService::new(ProxyProtocolLayer::new(real_ip).layer(app));
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                log::debug!("Connection from {} ({}) closed with error: {}", real_ip, peer_addr, e);
+            }
+        });
+    }
+}
+
+/// 读取PROXY协议v2头部，返回客户端地址
+///
+/// LOCAL命令和不带IP地址的地址族返回`None`，头部不合法时返回错误。
+pub async fn read_header<R: AsyncRead + Unpin>(stream: &mut R) -> std::io::Result<Option<SocketAddr>> {
+    let mut header = [0u8; 16];
+    stream.read_exact(&mut header).await?;
+    if header[..12] != SIGNATURE {
+        return Err(invalid_header("missing PROXY v2 signature"));
+    }
+    if header[12] >> 4 != 2 {
+        return Err(invalid_header("unsupported PROXY protocol version"));
+    }
+    
+    // 地址信息和TLV扩展，TLV扩展不使用
+    let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+    let mut addresses = vec![0u8; len];
+    stream.read_exact(&mut addresses).await?;
+    
+    match header[12] & 0x0f {
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => return Err(invalid_header("unsupported PROXY command")),
+    }
+    
+    match header[13] >> 4 {
+        // AF_INET：源地址、目标地址、源端口、目标端口
+        0x1 => {
+            if len < 12 {
+                return Err(invalid_header("truncated IPv4 address block"));
+            }
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        // AF_INET6
+        0x2 => {
+            if len < 36 {
+                return Err(invalid_header("truncated IPv6 address block"));
+            }
+            let octets: [u8; 16] = addresses[..16].try_into().unwrap();
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port)))
+        }
+        // AF_UNSPEC、AF_UNIX没有IP地址
+        _ => Ok(None),
+    }
+}
+
+fn invalid_header(message: &'static str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::Extension;
+    use std::net::Ipv4Addr;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpStream;
+    
+    /// 启动要求PROXY头部的服务，`/ip`返回请求附带的真实IP
+    async fn start_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/ip", get(|Extension(RealIp(ip)): Extension<RealIp>| async move { ip.to_string() }));
+        tokio::spawn(serve(listener, app));
+        addr
+    }
+    
+    /// PROXY协议v2的PROXY命令头部
+    fn proxy_header(source: SocketAddr, destination: SocketAddr) -> Vec<u8> {
+        let mut header = SIGNATURE.to_vec();
+        header.push(0x21);
+        let mut addresses = Vec::new();
+        match (source, destination) {
+            (SocketAddr::V4(source), SocketAddr::V4(destination)) => {
+                header.push(0x11);
+                addresses.extend_from_slice(&source.ip().octets());
+                addresses.extend_from_slice(&destination.ip().octets());
+            }
+            (SocketAddr::V6(source), SocketAddr::V6(destination)) => {
+                header.push(0x21);
+                addresses.extend_from_slice(&source.ip().octets());
+                addresses.extend_from_slice(&destination.ip().octets());
+            }
+            _ => unreachable!(),
+        }
+        addresses.extend_from_slice(&source.port().to_be_bytes());
+        addresses.extend_from_slice(&destination.port().to_be_bytes());
+        header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+        header.extend_from_slice(&addresses);
+        header
+    }
+    
+    /// 发送`header`后请求`/ip`，返回响应，连接被断开时返回空字符串
+    async fn request_ip(addr: SocketAddr, header: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(header).await.unwrap();
+        stream.write_all(b"GET /ip HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response).await;
+        response
+    }
+    
+    #[tokio::test]
+    async fn ipv4_source_is_attached_to_requests() {
+        let addr = start_server().await;
+        let header = proxy_header("203.0.113.7:40000".parse().unwrap(), addr);
+        let response = request_ip(addr, &header).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("203.0.113.7"), "{}", response);
+    }
+    
+    #[tokio::test]
+    async fn ipv6_source_is_attached_to_requests() {
+        let addr = start_server().await;
+        let header = proxy_header("[2001:db8::7]:40000".parse().unwrap(), "[2001:db8::1]:80".parse().unwrap());
+        let response = request_ip(addr, &header).await;
+        assert!(response.ends_with("2001:db8::7"), "{}", response);
+    }
+    
+    #[tokio::test]
+    async fn local_command_uses_connection_address() {
+        let addr = start_server().await;
+        let mut header = SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+        let response = request_ip(addr, &header).await;
+        assert!(response.ends_with(&Ipv4Addr::LOCALHOST.to_string()), "{}", response);
+    }
+    
+    #[tokio::test]
+    async fn connection_without_header_is_closed() {
+        let addr = start_server().await;
+        assert_eq!(request_ip(addr, b"").await, "");
+        
+        // 服务在拒绝连接后继续接受新连接
+        let header = proxy_header("203.0.113.8:40000".parse().unwrap(), addr);
+        assert!(request_ip(addr, &header).await.ends_with("203.0.113.8"));
+    }
+}
@@ -0,0 +1,217 @@
+/*
+VPNet Web Management Interface - 网络拓扑
+
+不依赖第三方库，使用SVG和简单的力导向布局绘制节点之间的连接：
+- 直连为实线，中继为虚线
+- 连线标注往返时延
+- 节点可拖动
+*/
+
+const SVG_NS = 'http://www.w3.org/2000/svg';
+
+// 力导向布局参数
+const LINK_DISTANCE = 140;
+const REPULSION = 40000;
+const SPRING = 0.05;
+const GRAVITY = 0.01;
+const DAMPING = 0.6;
+const NODE_MARGIN = 20;
+
+// 当前布局的动画帧，重新加载时先停止
+let topologyFrame = null;
+
+document.addEventListener('DOMContentLoaded', function() {
+    const refreshBtn = document.getElementById('topology-refresh');
+    if (refreshBtn) {
+        refreshBtn.addEventListener('click', loadTopologyData);
+    }
+});
+
+// 加载网络拓扑
+async function loadTopologyData() {
+    try {
+        const response = await fetch('/api/topology', {
+            headers: { 'Accept': 'application/json' }
+        });
+        if (!response.ok) {
+            throw new Error(`HTTP ${response.status}`);
+        }
+        
+        const topology = await response.json();
+        renderTopology(topology);
+        console.log('Topology data loaded:', topology);
+    } catch (error) {
+        console.error('Error loading topology data:', error);
+        showNotification('加载网络拓扑失败', 'error');
+    }
+}
+
+// 创建SVG元素
+function createSvgElement(tag, attributes = {}) {
+    const element = document.createElementNS(SVG_NS, tag);
+    for (const [name, value] of Object.entries(attributes)) {
+        element.setAttribute(name, value);
+    }
+    return element;
+}
+
+// 绘制网络拓扑
+function renderTopology(topology) {
+    const svg = document.getElementById('topology-graph');
+    svg.replaceChildren();
+    if (topologyFrame) {
+        cancelAnimationFrame(topologyFrame);
+        topologyFrame = null;
+    }
+    
+    const { width, height } = svg.getBoundingClientRect();
+    
+    // 节点初始均匀分布在圆周上，复制一份避免修改原始数据
+    const radius = Math.min(width, height) / 3;
+    const nodes = topology.nodes.map((node, i) => {
+        const angle = 2 * Math.PI * i / topology.nodes.length;
+        return {
+            ...node,
+            x: width / 2 + radius * Math.cos(angle),
+            y: height / 2 + radius * Math.sin(angle),
+            vx: 0,
+            vy: 0,
+            dragging: false
+        };
+    });
+    const nodesById = new Map(nodes.map(node => [node.node_id, node]));
+    const links = topology.edges
+        .filter(edge => nodesById.has(edge.src) && nodesById.has(edge.dst))
+        .map(edge => ({ ...edge, source: nodesById.get(edge.src), target: nodesById.get(edge.dst) }));
+    
+    const linkGroup = createSvgElement('g');
+    const labelGroup = createSvgElement('g');
+    const nodeGroup = createSvgElement('g');
+    
+    for (const link of links) {
+        link.line = createSvgElement('line', { class: link.direct ? 'link' : 'link relay' });
+        linkGroup.appendChild(link.line);
+        if (link.rtt_ms > 0) {
+            link.label = createSvgElement('text', { class: 'link-label', 'text-anchor': 'middle' });
+            link.label.textContent = `${link.rtt_ms.toFixed(1)} ms`;
+            labelGroup.appendChild(link.label);
+        }
+    }
+    
+    for (const node of nodes) {
+        const classes = ['node'];
+        if (node.local) classes.push('local');
+        if (node.status !== 'online') classes.push('offline');
+        
+        node.element = createSvgElement('g', { class: classes.join(' ') });
+        const label = createSvgElement('text', { dy: 26, 'text-anchor': 'middle' });
+        label.textContent = node.node_name || node.node_id;
+        const title = createSvgElement('title');
+        title.textContent = `${node.node_name}\n${node.virtual_ip}\n${node.address}`;
+        node.element.append(createSvgElement('circle', { r: node.local ? 14 : 10 }), label, title);
+        nodeGroup.appendChild(node.element);
+    }
+    
+    svg.append(linkGroup, labelGroup, nodeGroup);
+    
+    // 布局逐渐冷却，拖动节点时重新加热
+    let alpha = 1;
+    const tick = () => {
+        layoutStep(nodes, links, width, height, alpha);
+        drawTopology(nodes, links);
+        alpha *= 0.98;
+        topologyFrame = alpha > 0.01 ? requestAnimationFrame(tick) : null;
+    };
+    const reheat = () => {
+        alpha = Math.max(alpha, 0.3);
+        if (!topologyFrame) {
+            topologyFrame = requestAnimationFrame(tick);
+        }
+    };
+    
+    for (const node of nodes) {
+        enableDrag(svg, node, reheat);
+    }
+    tick();
+}
+
+// 计算一步布局：节点相互排斥，连线像弹簧一样保持固定长度，所有节点被拉向画布中心
+function layoutStep(nodes, links, width, height, alpha) {
+    for (let i = 0; i < nodes.length; i++) {
+        for (let j = i + 1; j < nodes.length; j++) {
+            const a = nodes[i];
+            const b = nodes[j];
+            const dx = b.x - a.x || Math.random() - 0.5;
+            const dy = b.y - a.y || Math.random() - 0.5;
+            const distanceSq = Math.max(dx * dx + dy * dy, 1);
+            const force = REPULSION * alpha / distanceSq;
+            const distance = Math.sqrt(distanceSq);
+            a.vx -= dx / distance * force;
+            a.vy -= dy / distance * force;
+            b.vx += dx / distance * force;
+            b.vy += dy / distance * force;
+        }
+    }
+    
+    for (const link of links) {
+        const dx = link.target.x - link.source.x;
+        const dy = link.target.y - link.source.y;
+        const distance = Math.sqrt(dx * dx + dy * dy) || 1;
+        const force = (distance - LINK_DISTANCE) * SPRING * alpha;
+        link.source.vx += dx / distance * force;
+        link.source.vy += dy / distance * force;
+        link.target.vx -= dx / distance * force;
+        link.target.vy -= dy / distance * force;
+    }
+    
+    for (const node of nodes) {
+        if (node.dragging) {
+            node.vx = 0;
+            node.vy = 0;
+            continue;
+        }
+        node.vx = (node.vx + (width / 2 - node.x) * GRAVITY * alpha) * DAMPING;
+        node.vy = (node.vy + (height / 2 - node.y) * GRAVITY * alpha) * DAMPING;
+        node.x = Math.min(Math.max(node.x + node.vx, NODE_MARGIN), width - NODE_MARGIN);
+        node.y = Math.min(Math.max(node.y + node.vy, NODE_MARGIN), height - NODE_MARGIN);
+    }
+}
+
+// 按节点坐标更新SVG元素
+function drawTopology(nodes, links) {
+    for (const link of links) {
+        link.line.setAttribute('x1', link.source.x);
+        link.line.setAttribute('y1', link.source.y);
+        link.line.setAttribute('x2', link.target.x);
+        link.line.setAttribute('y2', link.target.y);
+        if (link.label) {
+            link.label.setAttribute('x', (link.source.x + link.target.x) / 2);
+            link.label.setAttribute('y', (link.source.y + link.target.y) / 2);
+        }
+    }
+    for (const node of nodes) {
+        node.element.setAttribute('transform', `translate(${node.x},${node.y})`);
+    }
+}
+
+// 拖动节点，拖动期间节点固定在指针位置
+function enableDrag(svg, node, reheat) {
+    node.element.addEventListener('pointerdown', event => {
+        node.dragging = true;
+        node.element.setPointerCapture(event.pointerId);
+        reheat();
+    });
+    node.element.addEventListener('pointermove', event => {
+        if (!node.dragging) return;
+        const rect = svg.getBoundingClientRect();
+        node.x = event.clientX - rect.left;
+        node.y = event.clientY - rect.top;
+        reheat();
+    });
+    const release = event => {
+        node.dragging = false;
+        node.element.releasePointerCapture(event.pointerId);
+    };
+    node.element.addEventListener('pointerup', release);
+    node.element.addEventListener('pointercancel', release);
+}